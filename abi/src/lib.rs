@@ -0,0 +1,308 @@
+//! The representation of values that the compiler and the runtime system agree on.
+//!
+//! The compiler generates code that reads and writes values of the runtime system directly (e.g.,
+//! the payload of an `any`, or the tag of a heap value), instead of calling the runtime system.
+//! Both crates depend on this one, so that the layout has one definition. The runtime system
+//! checks that its types have this layout with [assert_abi], which fails the build when they
+//! drift apart. Rust picks the discriminants of `AnyEnum`, thus the runtime system checks them
+//! in its tests instead.
+#![no_std]
+
+/// The size of an `any`, in bytes. An `any` is a 64-bit immediate value, which is the largest
+/// WebAssembly type.
+pub const ANY_SIZE: u32 = 8;
+
+/// The byte offset of the 32-bit payload of an `any`, which follows its discriminant. (The
+/// compiler reads and writes the payload directly when it knows the type of the `any`.)
+pub const ANY_PAYLOAD_OFFSET: u32 = 4;
+
+/// The size of the tag of every heap value, in bytes.
+pub const TAG_SIZE: u32 = 4;
+
+/// The size of the length of an environment or a string, in bytes.
+pub const LENGTH_SIZE: u32 = 4;
+
+/// The size of the pointer from an environment to the function object of its closure, in bytes.
+pub const FN_OBJ_SIZE: u32 = 4;
+
+/// The size of the function index of an environment, in bytes.
+pub const FUNC_SIZE: u32 = 4;
+
+/// The byte offsets of the fields of an environment, which is laid out as follows:
+///
+/// Tag | u32 | Object | u32  | [EnvItem]
+///        ^      ^^      ^^
+///       len   fn_obj   func
+pub const ENV_LENGTH_OFFSET: u32 = TAG_SIZE;
+pub const ENV_FN_OBJ_OFFSET: u32 = ENV_LENGTH_OFFSET + LENGTH_SIZE;
+pub const ENV_FUNC_OFFSET: u32 = ENV_FN_OBJ_OFFSET + FN_OBJ_SIZE;
+pub const ENV_ITEMS_OFFSET: u32 = ENV_FUNC_OFFSET + FUNC_SIZE;
+
+/// The byte offsets of the fields of an object, which is a pointer to the data of the object, so
+/// that adding a field can move the data without moving the object:
+///
+/// Tag | ObjectData            ObjectData = Tag | [Slot]
+///
+/// A slot is the value of a field, which is an `Option<AnyEnum>` with the representation of the
+/// any that it holds. The slots are in the order of the fields of the class of the object.
+pub const OBJECT_DATA_OFFSET: u32 = TAG_SIZE;
+pub const OBJECT_SLOTS_OFFSET: u32 = TAG_SIZE;
+pub const OBJECT_SLOT_SIZE: u32 = ANY_SIZE;
+
+/// The byte offset of the `TypeTag` in a tag, which follows the mark bit.
+pub const TYPE_TAG_OFFSET: u32 = 1;
+
+/// The byte offsets of the fields of a string, which is laid out as follows:
+///
+/// Tag | u32  | u32    | [u8]
+///        ^^     ^^^^
+///       size   length
+///
+/// The size is the number of bytes of its UTF-8 encoding, which follows the header. The length is
+/// the number of its UTF-16 code units, which JavaScript counts (e.g., `"😀".length` is 2).
+pub const STRING_SIZE_OFFSET: u32 = TAG_SIZE;
+pub const STRING_LENGTH_OFFSET: u32 = STRING_SIZE_OFFSET + LENGTH_SIZE;
+pub const STRING_BYTES_OFFSET: u32 = STRING_LENGTH_OFFSET + LENGTH_SIZE;
+
+/// The type of a value on the heap, which is the second byte of its tag.
+///
+/// Most of these are *not* values and do *not* appear in Any
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[repr(u8)]
+pub enum TypeTag {
+    Array = 0,
+    /// We specify a value so we can make tags for strings from jankscripten
+    String = 1,
+    /// This is a DynObject from the perspective of JankyScript
+    HT = 2,
+    /// This is an actual object. In practice, it's just a pointer to the
+    /// actual object data, but when choosing what type this is, it's just an
+    /// object. i think
+    ObjectPtrPtr = 3,
+    /// This is *not* the tag of an object (poor naming!!). This is the tag
+    /// of the *object data*, which is one level of indirection behind the actual
+    /// object (does it even need a tag? what the heck is going on)
+    DynObject,
+    /// This only appears as part of
+    Env,
+    /// following are immediate values only ever on the heap for Ref
+    /// this is any 32-bit value on the heap that doesn't point to / store
+    /// other values. Examples: i32, bool, fn. These won't appear in Any
+    /// (right??) because they'd be dereffed before being coerced
+    NonPtr32,
+    Any,
+    /// these should only be used for Ref, most f64s go on the f64 heap. this
+    /// avoids another layer of indirection we just put a f64 immediately
+    /// following the tag. that f64 might be modified
+    MutF64,
+    /// this may or may not be duplicated by ObjectPtrPtr
+    Ptr,
+    /// A value of a heap type that the runtime system registered, whose
+    /// number is the `class_tag`. These appear in Any as objects
+    Extension,
+}
+
+/// The discriminants of the variants of `AnyEnum` in the runtime system, which are in the least
+/// significant byte of an `any`. The payload is in the most significant 32 bits.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[repr(u8)]
+pub enum AnyDiscriminant {
+    I32 = 0,
+    F64 = 1,
+    Bool = 2,
+    Ptr = 3,
+    Closure = 4,
+    Undefined = 5,
+    Null = 6,
+}
+
+/// A method table holds the function that a call to a method runs for each kind of receiver: an
+/// entry for each discriminant of an `any`, followed by an entry for each type tag, which the
+/// receivers that are pointers use. (Thus the entry for `AnyDiscriminant::Ptr` is unused.)
+pub const METHOD_TABLE_PTRS: u32 = AnyDiscriminant::Null as u32 + 1;
+pub const METHOD_TABLE_LEN: u32 = METHOD_TABLE_PTRS + TypeTag::Extension as u32 + 1;
+
+/// Why a program trapped, which the runtime system records for the host to read with
+/// `last_trap_reason`. The code that the compiler generates only records the reasons of its own
+/// traps when the program is compiled with `checked` set, but the runtime system always records
+/// the reasons of its own.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[repr(u32)]
+pub enum TrapReason {
+    /// The program has not trapped, or trapped for a reason that was not recorded.
+    None = 0,
+    /// A `trap` statement of NotWasm, e.g., an unsupported feature of JavaScript.
+    Trap = 1,
+    /// A method call on a receiver that has no such method.
+    MethodMissing = 2,
+    /// A call to a closure whose function is not one that the dispatcher knows.
+    BadClosure = 3,
+    /// A coercion from `any` to a type that the value does not have.
+    FromAny = 4,
+    /// An allocation that does not fit in the heap, even after it grows, which the host reports
+    /// as a `RangeError`.
+    OutOfMemory = 5,
+}
+
+/// A field of a [Layout], which is `size` bytes long and starts `offset` bytes into the value.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Field {
+    pub name: &'static str,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// The layout of a value that the compiler and the runtime system share. A value is `size` bytes
+/// long, followed by `items`, if it has a variable number of them (e.g., the variables of an
+/// environment), in which case the `size` of `items` is the size of one item.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Layout {
+    pub name: &'static str,
+    pub size: u32,
+    pub fields: &'static [Field],
+    pub items: Option<Field>,
+}
+
+const fn field(name: &'static str, offset: u32, size: u32) -> Field {
+    Field { name, offset, size }
+}
+
+/// The layouts of the values that the compiler reads and writes directly, written out byte by
+/// byte. The constants above describe the same layouts, and the assertions at the end of
+/// this file check that they agree with this table. The runtime system checks that its
+/// types agree with this table in its tests, and produces it for the host with `layout_dump`.
+pub const LAYOUTS: &[Layout] = &[
+    Layout {
+        name: "Tag",
+        size: 4,
+        fields: &[
+            field("marked", 0, 1),
+            field("type_tag", 1, 1),
+            field("class_tag", 2, 2),
+        ],
+        items: None,
+    },
+    Layout {
+        name: "Any",
+        size: 8,
+        fields: &[field("discriminant", 0, 1), field("payload", 4, 4)],
+        items: None,
+    },
+    Layout {
+        name: "Closure",
+        size: 4,
+        fields: &[field("env", 0, 4)],
+        items: None,
+    },
+    Layout {
+        name: "Env",
+        size: 16,
+        fields: &[
+            field("tag", 0, 4),
+            field("length", 4, 4),
+            field("fn_obj", 8, 4),
+            field("func", 12, 4),
+        ],
+        items: Some(field("items", 16, 8)),
+    },
+    Layout {
+        name: "Object",
+        size: 8,
+        fields: &[field("tag", 0, 4), field("data", 4, 4)],
+        items: None,
+    },
+    Layout {
+        name: "ObjectData",
+        size: 4,
+        fields: &[field("tag", 0, 4)],
+        items: Some(field("slots", 4, 8)),
+    },
+    Layout {
+        name: "String",
+        size: 12,
+        fields: &[
+            field("tag", 0, 4),
+            field("size", 4, 4),
+            field("length", 8, 4),
+        ],
+        items: Some(field("bytes", 12, 1)),
+    },
+];
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// The field or the items of `layout` that are named `name`, or a field that starts at
+/// `u32::MAX` if there is no such field, so that assertions about it fail.
+const fn lookup(layout: &str, name: &str) -> Field {
+    let mut i = 0;
+    while i < LAYOUTS.len() {
+        let l = &LAYOUTS[i];
+        if str_eq(l.name, layout) {
+            let mut j = 0;
+            while j < l.fields.len() {
+                if str_eq(l.fields[j].name, name) {
+                    return l.fields[j];
+                }
+                j += 1;
+            }
+            if let Some(items) = l.items {
+                if str_eq(items.name, name) {
+                    return items;
+                }
+            }
+        }
+        i += 1;
+    }
+    field("", u32::MAX, 0)
+}
+
+/// The byte offset of a field in [LAYOUTS], e.g., `offset("Env", "func")`.
+pub const fn offset(layout: &str, name: &str) -> u32 {
+    lookup(layout, name).offset
+}
+
+/// The size of a field in [LAYOUTS], or of one of its items.
+pub const fn size(layout: &str, name: &str) -> u32 {
+    lookup(layout, name).size
+}
+
+/// Fails to compile unless the constant expression `$cond` is true. For example:
+///
+/// ```
+/// abi::assert_abi!(abi::TAG_SIZE == 4);
+/// ```
+#[macro_export]
+macro_rules! assert_abi {
+    ($cond:expr) => {
+        const _: [(); 0] = [(); (!$cond) as usize];
+    };
+}
+
+// The constants agree with the table.
+assert_abi!(size("Any", "payload") + ANY_PAYLOAD_OFFSET == ANY_SIZE);
+assert_abi!(size("Tag", "marked") + size("Tag", "type_tag") + size("Tag", "class_tag") == TAG_SIZE);
+assert_abi!(offset("Tag", "type_tag") == TYPE_TAG_OFFSET);
+assert_abi!(offset("Env", "length") == ENV_LENGTH_OFFSET);
+assert_abi!(offset("Env", "fn_obj") == ENV_FN_OBJ_OFFSET);
+assert_abi!(offset("Env", "func") == ENV_FUNC_OFFSET);
+assert_abi!(offset("Env", "items") == ENV_ITEMS_OFFSET);
+assert_abi!(size("Env", "items") == ANY_SIZE);
+assert_abi!(offset("Object", "data") == OBJECT_DATA_OFFSET);
+assert_abi!(offset("ObjectData", "slots") == OBJECT_SLOTS_OFFSET);
+assert_abi!(size("ObjectData", "slots") == OBJECT_SLOT_SIZE);
+assert_abi!(offset("String", "size") == STRING_SIZE_OFFSET);
+assert_abi!(offset("String", "length") == STRING_LENGTH_OFFSET);
+assert_abi!(offset("String", "bytes") == STRING_BYTES_OFFSET);
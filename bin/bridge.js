@@ -0,0 +1,34 @@
+// Converts values between JavaScript and the runtime system of jankscripten
+// (see runtime/src/bridge.rs), so that a host can call the functions that a
+// program exports (with `jankscripten compile --export NAME`) with JavaScript
+// values and read their results.
+//
+// `runtime` is the exports of the instance of the runtime system. Values of
+// type `any` cross the boundary as BigInts.
+
+// Copies `text` into the buffer of the runtime system.
+function writeText(runtime, text) {
+    const bytes = new TextEncoder().encode(text);
+    const ptr = runtime.host_buffer(bytes.length);
+    new Uint8Array(runtime.memory.buffer, ptr, bytes.length).set(bytes);
+}
+
+// Converts a string, or a value that JSON can represent, to an `any`.
+function toRuntime(runtime, value) {
+    if (typeof value === 'string') {
+        writeText(runtime, value);
+        return runtime.host_string_to_heap();
+    }
+    writeText(runtime, JSON.stringify(value));
+    return runtime.host_json_to_heap();
+}
+
+// Converts an `any` to a JavaScript value, through JSON.
+function fromRuntime(runtime, any) {
+    const ptr = runtime.heap_to_host_json(any);
+    const view = new Uint8Array(runtime.memory.buffer, ptr);
+    const text = new TextDecoder().decode(view.subarray(0, view.indexOf(0)));
+    return JSON.parse(text);
+}
+
+module.exports = { toRuntime, fromRuntime };
@@ -7,12 +7,48 @@ const process = require('process');
 const assert = require('assert');
 const path = require('path');
 
+// Usage: node run.js FILENAME [--release] [--profile=PROFILE] [--seed=SEED]
+//                  [--clock=MS] [--trace-coercions] [--heap-size=BYTES]
+//
+// With --profile, the program must be compiled with --profile-gen, and this
+// script writes its profile to PROFILE after the run.
+//
+// With --trace-coercions, the program must be compiled with --trace-coercions,
+// and this script prints the coercions to and from any that ran to standard
+// error after the run, hottest first.
+//
+// With --seed, Math.random is a pseudo-random number generator with the given
+// seed, and with --clock, Date.now is a virtual clock that starts at MS (see
+// runtime/src/nondet.rs). Otherwise, they are the ones of Node.
+//
+// With --heap-size, the heap has the given size and does not grow, which
+// tests of the garbage collector use to collect often.
 let build = "debug";
-if (process.argv.length === 4 && process.argv[3] === "--release") {
-    build = "release";
-} else {
-    assert(process.argv.length === 3);
+let profilePath = undefined;
+let traceCoercions = false;
+let programPath = undefined;
+let seed = undefined;
+let clock = undefined;
+let heapSize = undefined;
+for (const arg of process.argv.slice(2)) {
+    if (arg === "--release") {
+        build = "release";
+    } else if (arg.startsWith("--profile=")) {
+        profilePath = arg.slice("--profile=".length);
+    } else if (arg === "--trace-coercions") {
+        traceCoercions = true;
+    } else if (arg.startsWith("--seed=")) {
+        seed = Number(arg.slice("--seed=".length));
+    } else if (arg.startsWith("--clock=")) {
+        clock = Number(arg.slice("--clock=".length));
+    } else if (arg.startsWith("--heap-size=")) {
+        heapSize = Number(arg.slice("--heap-size=".length));
+    } else {
+        assert(programPath === undefined);
+        programPath = arg;
+    }
 }
+assert(programPath !== undefined);
 
 // Allows us to call this script from any directory.
 const runtimePath = path.normalize(path.join(path.dirname(process.argv[1]),
@@ -20,6 +56,15 @@ const runtimePath = path.normalize(path.join(path.dirname(process.argv[1]),
 
 // keep a WebAssembly memory reference for `readString`
 let memory;
+// The reason that the program trapped, when it was compiled with --checked
+// (see runtime/src/trap.rs).
+let lastTrapReason = () => 0;
+// The names of the variants of TrapReason in abi/src/lib.rs.
+const trapReasons = ["None", "Trap", "MethodMissing", "BadClosure", "FromAny", "OutOfMemory"];
+// The dispatcher of the program, which calls closures for the runtime system
+// (see runtime/src/closure.rs). The program is instantiated after the runtime
+// system, so the runtime system calls it indirectly.
+let callClosure;
 
 function c_string(startOffset) {
     let view = new Uint8Array(memory.buffer, startOffset);
@@ -41,10 +86,38 @@ const imports = {
         jankscripten_error: function(startOffset) {
             let view = c_string(startOffset);
             console.error((new TextDecoder()).decode(view));
-        }
+        },
+        jankscripten_call_closure: function(closure, args, nargs) {
+            return callClosure(closure, args, nargs);
+        },
+        jankscripten_random: Math.random,
+        jankscripten_now: Date.now
     },
 };
 
+// The asynchronous operations that a program may start, indexed by their
+// number (see runtime/src/host.rs). Each receives a number and produces a
+// promise of a number.
+const hostOperations = [
+    // 0: A timer that waits for the given number of milliseconds.
+    ms => new Promise(resolve => setTimeout(() => resolve(0), ms)),
+];
+
+// Performs the asynchronous operations that the program started, and resumes
+// the program when each one completes, until the program starts no more.
+async function runHostOperations(exports, resume) {
+    const pending = [];
+    for (let id = exports.host_next(); id !== -1; id = exports.host_next()) {
+        const op = hostOperations[exports.host_op(id)];
+        assert(op !== undefined, `unknown host operation ${exports.host_op(id)}`);
+        pending.push(op(exports.host_arg(id)).then(result => {
+            resume(id, result);
+            return runHostOperations(exports, resume);
+        }));
+    }
+    await Promise.all(pending);
+}
+
 async function main(filename) {
     if (filename === '-') {
         filename = process.stdin.fd;
@@ -59,17 +132,62 @@ async function main(filename) {
     });
     const exports = runtimeInstance.exports;
     memory = exports.memory;
+    lastTrapReason = exports.last_trap_reason;
+    callClosure = programInstance.exports.call_closure;
+    if (seed !== undefined) {
+        exports.nondet_set_seed(seed);
+    }
+    // A runtime system without the date feature has no clock to set.
+    if (clock !== undefined && exports.nondet_set_clock !== undefined) {
+        exports.nondet_set_clock(clock);
+    }
+    if (heapSize !== undefined) {
+        exports.heap_set_size(heapSize);
+    }
     const startTime = Date.now();
     const result = programInstance.exports.main();
+    // Run the tasks of setTimeout after main, and after each continuation.
+    const runMicrotasks = programInstance.exports.run_microtasks || (() => {});
+    runMicrotasks();
+    if (programInstance.exports.resume !== undefined) {
+        await runHostOperations(exports, (id, result) => {
+            programInstance.exports.resume(id, result);
+            runMicrotasks();
+        });
+    }
     const endTime = Date.now();
     console.error(`Running time: ${endTime - startTime}ms`);
+    if (profilePath !== undefined) {
+        const profile = exports.profile_serialize();
+        if (profile === 0) {
+            throw new Error('the program was not compiled with --profile-gen');
+        }
+        fs.writeFileSync(profilePath, c_string(profile));
+    }
+    if (traceCoercions) {
+        const report = exports.profile_coercion_report();
+        if (report === 0) {
+            throw new Error('the program was not compiled with --trace-coercions');
+        }
+        console.error('Coercions (count, coercion):');
+        process.stderr.write(c_string(report));
+    }
     return result;
 }
 
 
-main(process.argv[2])
+main(programPath)
     .catch(err => {
+        const reason = lastTrapReason();
+        // The runtime system traps when the heap cannot grow, since NotWasm has
+        // no exceptions. JavaScript reports this as a RangeError.
+        if (err instanceof WebAssembly.RuntimeError && trapReasons[reason] === "OutOfMemory") {
+            err = new RangeError("out of memory");
+        }
         console.error(err);
+        if (err instanceof WebAssembly.RuntimeError && reason !== 0) {
+            console.error(`Trap reason: ${trapReasons[reason] || reason}`);
+        }
         process.exit(1);
     })
     .then(result => {
@@ -0,0 +1,9 @@
+function any(x) {
+    return x;
+}
+any(5); // Force any to be polymorphic
+let s = "hello";
+console.log(s.length);
+console.log(any(s).length);
+console.log(any([1, 2, 3]).length);
+console.log(any({ length: 4 }).length);
@@ -0,0 +1,10 @@
+var xs = [1, 2, 3];
+var ys = [0, ...xs, 4];
+var copy = [...xs];
+copy.push(10);
+
+console.log(ys.length);
+console.log(ys[0] + ys[1] + ys[3] + ys[4]);
+console.log(xs.length);
+console.log(copy.length);
+console.log([...xs, ...ys].length);
@@ -0,0 +1,15 @@
+// An async function awaits host operations, and the host resumes it when each one completes.
+function sleep(ms) {
+    return { op: 0, arg: ms };
+}
+async function countdown(name, n, ms) {
+    while (n > 0) {
+        var result = await sleep(ms);
+        console.log(name + " " + n + " " + result);
+        n = n - 1;
+    }
+    console.log(name + " done");
+}
+countdown("a", 2, 20);
+countdown("b", 1, 30);
+console.log("started");
@@ -0,0 +1,10 @@
+// ToBoolean on each kind of value, and booleans compared as numbers.
+var values = [0, 1, 0 / 0, "", "0", null, undefined, {}, []];
+for (var i = 0; i < values.length; i++) {
+    console.log(Boolean(values[i]) === !!values[i]);
+    console.log(!values[i]);
+}
+console.log(true == 1);
+console.log("1" == true);
+console.log(true > false);
+console.log(false < 0.5);
@@ -0,0 +1,17 @@
+function add3(x, y, z) {
+    return x + y + z;
+}
+
+var counter = {
+    count: 10,
+    add: function(x, y) {
+        return this.count + x + y;
+    }
+};
+
+var xs = [1, 2, 3];
+console.log(add3(...xs));
+console.log(add3(100, ...[20, 3]));
+console.log(add3(...[1], ...[2, 3]));
+console.log(add3(...[1, 2]));
+console.log(counter.add(...[1, 2]));
@@ -0,0 +1,6 @@
+// Char codes are UTF-16 code units, and code points combine surrogate pairs.
+let s = "h😀";
+console.log(s.charCodeAt(0));
+console.log(s.codePointAt(1));
+console.log(s.codePointAt(2));
+console.log(String.fromCharCode(104) + String.fromCharCode(65536 + 105));
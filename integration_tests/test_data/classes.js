@@ -0,0 +1,48 @@
+// A small hierarchy in the style of DeltaBlue: a base class, a subclass that
+// calls its superclass's constructor and methods, and static methods.
+
+class Constraint {
+    constructor(strength) {
+        this.strength = strength;
+    }
+
+    isSatisfied() {
+        return false;
+    }
+
+    describe() {
+        return "constraint of strength " + this.strength;
+    }
+
+    static required() {
+        return 0;
+    }
+}
+
+class UnaryConstraint extends Constraint {
+    constructor(variable, strength) {
+        super(strength);
+        this.variable = variable;
+        this.satisfied = false;
+    }
+
+    satisfy() {
+        this.satisfied = true;
+    }
+
+    isSatisfied() {
+        return this.satisfied;
+    }
+
+    describe() {
+        return "unary " + super.describe() + " on " + this.variable;
+    }
+}
+
+var c = new UnaryConstraint("v", Constraint.required());
+console.log(c.describe());
+console.log(c.isSatisfied());
+c.satisfy();
+console.log(c.isSatisfied());
+console.log(c instanceof Constraint);
+console.log(new Constraint(3).isSatisfied());
@@ -0,0 +1,25 @@
+function counter() {
+    var n = 0;
+    n = n + 10;
+    var get = function() { return n; };
+    return get;
+}
+log_any(counter()());
+
+var x = 1;
+x = 2;
+var f = function() { return x; };
+log_any(f());
+x = 3;
+log_any(f());
+
+var first = function() { return -1; };
+var i = 0;
+while (i < 3) {
+    var j = i;
+    if (i == 0) {
+        first = function() { return j; };
+    }
+    i = i + 1;
+}
+log_any(first());
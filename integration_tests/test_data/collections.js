@@ -0,0 +1,38 @@
+var m = new Map([["a", 1], ["b", 2]]);
+var key = {};
+m.set(key, 3);
+m.set(1, 4);
+m.set(1.0, 5);
+m.set("a", 6);
+console.log(m.size);
+console.log(m.get("a") + m.get(key) + m.get(1));
+console.log(m.get({}) === undefined);
+console.log(m.has("b"));
+console.log(m.delete("b"));
+console.log(m.delete("b"));
+var keys = "";
+m.forEach(function(value, key) {
+    keys = keys + typeof key + value;
+});
+console.log(keys);
+var sum = 0;
+for (var entry of m.entries()) {
+    sum = sum + entry[1];
+}
+console.log(sum);
+var s = new Set();
+s.add("x").add("y").add("x");
+s.add(2);
+console.log(s.size);
+var total = "";
+s.forEach(function(value) {
+    total = total + value;
+});
+console.log(total);
+var last = "";
+for (var value of s.values()) {
+    last = value;
+}
+console.log(last);
+s.clear();
+console.log(s.size + m.size);
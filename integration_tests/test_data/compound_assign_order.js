@@ -0,0 +1,22 @@
+// Compound assignments evaluate the subexpressions of the lvalue once, and
+// read the old value before evaluating the right-hand side.
+var calls = 0;
+var o = { x: 1 };
+function obj() {
+    calls = calls + 1;
+    return o;
+}
+function val() {
+    o.x = 10;
+    return 1;
+}
+obj().x += val();
+console.log(o.x);
+console.log(calls);
+var s = "5";
+var old = s++;
+console.log(old);
+console.log(s);
+var n = 0;
+var t = false && n++;
+console.log(n);
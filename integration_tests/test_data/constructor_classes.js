@@ -0,0 +1,18 @@
+// `Point` only assigns fields of `this`, so `new Point` creates objects that
+// already have the fields `x` and `y`.
+function Point(x, y) {
+    this.x = x;
+    this.y = y;
+    this.x = x * 10;
+}
+
+Point.prototype.sum = function() {
+    return this.x + this.y;
+}
+
+var p = new Point(1, 2);
+log_any(p.sum());
+var q = new Point(3, 4);
+q.z = 5;
+log_any(q.sum() + q.z);
+log_any(p.z);
@@ -0,0 +1,16 @@
+function greet(name, greeting = "Hello") {
+    return greeting + ", " + name;
+}
+
+function scale(x, factor = 2, offset = factor * 10) {
+    return x * factor + offset;
+}
+
+console.log(greet("Ada"));
+console.log(greet("Ada", undefined));
+console.log(greet("Ada", "Hi"));
+console.log(scale(3));
+console.log(scale(3, undefined, undefined));
+console.log(scale(3, 4));
+console.log(scale(3, 4, undefined));
+console.log(scale(3, 4, 0));
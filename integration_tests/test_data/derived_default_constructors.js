@@ -0,0 +1,28 @@
+class Animal {
+    constructor(name, legs) {
+        this.name = name;
+        this.legs = legs;
+    }
+    describe() {
+        return this.name + " has " + this.legs + " legs";
+    }
+}
+
+class Dog extends Animal {
+    bark() {
+        return this.name + " barks";
+    }
+}
+
+class Puppy extends Dog {
+    describe() {
+        return "little " + super.describe();
+    }
+}
+
+var d = new Dog("Rex", 4);
+var p = new Puppy("Bit", 4);
+console.log(d.describe());
+console.log(d.bark());
+console.log(p.describe());
+console.log(p instanceof Animal);
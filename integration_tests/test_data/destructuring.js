@@ -0,0 +1,15 @@
+function sum([a, b], {x, y: z}) {
+    return a + b + x + z;
+}
+
+var point = {x: 10, y: 20};
+var [first, , third] = [1, 2, 3];
+var {x, y} = point;
+var [p, {q, r: [s, t]}] = [4, {q: 5, r: [6, 7]}];
+var u = 8, [v, w] = [u + 1, u + 2];
+
+console.log(first + third);
+console.log(x + y);
+console.log(p + q + s + t);
+console.log(v * w);
+console.log(sum([1, 2], {x: 3, y: 4}));
@@ -0,0 +1,15 @@
+var e = new TypeError("bad");
+console.log(e.name);
+console.log(e.message);
+console.log(e instanceof TypeError);
+console.log(e instanceof Error);
+console.log(e instanceof RangeError);
+console.log(typeof e.stack);
+console.log(e.toString());
+console.log("" + new RangeError(42));
+var plain = Error();
+console.log(plain.message === "");
+console.log(plain.toString());
+var s = SyntaxError("oops");
+console.log(s instanceof SyntaxError);
+console.log(s.message);
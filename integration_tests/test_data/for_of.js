@@ -0,0 +1,36 @@
+function* range(n) {
+    var i = 0;
+    while (i < n) {
+        yield i;
+        i = i + 1;
+    }
+}
+
+function sum(xs) {
+    var total = 0;
+    for (var x of xs) {
+        total = total + x;
+    }
+    return total;
+}
+
+var s = "";
+for (var c of "abc") {
+    s = c + s;
+}
+console.log(s);
+for (var n of [1, 2, 3]) {
+    if (n === 2) {
+        continue;
+    }
+    console.log(n);
+}
+console.log(sum([10, 20, 30]));
+console.log(sum(range(5)));
+var last = 0;
+for (last of range(10)) {
+    if (last === 3) {
+        break;
+    }
+}
+console.log(last);
@@ -0,0 +1,49 @@
+function* range(start, end) {
+    for (var i = start; i < end; i++) {
+        yield i;
+    }
+}
+
+function* fib() {
+    var a = 0;
+    var b = 1;
+    while (true) {
+        yield a;
+        var t = a + b;
+        a = b;
+        b = t;
+    }
+}
+
+function* odds(limit) {
+    var n = 0;
+    while (true) {
+        n++;
+        if (n > limit) {
+            return;
+        }
+        if (n % 2 === 0) {
+            continue;
+        }
+        yield n;
+    }
+}
+
+var it = range(3, 6);
+var r = it.next();
+while (!r.done) {
+    console.log(r.value);
+    r = it.next();
+}
+
+var f = fib();
+var sum = 0;
+for (var k = 0; k < 10; k++) {
+    sum = sum + f.next().value;
+}
+console.log(sum);
+
+var o = odds(5);
+console.log(o.next().value + o.next().value + o.next().value);
+console.log(o.next().done);
+console.log(o.next().done);
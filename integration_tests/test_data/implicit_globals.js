@@ -0,0 +1,9 @@
+function init() {
+    total = 40;
+}
+init();
+total = total + 2;
+log_any(total);
+log_any(typeof notDefined);
+var x = 5;
+log_any(globalThis.x);
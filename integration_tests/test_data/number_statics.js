@@ -0,0 +1,17 @@
+console.log(Number.MAX_SAFE_INTEGER);
+console.log(Number.MIN_SAFE_INTEGER);
+console.log(Number.EPSILON > 0 && 1 + Number.EPSILON > 1);
+console.log(Number.MAX_VALUE > 1e308);
+console.log(Number.MIN_VALUE > 0 && Number.MIN_VALUE / 2 === 0);
+console.log(Number.POSITIVE_INFINITY > Number.MAX_VALUE);
+console.log(Number.NEGATIVE_INFINITY < -Number.MAX_VALUE);
+console.log(Number.isNaN(Number.NaN));
+console.log(Number.isNaN("abc"));
+console.log(Number.isInteger(5));
+console.log(Number.isInteger(5.5));
+console.log(Number.isInteger("5"));
+console.log(Number.isFinite(1 / 3));
+console.log(Number.isFinite(Number.POSITIVE_INFINITY));
+console.log(Number.parseFloat("3.25kg"));
+console.log(Number.parseFloat("  -2e3"));
+console.log(Number.isNaN(Number.parseFloat("kg")));
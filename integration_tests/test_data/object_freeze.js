@@ -0,0 +1,22 @@
+var point = { x: 1, y: 2 };
+console.log(Object.isFrozen(point));
+Object.freeze(point);
+point.x = 10;
+point.z = 3;
+console.log(point.x);
+console.log(point.z);
+console.log(Object.isFrozen(point));
+console.log(Object.isSealed(point));
+
+var config = { debug: false };
+// Library code often feature-tests these functions before using them.
+if (Object.seal) {
+    Object.seal(config);
+}
+config.debug = true;
+config.verbose = true;
+console.log(config.debug);
+console.log(config.verbose);
+console.log(Object.isSealed(config));
+console.log(Object.isFrozen(config));
+console.log(Object.isFrozen(5));
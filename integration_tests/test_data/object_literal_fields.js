@@ -0,0 +1,11 @@
+function point(x, y) {
+    return { x: x, y: y, x: x + 1 };
+}
+var p = point(1, 2);
+p.z = p.x + p.y;
+var o = { name: "o", inner: { value: 3.5 } };
+console.log(p.x);
+console.log(p.y);
+console.log(p.z);
+console.log(o.name);
+console.log(o.inner.value);
@@ -0,0 +1,11 @@
+// Object literals start with a class that has all of their fields, so a
+// repeated key is a single field and the order of the keys matters.
+let p = {x: 1, y: 2, x: 3};
+log_any(p.x + p.y);
+let q = {y: 10, x: 20};
+log_any(q.x - q.y);
+// Object literals still inherit from `Object.prototype`.
+Object.prototype.sum = function() {
+    return this.x + this.y;
+}
+log_any(q.sum());
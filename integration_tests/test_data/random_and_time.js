@@ -0,0 +1,6 @@
+var r = Math.random();
+console.log(r >= 0 && r < 1);
+console.log(Math.random() !== r);
+var t = Date.now();
+console.log(t > 0);
+console.log(Date.now() >= t);
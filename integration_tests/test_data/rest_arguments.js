@@ -0,0 +1,22 @@
+function count(...xs) {
+    return xs.length;
+}
+
+function sum(first, ...rest) {
+    var total = first;
+    for (var i = 0; i < rest.length; i++) {
+        total = total + rest[i];
+    }
+    return total;
+}
+
+function label(name = "x", ...values) {
+    return name + ": " + values.length;
+}
+
+console.log(count());
+console.log(count(1, 2, 3));
+console.log(sum(1));
+console.log(sum(1, 2, 3, 4));
+console.log(label());
+console.log(label("ys", 10, 20));
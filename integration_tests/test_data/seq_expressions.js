@@ -0,0 +1,17 @@
+// The comma operator evaluates its operands in order and produces the last one,
+// in any position.
+var log = "";
+function f(x) {
+    log = log + x;
+    return x;
+}
+var x = 1;
+console.log(x + (x = 10, x));
+console.log(f("a") + (f("b"), f("c")));
+var y = false && (f("d"), 1);
+console.log(log);
+var r = "";
+for (var i = 0, j = 3; i < j; i++, j--) {
+    r = r + i + j;
+}
+console.log(r);
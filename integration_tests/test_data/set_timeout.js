@@ -0,0 +1,8 @@
+// Callbacks run after the program, in the order in which they are due.
+function later() {
+    console.log("later");
+    setTimeout(function() { console.log("last"); });
+}
+setTimeout(later, 10);
+setTimeout(function() { console.log("soon"); }, 0);
+console.log("first");
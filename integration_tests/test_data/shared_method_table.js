@@ -0,0 +1,14 @@
+// Every call to slice with two arguments shares one method table, whatever
+// the receiver is.
+function any(x) {
+    return x;
+}
+function add(a, b) {
+    return a + b;
+}
+any(5);
+let xs = [1, 2, 3];
+console.log(any("hello").slice(1, 3));
+console.log(any(xs).slice(1, 3).length);
+console.log(any({ slice: add }).slice(1, 3));
+console.log(any("world").slice(0, 1));
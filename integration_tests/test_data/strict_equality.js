@@ -0,0 +1,19 @@
+function make() {
+    return function() { return 1; };
+}
+var o = {};
+var p = {};
+var a = [1];
+var f = make();
+var g = make();
+var half = 1 / 2;
+var nan = 0 / 0;
+console.log(o === o);
+console.log(o === p);
+console.log(a === a);
+console.log(a === [1]);
+console.log(f === f);
+console.log(f === g);
+console.log(half === 0.5);
+console.log(nan === nan);
+console.log("ab" === "a" + "b");
@@ -0,0 +1,8 @@
+// Arithmetic operators apply ToNumber to strings that look like numbers.
+var three = "3";
+console.log(three * 2);
+console.log(" 0x10 " - 1);
+console.log("-Infinity" * 1 < -1e308);
+console.log("12px" * 1);
+console.log(true + 1);
+console.log("" - 5);
@@ -0,0 +1,16 @@
+var a = "ab";
+var b = "a" + "b";
+var c = "abc";
+console.log(a === "ab");
+console.log(a === b);
+console.log(a === c);
+console.log(a !== c);
+console.log(a == b);
+console.log(a != b);
+var n = 0;
+for (var i = 0; i < 3; i = i + 1) {
+    if (c.charAt(i) === "b") {
+        n = n + 1;
+    }
+}
+console.log(n);
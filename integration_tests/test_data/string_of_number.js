@@ -0,0 +1,11 @@
+var s = "";
+for (var i = 250; i < 260; i = i + 1) {
+    s = s + String(i).length;
+}
+console.log(s);
+console.log(String(7) === "7");
+console.log(String(2.5));
+console.log(String(true) + String());
+console.log("" + 12 === String(12));
+console.log(String({}));
+console.log(String.fromCharCode(72));
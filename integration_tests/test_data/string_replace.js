@@ -0,0 +1,12 @@
+// replace replaces the first occurrence of the pattern, and calls a function
+// replacer back from the runtime system.
+function any(x) {
+    return x;
+}
+any(5);
+let s = "a-b-c";
+console.log(s.replace("-", "+"));
+console.log(s.replace("-", "[$&|$'|$$]"));
+console.log(s.replace("x", "y"));
+console.log(s.replace("b", function(m) { return m + m; }));
+console.log(any(s).replace("c", function(m, i) { return i; }));
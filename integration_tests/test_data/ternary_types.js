@@ -0,0 +1,10 @@
+function pick(b) {
+    return b ? 1 : "one";
+}
+var n = 5;
+var m = n > 2 ? n * 2 : n + 1;
+console.log(m + 1);
+console.log(pick(true));
+console.log(pick(false));
+var f = n > 2 ? function(x) { return x + n; } : 0;
+console.log(f(1));
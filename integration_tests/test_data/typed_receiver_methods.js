@@ -0,0 +1,11 @@
+function cut(s, i, j) {
+    var t = "<" + s + ">";
+    return t.slice(i, j);
+}
+function join(a, b) {
+    var xs = [1, 2];
+    return xs.concat(b).length + a;
+}
+console.log(cut("hello", 1, 3));
+console.log(cut("world", "2", 4));
+console.log(join(1, [3, 4]));
@@ -0,0 +1,8 @@
+// The URI-encoding global functions percent-encode UTF-8, and escape encodes
+// UTF-16 code units.
+let s = "a b&c=d/é€";
+let encoded = encodeURIComponent(s);
+console.log(encoded);
+console.log(decodeURIComponent(encoded));
+console.log(decodeURIComponent("%e2%82%ac!"));
+console.log(escape(s));
@@ -0,0 +1,10 @@
+// Strings are sequences of UTF-16 code units, so a character outside the
+// basic multilingual plane counts twice.
+let s = "aé😀b";
+console.log(s.length);
+console.log(s.slice(1, 4));
+console.log(s.slice(-1, 5));
+console.log(s.charAt(4));
+console.log(s.charCodeAt(2));
+console.log(s.charCodeAt(3));
+console.log("plain".charCodeAt(0));
@@ -21,6 +21,66 @@ struct Compile {
     /// Dump the offsets of interned strings to the console (for debugging).
     #[clap(long)]
     dump_interned: bool,
+    /// Print a report of the call graph of the NotWasm program to standard error, including how
+    /// many calls are monomorphic.
+    #[clap(long)]
+    analyze: bool,
+    /// Instrument the program to count calls and inline cache lookups. Run the program with
+    /// `bin/run.js --profile=FILE` to write the counts to FILE.
+    #[clap(long)]
+    profile_gen: bool,
+    /// Instrument the program to count the coercions to and from any that run. Run the program
+    /// with `bin/run.js --trace-coercions` to report the hottest coercions.
+    #[clap(long)]
+    trace_coercions: bool,
+    /// Use a profile that an instrumented run of the same program produced.
+    #[clap(long)]
+    profile_use: Option<String>,
+    /// Stop the program with a RangeError when calls nest more deeply than this.
+    #[clap(long)]
+    max_call_depth: Option<u32>,
+    /// Record why the program traps, which `bin/run.js` reports after the program stops (for
+    /// debugging miscompiles).
+    #[clap(long)]
+    checked: bool,
+    /// Export a NotWasm function of the program, so that a host can call it (may be repeated).
+    #[clap(long = "export")]
+    exports: Vec<String>,
+    /// Print the intermediate representation to standard error after a pass, e.g., typeinf or
+    /// intern (may be repeated).
+    #[clap(long)]
+    print_after: Vec<String>,
+    /// Skip a pass (may be repeated). Only for debugging: the passes that follow may rely on it.
+    #[clap(long = "disable-pass")]
+    disable_passes: Vec<String>,
+    /// Report the wall time of each pass to standard error.
+    #[clap(long)]
+    time_passes: bool,
+    /// Do not infer types with Z3: every variable, argument, and function result is any.
+    #[clap(long)]
+    dynamic_types: bool,
+    /// Set the cost of a kind of coercion for type inference, as NAME=COST (may be repeated).
+    /// The names are literal, value, assign, operator, elimination, call, and float_op.
+    #[clap(long = "typeinf-weight")]
+    typeinf_weights: Vec<String>,
+    /// Report the cost of the types that type inference picks to standard error.
+    #[clap(long)]
+    print_typeinf_objective: bool,
+    /// Write a tab-separated report of the variables, arguments, and function results that lose
+    /// their types when the functions of the program must be callable from untyped code.
+    #[clap(long)]
+    migration_report: Option<String>,
+    /// Emit the WebAssembly text format (wat) or an intermediate representation instead of
+    /// WebAssembly. The output goes to standard output, unless --output is given.
+    #[clap(long, default_value = "wasm", possible_values = &["wasm", "wat", "jankyscript", "notwasm"])]
+    emit: String,
+    /// The features of the runtime system that the program runs with, when it is built without
+    /// some of its default features (may be repeated, e.g., --runtime-feature uri).
+    #[clap(long = "runtime-feature")]
+    runtime_features: Option<Vec<String>>,
+    /// Fail if the module is larger than this many bytes, and list its largest functions.
+    #[clap(long)]
+    max_module_size: Option<usize>,
 }
 
 #[derive(Clap)]
@@ -42,14 +102,57 @@ struct Opts {
 
 impl Compile {
     fn libjankscripten_opts(&self) -> libjankscripten::opts::Opts {
-        let mut compile_opts = libjankscripten::opts::Opts::new();
-        if self.disable_gc {
-            compile_opts.disable_gc = true;
-        }
         let p = self.stdlib.as_ref().unwrap();
         let stdlib_source_code = fs::read_to_string(p).expect(&format!("reading {}", p));
-        compile_opts.notwasm_stdlib_source_code = stdlib_source_code;
-        compile_opts
+        let stdlib_includes = libjankscripten::notwasm::stdlib::read_includes(Path::new(p))
+            .expect(&format!("reading the includes of {}", p));
+        let mut typeinf_weights = libjankscripten::opts::TypeinfWeights::default();
+        for setting in &self.typeinf_weights {
+            if let Err(err) = typeinf_weights.set(setting) {
+                eprintln!("--typeinf-weight: {}", err);
+                process::exit(1);
+            }
+        }
+        let profile_use = self.profile_use.as_ref().map(|p| {
+            let profile = read_file(Path::new(p));
+            match libjankscripten::profile::Profile::parse(&profile) {
+                Ok(profile) => profile,
+                Err(err) => {
+                    eprintln!("{}: {}", p, err);
+                    process::exit(1);
+                }
+            }
+        });
+        let compile_opts = libjankscripten::opts::Opts::builder()
+            .disable_gc(self.disable_gc)
+            .stdlib(stdlib_source_code)
+            .stdlib_includes(stdlib_includes)
+            .profile_gen(self.profile_gen)
+            .trace_coercions(self.trace_coercions)
+            .profile_use(profile_use)
+            .max_call_depth(self.max_call_depth)
+            .checked(self.checked)
+            .exports(self.exports.clone())
+            .print_after(self.print_after.clone())
+            .disable_passes(self.disable_passes.clone())
+            .time_passes(self.time_passes)
+            .dynamic_types(self.dynamic_types)
+            .typeinf_weights(typeinf_weights)
+            .print_typeinf_objective(self.print_typeinf_objective)
+            .runtime_features(
+                self.runtime_features
+                    .clone()
+                    .unwrap_or_else(libjankscripten::notwasm::runtime_features),
+            )
+            .max_module_size(self.max_module_size)
+            .build();
+        match compile_opts {
+            Ok(compile_opts) => compile_opts,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
     }
 }
 
@@ -95,11 +198,54 @@ fn expect_extension(p: &Path) -> &str {
     }
 }
 
+fn emit_ir(opts: &Compile, ir: String) {
+    match &opts.output {
+        Some(name) => fs::write(name, ir).expect("writing IR output"),
+        None => print!("{}", ir),
+    }
+}
+
+fn report_call_graph(opts: &Compile, notwasm: &libjankscripten::notwasm::syntax::Program) {
+    if opts.analyze {
+        let graph = libjankscripten::notwasm::call_graph::call_graph(notwasm);
+        eprint!("{}", graph.report());
+    }
+}
+
+/// The number of sites in the report of the profile that --analyze prints.
+const HOTTEST_SITES: usize = 10;
+
+fn report_profile(opts: &Compile, profile: &Option<libjankscripten::profile::Profile>) {
+    if let (true, Some(profile)) = (opts.analyze, profile) {
+        eprintln!("Hottest sites in the profile:");
+        for (site, count) in profile.hottest().into_iter().take(HOTTEST_SITES) {
+            eprintln!("  {} {}", count, site);
+        }
+    }
+}
+
 fn compile_notwasm(opts: Compile, input: &str, output: &Path) {
     use libjankscripten::notwasm;
     let parsed = notwasm::parse(opts.input.as_str(), input);
+    match opts.emit.as_str() {
+        "wasm" | "wat" => (),
+        "notwasm" => return emit_ir(&opts, parsed.to_string()),
+        other => {
+            eprintln!("Cannot emit {} from NotWasm.", other);
+            process::exit(1);
+        }
+    }
 
-    let wasm = match notwasm::compile(&mut opts.libjankscripten_opts(), parsed, |_| ()) {
+    let mut compile_opts = opts.libjankscripten_opts();
+    report_profile(&opts, &compile_opts.profile_use);
+    let inspect = |notwasm: &notwasm::syntax::Program| report_call_graph(&opts, notwasm);
+    let compiled = if opts.emit == "wat" {
+        notwasm::compile_to_wat(&mut compile_opts, parsed, inspect)
+            .map(|(wat, interned_strings)| (wat.into_bytes(), interned_strings))
+    } else {
+        notwasm::compile(&mut compile_opts, parsed, inspect)
+    };
+    let wasm = match compiled {
         Ok((wasm, interned_strings)) => {
             if opts.dump_interned {
                 display_interned_strings_offset(interned_strings);
@@ -108,6 +254,9 @@ fn compile_notwasm(opts: Compile, input: &str, output: &Path) {
         }
         Err(e) => panic!("{}", e),
     };
+    if opts.emit == "wat" {
+        return emit_ir(&opts, String::from_utf8(wasm).unwrap());
+    }
     fs::write(output, wasm).expect("writing file");
 }
 
@@ -120,10 +269,34 @@ fn compile(opts: Compile) {
             let input = read_file(input_path);
             compile_notwasm(opts, &input, output_path.as_path());
         }
+        "js" if opts.emit != "wasm" => {
+            let js_code = read_file(input_path);
+            let ir = match opts.emit.as_str() {
+                "jankyscript" => libjankscripten::Ir::JankyScript,
+                "wat" => libjankscripten::Ir::Wat,
+                _ => libjankscripten::Ir::NotWasm,
+            };
+            let output = libjankscripten::javascript_to_ir(
+                opts.libjankscripten_opts(),
+                &opts.input,
+                &js_code,
+                ir,
+            )
+            .expect("compile error");
+            emit_ir(&opts, output);
+        }
         "js" => {
             let js_code = read_file(input_path);
+            let compile_opts = opts.libjankscripten_opts();
+            if let Some(report_path) = &opts.migration_report {
+                let report =
+                    libjankscripten::migration_report(&compile_opts, &opts.input, &js_code)
+                        .expect("compile error");
+                fs::write(report_path, report.to_string()).expect("writing migration report");
+            }
+            report_profile(&opts, &compile_opts.profile_use);
             let (wasm_bin, interned_strings) = libjankscripten::javascript_to_wasm(
-                opts.libjankscripten_opts(),
+                compile_opts,
                 &opts.input,
                 &js_code,
                 |janky| {
@@ -135,6 +308,7 @@ fn compile(opts: Compile) {
                     if opts.notwasm_dump {
                         eprintln!("{}", notwasm);
                     }
+                    report_call_graph(&opts, notwasm);
                 },
             )
             .expect("compile error");
@@ -168,7 +342,7 @@ fn parse_javascript(
     input: &String,
     input_path: &String,
 ) -> libjankscripten::javascript::Stmt {
-    match libjankscripten::javascript::parse(src_name, &input) {
+    match libjankscripten::javascript::parse_with_recovery(src_name, &input) {
         Ok(stmt) => stmt,
         Err(err) => {
             eprintln!("{}:\n{}", input_path, err);
@@ -0,0 +1,228 @@
+//! Compiles the JavaScript programs in `benches/js` and measures compile time, the size of the
+//! WebAssembly that we produce, and the execution time of that WebAssembly.
+//!
+//! Run with `cargo bench -p libjankscripten`. Execution uses Node and `bin/run.js`, so it requires
+//! `node` on the `PATH` and a runtime built with `(cd runtime && cargo build --release)`. Without
+//! them, we only measure compilation.
+//!
+//! Criterion stores its own statistics in `target/criterion`. In addition, each run writes a
+//! summary to `target/jankscripten-bench/<commit>.json` (and `latest.json`), which has the
+//! following shape, so that any CI system can track performance across commits:
+//!
+//! ```text
+//! { "commit": "...",
+//!   "benchmarks": [
+//!     { "name": "nbody", "compile_ns": 12345, "binary_bytes": 6789, "run_ns": 101112 }, ... ] }
+//! ```
+//!
+//! `run_ns` is `null` when we could not run the benchmark.
+use criterion::{criterion_group, criterion_main, Criterion};
+use libjankscripten::opts::Opts;
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const BENCHMARKS: &[&str] = &["richards", "splay", "nbody", "props"];
+
+/// The measurements for a single benchmark.
+struct Report {
+    name: &'static str,
+    compile: Duration,
+    binary_bytes: usize,
+    run: Option<Duration>,
+}
+
+impl Report {
+    fn to_json(&self) -> String {
+        let run_ns = match self.run {
+            Some(run) => run.as_nanos().to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            r#"{{ "name": "{}", "compile_ns": {}, "binary_bytes": {}, "run_ns": {} }}"#,
+            self.name,
+            self.compile.as_nanos(),
+            self.binary_bytes,
+            run_ns
+        )
+    }
+}
+
+/// Accumulates the time that Criterion measures, so that we can report the mean time per
+/// iteration.
+#[derive(Default)]
+struct MeanTime {
+    total: Cell<Duration>,
+    iters: Cell<u64>,
+}
+
+impl MeanTime {
+    fn add(&self, elapsed: Duration, iters: u64) {
+        self.total.set(self.total.get() + elapsed);
+        self.iters.set(self.iters.get() + iters);
+    }
+
+    fn mean(&self) -> Duration {
+        match self.iters.get() {
+            0 => Duration::default(),
+            n => self.total.get() / n as u32,
+        }
+    }
+}
+
+fn root_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("libjankscripten is in a workspace")
+        .to_path_buf()
+}
+
+fn opts() -> Opts {
+    let mut opts = Opts::new();
+    let stdlib = root_dir().join("stdlib.notwasm");
+    opts.notwasm_stdlib_source_code = fs::read_to_string(&stdlib)
+        .unwrap_or_else(|err| panic!("reading {}: {}", stdlib.display(), err));
+    opts.notwasm_stdlib_includes = libjankscripten::notwasm::stdlib::read_includes(&stdlib)
+        .unwrap_or_else(|err| panic!("reading the includes of {}: {}", stdlib.display(), err));
+    opts
+}
+
+fn compile(name: &str, js_code: &str) -> Vec<u8> {
+    let (wasm, _) = libjankscripten::javascript_to_wasm(opts(), name, js_code, |_| (), |_| ())
+        .unwrap_or_else(|err| panic!("compiling {}: {}", name, err));
+    wasm
+}
+
+/// The arguments to `bin/run.js` that select a build of the runtime system, if one exists.
+fn runtime_args() -> Option<Vec<&'static str>> {
+    let target = root_dir().join("target").join("wasm32-unknown-unknown");
+    if target.join("release").join("runtime.wasm").exists() {
+        Some(vec!["--release"])
+    } else if target.join("debug").join("runtime.wasm").exists() {
+        Some(vec![])
+    } else {
+        None
+    }
+}
+
+fn node_available() -> bool {
+    Command::new("node")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+fn run(wasm_path: &Path, runtime_args: &[&str]) -> Duration {
+    let start = Instant::now();
+    let status = Command::new("node")
+        .arg(root_dir().join("bin").join("run.js"))
+        .arg(wasm_path)
+        .args(runtime_args)
+        .stdout(Stdio::null())
+        .status()
+        .expect("running node");
+    let elapsed = start.elapsed();
+    assert!(status.success(), "{} failed", wasm_path.display());
+    elapsed
+}
+
+fn commit() -> String {
+    Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .current_dir(root_dir())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn write_reports(reports: &[Report]) {
+    let commit = commit();
+    let benchmarks = reports
+        .iter()
+        .map(Report::to_json)
+        .collect::<Vec<_>>()
+        .join(",\n    ");
+    let json = format!(
+        "{{ \"commit\": \"{}\",\n  \"benchmarks\": [\n    {} ] }}\n",
+        commit, benchmarks
+    );
+    let dir = root_dir().join("target").join("jankscripten-bench");
+    fs::create_dir_all(&dir).expect("creating benchmark report directory");
+    fs::write(dir.join(format!("{}.json", commit)), &json).expect("writing benchmark report");
+    fs::write(dir.join("latest.json"), &json).expect("writing benchmark report");
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let out_dir = root_dir().join("target").join("jankscripten-bench");
+    fs::create_dir_all(&out_dir).expect("creating benchmark output directory");
+    let runtime_args = if node_available() {
+        runtime_args()
+    } else {
+        None
+    };
+    if runtime_args.is_none() {
+        eprintln!("node or the runtime system is missing, so we will not run the benchmarks");
+    }
+
+    let mut reports = Vec::new();
+    for &name in BENCHMARKS {
+        let js_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("benches")
+            .join("js")
+            .join(format!("{}.js", name));
+        let js_code = fs::read_to_string(&js_path)
+            .unwrap_or_else(|err| panic!("reading {}: {}", js_path.display(), err));
+
+        let compile_time = MeanTime::default();
+        c.bench_function(&format!("compile/{}", name), |b| {
+            b.iter_custom(|iters| {
+                let start = Instant::now();
+                for _ in 0..iters {
+                    compile(name, &js_code);
+                }
+                let elapsed = start.elapsed();
+                compile_time.add(elapsed, iters);
+                elapsed
+            })
+        });
+
+        let wasm = compile(name, &js_code);
+        let wasm_path = out_dir.join(format!("{}.wasm", name));
+        fs::write(&wasm_path, &wasm)
+            .unwrap_or_else(|err| panic!("writing {}: {}", wasm_path.display(), err));
+
+        let run_time = runtime_args.as_ref().map(|args| {
+            let run_time = MeanTime::default();
+            let mut group = c.benchmark_group("run");
+            // Each iteration starts Node, so we take fewer samples than the default.
+            group.sample_size(10);
+            group.bench_function(name, |b| {
+                b.iter_custom(|iters| {
+                    let elapsed = (0..iters).map(|_| run(&wasm_path, args)).sum();
+                    run_time.add(elapsed, iters);
+                    elapsed
+                })
+            });
+            group.finish();
+            run_time.mean()
+        });
+
+        reports.push(Report {
+            name,
+            compile: compile_time.mean(),
+            binary_bytes: wasm.len(),
+            run: run_time,
+        });
+    }
+    write_reports(&reports);
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
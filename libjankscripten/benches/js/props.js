@@ -0,0 +1,63 @@
+// Reads the fields of a small object and of a wide object by computed
+// names, which misses the inline caches, so every read looks up the class of
+// the object.
+
+function Small() {
+    this.a = 1;
+    this.b = 2;
+    this.c = 3;
+    this.d = 4;
+}
+
+function Wide() {
+    this.f0 = 0;
+    this.f1 = 1;
+    this.f2 = 2;
+    this.f3 = 3;
+    this.f4 = 4;
+    this.f5 = 5;
+    this.f6 = 6;
+    this.f7 = 7;
+    this.f8 = 8;
+    this.f9 = 9;
+    this.f10 = 10;
+    this.f11 = 11;
+    this.f12 = 12;
+    this.f13 = 13;
+    this.f14 = 14;
+    this.f15 = 15;
+    this.f16 = 16;
+    this.f17 = 17;
+    this.f18 = 18;
+    this.f19 = 19;
+    this.f20 = 20;
+    this.f21 = 21;
+    this.f22 = 22;
+    this.f23 = 23;
+    this.f24 = 24;
+    this.f25 = 25;
+    this.f26 = 26;
+    this.f27 = 27;
+    this.f28 = 28;
+    this.f29 = 29;
+    this.f30 = 30;
+    this.f31 = 31;
+}
+
+function sumFields(obj, names, rounds) {
+    var sum = 0;
+    for (var r = 0; r < rounds; r++) {
+        for (var i = 0; i < names.length; i++) {
+            sum = sum + obj[names[i]];
+        }
+    }
+    return sum;
+}
+
+var smallNames = ["a", "b", "c", "d"];
+var wideNames = [];
+for (var i = 31; i >= 0; i--) {
+    wideNames.push("f" + i);
+}
+log_any(sumFields(new Small(), smallNames, 200000));
+log_any(sumFields(new Wide(), wideNames, 25000));
@@ -0,0 +1,165 @@
+// A splay tree benchmark, in the style of the V8 and Octane "splay" benchmark.
+// It builds a large splay tree, then repeatedly inserts new keys and removes
+// old ones.
+
+function Node(key, value) {
+    this.key = key;
+    this.value = value;
+    this.left = null;
+    this.right = null;
+}
+
+function SplayTree() {
+    this.root = null;
+}
+
+SplayTree.prototype.isEmpty = function() {
+    return this.root === null;
+};
+
+// Moves the node with the given key to the root of the tree. If the key is
+// not in the tree, the last node on the search path moves to the root.
+SplayTree.prototype.splay = function(key) {
+    if (this.root === null) {
+        return;
+    }
+    var dummy = new Node(0, null);
+    var left = dummy;
+    var right = dummy;
+    var current = this.root;
+    while (true) {
+        if (key < current.key) {
+            if (current.left === null) {
+                break;
+            }
+            if (key < current.left.key) {
+                // Rotate right.
+                var tmp = current.left;
+                current.left = tmp.right;
+                tmp.right = current;
+                current = tmp;
+                if (current.left === null) {
+                    break;
+                }
+            }
+            // Link right.
+            right.left = current;
+            right = current;
+            current = current.left;
+        } else if (key > current.key) {
+            if (current.right === null) {
+                break;
+            }
+            if (key > current.right.key) {
+                // Rotate left.
+                var tmp2 = current.right;
+                current.right = tmp2.left;
+                tmp2.left = current;
+                current = tmp2;
+                if (current.right === null) {
+                    break;
+                }
+            }
+            // Link left.
+            left.right = current;
+            left = current;
+            current = current.right;
+        } else {
+            break;
+        }
+    }
+    // Assemble.
+    left.right = current.left;
+    right.left = current.right;
+    current.left = dummy.right;
+    current.right = dummy.left;
+    this.root = current;
+};
+
+SplayTree.prototype.insert = function(key, value) {
+    if (this.isEmpty()) {
+        this.root = new Node(key, value);
+        return;
+    }
+    this.splay(key);
+    if (this.root.key === key) {
+        return;
+    }
+    var node = new Node(key, value);
+    if (key > this.root.key) {
+        node.left = this.root;
+        node.right = this.root.right;
+        this.root.right = null;
+    } else {
+        node.right = this.root;
+        node.left = this.root.left;
+        this.root.left = null;
+    }
+    this.root = node;
+};
+
+SplayTree.prototype.remove = function(key) {
+    if (this.isEmpty()) {
+        return;
+    }
+    this.splay(key);
+    if (this.root.key !== key) {
+        return;
+    }
+    var removed = this.root;
+    if (this.root.left === null) {
+        this.root = this.root.right;
+    } else {
+        var right = this.root.right;
+        this.root = this.root.left;
+        // Splay to make sure that the new root has an empty right child.
+        this.splay(key);
+        this.root.right = right;
+    }
+};
+
+SplayTree.prototype.find = function(key) {
+    if (this.isEmpty()) {
+        return null;
+    }
+    this.splay(key);
+    if (this.root.key === key) {
+        return this.root;
+    }
+    return null;
+};
+
+// A simple linear congruential generator (the ZX81 generator), so that the
+// benchmark is deterministic. Its intermediate values fit in 32 bits.
+var seed = 49734;
+function random() {
+    seed = (seed * 75 + 74) % 65537;
+    return seed;
+}
+
+var TREE_SIZE = 8000;
+var TREE_MODIFICATIONS = 80;
+var ITERATIONS = 50;
+
+var tree = new SplayTree();
+var keys = [];
+for (var i = 0; i < TREE_SIZE; i++) {
+    var key = random();
+    keys.push(key);
+    tree.insert(key, i);
+}
+
+var found = 0;
+for (var iter = 0; iter < ITERATIONS; iter++) {
+    for (var j = 0; j < TREE_MODIFICATIONS; j++) {
+        var newKey = random();
+        tree.insert(newKey, j);
+        var oldKey = keys[(iter * TREE_MODIFICATIONS + j) % TREE_SIZE];
+        tree.remove(oldKey);
+        keys[(iter * TREE_MODIFICATIONS + j) % TREE_SIZE] = newKey;
+        if (tree.find(newKey) !== null) {
+            found = found + 1;
+        }
+    }
+}
+log_any(found);
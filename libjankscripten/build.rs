@@ -1,6 +1,10 @@
 use cfgrammar::yacc::YaccKind;
 use lrlex::LexerBuilder;
 use lrpar::CTParserBuilder;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // '.error_on_conflicts(true)' does not print the conflicts that occur, which makes the error
@@ -19,5 +23,273 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     LexerBuilder::new()
         .rule_ids_map(lex_rule_ids_map)
         .process_file_in_src("notwasm/lexer.l")?;
+
+    runtime_manifest()?;
+    stdlib_includes()?;
+    // Printing rerun-if-changed (above) stops Cargo from rerunning this script when any file of
+    // the package changes, so we list the other inputs too.
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/notwasm/parser.y");
+    println!("cargo:rerun-if-changed=src/notwasm/lexer.l");
+    Ok(())
+}
+
+/// Writes the WebAssembly signatures of the functions that the runtime system exports, which are
+/// the `#[no_mangle]` functions in its source, to `runtime_manifest.rs` in `OUT_DIR`. The
+/// translator checks the runtime functions that NotWasm imports against them (see
+/// `notwasm::rt_bindings`), so that the bindings cannot drift from the runtime system. The
+/// manifest also records the feature of the runtime system that provides each function, if any,
+/// which either gates the module of the function in `lib.rs` or the function itself.
+fn runtime_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let src = Path::new(&env::var("CARGO_MANIFEST_DIR")?).join("../runtime/src");
+    println!("cargo:rerun-if-changed={}", src.display());
+    let module_features = module_features(&fs::read_to_string(src.join("lib.rs"))?);
+    let mut files = Vec::new();
+    rust_files(&src, &mut files)?;
+    files.sort();
+    let mut functions = BTreeMap::new();
+    for file in files {
+        let module = file
+            .strip_prefix(&src)?
+            .components()
+            .next()
+            .and_then(|module| Path::new(module.as_os_str()).file_stem())
+            .and_then(|module| module.to_str())
+            .unwrap_or("")
+            .to_string();
+        let source = fs::read_to_string(&file)?;
+        for mut export in exported_functions(&source) {
+            if export.feature.is_none() {
+                export.feature = module_features.get(&module).cloned();
+            }
+            if let Some(export) = functions.insert(export.name.clone(), export) {
+                panic!("the runtime system exports {} twice", export.name);
+            }
+        }
+    }
+    let features: BTreeSet<_> = functions
+        .values()
+        .filter_map(|f| f.feature.clone())
+        .collect();
+    let mut manifest = format!(
+        "pub const RUNTIME_FEATURES: &[&str] = &{:?};\n\n",
+        features.iter().collect::<Vec<_>>()
+    );
+    manifest.push_str(
+        "pub const RUNTIME_FUNCTIONS: &[(&str, &[&str], Option<&str>, Option<&str>)] = &[\n",
+    );
+    for export in functions.values() {
+        manifest.push_str(&format!(
+            "    ({:?}, &{:?}, {:?}, {:?}),\n",
+            export.name, export.params, export.result, export.feature
+        ));
+    }
+    manifest.push_str("];\n");
+    fs::write(
+        Path::new(&env::var("OUT_DIR")?).join("runtime_manifest.rs"),
+        manifest,
+    )?;
+    Ok(())
+}
+
+/// Writes `STDLIB_INCLUDES` to `stdlib_includes.rs` in `OUT_DIR`, which embeds the files that
+/// the standard library includes, directly or indirectly, by the names in their `include`
+/// directives, as `notwasm::stdlib::STDLIB_INCLUDES`. The playground and the fuzz targets have no
+/// file system, so they link the standard library with these, and a new include cannot be missing
+/// from them.
+fn stdlib_includes() -> Result<(), Box<dyn std::error::Error>> {
+    let root = Path::new(&env::var("CARGO_MANIFEST_DIR")?).join("..");
+    println!(
+        "cargo:rerun-if-changed={}",
+        root.join("stdlib.notwasm").display()
+    );
+    println!("cargo:rerun-if-changed={}", root.join("stdlib").display());
+    let mut pending = includes(&fs::read_to_string(root.join("stdlib.notwasm"))?);
+    let mut files = BTreeMap::new();
+    while let Some(name) = pending.pop() {
+        if !files.contains_key(&name) {
+            let path = fs::canonicalize(root.join(&name))?;
+            pending.extend(includes(&fs::read_to_string(&path)?));
+            files.insert(name, path);
+        }
+    }
+    let mut source = "pub const STDLIB_INCLUDES: &[(&str, &str)] = &[\n".to_string();
+    for (name, path) in files {
+        source.push_str(&format!("    ({:?}, include_str!({:?})),\n", name, path));
+    }
+    source.push_str("];\n");
+    fs::write(
+        Path::new(&env::var("OUT_DIR")?).join("stdlib_includes.rs"),
+        source,
+    )?;
+    Ok(())
+}
+
+/// The names in the `include "NAME";` directives of a file of the standard library, which are in
+/// its header (see `notwasm::stdlib::directives`).
+fn includes(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for line in source.lines().map(|line| line.trim()) {
+        if let Some(name) = line
+            .strip_prefix("include")
+            .and_then(|arg| arg.trim().strip_suffix(';'))
+            .map(|arg| arg.trim())
+            .and_then(|arg| arg.strip_prefix('"'))
+            .and_then(|arg| arg.strip_suffix('"'))
+        {
+            names.push(name.to_string());
+        } else if !line.is_empty()
+            && !line.starts_with("//")
+            && !line.starts_with("optional")
+            && !line.starts_with("init")
+        {
+            break;
+        }
+    }
+    names
+}
+
+fn rust_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            rust_files(&path, files)?;
+        } else if path.extension().map_or(false, |ext| ext == "rs") {
+            files.push(path);
+        }
+    }
     Ok(())
 }
+
+/// A function that the runtime system exports.
+struct Export {
+    name: String,
+    /// The WebAssembly types of the parameters and the result
+    params: Vec<&'static str>,
+    result: Option<&'static str>,
+    /// The feature of the runtime system that provides the function, if any
+    feature: Option<String>,
+}
+
+/// The feature that a `#[cfg(...)]` attribute requires, if any.
+fn feature_of(attribute: &str) -> Option<String> {
+    let start = attribute.find("feature = \"")? + "feature = \"".len();
+    let end = attribute[start..].find('"')?;
+    Some(attribute[start..start + end].to_string())
+}
+
+/// The features that gate the modules that `lib.rs` declares, by the names of the modules.
+fn module_features(lib: &str) -> HashMap<String, String> {
+    let mut features = HashMap::new();
+    let mut feature = None;
+    for line in lib.lines().map(|line| line.trim()) {
+        if line.starts_with("#[cfg(") {
+            feature = feature_of(line);
+        } else if let Some(module) = line
+            .trim_start_matches("pub ")
+            .strip_prefix("mod ")
+            .and_then(|module| module.strip_suffix(';'))
+        {
+            if let Some(feature) = feature.take() {
+                features.insert(module.trim().to_string(), feature);
+            }
+        } else {
+            feature = None;
+        }
+    }
+    features
+}
+
+/// The functions that follow a `#[no_mangle]` attribute in `source`.
+fn exported_functions(source: &str) -> Vec<Export> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut functions = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim() != "#[no_mangle]" {
+            continue;
+        }
+        // The item may span several lines, and other attributes or comments may precede it.
+        let mut feature = None;
+        let mut item = String::new();
+        for line in lines[i + 1..].iter().map(|line| line.trim()) {
+            if item.is_empty() && (line.starts_with("#[") || line.starts_with("//")) {
+                if line.starts_with("#[cfg(") {
+                    feature = feature_of(line);
+                }
+                continue;
+            }
+            match line.find(|c: char| c == '{' || c == ';') {
+                Some(end) => {
+                    item.push_str(&line[..end]);
+                    break;
+                }
+                None => {
+                    item.push_str(line);
+                    item.push(' ');
+                }
+            }
+        }
+        // Exported statics (e.g., JNKS_STRINGS) are not functions
+        if let Some((name, params, result)) = signature(&item) {
+            functions.push(Export {
+                name,
+                params,
+                result,
+                feature,
+            });
+        }
+    }
+    functions
+}
+
+/// The name and WebAssembly signature of `pub extern "C" fn name(x: T, ...) -> R`.
+fn signature(item: &str) -> Option<(String, Vec<&'static str>, Option<&'static str>)> {
+    let name_start = item.find("fn ")? + "fn ".len();
+    let open = item.find('(')?;
+    // The name may be followed by lifetime parameters
+    let name = item[name_start..open].split('<').next()?.trim().to_string();
+    let mut depth = 0;
+    let mut close = open;
+    let mut params = Vec::new();
+    let mut param_start = open + 1;
+    for (i, c) in item.char_indices().skip_while(|(i, _)| *i <= open) {
+        match c {
+            '(' | '<' | '[' => depth += 1,
+            ')' if depth == 0 => {
+                close = i;
+                break;
+            }
+            ')' | '>' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                params.push(&item[param_start..i]);
+                param_start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    params.push(&item[param_start..close]);
+    let params = params
+        .into_iter()
+        .filter(|param| !param.trim().is_empty())
+        .map(|param| wasm_type(param.splitn(2, ':').nth(1).unwrap_or("")).unwrap_or("i32"))
+        .collect();
+    let result = item[close + 1..]
+        .trim()
+        .strip_prefix("->")
+        .and_then(wasm_type);
+    Some((name, params, result))
+}
+
+/// The WebAssembly type of a Rust type of the runtime system. An `any` is a 64-bit immediate,
+/// and almost every other type is a 32-bit integer or pointer.
+fn wasm_type(ty: &str) -> Option<&'static str> {
+    match ty.trim() {
+        "" | "()" => None,
+        "f64" => Some("f64"),
+        "f32" => Some("f32"),
+        // ops.rs imports AnyValue as Any
+        "AnyValue" | "Any" | "AnyClosureVal" | "i64" | "u64" => Some("i64"),
+        ty if ty.starts_with("I64Val") => Some("i64"),
+        _ => Some("i32"),
+    }
+}
@@ -0,0 +1,13 @@
+//! Parsing the pretty-printed AST of a program produces the same AST.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use libjankscripten::javascript::parse;
+use libjankscripten_fuzz::Program;
+
+fuzz_target!(|program: Program| {
+    let js_code = program.to_string();
+    let ast = parse("fuzz", &js_code).expect("generated program does not parse");
+    let printed = ast.to_pretty(80);
+    let reparsed = parse("fuzz", &printed).expect("pretty-printed program does not parse");
+    assert_eq!(printed, reparsed.to_pretty(80));
+});
@@ -0,0 +1,19 @@
+//! Compiling a program to WebAssembly does not panic. (It may report an error.)
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use libjankscripten::notwasm::stdlib::STDLIB_INCLUDES;
+use libjankscripten::opts::Opts;
+use libjankscripten_fuzz::Program;
+
+const STDLIB: &str = include_str!("../../../stdlib.notwasm");
+
+fuzz_target!(|program: Program| {
+    let js_code = program.to_string();
+    let mut opts = Opts::new();
+    opts.notwasm_stdlib_source_code = STDLIB.to_string();
+    for (name, source) in STDLIB_INCLUDES {
+        opts.notwasm_stdlib_includes
+            .insert(name.to_string(), source.to_string());
+    }
+    let _ = libjankscripten::javascript_to_wasm(opts, "fuzz", &js_code, |_| (), |_| ());
+});
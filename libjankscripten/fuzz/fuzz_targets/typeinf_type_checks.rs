@@ -0,0 +1,18 @@
+//! Type inference produces a JankyScript program that type checks.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use libjankscripten::jankyscript;
+use libjankscripten::javascript::{desugar, parse, NameGen};
+use libjankscripten::opts::Opts;
+use libjankscripten_fuzz::Program;
+
+fuzz_target!(|program: Program| {
+    let js_code = program.to_string();
+    let mut js = parse("fuzz", &js_code).expect("generated program does not parse");
+    desugar(&mut js, &mut NameGen::default());
+    let mut janky = jankyscript::from_js::from_javascript(js);
+    // `compile` runs the type checker after type inference.
+    if let Err(err) = jankyscript::compile(&Opts::new(), &mut janky, |_| ()) {
+        panic!("{}\n{}", err, js_code);
+    }
+});
@@ -0,0 +1,348 @@
+//! A generator of JavaScript programs that only use the features that jankscripten supports.
+//!
+//! Every variable and function that a program refers to is defined: programs use the global
+//! variables `v0` to `v3`, which are initialized at the top of the program, and call the
+//! functions that they define at the top level. `Display` prints a program as JavaScript.
+use arbitrary::Arbitrary;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Arbitrary, Debug)]
+pub struct Program {
+    functions: Vec<Function>,
+    body: Vec<Stmt>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct Function {
+    num_params: Param,
+    body: Vec<Stmt>,
+    result: Expr,
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum Var {
+    V0,
+    V1,
+    V2,
+    V3,
+}
+
+/// A parameter of the enclosing function, which we print as `v0` if there is no such parameter.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum Param {
+    P0,
+    P1,
+    P2,
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum Field {
+    X,
+    Y,
+    Length,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Stmt {
+    Assign(Var, Expr),
+    SetField(Var, Field, Expr),
+    Log(Expr),
+    Expr(Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Break,
+}
+
+#[derive(Arbitrary, Debug)]
+enum BinOp {
+    Plus,
+    Minus,
+    Times,
+    Over,
+    Mod,
+    Less,
+    LessEqual,
+    Greater,
+    StrictEqual,
+    StrictNotEqual,
+    Equal,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    LeftShift,
+}
+
+#[derive(Arbitrary, Debug)]
+enum UnOp {
+    Minus,
+    Not,
+    Typeof,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Expr {
+    Int(i16),
+    /// Quarters, so that the literal has a short, exact decimal representation.
+    Float(i16),
+    Str(Word),
+    Bool(bool),
+    Null,
+    Undefined,
+    Var(Var),
+    Param(Param),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    Object(Vec<(Field, Expr)>),
+    Array(Vec<Expr>),
+    Dot(Box<Expr>, Field),
+    Index(Box<Expr>, Box<Expr>),
+    Call(u8, Vec<Expr>),
+    Lambda(Box<Expr>),
+}
+
+/// A string literal that does not need escaping.
+#[derive(Arbitrary, Debug)]
+struct Word(Vec<u8>);
+
+/// The context in which we print statements and expressions.
+#[derive(Clone, Copy)]
+struct Scope {
+    num_functions: usize,
+    num_params: usize,
+    in_loop: bool,
+}
+
+struct InScope<'a, T>(&'a T, Scope);
+
+impl Param {
+    fn index(self) -> usize {
+        match self {
+            Param::P0 => 0,
+            Param::P1 => 1,
+            Param::P2 => 2,
+        }
+    }
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let n = match self {
+            Var::V0 => 0,
+            Var::V1 => 1,
+            Var::V2 => 2,
+            Var::V3 => 3,
+        };
+        write!(f, "v{}", n)
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Field::X => write!(f, "x"),
+            Field::Y => write!(f, "y"),
+            Field::Length => write!(f, "length"),
+        }
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let op = match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Times => "*",
+            BinOp::Over => "/",
+            BinOp::Mod => "%",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::StrictEqual => "===",
+            BinOp::StrictNotEqual => "!==",
+            BinOp::Equal => "==",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::LeftShift => "<<",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl Display for UnOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnOp::Minus => write!(f, "-"),
+            UnOp::Not => write!(f, "!"),
+            UnOp::Typeof => write!(f, "typeof "),
+        }
+    }
+}
+
+impl Display for Word {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let word: String = self.0.iter().take(8).map(|b| (b'a' + b % 26) as char).collect();
+        write!(f, "\"{}\"", word)
+    }
+}
+
+fn write_block(f: &mut Formatter, stmts: &[Stmt], scope: Scope) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for stmt in stmts {
+        writeln!(f, "{}", InScope(stmt, scope))?;
+    }
+    write!(f, "}}")
+}
+
+fn write_args(f: &mut Formatter, args: &[Expr], scope: Scope) -> fmt::Result {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", InScope(arg, scope))?;
+    }
+    Ok(())
+}
+
+impl<'a> Display for InScope<'a, Stmt> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let scope = self.1;
+        match self.0 {
+            Stmt::Assign(x, e) => write!(f, "{} = {};", x, InScope(e, scope)),
+            Stmt::SetField(x, field, e) => write!(f, "{}.{} = {};", x, field, InScope(e, scope)),
+            Stmt::Log(e) => write!(f, "log_any({});", InScope(e, scope)),
+            // Parenthesize, so that an object literal is not a block.
+            Stmt::Expr(e) => write!(f, "({});", InScope(e, scope)),
+            Stmt::If(e, s1, s2) => {
+                write!(f, "if ({}) ", InScope(e, scope))?;
+                write_block(f, s1, scope)?;
+                write!(f, " else ")?;
+                write_block(f, s2, scope)
+            }
+            Stmt::While(e, body) => {
+                write!(f, "while ({}) ", InScope(e, scope))?;
+                write_block(
+                    f,
+                    body,
+                    Scope {
+                        in_loop: true,
+                        ..scope
+                    },
+                )
+            }
+            Stmt::Break if scope.in_loop => write!(f, "break;"),
+            Stmt::Break => write!(f, ";"),
+        }
+    }
+}
+
+impl<'a> Display for InScope<'a, Expr> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let scope = self.1;
+        match self.0 {
+            Expr::Int(n) if *n < 0 => write!(f, "({})", n),
+            Expr::Int(n) => write!(f, "{}", n),
+            Expr::Float(n) => write!(f, "({:?})", *n as f64 / 4.0),
+            Expr::Str(w) => write!(f, "{}", w),
+            Expr::Bool(b) => write!(f, "{}", b),
+            Expr::Null => write!(f, "null"),
+            Expr::Undefined => write!(f, "undefined"),
+            Expr::Var(x) => write!(f, "{}", x),
+            Expr::Param(p) if p.index() < scope.num_params => write!(f, "p{}", p.index()),
+            Expr::Param(_) => write!(f, "v0"),
+            Expr::Binary(op, e1, e2) => {
+                write!(f, "({} {} {})", InScope(&**e1, scope), op, InScope(&**e2, scope))
+            }
+            Expr::Unary(op, e) => write!(f, "({}{})", op, InScope(&**e, scope)),
+            Expr::Cond(e1, e2, e3) => write!(
+                f,
+                "({} ? {} : {})",
+                InScope(&**e1, scope),
+                InScope(&**e2, scope),
+                InScope(&**e3, scope)
+            ),
+            Expr::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (field, e)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, InScope(e, scope))?;
+                }
+                write!(f, "}}")
+            }
+            Expr::Array(es) => {
+                write!(f, "[")?;
+                write_args(f, es, scope)?;
+                write!(f, "]")
+            }
+            Expr::Dot(e, field) => write!(f, "{}.{}", InScope(&**e, scope), field),
+            Expr::Index(e1, e2) => {
+                write!(f, "{}[{}]", InScope(&**e1, scope), InScope(&**e2, scope))
+            }
+            Expr::Call(_, args) if scope.num_functions == 0 => {
+                write!(f, "log_any(")?;
+                write_args(f, args, scope)?;
+                write!(f, ")")
+            }
+            Expr::Call(n, args) => {
+                write!(f, "f{}(", *n as usize % scope.num_functions)?;
+                write_args(f, args, scope)?;
+                write!(f, ")")
+            }
+            Expr::Lambda(e) => write!(
+                f,
+                "(function(p0) {{ return {}; }})",
+                InScope(
+                    &**e,
+                    Scope {
+                        num_params: 1,
+                        in_loop: false,
+                        ..scope
+                    }
+                )
+            ),
+        }
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "var v0 = 0;")?;
+        writeln!(f, "var v1 = \"\";")?;
+        writeln!(f, "var v2 = {{ x: 0, y: 0 }};")?;
+        writeln!(f, "var v3 = [];")?;
+        let num_functions = self.functions.len();
+        for (i, func) in self.functions.iter().enumerate() {
+            let num_params = func.num_params.index() + 1;
+            let params = (0..num_params)
+                .map(|n| format!("p{}", n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let scope = Scope {
+                num_functions,
+                num_params,
+                in_loop: false,
+            };
+            write!(f, "function f{}({}) ", i, params)?;
+            writeln!(f, "{{")?;
+            for stmt in &func.body {
+                writeln!(f, "{}", InScope(stmt, scope))?;
+            }
+            writeln!(f, "return {};", InScope(&func.result, scope))?;
+            writeln!(f, "}}")?;
+        }
+        let scope = Scope {
+            num_functions,
+            num_params: 0,
+            in_loop: false,
+        };
+        for stmt in &self.body {
+            writeln!(f, "{}", InScope(stmt, scope))?;
+        }
+        Ok(())
+    }
+}
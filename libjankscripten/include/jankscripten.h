@@ -0,0 +1,32 @@
+/* The C API of the jankscripten compiler. See libjankscripten/src/ffi.rs for the rules of
+ * ownership. */
+#ifndef JANKSCRIPTEN_H
+#define JANKSCRIPTEN_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct Opts jnks_opts;
+typedef struct Output jnks_output;
+
+jnks_opts *jnks_opts_new(void);
+void jnks_opts_free(jnks_opts *opts);
+void jnks_opts_set_disable_gc(jnks_opts *opts, bool disable_gc);
+void jnks_opts_set_profile_gen(jnks_opts *opts, bool profile_gen);
+void jnks_opts_set_max_call_depth(jnks_opts *opts, uint32_t max_call_depth);
+void jnks_opts_set_max_module_size(jnks_opts *opts, size_t max_module_size);
+/* These produce an error message, which the caller frees with jnks_string_free, or NULL. */
+char *jnks_opts_set_stdlib(jnks_opts *opts, const char *stdlib);
+char *jnks_opts_add_stdlib_include(jnks_opts *opts, const char *name, const char *source);
+char *jnks_opts_set_profile_use(jnks_opts *opts, const char *profile);
+char *jnks_opts_add_export(jnks_opts *opts, const char *name);
+
+jnks_output *jnks_compile(const jnks_opts *opts, const char *src_name, const char *js_code);
+const uint8_t *jnks_output_wasm(const jnks_output *output, size_t *len);
+const char *jnks_output_error(const jnks_output *output);
+void jnks_output_free(jnks_output *output);
+
+void jnks_string_free(char *s);
+
+#endif
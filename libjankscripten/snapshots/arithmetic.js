@@ -0,0 +1,4 @@
+var x = 1 + 2 * 3;
+var y = x / 2;
+log_any(x);
+log_any(y + 0.5);
@@ -0,0 +1,10 @@
+function counter() {
+    var n = 0;
+    return function() {
+        n = n + 1;
+        return n;
+    };
+}
+var c = counter();
+c();
+log_any(c());
@@ -0,0 +1,10 @@
+function Point(x, y) {
+    this.x = x;
+    this.y = y;
+}
+Point.prototype.norm = function() {
+    return Math.sqrt(this.x * this.x + this.y * this.y);
+};
+var p = new Point(3, 4);
+log_any(p.norm());
+log_any("x = " + p.x);
@@ -0,0 +1,386 @@
+//! Differential testing against Node.
+//!
+//! We compile a JavaScript program to WebAssembly and run it with the runtime system, run the
+//! original program with Node, and compare what the two print. JankyScript programs print with
+//! `log_any`, so we define `log_any` as `console.log` before running a program with Node.
+//!
+//! We can also compare the compiled WebAssembly with the NotWasm interpreter, which runs the
+//! NotWasm program that we translate to WebAssembly. A mismatch indicates a bug in translation,
+//! whereas a mismatch with Node may be a bug in any phase of the compiler.
+//!
+//! Finally, we can run a program with a small heap that does not grow (see
+//! [Differential::run_with_heap_size]), so that it collects garbage often. The collector only
+//! frees what is unreachable if the program roots every value that it uses, so these tests catch
+//! mistakes in rooting and translation that the tests of the runtime system cannot.
+//!
+//! This relies on `node` being on the `PATH`, and on a build of the runtime system, which
+//! `bin/run.js` loads. The tests fail without them, unless `JNKS_SKIP_DIFFERENTIAL` is set, so
+//! that a test run that checks nothing does not pass.
+use crate::notwasm::interpreter;
+use crate::opts::Opts;
+use crate::shared::nondet;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+/// Defines the functions that JankyScript programs use to print.
+const NODE_PRELUDE: &str = "function log_any(x) { console.log(x); }\n";
+
+#[derive(Debug, Error)]
+pub enum DifferentialError {
+    #[error("error compiling {0}: {1}")]
+    Compile(String, String),
+    #[error("error running {0} with `{1}`: {2}")]
+    Run(String, String, String),
+    #[error("{name} printed different output with Node and WebAssembly\nNode:\n{node}\nWebAssembly:\n{wasm}")]
+    Mismatch {
+        name: String,
+        node: String,
+        wasm: String,
+    },
+    #[error("error interpreting {0}: {1}")]
+    Interpret(String, interpreter::InterpError),
+    #[error("{name} printed different output with the NotWasm interpreter and WebAssembly\nInterpreter:\n{interpreter}\nWebAssembly:\n{wasm}")]
+    InterpreterMismatch {
+        name: String,
+        interpreter: String,
+        wasm: String,
+    },
+}
+
+/// How to run programs for differential testing.
+pub struct Differential {
+    run_js: PathBuf,
+    release: bool,
+    stdlib: String,
+    stdlib_includes: HashMap<String, String>,
+}
+
+impl Differential {
+    /// `root` is the root of the jankscripten repository, which has `bin/run.js` and
+    /// `stdlib.notwasm`. We use the debug build of the runtime system, unless `release` is set.
+    pub fn new(root: &Path, release: bool) -> std::io::Result<Self> {
+        let stdlib = std::fs::read_to_string(root.join("stdlib.notwasm"))?;
+        let stdlib_includes = crate::notwasm::stdlib::read_includes(&root.join("stdlib.notwasm"))?;
+        Ok(Differential {
+            run_js: root.join("bin").join("run.js"),
+            release,
+            stdlib,
+            stdlib_includes,
+        })
+    }
+
+    /// Compile and run `js_code` with the runtime system, run it with Node, and check that both
+    /// print the same output. On success, returns the output.
+    pub fn check(&self, name: &str, js_code: &str) -> Result<String, DifferentialError> {
+        let node = self.run_node(name, js_code)?;
+        let wasm = self.run_wasm(name, js_code)?;
+        if normalize(&node) != normalize(&wasm) {
+            return Err(DifferentialError::Mismatch {
+                name: name.to_string(),
+                node,
+                wasm,
+            });
+        }
+        Ok(node)
+    }
+
+    /// Compile and run `js_code` with the runtime system, interpret the NotWasm program that it
+    /// compiles to, and check that both print the same output. On success, returns the output.
+    pub fn check_interpreter(
+        &self,
+        name: &str,
+        js_code: &str,
+    ) -> Result<String, DifferentialError> {
+        let mut interpreted = None;
+        let wasm = self.compile(name, js_code, |notwasm| {
+            interpreted = Some(interpreter::interpret(notwasm))
+        })?;
+        let interpreter = interpreted
+            .expect("compiler did not produce NotWasm")
+            .map_err(|err| DifferentialError::Interpret(name.to_string(), err))?;
+        let wasm = self.run_compiled(name, &wasm, None)?;
+        if normalize(&interpreter) != normalize(&wasm) {
+            return Err(DifferentialError::InterpreterMismatch {
+                name: name.to_string(),
+                interpreter,
+                wasm,
+            });
+        }
+        Ok(interpreter)
+    }
+
+    fn run_node(&self, name: &str, js_code: &str) -> Result<String, DifferentialError> {
+        let script = format!("{}{}", NODE_PRELUDE, js_code);
+        run(name, Command::new("node").arg("-"), script.as_bytes())
+    }
+
+    fn run_wasm(&self, name: &str, js_code: &str) -> Result<String, DifferentialError> {
+        let wasm = self.compile(name, js_code, |_| ())?;
+        self.run_compiled(name, &wasm, None)
+    }
+
+    /// Compile and run `js_code` with a heap of `heap_size` bytes that does not grow, and
+    /// return its output. Node has no `__JNKS`, so programs may use `__JNKS.run_gc` and
+    /// `__JNKS.heap_used`, and the caller checks the output itself.
+    pub fn run_with_heap_size(
+        &self,
+        name: &str,
+        js_code: &str,
+        heap_size: usize,
+    ) -> Result<String, DifferentialError> {
+        let wasm = self.compile(name, js_code, |_| ())?;
+        self.run_compiled(name, &wasm, Some(heap_size))
+    }
+
+    fn compile(
+        &self,
+        name: &str,
+        js_code: &str,
+        inspect_notwasm: impl FnOnce(&crate::notwasm::syntax::Program),
+    ) -> Result<Vec<u8>, DifferentialError> {
+        let mut opts = Opts::new();
+        opts.notwasm_stdlib_source_code = self.stdlib.clone();
+        opts.notwasm_stdlib_includes = self.stdlib_includes.clone();
+        let (wasm, _) = crate::javascript_to_wasm(opts, name, js_code, |_| (), inspect_notwasm)
+            .map_err(|err| DifferentialError::Compile(name.to_string(), err.to_string()))?;
+        Ok(wasm)
+    }
+
+    fn run_compiled(
+        &self,
+        name: &str,
+        wasm: &[u8],
+        heap_size: Option<usize>,
+    ) -> Result<String, DifferentialError> {
+        let mut command = Command::new("node");
+        // run.js reads the program from standard input when its argument is `-`.
+        command.arg(&self.run_js).arg("-");
+        // Make Math.random and Date.now agree with the NotWasm interpreter.
+        command
+            .arg(format!("--seed={}", nondet::SEED))
+            .arg(format!("--clock={}", nondet::CLOCK));
+        if self.release {
+            command.arg("--release");
+        }
+        if let Some(heap_size) = heap_size {
+            command.arg(format!("--heap-size={}", heap_size));
+        }
+        run(name, &mut command, wasm)
+    }
+}
+
+/// Runs `command`, which receives `stdin` on standard input, and returns its standard output.
+fn run(name: &str, command: &mut Command, stdin: &[u8]) -> Result<String, DifferentialError> {
+    let description = format!("{:?}", command);
+    let describe = |err: String| DifferentialError::Run(name.to_string(), description.clone(), err);
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| describe(err.to_string()))?;
+    child
+        .stdin
+        .take()
+        .expect("no stdin")
+        .write_all(stdin)
+        .map_err(|err| describe(err.to_string()))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|err| describe(err.to_string()))?;
+    if !output.status.success() {
+        return Err(describe(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn normalize(output: &str) -> String {
+    output.replace("\r\n", "\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Skips the tests when Node or the runtime system is missing.
+    const SKIP_VAR: &str = "JNKS_SKIP_DIFFERENTIAL";
+
+    /// Returns `None` when Node or the runtime system is not available and [SKIP_VAR] is set.
+    fn differential() -> Option<Differential> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let node = Command::new("node")
+            .arg("--version")
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+        let runtime = root
+            .join("target")
+            .join("wasm32-unknown-unknown")
+            .join("debug")
+            .join("runtime.wasm");
+        if !node || !runtime.exists() {
+            if std::env::var_os(SKIP_VAR).is_some() {
+                eprintln!("skipping differential test: node or the runtime system is missing");
+                return None;
+            }
+            panic!(
+                "the differential tests need node and {}: build the runtime system with \
+                 `(cd runtime && cargo build)`, or set {} to skip them",
+                runtime.display(),
+                SKIP_VAR
+            );
+        }
+        Some(Differential::new(root, false).expect("reading stdlib.notwasm"))
+    }
+
+    fn check(name: &str, js_code: &str) {
+        if let Some(differential) = differential() {
+            if let Err(err) = differential.check(name, js_code) {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        check(
+            "integer_arithmetic",
+            "log_any(1 + 2); log_any(7 - 10); log_any(6 * 7); log_any(17 % 5);",
+        );
+    }
+
+    #[test]
+    fn string_concatenation() {
+        check(
+            "string_concatenation",
+            r#"var s = "hello"; log_any(s + " world");"#,
+        );
+    }
+
+    #[test]
+    fn closures() {
+        check(
+            "closures",
+            "function counter() { var n = 0; return function() { n = n + 1; return n; }; }
+             var c = counter(); c(); c(); log_any(c());",
+        );
+    }
+
+    #[test]
+    fn interpreter_agrees_with_wasm() {
+        if let Some(differential) = differential() {
+            let js_code =
+                "var n = 0; var i = 0; while (i < 10) { n = n + i; i = i + 1; } log_any(n);";
+            if let Err(err) = differential.check_interpreter("interpreter_loop", js_code) {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_nondeterminism_agrees_with_interpreter() {
+        if let Some(differential) = differential() {
+            let js_code = "log_any(Math.random()); log_any(Math.random()); log_any(Date.now());";
+            if let Err(err) = differential.check_interpreter("nondeterminism", js_code) {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    /// Small enough that the programs below collect garbage many times.
+    const SMALL_HEAP: usize = 1 << 19;
+
+    fn check_gc(name: &str, js_code: &str, expected: &str) {
+        if let Some(differential) = differential() {
+            match differential.run_with_heap_size(name, js_code, SMALL_HEAP) {
+                Ok(output) => assert_eq!(normalize(&output), expected),
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn gc_objects() {
+        check_gc(
+            "gc_objects",
+            r#"
+            var keep = null;
+            for (var i = 0; i < 100000; i++) {
+                var garbage = { a: i, b: i + 1, c: "s" + i };
+                if (i % 1000 === 0) {
+                    keep = { value: garbage.a, next: keep };
+                }
+            }
+            var sum = 0;
+            for (var node = keep; node !== null; node = node.next) {
+                sum = sum + node.value;
+            }
+            log_any(sum);
+            "#,
+            "4950000",
+        );
+    }
+
+    #[test]
+    fn gc_arrays_and_closures() {
+        check_gc(
+            "gc_arrays_and_closures",
+            r#"
+            function adder(n) {
+                return function(x) { return x + n; };
+            }
+            var adders = [];
+            for (var i = 0; i < 50000; i++) {
+                var xs = [i, i + 1, i + 2];
+                var f = adder(xs[1]);
+                if (i % 5000 === 0) {
+                    adders.push(f);
+                }
+            }
+            var total = 0;
+            for (var j = 0; j < adders.length; j++) {
+                total = total + adders[j](1);
+            }
+            log_any(total);
+            "#,
+            "225020",
+        );
+    }
+
+    #[test]
+    fn gc_memory_is_stable() {
+        check_gc(
+            "gc_memory_is_stable",
+            r#"
+            function churn() {
+                for (var i = 0; i < 20000; i++) {
+                    var o = { x: i, s: "t" + i, xs: [i] };
+                }
+            }
+            churn();
+            __JNKS.run_gc();
+            var before = __JNKS.heap_used();
+            churn();
+            __JNKS.run_gc();
+            var after = __JNKS.heap_used();
+            log_any(after - before < 1024);
+            "#,
+            "true",
+        );
+    }
+
+    #[test]
+    fn node_prelude_defines_log_any() {
+        if let Some(differential) = differential() {
+            match differential.run_node("log_any", "log_any(40 + 2);") {
+                Ok(output) => assert_eq!(normalize(&output), "42"),
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+}
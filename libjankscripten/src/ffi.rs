@@ -0,0 +1,356 @@
+//! A C API for the compiler, so that build systems that are not written in Rust can drive it.
+//! `include/jankscripten.h` declares these functions.
+//!
+//! Ownership rules:
+//!
+//! - [jnks_opts_new] produces options, which the caller configures with the `jnks_opts_*`
+//!   functions and frees with [jnks_opts_free].
+//! - Every string that the caller passes is null-terminated UTF-8, which the API only borrows for
+//!   the duration of the call.
+//! - [jnks_compile] produces an output, which owns either the WebAssembly module or an error
+//!   message. The pointers that [jnks_output_wasm] and [jnks_output_error] produce are valid
+//!   until the caller frees the output with [jnks_output_free].
+//! - A function that produces an error message as a `char *` transfers it to the caller, who
+//!   frees it with [jnks_string_free].
+//!
+//! The compiler panics on some programs that it does not support. The API reports these panics
+//! as errors instead of unwinding into the caller.
+use crate::opts::Opts;
+use crate::profile::Profile;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// The result of [jnks_compile].
+pub struct Output(Result<Vec<u8>, CString>);
+
+#[no_mangle]
+pub extern "C" fn jnks_opts_new() -> *mut Opts {
+    Box::into_raw(Box::new(Opts::new()))
+}
+
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new], and the caller must not use it afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_free(opts: *mut Opts) {
+    if !opts.is_null() {
+        drop(Box::from_raw(opts));
+    }
+}
+
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_disable_gc(opts: *mut Opts, disable_gc: bool) {
+    (*opts).disable_gc = disable_gc;
+}
+
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_profile_gen(opts: *mut Opts, profile_gen: bool) {
+    (*opts).profile_gen = profile_gen;
+}
+
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_trace_coercions(opts: *mut Opts, trace_coercions: bool) {
+    (*opts).trace_coercions = trace_coercions;
+}
+
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_checked(opts: *mut Opts, checked: bool) {
+    (*opts).checked = checked;
+}
+
+/// Sets the maximum depth of calls, where zero means that the depth is unlimited.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_max_call_depth(opts: *mut Opts, max_call_depth: u32) {
+    (*opts).max_call_depth = if max_call_depth == 0 {
+        None
+    } else {
+        Some(max_call_depth)
+    };
+}
+
+/// Sets the maximum size of the module in bytes, where zero means that the size is unlimited.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_max_module_size(opts: *mut Opts, max_module_size: usize) {
+    (*opts).max_module_size = if max_module_size == 0 {
+        None
+    } else {
+        Some(max_module_size)
+    };
+}
+
+/// Sets the source code of the NotWasm standard library, which is `stdlib.notwasm` in the
+/// repository. Produces an error message, or null.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new], and `stdlib` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_stdlib(
+    opts: *mut Opts,
+    stdlib: *const c_char,
+) -> *mut c_char {
+    match to_str(stdlib) {
+        Ok(stdlib) => {
+            (*opts).notwasm_stdlib_source_code = stdlib.to_string();
+            ptr::null_mut()
+        }
+        Err(err) => err.into_raw(),
+    }
+}
+
+/// Adds a file that the NotWasm standard library includes, e.g., `stdlib/uri.notwasm`, which is
+/// `name` in the `include` directive. Produces an error message, or null.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new], and `name` and `source` must be null-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_add_stdlib_include(
+    opts: *mut Opts,
+    name: *const c_char,
+    source: *const c_char,
+) -> *mut c_char {
+    match (to_str(name), to_str(source)) {
+        (Ok(name), Ok(source)) => {
+            (*opts)
+                .notwasm_stdlib_includes
+                .insert(name.to_string(), source.to_string());
+            ptr::null_mut()
+        }
+        (Err(err), _) | (_, Err(err)) => err.into_raw(),
+    }
+}
+
+/// Uses the profile in `profile`, which an instrumented run produced. Produces an error message,
+/// or null.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new], and `profile` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_set_profile_use(
+    opts: *mut Opts,
+    profile: *const c_char,
+) -> *mut c_char {
+    let profile = match to_str(profile) {
+        Ok(profile) => profile,
+        Err(err) => return err.into_raw(),
+    };
+    match Profile::parse(profile) {
+        Ok(profile) => {
+            (*opts).profile_use = Some(profile);
+            ptr::null_mut()
+        }
+        Err(err) => error(err).into_raw(),
+    }
+}
+
+/// Exports the NotWasm function `name` from the module. Produces an error message, or null.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new], and `name` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_opts_add_export(opts: *mut Opts, name: *const c_char) -> *mut c_char {
+    match to_str(name) {
+        Ok(name) => {
+            (*opts).exports.push(name.to_string());
+            ptr::null_mut()
+        }
+        Err(err) => err.into_raw(),
+    }
+}
+
+/// Compiles the JavaScript program `js_code`, whose name in error messages is `src_name`.
+///
+/// # Safety
+///
+/// `opts` must come from [jnks_opts_new], and `src_name` and `js_code` must be null-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_compile(
+    opts: *const Opts,
+    src_name: *const c_char,
+    js_code: *const c_char,
+) -> *mut Output {
+    let result = match (to_str(src_name), to_str(js_code)) {
+        (Ok(src_name), Ok(js_code)) => {
+            let opts = (*opts).clone();
+            let compiled = catch_unwind(AssertUnwindSafe(|| {
+                crate::javascript_to_wasm(opts, src_name, js_code, |_| (), |_| ())
+                    .map(|(wasm, _)| wasm)
+                    .map_err(error)
+            }));
+            match compiled {
+                Ok(result) => result,
+                Err(panic) => Err(error(panic_message(&panic))),
+            }
+        }
+        (Err(err), _) | (_, Err(err)) => Err(err),
+    };
+    Box::into_raw(Box::new(Output(result)))
+}
+
+/// The WebAssembly module, or null if compilation failed. Stores the length of the module in
+/// `len`.
+///
+/// # Safety
+///
+/// `output` must come from [jnks_compile], and `len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_output_wasm(output: *const Output, len: *mut usize) -> *const u8 {
+    match &(*output).0 {
+        Ok(wasm) => {
+            *len = wasm.len();
+            wasm.as_ptr()
+        }
+        Err(_) => {
+            *len = 0;
+            ptr::null()
+        }
+    }
+}
+
+/// The error message, or null if compilation succeeded.
+///
+/// # Safety
+///
+/// `output` must come from [jnks_compile].
+#[no_mangle]
+pub unsafe extern "C" fn jnks_output_error(output: *const Output) -> *const c_char {
+    match &(*output).0 {
+        Ok(_) => ptr::null(),
+        Err(err) => err.as_ptr(),
+    }
+}
+
+/// # Safety
+///
+/// `output` must come from [jnks_compile], and the caller must not use it afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_output_free(output: *mut Output) {
+    if !output.is_null() {
+        drop(Box::from_raw(output));
+    }
+}
+
+/// # Safety
+///
+/// `s` must be an error message that the API produced, and the caller must not use it
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn jnks_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, CString> {
+    if s.is_null() {
+        return Err(error("received a null string"));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| error("received a string that is not UTF-8"))
+}
+
+/// An error message as a C string. The message cannot hold a null byte, so we replace any.
+fn error(err: impl ToString) -> CString {
+    CString::new(err.to_string().replace('\0', " ")).unwrap()
+}
+
+fn panic_message(panic: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "the compiler panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compile(js_code: &str, export: Option<&str>) -> Result<Vec<u8>, String> {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let stdlib_path = root.parent().unwrap().join("stdlib.notwasm");
+        let stdlib = std::fs::read_to_string(&stdlib_path).expect("error reading stdlib.notwasm");
+        let stdlib = CString::new(stdlib).unwrap();
+        let includes = crate::notwasm::stdlib::read_includes(&stdlib_path)
+            .expect("error reading the files that stdlib.notwasm includes");
+        let src_name = CString::new("<test>").unwrap();
+        let js_code = CString::new(js_code).unwrap();
+        unsafe {
+            let opts = jnks_opts_new();
+            assert!(jnks_opts_set_stdlib(opts, stdlib.as_ptr()).is_null());
+            for (name, source) in includes {
+                let name = CString::new(name).unwrap();
+                let source = CString::new(source).unwrap();
+                assert!(
+                    jnks_opts_add_stdlib_include(opts, name.as_ptr(), source.as_ptr()).is_null()
+                );
+            }
+            if let Some(export) = export {
+                let export = CString::new(export).unwrap();
+                assert!(jnks_opts_add_export(opts, export.as_ptr()).is_null());
+            }
+            let output = jnks_compile(opts, src_name.as_ptr(), js_code.as_ptr());
+            jnks_opts_free(opts);
+            let mut len = 0;
+            let wasm = jnks_output_wasm(output, &mut len);
+            let result = if wasm.is_null() {
+                let err = CStr::from_ptr(jnks_output_error(output));
+                Err(err.to_str().unwrap().to_string())
+            } else {
+                Ok(std::slice::from_raw_parts(wasm, len).to_vec())
+            };
+            jnks_output_free(output);
+            result
+        }
+    }
+
+    #[test]
+    fn compile_through_c_api() {
+        let wasm = compile("var x = 1 + 2; console.log(x);", None).unwrap();
+        assert_eq!(&wasm[0..4], b"\0asm");
+        let err = compile("console.log(1);", Some("no_such_function")).unwrap_err();
+        assert!(err.contains("no_such_function"));
+    }
+
+    #[test]
+    fn malformed_profile() {
+        let profile = CString::new("main 1\n").unwrap();
+        unsafe {
+            let opts = jnks_opts_new();
+            let err = jnks_opts_set_profile_use(opts, profile.as_ptr());
+            assert!(!err.is_null());
+            jnks_string_free(err);
+            jnks_opts_free(opts);
+        }
+    }
+}
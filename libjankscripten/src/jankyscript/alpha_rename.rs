@@ -0,0 +1,328 @@
+//! Give every variable in the program a unique name.
+//!
+//! JavaScript variables are function-scoped, so two functions may declare variables with the
+//! same name, and a nested function may declare a variable that shadows a variable of an
+//! enclosing function. After closure conversion, functions are lifted out of their scopes and
+//! several phases identify variables by name alone, so shadowed variables would collide. This
+//! pass keeps the first declaration of each name and renames every later declaration of the same
+//! name with [NameGen::rename], which preserves the original name for debugging.
+//!
+//! A variable that is declared several times in the same function is a single variable, so all
+//! of its declarations get the same name. The exception name of a `catch` is scoped to its
+//! handler. Variables that the program does not declare (e.g., `log_any`) keep their names.
+//!
+//! preconditions: desugaring, so that all functions are expressions
+
+use super::syntax::*;
+use crate::shared::NameGen;
+use im_rc::HashMap;
+use std::collections::HashSet;
+
+type Env = HashMap<Id, Id>;
+
+pub fn alpha_rename(program: &mut Stmt) {
+    let mut renamer = Renamer::default();
+    renamer.function(&mut [], program, &Env::new());
+}
+
+#[derive(Default)]
+struct Renamer {
+    ng: NameGen,
+    /// Every name that the program has declared so far.
+    declared: HashSet<Id>,
+}
+
+/// Collects the variables that `stmt` declares, excluding variables of nested functions.
+pub(super) fn declared_vars(stmt: &Stmt, vars: &mut Vec<Id>) {
+    match stmt {
+        Stmt::Var(x, ..) => vars.push(x.clone()),
+        Stmt::Block(stmts, _) => {
+            for s in stmts {
+                declared_vars(s, vars);
+            }
+        }
+        Stmt::If(_, s1, s2, _) | Stmt::Catch(s1, _, s2, _) | Stmt::Finally(s1, s2, _) => {
+            declared_vars(s1, vars);
+            declared_vars(s2, vars);
+        }
+        Stmt::Loop(s, _) | Stmt::ForIn(_, _, s, _) | Stmt::Label(_, s, _) => declared_vars(s, vars),
+        Stmt::Empty
+        | Stmt::Expr(..)
+        | Stmt::Break(..)
+        | Stmt::Throw(..)
+        | Stmt::Return(..)
+        | Stmt::Pragma(..) => (),
+    }
+}
+
+impl Renamer {
+    /// The name of a new variable, which is `x` unless the program already declares `x`.
+    fn bind(&mut self, x: &Id) -> Id {
+        if self.declared.insert(x.clone()) {
+            x.clone()
+        } else {
+            self.ng.rename(x)
+        }
+    }
+
+    /// Renames the parameters and the variables of a function, and then its body. The whole
+    /// program is a function with no parameters.
+    fn function(&mut self, args: &mut [(Id, Type)], body: &mut Stmt, env: &Env) {
+        let mut env = env.clone();
+        let mut locals = HashSet::new();
+        for (x, _) in args.iter_mut() {
+            let y = self.bind(x);
+            locals.insert(x.clone());
+            env.insert(std::mem::replace(x, y.clone()), y);
+        }
+        let mut vars = Vec::new();
+        declared_vars(body, &mut vars);
+        for x in vars {
+            if locals.insert(x.clone()) {
+                let y = self.bind(&x);
+                env.insert(x, y);
+            }
+        }
+        self.stmt(body, &env);
+    }
+
+    fn id(&self, x: &mut Id, env: &Env) {
+        if let Some(y) = env.get(x) {
+            *x = y.clone();
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt, env: &Env) {
+        match stmt {
+            Stmt::Var(x, _, e, _) => {
+                self.id(x, env);
+                self.expr(e, env);
+            }
+            Stmt::Block(stmts, _) => {
+                for s in stmts {
+                    self.stmt(s, env);
+                }
+            }
+            Stmt::Empty | Stmt::Break(..) | Stmt::Pragma(..) => (),
+            Stmt::Expr(e, _) | Stmt::Throw(e, _) | Stmt::Return(e, _) => self.expr(e, env),
+            Stmt::If(e, s1, s2, _) => {
+                self.expr(e, env);
+                self.stmt(s1, env);
+                self.stmt(s2, env);
+            }
+            Stmt::Loop(s, _) | Stmt::Label(_, s, _) => self.stmt(s, env),
+            Stmt::ForIn(x, e, s, _) => {
+                self.id(x, env);
+                self.expr(e, env);
+                self.stmt(s, env);
+            }
+            Stmt::Catch(body, x, handler, _) => {
+                self.stmt(body, env);
+                let y = self.bind(x);
+                let env = env.update(std::mem::replace(x, y.clone()), y);
+                self.stmt(handler, &env);
+            }
+            Stmt::Finally(body, finally, _) => {
+                self.stmt(body, env);
+                self.stmt(finally, env);
+            }
+        }
+    }
+
+    fn lval(&mut self, lval: &mut LValue, env: &Env) {
+        match lval {
+            LValue::Id(x, _) => self.id(x, env),
+            LValue::Dot(e, _) => self.expr(e, env),
+            LValue::Bracket(e1, e2, _) => {
+                self.expr(e1, env);
+                self.expr(e2, env);
+            }
+        }
+    }
+
+    fn exprs(&mut self, es: &mut [Expr], env: &Env) {
+        for e in es {
+            self.expr(e, env);
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr, env: &Env) {
+        match expr {
+            Expr::Id(x, _, _) => self.id(x, env),
+            Expr::Lit(..) => (),
+            Expr::Array(es, _) | Expr::JsOp(_, es, _, _) | Expr::PrimCall(_, es, _) => {
+                self.exprs(es, env)
+            }
+            Expr::Object(props, _) => {
+                for (_, e) in props {
+                    self.expr(e, env);
+                }
+            }
+            Expr::Dot(e, _, _)
+            | Expr::Unary(_, e, _)
+            | Expr::Length(e, _, _)
+            | Expr::Coercion(_, e, _)
+            | Expr::NewRef(e, _, _)
+            | Expr::Deref(e, _, _) => self.expr(e, env),
+            Expr::Bracket(e1, e2, _, _)
+            | Expr::Binary(_, e1, e2, _)
+            | Expr::Store(e1, e2, _, _) => {
+                self.expr(e1, env);
+                self.expr(e2, env);
+            }
+            Expr::Assign(lval, e, _) => {
+                self.lval(lval, env);
+                self.expr(e, env);
+            }
+            Expr::Call(f, args, _) | Expr::MethodCall(f, _, args, _, _) => {
+                self.expr(f, env);
+                self.exprs(args, env);
+            }
+            Expr::Func(f, _) => self.function(&mut f.args_with_typs, &mut f.body, env),
+            Expr::Closure(..) | Expr::EnvGet(..) => {
+                panic!("alpha_rename happens before closure conversion")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::from_js::from_javascript;
+    use super::super::interpreter::interpret;
+    use super::super::walk::*;
+    use super::*;
+    use crate::javascript::{desugar, parse};
+
+    fn janky(js_code: &str) -> Stmt {
+        let mut js = parse("<test>", js_code).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        from_javascript(js)
+    }
+
+    /// Collects the variables and parameters that each function declares. A function may declare
+    /// a variable more than once.
+    struct Declarations {
+        stack: Vec<HashSet<Id>>,
+        done: Vec<HashSet<Id>>,
+    }
+
+    impl Declarations {
+        /// The names that the program declares, once for every function that declares them.
+        fn names(program: &mut Stmt) -> Vec<Id> {
+            let mut declarations = Declarations {
+                stack: vec![HashSet::new()],
+                done: Vec::new(),
+            };
+            program.walk(&mut declarations);
+            let Declarations { stack, done } = declarations;
+            stack.into_iter().chain(done).flatten().collect()
+        }
+    }
+
+    impl Visitor for Declarations {
+        fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+            match stmt {
+                Stmt::Var(x, ..) | Stmt::Catch(_, x, _, _) => {
+                    self.stack.last_mut().unwrap().insert(x.clone());
+                }
+                _ => (),
+            }
+        }
+
+        fn enter_fn(&mut self, func: &mut Func, _loc: &Loc) {
+            self.stack.push(func.arg_names().cloned().collect());
+        }
+
+        fn exit_fn(&mut self, _func: &mut Func, _loc: &Loc) {
+            let declared = self.stack.pop().unwrap();
+            self.done.push(declared);
+        }
+    }
+
+    /// Renames the program, checks that no two functions declare the same name, and checks that
+    /// the program prints the same output.
+    fn check(js_code: &str) -> Stmt {
+        let mut program = janky(js_code);
+        let expected = interpret(&program).expect("error interpreting the original program");
+        alpha_rename(&mut program);
+        let mut unique = HashSet::new();
+        for x in Declarations::names(&mut program) {
+            assert!(
+                unique.insert(x.clone()),
+                "{} is declared in two functions",
+                x
+            );
+        }
+        let actual = interpret(&program).expect("error interpreting the renamed program");
+        assert_eq!(expected, actual);
+        program
+    }
+
+    #[test]
+    fn shadowed_function_declarations() {
+        check(
+            "function helper() { return 1; }
+            function f() {
+                function helper() { return 2; }
+                return helper();
+            }
+            function g() {
+                function helper() { return 3; }
+                return helper();
+            }
+            log_any(helper() + f() * 10 + g() * 100);",
+        );
+    }
+
+    #[test]
+    fn shadowed_variables_and_parameters() {
+        check(
+            "var x = 1;
+            function f(x) {
+                var y = x + 1;
+                return function(y) { var x = y * 2; return x; }(y);
+            }
+            log_any(f(x));
+            log_any(x);",
+        );
+    }
+
+    #[test]
+    fn redeclared_variable_is_one_variable() {
+        check(
+            "function f(x) {
+                var x = x + 1;
+                var x = x + 1;
+                return x;
+            }
+            log_any(f(1));",
+        );
+    }
+
+    #[test]
+    fn catch_binding_is_scoped_to_handler() {
+        check(
+            r#"var e = "outer";
+            try {
+                throw "inner";
+            } catch (e) {
+                log_any(e);
+            }
+            log_any(e);"#,
+        );
+    }
+
+    #[test]
+    fn renamed_names_keep_original_name() {
+        let mut program = check(
+            "var x = 1;
+            function f() { var x = 2; return x; }
+            log_any(f() + x);",
+        );
+        assert!(Declarations::names(&mut program)
+            .iter()
+            .any(|x| x.to_string().starts_with("$jnks_x$")));
+    }
+}
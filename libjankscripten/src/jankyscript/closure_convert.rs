@@ -2,8 +2,10 @@
 //! and code
 //!
 //! the relevant prerequisites are:
-//! - free variables that are assigned anywhere should be boxed so that the
-//!   environments will capture by name
+//! - free variables that may be assigned after a closure captures them should
+//!   be boxed so that the environments will capture by name (see
+//!   [super::collect_assigns]). the environment captures every other free
+//!   variable by value
 //!
 //! the process is:
 //!
@@ -49,8 +51,8 @@ struct ClosureConversion {
 impl Visitor for ClosureConversion {
     fn enter_fn(&mut self, func: &mut Func, _: &Loc) {
         self.free_vars_stack.push(
-            func.free_vars
-                .iter()
+            sorted_free_vars(func)
+                .into_iter()
                 .enumerate()
                 .map(|(i, (k, _))| (k.clone(), i as u32))
                 .collect(),
@@ -68,12 +70,11 @@ impl Visitor for ClosureConversion {
                 // the scope of the containing function not the function itself
                 self.free_vars_stack.pop();
                 // remember which variables should be passed on from env vs
-                // from stack. im_rc hashmap iter is guaranteed to be
-                // consistent for the same data
+                // from stack. the order of the environment must agree with
+                // enter_fn
                 let s_copy = s.clone();
-                let env = func
-                    .free_vars
-                    .iter()
+                let env = sorted_free_vars(func)
+                    .into_iter()
                     .map(
                         |(id, ty)| match self.compile_id(id, ty.clone(), s.clone()) {
                             Some(e) => (e, ty.clone()),
@@ -95,6 +96,15 @@ impl Visitor for ClosureConversion {
         }
     }
 }
+
+/// The free variables of `func`, sorted by name, so that the layout of its environment does not
+/// depend on the order of the hash table.
+fn sorted_free_vars(func: &Func) -> Vec<(&Id, &Type)> {
+    let mut free_vars: Vec<_> = func.free_vars.iter().collect();
+    free_vars.sort_by_key(|(id, _)| id.to_string());
+    free_vars
+}
+
 impl ClosureConversion {
     fn new() -> Self {
         Self {
@@ -1,7 +1,21 @@
 //! determine variables that need to be boxed
 //!
-//! we box a variable completely if it is captured by a function at any
-//! point in its scope AND it is assigned to at any point in its scope
+//! we box a variable if it is captured by a function at some point in its
+//! scope AND it may be assigned after a closure captures it, i.e., it is
+//! assigned:
+//!
+//! - in a function nested in its scope,
+//! - after a function that captures it (including by `var x = e` and by
+//!   `for (x in e)`), or
+//! - in a loop that also creates a function that captures it, since the next
+//!   iteration assigns it after the capture
+//!
+//! a variable that is only assigned before every closure captures it does not
+//! need a box: closure conversion copies its value into the environment of the
+//! closure (capture by value), and the value cannot change afterwards. this
+//! relies on the program order of the statements of a function being the order
+//! in which they run, except for loops, so names must be unique
+//! ([super::alpha_rename])
 //!
 //! if it isn't boxed, assignments after the environment creation will not
 //! update the closure environment; also, assignments into the closure
@@ -11,55 +25,132 @@
 //! It seems weird that inner is turned into a ref. It is never assigned from
 //! within a function.
 
+use super::fv::map_functions;
 use super::syntax::*;
 use super::walk::*;
+use crate::pos::Pos;
+use im_rc::HashMap;
 use im_rc::HashSet as ImmHashSet;
 
+/// The variables that a program assigns, and the ones that it must box. The program must be
+/// annotated with its free variables (see [super::fv::free_vars]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assigns {
+    /// The variables in the global scope that should be boxed, which [collect_assigns] produces
+    pub boxed: ImmHashSet<Id>,
+    /// The positions of the assignments to each variable, in the order of the program
+    pub sites: HashMap<Id, Vec<Pos>>,
+    /// The variables that each function should box ([Func::assigned_free_children]), by the
+    /// position of the function, in the order in which the functions start
+    pub functions: Vec<(Pos, ImmHashSet<Id>)>,
+}
+
 /// returns variables in the global scope that should be boxed
 pub fn collect_assigns(program: &mut Stmt) -> ImmHashSet<Id> {
+    analyze_assigns(program).boxed
+}
+
+/// Like [collect_assigns], but also produces where the program assigns variables, and the
+/// variables that each function should box.
+pub fn analyze_assigns(program: &mut Stmt) -> Assigns {
     let mut v = CollectAssigns::new();
     program.walk(&mut v);
     assert_eq!(v.free_children.len(), 1);
-    assert_eq!(v.assigned_vars.len(), 1);
+    assert_eq!(v.shared_assigns.len(), 1);
     let free_children = v.free_children.pop().unwrap();
-    let assigned_vars = v.assigned_vars.pop().unwrap();
-    free_children.intersection(assigned_vars.clone())
+    let shared_assigns = v.shared_assigns.pop().unwrap();
+    Assigns {
+        boxed: free_children.intersection(shared_assigns),
+        sites: v.sites,
+        functions: map_functions(program, |func| func.assigned_free_children.clone()),
+    }
 }
 
 struct CollectAssigns {
     /// this represents the free variables of all functions created in this
-    /// function ("children")
+    /// function ("children") so far
     free_children: Vec<ImmHashSet<Id>>,
+    /// the variables that this function or its children assign
     assigned_vars: Vec<ImmHashSet<Id>>,
+    /// the variables that may be assigned after a child captures them
+    shared_assigns: Vec<ImmHashSet<Id>>,
+    /// the loops of each function that we are in, innermost last
+    loops: Vec<Vec<LoopAssigns>>,
+    sites: HashMap<Id, Vec<Pos>>,
 }
+
+/// The variables that a loop assigns, and the ones that the functions that it creates capture
+#[derive(Default)]
+struct LoopAssigns {
+    assigned: ImmHashSet<Id>,
+    captured: ImmHashSet<Id>,
+}
+
 impl Visitor for CollectAssigns {
     fn enter_fn(&mut self, _func: &mut Func, _: &Loc) {
         self.free_children.push(ImmHashSet::new());
         self.assigned_vars.push(ImmHashSet::new());
+        self.shared_assigns.push(ImmHashSet::new());
+        self.loops.push(Vec::new());
     }
     fn exit_fn(&mut self, func: &mut Func, _: &Loc) {
         let free_children = self.free_children.pop().unwrap();
         let assigned_vars = self.assigned_vars.pop().unwrap();
-        func.assigned_free_children = free_children.intersection(assigned_vars.clone());
+        let shared_assigns = self.shared_assigns.pop().unwrap();
+        self.loops.pop();
+        func.assigned_free_children = free_children.intersection(shared_assigns);
         // add these free variables to the free children of our parent so
-        // it'll eventually have all the proper free children
+        // it'll eventually have all the proper free children. this is where
+        // the parent creates the closure, so it captures them here
+        let captured: ImmHashSet<Id> = func.free_vars.keys().cloned().collect();
         let parent_free_children = self.free_children.last_mut().unwrap();
-        *parent_free_children = parent_free_children
-            .clone()
-            .union(func.free_vars.keys().cloned().collect());
+        *parent_free_children = parent_free_children.clone().union(captured.clone());
+        if let Some(l) = self.loops.last_mut().unwrap().last_mut() {
+            l.captured = l.captured.clone().union(captured);
+        }
         // this might look similar but it's very different: we care about
         // the assigned vars of ourselves and our children but not our parents,
-        // so propagate up children's data
-        let parent_assigned_vars = self.last_assigned_vars();
-        *parent_assigned_vars = parent_assigned_vars.clone().union(assigned_vars);
+        // so propagate up children's data. our parent cannot know when we
+        // assign them, so they are all shared
+        let parent_assigned_vars = self.assigned_vars.last_mut().unwrap();
+        *parent_assigned_vars = parent_assigned_vars.clone().union(assigned_vars.clone());
+        let parent_shared_assigns = self.shared_assigns.last_mut().unwrap();
+        *parent_shared_assigns = parent_shared_assigns.clone().union(assigned_vars);
+    }
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _: &Loc) {
+        match stmt {
+            Stmt::Loop(..) => self.last_loops().push(LoopAssigns::default()),
+            Stmt::ForIn(id, ..) => {
+                self.last_loops().push(LoopAssigns::default());
+                self.assign(id);
+            }
+            _ => (),
+        }
+    }
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _: &Loc) {
+        match stmt {
+            Stmt::Var(id, ..) => self.assign(id),
+            Stmt::Loop(..) | Stmt::ForIn(..) => {
+                let l = self.last_loops().pop().unwrap();
+                let shared_assigns = self.shared_assigns.last_mut().unwrap();
+                *shared_assigns = shared_assigns
+                    .clone()
+                    .union(l.assigned.clone().intersection(l.captured.clone()));
+                if let Some(outer) = self.last_loops().last_mut() {
+                    outer.assigned = outer.assigned.clone().union(l.assigned);
+                    outer.captured = outer.captured.clone().union(l.captured);
+                }
+            }
+            _ => (),
+        }
     }
     fn exit_expr(&mut self, expr: &mut Expr, _: &Loc) {
         match expr {
-            Expr::Assign(lv, _, _) => {
+            Expr::Assign(lv, _, p) => {
                 match &**lv {
                     LValue::Id(id, _) => {
-                        let assigned_vars = self.last_assigned_vars();
-                        *assigned_vars = assigned_vars.update(id.clone());
+                        self.assign(id);
+                        self.sites.entry(id.clone()).or_default().push(p.clone());
                     }
                     // []/. => boxed already!
                     _ => (),
@@ -75,9 +166,108 @@ impl CollectAssigns {
             // start with the top level
             free_children: vec![ImmHashSet::new()],
             assigned_vars: vec![ImmHashSet::new()],
+            shared_assigns: vec![ImmHashSet::new()],
+            loops: vec![Vec::new()],
+            sites: HashMap::new(),
+        }
+    }
+    fn last_loops(&mut self) -> &mut Vec<LoopAssigns> {
+        self.loops.last_mut().unwrap()
+    }
+    /// records that the current function assigns `id`, which is shared if a
+    /// child has captured it already
+    fn assign(&mut self, id: &Id) {
+        let assigned_vars = self.assigned_vars.last_mut().unwrap();
+        *assigned_vars = assigned_vars.update(id.clone());
+        if self.free_children.last().unwrap().contains(id) {
+            let shared_assigns = self.shared_assigns.last_mut().unwrap();
+            *shared_assigns = shared_assigns.update(id.clone());
+        }
+        if let Some(l) = self.last_loops().last_mut() {
+            l.assigned = l.assigned.update(id.clone());
         }
     }
-    fn last_assigned_vars(&mut self) -> &mut ImmHashSet<Id> {
-        self.assigned_vars.last_mut().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::compile::insert_coercions;
+    use super::super::from_js::from_javascript;
+    use super::super::fv::analyze_free_vars;
+    use super::*;
+    use crate::javascript::{desugar, parse};
+    use crate::shared::NameGen;
+
+    fn program(js_code: &str) -> Stmt {
+        let mut js = parse("<test>", js_code).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        let mut program = from_javascript(js);
+        insert_coercions(&mut program, |_| ()).expect("error inserting coercions");
+        program
+    }
+
+    #[test]
+    fn captured_and_assigned() {
+        let mut program = program(
+            "var x = 1;
+            var y = 2;
+            var f = function() { return x + y; };
+            x = 2;
+            log_any(f());",
+        );
+        let free_vars = analyze_free_vars(&mut program);
+        assert_eq!(free_vars.functions.len(), 1);
+        let (_, f_free_vars) = &free_vars.functions[0];
+        assert!(f_free_vars.contains_key(&Id::from("x")));
+        assert!(f_free_vars.contains_key(&Id::from("y")));
+        let assigns = analyze_assigns(&mut program);
+        assert!(assigns.boxed.contains(&Id::from("x")));
+        assert!(!assigns.boxed.contains(&Id::from("y")));
+        assert!(!assigns.sites[&Id::from("x")].is_empty());
+        assert!(assigns.sites.get(&Id::from("y")).is_none());
+        assert_eq!(assigns.functions.len(), 1);
+    }
+
+    fn boxed(js_code: &str) -> ImmHashSet<Id> {
+        let mut program = program(js_code);
+        analyze_free_vars(&mut program);
+        collect_assigns(&mut program)
+    }
+
+    #[test]
+    fn assigned_before_capture_is_not_boxed() {
+        let boxed = boxed(
+            "var x = 1;
+            x = 2;
+            var f = function() { return x; };
+            log_any(f());",
+        );
+        assert!(!boxed.contains(&Id::from("x")));
+    }
+
+    #[test]
+    fn assigned_in_loop_with_capture_is_boxed() {
+        let boxed = boxed(
+            "var f = function() { return 0; };
+            var i = 0;
+            while (i < 3) {
+                f = function() { return i; };
+                i = i + 1;
+            }
+            log_any(f());",
+        );
+        assert!(boxed.contains(&Id::from("i")));
+    }
+
+    #[test]
+    fn assigned_in_function_is_boxed() {
+        let boxed = boxed(
+            "var x = 1;
+            var f = function() { x = x + 1; };
+            f();
+            log_any(x);",
+        );
+        assert!(boxed.contains(&Id::from("x")));
     }
 }
@@ -1,33 +1,94 @@
+use super::alpha_rename::alpha_rename;
 use super::box_assigns::box_assigns;
 use super::closure_convert::closure_convert;
 use super::coercions::reify_coercions;
 use super::collect_assigns::collect_assigns;
+use super::forward_coercions::forward_coercions;
 use super::fv::free_vars;
+use super::global_object::global_object;
 use super::insert_returns::insert_returns;
 use super::select_method_call::select_method_call;
 use super::syntax::*;
 use super::type_checking::{type_check, TypeCheckingError};
-use super::typeinf::typeinf;
+#[cfg(feature = "z3")]
+use super::typeinf::typeinf_with_weights;
+use super::typeinf_dynamic;
+use super::unbox_refs::unbox_refs;
+use crate::opts::Opts;
+use crate::passes::PassManager;
 
-pub fn compile<F>(janky_ast: &mut Stmt, inspect_janky: F) -> Result<(), TypeCheckingError>
+pub fn compile<F>(
+    opts: &Opts,
+    janky_ast: &mut Stmt,
+    inspect_janky: F,
+) -> Result<(), TypeCheckingError>
 where
     F: FnOnce(&Stmt) -> (),
 {
-    insert_returns(janky_ast);
-    typeinf(janky_ast);
-    select_method_call(janky_ast);
-    inspect_janky(&janky_ast);
-    reify_coercions(janky_ast);
-    type_check(janky_ast)?;
+    let mut passes = PassManager::new("JankyScript", opts);
+    add_insert_coercions(&mut passes, opts, inspect_janky);
+    passes.add("forward_coercions", forward_coercions);
     // TODO(luna): maybe the runtime should be added in jankierscript or
     // jankyscript. this would mean we could assert free_vars == \emptyset
-    free_vars(janky_ast);
-    let should_box_globals = collect_assigns(janky_ast);
-    box_assigns(janky_ast, should_box_globals);
-    closure_convert(janky_ast);
+    passes.add("free_vars", |janky_ast| {
+        free_vars(janky_ast);
+    });
+    passes.add("box_assigns", |janky_ast| {
+        let should_box_globals = collect_assigns(janky_ast);
+        box_assigns(janky_ast, should_box_globals);
+    });
+    passes.add("closure_convert", closure_convert);
+    passes.add("unbox_refs", unbox_refs);
     // type-checking should succeed after every phase.
-    type_check(janky_ast)?;
+    passes.add_fallible("type_check", |janky_ast| type_check(janky_ast));
     // Inspect after type-checking, so that all type annotations are present.
     //inspect_janky(&janky_ast);
-    Ok(())
+    passes.run(janky_ast)
+}
+
+/// The phases of `compile` up to, and including, coercion insertion, with the default options. The
+/// result has explicit coercions, but it is not yet closure-converted.
+pub fn insert_coercions<F>(janky_ast: &mut Stmt, inspect_janky: F) -> Result<(), TypeCheckingError>
+where
+    F: FnOnce(&Stmt) -> (),
+{
+    let opts = Opts::new();
+    let mut passes = PassManager::new("JankyScript", &opts);
+    add_insert_coercions(&mut passes, &opts, inspect_janky);
+    passes.run(janky_ast)
+}
+
+fn add_insert_coercions<'a, F>(
+    passes: &mut PassManager<'a, Stmt, TypeCheckingError>,
+    opts: &'a Opts,
+    inspect_janky: F,
+) where
+    F: FnOnce(&Stmt) -> () + 'a,
+{
+    passes.add("global_object", global_object);
+    passes.add("alpha_rename", alpha_rename);
+    passes.add("insert_returns", insert_returns);
+    passes.add("typeinf", move |janky_ast| typeinf(opts, janky_ast));
+    passes.add("select_method_call", select_method_call);
+    passes.inspect(inspect_janky);
+    passes.add("reify_coercions", reify_coercions);
+    passes.add_fallible("type_check", |janky_ast| type_check(janky_ast));
+}
+
+#[cfg(feature = "z3")]
+fn typeinf(opts: &Opts, janky_ast: &mut Stmt) {
+    if opts.dynamic_types {
+        typeinf_dynamic::typeinf(janky_ast);
+        return;
+    }
+    let objective = typeinf_with_weights(janky_ast, &opts.typeinf_weights);
+    if opts.print_typeinf_objective {
+        eprintln!("typeinf objective: {}", objective);
+    }
+}
+
+/// Without Z3, almost every type is any, so there are no costs to tune.
+#[cfg(not(feature = "z3"))]
+fn typeinf(_opts: &Opts, janky_ast: &mut Stmt) {
+    typeinf_dynamic::typeinf(janky_ast);
 }
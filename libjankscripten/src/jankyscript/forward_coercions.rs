@@ -0,0 +1,395 @@
+//! Keep the results of functions at their precise types across calls.
+//!
+//! When type inference gives a function the result type `any`, every return statement tags its
+//! value, and a caller that needs a precise type untags the result. When every return statement
+//! of the function tags a value of the same ground type `T`, and some caller untags the result to
+//! `T`, the result goes through `any` for nothing. This pass changes the result type of such a
+//! function to `T`:
+//!
+//! - `return tag<T>(e)` becomes `return e` in the function,
+//! - `untag<T>(f(..))` becomes `f(..)`, and
+//! - any other call `f(..)` becomes `tag<T>(f(..))`, so that its caller still receives an `any`.
+//!
+//! The pass only changes a function when the program assigns its variable once, to the function,
+//! and only uses the variable as the callee of calls, since any other use may pass the function
+//! where a function that produces `any` is expected. Finally, the pass removes coercions that
+//! undo each other, such as `untag<T>(tag<T>(e))`, wherever they occur.
+//!
+//! preconditions: [super::compile::insert_coercions], and [super::alpha_rename], so that a name
+//! identifies a single variable
+use super::syntax::*;
+use super::walk::*;
+use std::collections::{HashMap, HashSet};
+
+pub fn forward_coercions(program: &mut Stmt) {
+    let mut uses = Uses::default();
+    program.walk(&mut uses);
+    let forwarded = uses.forwarded();
+    if !forwarded.is_empty() {
+        program.walk(&mut Forward {
+            forwarded: &forwarded,
+        });
+    }
+    program.walk(&mut CancelCoercions);
+}
+
+#[derive(Default)]
+struct Uses {
+    /// The number of times that the program assigns each variable, including declarations with
+    /// an initializer other than `undefined`.
+    assignments: HashMap<Id, usize>,
+    /// The variables that the program assigns a function that produces `any`, whose return
+    /// statements all tag values of the same ground type, with that type.
+    returns: HashMap<Id, Type>,
+    /// The number of occurrences of each variable in an expression.
+    occurrences: HashMap<Id, usize>,
+    /// The number of occurrences of each variable as the callee of a call.
+    callees: HashMap<Id, usize>,
+    /// The pairs `(f, T)` of the calls `untag<T>(f(..))`.
+    untagged: HashSet<(Id, Type)>,
+}
+
+impl Uses {
+    /// The functions whose result the pass forwards, with their new result types.
+    fn forwarded(self) -> HashMap<Id, Type> {
+        let Uses {
+            assignments,
+            returns,
+            occurrences,
+            callees,
+            untagged,
+        } = self;
+        returns
+            .into_iter()
+            .filter(|(f, t)| {
+                assignments.get(f) == Some(&1)
+                    && occurrences.get(f) == callees.get(f)
+                    && untagged.contains(&(f.clone(), t.clone()))
+            })
+            .collect()
+    }
+
+    fn assign(&mut self, x: &Id, e: &mut Expr) {
+        *self.assignments.entry(x.clone()).or_insert(0) += 1;
+        if let Some(func) = func_of(e) {
+            if func.result_typ == Type::Any {
+                let mut v = Returns::default();
+                func.body.walk(&mut v);
+                if let (true, Some(t)) = (v.tagged, v.typ) {
+                    self.returns.insert(x.clone(), t);
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for Uses {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::Var(x, _, e, _) if !e.is_undefined() => self.assign(x, e),
+            Stmt::ForIn(x, ..) | Stmt::Catch(_, x, ..) => {
+                *self.assignments.entry(x.clone()).or_insert(0) += 1;
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Id(x, ..) => *self.occurrences.entry(x.clone()).or_insert(0) += 1,
+            Expr::Assign(lv, e, _) => {
+                if let LValue::Id(x, _) = &**lv {
+                    self.assign(x, e);
+                }
+            }
+            Expr::Call(f, ..) => {
+                if let Expr::Id(f, ..) = &**f {
+                    *self.callees.entry(f.clone()).or_insert(0) += 1;
+                }
+            }
+            Expr::Coercion(Coercion::Untag(t), e, _) => {
+                if let Some(f) = callee(e) {
+                    self.untagged.insert((f.clone(), t.clone()));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The function that `e` produces, which may be under identity coercions.
+fn func_of(e: &mut Expr) -> Option<&mut Func> {
+    match e {
+        Expr::Func(func, _) => Some(func),
+        Expr::Coercion(Coercion::Id(_), e, _) => func_of(e),
+        _ => None,
+    }
+}
+
+/// The function that `e` calls directly, if it is a call.
+fn callee(e: &Expr) -> Option<&Id> {
+    match e {
+        Expr::Call(f, ..) => match &**f {
+            Expr::Id(f, ..) => Some(f),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Determines if every return statement of a function tags a value of the same ground type.
+/// Nested functions have their own return statements.
+#[derive(Default)]
+struct Returns {
+    depth: usize,
+    typ: Option<Type>,
+    /// Whether every return statement so far tags its value, with the type `typ`.
+    tagged: bool,
+}
+
+impl Visitor for Returns {
+    fn enter_fn(&mut self, _func: &mut Func, _loc: &Loc) {
+        self.depth += 1;
+    }
+
+    fn exit_fn(&mut self, _func: &mut Func, _loc: &Loc) {
+        self.depth -= 1;
+    }
+
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if self.depth > 0 {
+            return;
+        }
+        if let Stmt::Return(e, _) = stmt {
+            match (&**e, &self.typ) {
+                (Expr::Coercion(Coercion::Tag(t), ..), None) if t.is_ground() => {
+                    self.typ = Some(t.clone());
+                    self.tagged = true;
+                }
+                (Expr::Coercion(Coercion::Tag(t), ..), Some(typ)) if t == typ => (),
+                _ => {
+                    self.tagged = false;
+                    self.typ = Some(Type::Any);
+                }
+            }
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.typ == Some(Type::Any)
+    }
+}
+
+/// Sets the result type of the function type `ty` to `t`.
+fn forward_type(ty: &mut Type, t: &Type) {
+    if let Type::Function(_, result) = ty {
+        **result = t.clone();
+    }
+}
+
+/// Sets the result type of the function in `e` to `t`, and removes the tags of its results.
+fn forward_func(e: &mut Expr, t: &Type) {
+    match e {
+        Expr::Func(func, _) => {
+            func.result_typ = t.clone();
+            func.body.walk(&mut UntagReturns { depth: 0 });
+        }
+        Expr::Coercion(Coercion::Id(ty), e, _) => {
+            forward_type(ty, t);
+            forward_func(e, t);
+        }
+        _ => (),
+    }
+}
+
+struct UntagReturns {
+    depth: usize,
+}
+
+impl Visitor for UntagReturns {
+    fn enter_fn(&mut self, _func: &mut Func, _loc: &Loc) {
+        self.depth += 1;
+    }
+
+    fn exit_fn(&mut self, _func: &mut Func, _loc: &Loc) {
+        self.depth -= 1;
+    }
+
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if self.depth > 0 {
+            return;
+        }
+        if let Stmt::Return(e, _) = stmt {
+            if let Expr::Coercion(Coercion::Tag(_), value, _) = &mut **e {
+                let value = value.take();
+                **e = value;
+            }
+        }
+    }
+}
+
+struct Forward<'a> {
+    forwarded: &'a HashMap<Id, Type>,
+}
+
+impl Visitor for Forward<'_> {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Var(x, ty, e, _) = stmt {
+            if let Some(t) = self.forwarded.get(x) {
+                forward_type(ty, t);
+                forward_func(e, t);
+            }
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Id(x, ty, _) => {
+                if let Some(t) = self.forwarded.get(x) {
+                    forward_type(ty, t);
+                }
+            }
+            Expr::Assign(lv, e, _) => {
+                if let LValue::Id(x, ty) = &mut **lv {
+                    if let Some(t) = self.forwarded.get(x) {
+                        forward_type(ty, t);
+                        forward_func(e, t);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Some(t) = callee(expr).and_then(|f| self.forwarded.get(f)) {
+            let t = t.clone();
+            let p = match expr {
+                Expr::Call(_, _, p) => p.clone(),
+                _ => unreachable!(),
+            };
+            let call = expr.take();
+            *expr = Expr::Coercion(Coercion::Tag(t), Box::new(call), p);
+        }
+    }
+}
+
+/// Removes the coercions that undo each other.
+struct CancelCoercions;
+
+impl Visitor for CancelCoercions {
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Coercion(outer, e, _) = expr {
+            if let Expr::Coercion(inner, value, _) = &mut **e {
+                let cancels = match (inner, outer) {
+                    (Coercion::Tag(t1), Coercion::Untag(t2)) => t1 == t2,
+                    // Every i32 is exactly representable as an f64.
+                    (Coercion::IntToFloat, Coercion::FloatToInt) => true,
+                    _ => false,
+                };
+                if cancels {
+                    let value = value.take();
+                    *expr = value;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::constructors::*;
+    use super::super::type_checking::type_check;
+    use super::*;
+    use crate::pos::Pos;
+
+    fn int_(n: i32) -> Expr {
+        lit_(Lit::Num(Num::Int(n)), Pos::UNKNOWN)
+    }
+
+    fn call_f(n: i32) -> Expr {
+        let f_ty = Type::Function(vec![Type::Int], Box::new(Type::Any));
+        Expr::Call(
+            Box::new(Expr::Id("f".into(), f_ty, Pos::UNKNOWN)),
+            vec![int_(n)],
+            Pos::UNKNOWN,
+        )
+    }
+
+    /// `var f = function(x: int): any { return tag<int>(x + 1); }`, followed by `stmts`.
+    fn program(stmts: Vec<Stmt>) -> Stmt {
+        let p = || Pos::UNKNOWN;
+        let f_ty = Type::Function(vec![Type::Int], Box::new(Type::Any));
+        let x = Expr::Id("x".into(), Type::Int, p());
+        let body = return_(
+            Expr::Coercion(
+                Coercion::Tag(Type::Int),
+                Box::new(binary_(BinaryOp::I32Add, x, int_(1), p())),
+                p(),
+            ),
+            p(),
+        );
+        let f = func(vec![("x".into(), Type::Int)], Type::Any, body, p());
+        let mut program = vec![
+            var_("f".into(), f_ty.clone(), lit_(Lit::Undefined, p()), p()),
+            expr_(
+                assign_var_(
+                    "f".into(),
+                    f_ty.clone(),
+                    Expr::Coercion(Coercion::Id(f_ty), Box::new(f), p()),
+                    p(),
+                ),
+                p(),
+            ),
+        ];
+        program.extend(stmts);
+        block_(program, p())
+    }
+
+    /// The result type of the function in `program`.
+    fn result_typ(program: &mut Stmt) -> Type {
+        struct ResultTyp(Option<Type>);
+        impl Visitor for ResultTyp {
+            fn enter_fn(&mut self, func: &mut Func, _loc: &Loc) {
+                self.0 = Some(func.result_typ.clone());
+            }
+        }
+        let mut v = ResultTyp(None);
+        program.walk(&mut v);
+        v.0.unwrap()
+    }
+
+    #[test]
+    fn untagged_result_is_forwarded() {
+        let untagged = Expr::Coercion(
+            Coercion::Untag(Type::Int),
+            Box::new(call_f(1)),
+            Pos::UNKNOWN,
+        );
+        let mut program = program(vec![
+            var_("y".into(), Type::Int, untagged, Pos::UNKNOWN),
+            var_("z".into(), Type::Any, call_f(2), Pos::UNKNOWN),
+        ]);
+        forward_coercions(&mut program);
+        type_check(&program).expect("error type-checking the forwarded program");
+        assert_eq!(result_typ(&mut program), Type::Int);
+    }
+
+    #[test]
+    fn escaping_function_is_not_forwarded() {
+        let untagged = Expr::Coercion(
+            Coercion::Untag(Type::Int),
+            Box::new(call_f(1)),
+            Pos::UNKNOWN,
+        );
+        let f_ty = Type::Function(vec![Type::Int], Box::new(Type::Any));
+        let g = Expr::Id("f".into(), f_ty.clone(), Pos::UNKNOWN);
+        let mut program = program(vec![
+            var_("y".into(), Type::Int, untagged, Pos::UNKNOWN),
+            var_("g".into(), f_ty, g, Pos::UNKNOWN),
+        ]);
+        forward_coercions(&mut program);
+        type_check(&program).expect("error type-checking the program");
+        assert_eq!(result_typ(&mut program), Type::Any);
+    }
+}
@@ -59,13 +59,14 @@ fn expr(e: Js::Expr) -> Expr {
             s,
         ),
         E::Length(obj, s) => Expr::Length(Box::new(expr(*obj)), Type::Missing, s),
-        E::Func(_, args, body, s) => func(
+        E::Func(_, args, body, _, s) => func(
             args.into_iter().map(|x| (x, Type::Missing)).collect(),
             Type::Missing,
             stmt(*body),
             s,
         ),
         E::Seq(_, _) => unexpected(e),
+        E::CallThis(..) | E::Apply(..) | E::Yield(..) | E::Await(..) => unexpected(e),
     }
 }
 
@@ -75,6 +76,7 @@ fn stmt(s: Js::Stmt) -> Stmt {
     match s {
         S::Block(stmts, s) => Block(stmts.into_iter().map(|s| stmt(s)).collect(), s),
         S::Empty => Empty,
+        S::Pragma(pragma, s) => Pragma(pragma, s),
         S::Expr(e, s) => Expr(Box::new(expr(*e)), s),
         S::If(c, t, e, s) => If(
             Box::new(expr(*c)),
@@ -86,6 +88,7 @@ fn stmt(s: Js::Stmt) -> Stmt {
             assert!(!is_var, "for..in was not desugared to not use var");
             for_in_(bind, expr(*container), stmt(*body), s)
         }
+        S::ForOf(.., s) => unexpected(&s),
         S::Label(x, st, s) => Label(x, Box::new(stmt(*st)), s),
         S::Break(x, s) => Break(x.unwrap(), s),
         S::Continue(_, s) => unexpected(&s),
@@ -115,7 +118,10 @@ fn stmt(s: Js::Stmt) -> Stmt {
             }
             vardecl(decls.remove(0), s)
         }
-        S::Func(_, _, _, s) => unexpected(&s),
+        S::Destructure(_, _, s) => unexpected(&s),
+        S::DefaultParam(_, _, s) | S::RestParam(_, s) => unexpected(&s),
+        S::Func(.., s) => unexpected(&s),
+        S::Class(_, s) => unexpected(&s),
         S::Return(e, s) => Return(Box::new(expr(*e)), s),
     }
 }
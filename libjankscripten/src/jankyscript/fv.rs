@@ -1,7 +1,26 @@
+//! Free variables.
+//!
+//! [free_vars] annotates every function of a program with the variables that it refers to but
+//! does not declare, and their types ([Func::free_vars]), which closure conversion puts in the
+//! environment of the function. [analyze_free_vars] also produces them, by the position of each
+//! function, for passes and tools that only need the analysis.
 use super::syntax::*;
+use super::walk::*;
+use crate::pos::Pos;
 use im_rc::HashMap;
 type IdMap = HashMap<Id, Type>;
 
+/// The free variables of a program and of each of its functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreeVars {
+    /// The variables that the program refers to but does not declare (e.g., the globals of the
+    /// runtime system)
+    pub program: HashMap<Id, Type>,
+    /// The free variables of each function, by the position of the function, in the order in
+    /// which the functions start
+    pub functions: Vec<(Pos, HashMap<Id, Type>)>,
+}
+
 fn empty() -> IdMap {
     IdMap::new()
 }
@@ -29,7 +48,7 @@ fn var_summary(stmt: &mut Stmt) -> (IdMap, IdMap) {
                 IdMap::unions(referenced.into_iter()),
             )
         }
-        Empty => (empty(), empty()),
+        Empty | Pragma(..) => (empty(), empty()),
         Expr(e, _) => (empty(), fv_expr(e)),
         If(cond, true_part, false_part, _) => {
             let (declared_in_true, referenced_in_true) = var_summary(true_part);
@@ -121,3 +140,36 @@ fn fv_expr(expr: &mut Expr) -> IdMap {
 pub fn free_vars(program: &mut Stmt) -> IdMap {
     fv_stmt(program)
 }
+
+/// Like [free_vars], but also produces the free variables of each function.
+pub fn analyze_free_vars(program: &mut Stmt) -> FreeVars {
+    let free_vars = free_vars(program);
+    FreeVars {
+        program: free_vars,
+        functions: map_functions(program, |func| func.free_vars.clone()),
+    }
+}
+
+/// Applies `f` to every function of `program`, in the order in which the functions start, and
+/// produces the results by the position of each function.
+pub(super) fn map_functions<T>(program: &mut Stmt, f: impl FnMut(&Func) -> T) -> Vec<(Pos, T)> {
+    let mut v = MapFunctions {
+        f,
+        results: Vec::new(),
+    };
+    program.walk(&mut v);
+    v.results
+}
+
+struct MapFunctions<F, T> {
+    f: F,
+    results: Vec<(Pos, T)>,
+}
+
+impl<F: FnMut(&Func) -> T, T> Visitor for MapFunctions<F, T> {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(func, p) = expr {
+            self.results.push((p.clone(), (self.f)(func)));
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Give variables that the program does not declare the semantics of JavaScript globals.
+//!
+//! In JavaScript, global variables are properties of the global object, `globalThis`. Assigning
+//! to a variable that the program does not declare creates a property of the global object, and
+//! reading a variable that the program does not declare reads that property, which is a
+//! `ReferenceError` if the property does not exist. This pass makes these accesses explicit:
+//!
+//! - `x = e` becomes `globalThis.x = e`,
+//! - `x` becomes a call to [RTSFunction::GlobalGet], which checks that `globalThis.x` exists, and
+//! - `typeof x` becomes `typeof globalThis.x`, since `typeof` does not throw.
+//!
+//! The variables of the standard library (e.g., `console`) are NotWasm globals, so the program
+//! does not have to declare them.
+//!
+//! Top-level `var` declarations are properties of the global object too. However, they are much
+//! faster as variables of `main`, and the difference is only observable through the global object
+//! itself. Therefore, top-level variables only become properties of the global object when the
+//! program refers to `globalThis` or `global`.
+//!
+//! preconditions: desugaring, so that all functions are expressions and all variables are
+//! declared at the top of their function
+use super::alpha_rename::declared_vars;
+use super::constructors::*;
+use super::syntax::*;
+use super::walk::*;
+use crate::javascript::UnaryOp as JsUnaryOp;
+use crate::pos::Pos;
+use crate::rts_function::RTSFunction;
+use crate::shared::std_lib::get_global_object;
+use crate::shared::NameGen;
+use im_rc::HashSet as ImHashSet;
+use std::collections::HashSet;
+
+/// The local variables in scope.
+type Env = ImHashSet<Id>;
+
+/// The names that refer to the global object in the standard library.
+const GLOBAL_OBJECT_NAMES: [&str; 2] = ["globalThis", "global"];
+
+pub fn global_object(program: &mut Stmt) {
+    let mut top_level = Vec::new();
+    declared_vars(program, &mut top_level);
+    let mut refers = RefersToGlobalObject(false);
+    program.walk(&mut refers);
+    let (properties, locals): (Vec<_>, Vec<_>) = top_level
+        .into_iter()
+        .partition(|x| refers.0 && matches!(x, Id::Named(_)));
+    let mut v = GlobalObject {
+        ng: NameGen::default(),
+        std_lib: get_global_object()
+            .into_iter()
+            .map(|(x, _)| Id::Named(x))
+            .collect(),
+        properties: properties.into_iter().collect(),
+    };
+    let env: Env = locals.into_iter().collect();
+    v.stmt(program, &env);
+}
+
+/// Determines if the program refers to the global object.
+struct RefersToGlobalObject(bool);
+
+impl Visitor for RefersToGlobalObject {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Id(Id::Named(x), _, _) = expr {
+            if GLOBAL_OBJECT_NAMES.contains(&x.as_str()) {
+                self.0 = true;
+            }
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Binding {
+    /// A local variable, a top-level variable of `main`, or a variable of the standard library.
+    Local,
+    /// A top-level variable that is a property of the global object.
+    Property,
+    /// A variable that the program does not declare.
+    Undeclared,
+}
+
+struct GlobalObject {
+    ng: NameGen,
+    std_lib: HashSet<Id>,
+    /// The top-level variables that are properties of the global object.
+    properties: HashSet<Id>,
+}
+
+fn global_this(p: &Pos) -> Expr {
+    Expr::Id(Id::from(GLOBAL_OBJECT_NAMES[0]), Type::Missing, p.clone())
+}
+
+impl GlobalObject {
+    fn binding(&self, x: &Id, env: &Env) -> Binding {
+        if env.contains(x) {
+            Binding::Local
+        } else if self.properties.contains(x) {
+            Binding::Property
+        } else if self.std_lib.contains(x) {
+            Binding::Local
+        } else {
+            Binding::Undeclared
+        }
+    }
+
+    fn function(&mut self, args: &[(Id, Type)], body: &mut Stmt, env: &Env) {
+        let mut env = env.clone();
+        env.extend(args.iter().map(|(x, _)| x.clone()));
+        let mut vars = Vec::new();
+        declared_vars(body, &mut vars);
+        env.extend(vars);
+        self.stmt(body, &env);
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt, env: &Env) {
+        match stmt {
+            Stmt::Var(x, _, e, p) => {
+                self.expr(e, env);
+                if self.binding(x, env) == Binding::Property {
+                    let lv = LValue::Dot(global_this(p), x.clone());
+                    *stmt = expr_(assign_(lv, e.take(), p.clone()), p.clone());
+                }
+            }
+            Stmt::Block(stmts, _) => {
+                for s in stmts {
+                    self.stmt(s, env);
+                }
+            }
+            Stmt::Empty | Stmt::Break(..) | Stmt::Pragma(..) => (),
+            Stmt::Expr(e, _) | Stmt::Throw(e, _) | Stmt::Return(e, _) => self.expr(e, env),
+            Stmt::If(e, s1, s2, _) => {
+                self.expr(e, env);
+                self.stmt(s1, env);
+                self.stmt(s2, env);
+            }
+            Stmt::Loop(s, _) | Stmt::Label(_, s, _) => self.stmt(s, env),
+            Stmt::ForIn(x, e, body, p) => {
+                self.expr(e, env);
+                self.stmt(body, env);
+                if self.binding(x, env) != Binding::Local {
+                    // The loop variable must be a local variable, so we bind a fresh variable and
+                    // assign it to the global at the start of each iteration.
+                    let p = p.clone();
+                    let y = self.ng.fresh("global");
+                    let lv = LValue::Dot(global_this(&p), std::mem::replace(x, y.clone()));
+                    let assign = expr_(
+                        assign_(lv, Expr::Id(y.clone(), Type::Missing, p.clone()), p.clone()),
+                        p.clone(),
+                    );
+                    **body = block_(vec![assign, body.take()], p.clone());
+                    let decl = var_(
+                        y,
+                        Type::Missing,
+                        Expr::Lit(Lit::Undefined, p.clone()),
+                        p.clone(),
+                    );
+                    *stmt = block_(vec![decl, stmt.take()], p);
+                }
+            }
+            Stmt::Catch(body, x, handler, _) => {
+                self.stmt(body, env);
+                self.stmt(handler, &env.update(x.clone()));
+            }
+            Stmt::Finally(body, finally, _) => {
+                self.stmt(body, env);
+                self.stmt(finally, env);
+            }
+        }
+    }
+
+    fn exprs(&mut self, es: &mut [Expr], env: &Env) {
+        for e in es {
+            self.expr(e, env);
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr, env: &Env) {
+        match expr {
+            Expr::Id(x, _, p) => match self.binding(x, env) {
+                Binding::Local => (),
+                Binding::Property => {
+                    let e = dot_(global_this(p), x.clone(), p.clone());
+                    *expr = e;
+                }
+                Binding::Undeclared => {
+                    let name = Expr::Lit(Lit::String(x.to_string()), p.clone());
+                    let e = Expr::PrimCall(
+                        RTSFunction::GlobalGet,
+                        vec![global_this(p), name],
+                        p.clone(),
+                    );
+                    *expr = e;
+                }
+            },
+            Expr::Lit(..) => (),
+            Expr::JsOp(JsOp::Unary(JsUnaryOp::TypeOf), args, _, _) => match args.as_mut_slice() {
+                // `typeof x` produces `"undefined"` when `x` is undeclared.
+                [arg] => {
+                    if let Expr::Id(x, _, p) = arg {
+                        if self.binding(x, env) == Binding::Undeclared {
+                            let e = dot_(global_this(p), x.clone(), p.clone());
+                            *arg = e;
+                            return;
+                        }
+                    }
+                    self.expr(arg, env);
+                }
+                args => self.exprs(args, env),
+            },
+            Expr::Array(es, _) | Expr::JsOp(_, es, _, _) | Expr::PrimCall(_, es, _) => {
+                self.exprs(es, env)
+            }
+            Expr::Object(props, _) => {
+                for (_, e) in props {
+                    self.expr(e, env);
+                }
+            }
+            Expr::Dot(e, _, _)
+            | Expr::Unary(_, e, _)
+            | Expr::Length(e, _, _)
+            | Expr::Coercion(_, e, _)
+            | Expr::NewRef(e, _, _)
+            | Expr::Deref(e, _, _) => self.expr(e, env),
+            Expr::Bracket(e1, e2, _, _)
+            | Expr::Binary(_, e1, e2, _)
+            | Expr::Store(e1, e2, _, _) => {
+                self.expr(e1, env);
+                self.expr(e2, env);
+            }
+            Expr::Assign(lval, e, p) => {
+                self.expr(e, env);
+                match &mut **lval {
+                    LValue::Id(x, _) => {
+                        if self.binding(x, env) != Binding::Local {
+                            let lv = LValue::Dot(global_this(p), x.clone());
+                            **lval = lv;
+                        }
+                    }
+                    LValue::Dot(e, _) => self.expr(e, env),
+                    LValue::Bracket(e1, e2, _) => {
+                        self.expr(e1, env);
+                        self.expr(e2, env);
+                    }
+                }
+            }
+            Expr::Call(f, args, _) | Expr::MethodCall(f, _, args, _, _) => {
+                self.expr(f, env);
+                self.exprs(args, env);
+            }
+            Expr::Func(f, _) => self.function(&f.args_with_typs, &mut f.body, env),
+            Expr::Closure(..) | Expr::EnvGet(..) => {
+                panic!("global_object happens before closure conversion")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::from_js::from_javascript;
+    use super::super::interpreter::{check_typeinf, interpret, InterpError};
+    use super::*;
+    use crate::javascript::{desugar, parse};
+
+    fn janky(js_code: &str) -> Stmt {
+        let mut js = parse("<test>", js_code).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        from_javascript(js)
+    }
+
+    /// Checks that the program prints `expected`, both before and after inserting coercions,
+    /// which makes accesses to the global object explicit.
+    fn check(js_code: &str, expected: &str) {
+        let mut program = janky(js_code);
+        let output = check_typeinf(&mut program).expect("error checking the program");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn assignment_creates_global() {
+        check(
+            "function f() { counter = 10; }
+            f();
+            log_any(counter + 1);",
+            "11",
+        );
+    }
+
+    #[test]
+    fn undeclared_read_throws_reference_error() {
+        check(
+            "try { log_any(missing); } catch (e) { log_any(e.name); }",
+            "ReferenceError",
+        );
+    }
+
+    #[test]
+    fn uncaught_reference_error() {
+        match interpret(&janky("log_any(missing);")) {
+            Err(InterpError::Uncaught(msg)) => {
+                assert_eq!(msg, "ReferenceError: missing is not defined")
+            }
+            result => panic!("expected a ReferenceError, received {:?}", result),
+        }
+    }
+
+    #[test]
+    fn typeof_undeclared_is_undefined() {
+        check("log_any(typeof missing);", "undefined");
+    }
+
+    #[test]
+    fn global_this_backs_top_level_vars() {
+        check(
+            "var x = 1;
+            function f() { return globalThis.x; }
+            globalThis.y = 2;
+            x = x + 10;
+            log_any(f() + y);",
+            "13",
+        );
+    }
+
+    #[test]
+    fn top_level_vars_are_local_without_global_object() {
+        let mut program = janky("var x = 1; function f() { return x; } log_any(f());");
+        global_object(&mut program);
+        assert!(!program.to_string().contains("globalThis"));
+    }
+}
@@ -14,7 +14,7 @@ use crate::javascript::syntax::Lit::Undefined;
 fn returns(stmt: &Stmt) -> bool {
     use Stmt::*;
     match stmt {
-        Var(..) | Empty | Break(..) | Expr(..) => false,
+        Var(..) | Empty | Break(..) | Expr(..) | Pragma(..) => false,
         Block(stmts, _) => stmts.iter().any(returns),
         If(_, true_part, false_part, _) => returns(true_part) && returns(false_part),
         // This is conservative. We could analyze the conditions and breaks etc...
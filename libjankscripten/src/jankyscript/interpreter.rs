@@ -0,0 +1,1613 @@
+//! A reference interpreter for JankyScript, which serves as an oracle for type inference.
+//!
+//! The interpreter runs JankyScript both before and after type inference. Before type inference,
+//! JavaScript operators (`JsOp`) are dynamically typed and the program has no coercions. After
+//! type inference and coercion insertion, operators are either typed NotWasm operators or calls
+//! to the runtime system, and the interpreter performs every coercion explicitly: a coercion to
+//! a ground type checks the tag of its argument, and a function coercion wraps its argument in a
+//! function that coerces arguments and results. `check_typeinf` runs a program in both ways and
+//! checks that type inference and coercion insertion did not change what the program prints.
+//!
+//! We interpret JankyScript before closure conversion, so closures capture their lexical scope.
+//! The global scope has the parts of the standard library that the test suite uses, e.g.,
+//! `log_any`, `console.log`, `Math`, `Date.now`, and `Object.create`. As in the runtime system, the
+//! prototype of an object is its `__proto__` field.
+//!
+//! The interpreter prints values like the `Display` trait of the runtime system, except that it
+//! prints integers and integral floats in the same way, since type inference may change the type
+//! of a number without changing its value.
+use super::syntax::*;
+use crate::javascript::{BinaryOp as JsBinaryOp, UnaryOp as JsUnaryOp};
+use crate::pos::Pos;
+use crate::rts_function::RTSFunction;
+use crate::shared::nondet;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum InterpError {
+    #[error("unbound identifier `{0}` at {1}")]
+    Unbound(Id, Pos),
+    #[error("unbound label `{0}`")]
+    UnboundLabel(Id),
+    #[error("the interpreter does not support {0} at {1}")]
+    Unsupported(String, Pos),
+    #[error("expected {0}, but received {1} at {2}")]
+    Tag(String, String, Pos),
+    #[error("uncaught exception: {0}")]
+    Uncaught(String),
+    #[error("type checking failed after coercion insertion: {0}")]
+    TypeChecking(String),
+    #[error(
+        "type inference changed the output of the program\nBefore:\n{before}\nAfter:\n{after}"
+    )]
+    Mismatch { before: String, after: String },
+}
+
+pub type InterpResult<T> = Result<T, InterpError>;
+
+/// A function in the standard library.
+#[derive(Clone, Copy, PartialEq)]
+enum Builtin {
+    LogAny,
+    MathSqrt,
+    MathSin,
+    MathAbs,
+    MathMin,
+    MathMax,
+    MathRandom,
+    DateNow,
+    ObjectCreate,
+    ObjectSeal,
+    ObjectFreeze,
+    ObjectIsSealed,
+    ObjectIsFrozen,
+    ObjectToString,
+    ParseInt,
+    Boolean,
+    StringFromCharCode,
+    Error,
+    SetTimeout,
+    /// Functions that only exist to inspect the runtime system, e.g., `__JNKS.run_gc`.
+    Nop,
+}
+
+/// The fields of an object, in the order in which they were added.
+struct Object<'a> {
+    fields: RefCell<Vec<(String, Value<'a>)>>,
+    /// Whether `Object.seal` or `Object.freeze` prevent adding fields to the object.
+    sealed: Cell<bool>,
+    /// Whether `Object.freeze` prevents changing the fields of the object.
+    frozen: Cell<bool>,
+}
+
+/// A function value. Every function is also an object, which has a `prototype` field.
+struct Closure<'a> {
+    func: &'a Func,
+    scope: Rc<Scope<'a>>,
+    object: Rc<Object<'a>>,
+}
+
+/// A function wrapped in a function coercion.
+struct Coerced<'a> {
+    func: Value<'a>,
+    args: Vec<Coercion>,
+    result: Coercion,
+}
+
+#[derive(Clone)]
+enum Value<'a> {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    String(Rc<str>),
+    Undefined,
+    Null,
+    Array(Rc<RefCell<Vec<Value<'a>>>>),
+    Object(Rc<Object<'a>>),
+    Closure(Rc<Closure<'a>>),
+    Builtin(Builtin),
+    Coerced(Rc<Coerced<'a>>),
+    Ref(Rc<RefCell<Value<'a>>>),
+}
+
+struct Scope<'a> {
+    vars: RefCell<HashMap<Id, Value<'a>>>,
+    parent: Option<Rc<Scope<'a>>>,
+    /// The global object, in the global scope. Top-level variables and variables that the program
+    /// assigns without declaring them are its fields.
+    global_object: Option<Rc<Object<'a>>>,
+    /// The scope of a `catch` handler only binds the exception. Variables that the handler
+    /// declares belong to the enclosing function.
+    catch: bool,
+}
+
+/// How a statement completes, other than by throwing an exception.
+enum Flow<'a> {
+    Normal,
+    Break(&'a Id),
+    Return(Value<'a>),
+}
+
+/// Why evaluation stopped abruptly: either the program threw an exception, or it did something
+/// that the interpreter does not allow.
+enum Abrupt<'a> {
+    Throw(Value<'a>),
+    Error(InterpError),
+}
+
+impl<'a> From<InterpError> for Abrupt<'a> {
+    fn from(err: InterpError) -> Self {
+        Abrupt::Error(err)
+    }
+}
+
+type Eval<'a, T> = Result<T, Abrupt<'a>>;
+
+struct Interpreter<'a> {
+    globals: Rc<Scope<'a>>,
+    object_prototype: Rc<Object<'a>>,
+    output: Vec<String>,
+    /// The callbacks of `setTimeout` and the times at which they are due, in the order in which
+    /// the program scheduled them. As in the runtime system, time is virtual.
+    tasks: Vec<(f64, Value<'a>)>,
+    num_tasks: i32,
+    /// The time at which the current task was due.
+    now: f64,
+    /// The state of `Math.random`, which is deterministic (see [crate::shared::nondet]).
+    random_state: u64,
+}
+
+/// Runs the program and returns what it printed, with one line per message.
+pub fn interpret(program: &Stmt) -> InterpResult<String> {
+    let mut interp = Interpreter::new();
+    let globals = interp.globals.clone();
+    match interp.exec(program, &globals) {
+        Ok(Flow::Break(l)) => return Err(InterpError::UnboundLabel(l.clone())),
+        Ok(_) => (),
+        Err(Abrupt::Throw(v)) => return Err(InterpError::Uncaught(display_exception(&v))),
+        Err(Abrupt::Error(err)) => return Err(err),
+    }
+    while let Some(callback) = interp.next_task() {
+        match interp.call(callback, vec![Value::Undefined], &Pos::UNKNOWN) {
+            Ok(_) => (),
+            Err(Abrupt::Throw(v)) => return Err(InterpError::Uncaught(display_exception(&v))),
+            Err(Abrupt::Error(err)) => return Err(err),
+        }
+    }
+    Ok(interp.output.join("\n"))
+}
+
+/// Runs `program` (which must not have been compiled), inserts coercions into it, runs it again,
+/// and checks that both runs print the same output. Returns the output.
+pub fn check_typeinf(program: &mut Stmt) -> InterpResult<String> {
+    let before = interpret(program)?;
+    super::compile::insert_coercions(program, |_| ())
+        .map_err(|err| InterpError::TypeChecking(err.to_string()))?;
+    let after = interpret(program)?;
+    if before != after {
+        return Err(InterpError::Mismatch { before, after });
+    }
+    Ok(after)
+}
+
+impl<'a> Object<'a> {
+    fn new(fields: Vec<(String, Value<'a>)>) -> Rc<Self> {
+        Rc::new(Object {
+            fields: RefCell::new(fields),
+            sealed: Cell::new(false),
+            frozen: Cell::new(false),
+        })
+    }
+
+    /// Looks up a field, following the prototype chain.
+    fn get(&self, field: &str) -> Value<'a> {
+        let fields = self.fields.borrow();
+        if let Some((_, v)) = fields.iter().find(|(x, _)| x == field) {
+            return v.clone();
+        }
+        match fields.iter().find(|(x, _)| x == "__proto__") {
+            Some((_, Value::Object(proto))) => proto.get(field),
+            _ => Value::Undefined,
+        }
+    }
+
+    /// Looks up a field of the object itself, ignoring the prototype chain.
+    fn get_own(&self, field: &str) -> Option<Value<'a>> {
+        let fields = self.fields.borrow();
+        fields
+            .iter()
+            .find(|(x, _)| x == field)
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Writes a field, unless the object is sealed and does not have it, or the object is frozen,
+    /// in which case the write does nothing, as in JavaScript outside of strict mode.
+    fn set(&self, field: &str, v: Value<'a>) {
+        if self.frozen.get() {
+            return;
+        }
+        let mut fields = self.fields.borrow_mut();
+        match fields.iter_mut().find(|(x, _)| x == field) {
+            Some((_, x)) => *x = v,
+            None if self.sealed.get() => (),
+            None => fields.push((field.to_string(), v)),
+        }
+    }
+}
+
+impl<'a> Scope<'a> {
+    fn new(parent: Option<Rc<Scope<'a>>>) -> Rc<Self> {
+        Rc::new(Scope {
+            vars: RefCell::new(HashMap::new()),
+            parent,
+            global_object: None,
+            catch: false,
+        })
+    }
+
+    fn new_global(global_object: Rc<Object<'a>>) -> Rc<Self> {
+        Rc::new(Scope {
+            vars: RefCell::new(HashMap::new()),
+            parent: None,
+            global_object: Some(global_object),
+            catch: false,
+        })
+    }
+
+    fn new_catch(parent: &Rc<Scope<'a>>, x: Id, exn: Value<'a>) -> Rc<Self> {
+        let mut vars = HashMap::new();
+        vars.insert(x, exn);
+        Rc::new(Scope {
+            vars: RefCell::new(vars),
+            parent: Some(parent.clone()),
+            global_object: None,
+            catch: true,
+        })
+    }
+
+    fn declare(&self, x: Id, v: Value<'a>) {
+        match (&self.parent, &self.global_object) {
+            (Some(parent), _) if self.catch && !self.vars.borrow().contains_key(&x) => {
+                parent.declare(x, v)
+            }
+            (_, Some(global_object)) => global_object.set(&x.to_string(), v),
+            _ => {
+                self.vars.borrow_mut().insert(x, v);
+            }
+        }
+    }
+
+    fn lookup(&self, x: &Id) -> Option<Value<'a>> {
+        if let Some(v) = self.vars.borrow().get(x) {
+            return Some(v.clone());
+        }
+        match (&self.parent, &self.global_object) {
+            (Some(parent), _) => parent.lookup(x),
+            (None, Some(global_object)) => global_object.get_own(&x.to_string()),
+            (None, None) => None,
+        }
+    }
+
+    /// Assigns to a variable. Assigning to a variable that the program does not declare creates
+    /// a field of the global object.
+    fn assign(&self, x: &Id, v: Value<'a>) -> bool {
+        if let Some(y) = self.vars.borrow_mut().get_mut(x) {
+            *y = v;
+            return true;
+        }
+        match (&self.parent, &self.global_object) {
+            (Some(parent), _) => parent.assign(x, v),
+            (None, Some(global_object)) => {
+                global_object.set(&x.to_string(), v);
+                true
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    fn new() -> Self {
+        let object_prototype = Object::new(vec![(
+            "toString".to_string(),
+            Value::Builtin(Builtin::ObjectToString),
+        )]);
+        let object = |fields: Vec<(&str, Value<'a>)>| {
+            Value::Object(Object::new(
+                fields
+                    .into_iter()
+                    .map(|(x, v)| (x.to_string(), v))
+                    .collect(),
+            ))
+        };
+        let global_object = Object::new(vec![(
+            "__proto__".to_string(),
+            Value::Object(object_prototype.clone()),
+        )]);
+        let globals = Scope::new_global(global_object.clone());
+        let builtins = vec![
+            ("log_any", Value::Builtin(Builtin::LogAny)),
+            (
+                "console",
+                object(vec![
+                    ("log", Value::Builtin(Builtin::LogAny)),
+                    ("info", Value::Builtin(Builtin::LogAny)),
+                ]),
+            ),
+            (
+                "Math",
+                object(vec![
+                    ("sqrt", Value::Builtin(Builtin::MathSqrt)),
+                    ("sin", Value::Builtin(Builtin::MathSin)),
+                    ("abs", Value::Builtin(Builtin::MathAbs)),
+                    ("min", Value::Builtin(Builtin::MathMin)),
+                    ("max", Value::Builtin(Builtin::MathMax)),
+                    ("random", Value::Builtin(Builtin::MathRandom)),
+                    ("PI", Value::Float(std::f64::consts::PI)),
+                ]),
+            ),
+            (
+                "Date",
+                object(vec![("now", Value::Builtin(Builtin::DateNow))]),
+            ),
+            (
+                "String",
+                object(vec![(
+                    "fromCharCode",
+                    Value::Builtin(Builtin::StringFromCharCode),
+                )]),
+            ),
+            (
+                "Object",
+                object(vec![
+                    ("create", Value::Builtin(Builtin::ObjectCreate)),
+                    ("seal", Value::Builtin(Builtin::ObjectSeal)),
+                    ("freeze", Value::Builtin(Builtin::ObjectFreeze)),
+                    ("isSealed", Value::Builtin(Builtin::ObjectIsSealed)),
+                    ("isFrozen", Value::Builtin(Builtin::ObjectIsFrozen)),
+                    ("prototype", Value::Object(object_prototype.clone())),
+                ]),
+            ),
+            (
+                "__JNKS",
+                object(vec![
+                    ("heap_dump", Value::Builtin(Builtin::Nop)),
+                    ("run_gc", Value::Builtin(Builtin::Nop)),
+                    ("mem_info", Value::Builtin(Builtin::Nop)),
+                    ("heap_used", Value::Builtin(Builtin::Nop)),
+                    ("class_stats", Value::Builtin(Builtin::Nop)),
+                    ("gc_stats", Value::Builtin(Builtin::Nop)),
+                    ("track_class_sites", Value::Builtin(Builtin::Nop)),
+                ]),
+            ),
+            ("global", Value::Object(global_object.clone())),
+            ("globalThis", Value::Object(global_object)),
+            ("parseInt", Value::Builtin(Builtin::ParseInt)),
+            ("Boolean", Value::Builtin(Builtin::Boolean)),
+            ("Error", Value::Builtin(Builtin::Error)),
+            ("setTimeout", Value::Builtin(Builtin::SetTimeout)),
+            ("undefined", Value::Undefined),
+            ("NaN", Value::Float(f64::NAN)),
+            ("Infinity", Value::Float(f64::INFINITY)),
+        ];
+        // The standard library is not part of the global object, as in the runtime system.
+        for (x, v) in builtins {
+            globals.vars.borrow_mut().insert(Id::from(x), v);
+        }
+        Interpreter {
+            globals,
+            object_prototype,
+            output: Vec::new(),
+            tasks: Vec::new(),
+            num_tasks: 0,
+            now: 0.0,
+            random_state: nondet::SEED,
+        }
+    }
+
+    /// Removes the callback of the task that is due first from the queue of `setTimeout`.
+    fn next_task(&mut self) -> Option<Value<'a>> {
+        let mut next: Option<usize> = None;
+        for (i, (due, _)) in self.tasks.iter().enumerate() {
+            match next {
+                Some(j) if self.tasks[j].0 <= *due => (),
+                _ => next = Some(i),
+            }
+        }
+        let (due, callback) = self.tasks.remove(next?);
+        self.now = due;
+        Some(callback)
+    }
+
+    fn new_object(&self, mut fields: Vec<(String, Value<'a>)>) -> Value<'a> {
+        fields.insert(
+            0,
+            (
+                "__proto__".to_string(),
+                Value::Object(self.object_prototype.clone()),
+            ),
+        );
+        Value::Object(Object::new(fields))
+    }
+
+    /// The exception that reading an undeclared variable throws.
+    fn reference_error(&self, x: &str) -> Abrupt<'a> {
+        Abrupt::Throw(self.new_object(vec![
+            ("name".to_string(), Value::String("ReferenceError".into())),
+            (
+                "message".to_string(),
+                Value::String(format!("{} is not defined", x).into()),
+            ),
+        ]))
+    }
+
+    fn exec(&mut self, stmt: &'a Stmt, scope: &Rc<Scope<'a>>) -> Eval<'a, Flow<'a>> {
+        match stmt {
+            Stmt::Var(x, _, e, _) => {
+                let v = self.eval(e, scope)?;
+                scope.declare(x.clone(), v);
+                Ok(Flow::Normal)
+            }
+            Stmt::Block(stmts, _) => {
+                for s in stmts {
+                    match self.exec(s, scope)? {
+                        Flow::Normal => (),
+                        flow => return Ok(flow),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::Empty | Stmt::Pragma(..) => Ok(Flow::Normal),
+            Stmt::Expr(e, _) => {
+                self.eval(e, scope)?;
+                Ok(Flow::Normal)
+            }
+            Stmt::If(test, s1, s2, _) => {
+                // Before type inference, the test may have any type.
+                if truthy(&self.eval(test, scope)?) {
+                    self.exec(s1, scope)
+                } else {
+                    self.exec(s2, scope)
+                }
+            }
+            Stmt::Loop(body, _) => loop {
+                match self.exec(body, scope)? {
+                    Flow::Normal => (),
+                    flow => return Ok(flow),
+                }
+            },
+            Stmt::ForIn(_, _, _, p) => {
+                Err(InterpError::Unsupported("for..in".to_string(), p.clone()).into())
+            }
+            Stmt::Label(l, body, _) => match self.exec(body, scope)? {
+                Flow::Break(l2) if l2 == l => Ok(Flow::Normal),
+                flow => Ok(flow),
+            },
+            Stmt::Break(l, _) => Ok(Flow::Break(l)),
+            Stmt::Catch(body, x, handler, _) => match self.exec(body, scope) {
+                Err(Abrupt::Throw(exn)) => {
+                    self.exec(handler, &Scope::new_catch(scope, x.clone(), exn))
+                }
+                result => result,
+            },
+            Stmt::Finally(body, finally, _) => {
+                let result = self.exec(body, scope);
+                match self.exec(finally, scope)? {
+                    Flow::Normal => result,
+                    flow => Ok(flow),
+                }
+            }
+            Stmt::Throw(e, _) => Err(Abrupt::Throw(self.eval(e, scope)?)),
+            Stmt::Return(e, _) => Ok(Flow::Return(self.eval(e, scope)?)),
+        }
+    }
+
+    fn eval_all(&mut self, es: &'a [Expr], scope: &Rc<Scope<'a>>) -> Eval<'a, Vec<Value<'a>>> {
+        es.iter().map(|e| self.eval(e, scope)).collect()
+    }
+
+    fn eval(&mut self, expr: &'a Expr, scope: &Rc<Scope<'a>>) -> Eval<'a, Value<'a>> {
+        match expr {
+            Expr::Lit(lit, p) => Ok(match lit {
+                Lit::Num(Num::Int(n)) => Value::Int(*n),
+                Lit::Num(Num::Float(x)) => Value::Float(*x),
+                Lit::String(s) => Value::String(s.as_str().into()),
+                Lit::Bool(b) => Value::Bool(*b),
+                Lit::Null => Value::Null,
+                Lit::Undefined => Value::Undefined,
+                Lit::Regex(..) => {
+                    return Err(InterpError::Unsupported(
+                        "regular expressions".to_string(),
+                        p.clone(),
+                    )
+                    .into())
+                }
+            }),
+            Expr::Array(es, _) => {
+                let vs = self.eval_all(es, scope)?;
+                Ok(Value::Array(Rc::new(RefCell::new(vs))))
+            }
+            Expr::Object(props, _) => {
+                // Assign the fields one at a time, so that a repeated key is a single field and a
+                // `__proto__` key replaces the prototype.
+                let object = self.new_object(Vec::with_capacity(props.len()));
+                for (key, e) in props {
+                    let key = match key {
+                        Key::Int(n) => n.to_string(),
+                        Key::Str(s) => s.clone(),
+                    };
+                    let v = self.eval(e, scope)?;
+                    if let Value::Object(o) = &object {
+                        o.set(&key, v);
+                    }
+                }
+                Ok(object)
+            }
+            Expr::Id(x, _, _) => match scope.lookup(x) {
+                Some(v) => Ok(v),
+                None => Err(self.reference_error(&x.to_string())),
+            },
+            Expr::Dot(e, x, p) => {
+                let v = self.eval(e, scope)?;
+                Ok(get_field(&v, &x.to_string(), p)?)
+            }
+            Expr::Bracket(e1, e2, _, p) => {
+                let container = self.eval(e1, scope)?;
+                let index = self.eval(e2, scope)?;
+                Ok(get_index(&container, &index, p)?)
+            }
+            Expr::JsOp(JsOp::Binary(op), args, _, p) => {
+                let mut vs = self.eval_all(args, scope)?;
+                let v2 = vs.pop().expect("binary operator without arguments");
+                let v1 = vs.pop().expect("binary operator with one argument");
+                self.js_binary(op, v1, v2, p)
+            }
+            Expr::JsOp(JsOp::Unary(op), args, _, p) => {
+                // `typeof x` produces `"undefined"` when `x` is undeclared.
+                if let (JsUnaryOp::TypeOf, [Expr::Id(x, _, _)]) = (op, args.as_slice()) {
+                    if scope.lookup(x).is_none() {
+                        return Ok(Value::String("undefined".into()));
+                    }
+                }
+                let v = self.eval_all(args, scope)?.pop();
+                self.js_unary(op, v.expect("unary operator without arguments"), p)
+            }
+            Expr::Unary(op, e, p) => {
+                let v = self.eval(e, scope)?;
+                Ok(unary(op, v, p)?)
+            }
+            Expr::Binary(op, e1, e2, p) => {
+                let v1 = self.eval(e1, scope)?;
+                let v2 = self.eval(e2, scope)?;
+                Ok(binary(op, v1, v2, p)?)
+            }
+            Expr::Assign(lv, e, p) => match &**lv {
+                LValue::Id(x, _) => {
+                    let v = self.eval(e, scope)?;
+                    if !scope.assign(x, v.clone()) {
+                        return Err(InterpError::Unbound(x.clone(), p.clone()).into());
+                    }
+                    Ok(v)
+                }
+                LValue::Dot(obj, x) => {
+                    let obj = self.eval(obj, scope)?;
+                    let v = self.eval(e, scope)?;
+                    set_field(&obj, &x.to_string(), v.clone(), p)?;
+                    Ok(v)
+                }
+                LValue::Bracket(container, index, _) => {
+                    let container = self.eval(container, scope)?;
+                    let index = self.eval(index, scope)?;
+                    let v = self.eval(e, scope)?;
+                    set_index(&container, &index, v.clone(), p)?;
+                    Ok(v)
+                }
+            },
+            Expr::Call(f, args, p) => {
+                let f = self.eval(f, scope)?;
+                let args = self.eval_all(args, scope)?;
+                self.call(f, args, p)
+            }
+            Expr::MethodCall(obj, method, args, _, p) => {
+                // Method calls that remain after type inference are on `any`-typed receivers.
+                // The arguments include the receiver.
+                let obj = self.eval(obj, scope)?;
+                let args = self.eval_all(args, scope)?;
+                match obj {
+                    Value::Object(_) | Value::Closure(_) | Value::Coerced(_) => {
+                        let f = get_field(&obj, method, p)?;
+                        self.call(f, args, p)
+                    }
+                    _ => Ok(method_call(method, args, p)?),
+                }
+            }
+            Expr::PrimCall(rts_func, args, p) => {
+                let args = self.eval_all(args, scope)?;
+                self.prim_call(rts_func, args, p)
+            }
+            Expr::Length(e, _, p) => {
+                let v = self.eval(e, scope)?;
+                match v {
+                    Value::Object(_) | Value::Closure(_) => Ok(get_field(&v, "length", p)?),
+                    _ => Ok(method_call("length", vec![v], p)?),
+                }
+            }
+            Expr::Func(func, _) => Ok(self.closure(func, scope)),
+            Expr::Closure(_, _, p) | Expr::EnvGet(_, _, p) => Err(InterpError::Unsupported(
+                "closure-converted programs".to_string(),
+                p.clone(),
+            )
+            .into()),
+            Expr::Coercion(coercion, e, p) => {
+                let v = self.eval(e, scope)?;
+                Ok(coerce(coercion, v, p)?)
+            }
+            Expr::NewRef(e, _, _) => {
+                let v = self.eval(e, scope)?;
+                Ok(Value::Ref(Rc::new(RefCell::new(v))))
+            }
+            Expr::Deref(e, _, p) => match self.eval(e, scope)? {
+                Value::Ref(r) => {
+                    let v = r.borrow().clone();
+                    Ok(v)
+                }
+                v => Err(tag_error("a reference", &v, p).into()),
+            },
+            Expr::Store(r, e, _, p) => match self.eval(r, scope)? {
+                Value::Ref(r) => {
+                    let v = self.eval(e, scope)?;
+                    *r.borrow_mut() = v.clone();
+                    Ok(v)
+                }
+                v => Err(tag_error("a reference", &v, p).into()),
+            },
+        }
+    }
+
+    fn closure(&self, func: &'a Func, scope: &Rc<Scope<'a>>) -> Value<'a> {
+        let prototype = self.new_object(vec![]);
+        Value::Closure(Rc::new(Closure {
+            func,
+            scope: scope.clone(),
+            object: Object::new(vec![("prototype".to_string(), prototype)]),
+        }))
+    }
+
+    /// Calls a function. After desugaring, the first argument is `this`.
+    fn call(&mut self, f: Value<'a>, args: Vec<Value<'a>>, p: &Pos) -> Eval<'a, Value<'a>> {
+        match f {
+            Value::Closure(closure) => {
+                let func = closure.func;
+                let scope = Scope::new(Some(closure.scope.clone()));
+                let mut args = args.into_iter();
+                for x in func.arg_names() {
+                    scope.declare(x.clone(), args.next().unwrap_or(Value::Undefined));
+                }
+                match self.exec(&func.body, &scope)? {
+                    Flow::Return(v) => Ok(v),
+                    Flow::Normal => Ok(Value::Undefined),
+                    Flow::Break(l) => Err(InterpError::UnboundLabel(l.clone()).into()),
+                }
+            }
+            Value::Coerced(coerced) => {
+                let mut args = args;
+                for (arg, coercion) in args.iter_mut().zip(&coerced.args) {
+                    let v = std::mem::replace(arg, Value::Undefined);
+                    *arg = coerce(coercion, v, p)?;
+                }
+                let result = self.call(coerced.func.clone(), args, p)?;
+                Ok(coerce(&coerced.result, result, p)?)
+            }
+            Value::Builtin(builtin) => self.builtin(builtin, args, p),
+            v => Err(tag_error("a function", &v, p).into()),
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: Vec<Value<'a>>, p: &Pos) -> Eval<'a, Value<'a>> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        Ok(match builtin {
+            Builtin::LogAny => {
+                self.output.push(display(&arg(1)));
+                Value::Undefined
+            }
+            Builtin::MathSqrt => Value::Float(to_number(&arg(1)).sqrt()),
+            Builtin::MathSin => Value::Float(to_number(&arg(1)).sin()),
+            Builtin::MathAbs => Value::Float(to_number(&arg(1)).abs()),
+            Builtin::MathMin | Builtin::MathMax => {
+                let (v1, v2) = (arg(1), arg(2));
+                let (x, y) = (to_number(&v1), to_number(&v2));
+                let first = if builtin == Builtin::MathMin {
+                    x <= y
+                } else {
+                    x >= y
+                };
+                match (x.is_nan() || y.is_nan(), first) {
+                    (true, _) => Value::Float(f64::NAN),
+                    (false, true) => v1,
+                    (false, false) => v2,
+                }
+            }
+            Builtin::MathRandom => Value::Float(nondet::splitmix64(&mut self.random_state)),
+            // As in the runtime system, the virtual clock advances when a task runs.
+            Builtin::DateNow => Value::Float(nondet::CLOCK + self.now),
+            Builtin::ObjectCreate => match arg(1) {
+                Value::Null => Value::Object(Object::new(vec![])),
+                proto @ Value::Object(_) => {
+                    Value::Object(Object::new(vec![("__proto__".to_string(), proto)]))
+                }
+                v => return Err(tag_error("an object or null", &v, p).into()),
+            },
+            // As in the runtime system, arrays and functions cannot be sealed or frozen.
+            Builtin::ObjectSeal | Builtin::ObjectFreeze => {
+                let v = arg(1);
+                if let Value::Object(obj) = &v {
+                    obj.sealed.set(true);
+                    if builtin == Builtin::ObjectFreeze {
+                        obj.frozen.set(true);
+                    }
+                }
+                v
+            }
+            Builtin::ObjectIsSealed | Builtin::ObjectIsFrozen => Value::Bool(match arg(1) {
+                Value::Object(obj) if builtin == Builtin::ObjectIsSealed => obj.sealed.get(),
+                Value::Object(obj) => obj.frozen.get(),
+                Value::Array(_) | Value::Closure(_) | Value::Builtin(_) | Value::Coerced(_) => {
+                    false
+                }
+                _ => true,
+            }),
+            Builtin::ObjectToString => Value::String("[object Object]".into()),
+            Builtin::ParseInt => parse_int(&display(&arg(1)), to_int32(&arg(2))),
+            Builtin::Boolean => Value::Bool(truthy(&arg(1))),
+            Builtin::StringFromCharCode => {
+                let unit = to_int32(&arg(1)) as u16;
+                Value::String(String::from_utf16_lossy(&[unit]).into())
+            }
+            Builtin::Error => self.new_object(vec![
+                ("name".to_string(), Value::String("Error".into())),
+                ("message".to_string(), arg(1)),
+            ]),
+            Builtin::SetTimeout => {
+                let delay = to_number(&arg(2));
+                let delay = if delay > 0.0 { delay } else { 0.0 };
+                self.tasks.push((self.now + delay, arg(1)));
+                self.num_tasks += 1;
+                Value::Int(self.num_tasks - 1)
+            }
+            Builtin::Nop => Value::Undefined,
+        })
+    }
+
+    fn prim_call(
+        &mut self,
+        rts_func: &RTSFunction,
+        mut args: Vec<Value<'a>>,
+        p: &Pos,
+    ) -> Eval<'a, Value<'a>> {
+        use RTSFunction::*;
+        if let GlobalGet = rts_func {
+            let name = display(&args.pop().expect("global_get without a name"));
+            let global_object = args.pop().expect("global_get without the global object");
+            return match &global_object {
+                Value::Object(obj) => obj
+                    .get_own(&name)
+                    .ok_or_else(|| self.reference_error(&name)),
+                v => Err(tag_error("an object", v, p).into()),
+            };
+        }
+        let unary_op = match rts_func {
+            Typeof => Some(JsUnaryOp::TypeOf),
+            Void => Some(JsUnaryOp::Void),
+            Neg => Some(JsUnaryOp::Minus),
+            BitwiseNot => Some(JsUnaryOp::Tilde),
+            _ => None,
+        };
+        if let Some(op) = unary_op {
+            let v = args
+                .pop()
+                .expect("unary runtime function without arguments");
+            return self.js_unary(&op, v, p);
+        }
+        let binary_op = match rts_func {
+            Plus => JsBinaryOp::Plus,
+            Minus => JsBinaryOp::Minus,
+            Times => JsBinaryOp::Times,
+            Over => JsBinaryOp::Over,
+            Mod | ModF64 => JsBinaryOp::Mod,
+            StrictEqual | StringEqual => JsBinaryOp::StrictEqual,
+            Equal => JsBinaryOp::Equal,
+            StrictNotEqual | StringNotEqual => JsBinaryOp::StrictNotEqual,
+            NotEqual => JsBinaryOp::NotEqual,
+            InstanceOf => JsBinaryOp::InstanceOf,
+            In => JsBinaryOp::In,
+            Method(name, _) => return Ok(method_call(name, args, p)?),
+            _ => {
+                let name = format!("the runtime function {:?}", rts_func);
+                return Err(InterpError::Unsupported(name, p.clone()).into());
+            }
+        };
+        let v2 = args
+            .pop()
+            .expect("binary runtime function without arguments");
+        let v1 = args
+            .pop()
+            .expect("binary runtime function with one argument");
+        let result = self.js_binary(&binary_op, v1, v2, p)?;
+        match rts_func {
+            // The runtime system always produces a float.
+            ModF64 => Ok(Value::Float(to_number(&result))),
+            _ => Ok(result),
+        }
+    }
+
+    /// Converts objects to strings with their `toString` method, as `+` does.
+    fn to_primitive(&mut self, v: Value<'a>, p: &Pos) -> Eval<'a, Value<'a>> {
+        match &v {
+            Value::Object(_) => {
+                let to_string = get_field(&v, "toString", p)?;
+                self.call(to_string, vec![v], p)
+            }
+            _ => Ok(v),
+        }
+    }
+
+    fn js_unary(&mut self, op: &JsUnaryOp, v: Value<'a>, p: &Pos) -> Eval<'a, Value<'a>> {
+        Ok(match op {
+            JsUnaryOp::Minus => match v {
+                Value::Int(n) if n != 0 && n != i32::MIN => Value::Int(-n),
+                _ => Value::Float(-to_number(&v)),
+            },
+            JsUnaryOp::Plus => match v {
+                Value::Int(n) => Value::Int(n),
+                _ => Value::Float(to_number(&v)),
+            },
+            JsUnaryOp::Not => Value::Bool(!truthy(&v)),
+            JsUnaryOp::Tilde => Value::Int(!to_int32(&v)),
+            JsUnaryOp::TypeOf => Value::String(type_of(&v).into()),
+            JsUnaryOp::Void => Value::Undefined,
+            JsUnaryOp::Delete => {
+                return Err(InterpError::Unsupported("delete".to_string(), p.clone()).into())
+            }
+        })
+    }
+
+    fn js_binary(
+        &mut self,
+        op: &JsBinaryOp,
+        v1: Value<'a>,
+        v2: Value<'a>,
+        p: &Pos,
+    ) -> Eval<'a, Value<'a>> {
+        use JsBinaryOp::*;
+        Ok(match op {
+            Plus => {
+                let v1 = self.to_primitive(v1, p)?;
+                let v2 = self.to_primitive(v2, p)?;
+                if is_string_like(&v1) || is_string_like(&v2) {
+                    Value::String(format!("{}{}", display(&v1), display(&v2)).into())
+                } else {
+                    arith(&v1, &v2, i32::checked_add, |x, y| x + y)
+                }
+            }
+            Minus => arith(&v1, &v2, i32::checked_sub, |x, y| x - y),
+            Times => arith(&v1, &v2, i32::checked_mul, |x, y| x * y),
+            Over => Value::Float(to_number(&v1) / to_number(&v2)),
+            Mod => arith(&v1, &v2, i32::checked_rem, |x, y| x % y),
+            PowerOf => Value::Float(to_number(&v1).powf(to_number(&v2))),
+            LessThan | GreaterThan | LessThanEqual | GreaterThanEqual => {
+                let ordering = match (&v1, &v2) {
+                    (Value::String(s1), Value::String(s2)) => s1.partial_cmp(s2),
+                    _ => to_number(&v1).partial_cmp(&to_number(&v2)),
+                };
+                Value::Bool(match (op, ordering) {
+                    (_, None) => false,
+                    (LessThan, Some(o)) => o == Ordering::Less,
+                    (GreaterThan, Some(o)) => o == Ordering::Greater,
+                    (LessThanEqual, Some(o)) => o != Ordering::Greater,
+                    (_, Some(o)) => o != Ordering::Less,
+                })
+            }
+            Equal => Value::Bool(abstract_equal(&v1, &v2)),
+            NotEqual => Value::Bool(!abstract_equal(&v1, &v2)),
+            StrictEqual => Value::Bool(strict_equal(&v1, &v2)),
+            StrictNotEqual => Value::Bool(!strict_equal(&v1, &v2)),
+            LeftShift => Value::Int(to_int32(&v1).wrapping_shl(to_int32(&v2) as u32)),
+            RightShift => Value::Int(to_int32(&v1).wrapping_shr(to_int32(&v2) as u32)),
+            UnsignedRightShift => {
+                let n = (to_int32(&v1) as u32).wrapping_shr(to_int32(&v2) as u32);
+                number(n as f64)
+            }
+            And => Value::Int(to_int32(&v1) & to_int32(&v2)),
+            Or => Value::Int(to_int32(&v1) | to_int32(&v2)),
+            XOr => Value::Int(to_int32(&v1) ^ to_int32(&v2)),
+            InstanceOf => {
+                let prototype = get_field(&v2, "prototype", p)?;
+                Value::Bool(inherits_from(&v1, &prototype))
+            }
+            In => return Err(InterpError::Unsupported("in".to_string(), p.clone()).into()),
+        })
+    }
+}
+
+/// Describes an uncaught exception. Errors print their name and message, as in Node.
+fn display_exception(v: &Value) -> String {
+    if let Value::Object(obj) = v {
+        if let (Some(name), Some(message)) = (obj.get_own("name"), obj.get_own("message")) {
+            return format!("{}: {}", display(&name), display(&message));
+        }
+    }
+    display(v)
+}
+
+fn tag_error(expected: &str, v: &Value, p: &Pos) -> InterpError {
+    InterpError::Tag(expected.to_string(), describe(v), p.clone())
+}
+
+/// A short description of a value for error messages.
+fn describe(v: &Value) -> String {
+    match v {
+        Value::String(s) => format!("the string {:?}", s),
+        Value::Array(_) => "an array".to_string(),
+        Value::Object(_) => "an object".to_string(),
+        Value::Closure(_) | Value::Builtin(_) | Value::Coerced(_) => "a function".to_string(),
+        Value::Ref(_) => "a reference".to_string(),
+        _ => display(v),
+    }
+}
+
+/// Prints a value like the `Display` trait of the runtime system.
+fn display(v: &Value) -> String {
+    match v {
+        Value::Int(n) => n.to_string(),
+        Value::Float(x) => number_to_string(*x),
+        Value::Bool(b) => b.to_string(),
+        Value::String(s) => s.to_string(),
+        Value::Undefined => "undefined".to_string(),
+        Value::Null => "null".to_string(),
+        Value::Array(elts) => {
+            let mut s = "[".to_string();
+            for elt in elts.borrow().iter() {
+                s.push_str(&display(elt));
+                s.push_str(", ");
+            }
+            s.push(']');
+            s
+        }
+        Value::Object(_) => "[object Object]".to_string(),
+        Value::Closure(_) | Value::Builtin(_) | Value::Coerced(_) => "function".to_string(),
+        Value::Ref(r) => display(&r.borrow()),
+    }
+}
+
+fn number_to_string(x: f64) -> String {
+    if x.is_nan() {
+        "NaN".to_string()
+    } else if x.is_infinite() {
+        let s = if x > 0.0 { "Infinity" } else { "-Infinity" };
+        s.to_string()
+    } else if x == 0.0 {
+        // Avoids printing `-0`.
+        "0".to_string()
+    } else {
+        x.to_string()
+    }
+}
+
+/// The result of arithmetic that produces a float, which is an integer if it can be one.
+fn number<'a>(x: f64) -> Value<'a> {
+    if x.fract() == 0.0 && x >= i32::MIN as f64 && x <= i32::MAX as f64 {
+        Value::Int(x as i32)
+    } else {
+        Value::Float(x)
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Bool(b) => *b,
+        Value::Int(n) => *n != 0,
+        Value::Float(x) => *x != 0.0 && !x.is_nan(),
+        Value::String(s) => !s.is_empty(),
+        Value::Undefined | Value::Null => false,
+        _ => true,
+    }
+}
+
+fn to_number(v: &Value) -> f64 {
+    match v {
+        Value::Int(n) => *n as f64,
+        Value::Float(x) => *x,
+        Value::Bool(b) => *b as i32 as f64,
+        Value::Null => 0.0,
+        Value::String(s) => string_to_number(s),
+        _ => f64::NAN,
+    }
+}
+
+/// JavaScript's `StringToNumber`. Rust's `parse` accepts strings that JavaScript does not (e.g.,
+/// `inf`), and vice versa (e.g., `0x10`), so only plain decimal literals are given to it.
+pub(crate) fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(|c: char| c.is_whitespace() || c == '\u{feff}');
+    if s.is_empty() {
+        return 0.0;
+    }
+    let radix = match s.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = &s[2..];
+        if digits.is_empty() {
+            return f64::NAN;
+        }
+        return digits.chars().fold(0.0, |acc, c| match c.to_digit(radix) {
+            Some(d) => acc * radix as f64 + d as f64,
+            None => f64::NAN,
+        });
+    }
+    match s {
+        "Infinity" | "+Infinity" => f64::INFINITY,
+        "-Infinity" => f64::NEG_INFINITY,
+        _ if s.chars().all(|c| c.is_ascii_digit() || ".eE+-".contains(c)) => {
+            s.parse().unwrap_or(f64::NAN)
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// JavaScript's `ToInteger`, which is zero for NaN.
+fn to_integer(v: &Value) -> f64 {
+    let n = to_number(v);
+    if n.is_nan() {
+        0.0
+    } else {
+        n.trunc()
+    }
+}
+
+/// JavaScript's `ToInt32`.
+fn to_int32(v: &Value) -> i32 {
+    match v {
+        Value::Int(n) => *n,
+        _ => {
+            let x = to_number(v);
+            if x.is_finite() {
+                x.trunc().rem_euclid(4294967296.0) as u32 as i32
+            } else {
+                0
+            }
+        }
+    }
+}
+
+fn is_string_like(v: &Value) -> bool {
+    matches!(
+        v,
+        Value::String(_) | Value::Array(_) | Value::Object(_) | Value::Closure(_)
+    )
+}
+
+/// Arithmetic that stays on integers when both operands are integers and the result does not
+/// overflow, like the runtime system.
+fn arith<'a>(
+    v1: &Value<'a>,
+    v2: &Value<'a>,
+    on_int: impl Fn(i32, i32) -> Option<i32>,
+    on_float: impl Fn(f64, f64) -> f64,
+) -> Value<'a> {
+    if let (Value::Int(m), Value::Int(n)) = (v1, v2) {
+        if let Some(r) = on_int(*m, *n) {
+            return Value::Int(r);
+        }
+    }
+    Value::Float(on_float(to_number(v1), to_number(v2)))
+}
+
+fn strict_equal(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::Int(_), Value::Int(_))
+        | (Value::Int(_), Value::Float(_))
+        | (Value::Float(_), Value::Int(_))
+        | (Value::Float(_), Value::Float(_)) => to_number(v1) == to_number(v2),
+        (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
+        (Value::String(s1), Value::String(s2)) => s1 == s2,
+        (Value::Undefined, Value::Undefined) | (Value::Null, Value::Null) => true,
+        (Value::Array(a1), Value::Array(a2)) => Rc::ptr_eq(a1, a2),
+        (Value::Object(o1), Value::Object(o2)) => Rc::ptr_eq(o1, o2),
+        (Value::Closure(c1), Value::Closure(c2)) => Rc::ptr_eq(c1, c2),
+        (Value::Coerced(c1), Value::Coerced(c2)) => Rc::ptr_eq(c1, c2),
+        (Value::Ref(r1), Value::Ref(r2)) => Rc::ptr_eq(r1, r2),
+        (Value::Builtin(b1), Value::Builtin(b2)) => b1 == b2,
+        _ => false,
+    }
+}
+
+fn abstract_equal(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::Undefined, Value::Null) | (Value::Null, Value::Undefined) => true,
+        (Value::Undefined, _) | (Value::Null, _) | (_, Value::Undefined) | (_, Value::Null) => {
+            strict_equal(v1, v2)
+        }
+        (Value::Int(_), Value::String(_))
+        | (Value::Float(_), Value::String(_))
+        | (Value::String(_), Value::Int(_))
+        | (Value::String(_), Value::Float(_))
+        | (Value::Bool(_), _)
+        | (_, Value::Bool(_)) => to_number(v1) == to_number(v2),
+        _ => strict_equal(v1, v2),
+    }
+}
+
+fn type_of(v: &Value) -> &'static str {
+    match v {
+        Value::Int(_) | Value::Float(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::String(_) => "string",
+        Value::Undefined => "undefined",
+        Value::Closure(_) | Value::Builtin(_) | Value::Coerced(_) => "function",
+        _ => "object",
+    }
+}
+
+/// Whether `prototype` is on the prototype chain of `v`.
+fn inherits_from(v: &Value, prototype: &Value) -> bool {
+    let mut v = v.clone();
+    loop {
+        let proto = match &v {
+            Value::Object(obj) => obj
+                .fields
+                .borrow()
+                .iter()
+                .find(|(x, _)| x == "__proto__")
+                .map(|(_, proto)| proto.clone()),
+            _ => None,
+        };
+        match proto {
+            Some(proto) if strict_equal(&proto, prototype) => return true,
+            Some(proto) => v = proto,
+            None => return false,
+        }
+    }
+}
+
+fn parse_int<'a>(s: &str, radix: i32) -> Value<'a> {
+    let radix = if radix == 0 { 10 } else { radix as u32 };
+    let s = s.trim_start();
+    let (sign, digits) = match s.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let mut n: Option<f64> = None;
+    for c in digits.chars() {
+        match c.to_digit(radix) {
+            Some(d) => n = Some(n.unwrap_or(0.0) * radix as f64 + d as f64),
+            None => break,
+        }
+    }
+    match n {
+        Some(n) => number(sign * n),
+        None => Value::Float(f64::NAN),
+    }
+}
+
+fn get_field<'a>(v: &Value<'a>, field: &str, p: &Pos) -> InterpResult<Value<'a>> {
+    match v {
+        Value::Object(obj) => Ok(obj.get(field)),
+        Value::Closure(closure) => Ok(closure.object.get(field)),
+        Value::Coerced(coerced) => get_field(&coerced.func, field, p),
+        v => Err(tag_error("an object", v, p)),
+    }
+}
+
+fn set_field<'a>(v: &Value<'a>, field: &str, x: Value<'a>, p: &Pos) -> InterpResult<()> {
+    match v {
+        Value::Object(obj) => obj.set(field, x),
+        Value::Closure(closure) => closure.object.set(field, x),
+        Value::Coerced(coerced) => return set_field(&coerced.func, field, x, p),
+        v => return Err(tag_error("an object", v, p)),
+    }
+    Ok(())
+}
+
+/// The value of `index` as an array index, if it is one.
+fn array_index(index: &Value) -> Option<usize> {
+    match index {
+        Value::Int(n) if *n >= 0 => Some(*n as usize),
+        Value::Float(x) if *x >= 0.0 && x.fract() == 0.0 => Some(*x as usize),
+        _ => None,
+    }
+}
+
+fn get_index<'a>(container: &Value<'a>, index: &Value<'a>, p: &Pos) -> InterpResult<Value<'a>> {
+    match (container, array_index(index)) {
+        (Value::Array(elts), Some(i)) => {
+            Ok(elts.borrow().get(i).cloned().unwrap_or(Value::Undefined))
+        }
+        (Value::Array(_), None) | (Value::String(_), None) => {
+            get_field_of_primitive(container, &display(index), p)
+        }
+        (Value::String(s), Some(i)) => Ok(s
+            .chars()
+            .nth(i)
+            .map(|c| Value::String(c.to_string().into()))
+            .unwrap_or(Value::Undefined)),
+        _ => get_field(container, &display(index), p),
+    }
+}
+
+fn get_field_of_primitive<'a>(v: &Value<'a>, field: &str, p: &Pos) -> InterpResult<Value<'a>> {
+    match field {
+        "length" => method_call("length", vec![v.clone()], p),
+        _ => Ok(Value::Undefined),
+    }
+}
+
+fn set_index<'a>(
+    container: &Value<'a>,
+    index: &Value<'a>,
+    v: Value<'a>,
+    p: &Pos,
+) -> InterpResult<()> {
+    match (container, array_index(index)) {
+        (Value::Array(elts), Some(i)) => {
+            let mut elts = elts.borrow_mut();
+            if i >= elts.len() {
+                elts.resize(i + 1, Value::Undefined);
+            }
+            elts[i] = v;
+            Ok(())
+        }
+        _ => set_field(container, &display(index), v, p),
+    }
+}
+
+/// The index that `slice` uses for `v`, where negative indices count from the end.
+fn slice_index(v: &Value, len: usize, default: usize) -> usize {
+    if let Value::Undefined = v {
+        return default;
+    }
+    let n = to_number(v);
+    if n.is_nan() {
+        0
+    } else if n < 0.0 {
+        (len as f64 + n).max(0.0) as usize
+    } else {
+        n.min(len as f64) as usize
+    }
+}
+
+/// Calls a method on an array or a string. The first argument is the receiver.
+fn method_call<'a>(method: &str, args: Vec<Value<'a>>, p: &Pos) -> InterpResult<Value<'a>> {
+    let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+    match (method, args.first()) {
+        ("length", Some(Value::String(s))) => Ok(Value::Int(s.encode_utf16().count() as i32)),
+        ("length", Some(Value::Array(elts))) => Ok(Value::Int(elts.borrow().len() as i32)),
+        ("push", Some(Value::Array(elts))) => {
+            let mut elts = elts.borrow_mut();
+            elts.extend(args[1..].iter().cloned());
+            Ok(Value::Int(elts.len() as i32))
+        }
+        ("slice", Some(Value::String(s))) => {
+            let units = s.encode_utf16().collect::<Vec<_>>();
+            let start = slice_index(&arg(1), units.len(), 0);
+            let end = slice_index(&arg(2), units.len(), units.len()).max(start);
+            Ok(Value::String(
+                String::from_utf16_lossy(&units[start..end]).into(),
+            ))
+        }
+        ("slice", Some(Value::Array(elts))) => {
+            let elts = elts.borrow();
+            let start = slice_index(&arg(1), elts.len(), 0);
+            let end = slice_index(&arg(2), elts.len(), elts.len()).max(start);
+            Ok(Value::Array(Rc::new(RefCell::new(
+                elts[start..end].to_vec(),
+            ))))
+        }
+        ("charAt", Some(Value::String(s))) => {
+            let units = s.encode_utf16().collect::<Vec<_>>();
+            let i = to_integer(&arg(1));
+            Ok(Value::String(match units.get(i as usize) {
+                Some(unit) if i >= 0.0 => String::from_utf16_lossy(&[*unit]).into(),
+                _ => "".into(),
+            }))
+        }
+        ("charCodeAt", Some(Value::String(s))) => {
+            let i = to_integer(&arg(1));
+            match s.encode_utf16().nth(i as usize) {
+                Some(unit) if i >= 0.0 => Ok(Value::Int(unit as i32)),
+                _ => Ok(Value::Float(f64::NAN)),
+            }
+        }
+        ("codePointAt", Some(Value::String(s))) => {
+            let i = to_integer(&arg(1));
+            if i < 0.0 {
+                return Ok(Value::Undefined);
+            }
+            let units = s
+                .encode_utf16()
+                .skip(i as usize)
+                .take(2)
+                .collect::<Vec<_>>();
+            Ok(match std::char::decode_utf16(units).next() {
+                Some(Ok(c)) => Value::Int(c as i32),
+                Some(Err(e)) => Value::Int(e.unpaired_surrogate() as i32),
+                None => Value::Undefined,
+            })
+        }
+        ("concat", Some(Value::String(_))) => Ok(Value::String(
+            args.iter().map(display).collect::<String>().into(),
+        )),
+        ("concat", Some(Value::Array(_))) => {
+            let mut result = Vec::new();
+            for arg in &args {
+                match arg {
+                    Value::Array(elts) => result.extend(elts.borrow().iter().cloned()),
+                    v => result.push(v.clone()),
+                }
+            }
+            Ok(Value::Array(Rc::new(RefCell::new(result))))
+        }
+        (_, Some(v)) => Err(tag_error(&format!("a receiver for `{}`", method), v, p)),
+        (_, None) => Err(InterpError::Unsupported(
+            format!("`{}` without a receiver", method),
+            p.clone(),
+        )),
+    }
+}
+
+/// Whether `v` has type `t`. Functions are also objects.
+fn has_type(v: &Value, t: &Type) -> bool {
+    match (t, v) {
+        (Type::Any, _) | (Type::Missing, _) | (Type::Metavar(_), _) => true,
+        (Type::Int, Value::Int(_)) => true,
+        (Type::Float, Value::Float(_)) => true,
+        (Type::Bool, Value::Bool(_)) => true,
+        (Type::String, Value::String(_)) => true,
+        (Type::Array, Value::Array(_)) => true,
+        (Type::DynObject, Value::Object(_)) => true,
+        (Type::DynObject, Value::Closure(_)) | (Type::DynObject, Value::Coerced(_)) => true,
+        (Type::Function(..), Value::Closure(_))
+        | (Type::Function(..), Value::Builtin(_))
+        | (Type::Function(..), Value::Coerced(_)) => true,
+        (Type::Ref(_), Value::Ref(_)) => true,
+        _ => false,
+    }
+}
+
+fn coerce<'a>(coercion: &Coercion, v: Value<'a>, p: &Pos) -> InterpResult<Value<'a>> {
+    match coercion {
+        Coercion::Id(_) => Ok(v),
+        Coercion::Tag(t) if has_type(&v, t) => Ok(v),
+        // Like `any_to_f64` in the runtime system, which is `ToNumber`.
+        Coercion::Untag(Type::Float) => Ok(Value::Float(to_number(&v))),
+        // Like `any_to_bool` in the runtime system, which is `ToBoolean`.
+        Coercion::Untag(Type::Bool) => Ok(Value::Bool(truthy(&v))),
+        Coercion::Untag(t) if has_type(&v, t) => Ok(v),
+        Coercion::Tag(t) | Coercion::Untag(t) => {
+            Err(tag_error(&format!("a value of type {}", t), &v, p))
+        }
+        Coercion::IntToFloat => match v {
+            Value::Int(n) => Ok(Value::Float(n as f64)),
+            v => Err(tag_error("an integer", &v, p)),
+        },
+        Coercion::FloatToInt => match v {
+            Value::Float(x) => Ok(Value::Int(x as i32)),
+            v => Err(tag_error("a float", &v, p)),
+        },
+        Coercion::Fun(args, result) => match v {
+            Value::Closure(_) | Value::Builtin(_) | Value::Coerced(_) => {
+                Ok(Value::Coerced(Rc::new(Coerced {
+                    func: v,
+                    args: args.clone(),
+                    result: (**result).clone(),
+                })))
+            }
+            v => Err(tag_error("a function", &v, p)),
+        },
+        Coercion::Seq(c1, c2) => {
+            let v = coerce(c1, v, p)?;
+            coerce(c2, v, p)
+        }
+        Coercion::Meta(..) => Err(InterpError::Unsupported(
+            "coercions that are not reified".to_string(),
+            p.clone(),
+        )),
+    }
+}
+
+fn unary<'a>(op: &UnaryOp, v: Value<'a>, p: &Pos) -> InterpResult<Value<'a>> {
+    match (op, v) {
+        (UnaryOp::Sqrt, Value::Float(x)) => Ok(Value::Float(x.sqrt())),
+        (UnaryOp::F64Neg, Value::Float(x)) => Ok(Value::Float(-x)),
+        (UnaryOp::I32Neg, Value::Int(n)) => Ok(Value::Int(n.wrapping_neg())),
+        (UnaryOp::I32Not, Value::Int(n)) => Ok(Value::Int(!n)),
+        (UnaryOp::Eqz, Value::Int(n)) => Ok(Value::Bool(n == 0)),
+        (UnaryOp::Eqz, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (UnaryOp::Nop, v) => Ok(v),
+        (op, v) => Err(tag_error(&format!("an operand of {:?}", op), &v, p)),
+    }
+}
+
+fn binary<'a>(op: &BinaryOp, v1: Value<'a>, v2: Value<'a>, p: &Pos) -> InterpResult<Value<'a>> {
+    use crate::notwasm::syntax::BinaryOp::*;
+    use Value::{Bool, Float, Int};
+    let trap = |msg: &str| InterpError::Tag(msg.to_string(), "zero".to_string(), p.clone());
+    Ok(match (op, &v1, &v2) {
+        (PtrEq, _, _) => Bool(strict_equal(&v1, &v2)),
+        (I32Eq, Int(m), Int(n)) => Bool(m == n),
+        (I32Eq, Bool(m), Bool(n)) => Bool(m == n),
+        (I32Ne, Int(m), Int(n)) => Bool(m != n),
+        (I32Ne, Bool(m), Bool(n)) => Bool(m != n),
+        (I32Add, Int(m), Int(n)) => Int(m.wrapping_add(*n)),
+        (I32Sub, Int(m), Int(n)) => Int(m.wrapping_sub(*n)),
+        (I32Mul, Int(m), Int(n)) => Int(m.wrapping_mul(*n)),
+        (I32Div, Int(m), Int(n)) => Int(m.checked_div(*n).ok_or_else(|| trap("a divisor"))?),
+        (I32Rem, Int(m), Int(n)) => Int(m.checked_rem(*n).ok_or_else(|| trap("a divisor"))?),
+        (I32GT, Int(m), Int(n)) => Bool(m > n),
+        (I32LT, Int(m), Int(n)) => Bool(m < n),
+        (I32Ge, Int(m), Int(n)) => Bool(m >= n),
+        (I32Le, Int(m), Int(n)) => Bool(m <= n),
+        (I32And, Int(m), Int(n)) => Int(m & n),
+        (I32And, Bool(m), Bool(n)) => Bool(*m && *n),
+        (I32Or, Int(m), Int(n)) => Int(m | n),
+        (I32Or, Bool(m), Bool(n)) => Bool(*m || *n),
+        (I32Xor, Int(m), Int(n)) => Int(m ^ n),
+        (I32Shl, Int(m), Int(n)) => Int(m.wrapping_shl(*n as u32)),
+        (I32Shr, Int(m), Int(n)) => Int(m.wrapping_shr(*n as u32)),
+        (I32ShrU, Int(m), Int(n)) => Int((*m as u32).wrapping_shr(*n as u32) as i32),
+        (F64Add, Float(x), Float(y)) => Float(x + y),
+        (F64Sub, Float(x), Float(y)) => Float(x - y),
+        (F64Mul, Float(x), Float(y)) => Float(x * y),
+        (F64Div, Float(x), Float(y)) => Float(x / y),
+        (F64Eq, Float(x), Float(y)) => Bool(x == y),
+        (F64Ne, Float(x), Float(y)) => Bool(x != y),
+        (F64LT, Float(x), Float(y)) => Bool(x < y),
+        (F64Le, Float(x), Float(y)) => Bool(x <= y),
+        (F64GT, Float(x), Float(y)) => Bool(x > y),
+        (F64Ge, Float(x), Float(y)) => Bool(x >= y),
+        _ => {
+            return Err(InterpError::Tag(
+                format!("operands of {:?}", op),
+                format!("{} and {}", describe(&v1), describe(&v2)),
+                p.clone(),
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::from_js::from_javascript;
+    use super::*;
+    use crate::javascript::{desugar, parse};
+    use crate::shared::NameGen;
+
+    fn janky(name: &str, js_code: &str) -> Stmt {
+        let mut js = parse(name, js_code).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        from_javascript(js)
+    }
+
+    fn check(js_code: &str) -> String {
+        match check_typeinf(&mut janky("<test>", js_code)) {
+            Ok(output) => output,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn arithmetic_and_strings() {
+        let output = check(
+            r#"
+            var x = 1 + 2 * 3;
+            var y = x / 2;
+            log_any(x);
+            log_any(y);
+            log_any("x = " + x);
+            log_any(7 % 3 === 1);
+            "#,
+        );
+        assert_eq!(output, "7\n3.5\nx = 7\ntrue");
+    }
+
+    #[test]
+    fn closures_capture_their_scope() {
+        let output = check(
+            "function counter() {
+                var n = 0;
+                return function() { n = n + 1; return n; };
+            }
+            var c = counter();
+            c();
+            log_any(c());",
+        );
+        assert_eq!(output, "2");
+    }
+
+    #[test]
+    fn functions_through_any() {
+        let output = check(
+            r#"
+            function id(x) { return x; }
+            log_any(id(3));
+            log_any(id("three"));
+            var f = id;
+            var o = { f: f };
+            log_any(o.f(2.5));
+            "#,
+        );
+        assert_eq!(output, "3\nthree\n2.5");
+    }
+
+    #[test]
+    fn prototypes_and_exceptions() {
+        let output = check(
+            r#"
+            function Point(x) { this.x = x; }
+            Point.prototype.getX = function() { return this.x; };
+            var p = new Point(10);
+            log_any(p.getX());
+            try {
+                throw "oops";
+            } catch (e) {
+                log_any(e);
+            }
+            "#,
+        );
+        assert_eq!(output, "10\noops");
+    }
+
+    #[test]
+    fn failed_untag_is_an_error() {
+        let mut program = janky("<test>", "log_any(1);");
+        super::super::compile::insert_coercions(&mut program, |_| ()).unwrap();
+        let untag = Expr::Coercion(
+            Coercion::Untag(Type::String),
+            Box::new(Expr::Lit(Lit::Num(Num::Int(1)), Pos::UNKNOWN)),
+            Pos::UNKNOWN,
+        );
+        let program = Stmt::Block(
+            vec![program, Stmt::Expr(Box::new(untag), Pos::UNKNOWN)],
+            Pos::UNKNOWN,
+        );
+        assert!(matches!(interpret(&program), Err(InterpError::Tag(..))));
+    }
+
+    /// Type inference must preserve the behavior of every program in the integration tests that
+    /// the interpreter supports.
+    #[test]
+    fn typeinf_preserves_behavior_of_integration_tests() {
+        let test_data = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .join("integration_tests")
+            .join("test_data");
+        let mut paths = std::fs::read_dir(&test_data)
+            .expect("reading integration tests")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "js"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            let name = path.display().to_string();
+            let js_code = std::fs::read_to_string(&path).unwrap();
+            match check_typeinf(&mut janky(&name, &js_code)) {
+                Ok(_) => (),
+                Err(InterpError::Unsupported(what, _)) => {
+                    eprintln!(
+                        "skipping {}: the interpreter does not support {}",
+                        name, what
+                    )
+                }
+                Err(err) => panic!("{}: {}", name, err),
+            }
+        }
+    }
+}
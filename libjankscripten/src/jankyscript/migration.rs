@@ -0,0 +1,235 @@
+//! A report of where a program loses types when its functions must be callable from untyped code.
+//!
+//! Type inference usually picks the most precise types for the whole program
+//! ([Objective::Precision]). However, a module that untyped code may call cannot assume anything
+//! about the arguments of its functions ([Objective::Compatibility]). This module runs type
+//! inference with both objectives and reports every variable, argument, and function result that
+//! is less precise under the second, so that programmers can see which types depend on the
+//! program being closed without reading the models that Z3 produces.
+//!
+//! The report is tab-separated, with a header line, and one line for every binder that loses its
+//! type:
+//!
+//! ```text
+//! position	kind	name	precise	compatible
+//! test.js: line 1, column 1	arg	x	int	any
+//! ```
+//!
+//! preconditions: desugaring
+
+use super::alpha_rename::alpha_rename;
+use super::global_object::global_object;
+use super::insert_returns::insert_returns;
+use super::syntax::*;
+use super::typeinf::{typeinf_with_objective, Objective};
+use super::walk::*;
+use crate::opts::TypeinfWeights;
+use crate::pos::Pos;
+use std::fmt;
+
+/// A binder whose type is less precise when functions must be callable from untyped code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LostType {
+    pub pos: Pos,
+    /// `var`, `arg`, or `result`.
+    pub kind: &'static str,
+    /// The name of the variable or argument, or of the variable that holds the function.
+    pub name: String,
+    pub precise: Type,
+    pub compatible: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationReport {
+    /// The number of binders in the program, including those that do not lose types.
+    pub binders: usize,
+    /// The cost of the types that each objective picks (see [typeinf_with_objective]).
+    pub precise_objective: u64,
+    pub compatible_objective: u64,
+    pub lost: Vec<LostType>,
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "position\tkind\tname\tprecise\tcompatible")?;
+        for lost in &self.lost {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}",
+                lost.pos, lost.kind, lost.name, lost.precise, lost.compatible
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn migration_report(program: &Stmt, weights: &TypeinfWeights) -> MigrationReport {
+    // The passes that precede type inference in compile.
+    let mut program = program.clone();
+    global_object(&mut program);
+    alpha_rename(&mut program);
+    insert_returns(&mut program);
+    let mut precise = program.clone();
+    let precise_objective = typeinf_with_objective(&mut precise, weights, Objective::Precision);
+    let mut compatible = program;
+    let compatible_objective =
+        typeinf_with_objective(&mut compatible, weights, Objective::Compatibility);
+    let precise = binders(&mut precise);
+    let compatible = binders(&mut compatible);
+    assert_eq!(precise.len(), compatible.len());
+    let n = precise.len();
+    let lost = precise
+        .into_iter()
+        .zip(compatible)
+        .filter(|(p, c)| p.typ != c.typ)
+        .map(|(p, c)| LostType {
+            pos: p.pos,
+            kind: p.kind,
+            name: p.name,
+            precise: p.typ,
+            compatible: c.typ,
+        })
+        .collect();
+    MigrationReport {
+        binders: n,
+        precise_objective,
+        compatible_objective,
+        lost,
+    }
+}
+
+struct Binder {
+    pos: Pos,
+    kind: &'static str,
+    name: String,
+    typ: Type,
+}
+
+/// The binders of the program that it names, in the order that they occur.
+fn binders(program: &mut Stmt) -> Vec<Binder> {
+    let mut v = Binders::default();
+    program.walk(&mut v);
+    v.binders
+}
+
+#[derive(Default)]
+struct Binders {
+    binders: Vec<Binder>,
+    /// The name of the variable that the next function initializes.
+    fun_name: Option<String>,
+}
+
+/// The name of `x` in the source program, or `None` if desugaring introduced it.
+fn source_name(x: &Id) -> Option<String> {
+    match x {
+        Id::Named(name) | Id::Renamed(name, _) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn is_func(e: &Expr) -> bool {
+    match e {
+        Expr::Func(..) => true,
+        Expr::Coercion(_, e, _) => is_func(e),
+        _ => false,
+    }
+}
+
+impl Visitor for Binders {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Var(x, t, e, p) = stmt {
+            if let Some(name) = source_name(x) {
+                if is_func(e) {
+                    self.fun_name = Some(name.clone());
+                }
+                self.binders.push(Binder {
+                    pos: p.clone(),
+                    kind: "var",
+                    name,
+                    typ: t.clone(),
+                });
+            }
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        // Variables are declared at the top of their functions, so most functions are assigned
+        // to their variables.
+        if let Expr::Assign(lv, e, _) = expr {
+            if let (LValue::Id(x, _), true) = (&**lv, is_func(e)) {
+                self.fun_name = source_name(x);
+            }
+        }
+        if let Expr::Func(f, p) = expr {
+            for (x, t) in &f.args_with_typs {
+                if let Some(name) = source_name(x) {
+                    self.binders.push(Binder {
+                        pos: p.clone(),
+                        kind: "arg",
+                        name,
+                        typ: t.clone(),
+                    });
+                }
+            }
+            let name = self
+                .fun_name
+                .take()
+                .unwrap_or_else(|| "(anonymous)".to_string());
+            self.binders.push(Binder {
+                pos: p.clone(),
+                kind: "result",
+                name,
+                typ: f.result_typ.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::javascript::{desugar, parse};
+    use crate::shared::NameGen;
+
+    fn report(js: &str) -> MigrationReport {
+        let mut js = parse("<text>", js).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        let janky = super::super::from_js::from_javascript(js);
+        migration_report(&janky, &TypeinfWeights::default())
+    }
+
+    #[test]
+    fn closed_program_loses_argument_types() {
+        let r = report(
+            r#"
+            function inc(x) {
+                return x + 1;
+            }
+            var y = inc(10);
+            "#,
+        );
+        let lost = r
+            .lost
+            .iter()
+            .map(|l| {
+                (
+                    l.kind,
+                    l.name.as_str(),
+                    l.precise.clone(),
+                    l.compatible.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(lost.contains(&("arg", "x", Type::Int, Type::Any)));
+        assert!(lost.contains(&("result", "inc", Type::Int, Type::Any)));
+        assert!(r.compatible_objective >= r.precise_objective);
+    }
+
+    #[test]
+    fn program_without_functions_loses_nothing() {
+        let r = report("var x = 1; var y = x + 2;");
+        assert_eq!(r.lost, vec![]);
+        assert_eq!(r.precise_objective, r.compatible_objective);
+    }
+}
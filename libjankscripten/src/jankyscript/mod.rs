@@ -1,20 +1,36 @@
+//! JankyScript, the typed intermediate language between JavaScript and NotWasm.
+//!
+//! Desugared JavaScript becomes JankyScript with [from_js::from_javascript], and [compile] runs
+//! the rest of the passes, which infer types, make coercions explicit, and closure-convert the
+//! program. [insert_coercions] runs only the passes up to coercion insertion, for tools that
+//! examine typed programs before closure conversion, such as the [interpreter].
+mod alpha_rename;
 mod box_assigns;
 mod closure_convert;
 mod coercions;
-mod collect_assigns;
+pub mod collect_assigns;
 mod compile;
+mod forward_coercions;
 pub mod constructors;
 pub mod from_js;
-mod fv;
+pub mod fv;
+mod global_object;
 mod insert_returns;
+pub mod interpreter;
+#[cfg(feature = "z3")]
+pub mod migration;
 mod operators;
+#[cfg(feature = "z3")]
 mod operators_z3;
 mod pretty;
 mod select_method_call;
 pub mod syntax;
 mod type_checking;
+#[cfg(feature = "z3")]
 mod typeinf;
+mod typeinf_dynamic;
 mod typeinf_env;
+mod unbox_refs;
 mod walk;
 
-pub use compile::compile;
+pub use compile::{compile, insert_coercions};
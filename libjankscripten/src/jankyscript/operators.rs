@@ -162,15 +162,19 @@ lazy_static! {
         table.add(Mod, typ!(fun(any, any) -> any), RTSFunction::Mod);
         table.add(Equal, typ!(fun(int, int) -> bool), I32Eq);
         table.add(Equal, typ!(fun(float, float) -> bool), F64Eq);
+        table.add(Equal, typ!(fun(string, string) -> bool), RTSFunction::StringEqual);
         table.add(Equal, typ!(fun(any, any) -> bool), RTSFunction::Equal);
         table.add(NotEqual, typ!(fun(int, int) -> bool), I32Ne);
         table.add(NotEqual, typ!(fun(float, float) -> bool), F64Ne);
+        table.add(NotEqual, typ!(fun(string, string) -> bool), RTSFunction::StringNotEqual);
         table.add(NotEqual, typ!(fun(any, any) -> bool), RTSFunction::NotEqual);
         table.add(StrictEqual, typ!(fun(int, int) -> bool), I32Eq);
         table.add(StrictEqual, typ!(fun(float, float) -> bool), F64Eq);
+        table.add(StrictEqual, typ!(fun(string, string) -> bool), RTSFunction::StringEqual);
         table.add(StrictEqual, typ!(fun(any, any) -> bool), RTSFunction::StrictEqual);
         table.add(StrictNotEqual, typ!(fun(int, int) -> bool), I32Ne);
         table.add(StrictNotEqual, typ!(fun(float, float) -> bool), F64Ne);
+        table.add(StrictNotEqual, typ!(fun(string, string) -> bool), RTSFunction::StringNotEqual);
         table.add(StrictNotEqual, typ!(fun(any, any) -> bool), RTSFunction::StrictNotEqual);
         table.add(LessThan, typ!(fun(int, int) -> bool), I32LT);
         // It's always safe to coerce to float because < only operates on
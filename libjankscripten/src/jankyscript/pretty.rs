@@ -1,6 +1,7 @@
 use super::syntax::*;
 
 use crate::pretty::Pretty;
+use crate::string_escaping::escape_string;
 use crate::{impl_Display_Pretty, prettyp};
 
 impl Pretty for Type {
@@ -102,7 +103,7 @@ impl Pretty for Lit {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         match self {
-            Lit::String(s) => pp.text(s).double_quotes(), // TODO(michael) escaping?
+            Lit::String(s) => pp.text(escape_string(s)),
             Lit::Regex(_s1, _s2) => todo!("regex literal pretty printing"),
             Lit::Bool(b) => pp.text(if *b { "true" } else { "false" }),
             Lit::Null => pp.text("null"),
@@ -128,7 +129,7 @@ impl Pretty for Key {
     {
         match self {
             Key::Int(i) => pp.as_string(i),
-            Key::Str(s) => pp.text(s).single_quotes(),
+            Key::Str(s) => pp.text(escape_string(s)),
         }
     }
 }
@@ -334,6 +335,7 @@ impl Pretty for Stmt {
                 ])
                 .braces(),
             Stmt::Empty => pp.hardline(),
+            Stmt::Pragma(pragma, _) => pp.text(pragma.to_string()),
             Stmt::Expr(e, _) => e.pretty(pp).append(";").group(),
             Stmt::If(e1, s2, s3, _) => pp.intersperse(
                 vec![
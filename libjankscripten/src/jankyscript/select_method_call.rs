@@ -3,12 +3,15 @@
 use super::constructors::*;
 use super::syntax::*;
 use super::walk::*;
+use crate::pos::Pos;
 use crate::rts_function::RTSFunction;
-use crate::shared::methods::get_type_by_prefix;
+use crate::shared::coercions::Coercion;
+use crate::shared::methods::{get_type_by_prefix, METHODS_TABLE};
 use crate::typ;
 
 /// Turn (typed) method calls into relevant typed calls
-/// For any, this leaves them be and they become AnyMethodCall in notwasm
+/// For any, this leaves them be and they become AnyMethodCall in notwasm, unless the receiver
+/// was only coerced to any for the call (see [typed_receiver])
 /// For object, this turns them into a dot and a call
 /// For all other times, this turns them into the relevant PrimCall!
 pub fn select_method_call(program: &mut Stmt) {
@@ -21,7 +24,11 @@ impl Visitor for MethodCallVisitor {
     fn exit_expr(&mut self, expr: &mut Expr, _: &Loc) {
         match expr {
             Expr::MethodCall(obj, method, args, typ, p) => match typ {
-                Type::Any => (),
+                Type::Any => {
+                    if let Some(call) = typed_receiver(method, args, p) {
+                        *expr = call;
+                    }
+                }
                 Type::DynObject => {
                     *expr = Expr::Call(
                         Box::new(Expr::Coercion(
@@ -62,3 +69,103 @@ impl Visitor for MethodCallVisitor {
         }
     }
 }
+
+/// Type inference may pick the `any` version of a method, even though it knows the type of the
+/// receiver, because the arguments are `any`s (e.g., `s.slice(i, j)` where `s` is a string, but
+/// `i` and `j` are arguments of an untyped function). The receiver is then coerced to `any`, and
+/// the method call dispatches on its type at runtime, only to coerce it back and the arguments
+/// from `any`. When the primitive method for the type of the receiver exists, we call it
+/// directly instead, with the same coercions of the arguments that dispatch would perform.
+///
+/// The receiver is the first argument, which desugar_this passes explicitly.
+fn typed_receiver(method: &str, args: &mut Vec<Expr>, p: &Pos) -> Option<Expr> {
+    let receiver_typ = match args.first() {
+        Some(Expr::Coercion(Coercion::Meta(t, Type::Any), _, _)) => t,
+        _ => return None,
+    };
+    let method_typ = METHODS_TABLE
+        .get(&(method, args.len()))?
+        .iter()
+        .find(|t| &t.unwrap_fun().0[0] == receiver_typ)?
+        .clone();
+    let (arg_typs, ret_typ) = method_typ.unwrap_fun();
+    let args = std::mem::replace(args, vec![])
+        .into_iter()
+        .zip(arg_typs)
+        .map(|(arg, arg_typ)| match arg {
+            // Skip the round trip through any.
+            Expr::Coercion(Coercion::Meta(t, Type::Any), e, _) if &t == arg_typ => *e,
+            arg => coercion_(Coercion::meta(Type::Any, arg_typ.clone()), arg, p.clone()),
+        })
+        .collect();
+    let call = Expr::PrimCall(
+        RTSFunction::Method(method.to_string(), method_typ.clone()),
+        args,
+        p.clone(),
+    );
+    Some(coercion_(
+        Coercion::meta(ret_typ.clone(), Type::Any),
+        call,
+        p.clone(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_any(t: Type, e: Expr) -> Expr {
+        coercion_(Coercion::meta(t, Type::Any), e, Pos::UNKNOWN)
+    }
+
+    fn id(x: &str, t: Type) -> Expr {
+        Expr::Id(Id::from(x), t, Pos::UNKNOWN)
+    }
+
+    fn method_call(args: Vec<Expr>) -> Expr {
+        Expr::MethodCall(
+            Box::new(id("s", Type::Any)),
+            "slice".to_string(),
+            args,
+            Type::Any,
+            Pos::UNKNOWN,
+        )
+    }
+
+    #[test]
+    fn known_receiver_calls_method_directly() {
+        let e = method_call(vec![
+            to_any(Type::String, id("s", Type::String)),
+            id("i", Type::Any),
+            to_any(Type::Int, id("j", Type::Int)),
+        ]);
+        let mut program = Stmt::Expr(Box::new(e), Pos::UNKNOWN);
+        select_method_call(&mut program);
+        let slice = typ!(fun(string, int, int) -> string);
+        let expected = to_any(
+            Type::String,
+            Expr::PrimCall(
+                RTSFunction::Method("slice".to_string(), slice),
+                vec![
+                    id("s", Type::String),
+                    coercion_(
+                        Coercion::meta(Type::Any, Type::Int),
+                        id("i", Type::Any),
+                        Pos::UNKNOWN,
+                    ),
+                    id("j", Type::Int),
+                ],
+                Pos::UNKNOWN,
+            ),
+        );
+        assert_eq!(program, Stmt::Expr(Box::new(expected), Pos::UNKNOWN));
+    }
+
+    #[test]
+    fn unknown_receiver_dispatches() {
+        let untyped = || method_call(vec![id("s", Type::Any), id("i", Type::Any)]);
+        let mut program = Stmt::Expr(Box::new(untyped()), Pos::UNKNOWN);
+        select_method_call(&mut program);
+        assert_eq!(program, Stmt::Expr(Box::new(untyped()), Pos::UNKNOWN));
+    }
+}
@@ -5,6 +5,7 @@ use super::operators::NotwasmOp;
 use crate::pos::Pos;
 use crate::rts_function::RTSFunction;
 pub use crate::shared::coercions::Coercion;
+pub use crate::shared::Pragma;
 pub use crate::shared::Type;
 use im_rc::HashMap;
 use im_rc::HashSet as ImmHashSet;
@@ -165,4 +166,6 @@ pub enum Stmt {
     Finally(Box<Stmt>, Box<Stmt>, Pos),
     Throw(Box<Expr>, Pos),
     Return(Box<Expr>, Pos),
+    /// A hint for the compiler, which annotates the statement that follows.
+    Pragma(Pragma, Pos),
 }
@@ -140,7 +140,7 @@ pub fn type_check(stmt: &Stmt) -> TypeCheckingResult<()> {
 
 fn type_check_stmt(stmt: &Stmt, env: Env, ret_ty: &Option<Type>) -> TypeCheckingResult<Env> {
     match stmt {
-        Stmt::Empty => Ok(env),
+        Stmt::Empty | Stmt::Pragma(..) => Ok(env),
         Stmt::Expr(e, _) => {
             type_check_expr(&e, env.clone())?;
             Ok(env)
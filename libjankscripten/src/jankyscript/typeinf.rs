@@ -22,6 +22,7 @@ use super::operators_z3::Z3Operators;
 use super::syntax::*;
 use super::typeinf_env::Env;
 use super::walk::{Loc, Visitor};
+use crate::opts::TypeinfWeights;
 use crate::pos::Pos;
 use crate::shared::methods::METHODS_TABLE;
 use crate::typ;
@@ -67,6 +68,21 @@ struct Typeinf<'a> {
     return_type: Type,
     trace: bool,
     z3ez: Z3EZ<'a>,
+    weights: TypeinfWeights,
+    objective: Objective,
+    /// The soft constraints and their costs, to calculate the cost of the model.
+    soft: Vec<(ast::Bool<'a>, u32)>,
+}
+
+/// What the types that type inference picks must achieve, besides type-checking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    /// Pick the types with the least cost of coercions.
+    Precision,
+    /// Pick the types with the least cost of coercions, such that every function can be called
+    /// with arguments of any type, and returns any. This is what migrating a module whose
+    /// functions untyped code may call must pick.
+    Compatibility,
 }
 
 /// Calculates the type of a literal.
@@ -231,12 +247,33 @@ impl<'a> Typeinf<'a> {
         }
     }
 
-    fn fresh_weight(&self) -> z3::ast::Bool<'a> {
+    /// A fresh constant that is false when a coercion is necessary, which costs `cost`.
+    fn fresh_weight(&mut self, cost: u32) -> z3::ast::Bool<'a> {
         let e = z3::ast::Bool::fresh_const(self.z.cxt, "w");
-        self.solver.assert_soft(&e, 1, None);
+        self.assert_soft(e.clone(), cost);
         return e;
     }
 
+    /// Prefer models in which `phi` is true: a model in which it is false costs `cost`.
+    fn assert_soft(&mut self, phi: ast::Bool<'a>, cost: u32) {
+        if cost > 0 {
+            self.solver.assert_soft(&phi, cost as usize, None);
+            self.soft.push((phi, cost));
+        }
+    }
+
+    /// The cost of a model, which is the sum of the costs of the soft constraints that are false.
+    fn objective(&self, model: &Model) -> u64 {
+        self.soft
+            .iter()
+            .filter(|(phi, _)| {
+                let value = model.eval(phi).expect("evaluating soft constraint");
+                value.as_bool() == Some(false)
+            })
+            .map(|(_, cost)| *cost as u64)
+            .sum()
+    }
+
     fn fresh_metavar(&mut self, prefix: &'static str) -> Type {
         let x = self.z.fresh(prefix);
         let n = self.vars.len();
@@ -263,7 +300,7 @@ impl<'a> Typeinf<'a> {
                 let (phi, _) = self.cgen_expr(&mut *e);
                 self.solver.assert(&phi);
             }
-            Stmt::Empty => (),
+            Stmt::Empty | Stmt::Pragma(..) => (),
             Stmt::Loop(s, _) => self.cgen_stmt(s),
             Stmt::Label(_, s, _) => self.cgen_stmt(s),
             Stmt::Block(stmts, _) => {
@@ -280,7 +317,7 @@ impl<'a> Typeinf<'a> {
             }
             Stmt::Return(e, p) => {
                 let (phi, t) = self.cgen_expr(e);
-                let w = self.fresh_weight();
+                let w = self.fresh_weight(self.weights.call);
                 self.solver.assert(&phi);
                 let t_r = self.return_type.clone();
                 let t_is_ground = self.is_ground(&t);
@@ -293,7 +330,7 @@ impl<'a> Typeinf<'a> {
                 **e = coerce(t, t_r, e.take(), p.clone());
             }
             Stmt::If(test, then_branch, else_branch, p) => {
-                let w = self.fresh_weight();
+                let w = self.fresh_weight(self.weights.elimination);
                 let (phi_1, t) = self.cgen_expr(test);
                 let phi_2 = z3f!(self,
                     (or (and (id w.clone())
@@ -363,7 +400,7 @@ impl<'a> Typeinf<'a> {
     // Consider the unfortunately named field "length" on a DynObject o
     // Then o.length is at some point written, so o is DynObject or any
     fn cgen_dot(&mut self, obj_e: &mut Expr, x: &mut Id, p: &mut Pos) -> ast::Bool<'a> {
-        let w = self.fresh_weight();
+        let w = self.fresh_weight(self.weights.elimination);
         let (phi_1, t) = self.cgen_expr(obj_e);
         let e = obj_e.take();
         *obj_e = coerce(t.clone(), Type::DynObject, e, p.clone());
@@ -395,8 +432,8 @@ impl<'a> Typeinf<'a> {
         let otf = self.fresh_metavar("o");
         *t = otf.clone();
         let ftf = self.fresh_metavar("f");
-        let wcoerce = self.fresh_weight();
-        let wrt = self.fresh_weight();
+        let wcoerce = self.fresh_weight(self.weights.elimination);
+        let wrt = self.fresh_weight(self.weights.elimination);
         // a : S -> b : T   or
         // a : S -> coerce(any, T) b
         // af = "a final"
@@ -459,7 +496,7 @@ impl<'a> Typeinf<'a> {
         // This includes a weird coercion for object that ONLY exists when
         // there are no special methods (elimination form)
         let obj_typ = self.fresh_metavar("objmethod");
-        let obj_w = self.fresh_weight();
+        let obj_w = self.fresh_weight(self.weights.elimination);
         let ann_args_typs: Vec<_> = args.iter().map(|_| self.fresh_metavar("param")).collect();
         let ann_ret_ty = self.fresh_metavar("ret");
         *typ = self.fresh_metavar("method type");
@@ -521,7 +558,10 @@ impl<'a> Typeinf<'a> {
             };
         // As an optimization, set up a single weight for each argument outside
         // of our type loop. These are used on our coercions
-        let arg_ws: Vec<_> = args.iter().map(|_| self.fresh_weight()).collect();
+        let arg_ws: Vec<_> = args
+            .iter()
+            .map(|_| self.fresh_weight(self.weights.call))
+            .collect();
         // C. Create conjuncts for every possible type the method could be
         // By convention we know that each object type is distinct, so each phi
         // is disjoint!
@@ -631,7 +671,10 @@ impl<'a> Typeinf<'a> {
         phis: B,
         t: Type,
     ) -> (ast::Bool<'a>, Type) {
-        let w = self.fresh_weight();
+        let w = match expr {
+            Expr::Lit(..) => self.fresh_weight(self.weights.literal),
+            _ => self.fresh_weight(self.weights.value),
+        };
         let alpha = self.fresh_metavar("wobbly");
         let is_ground = self.is_ground(&t);
         let phi = z3f!(self,
@@ -659,7 +702,6 @@ impl<'a> Typeinf<'a> {
     pub fn cgen_expr(&mut self, expr: &mut Expr) -> (ast::Bool<'a>, Type) {
         match expr {
             Expr::Binary(..)
-            | Expr::PrimCall(..)
             | Expr::NewRef(..)
             | Expr::Deref(..)
             | Expr::Store(..)
@@ -690,6 +732,19 @@ impl<'a> Typeinf<'a> {
                 }
                 self.wobbly(p.clone(), expr, self.zand(phis), Type::DynObject)
             }
+            Expr::PrimCall(rts_func, args, p) => {
+                // The only runtime functions that appear before type inference are the ones that
+                // global_object introduces. They have ground types, so their arguments must have
+                // exactly those types.
+                let p = p.clone();
+                let rts_typ = rts_func.janky_typ();
+                let (arg_ts, ret_t) = rts_typ.unwrap_fun();
+                let (mut phis, ts) = self.cgen_exprs(args.iter_mut());
+                for (t, arg_t) in ts.iter().zip(arg_ts) {
+                    phis.push(self.t(t)._eq(&self.t(arg_t)));
+                }
+                self.wobbly(p, expr, self.zand(phis), ret_t.clone())
+            }
             Expr::Id(x, t, p) => {
                 // NOTE(luna): A brief argument for wobbly/non-rigid vars:
                 // 1. The usual advantage of rigid vars is more consistent/sensical
@@ -713,12 +768,14 @@ impl<'a> Typeinf<'a> {
             Expr::Dot(obj_e, x, p) => (self.cgen_dot(obj_e, x, p), Type::Any),
             Expr::Bracket(o, f, t, p) => (self.cgen_bracket(o, f, t, p), Type::Any),
             Expr::JsOp(op, args, JsOpTypeinf { op_metavar }, p) => {
-                let w = self.fresh_weight();
+                let w = self.fresh_weight(self.weights.operator);
                 // Fresh metavariable for the operator that we will select, stored in the AST for
                 // the next phase.
                 *op_metavar = self.ops.fresh_op_selector();
                 // Fresh type metavariable for the result of this expression
                 let alpha_t = self.fresh_metavar("alpha");
+                let not_float = z3f!(self, (not (= (tid alpha_t.clone()) (typ float))));
+                self.assert_soft(not_float, self.weights.float_op);
                 // Recur into each argument and unzip Z3 constants and our type metavars
                 let args_rec = args.iter_mut().map(|e| self.cgen_expr(e));
                 let (mut args_phi, args_t): (Vec<_>, Vec<_>) = args_rec.unzip();
@@ -727,7 +784,7 @@ impl<'a> Typeinf<'a> {
                 for (arg, arg_t) in args.iter_mut().zip(&args_t) {
                     let a = arg.take();
                     let beta_t = self.fresh_metavar("beta");
-                    *arg = coerce(arg_t.clone(), beta_t.clone(), a, Default::default());
+                    *arg = coerce(arg_t.clone(), beta_t.clone(), a, p.clone());
                     betas_t.push(beta_t);
                 }
                 // In DNF, one disjunct for each overload
@@ -784,16 +841,19 @@ impl<'a> Typeinf<'a> {
                     let t = self.env.get(x);
                     *x_t = t.clone();
                     let (phi_1, e_t) = self.cgen_expr(&mut *e);
-                    // TODO(arjun): Can we get away with strict equality? If so, we must ensure that
-                    // the type of any expression may be any. This is already the case for literals,
-                    // function applications, and getting values from collections. However, what
-                    // about builtin operators? It seems reasonable to same that e1 === e2 *always*
-                    // produces a boolean and does not produce an any.
-                    //
-                    // However, introducing coercions to any in arbitrary places, such as here, can
-                    // break object identity. It is safer to coerce to any *only* where we introduce
-                    // new values, including the values produced by builtin operators.
-                    let phi_2 = self.t(&t)._eq(&self.t(&e_t));
+                    // The type of x must join the types of everything assigned to it. We
+                    // prefer the assigned type, but allow coercing it to any when it is
+                    // ground, like the result of a return. The branches of a ?: assign to the
+                    // same variable, so `c ? 1 : "s"` makes the result any and coerces both
+                    // branches, while `c ? 1 : 2` is an int.
+                    let w = self.fresh_weight(self.weights.assign);
+                    let e_t_is_ground = self.is_ground(&e_t);
+                    let phi_2 = z3f!(self,
+                        (or (and (id w.clone()) (= (tid t.clone()) (tid e_t.clone())))
+                            (and (not (id w))
+                                 (id e_t_is_ground)
+                                 (= (tid t.clone()) (typ any)))));
+                    **e = coerce(e_t, t.clone(), e.take(), p.clone());
                     (phi_1 & phi_2, t)
                 }
                 LValue::Dot(c, f) => {
@@ -810,8 +870,8 @@ impl<'a> Typeinf<'a> {
                 }
             },
             Expr::Call(f, args, p) => {
-                let w_1 = self.fresh_weight();
-                let w_2 = self.fresh_weight();
+                let w_1 = self.fresh_weight(self.weights.call);
+                let w_2 = self.fresh_weight(self.weights.call);
                 let (phi_1, t_f) = self.cgen_expr(f);
                 let (args_phi, args_t) = self.cgen_exprs(args.iter_mut());
                 let phi_2 = self.zand(args_phi);
@@ -872,8 +932,13 @@ impl<'a> Typeinf<'a> {
                 self.env = outer_env;
 
                 let args: Vec<Type> = f.args_with_typs.iter().map(|(_, t)| t.clone()).collect();
+                if self.objective == Objective::Compatibility {
+                    for t in args.iter().chain(std::iter::once(&return_typ)) {
+                        self.solver.assert(&z3f!(self, (= (tid t) (typ any))));
+                    }
+                }
 
-                let w = self.fresh_weight();
+                let w = self.fresh_weight(self.weights.value);
                 let beta = self.fresh_metavar("beta");
                 // When `(not w)` is true, the auxiliary grounding assertions
                 // produced by `zfun` force `args` and `return_typ` to be `any`.
@@ -906,6 +971,22 @@ impl<'a> Typeinf<'a> {
 }
 
 pub fn typeinf(stmt: &mut Stmt) {
+    typeinf_with_weights(stmt, &TypeinfWeights::default());
+}
+
+/// Type inference with the given costs of coercions. Returns the cost of the types that it
+/// picks, which is the least cost of any types for the program.
+pub fn typeinf_with_weights(stmt: &mut Stmt, weights: &TypeinfWeights) -> u64 {
+    typeinf_with_objective(stmt, weights, Objective::Precision)
+}
+
+/// Type inference with the given costs of coercions and objective. Returns the cost of the types
+/// that it picks.
+pub fn typeinf_with_objective(
+    stmt: &mut Stmt,
+    weights: &TypeinfWeights,
+    objective: Objective,
+) -> u64 {
     let z3_cfg = z3::Config::new();
     let cxt = z3::Context::new(&z3_cfg);
     let bool_sort = z3::Sort::bool(&cxt);
@@ -931,6 +1012,9 @@ pub fn typeinf(stmt: &mut Stmt) {
         // Cannot have return statement at top-level
         return_type: Type::Missing,
         env,
+        weights: weights.clone(),
+        objective,
+        soft: Vec::new(),
     };
     state.cgen_stmt(stmt);
     if state.trace {
@@ -958,6 +1042,7 @@ pub fn typeinf(stmt: &mut Stmt) {
         ops: &state.ops,
     };
     stmt.walk(&mut subst_metavar);
+    state.objective(&model)
 }
 
 #[cfg(test)]
@@ -967,7 +1052,8 @@ mod tests {
     use super::super::syntax::*;
     use super::super::type_checking::type_check;
     use super::super::walk::*;
-    use super::typeinf;
+    use super::{typeinf, typeinf_with_weights};
+    use crate::opts::TypeinfWeights;
 
     #[derive(Default)]
     struct CountToAnys {
@@ -999,6 +1085,20 @@ mod tests {
         return count_anys.num_anys;
     }
 
+    /// The number of coercions to any and the cost of the model.
+    fn weighted_typeinf_test(s: &str, weights: &TypeinfWeights) -> (usize, u64) {
+        let mut js = parse("<text>", s).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        let mut janky = crate::jankyscript::from_js::from_javascript(js);
+        let objective = typeinf_with_weights(&mut janky, weights);
+        println!("after typeinf: {}", janky);
+        let mut count_anys = CountToAnys::default();
+        janky.walk(&mut count_anys);
+        type_check(&janky).expect("result of type inference does not type check");
+        (count_anys.num_anys, objective)
+    }
+
     #[test]
     fn janky_plus() {
         let n = typeinf_test(r#"1 + "2";"#);
@@ -1011,6 +1111,12 @@ mod tests {
         assert_eq!(n, 0);
     }
 
+    #[test]
+    fn string_strict_equal() {
+        let n = typeinf_test(r#"var s = "a"; s === "b";"#);
+        assert_eq!(n, 0);
+    }
+
     #[test]
     fn simple_update() {
         let n = typeinf_test(
@@ -1322,4 +1428,49 @@ mod tests {
         );
         assert_eq!(n, 2);
     }
+
+    #[test]
+    fn ternary_same_branch_types() {
+        let n = typeinf_test(
+            r#"
+            var x = 1;
+            var y = x === 1 ? 10 : 20;
+            y + 1;
+            "#,
+        );
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn ternary_joins_branch_types() {
+        let n = typeinf_test(
+            r#"
+            var x = 1;
+            var y = x === 1 ? 10 : "ten";
+            "#,
+        );
+        assert_eq!(n, 2); // 10 as any, "ten" as any
+    }
+
+    #[test]
+    fn objective_of_precise_program() {
+        let r = weighted_typeinf_test("1 + 2;", &TypeinfWeights::default());
+        assert_eq!(r, (0, 0));
+    }
+
+    #[test]
+    fn weighted_literals() {
+        let program = r#"
+            var x = 20;
+            x = true;
+        "#;
+        let r = weighted_typeinf_test(program, &TypeinfWeights::default());
+        assert_eq!(r, (2, 2));
+        // The initializer of x must be a coerced literal, but true can be coerced by the
+        // assignment instead.
+        let mut weights = TypeinfWeights::default();
+        weights.literal = 5;
+        let r = weighted_typeinf_test(program, &weights);
+        assert_eq!(r, (2, 6));
+    }
 }
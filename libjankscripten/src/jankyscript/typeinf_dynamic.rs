@@ -0,0 +1,361 @@
+//! Type inference without Z3, for builds of the compiler that cannot link to it, such as the
+//! WebAssembly build of the playground.
+//!
+//! This pass picks the solution to the constraints of [super::typeinf] in which every variable,
+//! argument, and result is `any`. It never fails, but the program that it produces is as slow as
+//! an untyped one: only literals, operators on literals, and primitive methods keep precise types.
+//! It fills in the same annotations as type inference, so the phases that follow cannot tell the
+//! difference.
+
+use super::super::shared::coercions::Coercion;
+use super::constructors::coercion_;
+use super::operators::{NotwasmOp, OVERLOADS};
+use super::syntax::*;
+use super::typeinf_env::Env;
+use crate::pos::Pos;
+use crate::shared::methods::METHODS_TABLE;
+
+/// Calculates the type of a literal.
+fn typ_lit(lit: &Lit) -> Type {
+    match lit {
+        Lit::Num(Num::Float(_)) => Type::Float,
+        Lit::Num(Num::Int(_)) => Type::Int,
+        Lit::String(_) => Type::String,
+        Lit::Bool(_) => Type::Bool,
+        Lit::Regex(..) => Type::Any,
+        Lit::Undefined => Type::Any,
+        Lit::Null => Type::Any,
+    }
+}
+
+/// Coerces `e` from `src` to `dst`, unless they are the same type.
+fn coerce(e: &mut Expr, src: Type, dst: Type, p: &Pos) {
+    if src != dst {
+        *e = coercion_(Coercion::meta(src, dst), e.take(), p.clone());
+    }
+}
+
+struct Typeinf {
+    env: Env,
+    return_type: Type,
+}
+
+impl Typeinf {
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Var(x, t, e, p) => {
+                *t = Type::Any;
+                self.env.update(x.clone(), Type::Any);
+                let e_t = self.expr(e);
+                coerce(e, e_t, Type::Any, p);
+            }
+            Stmt::Expr(e, _) => {
+                self.expr(e);
+            }
+            Stmt::Empty | Stmt::Pragma(..) | Stmt::Break(..) => (),
+            Stmt::Loop(s, _) | Stmt::Label(_, s, _) => self.stmt(s),
+            Stmt::Block(stmts, _) => {
+                for s in stmts.iter_mut() {
+                    self.stmt(s);
+                }
+            }
+            Stmt::Catch(body, exn_name, catch_body, _) => {
+                self.stmt(body);
+                let env = self.env.clone();
+                self.env.update(exn_name.clone(), Type::Any);
+                self.stmt(catch_body);
+                self.env = env;
+            }
+            Stmt::Return(e, p) => {
+                let t = self.expr(e);
+                coerce(e, t, self.return_type.clone(), p);
+            }
+            Stmt::If(test, then_branch, else_branch, p) => {
+                let t = self.expr(test);
+                coerce(test, t, Type::Bool, p);
+                self.stmt(then_branch);
+                self.stmt(else_branch);
+            }
+            Stmt::ForIn(x, iter, body, p) => {
+                let t = self.expr(iter);
+                coerce(iter, t, Type::String, p);
+                let outer_env = self.env.clone();
+                self.env.update(x.clone(), Type::String);
+                self.stmt(body);
+                self.env = outer_env;
+            }
+            Stmt::Throw(e, p) => {
+                let t = self.expr(e);
+                coerce(e, t, Type::Any, p);
+            }
+            Stmt::Finally(main_block, finally_block, _) => {
+                self.stmt(main_block);
+                self.stmt(finally_block);
+            }
+        }
+    }
+
+    /// Coerces every expression in `exprs` to `any`.
+    fn exprs_any<'b>(&mut self, exprs: impl Iterator<Item = &'b mut Expr>, p: &Pos) {
+        for e in exprs {
+            let t = self.expr(e);
+            coerce(e, t, Type::Any, p);
+        }
+    }
+
+    /// Coerces the object to `DynObject`, like the only solution of [super::typeinf].
+    fn dot(&mut self, obj_e: &mut Expr, p: &Pos) {
+        let t = self.expr(obj_e);
+        coerce(obj_e, t, Type::DynObject, p);
+    }
+
+    /// Uses an array with an integer index, or an object with a string field, when one of the two
+    /// is known and the other is either known to match or `any`. Otherwise, uses `any` for both.
+    fn bracket(&mut self, o: &mut Expr, f: &mut Expr, t: &mut Type, p: &Pos) {
+        let o_t = self.expr(o);
+        let f_t = self.expr(f);
+        let (o_final, f_final) = match (&o_t, &f_t) {
+            (Type::Array, Type::Int) | (Type::Array, Type::Any) | (Type::Any, Type::Int) => {
+                (Type::Array, Type::Int)
+            }
+            (Type::DynObject, Type::String)
+            | (Type::DynObject, Type::Any)
+            | (Type::Any, Type::String) => (Type::DynObject, Type::String),
+            _ => (Type::Any, Type::Any),
+        };
+        *t = o_final.clone();
+        coerce(o, o_t, o_final, p);
+        coerce(f, f_t, f_final, p);
+    }
+
+    /// Uses the type of the method in the methods table that matches the type of the object, or
+    /// `(any, ...) -> any` otherwise. The object is the first argument.
+    fn method_call(
+        &mut self,
+        obj: &mut Expr,
+        method: &str,
+        args: &mut Vec<Expr>,
+        typ: &mut Type,
+        p: &Pos,
+    ) -> Type {
+        let obj_t = self.expr(obj);
+        let args_t: Vec<Type> = args.iter_mut().map(|e| self.expr(e)).collect();
+        let table_typ = METHODS_TABLE
+            .get(&(method, args.len()))
+            .and_then(|typs| typs.iter().find(|t| t.unwrap_fun().0[0] == obj_t));
+        let (obj_final, expect_args, expect_ret) = match table_typ {
+            Some(t) => {
+                let (expect_args, expect_ret) = t.unwrap_fun();
+                (obj_t.clone(), expect_args.clone(), expect_ret.clone())
+            }
+            None => {
+                // A method that is not in the table eliminates an object. Otherwise, we do not
+                // know which primitive method to use, so we use the dynamic one.
+                let obj_final = if obj_t == Type::DynObject
+                    || METHODS_TABLE.get(&(method, args.len())).is_none()
+                {
+                    Type::DynObject
+                } else {
+                    Type::Any
+                };
+                (obj_final, vec![Type::Any; args.len()], Type::Any)
+            }
+        };
+        *typ = obj_final.clone();
+        coerce(obj, obj_t, obj_final, p);
+        for ((arg, arg_t), expect) in args.iter_mut().zip(args_t).zip(expect_args) {
+            coerce(arg, arg_t, expect, p);
+        }
+        expect_ret
+    }
+
+    fn length(&mut self, obj: &mut Expr, typ: &mut Type, p: &Pos) -> Type {
+        let obj_t = self.expr(obj);
+        match obj_t {
+            Type::String | Type::Array => {
+                *typ = obj_t;
+                Type::Int
+            }
+            Type::DynObject => {
+                *typ = obj_t;
+                Type::Any
+            }
+            _ => {
+                *typ = Type::Any;
+                coerce(obj, obj_t, Type::Any, p);
+                Type::Any
+            }
+        }
+    }
+
+    /// Selects the overload of an operator whose arguments have exactly the types of `args_t`.
+    /// Otherwise, selects the overload on `any`, or the overload that permits coercions.
+    fn select_op(op: &JsOp, args_t: &[Type]) -> (Type, NotwasmOp) {
+        let overloads = || OVERLOADS.overloads(op);
+        let exact = overloads().find(|(t, _)| t.unwrap_fun().0.as_slice() == args_t);
+        let on_any = || overloads().find(|(t, _)| t.unwrap_fun().0.iter().all(|t| t == &Type::Any));
+        match exact.or_else(on_any).or_else(|| OVERLOADS.coercible(op)) {
+            Some((t, notwasm_op)) => (t.clone(), notwasm_op.clone()),
+            None => panic!("no overload of {:?} for {:?}", op, args_t),
+        }
+    }
+
+    /// Returns the type of `expr`, after inserting coercions into its subexpressions.
+    fn expr(&mut self, expr: &mut Expr) -> Type {
+        match expr {
+            Expr::Binary(..)
+            | Expr::NewRef(..)
+            | Expr::Deref(..)
+            | Expr::Store(..)
+            | Expr::EnvGet(..)
+            | Expr::Coercion(..)
+            | Expr::Closure(..)
+            | Expr::Unary(..) => panic!("unexpected {:?}", &expr),
+            Expr::Lit(l, _) => typ_lit(l),
+            Expr::Array(es, p) => {
+                let p = p.clone();
+                self.exprs_any(es.iter_mut(), &p);
+                Type::Array
+            }
+            Expr::Object(props, p) => {
+                let p = p.clone();
+                self.exprs_any(props.iter_mut().map(|(_, e)| e), &p);
+                Type::DynObject
+            }
+            Expr::PrimCall(rts_func, args, p) => {
+                let rts_typ = rts_func.janky_typ();
+                let (arg_ts, ret_t) = rts_typ.unwrap_fun();
+                for (arg, arg_t) in args.iter_mut().zip(arg_ts) {
+                    let t = self.expr(arg);
+                    coerce(arg, t, arg_t.clone(), p);
+                }
+                ret_t.clone()
+            }
+            Expr::Id(x, t, _) => {
+                *t = self.env.get(x);
+                t.clone()
+            }
+            Expr::Dot(obj_e, _, p) => {
+                self.dot(obj_e, p);
+                Type::Any
+            }
+            Expr::Bracket(o, f, t, p) => {
+                self.bracket(o, f, t, p);
+                Type::Any
+            }
+            Expr::JsOp(op, args, _, p) => {
+                let args_t: Vec<Type> = args.iter_mut().map(|e| self.expr(e)).collect();
+                let (op_t, notwasm_op) = Self::select_op(op, &args_t);
+                let (op_args_t, op_ret_t) = op_t.unwrap_fun();
+                for ((arg, arg_t), op_arg_t) in args.iter_mut().zip(args_t).zip(op_args_t) {
+                    coerce(arg, arg_t, op_arg_t.clone(), p);
+                }
+                let ret_t = op_ret_t.clone();
+                let p = p.clone();
+                let args = std::mem::replace(args, Default::default());
+                *expr = notwasm_op.make_app(args, p);
+                ret_t
+            }
+            Expr::MethodCall(obj, method, args, typ, p) => {
+                let p = p.clone();
+                let method = method.clone();
+                self.method_call(obj, &method, args, typ, &p)
+            }
+            Expr::Length(obj, typ, p) => {
+                let p = p.clone();
+                self.length(obj, typ, &p)
+            }
+            Expr::Assign(lval, e, p) => match &mut **lval {
+                LValue::Id(x, x_t) => {
+                    let t = self.env.get(x);
+                    *x_t = t.clone();
+                    let e_t = self.expr(e);
+                    coerce(e, e_t, t.clone(), p);
+                    t
+                }
+                LValue::Dot(c, _) => {
+                    let e_t = self.expr(e);
+                    coerce(e, e_t, Type::Any, p);
+                    self.dot(c, p);
+                    Type::Any
+                }
+                LValue::Bracket(o, f, t) => {
+                    let e_t = self.expr(e);
+                    coerce(e, e_t, Type::Any, p);
+                    self.bracket(o, f, t, p);
+                    Type::Any
+                }
+            },
+            Expr::Call(f, args, p) => {
+                let f_t = self.expr(f);
+                match f_t {
+                    Type::Function(params, ret) if params.len() == args.len() => {
+                        for (arg, param) in args.iter_mut().zip(params) {
+                            let t = self.expr(arg);
+                            coerce(arg, t, param, p);
+                        }
+                        *ret
+                    }
+                    f_t => {
+                        self.exprs_any(args.iter_mut(), p);
+                        coerce(f, f_t, Type::ground_function(args.len()), p);
+                        Type::Any
+                    }
+                }
+            }
+            Expr::Func(f, _) => {
+                let outer_env = self.env.clone();
+                let outer_return_typ = std::mem::replace(&mut self.return_type, Type::Any);
+                f.result_typ = Type::Any;
+                for (x, t) in f.args_with_typs.iter_mut() {
+                    assert_eq!(t, &Type::Missing);
+                    *t = Type::Any;
+                    self.env.update(x.clone(), Type::Any);
+                }
+                self.stmt(&mut *f.body);
+                self.return_type = outer_return_typ;
+                self.env = outer_env;
+                Type::ground_function(f.args_with_typs.len())
+            }
+        }
+    }
+}
+
+pub fn typeinf(stmt: &mut Stmt) {
+    let mut state = Typeinf {
+        env: Env::new(),
+        // Cannot have return statement at top-level
+        return_type: Type::Missing,
+    };
+    state.stmt(stmt);
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::javascript::{desugar, parse};
+    use super::super::super::shared::NameGen;
+    use super::super::type_checking::type_check;
+    use super::typeinf;
+
+    fn typeinf_test(s: &str) {
+        let mut js = parse("<text>", s).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        let mut janky = crate::jankyscript::from_js::from_javascript(js);
+        typeinf(&mut janky);
+        type_check(&janky).expect("result of type inference does not type check");
+    }
+
+    #[test]
+    fn dynamic_solution_type_checks() {
+        typeinf_test(
+            r#"
+            function f(x, y) { return x + y; }
+            var a = [1, 2];
+            var o = { x: f(a[0], 2) };
+            o.y = a.length;
+            if (o.x > 2 && 1 + 2 === 3) { f("a", "b"); }
+        "#,
+        );
+    }
+}
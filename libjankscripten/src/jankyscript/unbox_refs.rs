@@ -0,0 +1,226 @@
+//! Turn boxed variables that no closure captures back into plain variables.
+//!
+//! [super::box_assigns] boxes every variable that is free in a nested function and assigned
+//! anywhere, so that the nested function and its enclosing function share the variable. However,
+//! only a closure that captures the variable needs its box. After closure conversion, a closure
+//! captures `x` by putting `x` itself into its environment, whereas a function that reads or
+//! writes `x` does so with `Deref(x)` and `Store(x, e)`. When the program only uses a boxed
+//! variable in these two ways, this pass removes its box:
+//!
+//! - `var x: Ref(T) = newRef(e)` becomes `var x: T = e`, and `var x: Ref(T) = undefined`, which
+//!   declares a variable that the program initializes later, becomes `var x: T = undefined`,
+//! - `Deref(x)` becomes `x`, and
+//! - `Store(x, e)` becomes `x = e`.
+//!
+//! preconditions: [super::closure_convert], and [super::alpha_rename], so that a name identifies
+//! a single variable
+use super::constructors::*;
+use super::syntax::*;
+use super::walk::*;
+use std::collections::{HashMap, HashSet};
+
+pub fn unbox_refs(program: &mut Stmt) {
+    let mut uses = Uses::default();
+    program.walk(&mut uses);
+    let unboxed = uses.unboxed();
+    if !unboxed.is_empty() {
+        program.walk(&mut Unbox { unboxed });
+    }
+}
+
+#[derive(Default)]
+struct Uses {
+    /// The variables that `var x: Ref(T) = newRef(e)` or `var x: Ref(T) = undefined` declares.
+    boxed: HashSet<Id>,
+    /// The variables that the program binds or assigns in any other way.
+    other_bindings: HashSet<Id>,
+    /// The number of occurrences of each variable in an expression.
+    occurrences: HashMap<Id, usize>,
+    /// The number of occurrences of each variable as the box of `Deref` or `Store`.
+    box_occurrences: HashMap<Id, usize>,
+}
+
+impl Uses {
+    /// The boxed variables that only occur as the box of `Deref` or `Store`.
+    fn unboxed(self) -> HashSet<Id> {
+        let Uses {
+            boxed,
+            other_bindings,
+            occurrences,
+            box_occurrences,
+        } = self;
+        boxed
+            .into_iter()
+            .filter(|x| !other_bindings.contains(x) && occurrences.get(x) == box_occurrences.get(x))
+            .collect()
+    }
+}
+
+impl Visitor for Uses {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::Var(x, Type::Ref(_), e, _)
+                if matches!(**e, Expr::NewRef(..)) || e.is_undefined() =>
+            {
+                self.boxed.insert(x.clone());
+            }
+            Stmt::Var(x, ..) | Stmt::ForIn(x, ..) | Stmt::Catch(_, x, ..) => {
+                self.other_bindings.insert(x.clone());
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_fn(&mut self, func: &mut Func, _loc: &Loc) {
+        self.other_bindings.extend(func.arg_names().cloned());
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Id(x, ..) => *self.occurrences.entry(x.clone()).or_insert(0) += 1,
+            Expr::Deref(e, ..) | Expr::Store(e, ..) => {
+                if let Expr::Id(x, ..) = &**e {
+                    *self.box_occurrences.entry(x.clone()).or_insert(0) += 1;
+                }
+            }
+            Expr::Assign(lv, ..) => {
+                if let LValue::Id(x, _) = &**lv {
+                    self.other_bindings.insert(x.clone());
+                }
+            }
+            // The walk does not enter the bodies of closures.
+            Expr::Closure(func, ..) => {
+                self.other_bindings.extend(func.arg_names().cloned());
+                func.body.walk(self);
+            }
+            _ => (),
+        }
+    }
+}
+
+struct Unbox {
+    unboxed: HashSet<Id>,
+}
+
+impl Visitor for Unbox {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Closure(func, ..) = expr {
+            func.body.walk(self);
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Deref(e, ty, p) => {
+                if let Expr::Id(x, _, _) = &**e {
+                    if self.unboxed.contains(x) {
+                        let e = Expr::Id(x.clone(), ty.clone(), p.clone());
+                        *expr = e;
+                    }
+                }
+            }
+            Expr::Store(e1, e2, ty, p) => {
+                if let Expr::Id(x, _, _) = &**e1 {
+                    if self.unboxed.contains(x) {
+                        let e = assign_var_(x.clone(), ty.clone(), e2.take(), p.clone());
+                        *expr = e;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Var(x, ty, e, _) = stmt {
+            if self.unboxed.contains(x) {
+                if let Type::Ref(t) = ty {
+                    let t = (**t).clone();
+                    *ty = t;
+                }
+                if let Expr::NewRef(init, ..) = &mut **e {
+                    let init = init.take();
+                    **e = init;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::box_assigns::box_assigns;
+    use super::super::closure_convert::closure_convert;
+    use super::super::collect_assigns::collect_assigns;
+    use super::super::compile::insert_coercions;
+    use super::super::from_js::from_javascript;
+    use super::super::fv::free_vars;
+    use super::super::interpreter::interpret;
+    use super::super::type_checking::type_check;
+    use super::*;
+    use crate::javascript::{desugar, parse};
+    use crate::shared::NameGen;
+
+    /// Compiles the program like [super::super::compile], but also boxes the variables in
+    /// `boxed`. Produces the output of the program and the compiled program.
+    fn compile(js_code: &str, boxed: &[&str]) -> (String, Stmt) {
+        let mut js = parse("<test>", js_code).expect("error parsing JavaScript");
+        let mut ng = NameGen::default();
+        desugar(&mut js, &mut ng);
+        let mut program = from_javascript(js);
+        let output = interpret(&program).expect("error interpreting the program");
+        insert_coercions(&mut program, |_| ()).expect("error inserting coercions");
+        free_vars(&mut program);
+        let should_box =
+            collect_assigns(&mut program).union(boxed.iter().map(|x| Id::from(*x)).collect());
+        box_assigns(&mut program, should_box);
+        closure_convert(&mut program);
+        unbox_refs(&mut program);
+        type_check(&program).expect("error type-checking the unboxed program");
+        (output, program)
+    }
+
+    /// The variables that the program declares with a `Ref` type.
+    struct BoxedVars(Vec<Id>);
+
+    impl Visitor for BoxedVars {
+        fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+            if let Stmt::Var(x, Type::Ref(_), ..) = stmt {
+                self.0.push(x.clone());
+            }
+        }
+
+        fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+            if let Expr::Closure(func, ..) = expr {
+                func.body.walk(self);
+            }
+        }
+    }
+
+    fn boxed_vars(program: &mut Stmt) -> Vec<Id> {
+        let mut v = BoxedVars(Vec::new());
+        program.walk(&mut v);
+        v.0
+    }
+
+    #[test]
+    fn uncaptured_variable_is_unboxed() {
+        let (output, mut program) = compile("var x = 1; x = x + 1; log_any(x);", &["x"]);
+        assert!(boxed_vars(&mut program).is_empty());
+        assert_eq!(interpret(&program).unwrap(), output);
+    }
+
+    #[test]
+    fn captured_variable_stays_boxed() {
+        let (_, mut program) = compile(
+            "var x = 1;
+            var y = 2;
+            function f() { return x; }
+            x = 2;
+            y = y + x;
+            log_any(f() + y);",
+            &["y"],
+        );
+        assert_eq!(boxed_vars(&mut program), vec![Id::from("x")]);
+    }
+}
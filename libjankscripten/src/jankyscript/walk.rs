@@ -1,7 +1,6 @@
 //! A visitor for JavaScript ASTs.
 
 use super::syntax::*;
-use std::cell::RefCell;
 
 /// Statements, expressions, and other types of AST nodes have a `walk` method
 /// that receives an implementation of this `Visitor` trait.
@@ -32,58 +31,18 @@ pub trait Visitor {
     /// called after recursing on a function
     fn exit_fn(&mut self, _func: &mut Func, _loc: &Loc) {}
     fn enter_typ(&mut self, _typ: &mut Type, _loc: &Loc) {}
+    /// called before visiting each node. Once this produces `true`, the walk
+    /// ends without calling any more `enter_` or `exit_` methods
+    fn done(&self) -> bool {
+        false
+    }
 }
 
 struct VisitorState<'v, V> {
     visitor: &'v mut V,
 }
 
-#[derive(Debug)]
-pub struct BlockContext {
-    pub index: usize,
-    pub len: usize,
-    patches: RefCell<Vec<(usize, Stmt)>>,
-}
-
-impl BlockContext {
-    pub fn new(index: usize, len: usize) -> Self {
-        BlockContext {
-            index,
-            len,
-            patches: RefCell::new(vec![]),
-        }
-    }
-
-    /// Insert `stmt` at position `index` into the block. The position 0 is
-    /// before the first element, and the last position is after the last
-    /// element. E.g., if the block has statements `[s0, s1, s2]` then the
-    /// indices are `[0, s0, 1, s1, 2, s2, 3]`.
-    ///
-    /// Note that you can insert multiple statements at the same index.
-    /// They will appear in the order they were added.
-    ///
-    /// Panics if the index is invalid.
-    pub fn insert(&self, index: usize, stmt: Stmt) {
-        assert!(index <= self.len);
-        self.patches.borrow_mut().push((index, stmt));
-    }
-
-    fn apply_patches(mut self, block: &mut Vec<Stmt>) {
-        // but we want patches to be applied in order they were added. because
-        // we add them in reverse order we want to iterate in reverse order
-        // Vec::sort_by maintains diff=0 values to be in their original order
-        // (stable), but we want the opposite behavior. to do this we sort in
-        // ascending order and reverse the whole iterator
-        let patches = self.patches.get_mut();
-        patches.sort_by(|(m, _), (n, _)| m.cmp(n));
-        // .rev() reverses the reverse-sorted iterator now
-        for (index, stmt) in patches.drain(0..).rev() {
-            // Inserting shifts all elements to the right. However, the
-            // indices are in descending order.
-            block.insert(index, stmt);
-        }
-    }
-}
+pub type BlockContext = crate::shared::walk::BlockContext<Stmt>;
 
 /// A single-level of a context.
 #[derive(Debug)]
@@ -172,11 +131,14 @@ where
 
     pub fn walk_stmt(&mut self, stmt: &mut Stmt, loc: &Loc) {
         use Stmt::*;
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_stmt(stmt, loc);
         // recurse
         match stmt {
             // 0
-            Empty | Break(_, _) => (),
+            Empty | Break(_, _) | Pragma(..) => (),
             // 1xStmt
             Label(.., a, _) | Loop(a, _) => {
                 let loc = Loc::Node(Context::Stmt, loc);
@@ -222,7 +184,9 @@ where
                 self.walk_stmt(sb, &loc);
             }
         }
-        self.visitor.exit_stmt(stmt, &loc);
+        if !self.visitor.done() {
+            self.visitor.exit_stmt(stmt, &loc);
+        }
     }
 
     pub fn walk_coercion(&mut self, coercion: &mut Coercion, loc: &Loc) {
@@ -240,6 +204,9 @@ where
 
     pub fn walk_expr(&mut self, expr: &mut Expr, loc: &Loc) {
         use Expr::*;
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_expr(expr, loc);
         match expr {
             // 0
@@ -256,7 +223,9 @@ where
                 }
                 self.walk_type(&mut f.result_typ, &loc);
                 self.walk_stmt(&mut *f.body, &loc);
-                self.visitor.exit_fn(f, &loc);
+                if !self.visitor.done() {
+                    self.visitor.exit_fn(f, &loc);
+                }
             }
             JsOp(_, es, _, _) => {
                 let loc = Loc::Node(Context::Expr, loc);
@@ -334,13 +303,18 @@ where
                 }
             }
         }
-        self.visitor.exit_expr(expr, loc);
+        if !self.visitor.done() {
+            self.visitor.exit_expr(expr, loc);
+        }
     }
 
     /// like [Stmt::walk], but as a method on LValue. does the *exact*
     /// same thing
     pub fn walk_lval(&mut self, lval: &mut LValue, loc: &Loc) {
         use LValue::*;
+        if self.visitor.done() {
+            return;
+        }
         match lval {
             Id(x, t) => {
                 let loc = Loc::Node(Context::BoundId(x), loc);
@@ -360,6 +334,9 @@ where
     }
 
     pub fn walk_type(&mut self, typ: &mut Type, loc: &Loc) {
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_typ(typ, loc);
         let loc = Loc::Node(Context::Type, &loc);
         match typ {
@@ -0,0 +1,211 @@
+//! Infer the fields of the objects that simple constructors create.
+//!
+//! `new F(args)` creates an object that inherits from `F.prototype` and then calls `F`, which
+//! usually adds fields to the object one at a time. Adding a field transitions the object to a new
+//! hidden class, which copies the fields of the object. However, when `F` is a *simple
+//! constructor*, whose body only assigns fields of `this`, we know the fields of the object in
+//! advance. Therefore, `desugar_this` creates the object as the object literal
+//! `{__proto__: F.prototype, x: undefined, ...}`, which starts with the final class, so the
+//! assignments in `F` write fields that already exist.
+//!
+//! A constructor is simple when:
+//!
+//! - it is a function statement at the top level of the program, and the program does not bind
+//!   or assign any other variable with the same name,
+//! - its body only contains `this.x = e` statements, where `e` does not refer to `this`, so the
+//!   constructor cannot observe a field before it assigns it, and
+//! - the program does not refer to the global object, which could replace the constructor.
+//!
+//! preconditions: desugar_function_stmts, lift_vars
+use super::syntax::*;
+use super::walk::*;
+use std::collections::HashMap;
+
+/// The names that refer to the global object in the standard library.
+const GLOBAL_OBJECT_NAMES: [&str; 2] = ["globalThis", "global"];
+
+/// Maps the name of each simple constructor to the fields that it assigns, in the order that it
+/// first assigns them.
+pub fn constructor_classes(program: &mut Stmt) -> HashMap<Id, Vec<String>> {
+    let mut bindings = Bindings::default();
+    program.walk(&mut bindings);
+    let mut classes = HashMap::new();
+    if bindings.refers_to_global_object {
+        return classes;
+    }
+    let stmts = match program {
+        Stmt::Block(stmts, _) => stmts,
+        _ => return classes,
+    };
+    for stmt in stmts {
+        if let Stmt::Expr(e, _) = stmt {
+            if let Expr::Assign(AssignOp::Equal, lv, f, _) = &mut **e {
+                if let (LValue::Id(x), Expr::Func(None, _, body, ..)) = (&**lv, &mut **f) {
+                    // The hoisted declaration of the variable and this assignment.
+                    if bindings.count(x) != 2 {
+                        continue;
+                    }
+                    if let Some(fields) = assigned_fields(body) {
+                        classes.insert(x.clone(), fields);
+                    }
+                }
+            }
+        }
+    }
+    classes
+}
+
+/// The fields that `body` assigns, if it is the body of a simple constructor.
+fn assigned_fields(body: &mut Stmt) -> Option<Vec<String>> {
+    let stmts = match body {
+        Stmt::Block(stmts, _) => stmts,
+        _ => return None,
+    };
+    let mut fields: Vec<String> = Vec::new();
+    for stmt in stmts {
+        match stmt {
+            Stmt::Empty => (),
+            Stmt::Expr(e, _) => match &mut **e {
+                Expr::Assign(AssignOp::Equal, lv, e, _) => match &**lv {
+                    LValue::Dot(Expr::This, x) if !refers_to_this(e) => {
+                        let x = x.to_string();
+                        if x == "__proto__" {
+                            return None;
+                        }
+                        if !fields.contains(&x) {
+                            fields.push(x);
+                        }
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    if fields.is_empty() {
+        None
+    } else {
+        Some(fields)
+    }
+}
+
+fn refers_to_this(e: &mut Expr) -> bool {
+    let mut v = RefersToThis(false);
+    e.walk(&mut v);
+    v.0
+}
+
+struct RefersToThis(bool);
+
+impl Visitor for RefersToThis {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::This = expr {
+            self.0 = true;
+        }
+    }
+}
+
+/// Counts the declarations, parameters, and assignments of each variable in the program.
+#[derive(Default)]
+struct Bindings {
+    counts: HashMap<Id, usize>,
+    refers_to_global_object: bool,
+}
+
+impl Bindings {
+    fn bind(&mut self, x: &Id) {
+        *self.counts.entry(x.clone()).or_insert(0) += 1;
+    }
+
+    fn count(&self, x: &Id) -> usize {
+        self.counts.get(x).cloned().unwrap_or(0)
+    }
+}
+
+impl Visitor for Bindings {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::VarDecl(decls, _) => {
+                for decl in decls {
+                    self.bind(&decl.name);
+                }
+            }
+            Stmt::ForIn(_, x, ..) | Stmt::Catch(_, x, ..) | Stmt::Func(x, ..) => self.bind(x),
+            _ => (),
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Id(x, _) => {
+                if GLOBAL_OBJECT_NAMES.contains(&x.to_string().as_str()) {
+                    self.refers_to_global_object = true;
+                }
+            }
+            Expr::Assign(_, lv, ..) | Expr::UnaryAssign(_, lv, _) => {
+                if let LValue::Id(x) = &**lv {
+                    self.bind(x);
+                }
+            }
+            Expr::Func(name, params, ..) => {
+                for x in name.iter().chain(params.iter()) {
+                    self.bind(x);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::desugar_function_stmts::DesugarFunctionStmts;
+    use super::super::{add_blocks, desugar_vardecls, lift_vars};
+    use super::*;
+    use crate::javascript::parse;
+
+    fn classes(js_code: &str) -> HashMap<Id, Vec<String>> {
+        let mut program = parse("<test>", js_code).expect("error parsing JavaScript");
+        program.walk(&mut DesugarFunctionStmts {});
+        add_blocks::add_blocks(&mut program);
+        desugar_vardecls::desugar_vardecls(&mut program);
+        lift_vars::lift_vars(&mut program);
+        constructor_classes(&mut program)
+    }
+
+    #[test]
+    fn simple_constructor() {
+        let classes = classes(
+            "function Point(x, y) { this.x = x; this.y = y; this.x = x + 1; }
+            var p = new Point(1, 2);",
+        );
+        assert_eq!(
+            classes.get(&Id::from("Point")),
+            Some(&vec!["x".to_string(), "y".to_string()])
+        );
+    }
+
+    #[test]
+    fn constructor_that_reads_this() {
+        let classes = classes("function F(x) { this.x = x; this.y = this.x; }");
+        assert!(classes.is_empty());
+    }
+
+    #[test]
+    fn constructor_with_other_statements() {
+        let classes = classes("function F(x) { if (x) { this.x = x; } }");
+        assert!(classes.is_empty());
+    }
+
+    #[test]
+    fn reassigned_constructor() {
+        let classes = classes(
+            "function F(x) { this.x = x; }
+            function G(x) { this.y = x; }
+            F = G;
+            function H(G) { return G; }",
+        );
+        assert!(classes.is_empty());
+    }
+}
@@ -31,6 +31,10 @@ pub fn forin_<I: Into<Id>>(a: bool, b: I, c: Expr, d: Stmt, s: Pos) -> Stmt {
     Stmt::ForIn(a, b.into(), Box::new(c), Box::new(d), s)
 }
 
+pub fn forof_<I: Into<Id>>(a: bool, b: I, c: Expr, d: Stmt, s: Pos) -> Stmt {
+    Stmt::ForOf(a, b.into(), Box::new(c), Box::new(d), s)
+}
+
 pub fn label_<I: Into<Id>>(a: I, b: Stmt, s: Pos) -> Stmt {
     Stmt::Label(a.into(), Box::new(b), s)
 }
@@ -70,6 +74,7 @@ pub fn func_<I: Into<Id>, J: Into<Id>>(a: I, b: Vec<J>, c: Stmt, s: Pos) -> Stmt
         a.into(),
         b.into_iter().map(Into::into).collect(),
         Box::new(c),
+        FuncKind::Plain,
         s,
     )
 }
@@ -148,6 +153,7 @@ pub fn expr_func_<I: Into<Id>, J: Into<Id>>(a: Option<I>, b: Vec<J>, c: Stmt, s:
         a.map(|x| x.into()),
         b.into_iter().map(Into::into).collect(),
         Box::new(c),
+        FuncKind::Plain,
         s,
     )
 }
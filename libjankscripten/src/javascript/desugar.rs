@@ -1,6 +1,24 @@
 use super::*;
 
 pub fn desugar(stmt: &mut Stmt, ng: &mut NameGen) {
+    // classes become constructor functions, whose parameters the passes below lower
+    desugar_classes::desugar_classes(stmt, ng);
+    // patterns become variable declarations, before anything looks at them
+    desugar_destructuring::desugar_destructuring(stmt, ng);
+    // for..of becomes for, before desugar_loops labels loops
+    desugar_for_of::desugar_for_of(stmt, ng);
+    // generator functions become ordinary functions that return an iterator, and async
+    // functions ones that pass their continuation to the host, while their bodies still have
+    // their loops and their defaults and rest parameters
+    // dep: desugar_classes, desugar_destructuring, desugar_for_of
+    generators::desugar_generators(stmt);
+    // default and rest parameters become ordinary ones, while function statements are still
+    // declarations
+    // dep: generators
+    desugar_params::desugar_params(stmt);
+    // omitted arguments become undefined, while function statements are still declarations
+    // dep: desugar_params
+    desugar_missing_args::desugar_missing_args(stmt);
     stmt.walk(&mut super::desugar_function_stmts::DesugarFunctionStmts {});
     normalize_std_lib_calls::normalize_std_lib_calls(stmt);
     desugar_switch::desugar_switch(stmt, ng);
@@ -14,15 +32,17 @@ pub fn desugar(stmt: &mut Stmt, ng: &mut NameGen) {
     // dep: desugar_vardecls
     // we want this to go sooner rather than later to reduce anys
     lift_vars::lift_vars(stmt);
-    // dep: add_blocks, normalize_std_lib_calls
+    // dep: add_blocks, normalize_std_lib_calls, lift_vars
     desugar_this::desugar_this(stmt, ng);
-    // accesses are immediately applied
-    // dep: desugar_this, add_blocks
-    desugar_function_applications::desugar_function_applications(stmt, ng);
     // dep: desugar_loops, add_blocks
     desugar_logical::desugar_logical(stmt, ng);
-    // dep: desugar_function_applications, add_blocks
+    // names the subexpressions of lvalues, including calls, so that they are
+    // evaluated once and before the right-hand side
+    // dep: desugar_logical, add_blocks
     desugar_updates::desugar_updates(stmt, ng);
+    // accesses are immediately applied
+    // dep: desugar_this, desugar_logical, desugar_updates, add_blocks
+    desugar_function_applications::desugar_function_applications(stmt, ng);
     desugar_bracket_str::desugar_bracket_str(stmt);
     // dep: desugar_this
     resugar_method_call::resugar_method_call(stmt);
@@ -346,6 +366,87 @@ mod test {
         okay(prog);
     }
 
+    #[test]
+    fn desugar_function_called_before_definition() {
+        okay(
+            "var x = f();
+            function f() { return g(); }
+            function g() { return 1; }
+            x;",
+        );
+    }
+
+    #[test]
+    fn desugar_last_function_declaration_wins() {
+        okay(
+            "function f() { return 1; }
+            var x = f();
+            function f() { return 2; }
+            x * 10 + f();",
+        );
+    }
+
+    #[test]
+    fn desugar_var_without_initializer_keeps_function() {
+        okay(
+            "var g;
+            function g() { return 1; }
+            var g;
+            g();",
+        );
+    }
+
+    #[test]
+    fn desugar_var_redeclares_parameter() {
+        okay(
+            "function f(x) {
+                var x;
+                var y = x;
+                var x = x + 1;
+                return x * 10 + y;
+            }
+            f(5);",
+        );
+    }
+
+    #[test]
+    fn desugar_var_in_nested_block_has_function_scope() {
+        okay(
+            "function f(b) {
+                if (b) { var y = 1; }
+                while (false) { var z = 2; }
+                return [y, z];
+            }
+            f(true);",
+        );
+    }
+
+    #[test]
+    fn desugar_for_in_var_has_function_scope() {
+        okay(
+            "function f(o) {
+                for (var k in o) { }
+                return k;
+            }
+            f({ a: 1 });",
+        );
+    }
+
+    #[test]
+    fn desugar_function_in_block_is_hoisted_to_block() {
+        okay(
+            r#"var r = typeof g;
+            if (true) {
+                r = r + g();
+                function g() { return "ok"; }
+            }
+            if (false) {
+                function h() { return 1; }
+            }
+            r + typeof h;"#,
+        );
+    }
+
     #[test]
     fn desugar_test_for_to_while() {
         let program = "var r=0;
@@ -540,6 +641,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn desugar_simple_constructor() {
+        okay(
+            "
+            function Point(x, y) {
+                this.x = x;
+                this.y = y;
+            }
+            Point.prototype.sum = function() {
+                return this.x + this.y;
+            };
+            var p = new Point(1, 2);
+            p.sum();",
+        );
+    }
+
     #[test]
     fn test_desugar_bracket_str() {
         okay(
@@ -549,4 +666,240 @@ mod test {
             o["field"];"#,
         );
     }
+
+    #[test]
+    fn desugar_compound_assign_evaluates_lvalue_once() {
+        okay(
+            r#"
+            var log = "";
+            var o = { x: 1 };
+            function obj() {
+                log = log + "o";
+                return o;
+            }
+            function key() {
+                log = log + "k";
+                return "x";
+            }
+            function val() {
+                log = log + "v";
+                o.x = 10;
+                return 1;
+            }
+            obj()[key()] += val();
+            obj().x *= 3;
+            log + ":" + o.x;"#,
+        );
+    }
+
+    #[test]
+    fn desugar_updates_of_strings_and_fields() {
+        okay(
+            r#"
+            var i = 0;
+            var a = ["5", "7"];
+            function idx() {
+                i = i + 1;
+                return i - 1;
+            }
+            var old = a[idx()]++;
+            var pre = --a[idx()];
+            [i, old, pre, a[0], a[1]].join(",");"#,
+        );
+    }
+
+    #[test]
+    fn desugar_updates_in_conditionals() {
+        okay(
+            r#"
+            var x = 0;
+            var y = 0;
+            var z = 0;
+            var t = false && x++;
+            var u = true || ++y;
+            var v = x > 0 ? z-- : z++;
+            [t, u, v, x, y, z].join(",");"#,
+        );
+    }
+
+    #[test]
+    fn desugar_seq_in_operands() {
+        okay(
+            r#"
+            var log = "";
+            function f(x) {
+                log = log + x;
+                return x;
+            }
+            var x = 1;
+            var a = f("a") + (f("b"), f("c"));
+            var b = x + (x = 10, x);
+            var c = [f("d"), (f("e"), f("f"))];
+            f((f("g"), "h"));
+            log + ":" + a + ":" + b + ":" + c;"#,
+        );
+    }
+
+    #[test]
+    fn desugar_seq_in_conditionals() {
+        okay(
+            r#"
+            var log = "";
+            function f(x) {
+                log = log + x;
+                return x;
+            }
+            var a = false && (f("a"), f("b"));
+            var b = true || (f("c"), 1);
+            var c = f("") ? (f("d"), 1) : (f("e"), 2);
+            var d = (f("f"), true) && (f("g"), false || (f("h"), 3));
+            log + ":" + a + ":" + b + ":" + c + ":" + d;"#,
+        );
+    }
+
+    #[test]
+    fn desugar_seq_in_loops() {
+        okay(
+            r#"
+            var r = "";
+            for (var i = 0, j = 5; (r = r + i, i < j); i++, j--) {
+                if (i == 1) {
+                    continue;
+                }
+                r = r + j;
+            }
+            r;"#,
+        );
+    }
+
+    #[test]
+    fn default_arguments() {
+        okay(
+            r#"
+            function f(a, b = a + 1, [c, d] = [b, 10]) {
+                return a + ":" + b + ":" + c + ":" + d;
+            }
+            f(1) + " " + f(1, undefined, [2, 3]) + " " + f(1, null);"#,
+        );
+    }
+
+    #[test]
+    fn array_spread() {
+        okay(
+            r#"
+            var xs = [1, [2]];
+            var ys = [0, ...xs, 3, ...xs, ...[], 4];
+            var zs = [...xs];
+            zs.push(5);
+            ys.length + ":" + xs.length + ":" + ys[2][0] + ":" + ys[6];"#,
+        );
+    }
+
+    #[test]
+    fn classes() {
+        okay(
+            r#"
+            class Shape {
+                constructor(name) {
+                    this.name = name;
+                }
+                describe() {
+                    return this.name + " with area " + this.area();
+                }
+                static unit() {
+                    return new Square(1);
+                }
+            }
+            class Square extends Shape {
+                constructor(side) {
+                    super("square");
+                    this.side = side;
+                }
+                area() {
+                    return this.side * this.side;
+                }
+                describe() {
+                    return "a " + super.describe();
+                }
+            }
+            var s = new Square(3);
+            s.describe() + ":" + Shape.unit().area() + ":" + (s instanceof Shape);"#,
+        );
+    }
+
+    #[test]
+    fn generators() {
+        okay(
+            r#"
+            function* evens(limit) {
+                var n = 0;
+                outer: while (true) {
+                    if (n > limit) {
+                        return;
+                    }
+                    for (var i = 0; i < 3; i++) {
+                        if (n % 2 === 0) {
+                            yield n;
+                            n++;
+                            continue outer;
+                        }
+                    }
+                    n++;
+                }
+            }
+            var it = evens(7);
+            var r = it.next();
+            var log = "";
+            while (!r.done) {
+                log = log + r.value + ",";
+                r = it.next();
+            }
+            log + it.next().done;"#,
+        );
+    }
+
+    #[test]
+    fn generators_with_for_of_destructuring_and_classes() {
+        okay(
+            r#"
+            function* pairs(xs, ...ys) {
+                for (var pair of xs) {
+                    var [a, b] = pair;
+                    class Pair {
+                        constructor(a, b) {
+                            this.sum = a + b;
+                        }
+                    }
+                    yield new Pair(a, b).sum;
+                }
+                yield ys.length;
+            }
+            var it = pairs([[1, 2], [3, 4]], 5, 6);
+            [it.next().value, it.next().value, it.next().value, it.next().done];"#,
+        );
+    }
+
+    #[test]
+    fn for_of() {
+        okay(
+            r#"
+            function concat(xs) {
+                var s = "";
+                for (var x of xs) {
+                    if (x === "b") {
+                        continue;
+                    }
+                    s = s + x;
+                }
+                return s;
+            }
+            var n = 0;
+            for (n of [1, 2, 3]) {
+                if (n === 2) {
+                    break;
+                }
+            }
+            concat("abc") + concat([1, 2, 3]) + n;"#,
+        );
+    }
 }
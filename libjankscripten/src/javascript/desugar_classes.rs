@@ -0,0 +1,262 @@
+//! Turns class declarations into constructor functions and assignments to their prototypes:
+//!
+//! ```text
+//! class C extends S {             var C = function (x) {
+//!     constructor(x) {                S.call(this, x);
+//!         super(x);                   this.x = x;
+//!         this.x = x;             };
+//!     }                      =>   C.prototype = Object.create(S.prototype);
+//!     m() {                       C.prototype.constructor = C;
+//!         return super.m();       C.prototype.m = function () {
+//!     }                               return S.prototype.m.call(this);
+//!     static s() { }              };
+//! }                               C.s = function () { };
+//! ```
+//!
+//! `super(args)` calls the superclass with the `this` of the constructor, and `super.m` is the
+//! field of the prototype of the superclass, or of the superclass itself in static methods. Without
+//! a superclass, `super` refers to `Object`. A class without a constructor gets the default one,
+//! which is `constructor() { }`, or `constructor(...args) { super(...args); }` in a derived class.
+//!
+//! The class is bound where it is declared, like `let`, which the compiler binds like `var`. So
+//! unlike a function statement, the class does not exist before its declaration runs.
+use super::constructors::*;
+use super::syntax::*;
+use super::*;
+
+/// Replaces `super` in the body of a constructor or method. `super(args)` calls `super_class`,
+/// and `super.m` is the field of `home`, which is the prototype of the superclass, or the
+/// superclass itself in static methods.
+struct ResolveSuper {
+    super_class: Expr,
+    home: Expr,
+}
+
+fn is_super(e: &Expr) -> bool {
+    match e {
+        Expr::Id(Id::Named(x), _) => x == "super",
+        _ => false,
+    }
+}
+
+impl ResolveSuper {
+    /// The function that a call of `f` calls with the `this` of the caller, if `f` is `super`,
+    /// `super.x`, or `super[e]`.
+    fn callee(&self, f: &mut Expr) -> Option<Expr> {
+        match f {
+            f if is_super(f) => Some(self.super_class.clone()),
+            Expr::Dot(obj, x, s) if is_super(obj) => {
+                Some(dot_(self.home.clone(), x.clone(), s.clone()))
+            }
+            Expr::Bracket(obj, e, s) if is_super(obj) => {
+                Some(bracket_(self.home.clone(), e.take(), s.clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Visitor for ResolveSuper {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Call(f, args, s) => {
+                if let Some(f) = self.callee(f) {
+                    let args = std::mem::replace(args, vec![]);
+                    let s = s.clone();
+                    *expr = Expr::CallThis(Box::new(f), Box::new(Expr::This), args, s);
+                }
+            }
+            // `super(...xs)` is `jnks_apply(S, this, xs)` (see desugar_this).
+            Expr::Apply(f, args, s) => {
+                if let Some(f) = self.callee(f) {
+                    let args = vec![f, Expr::This, args.take()];
+                    *expr = call_(id_("jnks_apply", s.clone()), args, s.clone());
+                }
+            }
+            Expr::Dot(obj, _, _) | Expr::Bracket(obj, _, _) if is_super(obj) => {
+                **obj = self.home.clone();
+            }
+            _ => (),
+        }
+    }
+}
+
+struct DesugarClasses<'a> {
+    ng: &'a mut NameGen,
+}
+
+impl DesugarClasses<'_> {
+    fn desugar(&mut self, class: Class, s: Pos) -> Stmt {
+        let Class {
+            name,
+            super_class,
+            constructor,
+            methods,
+        } = class;
+        let class = id_(name.clone(), s.clone());
+        let prototype = |e: Expr| dot_(e, "prototype", s.clone());
+        let super_class_or_object = match &super_class {
+            Some(super_class) => (**super_class).clone(),
+            None => id_("Object", s.clone()),
+        };
+        let mut constructor = match (constructor, &super_class) {
+            (Some(constructor), _) => *constructor,
+            (None, None) => {
+                expr_func_::<Id, Id>(None, vec![], Stmt::Block(vec![], s.clone()), s.clone())
+            }
+            // `constructor(...args) { super(...args); }`, which desugar_params lowers
+            (None, Some(_)) => {
+                let args = self.ng.fresh("args");
+                let body = vec![
+                    Stmt::RestParam(args.clone(), s.clone()),
+                    expr_(
+                        Expr::Apply(
+                            Box::new(id_("super", s.clone())),
+                            Box::new(id_(args.clone(), s.clone())),
+                            s.clone(),
+                        ),
+                        s.clone(),
+                    ),
+                ];
+                expr_func_(
+                    None::<Id>,
+                    vec![args],
+                    Stmt::Block(body, s.clone()),
+                    s.clone(),
+                )
+            }
+        };
+        constructor.walk(&mut ResolveSuper {
+            super_class: super_class_or_object.clone(),
+            home: prototype(super_class_or_object.clone()),
+        });
+        let mut stmts = vec![vardecl1_(name, constructor, s.clone())];
+        if let Some(super_class) = super_class {
+            let proto = call_(
+                dot_(id_("Object", s.clone()), "create", s.clone()),
+                vec![prototype(*super_class)],
+                s.clone(),
+            );
+            stmts.push(expr_(
+                assign_(
+                    lval_dot_(class.clone(), "prototype", s.clone()),
+                    proto,
+                    s.clone(),
+                ),
+                s.clone(),
+            ));
+            stmts.push(expr_(
+                assign_(
+                    lval_dot_(prototype(class.clone()), "constructor", s.clone()),
+                    class.clone(),
+                    s.clone(),
+                ),
+                s.clone(),
+            ));
+        }
+        for Method {
+            is_static,
+            key,
+            mut func,
+        } in methods
+        {
+            let (target, home) = if is_static {
+                (class.clone(), super_class_or_object.clone())
+            } else {
+                (
+                    prototype(class.clone()),
+                    prototype(super_class_or_object.clone()),
+                )
+            };
+            func.walk(&mut ResolveSuper {
+                super_class: super_class_or_object.clone(),
+                home,
+            });
+            let lv = match key {
+                Key::Str(x) => lval_dot_(target, x, s.clone()),
+                Key::Int(n) => LValue::Bracket(target, int_(n, s.clone())),
+            };
+            stmts.push(expr_(assign_(lv, func, s.clone()), s.clone()));
+        }
+        Stmt::Block(stmts, s)
+    }
+}
+
+impl Visitor for DesugarClasses<'_> {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Class(..) = stmt {
+            if let Stmt::Class(class, s) = stmt.take() {
+                *stmt = self.desugar(*class, s);
+            }
+        }
+    }
+}
+
+pub fn desugar_classes(program: &mut Stmt, ng: &mut NameGen) {
+    program.walk(&mut DesugarClasses { ng });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::javascript::testing::desugar_okay;
+
+    fn okay(script: &str) {
+        desugar_okay(script, desugar_classes);
+    }
+
+    #[test]
+    fn base_classes() {
+        okay(
+            r#"
+            class Point {
+                constructor(x, y) {
+                    this.x = x;
+                    this.y = y;
+                }
+                norm() {
+                    return this.x * this.x + this.y * this.y;
+                }
+                static origin() {
+                    return new Point(0, 0);
+                }
+            }
+            class Empty { }
+            var p = new Point(3, 4);
+            [p.norm(), Point.origin().norm(), new Empty() instanceof Empty]
+            "#,
+        );
+    }
+
+    #[test]
+    fn super_calls() {
+        okay(
+            r#"
+            class Shape {
+                constructor(name) {
+                    this.name = name;
+                }
+                describe() {
+                    return "a " + this.name;
+                }
+                static kind() {
+                    return "shape";
+                }
+            }
+            class Square extends Shape {
+                constructor(side) {
+                    super("square");
+                    this.side = side;
+                }
+                describe() {
+                    return super.describe() + " of side " + this.side;
+                }
+                static kind() {
+                    return "square " + super.kind();
+                }
+            }
+            [new Square(2).describe(), Square.kind(), new Square(1) instanceof Shape]
+            "#,
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Turns destructuring declarations into declarations of their variables.
+//!
+//! `var [a, {x, y: z}] = e;` becomes:
+//!
+//! ```text
+//! var tmp1 = e, a = tmp1[0], tmp2 = tmp1[1], x = tmp2["x"], z = tmp2["y"];
+//! ```
+//!
+//! The parser turns patterns in function arguments into declarations at the
+//! start of the body, so this pass handles them too.
+
+use super::constructors::*;
+use super::syntax::*;
+use super::*;
+
+struct DesugarDestructuring<'a> {
+    ng: &'a mut NameGen,
+}
+
+impl DesugarDestructuring<'_> {
+    /// Declares the variables of `pattern`, which destructures `e`.
+    fn destructure(&mut self, pattern: Pattern, e: Expr, s: &Pos, decls: &mut Vec<VarDecl>) {
+        match pattern {
+            Pattern::Id(x) => decls.push(VarDecl {
+                name: x,
+                named: Box::new(e),
+            }),
+            Pattern::Array(elems) => {
+                let tmp = self.name(e, decls);
+                for (i, elem) in elems.into_iter().enumerate() {
+                    if let Some(elem) = elem {
+                        let e = bracket_(
+                            id_(tmp.clone(), s.clone()),
+                            int_(i as i32, s.clone()),
+                            s.clone(),
+                        );
+                        self.destructure(elem, e, s, decls);
+                    }
+                }
+            }
+            Pattern::Object(props) => {
+                let tmp = self.name(e, decls);
+                for (key, pat) in props {
+                    let key = match key {
+                        Key::Int(n) => int_(n, s.clone()),
+                        Key::Str(x) => str_(x, s.clone()),
+                    };
+                    let e = bracket_(id_(tmp.clone(), s.clone()), key, s.clone());
+                    self.destructure(pat, e, s, decls);
+                }
+            }
+        }
+    }
+
+    /// A variable that holds `e`. We do not reuse variables of the program, since the
+    /// declarations may assign to them, e.g., `var {x, y} = x;`.
+    fn name(&mut self, e: Expr, decls: &mut Vec<VarDecl>) -> Id {
+        match e {
+            Expr::Id(x @ Id::Generated(..), _) => x,
+            e => {
+                let tmp = self.ng.fresh("destructure");
+                decls.push(VarDecl {
+                    name: tmp.clone(),
+                    named: Box::new(e),
+                });
+                tmp
+            }
+        }
+    }
+}
+
+impl Visitor for DesugarDestructuring<'_> {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Destructure(pattern, e, s) = stmt {
+            let pattern = std::mem::replace(pattern, Pattern::Array(vec![]));
+            let mut decls = Vec::new();
+            self.destructure(pattern, e.take(), s, &mut decls);
+            *stmt = Stmt::VarDecl(decls, s.clone());
+        }
+    }
+}
+
+pub fn desugar_destructuring(program: &mut Stmt, ng: &mut NameGen) {
+    let mut v = DesugarDestructuring { ng };
+    program.walk(&mut v);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::javascript::testing::desugar_okay;
+
+    fn okay(script: &str) {
+        desugar_okay(script, desugar_destructuring);
+    }
+
+    #[test]
+    fn array_pattern() {
+        okay("var arr = [1, 2, 3]; var [a, , c] = arr; a + c");
+    }
+
+    #[test]
+    fn object_pattern() {
+        okay(r#"var {x, y: z, "w": w} = {x: 1, y: 2, w: 3}; x * 100 + z * 10 + w"#);
+    }
+
+    #[test]
+    fn nested_pattern_reuses_own_temporaries() {
+        okay("var [a, {x, y: [b, c]}] = [1, {x: 2, y: [3, 4]}]; [a, x, b, c]");
+    }
+
+    #[test]
+    fn pattern_reads_variable_it_declares() {
+        okay("var x = {x: 1, y: 2}; var {x, y} = x; x + y");
+    }
+
+    #[test]
+    fn mixed_declarators() {
+        okay("var i = 1, [a, b] = [i, i + 1], j = a + b; j");
+    }
+
+    #[test]
+    fn argument_patterns() {
+        okay(
+            r#"
+            function f([a, b], {x}, y) {
+                return a + b + x + y;
+            }
+            f([1, 2], {x: 3}, 4)
+            "#,
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Turns for..of loops into for loops.
+//!
+//! When the collection is an array or string literal, the loop indexes it:
+//!
+//! ```text
+//! for (var coll = e, i = 0; i < coll.length; i = i + 1) { x = coll[i]; body }
+//! ```
+//!
+//! Otherwise, the loop checks the collection at runtime. A string is indexed with `charAt`,
+//! anything else with a length is indexed like an array, and anything without one is an
+//! iterator (e.g., the object that a generator returns), which the loop steps with `next()`
+//! until it is `done`:
+//!
+//! ```text
+//! for (var coll = e, i = 0; true; i = i + 1) {
+//!   if (typeof coll === "string") { if (!(i < coll.length)) { break; } x = coll.charAt(i); }
+//!   else if (coll.length !== undefined) { if (!(i < coll.length)) { break; } x = coll[i]; }
+//!   else { var r = coll.next(); if (r.done) { break; } x = r.value; }
+//!   body
+//! }
+//! ```
+//!
+//! There are no symbols, so an iterator is its own iterable. The result is a single loop, so a
+//! label on the for..of labels it too.
+
+use super::constructors::*;
+use super::syntax::*;
+use super::*;
+
+struct DesugarForOf<'a> {
+    ng: &'a mut NameGen,
+}
+
+/// How to get the elements of a collection.
+enum Elements {
+    String,
+    Array,
+    Dynamic,
+}
+
+impl DesugarForOf<'_> {
+    fn desugar(&mut self, is_var: bool, x: Id, coll: Expr, body: Stmt, s: Pos) -> Stmt {
+        let elements = match &coll {
+            Expr::Lit(Lit::String(..), _) => Elements::String,
+            Expr::Array(..) => Elements::Array,
+            _ => Elements::Dynamic,
+        };
+        let c = self.ng.fresh("coll");
+        let i = self.ng.fresh("index");
+        let coll_ = || id_(c.clone(), s.clone());
+        let index_ = || id_(i.clone(), s.clone());
+        // `x = e`, or `var x = e`, which lift_vars hoists
+        let bind = |e: Expr| {
+            if is_var {
+                vardecl1_(x.clone(), e, s.clone())
+            } else {
+                expr_(assign_(x.clone(), e, s.clone()), s.clone())
+            }
+        };
+        let in_bounds = || {
+            binary_(
+                BinOp::BinaryOp(BinaryOp::LessThan),
+                index_(),
+                dot_(coll_(), "length", s.clone()),
+                s.clone(),
+            )
+        };
+        let exit_if = |cond: Expr| {
+            if_(
+                cond,
+                Stmt::Block(vec![break_::<Id>(None, s.clone())], s.clone()),
+                Stmt::Empty,
+                s.clone(),
+            )
+        };
+        let exit_unless = |cond: Expr| exit_if(not_(cond, s.clone()));
+        let char_at = || {
+            call_(
+                dot_(coll_(), "charAt", s.clone()),
+                vec![index_()],
+                s.clone(),
+            )
+        };
+        let (cond, next) = match elements {
+            Elements::String => (in_bounds(), bind(char_at())),
+            Elements::Array => (in_bounds(), bind(bracket_(coll_(), index_(), s.clone()))),
+            Elements::Dynamic => {
+                let r = self.ng.fresh("next");
+                let is_string = binary_(
+                    BinOp::BinaryOp(BinaryOp::StrictEqual),
+                    unary_(UnaryOp::TypeOf, coll_(), s.clone()),
+                    str_("string", s.clone()),
+                    s.clone(),
+                );
+                let has_length = binary_(
+                    BinOp::BinaryOp(BinaryOp::StrictNotEqual),
+                    dot_(coll_(), "length", s.clone()),
+                    UNDEFINED_,
+                    s.clone(),
+                );
+                let step = Stmt::Block(
+                    vec![
+                        vardecl1_(
+                            r.clone(),
+                            call_(dot_(coll_(), "next", s.clone()), vec![], s.clone()),
+                            s.clone(),
+                        ),
+                        exit_if(dot_(id_(r.clone(), s.clone()), "done", s.clone())),
+                        bind(dot_(id_(r, s.clone()), "value", s.clone())),
+                    ],
+                    s.clone(),
+                );
+                let next = if_(
+                    is_string,
+                    Stmt::Block(vec![exit_unless(in_bounds()), bind(char_at())], s.clone()),
+                    if_(
+                        has_length,
+                        Stmt::Block(
+                            vec![
+                                exit_unless(in_bounds()),
+                                bind(bracket_(coll_(), index_(), s.clone())),
+                            ],
+                            s.clone(),
+                        ),
+                        step,
+                        s.clone(),
+                    ),
+                    s.clone(),
+                );
+                (TRUE_, next)
+            }
+        };
+        for_(
+            ForInit::Decl(vec![
+                VarDecl {
+                    name: c.clone(),
+                    named: Box::new(coll),
+                },
+                VarDecl {
+                    name: i.clone(),
+                    named: Box::new(int_(0, s.clone())),
+                },
+            ]),
+            cond,
+            assign_(
+                i.clone(),
+                binary_(
+                    BinOp::BinaryOp(BinaryOp::Plus),
+                    index_(),
+                    int_(1, s.clone()),
+                    s.clone(),
+                ),
+                s.clone(),
+            ),
+            Stmt::Block(vec![next, body], s.clone()),
+            s.clone(),
+        )
+    }
+}
+
+impl Visitor for DesugarForOf<'_> {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::ForOf(is_var, x, coll, body, s) = stmt {
+            *stmt = self.desugar(*is_var, x.clone(), coll.take(), body.take(), s.clone());
+        }
+    }
+}
+
+pub fn desugar_for_of(program: &mut Stmt, ng: &mut NameGen) {
+    let mut v = DesugarForOf { ng };
+    program.walk(&mut v);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::javascript::testing::desugar_okay;
+
+    fn okay(script: &str) {
+        desugar_okay(script, desugar_for_of);
+    }
+
+    #[test]
+    fn array_literal() {
+        okay("var sum = 0; for (var x of [1, 2, 3]) { sum = sum + x; } sum");
+    }
+
+    #[test]
+    fn string_literal() {
+        okay(r#"var s = ""; for (var c of "abc") { s = c + s; } s"#);
+    }
+
+    #[test]
+    fn dynamic_collections() {
+        okay(
+            r#"
+            function sum(xs) {
+                var total = 0, x;
+                for (x of xs) {
+                    total = total + x;
+                }
+                return total;
+            }
+            [sum([1, 2, 3]), sum("12")]
+            "#,
+        );
+    }
+
+    #[test]
+    fn break_and_continue() {
+        okay(
+            r#"
+            var xs = [];
+            outer: for (var x of [1, 2, 3, 4, 5]) {
+                for (var y of "ab") {
+                    if (x === 2) { continue outer; }
+                    if (x === 4) { break outer; }
+                    xs.push(y + x);
+                }
+            }
+            xs
+            "#,
+        );
+    }
+}
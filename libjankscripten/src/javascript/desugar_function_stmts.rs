@@ -4,13 +4,14 @@ use crate::pos::Pos;
 
 pub struct DesugarFunctionStmts {}
 
-/// If `stmt` is a `Stmt::Func`, replace it with empty and return the functino's name, arguments,
+/// If `stmt` is a `Stmt::Func`, replace it with empty and return the function's name, arguments,
 /// and body.
 fn take_fun_stmt(stmt: &mut Stmt) -> Option<(Id, Vec<Id>, Box<Stmt>, Pos)> {
     if let Stmt::Func(..) = stmt {
         let fun_stmt = stmt.take();
         match fun_stmt {
-            Stmt::Func(name, args, body, s) => {
+            // generators run first, so the function is not a generator
+            Stmt::Func(name, args, body, _, s) => {
                 return Some((name, args, body, s));
             }
             _ => unreachable!(),
@@ -22,10 +23,15 @@ fn take_fun_stmt(stmt: &mut Stmt) -> Option<(Id, Vec<Id>, Box<Stmt>, Pos)> {
 impl Visitor for DesugarFunctionStmts {
     fn exit_stmt(&mut self, stmt: &mut Stmt, loc: &Loc) {
         if let Some((name, args, body, s)) = take_fun_stmt(stmt) {
-            let named = Box::new(Expr::Func(None, args, body, s.clone()));
-            let block_cxt = loc.body_of_enclosing_function_or_program();
+            let named = Box::new(Expr::Func(None, args, body, FuncKind::Plain, s.clone()));
             // Insert `var name = function(args ...) { body ... }` at the top of the block that
-            // defines the innermost enclosing function.
+            // defines the function. A declaration at the top of a function body is hoisted to the
+            // top of the function. Following Annex B of the specification, a declaration in a
+            // nested block is hoisted to the top of that block, and the variable is `undefined`
+            // before the block runs.
+            let block_cxt = loc
+                .enclosing_block()
+                .expect("function declaration outside of a block");
             block_cxt.insert(0, Stmt::VarDecl(vec![VarDecl { name, named }], s.clone()));
         }
     }
@@ -1,8 +1,21 @@
 //! desugar &&, ||, ?:, and , (seq)
+//!
+//! Each of these becomes statements that are inserted before the statement that
+//! contains the expression. To preserve evaluation order, the operands to the
+//! left of a desugared expression are named first, e.g.:
+//!
+//! ```text
+//! f() + (g(), h())    =>    var operand = f(); g(); operand + h()
+//! ```
+//!
+//! The right operand of && and ||, and the branches of ?:, are desugared into
+//! the blocks of the `if` that replaces them, so that their effects only happen
+//! when they are evaluated.
 
 use super::constructors::*;
 use super::syntax::LogicalOp;
 use super::*;
+use crate::pos::Pos;
 
 /// desugar &&, ||, ?:, and , (seq)
 pub fn desugar_logical(stmt: &mut Stmt, ng: &mut NameGen) {
@@ -12,47 +25,183 @@ pub fn desugar_logical(stmt: &mut Stmt, ng: &mut NameGen) {
 /// visitor for all logical desugars
 struct DesugarLogical<'a>(&'a mut NameGen);
 impl Visitor for DesugarLogical<'_> {
-    fn exit_expr(&mut self, expr: &mut Expr, loc: &Loc) {
-        let ctx = loc.enclosing_block().expect("expected block context");
+    fn exit_stmt(&mut self, stmt: &mut Stmt, loc: &Loc) {
+        let mut before = vec![];
+        match stmt {
+            Stmt::Expr(e, _)
+            | Stmt::Return(e, _)
+            | Stmt::Throw(e, _)
+            | Stmt::If(e, _, _, _)
+            | Stmt::ForIn(_, _, e, _, _) => self.desugar(e, &mut before),
+            Stmt::VarDecl(decls, _) => {
+                for decl in decls {
+                    self.desugar(&mut decl.named, &mut before);
+                }
+            }
+            // The conditions of loops are `true` after desugar_loops, and
+            // switch has been desugared
+            _ => (),
+        }
+        if !before.is_empty() {
+            let ctx = loc.enclosing_block().expect("expected block context");
+            for s in before {
+                ctx.insert(ctx.index, s);
+            }
+        }
+    }
+}
+
+impl DesugarLogical<'_> {
+    /// Desugar `expr`, pushing the statements that must run before it onto `before`,
+    /// in order. The bodies of functions are statements that the visitor desugars on its
+    /// own.
+    fn desugar(&mut self, expr: &mut Expr, before: &mut Vec<Stmt>) {
         match expr {
-            Expr::Binary(BinOp::LogicalOp(op), left, right, ref s) => {
+            Expr::Binary(BinOp::LogicalOp(op), left, right, s) => {
+                let s = s.clone();
+                self.desugar(left, before);
                 let left_name = self.0.fresh("left");
-                let (cons, alt, op_name) = match op {
-                    LogicalOp::And => (right.take(), id_(left_name.clone(), s.clone()), "and"),
-                    LogicalOp::Or => (id_(left_name.clone(), s.clone()), right.take(), "or"),
+                let mut right_before = vec![];
+                self.desugar(right, &mut right_before);
+                let (cons_before, cons, alt_before, alt, op_name) = match op {
+                    LogicalOp::And => (
+                        right_before,
+                        right.take(),
+                        vec![],
+                        id_(left_name.clone(), s.clone()),
+                        "and",
+                    ),
+                    LogicalOp::Or => (
+                        vec![],
+                        id_(left_name.clone(), s.clone()),
+                        right_before,
+                        right.take(),
+                        "or",
+                    ),
                 };
                 let result = self.0.fresh(op_name);
                 let if_stmt = if_(
                     id_(left_name.clone(), s.clone()),
-                    expr_(assign_(result.clone(), cons, s.clone()), s.clone()),
-                    expr_(assign_(result.clone(), alt, s.clone()), s.clone()),
+                    assign_block(cons_before, result.clone(), cons, s.clone()),
+                    assign_block(alt_before, result.clone(), alt, s.clone()),
                     s.clone(),
                 );
-                ctx.insert(ctx.index, vardecl1_(result.clone(), UNDEFINED_, s.clone()));
-                ctx.insert(ctx.index, vardecl1_(left_name, left.take(), s.clone()));
-                ctx.insert(ctx.index, if_stmt);
-                *expr = id_(result, s.clone());
+                before.push(vardecl1_(result.clone(), UNDEFINED_, s.clone()));
+                before.push(vardecl1_(left_name, left.take(), s.clone()));
+                before.push(if_stmt);
+                *expr = id_(result, s);
             }
             Expr::If(cond, cons, alt, s) => {
+                let s = s.clone();
+                self.desugar(cond, before);
+                let mut cons_before = vec![];
+                self.desugar(cons, &mut cons_before);
+                let mut alt_before = vec![];
+                self.desugar(alt, &mut alt_before);
                 let result = self.0.fresh("if_expr");
-                ctx.insert(ctx.index, vardecl1_(result.clone(), UNDEFINED_, s.clone()));
+                before.push(vardecl1_(result.clone(), UNDEFINED_, s.clone()));
                 let if_stmt = if_(
                     cond.take(),
-                    expr_(assign_(result.clone(), cons.take(), s.clone()), s.clone()),
-                    expr_(assign_(result.clone(), alt.take(), s.clone()), s.clone()),
+                    assign_block(cons_before, result.clone(), cons.take(), s.clone()),
+                    assign_block(alt_before, result.clone(), alt.take(), s.clone()),
                     s.clone(),
                 );
-                ctx.insert(ctx.index, if_stmt);
-                *expr = id_(result, s.clone());
+                before.push(if_stmt);
+                *expr = id_(result, s);
             }
             Expr::Seq(es, s) => {
-                let last = es.pop().expect("sequence with no exprs");
+                let mut last = es.pop().expect("sequence with no exprs");
                 for e in es {
-                    ctx.insert(ctx.index, expr_(e.take(), s.clone()));
+                    self.desugar(e, before);
+                    before.push(expr_(e.take(), s.clone()));
                 }
+                self.desugar(&mut last, before);
                 *expr = last;
             }
-            _ => (),
+            // Desugared by the visitor
+            Expr::Func(..) => (),
+            _ => {
+                // The operands, in the order that they are evaluated. When an operand
+                // needs statements, the operands to its left are named first.
+                let mut operands = operands_mut(expr);
+                let mut named = 0;
+                for i in 0..operands.len() {
+                    let mut operand_before = vec![];
+                    self.desugar(operands[i], &mut operand_before);
+                    if !operand_before.is_empty() {
+                        for operand in &mut operands[named..i] {
+                            self.name_operand(operand, before);
+                        }
+                        named = i;
+                        before.extend(operand_before);
+                    }
+                }
+            }
+        }
+    }
+
+    fn name_operand(&mut self, operand: &mut Expr, before: &mut Vec<Stmt>) {
+        match operand {
+            // These can't be changed by the statements that follow.
+            Expr::Lit(..) | Expr::This | Expr::Func(..) | Expr::Id(Id::Generated(_), _) => (),
+            _ => {
+                let name = self.0.fresh("operand");
+                before.push(vardecl1_(name.clone(), operand.take(), Pos::UNKNOWN));
+                *operand = id_(name, Pos::UNKNOWN);
+            }
+        }
+    }
+}
+
+/// The subexpressions of `expr`, in the order that they are evaluated. The object of a method
+/// call stands in for its callee, so that the call remains a method call.
+fn operands_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    fn lval_operands_mut(lv: &mut LValue) -> Vec<&mut Expr> {
+        match lv {
+            LValue::Id(_) => vec![],
+            LValue::Dot(e, _) => vec![&mut **e],
+            LValue::Bracket(e1, e2) => vec![&mut **e1, &mut **e2],
+        }
+    }
+    match expr {
+        Expr::Lit(..) | Expr::This | Expr::Id(..) | Expr::Func(..) => vec![],
+        Expr::Array(es, _) | Expr::Seq(es, _) => es.iter_mut().collect(),
+        Expr::Object(props, _) => props.iter_mut().map(|(_, e)| e).collect(),
+        Expr::Dot(e, _, _) | Expr::Unary(_, e, _) | Expr::Length(e, _) | Expr::Await(e, _) => {
+            vec![&mut **e]
+        }
+        Expr::Yield(e, _) => e.iter_mut().map(|e| &mut **e).collect(),
+        Expr::Bracket(e1, e2, _) | Expr::Binary(_, e1, e2, _) | Expr::Apply(e1, e2, _) => {
+            vec![&mut **e1, &mut **e2]
+        }
+        Expr::If(e1, e2, e3, _) => vec![&mut **e1, &mut **e2, &mut **e3],
+        Expr::UnaryAssign(_, lv, _) => lval_operands_mut(lv),
+        Expr::Assign(_, lv, e, _) => {
+            let mut operands = lval_operands_mut(lv);
+            operands.push(&mut **e);
+            operands
+        }
+        Expr::Call(f, args, _) | Expr::New(f, args, _) => {
+            let mut operands = match &mut **f {
+                Expr::Dot(obj, _, _) => vec![&mut **obj],
+                Expr::Bracket(obj, field, _) => vec![&mut **obj, &mut **field],
+                f => vec![f],
+            };
+            operands.extend(args.iter_mut());
+            operands
         }
+        Expr::CallThis(f, this, args, _) => {
+            let mut operands = vec![&mut **f, &mut **this];
+            operands.extend(args.iter_mut());
+            operands
+        }
+        Expr::MethodCall(_, _, args, _) => args.iter_mut().collect(),
     }
 }
+
+/// `{ before; x = e; }`, as a block so that later passes that name the subexpressions
+/// of `e` insert their statements into the branch and not before the `if`
+fn assign_block(mut before: Vec<Stmt>, x: Id, e: Expr, s: Pos) -> Stmt {
+    before.push(expr_(assign_(x, e, s.clone()), s.clone()));
+    Stmt::Block(before, s)
+}
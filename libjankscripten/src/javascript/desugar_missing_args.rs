@@ -0,0 +1,176 @@
+//! Passes `undefined` for the omitted arguments of calls to declared functions.
+//!
+//! In JavaScript, a call may omit trailing arguments, and the missing parameters are `undefined`,
+//! which also makes default arguments apply. The rest of the compiler expects calls with the arity
+//! of the function, so this pass pads a call `f(a)` of `function f(x, y, z) { ... }` to
+//! `f(a, undefined, undefined)`.
+//!
+//! We only pad calls when we know which function they call: `f` must be declared once, with a
+//! function statement or as `var f = function (...) { ... }`, and nothing else in the program may
+//! bind or assign `f`. We also leave calls
+//! alone when the body of `f` uses `arguments`, since padding would change `arguments.length`.
+use super::constructors::*;
+use super::syntax::*;
+use super::walk::*;
+use std::collections::HashMap;
+
+/// The arity of each declared function, or `None` if we cannot pad calls to it.
+#[derive(Default)]
+struct Arities {
+    arities: HashMap<Id, Option<usize>>,
+}
+
+impl Arities {
+    fn bind(&mut self, x: &Id) {
+        self.arities.insert(x.clone(), None);
+    }
+
+    fn declare(&mut self, f: &Id, arity: usize) {
+        let entry = if self.arities.contains_key(f) {
+            None
+        } else {
+            Some(arity)
+        };
+        self.arities.insert(f.clone(), entry);
+    }
+}
+
+impl Visitor for Arities {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::Func(f, params, body, ..) => {
+                if uses_arguments(body) {
+                    self.bind(f);
+                } else {
+                    self.declare(f, params.len());
+                }
+                params.iter().for_each(|x| self.bind(x));
+            }
+            Stmt::VarDecl(decls, _) => {
+                for decl in decls {
+                    match &mut *decl.named {
+                        Expr::Func(_, params, body, ..) if !uses_arguments(body) => {
+                            self.declare(&decl.name, params.len())
+                        }
+                        _ => self.bind(&decl.name),
+                    }
+                }
+            }
+            Stmt::Catch(_, x, _, _) => self.bind(x),
+            Stmt::ForIn(_, x, _, _, _) => self.bind(x),
+            Stmt::ForOf(_, x, _, _, _) => self.bind(x),
+            _ => (),
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Func(f, params, ..) => {
+                f.iter().for_each(|x| self.bind(x));
+                params.iter().for_each(|x| self.bind(x));
+            }
+            Expr::Assign(_, lv, _, _) | Expr::UnaryAssign(_, lv, _) => {
+                if let LValue::Id(x) = &**lv {
+                    self.bind(x);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Produces `true` if `body` refers to `arguments`, including in nested functions, which is
+/// harmless.
+fn uses_arguments(body: &mut Stmt) -> bool {
+    struct UsesArguments(bool);
+    impl Visitor for UsesArguments {
+        fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+            if let Expr::Id(Id::Named(x), _) = expr {
+                self.0 = self.0 || x == "arguments";
+            }
+        }
+        fn done(&self) -> bool {
+            self.0
+        }
+    }
+    let mut v = UsesArguments(false);
+    body.walk(&mut v);
+    v.0
+}
+
+struct PadArgs {
+    arities: HashMap<Id, usize>,
+}
+
+impl Visitor for PadArgs {
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Call(f, args, _) = expr {
+            if let Expr::Id(f, _) = &**f {
+                if let Some(arity) = self.arities.get(f) {
+                    while args.len() < *arity {
+                        args.push(UNDEFINED_);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The arity of each function that we know the calls `f(...)` to call, by the rules above.
+pub fn declared_functions(program: &mut Stmt) -> HashMap<Id, usize> {
+    let mut v = Arities::default();
+    program.walk(&mut v);
+    v.arities
+        .into_iter()
+        .filter_map(|(f, arity)| arity.map(|arity| (f, arity)))
+        .collect()
+}
+
+pub fn desugar_missing_args(program: &mut Stmt) {
+    let arities = declared_functions(program);
+    program.walk(&mut PadArgs { arities });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::javascript::testing::desugar_okay;
+
+    fn okay(script: &str) {
+        desugar_okay(script, |program, _| desugar_missing_args(program));
+    }
+
+    #[test]
+    fn pads_omitted_arguments() {
+        okay(
+            r#"
+            function f(x, y, z) { return [x, y, z]; }
+            [f(), f(1), f(1, 2), f(1, 2, 3)]
+            "#,
+        );
+    }
+
+    #[test]
+    fn pads_omitted_arguments_of_var_bound_functions() {
+        okay(
+            r#"
+            var f = function(x, y) { return [x, y]; };
+            var g = function(x) { return arguments.length; };
+            [f(), f(1), g()]
+            "#,
+        );
+    }
+
+    #[test]
+    fn leaves_rebound_functions() {
+        okay(
+            r#"
+            function f(x, y) { return [x, y]; }
+            function g(x, y) { return arguments.length; }
+            var r = [f(1), g(1)];
+            f = function(x) { return x; };
+            r.concat([f(2)])
+            "#,
+        );
+    }
+}
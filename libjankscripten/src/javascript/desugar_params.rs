@@ -0,0 +1,299 @@
+//! Desugars default and rest parameters, which the parser leaves at the start of the body of their
+//! function as `DefaultParam` and `RestParam` statements.
+//!
+//! A default parameter becomes a test at the start of the body, so defaults are evaluated in
+//! order, and may refer to earlier parameters:
+//!
+//! ```text
+//! function f(x = e) { ... }    =>    function f(x) { if (x === undefined) { x = e; } ... }
+//! ```
+//!
+//! The rest of the compiler expects calls with the arity of the function, and does not support
+//! `arguments`, so a rest parameter becomes an ordinary parameter, and every call packs its extra
+//! arguments into an array, after it passes `undefined` for the omitted ones:
+//!
+//! ```text
+//! function f(x, ...ys) { ... }        function f(x, ys) { ... }
+//! f();                           =>   f(undefined, []);
+//! f(1, 2, 3);                         f(1, [2, 3]);
+//! ```
+//!
+//! So we must know which function each call calls. A function with a rest parameter must be
+//! declared once, with a function statement or as the initializer of a `var`, nothing else may bind
+//! or assign its name, and its body must not use `arguments`. The program may call it by name, with
+//! `f(...)`, `new f(...)`, or `f.call(this, ...)` from desugar_classes, read and assign its
+//! fields, and test `x instanceof f`. A class `f` also stores itself in `f.prototype.constructor`,
+//! so a call through that field does not pack its arguments. The parser rejects rest parameters of
+//! other function expressions, and this pass panics when a program breaks the other rules.
+//!
+//! desugar_classes turns `super(...xs)` into `jnks_apply(f, this, xs)`. When `f` has no parameter
+//! but the rest parameter, that becomes `f.call(this, [].concat(xs))`, which is how the default
+//! constructors of derived classes call one another.
+use super::constructors::*;
+use super::desugar_missing_args::declared_functions;
+use super::syntax::*;
+use super::walk::*;
+use crate::pos::Pos;
+use std::collections::HashMap;
+
+/// Lowers default parameters, and removes rest parameters, which it records with the position of
+/// their function.
+#[derive(Default)]
+struct Params {
+    rest: HashMap<Id, Pos>,
+}
+
+impl Visitor for Params {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::DefaultParam(x, e, s) => {
+                let x = x.clone();
+                let e = std::mem::replace(&mut **e, UNDEFINED_);
+                let s = s.clone();
+                let is_undefined = binary_(
+                    BinOp::BinaryOp(BinaryOp::StrictEqual),
+                    id_(x.clone(), s.clone()),
+                    UNDEFINED_,
+                    s.clone(),
+                );
+                let init = expr_(assign_(x, e, s.clone()), s.clone());
+                *stmt = if_(
+                    is_undefined,
+                    Stmt::Block(vec![init], s.clone()),
+                    Stmt::Empty,
+                    s,
+                );
+            }
+            Stmt::Func(f, _, body, _, s) => {
+                if remove_rest_param(body) {
+                    self.rest.insert(f.clone(), s.clone());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Before exit_expr sees the function expression.
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::VarDecl(decls, _) = stmt {
+            for decl in decls {
+                if let Expr::Func(_, _, body, _, s) = &mut *decl.named {
+                    if remove_rest_param(body) {
+                        self.rest.insert(decl.name.clone(), s.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(_, _, body, _, s) = expr {
+            if remove_rest_param(body) {
+                panic!("rest parameter of a function expression at {}", s);
+            }
+        }
+    }
+}
+
+/// Removes the `RestParam` from the body of a function, and produces `true` if there was one.
+fn remove_rest_param(body: &mut Stmt) -> bool {
+    match body {
+        Stmt::Block(stmts, _) => {
+            let n = stmts.len();
+            stmts.retain(|stmt| !matches!(stmt, Stmt::RestParam(..)));
+            stmts.len() != n
+        }
+        _ => false,
+    }
+}
+
+/// Packs the arguments of calls to the functions with rest parameters, and counts the references
+/// to each function, and the ones that the rules above allow.
+struct PackArgs {
+    arities: HashMap<Id, usize>,
+    allowed: HashMap<Id, usize>,
+    refs: HashMap<Id, usize>,
+}
+
+impl PackArgs {
+    /// The function with a rest parameter that `e` names, if any.
+    fn rest_function(&self, e: &Expr) -> Option<Id> {
+        match e {
+            Expr::Id(f, _) if self.arities.contains_key(f) => Some(f.clone()),
+            _ => None,
+        }
+    }
+
+    fn allow(&mut self, f: Id) {
+        *self.allowed.entry(f).or_insert(0) += 1;
+    }
+
+    fn pack(&mut self, f: Id, args: &mut Vec<Expr>, s: &Pos) {
+        let n = self.arities[&f] - 1;
+        while args.len() < n {
+            args.push(UNDEFINED_);
+        }
+        let rest = args.split_off(n);
+        args.push(Expr::Array(rest, s.clone()));
+        self.allow(f);
+    }
+}
+
+impl Visitor for PackArgs {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Some(f) = self.rest_function(expr) {
+            *self.refs.entry(f).or_insert(0) += 1;
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Call(f, args, s) | Expr::New(f, args, s) | Expr::CallThis(f, _, args, s) => {
+                if let Some(f) = self.rest_function(f) {
+                    self.pack(f, args, s);
+                } else if let (Expr::Id(Id::Named(apply), _), [f, this, xs]) =
+                    (&**f, args.as_mut_slice())
+                {
+                    match self.rest_function(f) {
+                        Some(g) if apply == "jnks_apply" && self.arities[&g] == 1 => {
+                            let copy = call_(
+                                dot_(Expr::Array(vec![], s.clone()), "concat", s.clone()),
+                                vec![xs.take()],
+                                s.clone(),
+                            );
+                            *expr = Expr::CallThis(
+                                Box::new(f.take()),
+                                Box::new(this.take()),
+                                vec![copy],
+                                s.clone(),
+                            );
+                            self.allow(g);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            Expr::Dot(obj, Id::Named(x), _) if x != "call" && x != "apply" && x != "bind" => {
+                if let Some(f) = self.rest_function(obj) {
+                    self.allow(f);
+                }
+            }
+            Expr::Binary(BinOp::BinaryOp(BinaryOp::InstanceOf), _, f, _) => {
+                if let Some(f) = self.rest_function(f) {
+                    self.allow(f);
+                }
+            }
+            Expr::Assign(_, lv, e, _) => {
+                if let LValue::Dot(obj, _) | LValue::Bracket(obj, _) = &**lv {
+                    if let Some(f) = self.rest_function(obj) {
+                        self.allow(f);
+                    }
+                }
+                if let (LValue::Dot(_, Id::Named(x)), Some(f)) = (&**lv, self.rest_function(e)) {
+                    if x == "constructor" {
+                        self.allow(f);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn desugar_params(program: &mut Stmt) {
+    let mut params = Params::default();
+    program.walk(&mut params);
+    if params.rest.is_empty() {
+        return;
+    }
+    let declared = declared_functions(program);
+    let mut arities = HashMap::new();
+    for (f, s) in params.rest {
+        match declared.get(&f) {
+            Some(arity) => {
+                arities.insert(f, *arity);
+            }
+            None => panic!(
+                "the function {} at {} has a rest parameter, but is not only declared once",
+                f, s
+            ),
+        }
+    }
+    let mut v = PackArgs {
+        arities,
+        allowed: HashMap::new(),
+        refs: HashMap::new(),
+    };
+    program.walk(&mut v);
+    for (f, refs) in v.refs {
+        if v.allowed.get(&f) != Some(&refs) {
+            panic!(
+                "the function {} has a rest parameter, so it may only be called by name",
+                f
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::javascript::testing::desugar_okay;
+
+    fn okay(script: &str) {
+        desugar_okay(script, |program, _| desugar_params(program));
+    }
+
+    #[test]
+    fn default_params() {
+        okay(
+            r#"
+            function f(x, y = x + 1, z = y * 2) { return [x, y, z]; }
+            [f(1), f(1, undefined, 5), f(1, 2, 3), f(1, null)]
+            "#,
+        );
+    }
+
+    #[test]
+    fn rest_params() {
+        okay(
+            r#"
+            function f(x, ...ys) { return [x, ys.length, ys]; }
+            function g(...xs) { return xs.length === 0 ? 0 : xs[0] + g(); }
+            [f(), f(1), f(1, 2, 3), g(), g(10, 20)]
+            "#,
+        );
+    }
+
+    #[test]
+    fn default_and_rest_params_with_patterns() {
+        okay(
+            r#"
+            function f([a, b] = [1, 2], c = a + b, ...ds) { return [a, b, c, ds]; }
+            [f(), f([3, 4]), f(undefined, 0, 5, 6)]
+            "#,
+        );
+    }
+
+    #[test]
+    fn rest_params_of_var_bound_functions() {
+        okay(
+            r#"
+            var f = function(...xs) { return xs; };
+            [f(), f(1, 2), new f(3).length, [] instanceof f]
+            "#,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "may only be called by name")]
+    fn rest_params_of_escaping_functions() {
+        okay(
+            r#"
+            function f(...xs) { return xs; }
+            var g = f;
+            g(1, 2)
+            "#,
+        );
+    }
+}
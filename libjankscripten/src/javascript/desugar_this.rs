@@ -1,36 +1,85 @@
+use super::constructor_classes::constructor_classes;
 use super::constructors::*;
 use super::syntax::*;
 use super::*;
+use std::collections::HashMap;
 
 struct ThisParameter<'a> {
     ng: &'a mut NameGen,
     this_name: Id,
+    /// The fields that each simple constructor assigns.
+    constructor_classes: HashMap<Id, Vec<String>>,
+}
+
+impl ThisParameter<'_> {
+    /// The `this` of a call of `f`.
+    fn this_of(&mut self, f: &mut Expr, loc: &Loc) -> Expr {
+        match f {
+            // only syntactically immediate bracket/dot preserves the
+            // object as `this`
+            Expr::Bracket(obj, _, s) | Expr::Dot(obj, _, s) => {
+                // fresh the obj so we can pass it to the method
+                let cxt = loc.enclosing_block().unwrap();
+                let obj_name = self.ng.fresh("obj4this");
+                cxt.insert(
+                    cxt.index,
+                    vardecl1_(obj_name.clone(), obj.take(), s.clone()),
+                );
+                *obj = Box::new(id_(obj_name.clone(), s.clone()));
+                id_(obj_name, s.clone())
+            }
+            // for the rest, we'll hand undefined. all our benchmarks
+            // play nice with "use strict";
+            _ => UNDEFINED_,
+        }
+    }
 }
 
 impl Visitor for ThisParameter<'_> {
     fn exit_expr(&mut self, expr: &mut Expr, loc: &Loc) {
         match expr {
-            Expr::Call(f, args, _) => match &mut **f {
-                // only syntactically immediate bracket/dot preserves the
-                // object as `this`
-                Expr::Bracket(obj, _, s) | Expr::Dot(obj, _, s) => {
-                    // fresh the obj so we can pass it to the method
-                    let cxt = loc.enclosing_block().unwrap();
-                    let obj_name = self.ng.fresh("obj4this");
-                    cxt.insert(
-                        cxt.index,
-                        vardecl1_(obj_name.clone(), obj.take(), s.clone()),
-                    );
-                    *obj = Box::new(id_(obj_name.clone(), s.clone()));
-                    args.insert(0, id_(obj_name, s.clone()));
-                }
-                // for the rest, we'll hand undefined. all our benchmarks
-                // play nice with "use strict";
-                _ => args.insert(0, UNDEFINED_),
-            },
+            Expr::Call(f, args, _) => {
+                let this = self.this_of(f, loc);
+                args.insert(0, this);
+            }
+            Expr::Apply(f, args, s) => {
+                // `f(...args)` is `jnks_apply(f, this, args)`, which the standard library provides,
+                // and which receives undefined as its own `this`, like any other function
+                let this = self.this_of(f, loc);
+                *expr = call_(
+                    id_("jnks_apply", s.clone()),
+                    vec![UNDEFINED_, f.take(), this, args.take()],
+                    s.clone(),
+                );
+            }
+            Expr::CallThis(f, this, args, s) => {
+                // Since functions receive `this` as their first argument, `f.call(this, args...)`
+                // is `f(this, args...)`. We name `f` unless it is a variable, since later passes
+                // treat a call of a field as a method call.
+                let f = match f.take() {
+                    f @ Expr::Id(..) => f,
+                    f => {
+                        let cxt = loc.enclosing_block().unwrap();
+                        let f_name = self.ng.fresh("call_this");
+                        cxt.insert(cxt.index, vardecl1_(f_name.clone(), f, s.clone()));
+                        id_(f_name, s.clone())
+                    }
+                };
+                let mut args = std::mem::replace(args, vec![]);
+                args.insert(0, this.take());
+                *expr = call_(f, args, s.clone());
+            }
             Expr::New(f, args, s) => {
                 // Desugar `new` into:
                 //     new MyFunc(args...) => var $func = MyFunc; var $obj = Object.create($func.prototype), $func.call($obj, args...), $obj
+                //
+                // When MyFunc is a simple constructor that assigns the fields x, y, ..., the object
+                // is instead `{__proto__: $func.prototype, x: undefined, y: undefined, ...}`,
+                // which already has the class that the constructor would give it.
+                let fields = match &**f {
+                    Expr::Id(x, _) => self.constructor_classes.get(x).cloned(),
+                    _ => None,
+                };
 
                 // syntax block surrounding this new expression
                 let cxt = loc.enclosing_block().unwrap();
@@ -44,22 +93,29 @@ impl Visitor for ThisParameter<'_> {
                 // generate a new name for the object
                 let obj_name = self.ng.fresh("new_obj");
 
-                // Object.create(f.prototype)
-                let new_obj = call_(
-                    dot_(id_("Object", s.clone()), "create", s.clone()),
-                    vec![
-                        id_("Object", s.clone()),
-                        Expr::Dot(
-                            Box::new(Expr::Id(func_name.clone(), s.clone())),
-                            Id::Named("prototype".to_string()),
-                            s.clone(),
-                        ),
-                    ],
+                let prototype = Expr::Dot(
+                    Box::new(Expr::Id(func_name.clone(), s.clone())),
+                    Id::Named("prototype".to_string()),
                     s.clone(),
                 );
+                let new_obj = match fields {
+                    // {__proto__: f.prototype, x: undefined, ...}
+                    Some(fields) => {
+                        let mut props = vec![(Key::Str("__proto__".to_string()), prototype)];
+                        props.extend(fields.into_iter().map(|x| (Key::Str(x), UNDEFINED_)));
+                        Expr::Object(props, s.clone())
+                    }
+                    // Object.create(f.prototype)
+                    None => call_(
+                        dot_(id_("Object", s.clone()), "create", s.clone()),
+                        vec![id_("Object", s.clone()), prototype],
+                        s.clone(),
+                    ),
+                };
 
                 // Insert into the surrounding syntax block:
                 //     let $name = Object.create(f.prototype);
+                // or the object literal above.
                 cxt.insert(cxt.index, vardecl1_(obj_name.clone(), new_obj, s.clone()));
 
                 // args => $obj, args...
@@ -78,7 +134,7 @@ impl Visitor for ThisParameter<'_> {
                 // make the entire `new` expression evaluate to just the new object
                 *expr = id_(obj_name, s.clone());
             }
-            Expr::Func(_, params, _, _) => {
+            Expr::Func(_, params, ..) => {
                 // yes for once using a named id is correct here, because
                 // it's a special name that may or may not be used by the body
                 params.insert(0, self.this_name.clone());
@@ -95,6 +151,7 @@ pub fn desugar_this(program: &mut Stmt, ng: &mut NameGen) {
     let mut v = ThisParameter {
         this_name: ng.fresh("this"),
         ng,
+        constructor_classes: constructor_classes(program),
     };
     program.walk(&mut v);
 }
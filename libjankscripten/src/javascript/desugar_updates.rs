@@ -1,17 +1,40 @@
+//! desugar compound assignments (`lv op= e`) and updates (`++lv`, `lv--`, etc.)
+//! into plain assignments
+//!
+//! The subexpressions of the lvalue are named before the read-modify-write, so
+//! that `o[f()] += 1` evaluates `o` and `f()` exactly once. When the right-hand
+//! side may have effects, the old value is named too, since JavaScript reads
+//! it before evaluating the right-hand side:
+//!
+//! ```text
+//! o[f()] += g()    =>    var t1 = f(); var t2 = o[t1]; o[t1] = t2 + g();
+//! ```
+//!
+//! Like the other passes that insert statements before the enclosing
+//! statement, this evaluates the named expressions before any *siblings* of
+//! the update, e.g. `x + x++` reads `x` after it is incremented.
+
 use super::constructors::*;
 use super::syntax::*;
 use super::walk::*;
 use super::*;
 use crate::pos::Pos;
 
-//note: will depend on function naming from desugar_function_applications in order to handle f().x += 1; cases
-// x += 1;    =>    x = x + 1;
-
 struct DesugarFancyUpdates<'a> {
     ng: &'a mut NameGen,
 }
 
 impl Visitor for DesugarFancyUpdates<'_> {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        // The result of `x++;` is unused, so it is the same as `++x;`, which
+        // does not need a temporary for the old value.
+        if let Stmt::Expr(e, _) = stmt {
+            if let Expr::UnaryAssign(op, _, _) = &mut **e {
+                *op = op.to_prefix();
+            }
+        }
+    }
+
     fn exit_expr(&mut self, expr: &mut Expr, loc: &Loc) {
         match expr {
             Expr::Assign(AssignOp::Equal, _lv, _rhs, _) => {}
@@ -39,50 +62,40 @@ impl Visitor for DesugarFancyUpdates<'_> {
                 )
             }
             Expr::UnaryAssign(op, lv, s) => {
-                // There are four essential cases
-                //
-                // ++x => x = x + 1, x
-                //
-                // ++e.x => tmp = e, tmp.x = tmp.x + 1, tmp.x                              tmp fresh
-                //
-                // x++ => tmp = x, x = x + 1, tmp                                          tmp fresh
+                // There are two cases, after naming the subexpressions of lv:
                 //
-                // e.x++ => tmp1 = e, tmp2 = tmp1.x, tmp1.x = tmp1.x + 1, tmp    tmp1 and tmp2 fresh
+                // ++lv => lv = +lv + 1
                 //
-                // The decrement operators are similar to the increment operators. Computed field
-                // lookups (e1[e2]) may require a new temporary variable too.
+                // lv++ => tmp = +lv, lv = tmp + 1, tmp                  tmp fresh
                 //
-                // We can do a bit better than cases above suggest by using the  is_essentially_atom
-                // methods to avoid introducing unnecessary temporary variables.
-                let block = loc.enclosing_block().unwrap();
-                let e = self.lval_to_expr(lv, loc, s.clone());
-                // Insert the statement 'atom = atom + 1' immediately before this expression.
-                block.insert(
-                    block.index,
-                    expr_(
-                        assign_(
-                            lv.take(),
-                            binary_(
-                                op.binop(),
-                                e.clone(),
-                                Expr::Lit(Lit::Num(Num::Int(1)), s.clone()),
+                // The unary + is ToNumber, since ++ does not concatenate strings. In the
+                // postfix case, the first two expressions become statements immediately
+                // before this one. The decrement operators are similar.
+                let s = s.clone();
+                let old = unary_(
+                    UnaryOp::Plus,
+                    self.lval_to_expr(lv, loc, s.clone()),
+                    s.clone(),
+                );
+                let one = Expr::Lit(Lit::Num(Num::Int(1)), s.clone());
+                if op.is_prefix() {
+                    *expr = assign_(lv.take(), binary_(op.binop(), old, one, s.clone()), s);
+                } else {
+                    let block = loc.enclosing_block().unwrap();
+                    let tmp = self.ng.fresh("update_old");
+                    block.insert(block.index, vardecl1_(tmp.clone(), old, s.clone()));
+                    block.insert(
+                        block.index,
+                        expr_(
+                            assign_(
+                                lv.take(),
+                                binary_(op.binop(), id_(tmp.clone(), s.clone()), one, s.clone()),
                                 s.clone(),
                             ),
                             s.clone(),
                         ),
-                        s.clone(),
-                    ),
-                );
-                if op.is_prefix() {
-                    *expr = e;
-                } else {
-                    // atom = atom - 1
-                    *expr = binary_(
-                        op.other_binop(),
-                        e.clone(),
-                        Expr::Lit(Lit::Num(Num::Int(1)), s.clone()),
-                        s.clone(),
-                    )
+                    );
+                    *expr = id_(tmp, s);
                 }
             }
             _ => {
@@ -124,7 +137,14 @@ impl DesugarFancyUpdates<'_> {
         loc: &Loc,
         s: Pos,
     ) -> Expr {
-        let expr = self.lval_to_expr(lv, loc, s.clone());
+        let mut expr = self.lval_to_expr(lv, loc, s.clone());
+        if !rhs.is_essentially_atom() {
+            // The right-hand side may change the value of lv, so read it first.
+            let cxt = loc.enclosing_block().unwrap();
+            let old = self.ng.fresh("update_old");
+            cxt.insert(cxt.index, vardecl1_(old.clone(), expr, s.clone()));
+            expr = id_(old, s.clone());
+        }
         assign_(
             lv.take(),
             binary_(BinOp::BinaryOp(bin_op), expr, rhs.take(), s.clone()),
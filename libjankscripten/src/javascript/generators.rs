@@ -0,0 +1,729 @@
+//! A state-machine transform for generator functions whose `yield`s are statements, and for async
+//! functions whose `await`s are statements.
+//!
+//! This pass turns every generator function into an ordinary function that returns an iterator.
+//! The `next` method of the iterator resumes the body where it last stopped:
+//!
+//! ```text
+//! function* range(n) {        function range(n) {
+//!     var i = 0;                  var i = undefined;
+//!     while (i < n) {             var $jnks_this = this;
+//!         yield i;                var $jnks_state = 0;
+//!         i++;                    return {next: function () {
+//!     }                               $jnks_machine: while (true) {
+//! }                      =>               if ($jnks_state === 0) { i = 0; <jump 1> }
+//!                                         if ($jnks_state === 1) {
+//!                                             if (!(i < n)) { <jump 3> }
+//!                                             $jnks_state = 2;
+//!                                             return {value: i, done: false};
+//!                                         }
+//!                                         if ($jnks_state === 2) { i++; <jump 1> }
+//!                                         if ($jnks_state === 3) { <finish> }
+//!                                         return {value: undefined, done: true};
+//!                                     }
+//!                                 }};
+//!                             }
+//! ```
+//!
+//! where `<jump k>` is `$jnks_state = k; continue $jnks_machine;`, and every state ends with a
+//! jump or a `return`. The variables of the body are declared by the generator function, so that
+//! they survive between calls to `next`, and `this` refers to the `this` of the generator
+//! function.
+//!
+//! Statements that do not contain `yield` are copied into their states, but their `return`,
+//! `break`, and `continue` statements become jumps when they leave the statement. The transform
+//! supports `yield e;` in blocks, `if` statements, labels, and loops, and ignores the value that
+//! `next` receives. The parser rejects `yield*`, and this pass panics on `yield` inside an
+//! expression, and on `switch` and `try` statements and `for..in` loops that contain `yield`.
+//!
+//! An async function runs its state machine at once, and each `await` passes the machine to the
+//! host as the continuation of an asynchronous operation (see runtime/src/host.rs), so the
+//! function is in continuation-passing style:
+//!
+//! ```text
+//! async function f(ms) {          function f(ms) {
+//!     var r = await sleep(ms);        var r = undefined;
+//!     log(r);                         ...
+//! }                          =>       var $jnks_resume = function ($jnks_sent) {
+//!                                         $jnks_machine: while (true) {
+//!                                             if ($jnks_state === 0) {
+//!                                                 $jnks_state = 1;
+//!                                                 $jnks_awaited = sleep(ms);
+//!                                                 jnks_await($jnks_awaited.op, $jnks_awaited.arg,
+//!                                                     $jnks_resume);
+//!                                                 return undefined;
+//!                                             }
+//!                                             if ($jnks_state === 1) {
+//!                                                 r = $jnks_sent; log(r); <finish>
+//!                                             }
+//!                                             ...
+//!                                         }
+//!                                     };
+//!                                     $jnks_resume(undefined);
+//!                                 }
+//! ```
+//!
+//! The operand of `await` must be a host operation `{op: n, arg: x}`, which `jnks_await` in the
+//! standard library starts, and the result of the operation is the value of `await`. The
+//! transform supports `await e;` and `x = await e;` wherever it supports `yield e;`, and
+//! `var x = await e;` too. There are no promises, so an async function produces `undefined`
+//! when it is called, and the host ignores what it returns.
+//!
+//! The pass runs after desugar_classes, desugar_destructuring, and desugar_for_of, so that the body
+//! has no classes, destructuring, or `for..of` loops, and before desugar_params, which lowers the
+//! defaults and rest parameters that stay at the start of the generator function.
+
+use super::constructors::*;
+use super::syntax::*;
+use super::walk::*;
+use crate::pos::Pos;
+
+/// The variables of the transformed function. The `$jnks_` prefix is reserved for the compiler,
+/// and each generator function declares its own, so nested generators do not interfere.
+const STATE: &str = "$jnks_state";
+const THIS: &str = "$jnks_this";
+const MACHINE: &str = "$jnks_machine";
+/// The continuation of an async function, which receives the result of each `await`.
+const RESUME: &str = "$jnks_resume";
+const SENT: &str = "$jnks_sent";
+/// The host operation of the last `await`.
+const AWAITED: &str = "$jnks_awaited";
+
+/// The state of a generator that has returned.
+const FINISHED: i32 = -1;
+
+type GeneratorResult<T> = Result<T, &'static str>;
+
+/// Turns the body of a generator function into the body of a function that returns an iterator,
+/// or the body of an async function into one that starts its continuation.
+fn generator_body(kind: FuncKind, params: &[Id], body: Stmt, s: Pos) -> GeneratorResult<Stmt> {
+    let mut stmts = match body {
+        Stmt::Block(stmts, _) => stmts,
+        Stmt::Empty => vec![],
+        stmt => vec![stmt],
+    };
+    // The directives and the defaults of the parameters stay at the start of the generator
+    // function, so that the parameters get their values when it is called.
+    let n = stmts
+        .iter()
+        .take_while(|s| {
+            matches!(
+                s,
+                Stmt::Pragma(..) | Stmt::DefaultParam(..) | Stmt::RestParam(..)
+            )
+        })
+        .count();
+    let mut prologue: Vec<Stmt> = stmts.drain(..n).collect();
+    let mut body = Stmt::Block(stmts, s.clone());
+    let mut hoist = Hoist::default();
+    body.walk(&mut hoist);
+    if let Some(err) = hoist.error {
+        return Err(err);
+    }
+    let mut machine = Machine {
+        kind,
+        states: vec![vec![]],
+        current: 0,
+        targets: vec![],
+        s: s.clone(),
+    };
+    machine.compile(body, vec![])?;
+    machine.finish(UNDEFINED_);
+
+    prologue.extend(hoist.funcs);
+    // Declaring an argument again would forget its value.
+    for x in hoist.vars.into_iter().filter(|x| !params.contains(x)) {
+        prologue.push(vardecl1_(x, UNDEFINED_, s.clone()));
+    }
+    prologue.push(vardecl1_(THIS, Expr::This, s.clone()));
+    prologue.push(vardecl1_(STATE, int_(0, s.clone()), s.clone()));
+    let finished = machine.finished(UNDEFINED_);
+    let mut dispatch: Vec<Stmt> = machine
+        .states
+        .into_iter()
+        .enumerate()
+        .map(|(k, state)| {
+            let is_state = binary_(
+                BinOp::BinaryOp(BinaryOp::StrictEqual),
+                id_(STATE, s.clone()),
+                int_(k as i32, s.clone()),
+                s.clone(),
+            );
+            if_(
+                is_state,
+                Stmt::Block(state, s.clone()),
+                Stmt::Empty,
+                s.clone(),
+            )
+        })
+        .collect();
+    dispatch.push(return_(finished, s.clone()));
+    let machine = Stmt::Block(
+        vec![label_(
+            MACHINE,
+            while_(TRUE_, Stmt::Block(dispatch, s.clone()), s.clone()),
+            s.clone(),
+        )],
+        s.clone(),
+    );
+    if kind == FuncKind::Async {
+        let resume = Expr::Func(
+            None,
+            vec![SENT.into()],
+            Box::new(machine),
+            FuncKind::Plain,
+            s.clone(),
+        );
+        prologue.push(vardecl1_(AWAITED, UNDEFINED_, s.clone()));
+        prologue.push(vardecl1_(RESUME, resume, s.clone()));
+        prologue.push(expr_(
+            call_(id_(RESUME, s.clone()), vec![UNDEFINED_], s.clone()),
+            s.clone(),
+        ));
+    } else {
+        let next = Expr::Func(None, vec![], Box::new(machine), FuncKind::Plain, s.clone());
+        let iterator = Expr::Object(vec![(Key::Str("next".to_string()), next)], s.clone());
+        prologue.push(return_(iterator, s.clone()));
+    }
+    Ok(Stmt::Block(prologue, s))
+}
+
+/// `{value: value, done: done}`, which `next` produces.
+fn result(value: Expr, done: bool) -> Expr {
+    Expr::Object(
+        vec![
+            (Key::Str("value".to_string()), value),
+            (
+                Key::Str("done".to_string()),
+                Expr::Lit(Lit::Bool(done), Pos::UNKNOWN),
+            ),
+        ],
+        Pos::UNKNOWN,
+    )
+}
+
+/// Where `break` and `continue` statements that name a loop or label of the body go.
+struct Target {
+    labels: Vec<Id>,
+    break_to: usize,
+    /// `None` for a labelled statement that is not a loop.
+    continue_to: Option<usize>,
+}
+
+struct Machine {
+    /// `Generator` or `Async`
+    kind: FuncKind,
+    /// The statements of each state.
+    states: Vec<Vec<Stmt>>,
+    /// The state that receives the statements that [Machine::emit] produces.
+    current: usize,
+    /// The loops and labelled statements that enclose the current statement, innermost last.
+    targets: Vec<Target>,
+    s: Pos,
+}
+
+impl Machine {
+    fn new_state(&mut self) -> usize {
+        self.states.push(vec![]);
+        self.states.len() - 1
+    }
+
+    fn emit(&mut self, stmt: Stmt) {
+        self.states[self.current].push(stmt);
+    }
+
+    fn set_state(&self, k: i32) -> Stmt {
+        expr_(
+            assign_(STATE, int_(k, self.s.clone()), self.s.clone()),
+            self.s.clone(),
+        )
+    }
+
+    /// `$jnks_state = k; continue $jnks_machine;`
+    fn jump(&self, k: usize) -> Stmt {
+        Stmt::Block(
+            vec![
+                self.set_state(k as i32),
+                continue_(Some(MACHINE), self.s.clone()),
+            ],
+            self.s.clone(),
+        )
+    }
+
+    /// What the transformed function returns when the body returns `value`. The host ignores what
+    /// the continuation of an async function returns.
+    fn finished(&self, value: Expr) -> Expr {
+        match self.kind {
+            FuncKind::Async => value,
+            _ => result(value, true),
+        }
+    }
+
+    /// Ends the current state with `return value;`, which finishes the generator.
+    fn finish(&mut self, value: Expr) {
+        let set_state = self.set_state(FINISHED);
+        self.emit(set_state);
+        self.emit(return_(self.finished(value), self.s.clone()));
+    }
+
+    /// Ends the current state with `yield value;` or `await value;`, and starts the state that
+    /// resumes it, which assigns the result of `await` to `x`, if any.
+    fn suspend(&mut self, mut value: Expr, x: Option<Id>, s: Pos) -> GeneratorResult<()> {
+        if contains_yield_expr(&mut value) {
+            return Err("yield or await inside an expression");
+        }
+        let next = self.new_state();
+        let set_state = self.set_state(next as i32);
+        self.emit(set_state);
+        match self.kind {
+            FuncKind::Async => {
+                let awaited = |field: &str| dot_(id_(AWAITED, s.clone()), field, s.clone());
+                let start = call_(
+                    id_("jnks_await", s.clone()),
+                    vec![awaited("op"), awaited("arg"), id_(RESUME, s.clone())],
+                    s.clone(),
+                );
+                self.emit(expr_(assign_(AWAITED, value, s.clone()), s.clone()));
+                self.emit(expr_(start, s.clone()));
+                self.emit(return_(UNDEFINED_, s));
+            }
+            _ => self.emit(return_(result(value, false), s)),
+        }
+        self.current = next;
+        if let Some(x) = x {
+            let resume = assign_(x, id_(SENT, self.s.clone()), self.s.clone());
+            self.emit(expr_(resume, self.s.clone()));
+        }
+        Ok(())
+    }
+
+    /// Compiles `stmt`, which `labels` name, into the current state and the states that follow.
+    fn compile(&mut self, mut stmt: Stmt, mut labels: Vec<Id>) -> GeneratorResult<()> {
+        if !contains_yield(&mut stmt) {
+            let mut jumps = Jumps {
+                machine: &*self,
+                labels: vec![],
+                loops: 0,
+                breakables: 0,
+                funcs: 0,
+            };
+            stmt.walk(&mut jumps);
+            self.emit(stmt);
+            return Ok(());
+        }
+        match stmt {
+            Stmt::Block(stmts, _) => {
+                for stmt in stmts {
+                    self.compile(stmt, vec![])?;
+                }
+            }
+            Stmt::Expr(e, s) => match *e {
+                Expr::Yield(value, _) => self.suspend(value.map_or(UNDEFINED_, |e| *e), None, s)?,
+                Expr::Await(value, _) => self.suspend(*value, None, s)?,
+                Expr::Assign(AssignOp::Equal, lv, value, _) => match (*lv, *value) {
+                    (LValue::Id(x), Expr::Await(value, _)) => self.suspend(*value, Some(x), s)?,
+                    _ => return Err("yield or await inside an expression"),
+                },
+                _ => return Err("yield or await inside an expression"),
+            },
+            Stmt::If(mut cond, then, other, _) => {
+                if contains_yield_expr(&mut cond) {
+                    return Err("yield or await inside an expression");
+                }
+                let then_state = self.new_state();
+                let other_state = self.new_state();
+                let end = self.new_state();
+                let branch = if_(
+                    *cond,
+                    self.jump(then_state),
+                    self.jump(other_state),
+                    self.s.clone(),
+                );
+                self.emit(branch);
+                self.current = then_state;
+                self.compile(*then, vec![])?;
+                self.emit(self.jump(end));
+                self.current = other_state;
+                self.compile(*other, vec![])?;
+                self.emit(self.jump(end));
+                self.current = end;
+            }
+            Stmt::While(mut cond, body, _) => {
+                if contains_yield_expr(&mut cond) {
+                    return Err("yield or await inside an expression");
+                }
+                let test = self.new_state();
+                let end = self.new_state();
+                self.emit(self.jump(test));
+                self.current = test;
+                self.exit_unless(*cond, end);
+                self.compile_loop_body(*body, labels, end, test)?;
+                self.emit(self.jump(test));
+                self.current = end;
+            }
+            Stmt::DoWhile(body, mut cond, _) => {
+                if contains_yield_expr(&mut cond) {
+                    return Err("yield or await inside an expression");
+                }
+                let start = self.new_state();
+                let test = self.new_state();
+                let end = self.new_state();
+                self.emit(self.jump(start));
+                self.current = start;
+                self.compile_loop_body(*body, labels, end, test)?;
+                self.emit(self.jump(test));
+                self.current = test;
+                self.exit_unless(*cond, end);
+                self.emit(self.jump(start));
+                self.current = end;
+            }
+            Stmt::For(init, mut cond, mut update, body, s) => {
+                let mut init = match init {
+                    ForInit::Expr(e) => *e,
+                    // Hoist turns declarations into assignments.
+                    ForInit::Decl(_) => unreachable!("declaration in a generator"),
+                };
+                if contains_yield_expr(&mut init)
+                    || contains_yield_expr(&mut cond)
+                    || contains_yield_expr(&mut update)
+                {
+                    return Err("yield or await inside an expression");
+                }
+                let test = self.new_state();
+                let next = self.new_state();
+                let end = self.new_state();
+                self.emit(expr_(init, s.clone()));
+                self.emit(self.jump(test));
+                self.current = test;
+                self.exit_unless(*cond, end);
+                self.compile_loop_body(*body, labels, end, next)?;
+                self.emit(self.jump(next));
+                self.current = next;
+                self.emit(expr_(*update, s));
+                self.emit(self.jump(test));
+                self.current = end;
+            }
+            Stmt::Label(x, body, _) => {
+                labels.push(x);
+                match *body {
+                    body @ Stmt::While(..) | body @ Stmt::DoWhile(..) | body @ Stmt::For(..) => {
+                        self.compile(body, labels)?
+                    }
+                    body => {
+                        let end = self.new_state();
+                        self.targets.push(Target {
+                            labels,
+                            break_to: end,
+                            continue_to: None,
+                        });
+                        self.compile(body, vec![])?;
+                        self.targets.pop();
+                        self.emit(self.jump(end));
+                        self.current = end;
+                    }
+                }
+            }
+            Stmt::Switch(..)
+            | Stmt::Catch(..)
+            | Stmt::Finally(..)
+            | Stmt::ForIn(..)
+            | Stmt::ForOf(..) => {
+                return Err("yield or await inside switch, try, for..in, or for..of")
+            }
+            _ => return Err("yield or await inside an expression"),
+        }
+        Ok(())
+    }
+
+    /// `if (!cond) { <jump end> }`
+    fn exit_unless(&mut self, cond: Expr, end: usize) {
+        let exit = if_(
+            not_(cond, self.s.clone()),
+            self.jump(end),
+            Stmt::Empty,
+            self.s.clone(),
+        );
+        self.emit(exit);
+    }
+
+    fn compile_loop_body(
+        &mut self,
+        body: Stmt,
+        labels: Vec<Id>,
+        break_to: usize,
+        continue_to: usize,
+    ) -> GeneratorResult<()> {
+        self.targets.push(Target {
+            labels,
+            break_to,
+            continue_to: Some(continue_to),
+        });
+        self.compile(body, vec![])?;
+        self.targets.pop();
+        Ok(())
+    }
+}
+
+/// Declares the variables and functions of the body in the generator function, and refers to its
+/// `this`.
+#[derive(Default)]
+struct Hoist {
+    vars: Vec<Id>,
+    funcs: Vec<Stmt>,
+    /// The depth of nested functions, whose bodies are left alone.
+    depth: usize,
+    error: Option<&'static str>,
+}
+
+impl Hoist {
+    fn declare(&mut self, x: &Id) {
+        if !self.vars.contains(x) {
+            self.vars.push(x.clone());
+        }
+    }
+
+    fn assign(&mut self, decls: Vec<VarDecl>, s: &Pos) -> Expr {
+        let mut assigns: Vec<Expr> = decls
+            .into_iter()
+            .map(|VarDecl { name, named }| {
+                self.declare(&name);
+                assign_(name, *named, s.clone())
+            })
+            .collect();
+        if assigns.len() == 1 {
+            assigns.remove(0)
+        } else {
+            Expr::Seq(assigns, s.clone())
+        }
+    }
+}
+
+impl Visitor for Hoist {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Func(..) = stmt {
+            self.depth += 1;
+        }
+    }
+
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Func(..) = stmt {
+            self.depth -= 1;
+            if self.depth == 0 {
+                self.funcs.push(stmt.take());
+            }
+            return;
+        }
+        if self.depth > 0 {
+            return;
+        }
+        match stmt {
+            Stmt::VarDecl(decls, s) => {
+                let s = s.clone();
+                let decls = std::mem::replace(decls, vec![]);
+                *stmt = expr_(self.assign(decls, &s), s);
+            }
+            Stmt::For(init, _, _, _, s) => {
+                if let ForInit::Decl(decls) = init {
+                    let decls = std::mem::replace(decls, vec![]);
+                    *init = ForInit::Expr(Box::new(self.assign(decls, s)));
+                }
+            }
+            Stmt::ForIn(is_var, x, _, _, _) | Stmt::ForOf(is_var, x, _, _, _) => {
+                if *is_var {
+                    *is_var = false;
+                    self.declare(x);
+                }
+            }
+            Stmt::Destructure(..) => self.error = Some("destructuring in a generator"),
+            Stmt::Class(..) => self.error = Some("class declaration in a generator"),
+            _ => (),
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(..) = expr {
+            self.depth += 1;
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Func(..) => self.depth -= 1,
+            Expr::This if self.depth == 0 => *expr = id_(THIS, Pos::UNKNOWN),
+            _ => (),
+        }
+    }
+}
+
+/// Turns the `return`, `break`, and `continue` statements of a statement that does not contain
+/// `yield` into jumps, when they leave the statement.
+struct Jumps<'a> {
+    machine: &'a Machine,
+    /// The labels that the statement declares.
+    labels: Vec<Id>,
+    /// The number of loops, and of loops and `switch` statements, around the current statement.
+    loops: usize,
+    breakables: usize,
+    /// The depth of nested functions, whose bodies are left alone.
+    funcs: usize,
+}
+
+impl Jumps<'_> {
+    fn target(&self, label: &Option<Id>) -> Option<&Target> {
+        match label {
+            None => self
+                .machine
+                .targets
+                .iter()
+                .rev()
+                .find(|t| t.continue_to.is_some()),
+            Some(x) if self.labels.contains(x) => None,
+            Some(x) => self
+                .machine
+                .targets
+                .iter()
+                .rev()
+                .find(|t| t.labels.contains(x)),
+        }
+    }
+}
+
+impl Visitor for Jumps<'_> {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::While(..)
+            | Stmt::DoWhile(..)
+            | Stmt::For(..)
+            | Stmt::ForIn(..)
+            | Stmt::ForOf(..) => {
+                self.loops += 1;
+                self.breakables += 1;
+            }
+            Stmt::Switch(..) => self.breakables += 1,
+            Stmt::Label(x, ..) => self.labels.push(x.clone()),
+            _ => (),
+        }
+    }
+
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::While(..)
+            | Stmt::DoWhile(..)
+            | Stmt::For(..)
+            | Stmt::ForIn(..)
+            | Stmt::ForOf(..) => {
+                self.loops -= 1;
+                self.breakables -= 1;
+            }
+            Stmt::Switch(..) => self.breakables -= 1,
+            Stmt::Label(..) => {
+                self.labels.pop();
+            }
+            _ if self.funcs > 0 => (),
+            Stmt::Return(e, s) => {
+                let s = s.clone();
+                *stmt = Stmt::Block(
+                    vec![
+                        self.machine.set_state(FINISHED),
+                        return_(self.machine.finished(e.take()), s.clone()),
+                    ],
+                    s,
+                );
+            }
+            Stmt::Break(x, _) if x.is_some() || self.breakables == 0 => {
+                if let Some(target) = self.target(x) {
+                    *stmt = self.machine.jump(target.break_to);
+                }
+            }
+            Stmt::Continue(x, _) if x.is_some() || self.loops == 0 => {
+                if let Some(k) = self.target(x).and_then(|t| t.continue_to) {
+                    *stmt = self.machine.jump(k);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(..) = expr {
+            self.funcs += 1;
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(..) = expr {
+            self.funcs -= 1;
+        }
+    }
+}
+
+/// Finds `yield` and `await` outside of nested functions.
+#[derive(Default)]
+struct ContainsYield {
+    found: bool,
+    depth: usize,
+}
+
+impl Visitor for ContainsYield {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Func(..) => self.depth += 1,
+            Expr::Yield(..) | Expr::Await(..) if self.depth == 0 => self.found = true,
+            _ => (),
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(..) = expr {
+            self.depth -= 1;
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.found
+    }
+}
+
+fn contains_yield(stmt: &mut Stmt) -> bool {
+    let mut v = ContainsYield::default();
+    stmt.walk(&mut v);
+    v.found
+}
+
+fn contains_yield_expr(expr: &mut Expr) -> bool {
+    let mut v = ContainsYield::default();
+    expr.walk(&mut v);
+    v.found
+}
+
+/// Transforms the generator and async functions, innermost first, so that the transform of a
+/// function sees the ones nested in its body as ordinary functions.
+struct Generators;
+
+impl Generators {
+    fn transform(params: &[Id], body: &mut Stmt, kind: &mut FuncKind, s: &Pos) {
+        if *kind != FuncKind::Plain {
+            *body = generator_body(*kind, params, body.take(), s.clone())
+                .unwrap_or_else(|err| panic!("{} at {}", err, s));
+            *kind = FuncKind::Plain;
+        }
+    }
+}
+
+impl Visitor for Generators {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Func(_, params, body, kind, s) = stmt {
+            Generators::transform(params, body, kind, s);
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(_, params, body, kind, s) = expr {
+            Generators::transform(params, body, kind, s);
+        }
+    }
+}
+
+pub fn desugar_generators(program: &mut Stmt) {
+    program.walk(&mut Generators);
+}
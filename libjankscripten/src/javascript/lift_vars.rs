@@ -1,29 +1,85 @@
+//! Hoist variable declarations to the top of the enclosing function.
+//!
+//! A `var` is function-scoped and exists from the start of the function, so we declare every
+//! variable once, at the top of the function, and turn each `var x = e` into an assignment. A
+//! declaration without an initializer does nothing at runtime, so `var x;` disappears, and a
+//! variable that has the same name as a parameter is the parameter.
+//!
+//! preconditions: desugar_function_stmts, add_blocks, desugar_vardecls
 use super::constructors::*;
 use super::syntax::*;
 use super::walk::*;
+use crate::pos::Pos;
+use std::collections::HashSet;
 
 pub fn lift_vars(js: &mut Stmt) {
-    let mut v = LiftVars;
+    let mut v = LiftVars {
+        declared: vec![HashSet::new()],
+    };
     js.walk(&mut v);
+    directives_first(js);
 }
 
-struct LiftVars;
+/// Moves the directives (e.g., `"use strict";`) of a body back before the declarations that we
+/// inserted at its top, since a directive must start the body.
+fn directives_first(body: &mut Stmt) {
+    if let Stmt::Block(stmts, _) = body {
+        let (mut directives, rest): (Vec<_>, Vec<_>) = stmts
+            .drain(..)
+            .partition(|stmt| matches!(stmt, Stmt::Pragma(pragma, _) if pragma.is_directive()));
+        directives.extend(rest);
+        *stmts = directives;
+    }
+}
+
+struct LiftVars {
+    /// The names that each enclosing function has declared, including its parameters. The first
+    /// element is the top-level of the program.
+    declared: Vec<HashSet<Id>>,
+}
+
+impl LiftVars {
+    /// Declares `x` at the top of the enclosing function, unless it is already declared.
+    fn declare(&mut self, x: &Id, s: &Pos, loc: &Loc) {
+        let declared = self.declared.last_mut().expect("no enclosing function");
+        if declared.insert(x.clone()) {
+            loc.body_of_enclosing_function_or_program()
+                .insert(0, vardecl1_(x.clone(), UNDEFINED_, s.clone()));
+        }
+    }
+}
 
 impl Visitor for LiftVars {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(_, args, ..) = expr {
+            self.declared.push(args.iter().cloned().collect());
+        }
+    }
+
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::Func(_, _, body, ..) = expr {
+            self.declared.pop();
+            directives_first(body);
+        }
+    }
+
     fn exit_stmt(&mut self, stmt: &mut Stmt, loc: &Loc) {
         match stmt {
             Stmt::VarDecl(decl, s) => {
                 let decl1 = decl.pop().expect("no decls in vardecl");
                 assert_eq!(decl.pop(), None, "vardecls not desugared");
-                let new_decl = vardecl1_(decl1.name.clone(), UNDEFINED_, s.clone());
-                loc.body_of_enclosing_function_or_program()
-                    .insert(0, new_decl);
-                *stmt = expr_(assign_(decl1.name, *decl1.named, s.clone()), s.clone());
+                let s = s.clone();
+                self.declare(&decl1.name, &s, loc);
+                // `undefined` in the source is an identifier, so the parser only produces this
+                // literal for a declaration without an initializer.
+                *stmt = match *decl1.named {
+                    Expr::Lit(Lit::Undefined, _) => Stmt::Empty,
+                    named => expr_(assign_(decl1.name, named, s.clone()), s),
+                };
             }
-            Stmt::ForIn(is_var, bind, container, _, s) if *is_var => {
-                let new_decl = vardecl1_(bind.clone(), container.take(), s.clone());
-                let block_context = loc.body_of_enclosing_function_or_program();
-                block_context.insert(block_context.index, new_decl);
+            Stmt::ForIn(is_var, bind, _, _, s) if *is_var => {
+                let s = s.clone();
+                self.declare(bind, &s, loc);
                 *is_var = false;
             }
             _ => (),
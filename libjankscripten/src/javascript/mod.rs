@@ -2,17 +2,24 @@
 //! and desugaring.
 
 mod add_blocks;
+mod constructor_classes;
 pub mod constructors;
 mod desugar;
 mod desugar_bracket_str;
+mod desugar_classes;
+mod desugar_destructuring;
+mod desugar_for_of;
 mod desugar_function_applications;
 mod desugar_function_stmts;
 mod desugar_logical;
+mod desugar_missing_args;
 mod desugar_loops;
+mod desugar_params;
 mod desugar_switch;
 mod desugar_this;
 mod desugar_updates;
 mod desugar_vardecls;
+mod generators;
 mod lift_vars;
 mod normalize_std_lib_calls;
 mod parser;
@@ -8,7 +8,10 @@
 //! arguments
 //!
 //! parseInt => default radix insertion (10)
-//! Error => default message insertion ("")
+//! Error, TypeError, RangeError, SyntaxError => default message insertion (undefined)
+//! setTimeout => default delay insertion (0)
+//! Map, Set => default iterable insertion (undefined)
+//! String => default value insertion ("")
 use super::constructors::*;
 use super::syntax::*;
 use super::*;
@@ -32,15 +35,42 @@ impl Visitor for NormalizeStdLibCalls {
                                 got => panic!("why was parseInt given {} arguments", got),
                             }
                         }
-                        "Error" => {
+                        "setTimeout" => {
                             match args.len() {
-                                // default message of empty
+                                // default delay of 0
+                                1 => args.push(int_(0, s.clone())),
+                                // perfect already
+                                2 => (),
+                                got => panic!("why was setTimeout given {} arguments", got),
+                            }
+                        }
+                        "Error" | "TypeError" | "RangeError" | "SyntaxError" => {
+                            match args.len() {
+                                // no message of its own
+                                0 => args.push(UNDEFINED_),
+                                // perfect already
+                                1 => (),
+                                got => panic!("why was {} given {} arguments at {}", id, got, s),
+                            }
+                        }
+                        "String" => {
+                            match args.len() {
+                                // `String()` is the empty string
                                 0 => args.push(str_("", s.clone())),
                                 // perfect already
                                 1 => (),
                                 got => panic!("why was {} given {} arguments at {}", id, got, s),
                             }
                         }
+                        "Map" | "Set" => {
+                            match args.len() {
+                                // no entries
+                                0 => args.push(UNDEFINED_),
+                                // perfect already
+                                1 => (),
+                                got => panic!("why was {} given {} arguments at {}", id, got, s),
+                            }
+                        }
                         _ => (),
                     }
                 }
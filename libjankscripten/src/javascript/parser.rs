@@ -6,8 +6,9 @@
 use super::constructors::*;
 use super::syntax as S;
 use crate::pos::Pos;
+use crate::shared::Pragma;
 use std::rc::Rc;
-use swc_common::{FileName, SourceMap, Span};
+use swc_common::{BytePos, FileName, SourceFile, SourceMap, Span, Spanned, SyntaxContext};
 use swc_ecma_ast as swc;
 use swc_ecma_parser::{lexer, Parser, StringInput, Syntax};
 use thiserror::Error;
@@ -25,6 +26,12 @@ pub enum ParseError {
     /// The SWC AST had a JavaScript feature that we do not support.
     #[error("Unsupported: {0}")]
     Unsupported(String),
+    /// A syntax error reported by SWC, rendered with its position.
+    #[error("Syntax error: {0}")]
+    Syntax(String),
+    /// Every error found by [parse_with_recovery], sorted by position.
+    #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))]
+    Multiple(Vec<ParseError>),
 }
 
 pub type ParseResult<T> = Result<T, ParseError>;
@@ -47,26 +54,221 @@ pub fn parse(src_name: &str, js_code: &str) -> ParseResult<S::Stmt> {
     let source_file =
         source_map.new_source_file(FileName::Custom(src_name.to_string()), js_code.into());
 
+    // Parse our script into the library's AST
+    let (script, _) = parse_swc_script(&source_file, source_file.start_pos, source_file.end_pos);
+    let script = match script {
+        Ok(s) => s,
+        Err(e) => return Err(ParseError::SWC(e)),
+    };
+
+    parse_script(script, &source_map)
+}
+
+/// Parse a full JavaScript program, but instead of stopping at the first
+/// error, keep going and report every error in the program.
+///
+/// When SWC fails, we keep the top-level statements before the one with the
+/// error, then resynchronize at the end of that statement (see
+/// [top_level_boundaries]) and parse the rest of the program. Thus an error
+/// in a function or an object literal that spans several lines is reported
+/// once, instead of once for every line after it. Statements that we do not
+/// support are reported individually. The errors are sorted by position, and
+/// the program is only returned if there were no errors at all.
+pub fn parse_with_recovery(src_name: &str, js_code: &str) -> ParseResult<S::Stmt> {
+    let source_map: Rc<SourceMap> = Default::default();
+    let source_file =
+        source_map.new_source_file(FileName::Custom(src_name.to_string()), js_code.into());
+
+    let mut errors = Vec::new();
+    let mut swc_stmts = Vec::new();
+    let mut lo = source_file.start_pos;
+    let end = source_file.end_pos;
+    while lo < end {
+        let (script, recoverable) = parse_swc_script(&source_file, lo, end);
+        errors.extend(
+            recoverable
+                .into_iter()
+                .map(|e| (e.span().lo, syntax_error(e, &source_map))),
+        );
+        match script {
+            Ok(script) => {
+                swc_stmts.extend(script.body);
+                break;
+            }
+            Err(e) => {
+                let span = e.span();
+                errors.push((span.lo, syntax_error(e, &source_map)));
+                // `lo` is the start of a top-level statement, so the
+                // boundaries from `lo` on are those of the whole program.
+                let boundaries: Vec<BytePos> =
+                    top_level_boundaries(&source_file.src[offset(&source_file, lo)..])
+                        .into_iter()
+                        .map(|i| lo + BytePos(i as u32))
+                        .collect();
+                // The statements before the one with the error are fine,
+                // as long as they parse on their own. If they don't (e.g.,
+                // the error is an unclosed brace), SWC has already reported
+                // the real problem, so we quietly drop them.
+                let stmt_start = boundaries
+                    .iter()
+                    .rev()
+                    .find(|pos| **pos <= span.lo)
+                    .copied()
+                    .unwrap_or(lo);
+                if stmt_start > lo {
+                    if let (Ok(prefix), _) = parse_swc_script(&source_file, lo, stmt_start) {
+                        swc_stmts.extend(prefix.body);
+                    }
+                }
+                let error_end = span.hi.max(lo);
+                lo = match boundaries.into_iter().find(|pos| *pos > error_end) {
+                    Some(pos) => pos,
+                    // The brackets around the error are not closed.
+                    None => next_unindented_line(&source_file, error_end),
+                };
+            }
+        }
+    }
+
+    let mut stmts = Vec::new();
+    for stmt in swc_stmts {
+        let stmt_lo = stmt.span().lo;
+        stmts.extend(leading_pragmas(stmt.span(), &source_map));
+        match parse_stmt(stmt, &source_map) {
+            Ok(stmt) => stmts.push(stmt),
+            Err(e) => errors.push((stmt_lo, e)),
+        }
+    }
+    parse_directives(&mut stmts);
+
+    if errors.is_empty() {
+        let span = Span::new(
+            source_file.start_pos,
+            source_file.end_pos,
+            SyntaxContext::empty(),
+        );
+        Ok(wrap_program(stmts, Pos::from_swc(&source_map, span)))
+    } else {
+        // A stable sort keeps the errors of a statement in order.
+        errors.sort_by_key(|(pos, _)| *pos);
+        Err(ParseError::Multiple(
+            errors.into_iter().map(|(_, e)| e).collect(),
+        ))
+    }
+}
+
+/// Run SWC on the source between `lo` and `hi`, returning the script and
+/// any recoverable errors that SWC reported along the way.
+fn parse_swc_script(
+    source_file: &SourceFile,
+    lo: BytePos,
+    hi: BytePos,
+) -> (
+    Result<swc::Script, swc_ecma_parser::error::Error>,
+    Vec<swc_ecma_parser::error::Error>,
+) {
+    let src = &source_file.src[offset(source_file, lo)..offset(source_file, hi)];
     // Create a lexer for the parser
     let lexer = lexer::Lexer::new(
         // We want to parse ECMAScript
         Syntax::Es(Default::default()),
         // JscTarget defaults to es5
         Default::default(),
-        StringInput::from(&*source_file),
+        StringInput::new(src, lo, hi),
         None,
     );
 
     // Create the actual parser
     let mut parser = Parser::new_from(lexer);
+    let script = parser.parse_script();
+    (script, parser.take_errors())
+}
 
-    // Parse our script into the library's AST
-    let script = match parser.parse_script() {
-        Ok(s) => s,
-        Err(e) => return Err(ParseError::SWC(e)),
-    };
+/// The index of `pos` in the text of `source_file`.
+fn offset(source_file: &SourceFile, pos: BytePos) -> usize {
+    (pos - source_file.start_pos).0 as usize
+}
 
-    parse_script(script, &source_map)
+/// The offsets in `src` at which a top-level statement may start: after a `;`,
+/// a newline, or a `}` that is not inside brackets. Strings, template literals,
+/// and comments are skipped. Regular expressions are not, since telling them
+/// from division needs a parser, so a bracket in one throws the depth off.
+fn top_level_boundaries(src: &str) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    let mut depth = 0;
+    let mut chars = src.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' | '\'' | '`' => {
+                while let Some((_, d)) = chars.next() {
+                    if d == '\\' {
+                        chars.next();
+                    } else if d == c || (d == '\n' && c != '`') {
+                        break;
+                    }
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                while let Some((_, d)) = chars.peek() {
+                    if *d == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut prev = ' ';
+                while let Some((_, d)) = chars.next() {
+                    if prev == '*' && d == '/' {
+                        break;
+                    }
+                    prev = d;
+                }
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                // A stray closing bracket is an error of its own, after
+                // which we are at the top level again.
+                depth = (depth - 1).max(0);
+                if c == '}' && depth == 0 {
+                    boundaries.push(i + 1);
+                }
+            }
+            ';' | '\n' if depth == 0 => boundaries.push(i + 1),
+            _ => (),
+        }
+    }
+    boundaries
+}
+
+/// The start of the first line after `pos` that is not indented and does not
+/// start with a closing bracket, which is likely the next top-level statement.
+fn next_unindented_line(source_file: &SourceFile, pos: BytePos) -> BytePos {
+    let start = offset(source_file, pos);
+    let mut line_start = start;
+    for line in source_file.src[start..].split_inclusive('\n') {
+        if line_start > start
+            && line
+                .chars()
+                .next()
+                .map_or(false, |c| !c.is_whitespace() && !"})]".contains(c))
+        {
+            return pos + BytePos((line_start - start) as u32);
+        }
+        line_start += line.len();
+    }
+    source_file.end_pos
+}
+
+/// Construct a syntax error from an SWC error, with its location.
+fn syntax_error(e: swc_ecma_parser::error::Error, source_map: &Rc<SourceMap>) -> ParseError {
+    let span = e.span();
+    ParseError::Syntax(format!(
+        "{:?} at {}",
+        e.kind(),
+        source_map.span_to_string(span)
+    ))
 }
 
 macro_rules! unsupported {
@@ -104,10 +306,74 @@ fn parse_id(ident: swc::Ident) -> S::Id {
     S::Id::Named(ident.sym.to_string())
 }
 
+/// The variable of a for-in or for-of loop, and whether the loop declares it.
+fn parse_for_binding(
+    left: swc::VarDeclOrPat,
+    kind: &str,
+    span: Span,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<(bool, swc::Ident)> {
+    // figure out if we're declaring a variable as part of this loop,
+    // or if we're reusing an already-bound identifier. if it's neither
+    // of these, we don't support it.
+    match left {
+        // re-using an already-bound identifier
+        swc::VarDeclOrPat::Pat(swc::Pat::Ident(ident)) => Ok((false, ident)),
+        swc::VarDeclOrPat::Pat(swc::Pat::Expr(boxed_expr)) => {
+            // nested match because you can't match inside boxes without
+            // nightly rust
+            match *boxed_expr {
+                swc::Expr::Ident(ident) => Ok((false, ident)),
+                _ => unsupported_message(
+                    &format!("unsupported expression in a {} loop declaration", kind),
+                    span,
+                    source_map,
+                ),
+            }
+        }
+
+        // var case
+        swc::VarDeclOrPat::VarDecl(swc::VarDecl {
+            span,
+            kind: swc::VarDeclKind::Var, // no `let`
+            declare: _,
+            mut decls,
+        }) => {
+            if decls.len() != 1 {
+                return unsupported_message("only a single var decl is allowed", span, source_map);
+            }
+            match decls.remove(0) {
+                // a single decl
+                swc::VarDeclarator {
+                    span: _,
+                    init: None,                   // no initializer
+                    name: swc::Pat::Ident(ident), // no obj destructuring
+                    definite: _,
+                } => Ok((true, ident)),
+                // any other type of decl
+                _ => unsupported_message("only var decls are allowed here", span, source_map),
+            }
+        }
+
+        // The program may pattern match on the index, which we do not support.
+        other => unsupported_message(
+            &format!("unsupported index in a {} loop: {:?}", kind, other),
+            span,
+            source_map,
+        ),
+    }
+}
+
 /// Parse an entire swc script
 fn parse_script(script: swc::Script, source_map: &Rc<SourceMap>) -> ParseResult<S::Stmt> {
     let mut stmts = parse_stmts(script.body, source_map)?;
+    parse_directives(&mut stmts);
+    Ok(wrap_program(stmts, Pos::from_swc(source_map, script.span)))
+}
 
+/// Turn the statements of a program into a single block, whose position is
+/// `pos`, the position of the whole program.
+fn wrap_program(mut stmts: Vec<S::Stmt>, pos: Pos) -> S::Stmt {
     // Desugaring expects the program to have a single block statement at the
     // top of the program AST. If the entire program is already surrounded in a
     // block statement, we'll just return that one. Otherwise, we manually wrap
@@ -115,18 +381,94 @@ fn parse_script(script: swc::Script, source_map: &Rc<SourceMap>) -> ParseResult<
 
     if stmts.len() == 1 {
         if let S::Stmt::Block(_, s) = &stmts[0] {
-            return Ok(stmts.pop().unwrap());
+            return stmts.pop().unwrap();
         }
     }
 
-    Ok(S::Stmt::Block(stmts, Default::default()))
+    S::Stmt::Block(stmts, pos)
 }
 
-/// Parse multiple swc statements.
+/// Parse multiple swc statements. Pragma comments become statements that
+/// precede the statement that they annotate.
 fn parse_stmts(stmts: Vec<swc::Stmt>, source_map: &Rc<SourceMap>) -> ParseResult<Vec<S::Stmt>> {
-    stmts
+    let mut parsed = Vec::with_capacity(stmts.len());
+    for stmt in stmts {
+        parsed.extend(leading_pragmas(stmt.span(), source_map));
+        parsed.push(parse_stmt(stmt, source_map)?);
+    }
+    Ok(parsed)
+}
+
+/// Parse a statement that is the body of another one (e.g., of a loop or an
+/// `if`), rather than in a list of statements. Its pragmas go in a block
+/// with it.
+fn parse_body(stmt: swc::Stmt, source_map: &Rc<SourceMap>) -> ParseResult<S::Stmt> {
+    let span = stmt.span();
+    let mut stmts = leading_pragmas(span, source_map);
+    let stmt = parse_stmt(stmt, source_map)?;
+    if stmts.is_empty() {
+        return Ok(stmt);
+    }
+    stmts.push(stmt);
+    Ok(S::Stmt::Block(stmts, Pos::from_swc(source_map, span)))
+}
+
+/// Turn the directives in the prologue of a script or function body (e.g.,
+/// `"use strict";`) into pragmas. Directives that we do not know are left
+/// alone, since they are ordinary expression statements.
+fn parse_directives(stmts: &mut Vec<S::Stmt>) {
+    for stmt in stmts.iter_mut() {
+        let pragma = match stmt {
+            S::Stmt::Pragma(..) => continue,
+            S::Stmt::Expr(e, p) => match &**e {
+                S::Expr::Lit(S::Lit::String(directive), _) => {
+                    Pragma::from_directive(directive).map(|pragma| (pragma, p.clone()))
+                }
+                _ => break,
+            },
+            _ => break,
+        };
+        if let Some((pragma, p)) = pragma {
+            *stmt = S::Stmt::Pragma(pragma, p);
+        }
+    }
+}
+
+/// The pragmas in the comments that immediately precede `span`, i.e., that
+/// are only separated from it by whitespace and other comments.
+///
+/// We find these by scanning the source backwards, instead of asking SWC to
+/// collect comments, so that we do not need to thread the comments through
+/// every parsing function. Trailing line comments (`x; // ...`) belong to the
+/// previous statement, so we only consider line comments that start a line.
+fn leading_pragmas(span: Span, source_map: &Rc<SourceMap>) -> Vec<S::Stmt> {
+    let file = source_map.lookup_byte_offset(span.lo);
+    let mut text = file.sf.src[..file.pos.0 as usize].trim_end();
+    let mut comments = Vec::new();
+    loop {
+        if let Some(start) = text.rfind("/*") {
+            if let Some(comment) = text[start + 2..].strip_suffix("*/") {
+                comments.push(comment);
+                text = text[..start].trim_end();
+                continue;
+            }
+        }
+        let line_start = text.rfind('\n').map_or(0, |i| i + 1);
+        if let Some(i) = text[line_start..].find("//") {
+            if text[line_start..line_start + i].trim().is_empty() {
+                comments.push(&text[line_start + i + 2..]);
+                text = text[..line_start].trim_end();
+                continue;
+            }
+        }
+        break;
+    }
+    let pos = Pos::from_swc(source_map, span);
+    comments
         .into_iter()
-        .map(|stmt| parse_stmt(stmt, source_map))
+        .rev()
+        .filter_map(Pragma::from_comment)
+        .map(|pragma| S::Stmt::Pragma(pragma, pos.clone()))
         .collect()
 }
 
@@ -149,7 +491,7 @@ fn parse_stmt(stmt: swc::Stmt, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
             Ok(decl)
         }
         DoWhile(do_while_stmt) => {
-            let body = parse_stmt(*do_while_stmt.body, source_map)?;
+            let body = parse_body(*do_while_stmt.body, source_map)?;
             let test = parse_expr(*do_while_stmt.test, source_map)?;
             Ok(dowhile_(
                 body,
@@ -192,7 +534,7 @@ fn parse_stmt(stmt: swc::Stmt, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
                 init,
                 parse_opt_expr(for_stmt.test, source_map)?,
                 parse_opt_expr(for_stmt.update, source_map)?,
-                parse_stmt(*for_stmt.body, source_map)?,
+                parse_body(*for_stmt.body, source_map)?,
                 Pos::from_swc(source_map, for_stmt.span),
             ))
         }
@@ -202,85 +544,40 @@ fn parse_stmt(stmt: swc::Stmt, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
             body,
             span,
         }) => {
-            // figure out if we're declaring a variable as part of this for in,
-            // or if we're reusing an already-bound identifier. if it's neither
-            // of these, we don't support it.
-            let (is_var, id) = match left {
-                // re-using an already-bound identifier
-                swc::VarDeclOrPat::Pat(swc::Pat::Ident(ident)) => (false, ident),
-                swc::VarDeclOrPat::Pat(swc::Pat::Expr(boxed_expr)) => {
-                    // nested match because you can't match inside boxes without
-                    // nightly rust
-                    match *boxed_expr {
-                        swc::Expr::Ident(ident) => (false, ident),
-                        _ => {
-                            return unsupported_message(
-                                "unsupported expression in a for-in loop declaration",
-                                span,
-                                source_map,
-                            );
-                        }
-                    }
-                }
-
-                // var case
-                swc::VarDeclOrPat::VarDecl(swc::VarDecl {
-                    span,
-                    kind: swc::VarDeclKind::Var, // no `let`
-                    declare: _,
-                    mut decls,
-                }) => {
-                    if decls.len() != 1 {
-                        return unsupported_message(
-                            "only a single var decl is allowed",
-                            span,
-                            source_map,
-                        );
-                    }
-                    match decls.remove(0) {
-                        // a single decl
-                        swc::VarDeclarator {
-                            span: _,
-                            init: None,                   // no initializer
-                            name: swc::Pat::Ident(ident), // no obj destructuring
-                            definite: _,
-                        } => (true, ident),
-                        // any other type of decl
-                        _ => {
-                            return unsupported_message(
-                                "only var decls are allowed here",
-                                span,
-                                source_map,
-                            );
-                        }
-                    }
-                }
-
-                // The program may pattern match on the index, which we do not support.
-                other => {
-                    return unsupported_message(
-                        &format!("unsupported index in a for-in loop: {:?}", other),
-                        span,
-                        source_map,
-                    );
-                }
-            };
-
+            let (is_var, id) = parse_for_binding(left, "for-in", span, source_map)?;
             Ok(forin_(
                 is_var,
                 parse_id(id),
                 parse_expr(*right, source_map)?,
-                parse_stmt(*body, source_map)?,
+                parse_body(*body, source_map)?,
+                Pos::from_swc(source_map, span),
+            ))
+        }
+        ForOf(swc::ForOfStmt {
+            span,
+            await_token,
+            left,
+            right,
+            body,
+        }) => {
+            if await_token.is_some() {
+                return unsupported_message("for-await loop", span, source_map);
+            }
+            let (is_var, id) = parse_for_binding(left, "for-of", span, source_map)?;
+            Ok(forof_(
+                is_var,
+                parse_id(id),
+                parse_expr(*right, source_map)?,
+                parse_body(*body, source_map)?,
                 Pos::from_swc(source_map, span),
             ))
         }
-        ForOf(for_of_stmt) => unsupported!(for_of_stmt.span, source_map),
         If(if_stmt) => {
             // test
             let cond_expr = parse_expr(*if_stmt.test, source_map)?;
 
             // consequent
-            let then_stmt = parse_stmt(*if_stmt.cons, source_map)?;
+            let then_stmt = parse_body(*if_stmt.cons, source_map)?;
 
             // alternate
             let else_stmt = parse_opt_stmt(if_stmt.alt, source_map)?;
@@ -292,11 +589,22 @@ fn parse_stmt(stmt: swc::Stmt, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
                 Pos::from_swc(source_map, if_stmt.span),
             ))
         }
-        Labeled(labeled_stmt) => Ok(label_(
-            parse_id(labeled_stmt.label),
-            parse_stmt(*labeled_stmt.body, source_map)?,
-            Pos::from_swc(source_map, labeled_stmt.span),
-        )),
+        Labeled(labeled_stmt) => {
+            // The pragmas between the label and its body go before the
+            // label, since `continue` needs the label on the loop itself.
+            let mut stmts = leading_pragmas(labeled_stmt.body.span(), source_map);
+            let s = Pos::from_swc(source_map, labeled_stmt.span);
+            stmts.push(label_(
+                parse_id(labeled_stmt.label),
+                parse_stmt(*labeled_stmt.body, source_map)?,
+                s.clone(),
+            ));
+            if stmts.len() == 1 {
+                Ok(stmts.pop().unwrap())
+            } else {
+                Ok(S::Stmt::Block(stmts, s))
+            }
+        }
         Return(return_stmt) => Ok(return_(
             parse_opt_expr(return_stmt.arg, source_map)?,
             Pos::from_swc(source_map, return_stmt.span),
@@ -382,7 +690,7 @@ fn parse_stmt(stmt: swc::Stmt, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
         }
         While(while_stmt) => {
             let test = parse_expr(*while_stmt.test, source_map)?;
-            let body = parse_stmt(*while_stmt.body, source_map)?;
+            let body = parse_body(*while_stmt.body, source_map)?;
             Ok(while_(
                 test,
                 body,
@@ -401,7 +709,7 @@ fn parse_opt_stmt(
 ) -> ParseResult<S::Stmt> {
     match opt_stmt {
         None => Ok(S::Stmt::Empty),
-        Some(stmt) => Ok(parse_stmt(*stmt, source_map)?),
+        Some(stmt) => Ok(parse_body(*stmt, source_map)?),
     }
 }
 
@@ -409,13 +717,7 @@ fn parse_opt_stmt(
 fn parse_expr(expr: swc::Expr, source_map: &Rc<SourceMap>) -> ParseResult<S::Expr> {
     use swc::Expr::*;
     match expr {
-        Array(swc::ArrayLit { elems, span }) => {
-            let elems: ParseResult<Vec<_>> = elems
-                .into_iter()
-                .map(|e| parse_opt_expr_or_spread(e, source_map))
-                .collect();
-            Ok(S::Expr::Array(elems?, Pos::from_swc(source_map, span)))
-        }
+        Array(swc::ArrayLit { elems, span }) => parse_array_lit(elems, span, source_map),
         Arrow(arrow_expr) => unsupported!(arrow_expr.span, source_map),
         Assign(swc::AssignExpr {
             left,
@@ -428,7 +730,10 @@ fn parse_expr(expr: swc::Expr, source_map: &Rc<SourceMap>) -> ParseResult<S::Exp
             let right = parse_expr(*right, source_map)?;
             Ok(op_assign_(op, left, right, Pos::from_swc(source_map, span)))
         }
-        Await(await_expr) => unsupported!(await_expr.span, source_map),
+        Await(swc::AwaitExpr { span, arg }) => Ok(S::Expr::Await(
+            Box::new(parse_expr(*arg, source_map)?),
+            Pos::from_swc(source_map, span),
+        )),
         Bin(swc::BinExpr {
             op,
             left,
@@ -441,19 +746,32 @@ fn parse_expr(expr: swc::Expr, source_map: &Rc<SourceMap>) -> ParseResult<S::Exp
 
             Ok(binary_(op, left, right, Pos::from_swc(source_map, span)))
         }
-        Class(class_expr) => unsupported!(class_expr.class.span, source_map),
+        Class(class_expr) => {
+            unsupported_message("class expression", class_expr.class.span, source_map)
+        }
         Call(swc::CallExpr {
             args,
             callee,
             span,
             type_args,
         }) => {
+            let callee = parse_expr_or_super(callee, source_map)?;
+            let pos = Pos::from_swc(source_map, span);
+            // `f(a, ...xs)` applies `f` to the array `[a, ...xs]`, and `f(...xs)`
+            // applies it to `xs` itself, since jnks_apply does not keep the array.
+            if args.iter().any(|e| e.spread.is_some()) {
+                let args = if args.len() == 1 {
+                    parse_expr(*args.into_iter().next().unwrap().expr, source_map)?
+                } else {
+                    parse_array_lit(args.into_iter().map(Some).collect(), span, source_map)?
+                };
+                return Ok(S::Expr::Apply(Box::new(callee), Box::new(args), pos));
+            }
             let args: ParseResult<Vec<_>> = args
                 .into_iter()
                 .map(|e| parse_expr_or_spread(e, source_map))
                 .collect();
-            let callee = parse_expr_or_super(callee, source_map);
-            Ok(call_(callee?, args?, Pos::from_swc(source_map, span)))
+            Ok(call_(callee, args?, pos))
         }
         Cond(swc::CondExpr {
             test,
@@ -473,13 +791,15 @@ fn parse_expr(expr: swc::Expr, source_map: &Rc<SourceMap>) -> ParseResult<S::Exp
                 None => None,
             };
             let span = function.span;
-            let (params, body) = parse_function(function, source_map)?;
+            let (params, body, kind) = parse_function(function, source_map)?;
+            reject_rest_param(&body, span, source_map)?;
 
             // put it all together
-            Ok(expr_func_(
+            Ok(S::Expr::Func(
                 ident,
                 params,
-                body,
+                Box::new(body),
+                kind,
                 Pos::from_swc(source_map, span),
             ))
         }
@@ -585,7 +905,20 @@ fn parse_expr(expr: swc::Expr, source_map: &Rc<SourceMap>) -> ParseResult<S::Exp
 
             Ok(unaryassign_(op, arg, Pos::from_swc(source_map, span)))
         }
-        Yield(yield_expr) => unsupported!(yield_expr.span, source_map),
+        Yield(swc::YieldExpr {
+            span,
+            arg,
+            delegate,
+        }) => {
+            if delegate {
+                return unsupported_message("yield*", span, source_map);
+            }
+            let arg = match arg {
+                Some(e) => Some(Box::new(parse_expr(*e, source_map)?)),
+                None => None,
+            };
+            Ok(S::Expr::Yield(arg, Pos::from_swc(source_map, span)))
+        }
     }
 }
 
@@ -649,6 +982,113 @@ fn parse_var_declarator(
     })
 }
 
+/// Parse the declarators of a `var` statement, which may destructure values. A statement that
+/// mixes patterns and variables, e.g., `var x = 1, [a, b] = arr, y = 2;`, becomes a block of
+/// `VarDecl` and `Destructure` statements, which preserves the order of the initializers.
+fn parse_var_declarators(
+    decls: Vec<swc::VarDeclarator>,
+    span: Span,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<S::Stmt> {
+    let pos = Pos::from_swc(source_map, span);
+    let mut stmts = Vec::new();
+    let mut var_decls = Vec::new();
+    for decl in decls {
+        match decl.name {
+            swc::Pat::Array(_) | swc::Pat::Object(_) => {
+                if !var_decls.is_empty() {
+                    stmts.push(S::Stmt::VarDecl(
+                        std::mem::replace(&mut var_decls, Vec::new()),
+                        pos.clone(),
+                    ));
+                }
+                let init = match decl.init {
+                    Some(init) => parse_expr(*init, source_map)?,
+                    None => {
+                        return unsupported_message(
+                            "destructuring declaration without an initializer",
+                            decl.span,
+                            source_map,
+                        )
+                    }
+                };
+                stmts.push(S::Stmt::Destructure(
+                    parse_pattern(decl.name, decl.span, source_map)?,
+                    Box::new(init),
+                    Pos::from_swc(source_map, decl.span),
+                ));
+            }
+            _ => var_decls.push(parse_var_declarator(decl, source_map)?),
+        }
+    }
+    if !var_decls.is_empty() {
+        stmts.push(S::Stmt::VarDecl(var_decls, pos.clone()));
+    }
+    if stmts.len() == 1 {
+        Ok(stmts.remove(0))
+    } else {
+        Ok(S::Stmt::Block(stmts, pos))
+    }
+}
+
+/// Parse an swc pattern that declares variables. `span` should be the source
+/// location of the surrounding declaration. `span` is used for error reporting
+/// purposes.
+fn parse_pattern(
+    pattern: swc::Pat,
+    span: Span,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<S::Pattern> {
+    use swc::Pat::*;
+    match pattern {
+        Ident(ident) => Ok(S::Pattern::Id(parse_id(ident))),
+        Array(swc::ArrayPat { elems, .. }) => {
+            let elems: ParseResult<Vec<_>> = elems
+                .into_iter()
+                .map(|elem| match elem {
+                    Some(pat) => Ok(Some(parse_pattern(pat, span, source_map)?)),
+                    None => Ok(None),
+                })
+                .collect();
+            Ok(S::Pattern::Array(elems?))
+        }
+        Object(swc::ObjectPat { props, .. }) => {
+            let props: ParseResult<Vec<_>> = props
+                .into_iter()
+                .map(|prop| parse_object_pat_prop(prop, span, source_map))
+                .collect();
+            Ok(S::Pattern::Object(props?))
+        }
+        Rest(_) => unsupported_message("rest pattern", span, source_map),
+        Assign(_) => unsupported_message("default value in a pattern", span, source_map),
+        _ => unsupported!(span, source_map),
+    }
+}
+
+/// `span` is the span of the surrounding declaration.
+fn parse_object_pat_prop(
+    prop: swc::ObjectPatProp,
+    span: Span,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<(S::Key, S::Pattern)> {
+    use swc::ObjectPatProp::*;
+    match prop {
+        KeyValue(swc::KeyValuePatProp { key, value }) => Ok((
+            parse_prop_name(key, span, source_map)?,
+            parse_pattern(*value, span, source_map)?,
+        )),
+        // `{x}` is short for `{x: x}`.
+        Assign(swc::AssignPatProp {
+            key, value: None, ..
+        }) => Ok((
+            S::Key::Str(key.sym.to_string()),
+            S::Pattern::Id(parse_id(key)),
+        )),
+        Assign(_) => unsupported_message("default value in a pattern", span, source_map),
+        Rest(_) => unsupported_message("rest pattern", span, source_map),
+    }
+}
+
 fn parse_switch_case(
     case: swc::SwitchCase,
     source_map: &Rc<SourceMap>,
@@ -670,7 +1110,50 @@ fn parse_expr_or_super(eos: swc::ExprOrSuper, source_map: &Rc<SourceMap>) -> Par
     use swc::ExprOrSuper::*;
     match eos {
         Expr(expr) => parse_expr(*expr, source_map),
-        Super(swc::Super { span }) => unsupported!(span, source_map),
+        // `super` is a keyword, so it cannot be the name of a variable. The
+        // enclosing class replaces it (see desugar_classes).
+        Super(swc::Super { span }) => Ok(id_("super", Pos::from_swc(source_map, span))),
+    }
+}
+
+/// Parse an array literal. The spread elements of `[a, ...xs, b]` become
+/// `[a].concat(xs).concat([b])`, which evaluates the elements in order. Since
+/// `concat` only takes apart arrays, so does spread.
+fn parse_array_lit(
+    elems: Vec<Option<swc::ExprOrSpread>>,
+    span: Span,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<S::Expr> {
+    let pos = Pos::from_swc(source_map, span);
+    let concat = |array: S::Expr, elems: S::Expr| {
+        call_(dot_(array, "concat", pos.clone()), vec![elems], pos.clone())
+    };
+    // The array up to the last spread element, and the elements after it.
+    let mut array = None;
+    let mut rest = Vec::new();
+    for elem in elems {
+        match elem {
+            Some(swc::ExprOrSpread {
+                spread: Some(_),
+                expr,
+            }) => {
+                let spread = parse_expr(*expr, source_map)?;
+                let prefix = S::Expr::Array(std::mem::replace(&mut rest, Vec::new()), pos.clone());
+                array = Some(match array {
+                    None => concat(prefix, spread),
+                    Some(array) => match prefix {
+                        S::Expr::Array(ref elems, _) if elems.is_empty() => concat(array, spread),
+                        prefix => concat(concat(array, prefix), spread),
+                    },
+                });
+            }
+            elem => rest.push(parse_opt_expr_or_spread(elem, source_map)?),
+        }
+    }
+    match array {
+        None => Ok(S::Expr::Array(rest, pos)),
+        Some(array) if rest.is_empty() => Ok(array),
+        Some(array) => Ok(concat(array, S::Expr::Array(rest, pos.clone()))),
     }
 }
 
@@ -680,7 +1163,9 @@ fn parse_expr_or_spread(
 ) -> ParseResult<S::Expr> {
     match eos.spread {
         None => parse_expr(*eos.expr, source_map),
-        Some(span) => unsupported!(span, source_map),
+        // Calls apply the function to an array instead (see [parse_expr]), but
+        // there is no such form for `new`.
+        Some(span) => unsupported_message("spread argument of new", span, source_map),
     }
 }
 
@@ -694,8 +1179,58 @@ fn parse_opt_expr_or_spread(
     }
 }
 
-fn parse_func_arg(arg: swc::Param, source_map: &Rc<SourceMap>) -> ParseResult<S::Id> {
-    Ok(parse_id_from_pattern(arg.pat, arg.span, source_map)?)
+/// Parse the `index`th argument of a function, which produces its name and
+/// the statements that the caller puts at the start of the body.
+///
+/// - An argument with a default value, `x = e`, produces `DefaultParam(x, e)`,
+///   which desugar_params lowers.
+/// - A rest argument, `...x`, produces `RestParam(x)`, which desugar_params
+///   lowers.
+/// - An argument that is a pattern produces a placeholder name for the
+///   argument, and a declaration that destructures it. The parser does not
+///   have a name generator, so the placeholder is named after the position of
+///   the argument, with the `$jnks_` prefix that the compiler reserves for its
+///   own names.
+fn parse_func_arg(
+    index: usize,
+    pat: swc::Pat,
+    span: Span,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<(S::Id, Vec<S::Stmt>)> {
+    let pos = Pos::from_swc(source_map, span);
+    match pat {
+        swc::Pat::Array(_) | swc::Pat::Object(_) => {
+            let x = S::Id::Named(format!("$jnks_arg{}", index));
+            let pattern = parse_pattern(pat, span, source_map)?;
+            let destructure =
+                S::Stmt::Destructure(pattern, Box::new(id_(x.clone(), pos.clone())), pos);
+            Ok((x, vec![destructure]))
+        }
+        swc::Pat::Assign(swc::AssignPat { left, right, .. }) => {
+            let (x, mut stmts) = parse_func_arg(index, *left, span, source_map)?;
+            let default =
+                S::Stmt::DefaultParam(x.clone(), Box::new(parse_expr(*right, source_map)?), pos);
+            stmts.insert(0, default);
+            Ok((x, stmts))
+        }
+        swc::Pat::Rest(swc::RestPat { arg, .. }) => {
+            let x = parse_id_from_pattern(*arg, span, source_map)?;
+            Ok((x.clone(), vec![S::Stmt::RestParam(x, pos)]))
+        }
+        pat => Ok((parse_id_from_pattern(pat, span, source_map)?, vec![])),
+    }
+}
+
+/// Rejects a rest argument of a function that is not a function statement.
+/// desugar_params packs the extra arguments of a call into an array, so it must
+/// know the function that the call calls.
+fn reject_rest_param(body: &S::Stmt, span: Span, source_map: &Rc<SourceMap>) -> ParseResult<()> {
+    if let S::Stmt::Block(stmts, _) = body {
+        if stmts.iter().any(|s| matches!(s, S::Stmt::RestParam(..))) {
+            return unsupported_message("rest argument of a function expression", span, source_map);
+        }
+    }
+    Ok(())
 }
 
 fn parse_lit(lit: swc::Lit, source_map: &Rc<SourceMap>) -> ParseResult<(S::Lit, Span)> {
@@ -991,13 +1526,12 @@ fn parse_decl(decl: swc::Decl, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
             kind, //: swc::VarDeclKind::Var,
             declare: _,
             decls,
-        }) => {
-            let decls: ParseResult<Vec<_>> = decls
-                .into_iter()
-                .map(|d| parse_var_declarator(d, source_map))
-                .collect();
-            Ok(S::Stmt::VarDecl(decls?, Pos::from_swc(source_map, span)))
-        }
+        }) => parse_var_declarators(decls, span, source_map),
+        Class(swc::ClassDecl {
+            ident,
+            declare: _,
+            class,
+        }) => parse_class(ident, class, source_map),
         Fn(swc::FnDecl {
             ident,
             declare: _,
@@ -1005,11 +1539,12 @@ fn parse_decl(decl: swc::Decl, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
         }) => {
             let ident = parse_id(ident);
             let span = function.span;
-            let (params, body) = parse_function(function, source_map)?;
+            let (params, body, kind) = parse_function(function, source_map)?;
             Ok(S::Stmt::Func(
                 ident,
                 params,
                 Box::new(body),
+                kind,
                 Pos::from_swc(source_map, span),
             ))
         }
@@ -1020,7 +1555,7 @@ fn parse_decl(decl: swc::Decl, source_map: &Rc<SourceMap>) -> ParseResult<S::Stm
 fn parse_function(
     function: swc::Function,
     source_map: &Rc<SourceMap>,
-) -> ParseResult<(Vec<S::Id>, S::Stmt)> {
+) -> ParseResult<(Vec<S::Id>, S::Stmt, S::FuncKind)> {
     let swc::Function {
         params,
         decorators,
@@ -1031,26 +1566,147 @@ fn parse_function(
         ..
     } = function;
     // rule out cases we don't handle
-    if is_generator {
-        return unsupported_message("generators not supported", span, source_map);
-    }
-    if is_async {
-        return unsupported_message("async not supported", span, source_map);
-    }
+    let kind = match (is_generator, is_async) {
+        (false, false) => S::FuncKind::Plain,
+        (true, false) => S::FuncKind::Generator,
+        (false, true) => S::FuncKind::Async,
+        (true, true) => return unsupported_message("async generator", span, source_map),
+    };
     if decorators.len() > 0 {
         return unsupported_message("class decorators not supported", span, source_map);
     }
+    let (params, body) = parse_params_and_body(params, body, source_map)?;
+    Ok((params, body, kind))
+}
+
+/// Parse the arguments and body of a function, which may also be the
+/// constructor of a class.
+fn parse_params_and_body(
+    params: Vec<swc::Param>,
+    body: Option<swc::BlockStmt>,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<(Vec<S::Id>, S::Stmt)> {
     let params: ParseResult<Vec<_>> = params
         .into_iter()
-        .map(|p| parse_func_arg(p, source_map))
+        .enumerate()
+        .map(|(i, p)| parse_func_arg(i, p.pat, p.span, source_map))
         .collect();
+    let (params, prologue): (Vec<_>, Vec<_>) = params?.into_iter().unzip();
+    let prologue = prologue.into_iter().flatten();
     let body = match body {
-        Some(block) => parse_block(block, source_map)?,
+        Some(block) => {
+            let mut stmts = parse_stmts(block.stmts, source_map)?;
+            parse_directives(&mut stmts);
+            // The prologue goes after the directives, so that they still
+            // apply to the whole body.
+            let start = stmts
+                .iter()
+                .take_while(|s| matches!(s, S::Stmt::Pragma(..)))
+                .count();
+            stmts.splice(start..start, prologue);
+            S::Stmt::Block(stmts, Pos::from_swc(source_map, block.span))
+        }
         None => S::Stmt::Empty,
     };
 
     // put it all together
-    Ok((params?, body))
+    Ok((params, body))
+}
+
+/// Parse a class declaration, which desugar_classes lowers. The superclass
+/// must be a variable or a field of one, since the desugaring copies it.
+/// Getters, setters, and fields are not supported.
+fn parse_class(
+    ident: swc::Ident,
+    class: swc::Class,
+    source_map: &Rc<SourceMap>,
+) -> ParseResult<S::Stmt> {
+    fn is_path(e: &S::Expr) -> bool {
+        match e {
+            S::Expr::Id(..) => true,
+            S::Expr::Dot(e, _, _) => is_path(e),
+            _ => false,
+        }
+    }
+    let swc::Class {
+        span,
+        decorators,
+        body,
+        super_class,
+        ..
+    } = class;
+    if decorators.len() > 0 {
+        return unsupported_message("class decorators not supported", span, source_map);
+    }
+    let super_class = match super_class {
+        None => None,
+        Some(e) => match parse_expr(*e, source_map)? {
+            e if is_path(&e) => Some(Box::new(e)),
+            _ => return unsupported_message("superclass that is not a variable", span, source_map),
+        },
+    };
+    let mut constructor = None;
+    let mut methods = Vec::new();
+    for member in body {
+        match member {
+            swc::ClassMember::Constructor(swc::Constructor {
+                span, params, body, ..
+            }) => {
+                let params: ParseResult<Vec<_>> = params
+                    .into_iter()
+                    .map(|p| match p {
+                        swc::ParamOrTsParamProp::Param(p) => Ok(p),
+                        swc::ParamOrTsParamProp::TsParamProp(p) => unsupported!(p.span, source_map),
+                    })
+                    .collect();
+                // The class binds the constructor with a `var`, so it may have a rest
+                // argument (see desugar_params).
+                let (params, body) = parse_params_and_body(params?, body, source_map)?;
+                let pos = Pos::from_swc(source_map, span);
+                constructor = Some(Box::new(S::Expr::Func(
+                    None,
+                    params,
+                    Box::new(body),
+                    S::FuncKind::Plain,
+                    pos,
+                )));
+            }
+            swc::ClassMember::Method(swc::ClassMethod {
+                span,
+                key,
+                function,
+                kind,
+                is_static,
+                ..
+            }) => {
+                match kind {
+                    swc::MethodKind::Method => (),
+                    _ => return unsupported_message("getter or setter", span, source_map),
+                }
+                let key = parse_prop_name(key, span, source_map)?;
+                let (params, body, kind) = parse_function(function, source_map)?;
+                reject_rest_param(&body, span, source_map)?;
+                let pos = Pos::from_swc(source_map, span);
+                methods.push(S::Method {
+                    is_static,
+                    key,
+                    func: S::Expr::Func(None, params, Box::new(body), kind, pos),
+                });
+            }
+            swc::ClassMember::Empty(_) => (),
+            _ => return unsupported_message("class member", span, source_map),
+        }
+    }
+    let class = S::Class {
+        name: parse_id(ident),
+        super_class,
+        constructor,
+        methods,
+    };
+    Ok(S::Stmt::Class(
+        Box::new(class),
+        Pos::from_swc(source_map, span),
+    ))
 }
 
 /// Convert a numeric value from the parser into our AST's numbers.
@@ -1099,3 +1755,133 @@ fn span_from_decl(decl: swc::Decl) -> Span {
         | TsModule(TsModuleDecl { span, .. }) => span,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::{desugar, NameGen};
+    use super::*;
+    use crate::jankyscript::from_js::from_javascript;
+    use crate::jankyscript::syntax as J;
+
+    /// The errors that [parse_with_recovery] reports for `js_code`.
+    fn errors(js_code: &str) -> Vec<String> {
+        match parse_with_recovery("test.js", js_code) {
+            Err(ParseError::Multiple(errors)) => errors.iter().map(|e| e.to_string()).collect(),
+            Err(e) => panic!("expected several errors, got {}", e),
+            Ok(_) => panic!("expected errors"),
+        }
+    }
+
+    /// The line of the position of an error, e.g., 2 in `... at test.js:2:9: 2:10`.
+    fn line(error: &str) -> usize {
+        let name = error.find("test.js").expect("error without a position");
+        let start = name + error[name..].find(':').unwrap() + 1;
+        let end = start + error[start..].find(':').unwrap();
+        error[start..end].parse().unwrap()
+    }
+
+    #[test]
+    fn reports_separated_errors() {
+        let errors = errors(
+            "var a = 1;
+var b = ;
+var c = 2;
+var d = );
+var e = 3;
+var f = * 2;
+var g = 4;",
+        );
+        assert_eq!(
+            errors.iter().map(|e| line(e)).collect::<Vec<_>>(),
+            vec![2, 4, 6]
+        );
+    }
+
+    #[test]
+    fn resynchronizes_after_a_multi_line_statement() {
+        let errors = errors(
+            "function f() {
+    var x = ;
+    return x;
+}
+var o = {
+    a: 1,
+    b: 2
+};
+var y = );",
+        );
+        assert_eq!(
+            errors.iter().map(|e| line(e)).collect::<Vec<_>>(),
+            vec![2, 9]
+        );
+    }
+
+    #[test]
+    fn sorts_errors_by_position() {
+        let errors = errors("debugger;\nvar x = ;");
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("Unsupported"));
+        assert!(errors[1].starts_with("Syntax error"));
+    }
+
+    /// The pragmas of the JankyScript statement `stmt` and of the statements in it, with the
+    /// variable that the statement after each pragma assigns, if it is an assignment.
+    fn annotated(stmt: &J::Stmt, pragmas: &mut Vec<(Pragma, Option<J::Id>)>) {
+        match stmt {
+            J::Stmt::Block(stmts, _) => {
+                for (i, stmt) in stmts.iter().enumerate() {
+                    if let J::Stmt::Pragma(pragma, _) = stmt {
+                        let assigned = match stmts.get(i + 1) {
+                            Some(J::Stmt::Expr(e, _)) => match &**e {
+                                J::Expr::Assign(lv, _, _) => match &**lv {
+                                    J::LValue::Id(x, _) => Some(x.clone()),
+                                    _ => None,
+                                },
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        pragmas.push((pragma.clone(), assigned));
+                    }
+                    annotated(stmt, pragmas);
+                }
+            }
+            J::Stmt::If(_, s1, s2, _) => {
+                annotated(s1, pragmas);
+                annotated(s2, pragmas);
+            }
+            J::Stmt::Loop(s, _) | J::Stmt::Label(_, s, _) => annotated(s, pragmas),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn pragmas_reach_jankyscript() {
+        let mut js = parse(
+            "test.js",
+            r#""use strict";
+var n = 0;
+while (n < 10) {
+    /* jankscripten: assume-int */
+    n = n + 1;
+}
+if (n > 5) /* jankscripten: assume-int */ n = n - 1;"#,
+        )
+        .unwrap();
+        desugar(&mut js, &mut NameGen::default());
+        let janky = from_javascript(js);
+        match &janky {
+            J::Stmt::Block(stmts, _) => {
+                assert!(matches!(stmts[0], J::Stmt::Pragma(Pragma::UseStrict, _)))
+            }
+            _ => panic!("expected a block"),
+        }
+        let mut pragmas = Vec::new();
+        annotated(&janky, &mut pragmas);
+        let assume_int = (Pragma::AssumeInt, Some(J::Id::from("n")));
+        assert_eq!(
+            pragmas,
+            vec![(Pragma::UseStrict, None), assume_int.clone(), assume_int]
+        );
+    }
+}
@@ -23,6 +23,7 @@ impl Stmt {
                 .append(D::line())
                 .append(D::text("}")),
             Empty => D::text(";"),
+            Pragma(pragma, _) => D::text(pragma.to_string()),
             Expr(e, _) => D::text("(")
                 .append(e.to_doc())
                 .append(D::text(")"))
@@ -78,6 +79,13 @@ impl Stmt {
                 .append(container.to_doc())
                 .append(D::text(") "))
                 .append(body.to_doc()),
+            ForOf(is_decl, name, container, body, _) => D::text("for (")
+                .append(if *is_decl { D::text("var ") } else { D::nil() })
+                .append(name.to_doc())
+                .append(D::text(" of "))
+                .append(container.to_doc())
+                .append(D::text(") "))
+                .append(body.to_doc()),
             Label(name, stmt, _) => name.to_doc().append(D::text(": ")).append(stmt.to_doc()),
             Break(maybe_lbl, _) => D::text("break")
                 .append(option_label_to_doc(maybe_lbl))
@@ -97,7 +105,21 @@ impl Stmt {
                 .append(final_block.to_doc()),
             Throw(e, _) => D::text("throw ").append(e.to_doc()).append(D::text(";")),
             VarDecl(decls, _) => vardecls_to_doc(decls).append(D::text(";")),
-            Func(name, params, body, _) => func_to_doc(Some(name), params, body),
+            Destructure(pat, e, _) => D::text("var ")
+                .append(pattern_to_doc(pat))
+                .append(D::text(" = "))
+                .append(e.to_doc())
+                .append(D::text(";")),
+            DefaultParam(x, e, _) => D::text("if (")
+                .append(x.to_doc())
+                .append(D::text(" === undefined) "))
+                .append(x.to_doc())
+                .append(D::text(" = "))
+                .append(e.to_doc())
+                .append(D::text(";")),
+            RestParam(x, _) => D::text("/* ...").append(x.to_doc()).append(D::text(" */")),
+            Func(name, params, body, kind, _) => func_to_doc(Some(name), params, body, *kind),
+            Class(class, _) => class_to_doc(class),
             Return(e, _) => D::text("return ").append(e.to_doc()).append(D::text(";")),
         }
     }
@@ -169,6 +191,18 @@ impl Expr {
                 .append(D::space())
                 .append(to.to_doc()),
             Call(clos, args, _) => fn_call_to_doc(clos, args),
+            Apply(f, args, _) => f
+                .to_doc()
+                .append(D::text("(..."))
+                .append(args.to_doc())
+                .append(D::text(")")),
+            CallThis(f, this, args, _) => f.to_doc().append(D::text(".call(")).append(
+                D::intersperse(
+                    std::iter::once(this.to_doc()).chain(args.iter().map(|e| e.to_doc())),
+                    D::text(", "),
+                )
+                .append(D::text(")")),
+            ),
             MethodCall(obj, method, args, _) => obj.to_doc().append(
                 D::text(".").append(D::text(method)).append(
                     D::text("(")
@@ -180,10 +214,19 @@ impl Expr {
                 ),
             ),
             Length(obj, _) => obj.to_doc().append(D::text(".length")),
-            Func(maybe_name, params, body, _) => func_to_doc(maybe_name.as_ref(), params, body),
+            Func(maybe_name, params, body, kind, _) => {
+                func_to_doc(maybe_name.as_ref(), params, body, *kind)
+            }
             Seq(es, _) => D::text("(")
                 .append(D::intersperse(es.iter().map(Expr::to_doc), D::text(", ")))
                 .append(D::text(")")),
+            Yield(e, _) => D::text("(yield")
+                .append(match e {
+                    Some(e) => D::space().append(e.to_doc()),
+                    None => D::nil(),
+                })
+                .append(D::text(")")),
+            Await(e, _) => D::text("(await ").append(e.to_doc()).append(D::text(")")),
         }
     }
     pub fn to_pretty(&self, width: usize) -> String {
@@ -333,6 +376,33 @@ fn assign_op_to_doc(op: &AssignOp) -> D<()> {
     })
 }
 
+fn pattern_to_doc(pat: &Pattern) -> D<()> {
+    match pat {
+        Pattern::Id(x) => x.to_doc(),
+        Pattern::Array(elems) => D::text("[")
+            .append(D::intersperse(
+                elems.iter().map(|elem| match elem {
+                    Some(pat) => pattern_to_doc(pat),
+                    None => D::nil(),
+                }),
+                D::text(", "),
+            ))
+            .append(D::text("]")),
+        Pattern::Object(props) => D::text("{")
+            .append(D::intersperse(
+                props.iter().map(|(key, pat)| {
+                    let key = match key {
+                        Key::Int(n) => D::text(format!("{}", n)),
+                        Key::Str(s) => D::text(s),
+                    };
+                    key.append(D::text(": ")).append(pattern_to_doc(pat))
+                }),
+                D::text(", "),
+            ))
+            .append(D::text("}")),
+    }
+}
+
 fn vardecls_to_doc(decls: &[VarDecl]) -> D<()> {
     let first = decls.first().expect("wouldn't exist without one");
     let rest = &decls[1..];
@@ -374,18 +444,99 @@ fn option_label_to_doc(maybe_lbl: &Option<Id>) -> D<()> {
     }
 }
 
-fn func_to_doc<'a>(maybe_name: Option<&'a Id>, params: &'a [Id], body: &'a Stmt) -> D<'a, ()> {
-    D::text("function")
+fn func_to_doc<'a>(
+    maybe_name: Option<&'a Id>,
+    params: &'a [Id],
+    body: &'a Stmt,
+    kind: FuncKind,
+) -> D<'a, ()> {
+    let keyword = match kind {
+        FuncKind::Plain => "function",
+        FuncKind::Generator => "function*",
+        FuncKind::Async => "async function",
+    };
+    D::text(keyword)
         .append(match maybe_name {
             Some(name) => D::space().append(name.to_doc()),
             None => D::nil(),
         })
-        .append(D::text("("))
-        .append(D::intersperse(params.iter().map(Id::to_doc), D::text(", ")))
+        .append(params_and_body_to_doc(params, body))
+}
+
+fn params_and_body_to_doc<'a>(params: &'a [Id], body: &'a Stmt) -> D<'a, ()> {
+    D::text("(")
+        .append(D::intersperse(
+            params.iter().map(|x| {
+                if is_rest_param(x, body) {
+                    D::text("...").append(x.to_doc())
+                } else {
+                    x.to_doc()
+                }
+            }),
+            D::text(", "),
+        ))
         .append(D::text(") "))
         .append(body.to_doc())
 }
 
+fn class_to_doc(class: &syntax::Class) -> D<()> {
+    // `name(params) body`, or a field `name = e;` if the method is not a function
+    fn method_to_doc<'a>(name: D<'a, ()>, func: &'a Expr) -> D<'a, ()> {
+        match func {
+            Expr::Func(_, params, body, FuncKind::Plain, _) => {
+                name.append(params_and_body_to_doc(params, body))
+            }
+            Expr::Func(_, params, body, FuncKind::Generator, _) => D::text("*")
+                .append(name)
+                .append(params_and_body_to_doc(params, body)),
+            Expr::Func(_, params, body, FuncKind::Async, _) => D::text("async ")
+                .append(name)
+                .append(params_and_body_to_doc(params, body)),
+            e => name.append(D::text(" = ")).append(e.to_doc()).append(";"),
+        }
+    }
+    let constructor = class
+        .constructor
+        .iter()
+        .map(|func| method_to_doc(D::text("constructor"), func));
+    let methods = class.methods.iter().map(|method| {
+        let name = match &method.key {
+            Key::Int(n) => D::text(format!("{}", n)),
+            Key::Str(s) => D::text(s),
+        };
+        let name = if method.is_static {
+            D::text("static ").append(name)
+        } else {
+            name
+        };
+        method_to_doc(name, &method.func)
+    });
+    D::text("class ")
+        .append(class.name.to_doc())
+        .append(match &class.super_class {
+            Some(e) => D::text(" extends ").append(e.to_doc()),
+            None => D::nil(),
+        })
+        .append(D::text(" {"))
+        .append(
+            D::line()
+                .append(D::intersperse(constructor.chain(methods), D::line()))
+                .nest(INDENT),
+        )
+        .append(D::line())
+        .append(D::text("}"))
+}
+
+/// Produces `true` if `x` is the rest parameter of the function with the given body.
+fn is_rest_param(x: &Id, body: &Stmt) -> bool {
+    match body {
+        Stmt::Block(stmts, _) => stmts
+            .iter()
+            .any(|stmt| matches!(stmt, Stmt::RestParam(y, _) if x == y)),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::javascript::parse;
@@ -527,6 +678,15 @@ mod test {
         );
     }
 
+    #[test]
+    fn rest_params_and_spread_arguments() {
+        parse_pretty_parse(
+            "function f(x, ...ys) {
+    return g(x, ...ys, 1) + g(...ys) + o.m(...ys);
+}",
+        )
+    }
+
     #[test]
     fn top_level_application() {
         parse_pretty_parse(
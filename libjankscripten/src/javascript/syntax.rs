@@ -6,6 +6,7 @@
 
 use crate::pos::Pos;
 pub use crate::shared::Id;
+pub use crate::shared::Pragma;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum BinOp {
@@ -132,10 +133,32 @@ pub enum Expr {
     If(Box<Expr>, Box<Expr>, Box<Expr>, Pos),
     Assign(AssignOp, Box<LValue>, Box<Expr>, Pos),
     Call(Box<Expr>, Vec<Expr>, Pos),
+    /// `CallThis(f, this, args, Pos)` is `f.call(this, args...)`, which calls `f` with `this`
+    /// bound to the value of `this`. Classes use it for `super`.
+    CallThis(Box<Expr>, Box<Expr>, Vec<Expr>, Pos),
+    /// `Apply(f, args, Pos)` is `f(...args)`, which calls `f` with the elements of the array
+    /// `args`. The parser builds the array from the arguments of a call with spread arguments,
+    /// and desugaring turns it into a call to `jnks_apply`.
+    Apply(Box<Expr>, Box<Expr>, Pos),
     MethodCall(Id, String, Vec<Expr>, Pos),
     Length(Box<Expr>, Pos),
-    Func(Option<Id>, Vec<Id>, Box<Stmt>, Pos),
+    /// `Func(name, params, body, kind, Pos)`. Desugaring turns generator and async functions
+    /// into ordinary functions.
+    Func(Option<Id>, Vec<Id>, Box<Stmt>, FuncKind, Pos),
     Seq(Vec<Expr>, Pos),
+    /// `yield e`, or `yield` without `e`, in the body of a generator function.
+    Yield(Option<Box<Expr>>, Pos),
+    /// `await e` in the body of an async function.
+    Await(Box<Expr>, Pos),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FuncKind {
+    Plain,
+    /// `function* (...) { ... }`, which returns an iterator (see generators).
+    Generator,
+    /// `async function (...) { ... }`, which may await host operations (see generators).
+    Async,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -144,6 +167,36 @@ pub struct VarDecl {
     pub named: Box<Expr>,
 }
 
+/// The left-hand side of a destructuring declaration, e.g., `[a, , {x, y: z}]`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Id(Id),
+    /// `None` is a hole, which skips an element of the array.
+    Array(Vec<Option<Pattern>>),
+    /// `{x, y: z}` is `Object(vec![(x, Id(x)), (y, Id(z))])`.
+    Object(Vec<(Key, Pattern)>),
+}
+
+/// `class name extends super_class { constructor(...) { ... } methods }`, which desugaring turns
+/// into a constructor function and assignments to its prototype. The constructor and the methods
+/// are function expressions, whose bodies may refer to `super`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Class {
+    pub name: Id,
+    /// A variable or a field of one, since desugaring copies it.
+    pub super_class: Option<Box<Expr>>,
+    pub constructor: Option<Box<Expr>>,
+    pub methods: Vec<Method>,
+}
+
+/// `static key(...) { ... }` or `key(...) { ... }` in a class.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Method {
+    pub is_static: bool,
+    pub key: Key,
+    pub func: Expr,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum ForInit {
     Expr(Box<Expr>),
@@ -163,6 +216,9 @@ pub enum Stmt {
     /// `ForIn(true, x, .., Pos)` indicates `for (var x ...`.
     /// `ForIn(false, x, .., Pos)` indicates `for (x ...`.
     ForIn(bool, Id, Box<Expr>, Box<Stmt>, Pos),
+    /// `ForOf(is_var, x, coll, body, Pos)` is `for (var x of coll) body`, with the same meaning of
+    /// `is_var` as `ForIn`. Desugaring turns it into a `For`.
+    ForOf(bool, Id, Box<Expr>, Box<Stmt>, Pos),
     Label(Id, Box<Stmt>, Pos),
     Break(Option<Id>, Pos),
     Continue(Option<Id>, Pos),
@@ -176,8 +232,24 @@ pub enum Stmt {
     /// Could be:
     /// `var x = 10, y = 30;`
     VarDecl(Vec<VarDecl>, Pos),
-    Func(Id, Vec<Id>, Box<Stmt>, Pos),
+    /// `var [a, b] = e;` or `var {x, y} = e;`, which desugaring turns into a `VarDecl`.
+    Destructure(Pattern, Box<Expr>, Pos),
+    /// `DefaultParam(x, e, Pos)` gives the parameter `x` of `function f(x = e) { ... }` its
+    /// default. The parser puts it at the start of the body of the function, and desugaring turns
+    /// it into `if (x === undefined) { x = e; }`.
+    DefaultParam(Id, Box<Expr>, Pos),
+    /// `RestParam(x, Pos)` marks the last parameter `x` of `function f(..., ...x) { ... }`. The
+    /// parser puts it at the start of the body of the function, and desugaring removes it and
+    /// packs the extra arguments of calls to `f` into an array.
+    RestParam(Id, Pos),
+    /// `Func(name, params, body, kind, Pos)`, like `Expr::Func`.
+    Func(Id, Vec<Id>, Box<Stmt>, FuncKind, Pos),
+    /// A class declaration, which desugaring turns into a `VarDecl` of its constructor and
+    /// assignments to its prototype.
+    Class(Box<Class>, Pos),
     Return(Box<Expr>, Pos),
+    /// A hint for the compiler, which annotates the statement that follows.
+    Pragma(Pragma, Pos),
 }
 
 impl Expr {
@@ -233,11 +305,12 @@ impl UnaryAssignOp {
         }
     }
 
-    pub fn other_binop(&self) -> BinOp {
+    /// The prefix operator that makes the same update, e.g. `++` for `x++`.
+    pub fn to_prefix(&self) -> UnaryAssignOp {
         use UnaryAssignOp::*;
         match self {
-            PostInc | PreInc => BinOp::BinaryOp(BinaryOp::Minus),
-            PostDec | PreDec => BinOp::BinaryOp(BinaryOp::Plus),
+            PostInc | PreInc => PreInc,
+            PostDec | PreDec => PreDec,
         }
     }
 }
@@ -1,7 +1,6 @@
 //! A visitor for JavaScript ASTs.
 
 use super::syntax::*;
-use std::cell::RefCell;
 
 /// Statements, expressions, and other types of AST nodes have a `walk` method
 /// that receives an implementation of this `Visitor` trait.
@@ -27,58 +26,18 @@ pub trait Visitor {
     fn exit_stmt(&mut self, _stmt: &mut Stmt, _loc: &Loc) {}
     /// called after recursing on an expression, with the new value
     fn exit_expr(&mut self, _expr: &mut Expr, _loc: &Loc) {}
+    /// called before visiting each node. Once this produces `true`, the walk
+    /// ends without calling any more `enter_` or `exit_` methods
+    fn done(&self) -> bool {
+        false
+    }
 }
 
 struct VisitorState<'v, V> {
     visitor: &'v mut V,
 }
 
-#[derive(Debug)]
-pub struct BlockContext {
-    pub index: usize,
-    pub len: usize,
-    patches: RefCell<Vec<(usize, Stmt)>>,
-}
-
-impl BlockContext {
-    pub fn new(index: usize, len: usize) -> Self {
-        BlockContext {
-            index,
-            len,
-            patches: RefCell::new(vec![]),
-        }
-    }
-
-    /// Insert `stmt` at position `index` into the block. The position 0 is
-    /// before the first element, and the last position is after the last
-    /// element. E.g., if the block has statements `[s0, s1, s2]` then the
-    /// indices are `[0, s0, 1, s1, 2, s2, 3]`.
-    ///
-    /// Note that you can insert multiple statements at the same index.
-    /// They will appear in the order they were added.
-    ///
-    /// Panics if the index is invalid.
-    pub fn insert(&self, index: usize, stmt: Stmt) {
-        assert!(index <= self.len);
-        self.patches.borrow_mut().push((index, stmt));
-    }
-
-    fn apply_patches(mut self, block: &mut Vec<Stmt>) {
-        // but we want patches to be applied in order they were added. because
-        // we add them in reverse order we want to iterate in reverse order
-        // Vec::sort_by maintains diff=0 values to be in their original order
-        // (stable), but we want the opposite behavior. to do this we sort in
-        // ascending order and reverse the whole iterator
-        let patches = self.patches.get_mut();
-        patches.sort_by(|(m, _), (n, _)| m.cmp(n));
-        // .rev() reverses the reverse-sorted iterator now
-        for (index, stmt) in patches.drain(0..).rev() {
-            // Inserting shifts all elements to the right. However, the
-            // indices are in descending order.
-            block.insert(index, stmt);
-        }
-    }
-}
+pub type BlockContext = crate::shared::walk::BlockContext<Stmt>;
 
 /// A single-level of a context.
 #[derive(Debug)]
@@ -161,16 +120,19 @@ where
 
     pub fn walk_stmt(&mut self, stmt: &mut Stmt, loc: &Loc) {
         use Stmt::*;
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_stmt(stmt, loc);
         // recurse
         match stmt {
             // 0
-            Empty | Break(_, _) | Continue(_, _) => (),
+            Empty | Break(_, _) | Continue(_, _) | Pragma(..) | RestParam(..) => (),
             Label(.., a, _) => {
                 let loc = Loc::Node(Context::Stmt, loc);
                 self.walk_stmt(a, &loc);
             }
-            Func(.., a, _) => {
+            Func(.., a, _, _) => {
                 let loc = Loc::Node(Context::FunctionBody, loc);
                 self.walk_stmt(a, &loc);
             }
@@ -197,17 +159,41 @@ where
                     self.walk_expr(named, &loc);
                 }
             }
+            Destructure(_, e, _) => {
+                let loc = Loc::Node(Context::VarDeclRhs, loc);
+                self.walk_expr(e, &loc);
+            }
+            DefaultParam(_, e, _) => {
+                let loc = Loc::Node(Context::Stmt, loc);
+                self.walk_expr(e, &loc);
+            }
             // 1xExpr
             Throw(a, _) | Return(a, _) | Expr(a, _) => {
                 let loc = Loc::Node(Context::Stmt, loc);
                 self.walk_expr(a, &loc);
             }
             // 1xExpr, 1xStmt
-            DoWhile(st, e, _) | ForIn(.., e, st, _) | While(e, st, _) => {
+            DoWhile(st, e, _) | ForIn(.., e, st, _) | ForOf(.., e, st, _) | While(e, st, _) => {
                 let loc = Loc::Node(Context::Loop, loc);
                 self.walk_expr(e, &loc);
                 self.walk_stmt(st, &loc);
             }
+            // the superclass, the constructor, and the methods
+            Class(class, _) => {
+                let loc = Loc::Node(Context::Stmt, loc);
+                let super::Class {
+                    super_class,
+                    constructor,
+                    methods,
+                    ..
+                } = &mut **class;
+                for e in super_class.iter_mut().chain(constructor.iter_mut()) {
+                    self.walk_expr(e, &loc);
+                }
+                for method in methods {
+                    self.walk_expr(&mut method.func, &loc);
+                }
+            }
             // 1xExpr, 2xStmt
             If(e, sa, sb, _) => {
                 let loc = Loc::Node(Context::Stmt, loc);
@@ -233,11 +219,16 @@ where
                 self.walk_stmt(st, &loc);
             }
         }
-        self.visitor.exit_stmt(stmt, &loc);
+        if !self.visitor.done() {
+            self.visitor.exit_stmt(stmt, &loc);
+        }
     }
 
     pub fn walk_expr(&mut self, expr: &mut Expr, loc: &Loc) {
         use Expr::*;
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_expr(expr, loc);
         match expr {
             // 0
@@ -247,7 +238,7 @@ where
                 let loc = Loc::Node(Context::Expr, loc);
                 self.walk_lval(lv, &loc);
             }
-            Func(.., st, _) => {
+            Func(.., st, _, _) => {
                 let loc = Loc::Node(Context::FunctionBody, loc);
                 self.walk_stmt(st, &loc);
             }
@@ -266,10 +257,17 @@ where
                 }
             }
             // 1xExpr
-            Dot(e, .., _) | Unary(.., e, _) | Length(e, _) => {
+            Dot(e, .., _) | Unary(.., e, _) | Length(e, _) | Await(e, _) => {
                 let loc = Loc::Node(Context::Expr, loc);
                 self.walk_expr(e, &loc);
             }
+            // 0 or 1xExpr
+            Yield(e, _) => {
+                let loc = Loc::Node(Context::Expr, loc);
+                if let Some(e) = e {
+                    self.walk_expr(e, &loc);
+                }
+            }
             // 1xExpr, 1xLValue
             Assign(op, lv, e, _) => {
                 let lv_loc = Loc::Node(Context::LValue, loc);
@@ -285,8 +283,17 @@ where
                     self.walk_expr(e, &loc);
                 }
             }
+            // 2xExpr, 1x[Expr]
+            CallThis(ea, eb, es, _) => {
+                let loc = Loc::Node(Context::Expr, loc);
+                self.walk_expr(ea, &loc);
+                self.walk_expr(eb, &loc);
+                for e in es {
+                    self.walk_expr(e, &loc);
+                }
+            }
             // 2xExpr
-            Bracket(ea, eb, _) | Binary(.., ea, eb, _) => {
+            Bracket(ea, eb, _) | Binary(.., ea, eb, _) | Apply(ea, eb, _) => {
                 let loc = Loc::Node(Context::Expr, loc);
                 self.walk_expr(ea, &loc);
                 self.walk_expr(eb, &loc);
@@ -300,13 +307,18 @@ where
             }
             MethodCall(..) => unimplemented!(),
         }
-        self.visitor.exit_expr(expr, loc);
+        if !self.visitor.done() {
+            self.visitor.exit_expr(expr, loc);
+        }
     }
 
     /// like [Stmt::walk], but as a method on LValue. does the *exact*
     /// same thing
     pub fn walk_lval(&mut self, lval: &mut LValue, loc: &Loc) {
         use LValue::*;
+        if self.visitor.done() {
+            return;
+        }
         match lval {
             Id(_) => (),
             Dot(e, ..) => {
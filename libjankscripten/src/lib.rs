@@ -1,16 +1,27 @@
 //! the jankscripten system without IO/main
 
 use std::collections::HashMap;
+use std::error::Error;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod differential;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
 pub mod jankyscript;
 pub mod javascript;
 pub mod notwasm;
 pub mod opts;
+mod passes;
 pub mod pos;
+#[cfg(target_arch = "wasm32")]
+pub mod playground;
 mod pretty;
-mod rope;
+pub mod profile;
 mod rts_function;
 pub mod shared;
+#[cfg(test)]
+mod snapshots;
 mod string_escaping;
+#[cfg(feature = "z3")]
 mod z3ez;
 
 pub fn javascript_to_wasm<F, G>(
@@ -19,16 +30,104 @@ pub fn javascript_to_wasm<F, G>(
     js_code: &str,
     inspect_janky: F,
     inspect_notwasm: G,
-) -> Result<(Vec<u8>, HashMap<String, u32>), Box<dyn std::error::Error>>
+) -> Result<(Vec<u8>, HashMap<String, u32>), Box<dyn Error>>
 where
     F: FnOnce(&jankyscript::syntax::Stmt) -> (),
     G: FnOnce(&notwasm::syntax::Program) -> (),
 {
-    let mut js_ast = javascript::parse(src_name, js_code)?;
+    opts.validate()?;
+    let mut js_ast = passes::time(&opts, "JavaScript", "parse", || {
+        javascript::parse_with_recovery(src_name, js_code)
+    })?;
     let mut ng = shared::NameGen::default();
-    javascript::desugar(&mut js_ast, &mut ng);
-    let mut janky_ast = jankyscript::from_js::from_javascript(js_ast);
-    jankyscript::compile(&mut janky_ast, inspect_janky).unwrap();
-    let notwasm_ast = notwasm::from_jankyscript(janky_ast);
+    passes::time(&opts, "JavaScript", "desugar", || {
+        javascript::desugar(&mut js_ast, &mut ng)
+    });
+    let mut janky_ast = passes::time(&opts, "JankyScript", "from_javascript", || {
+        jankyscript::from_js::from_javascript(js_ast)
+    });
+    jankyscript::compile(&opts, &mut janky_ast, inspect_janky).unwrap();
+    let notwasm_ast = passes::time(&opts, "NotWasm", "from_jankyscript", || {
+        notwasm::from_jankyscript(janky_ast)
+    });
     notwasm::compile(&mut opts, notwasm_ast, inspect_notwasm)
 }
+
+/// Compiles JavaScript and reports on the call graph of the NotWasm program, which includes the
+/// NotWasm standard library. See [notwasm::call_graph].
+pub fn analyze_javascript(
+    opts: opts::Opts,
+    src_name: &str,
+    js_code: &str,
+) -> Result<notwasm::call_graph::CallGraphReport, Box<dyn Error>> {
+    let mut report = None;
+    javascript_to_wasm(
+        opts,
+        src_name,
+        js_code,
+        |_| (),
+        |notwasm| report = Some(notwasm::call_graph::call_graph(notwasm).report()),
+    )?;
+    Ok(report.expect("NotWasm was not inspected"))
+}
+
+/// Runs type inference on JavaScript twice, and reports where the program loses types when its
+/// functions must be callable from untyped code. See [jankyscript::migration].
+#[cfg(feature = "z3")]
+pub fn migration_report(
+    opts: &opts::Opts,
+    src_name: &str,
+    js_code: &str,
+) -> Result<jankyscript::migration::MigrationReport, Box<dyn Error>> {
+    let mut js_ast = javascript::parse_with_recovery(src_name, js_code)?;
+    let mut ng = shared::NameGen::default();
+    javascript::desugar(&mut js_ast, &mut ng);
+    let janky_ast = jankyscript::from_js::from_javascript(js_ast);
+    Ok(jankyscript::migration::migration_report(
+        &janky_ast,
+        &opts.typeinf_weights,
+    ))
+}
+
+/// An intermediate representation that `javascript_to_ir` can emit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ir {
+    JankyScript,
+    NotWasm,
+    /// The WebAssembly text of the compiled module
+    Wat,
+}
+
+/// Compiles JavaScript and returns the pretty-printed intermediate representation, instead of
+/// WebAssembly. Emitted NotWasm is valid concrete syntax, which `notwasm::parse` accepts.
+/// Emitted WebAssembly is in the text format.
+pub fn javascript_to_ir(
+    opts: opts::Opts,
+    src_name: &str,
+    js_code: &str,
+    ir: Ir,
+) -> Result<String, Box<dyn Error>> {
+    let mut output = String::new();
+    match ir {
+        Ir::JankyScript => javascript_to_wasm(
+            opts,
+            src_name,
+            js_code,
+            |janky| output = janky.to_string(),
+            |_| (),
+        )?,
+        Ir::NotWasm => javascript_to_wasm(
+            opts,
+            src_name,
+            js_code,
+            |_| (),
+            |notwasm| output = notwasm.to_string(),
+        )?,
+        Ir::Wat => {
+            let compiled = javascript_to_wasm(opts, src_name, js_code, |_| (), |_| ())?;
+            output = wasmprinter::print_bytes(&compiled.0)?;
+            compiled
+        }
+    };
+    Ok(output)
+}
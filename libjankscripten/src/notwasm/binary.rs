@@ -0,0 +1,144 @@
+//! Reads the sections of encoded WebAssembly modules.
+//!
+//! parity_wasm cannot deserialize the modules that the compiler produces, since it does not
+//! support their passive element segment (see translation::passive_element_section), so
+//! [size](super::size) and [metadata](super::metadata) read the few parts that they need
+//! themselves.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error("malformed WebAssembly module: {0}")]
+pub struct Malformed(pub &'static str);
+
+/// A section of a module.
+pub struct RawSection<'a> {
+    pub id: u8,
+    /// The position of the section in the module, which starts with its id.
+    pub start: usize,
+    /// The position of the end of the section.
+    pub end: usize,
+    pub payload: &'a [u8],
+}
+
+/// The sections of `wasm`, in order.
+pub fn sections(wasm: &[u8]) -> Result<Vec<RawSection<'_>>, Malformed> {
+    let mut reader = Reader::new(wasm);
+    if reader.take(8)? != b"\0asm\x01\0\0\0" {
+        return Err(Malformed("not a module"));
+    }
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let start = reader.pos;
+        let id = reader.byte()?;
+        let len = reader.u32()? as usize;
+        let payload = reader.take(len)?;
+        sections.push(RawSection {
+            id,
+            start,
+            end: reader.pos,
+            payload,
+        });
+    }
+    Ok(sections)
+}
+
+impl<'a> RawSection<'a> {
+    /// The name of a custom section, and the rest of its payload.
+    pub fn custom(&self) -> Result<Option<(String, &'a [u8])>, Malformed> {
+        if self.id != 0 {
+            return Ok(None);
+        }
+        let mut payload = Reader::new(self.payload);
+        let name = payload.name()?;
+        Ok(Some((name, payload.rest())))
+    }
+}
+
+/// Reads the bytes of a module, which parity_wasm does not tell the positions of.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], Malformed> {
+        if n > self.bytes.len() - self.pos {
+            return Err(Malformed("unexpected end"));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.pos..];
+        self.pos = self.bytes.len();
+        bytes
+    }
+
+    pub fn byte(&mut self) -> Result<u8, Malformed> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// An unsigned LEB128 integer.
+    pub fn u32(&mut self) -> Result<u32, Malformed> {
+        let mut n = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(Malformed("integer too long"))
+    }
+
+    /// A name, which is its length and its UTF-8 bytes.
+    pub fn name(&mut self) -> Result<String, Malformed> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    /// The limits of a table or a memory.
+    fn limits(&mut self) -> Result<(), Malformed> {
+        let flags = self.byte()?;
+        self.u32()?;
+        if flags & 1 != 0 {
+            self.u32()?;
+        }
+        Ok(())
+    }
+
+    /// The number of functions that the import section `self` imports.
+    pub fn function_imports(&mut self) -> Result<usize, Malformed> {
+        let mut functions = 0;
+        for _ in 0..self.u32()? {
+            self.name()?;
+            self.name()?;
+            match self.byte()? {
+                0 => {
+                    self.u32()?;
+                    functions += 1;
+                }
+                1 => {
+                    self.byte()?;
+                    self.limits()?;
+                }
+                2 => self.limits()?,
+                3 => {
+                    self.take(2)?;
+                }
+                _ => return Err(Malformed("unknown import")),
+            }
+        }
+        Ok(functions)
+    }
+}
@@ -0,0 +1,612 @@
+//! A whole-program call graph of NotWasm.
+//!
+//! NotWasm has four kinds of calls: `f(..)` calls the function `f` directly, `x(..)` calls the
+//! function in the variable `x`, `x!(..)` calls a closure, and `x?.m<..>(..)` calls the method `m`
+//! of a value of type `any`, which dispatches on the type of `x`. A call that is not direct is
+//! *monomorphic* when the analysis finds exactly one function that it may call. A monomorphic
+//! call can be devirtualized (i.e., turned into a direct call), which also makes it a candidate
+//! for inlining.
+//!
+//! The analysis tracks the functions that each variable may hold. It is flow-insensitive, and it
+//! is interprocedural only through closure environments: slot `i` of the environment of `f` may
+//! hold any function that the `i`th atom of some `clos(f, ..)` may hold. Parameters, globals, and
+//! values that pass through `any` may hold any function. The receiver of a method call usually
+//! has type `any`, so the analysis also tracks the type of the value that `any(a)` injects.
+use super::syntax::*;
+use crate::pos::Pos;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// `f(..)`, where `f` is the name of a function
+    Direct,
+    /// `x(..)`, where `x` is a variable of function type
+    Indirect,
+    /// `x!(..)`
+    Closure,
+    /// `x?.m<..>(..)`
+    Method,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    /// The function that contains the call.
+    pub caller: Id,
+    pub kind: CallKind,
+    pub pos: Pos,
+    /// The functions that the call may call, sorted by name, or `None` if the analysis does not
+    /// know. The target of a method call is the function that implements the method for one type
+    /// of receiver (e.g., `string_length`), which may be in the runtime system.
+    pub targets: Option<Vec<Id>>,
+}
+
+impl CallSite {
+    /// The only function that the call may call, if the analysis knows it.
+    pub fn target(&self) -> Option<&Id> {
+        match &self.targets {
+            Some(targets) if targets.len() == 1 => Some(&targets[0]),
+            _ => None,
+        }
+    }
+
+    pub fn is_monomorphic(&self) -> bool {
+        self.target().is_some()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CallCounts {
+    pub total: usize,
+    pub monomorphic: usize,
+}
+
+/// A summary of the call graph.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CallGraphReport {
+    pub functions: usize,
+    pub direct_calls: usize,
+    pub indirect_calls: CallCounts,
+    pub closure_calls: CallCounts,
+    pub method_calls: CallCounts,
+}
+
+impl std::fmt::Display for CallGraphReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "direct calls: {}", self.direct_calls)?;
+        for (name, counts) in &[
+            ("indirect calls", self.indirect_calls),
+            ("closure calls", self.closure_calls),
+            ("method calls", self.method_calls),
+        ] {
+            writeln!(
+                f,
+                "{}: {} ({} monomorphic)",
+                name, counts.total, counts.monomorphic
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct CallGraph {
+    pub functions: usize,
+    /// Every call in the program, ordered by caller.
+    pub sites: Vec<CallSite>,
+}
+
+impl CallGraph {
+    /// The functions that `caller` may call, or `None` if it has a call whose targets the
+    /// analysis does not know.
+    pub fn callees(&self, caller: &Id) -> Option<HashSet<&Id>> {
+        let mut callees = HashSet::new();
+        for site in self.sites.iter().filter(|site| &site.caller == caller) {
+            callees.extend(site.targets.as_ref()?);
+        }
+        Some(callees)
+    }
+
+    pub fn report(&self) -> CallGraphReport {
+        let mut report = CallGraphReport {
+            functions: self.functions,
+            ..CallGraphReport::default()
+        };
+        for site in &self.sites {
+            let counts = match site.kind {
+                CallKind::Direct => {
+                    report.direct_calls += 1;
+                    continue;
+                }
+                CallKind::Indirect => &mut report.indirect_calls,
+                CallKind::Closure => &mut report.closure_calls,
+                CallKind::Method => &mut report.method_calls,
+            };
+            counts.total += 1;
+            if site.is_monomorphic() {
+                counts.monomorphic += 1;
+            }
+        }
+        report
+    }
+}
+
+pub fn call_graph(program: &Program) -> CallGraph {
+    let mut names: Vec<_> = program.functions.keys().collect();
+    names.sort_by_key(|x| x.to_string());
+    let mut analysis = Analysis {
+        program,
+        locals: HashMap::new(),
+        vars: HashMap::new(),
+        any_types: HashMap::new(),
+        env_slots: HashMap::new(),
+        changed: false,
+    };
+    for &name in &names {
+        let func = &program.functions[name];
+        let mut locals: HashSet<Id> = func.params.iter().cloned().collect();
+        declared_vars(&func.body, &mut locals);
+        for x in &func.params {
+            let key = (name.clone(), x.clone());
+            analysis.vars.insert(key.clone(), Targets::Unknown);
+            analysis.any_types.insert(key, AnyType::Many);
+        }
+        analysis.locals.insert(name.clone(), locals);
+    }
+    loop {
+        analysis.changed = false;
+        for &name in &names {
+            analysis.stmt(name, &program.functions[name].body);
+        }
+        if !analysis.changed {
+            break;
+        }
+    }
+    let mut sites = Vec::new();
+    for &name in &names {
+        analysis.call_sites(name, &program.functions[name].body, &mut sites);
+    }
+    CallGraph {
+        functions: names.len(),
+        sites,
+    }
+}
+
+/// The functions that `roots` may call, directly or through other functions, including the roots.
+/// A closure call whose targets the analysis does not know may call any function that receives an
+/// environment, and any other call whose targets it does not know may call any function.
+pub fn reachable(program: &Program, graph: &CallGraph, roots: &[Id]) -> HashSet<Id> {
+    let receives_env = |f: &Id| program.functions[f].fn_type.args.first() == Some(&Type::Env);
+    let mut reached: HashSet<Id> = HashSet::new();
+    let mut pending: Vec<Id> = roots
+        .iter()
+        .filter(|f| program.functions.contains_key(f))
+        .cloned()
+        .collect();
+    while let Some(f) = pending.pop() {
+        if !reached.insert(f.clone()) {
+            continue;
+        }
+        for site in graph.sites.iter().filter(|site| site.caller == f) {
+            match (&site.targets, site.kind) {
+                (Some(targets), _) => pending.extend(
+                    targets
+                        .iter()
+                        .filter(|g| program.functions.contains_key(g))
+                        .cloned(),
+                ),
+                (None, CallKind::Closure) => pending.extend(
+                    program
+                        .functions
+                        .keys()
+                        .filter(|g| receives_env(g))
+                        .cloned(),
+                ),
+                (None, _) => pending.extend(program.functions.keys().cloned()),
+            }
+        }
+    }
+    reached
+}
+
+fn declared_vars(stmt: &Stmt, vars: &mut HashSet<Id>) {
+    match stmt {
+        Stmt::Var(var_stmt, _) => {
+            vars.insert(var_stmt.id.clone());
+        }
+        Stmt::If(_, s1, s2, _) => {
+            declared_vars(s1, vars);
+            declared_vars(s2, vars);
+        }
+        Stmt::Loop(s, _) | Stmt::Label(_, s, _) => declared_vars(s, vars),
+        Stmt::Block(ss, _) => {
+            for s in ss {
+                declared_vars(s, vars);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// The functions that a value may be.
+#[derive(Debug, Clone, PartialEq)]
+enum Targets {
+    Known(HashSet<Id>),
+    Unknown,
+}
+
+impl Targets {
+    fn none() -> Self {
+        Targets::Known(HashSet::new())
+    }
+
+    fn one(f: &Id) -> Self {
+        Targets::Known(std::iter::once(f.clone()).collect())
+    }
+
+    /// Adds the functions of `other`, and produces `true` if that changes `self`.
+    fn join(&mut self, other: &Targets) -> bool {
+        match other {
+            Targets::Unknown => {
+                let changed = *self != Targets::Unknown;
+                *self = Targets::Unknown;
+                changed
+            }
+            Targets::Known(gs) => match self {
+                Targets::Unknown => false,
+                Targets::Known(fs) => {
+                    let len = fs.len();
+                    fs.extend(gs.iter().cloned());
+                    fs.len() != len
+                }
+            },
+        }
+    }
+
+    fn sorted(&self) -> Option<Vec<Id>> {
+        match self {
+            Targets::Unknown => None,
+            Targets::Known(fs) => {
+                let mut fs: Vec<_> = fs.iter().cloned().collect();
+                fs.sort_by_key(|f| f.to_string());
+                Some(fs)
+            }
+        }
+    }
+}
+
+/// The type of the values that a variable of type `any` may hold.
+#[derive(Debug, Clone, PartialEq)]
+enum AnyType {
+    One(Type),
+    Many,
+}
+
+impl AnyType {
+    fn join(&mut self, other: &AnyType) -> bool {
+        if *self == *other || *self == AnyType::Many {
+            return false;
+        }
+        *self = AnyType::Many;
+        true
+    }
+}
+
+struct Analysis<'a> {
+    program: &'a Program,
+    /// The parameters and variables of each function.
+    locals: HashMap<Id, HashSet<Id>>,
+    /// The functions that each variable of each function may hold. A variable that is missing
+    /// does not hold any function yet.
+    vars: HashMap<(Id, Id), Targets>,
+    /// Like `vars`, but for the types of the values that `any` variables hold.
+    any_types: HashMap<(Id, Id), AnyType>,
+    /// The functions that each slot of the environment of each function may hold.
+    env_slots: HashMap<(Id, u32), Targets>,
+    changed: bool,
+}
+
+impl<'a> Analysis<'a> {
+    fn is_local(&self, f: &Id, x: &Id) -> bool {
+        self.locals[f].contains(x)
+    }
+
+    fn stmt(&mut self, f: &Id, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var(var_stmt, _) => self.define(f, &var_stmt.id, &var_stmt.named),
+            Stmt::Assign(x, e, _) if self.is_local(f, x) => self.define(f, x, e),
+            Stmt::Assign(_, e, _) | Stmt::Expression(e, _) | Stmt::Store(_, e, _) => {
+                self.expr(f, e)
+            }
+            Stmt::If(_, s1, s2, _) => {
+                self.stmt(f, s1);
+                self.stmt(f, s2);
+            }
+            Stmt::Loop(s, _) | Stmt::Label(_, s, _) => self.stmt(f, s),
+            Stmt::Block(ss, _) => {
+                for s in ss {
+                    self.stmt(f, s);
+                }
+            }
+            Stmt::Empty | Stmt::Break(..) | Stmt::Return(..) | Stmt::Trap | Stmt::Goto(..) => (),
+        }
+    }
+
+    /// Records that the variable `x` of `f` may hold the value of `e`.
+    fn define(&mut self, f: &Id, x: &Id, e: &Expr) {
+        self.expr(f, e);
+        let key = (f.clone(), x.clone());
+        let targets = self.expr_targets(f, e);
+        let changed = self
+            .vars
+            .entry(key.clone())
+            .or_insert_with(Targets::none)
+            .join(&targets);
+        self.changed |= changed;
+        if let Some(any_type) = self.expr_any_type(f, e) {
+            let changed = match self.any_types.get_mut(&key) {
+                Some(old) => old.join(&any_type),
+                None => {
+                    self.any_types.insert(key, any_type);
+                    true
+                }
+            };
+            self.changed |= changed;
+        }
+    }
+
+    /// Records the functions that the environments of closures may hold.
+    fn expr(&mut self, f: &Id, e: &Expr) {
+        if let Expr::Closure(g, env, _) = e {
+            for (i, (a, _)) in env.iter().enumerate() {
+                let targets = self.atom_targets(f, a);
+                let changed = self
+                    .env_slots
+                    .entry((g.clone(), i as u32))
+                    .or_insert_with(Targets::none)
+                    .join(&targets);
+                self.changed |= changed;
+            }
+        }
+    }
+
+    fn expr_targets(&self, f: &Id, e: &Expr) -> Targets {
+        match e {
+            Expr::Closure(g, ..) => Targets::one(g),
+            Expr::Atom(a, _) => self.atom_targets(f, a),
+            _ => Targets::Unknown,
+        }
+    }
+
+    fn atom_targets(&self, f: &Id, a: &Atom) -> Targets {
+        match a {
+            Atom::Id(x, _) if self.is_local(f, x) => self
+                .vars
+                .get(&(f.clone(), x.clone()))
+                .cloned()
+                .unwrap_or_else(Targets::none),
+            Atom::Id(x, _) if self.program.functions.contains_key(x) => Targets::one(x),
+            Atom::EnvGet(i, ..) => self
+                .env_slots
+                .get(&(f.clone(), *i))
+                .cloned()
+                .unwrap_or_else(Targets::none),
+            _ => Targets::Unknown,
+        }
+    }
+
+    /// The type of the value of `e`, if it has type `any`, or `None` if the analysis does not know
+    /// anything about it yet.
+    fn expr_any_type(&self, f: &Id, e: &Expr) -> Option<AnyType> {
+        match e {
+            Expr::Atom(Atom::ToAny(to_any, _), _) => match &to_any.ty {
+                Some(ty) => Some(AnyType::One(ty.clone())),
+                None => Some(AnyType::Many),
+            },
+            Expr::Atom(Atom::Id(x, _), _) if self.is_local(f, x) => {
+                self.any_types.get(&(f.clone(), x.clone())).cloned()
+            }
+            _ => Some(AnyType::Many),
+        }
+    }
+
+    fn call_sites(&self, f: &Id, stmt: &Stmt, sites: &mut Vec<CallSite>) {
+        match stmt {
+            Stmt::Var(VarStmt { named: e, .. }, _)
+            | Stmt::Assign(_, e, _)
+            | Stmt::Expression(e, _)
+            | Stmt::Store(_, e, _) => {
+                if let Some(site) = self.call_site(f, e) {
+                    sites.push(site);
+                }
+            }
+            Stmt::If(_, s1, s2, _) => {
+                self.call_sites(f, s1, sites);
+                self.call_sites(f, s2, sites);
+            }
+            Stmt::Loop(s, _) | Stmt::Label(_, s, _) => self.call_sites(f, s, sites),
+            Stmt::Block(ss, _) => {
+                for s in ss {
+                    self.call_sites(f, s, sites);
+                }
+            }
+            Stmt::Empty | Stmt::Break(..) | Stmt::Return(..) | Stmt::Trap | Stmt::Goto(..) => (),
+        }
+    }
+
+    fn call_site(&self, f: &Id, e: &Expr) -> Option<CallSite> {
+        let (kind, targets, pos) = match e {
+            Expr::Call(g, _, p) if self.is_local(f, g) => (
+                CallKind::Indirect,
+                self.atom_targets(f, &Atom::Id(g.clone(), p.clone())),
+                p,
+            ),
+            Expr::Call(g, _, p) => (CallKind::Direct, Targets::one(g), p),
+            Expr::ClosureCall(g, _, p) => (
+                CallKind::Closure,
+                self.atom_targets(f, &Atom::Id(g.clone(), p.clone())),
+                p,
+            ),
+            Expr::AnyMethodCall(x, method, _, typs, p) => {
+                let method = match method {
+                    Lit::String(m) | Lit::Interned(m, _) => m,
+                    _ => panic!("method name should be a string"),
+                };
+                let receiver_type = match self.any_types.get(&(f.clone(), x.clone())) {
+                    Some(AnyType::One(ty)) if self.is_local(f, x) => Some(ty),
+                    _ => None,
+                };
+                let targets = typs
+                    .iter()
+                    .map(|t| &t.unwrap_fun().0[0])
+                    .filter(|ty| receiver_type.map_or(true, |receiver| receiver == *ty))
+                    .map(|ty| Id::Named(format!("{}_{}", ty, method)))
+                    .collect();
+                (CallKind::Method, Targets::Known(targets), p)
+            }
+            _ => return None,
+        };
+        Some(CallSite {
+            caller: f.clone(),
+            kind,
+            pos: pos.clone(),
+            targets: targets.sorted(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    fn sites(notwasm_code: &str) -> Vec<(CallKind, Option<Vec<String>>)> {
+        let program = parse("<test>", notwasm_code);
+        call_graph(&program)
+            .sites
+            .into_iter()
+            .filter(|site| site.caller == Id::from("main"))
+            .map(|site| {
+                let targets = site
+                    .targets
+                    .map(|targets| targets.iter().map(|f| f.to_string()).collect());
+                (site.kind, targets)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closure_calls() {
+        let sites = sites(
+            r#"
+            function f(_: env, x: i32): i32 { return x; }
+            function g(_: env, x: i32): i32 { return x; }
+            function main() {
+                var one = 1;
+                var c = clos(f);
+                var r = c!(one);
+                var d = clos(f);
+                var e = clos(g);
+                d = e;
+                r = d!(one);
+                r = f(one);
+            }
+            "#,
+        );
+        assert_eq!(
+            sites,
+            vec![
+                (CallKind::Closure, Some(vec!["f".to_string()])),
+                (
+                    CallKind::Closure,
+                    Some(vec!["f".to_string(), "g".to_string()])
+                ),
+                (CallKind::Direct, Some(vec!["f".to_string()])),
+            ]
+        );
+    }
+
+    #[test]
+    fn closure_in_environment() {
+        let program = parse(
+            "<test>",
+            r#"
+            function inner(_: env): i32 { return 1; }
+            function outer(_: env): i32 {
+                var c = env.0: clos () -> i32;
+                var r = c!();
+                return r;
+            }
+            function main() {
+                var i = clos(inner);
+                var o = clos(outer, i: clos () -> i32);
+                var r = o!();
+            }
+            "#,
+        );
+        let graph = call_graph(&program);
+        let inner = Id::from("inner");
+        assert_eq!(
+            graph.callees(&Id::from("outer")),
+            Some(std::iter::once(&inner).collect())
+        );
+        let report = graph.report();
+        assert_eq!(report.functions, 3);
+        assert_eq!(
+            report.closure_calls,
+            CallCounts {
+                total: 2,
+                monomorphic: 2
+            }
+        );
+    }
+
+    #[test]
+    fn reachable_functions() {
+        let program = parse(
+            "<test>",
+            r#"
+            function f(_: env): i32 { return 1; }
+            function g(x: i32): i32 { return x; }
+            function h(x: i32): i32 { return x; }
+            function main() {
+                var x = any(1);
+                var c = x as clos () -> i32;
+                var r = c!();
+                r = g(r);
+            }
+            "#,
+        );
+        let graph = call_graph(&program);
+        let mut names: Vec<_> = reachable(&program, &graph, &[Id::from("main")])
+            .into_iter()
+            .map(|f| f.to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["f", "g", "main"]);
+    }
+
+    #[test]
+    fn unknown_closures() {
+        let sites = sites(
+            r#"
+            function main() {
+                var x = any(1);
+                var c = x as clos () -> i32;
+                var r = c!();
+                var n = x?.size<(str) -> i32, (Array) -> i32>();
+            }
+            "#,
+        );
+        assert_eq!(
+            sites,
+            vec![
+                (CallKind::Closure, None),
+                (
+                    CallKind::Method,
+                    Some(vec!["array_size".to_string(), "string_size".to_string()])
+                ),
+            ]
+        );
+    }
+}
@@ -1,8 +1,37 @@
-use super::syntax::Program;
+use super::syntax::{Id, Program};
+use super::type_checking::RuntimeEnv;
 use super::*;
 use crate::opts::Opts;
+use crate::passes::{time, PassManager};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
+use std::rc::Rc;
+
+thread_local!(
+    /// The standard library of the last compilation, and the runtime that it is, type-checked,
+    /// so that compiling several programs with the same standard library only checks it once.
+    static RUNTIME_ENV: RefCell<Option<(String, HashMap<String, String>, Rc<RuntimeEnv>)>> =
+        RefCell::new(None)
+);
+
+/// The type-checked runtime of the standard library whose root file is `root`.
+fn runtime_env(
+    root: &str,
+    includes: &HashMap<String, String>,
+) -> Result<Rc<RuntimeEnv>, Box<dyn Error>> {
+    RUNTIME_ENV.with(|cache| {
+        if let Some((cached_root, cached_includes, env)) = &*cache.borrow() {
+            if cached_root == root && cached_includes == includes {
+                return Ok(env.clone());
+            }
+        }
+        let mut runtime = stdlib::link_all(root, includes)?;
+        let env = Rc::new(type_checking::check_runtime(&mut runtime)?);
+        *cache.borrow_mut() = Some((root.to_string(), includes.clone(), env.clone()));
+        Ok(env)
+    })
+}
 
 pub fn compile<G>(
     opts: &mut Opts,
@@ -14,14 +43,55 @@ where
 {
     //label_apps(&mut program);
     //elim_gotos(&mut program);
-    let mut src = String::new();
-    std::mem::swap(&mut src, &mut opts.notwasm_stdlib_source_code);
-    let notwasm_std_lib = parse("std_lib.notwasm", src);
-    program.merge_in(notwasm_std_lib);
+    let src = std::mem::take(&mut opts.notwasm_stdlib_source_code);
+    stdlib::link(&mut program, &src, &opts.notwasm_stdlib_includes)?;
+    if let Some(profile) = &opts.profile_use {
+        let functions: Vec<String> = program.functions.keys().map(|f| f.to_string()).collect();
+        profile.check(functions.iter().map(|f| f.as_str()))?;
+    }
 
-    type_checking::type_check(&mut program)?;
-    let inverted_interned_strings = intern(&mut program);
-    inspect(&program);
-    let wasm = translate(opts, program)?;
+    for name in &opts.exports {
+        if !program.functions.contains_key(&Id::Named(name.clone())) {
+            return Err(format!("cannot export {}, which is not a function", name).into());
+        }
+    }
+
+    let mut inverted_interned_strings = HashMap::new();
+    let mut passes = PassManager::<Program, Box<dyn Error>>::new("NotWasm", opts);
+    passes.add_fallible("type_check", |program| {
+        let runtime = runtime_env(&src, &opts.notwasm_stdlib_includes)?;
+        type_checking::type_check_with(program, &runtime)?;
+        Ok(())
+    });
+    passes.add_fallible("check_no_gc", |program| Ok(check_no_gc(program)?));
+    passes.add("propagate_constant_globals", propagate_constant_globals);
+    passes.add("elide_shadow_frames", elide_shadow_frames);
+    passes.add("escape_analysis", escape_analysis);
+    if let Some(profile) = &opts.profile_use {
+        passes.add("speculate", move |program| speculate(program, profile));
+    }
+    passes.add("intern", |program| {
+        inverted_interned_strings = intern(program);
+    });
+    passes.inspect(inspect);
+    passes.run(&mut program)?;
+    let wasm = time(opts, "NotWasm", "translate", || translate(opts, program))?;
+    if let Some(budget) = opts.max_module_size {
+        size::size_report(&wasm)?.check_budget(budget)?;
+    }
     Ok((wasm, inverted_interned_strings))
 }
+
+/// Like [compile], but produces the WebAssembly text of the module, which is easier to read and
+/// to diff than the binary.
+pub fn compile_to_wat<G>(
+    opts: &mut Opts,
+    program: Program,
+    inspect: G,
+) -> Result<(String, HashMap<String, u32>), Box<dyn Error>>
+where
+    G: FnOnce(&Program) -> (),
+{
+    let (wasm, inverted_interned_strings) = compile(opts, program, inspect)?;
+    Ok((wasmprinter::print_bytes(&wasm)?, inverted_interned_strings))
+}
@@ -0,0 +1,208 @@
+//! Global constant propagation.
+//!
+//! A global that a literal initializes and that no function assigns is a constant (e.g., a string
+//! that the program only reads), so its uses may be the literal itself, which the translator
+//! compiles to a constant (or an interned string) instead of a read of a global.
+//! Once nothing reads it, the global is removed, so the module has fewer globals, and the garbage
+//! collector has fewer roots.
+//!
+//! The analysis is conservative. A global is not a constant if a function declares a variable or
+//! a parameter with the same name, since the name may refer to the variable. Some expressions
+//! need a variable where an atom would be a literal (e.g., the arguments of a call), so a global
+//! that they use stays, although the atoms that read it are literals.
+use super::syntax::*;
+use super::walk::*;
+use std::collections::{HashMap, HashSet};
+
+pub fn propagate_constant_globals(program: &mut Program) {
+    let mut uses = Uses::default();
+    for func in program.functions.values_mut() {
+        uses.bound.extend(func.params.iter().cloned());
+        func.body.walk(&mut uses);
+    }
+    // A global that a constant initializes (e.g., `var y: i32 = x;`) is a constant once we
+    // propagate the constant, so we repeat until there are no new constants.
+    let mut propagated = HashSet::new();
+    loop {
+        let constants: HashMap<Id, Lit> = program
+            .globals
+            .iter()
+            .filter(|(x, _)| !uses.bound.contains(*x) && !propagated.contains(*x))
+            .filter_map(|(x, global)| match &global.atom {
+                Some(Atom::Lit(lit, _)) if lit.notwasm_typ() == global.ty => {
+                    Some((x.clone(), lit.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        if constants.is_empty() {
+            return;
+        }
+        program.walk(&mut Propagate(&constants));
+        for x in constants.keys() {
+            if !uses.needs_variable.contains(x) {
+                program.globals.remove(x);
+            }
+            propagated.insert(x.clone());
+        }
+    }
+}
+
+#[derive(Default)]
+struct Uses {
+    /// The names that a function assigns, or declares as a variable or a parameter.
+    bound: HashSet<Id>,
+    /// The names that an expression uses where it needs a variable.
+    needs_variable: HashSet<Id>,
+}
+
+impl Visitor for Uses {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        match stmt {
+            Stmt::Var(var_stmt, _) => {
+                self.bound.insert(var_stmt.id.clone());
+            }
+            Stmt::Assign(x, ..) | Stmt::Store(x, ..) => {
+                self.bound.insert(x.clone());
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Call(_, args, _)
+            | Expr::PrimCall(_, args, _)
+            | Expr::Speculate(_, _, args, _) => {
+                self.needs_variable.extend(args.iter().cloned());
+            }
+            Expr::ClosureCall(f, args, _) | Expr::AnyMethodCall(f, _, args, _, _) => {
+                self.needs_variable.insert(f.clone());
+                self.needs_variable.extend(args.iter().cloned());
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_atom(&mut self, atom: &mut Atom, _loc: &Loc) {
+        if let Atom::AnyLength(x, ..) = atom {
+            self.needs_variable.insert(x.clone());
+        }
+    }
+}
+
+struct Propagate<'a>(&'a HashMap<Id, Lit>);
+
+impl Visitor for Propagate<'_> {
+    fn exit_atom(&mut self, atom: &mut Atom, _loc: &Loc) {
+        if let Atom::Id(x, p) = atom {
+            if let Some(lit) = self.0.get(x) {
+                *atom = Atom::Lit(lit.clone(), p.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    const COUNTER: &str = r#"
+        var count: i32 = 0;
+        function main(): i32 {
+            count = count + 1;
+            return count;
+        }
+    "#;
+
+    fn propagate(program: &str) -> Program {
+        let mut program = parse("<test>", program);
+        propagate_constant_globals(&mut program);
+        program
+    }
+
+    #[test]
+    fn literals_replace_reads() {
+        let program = propagate(
+            r#"
+            const greeting: str = "hello";
+            var limit: i32 = 10;
+            var derived: i32 = limit;
+            function main(): i32 {
+                var s = greeting;
+                var n = limit + 1;
+                return derived;
+            }
+        "#,
+        );
+        assert!(program.globals.is_empty());
+        let expected = propagate(
+            r#"
+            function main(): i32 {
+                var s = "hello";
+                var n = 10 + 1;
+                return 10;
+            }
+        "#,
+        );
+        assert_eq!(program.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn assigned_globals_are_not_constants() {
+        let program = propagate(COUNTER);
+        assert!(program.globals.contains_key(&Id::from("count")));
+        assert_eq!(program, parse("<test>", COUNTER));
+    }
+
+    #[test]
+    fn shadowed_globals_are_not_constants() {
+        let program = propagate(
+            r#"
+            var x: i32 = 0;
+            function f(x: i32): i32 {
+                return x;
+            }
+            function main(): i32 {
+                var r = f(x);
+                return r;
+            }
+        "#,
+        );
+        assert!(program.globals.contains_key(&Id::from("x")));
+    }
+
+    #[test]
+    fn arguments_keep_globals() {
+        let program = propagate(
+            r#"
+            var x: i32 = 7;
+            function f(y: i32): i32 {
+                return y;
+            }
+            function main(): i32 {
+                var y = x;
+                var r = f(x);
+                return r;
+            }
+        "#,
+        );
+        assert!(program.globals.contains_key(&Id::from("x")));
+        let expected = parse(
+            "<test>",
+            r#"
+            var x: i32 = 7;
+            function f(y: i32): i32 {
+                return y;
+            }
+            function main(): i32 {
+                var y = 7;
+                var r = f(x);
+                return r;
+            }
+        "#,
+        );
+        assert_eq!(program.to_string(), expected.to_string());
+    }
+}
@@ -106,6 +106,7 @@ pub fn program_(functions: HashMap<Id, Function>) -> Program {
         rts_fn_imports: HashMap::new(),
         globals: HashMap::new(),
         data: Vec::new(),
+        interned: HashMap::new(),
     }
 }
 pub fn program1_(func: Function) -> Program {
@@ -133,6 +134,7 @@ pub fn func_i32_(body: Stmt, s: Pos) -> Function {
         },
         params: Vec::new(),
         span: s,
+        no_gc: false,
     }
 }
 
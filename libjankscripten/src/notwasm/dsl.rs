@@ -0,0 +1,502 @@
+//! A macro for writing NotWasm programs in Rust, without nesting constructors by hand.
+//!
+//! `notwasm!` accepts a subset of the concrete syntax of NotWasm and expands to the syntax types,
+//! with unknown positions. Every position that needs something outside the subset can use
+//! `(unquote <e>)`, where `<e>` is a Rust expression of the right syntax type:
+//!
+//! ```text
+//! <program> ::= <function> ...
+//! <function> ::= [nogc] function <id> ( <id>: <type>, ... ) [: <type>] { <stmt> ... }
+//! <stmt> ::= var <id> [: <type>] = <expr>;
+//!          | <id> = <expr>;
+//!          | return <atom>;
+//!          | break <string_lit>;          // e.g., `break "$loop";`
+//!          | trap;
+//!          | <expr>;
+//!          | (unquote <stmt>);
+//!          | if (<atom>) { <stmt> ... } [else { <stmt> ... }]
+//!          | while (<atom>) { <stmt> ... }
+//!          | loop { <stmt> ... }
+//!          | <string_lit>: { <stmt> ... }
+//! <expr> ::= {} | { <id>, ... }
+//!          | <id>(<id>, ...)              // Call
+//!          | <id>!(<id>, ...)             // ClosureCall
+//!          | !<id>(<id>, ...)             // PrimCall
+//!          | <atom>.<id> = <atom>
+//!          | <atom>[<atom>] = <atom>
+//!          | <atom>
+//! <atom> ::= <lit> | null | undefined | <id>
+//!          | any(<atom>) | <atom> as <type> | <atom>.<id>
+//!          | sqrt(<atom>) | -(<atom>) | !(<atom>)
+//!          | <atom> <op> <atom>            // e.g., `x + 1`, `x +. y`, `x === y`
+//!          | (<atom>)
+//!          | (unquote <atom>)
+//! <type> ::= any | i32 | f64 | bool | str | Array | DynObject | HT | ptr | env
+//!          | (unquote <type>)
+//! ```
+//!
+//! Literals are Rust literals, thus `1.5` is an `f64` and `1` is an `i32`. An operand of a
+//! binary operator, `as`, or `.` is a single token or parenthesized, so `x + y + z` must be
+//! written `(x + y) + z`. The macro consumes statements a few tokens at a time, so a very long
+//! function may need a higher `recursion_limit`; such programs are easier to write with
+//! [super::parse].
+
+use super::syntax::Lit;
+
+/// The Rust literals that are NotWasm literals.
+pub trait ToLit {
+    fn to_lit(self) -> Lit;
+}
+
+impl ToLit for i32 {
+    fn to_lit(self) -> Lit {
+        Lit::I32(self)
+    }
+}
+
+impl ToLit for f64 {
+    fn to_lit(self) -> Lit {
+        Lit::F64(self)
+    }
+}
+
+impl ToLit for bool {
+    fn to_lit(self) -> Lit {
+        Lit::Bool(self)
+    }
+}
+
+impl ToLit for &str {
+    fn to_lit(self) -> Lit {
+        Lit::String(self.to_string())
+    }
+}
+
+#[macro_export]
+macro_rules! notwasm {
+    // Types
+    (@type any) => ($crate::notwasm::syntax::Type::Any);
+    (@type i32) => ($crate::notwasm::syntax::Type::I32);
+    (@type f64) => ($crate::notwasm::syntax::Type::F64);
+    (@type bool) => ($crate::notwasm::syntax::Type::Bool);
+    (@type str) => ($crate::notwasm::syntax::Type::String);
+    (@type Array) => ($crate::notwasm::syntax::Type::Array);
+    (@type DynObject) => ($crate::notwasm::syntax::Type::DynObject);
+    (@type HT) => ($crate::notwasm::syntax::Type::HT);
+    (@type ptr) => ($crate::notwasm::syntax::Type::Ptr);
+    (@type env) => ($crate::notwasm::syntax::Type::Env);
+    (@type (unquote $e:expr)) => ($e);
+
+    // Binary operators, which may be two Rust tokens
+    (@binop +) => ($crate::notwasm::syntax::BinaryOp::I32Add);
+    (@binop -) => ($crate::notwasm::syntax::BinaryOp::I32Sub);
+    (@binop *) => ($crate::notwasm::syntax::BinaryOp::I32Mul);
+    (@binop /) => ($crate::notwasm::syntax::BinaryOp::I32Div);
+    (@binop %) => ($crate::notwasm::syntax::BinaryOp::I32Rem);
+    (@binop >) => ($crate::notwasm::syntax::BinaryOp::I32GT);
+    (@binop <) => ($crate::notwasm::syntax::BinaryOp::I32LT);
+    (@binop >=) => ($crate::notwasm::syntax::BinaryOp::I32Ge);
+    (@binop <=) => ($crate::notwasm::syntax::BinaryOp::I32Le);
+    (@binop ==) => ($crate::notwasm::syntax::BinaryOp::I32Eq);
+    (@binop !=) => ($crate::notwasm::syntax::BinaryOp::I32Ne);
+    (@binop &) => ($crate::notwasm::syntax::BinaryOp::I32And);
+    (@binop |) => ($crate::notwasm::syntax::BinaryOp::I32Or);
+    (@binop ^) => ($crate::notwasm::syntax::BinaryOp::I32Xor);
+    (@binop <<) => ($crate::notwasm::syntax::BinaryOp::I32Shl);
+    (@binop >>) => ($crate::notwasm::syntax::BinaryOp::I32Shr);
+    (@binop >> >) => ($crate::notwasm::syntax::BinaryOp::I32ShrU);
+    (@binop == =) => ($crate::notwasm::syntax::BinaryOp::PtrEq);
+    (@binop + .) => ($crate::notwasm::syntax::BinaryOp::F64Add);
+    (@binop - .) => ($crate::notwasm::syntax::BinaryOp::F64Sub);
+    (@binop * .) => ($crate::notwasm::syntax::BinaryOp::F64Mul);
+    (@binop / .) => ($crate::notwasm::syntax::BinaryOp::F64Div);
+    (@binop == .) => ($crate::notwasm::syntax::BinaryOp::F64Eq);
+    (@binop != .) => ($crate::notwasm::syntax::BinaryOp::F64Ne);
+    (@binop > .) => ($crate::notwasm::syntax::BinaryOp::F64GT);
+    (@binop < .) => ($crate::notwasm::syntax::BinaryOp::F64LT);
+    (@binop >= .) => ($crate::notwasm::syntax::BinaryOp::F64Ge);
+    (@binop <= .) => ($crate::notwasm::syntax::BinaryOp::F64Le);
+
+    // Atoms. The rules that start with a token that may begin a literal come before the rule for
+    // literals.
+    (@atom (unquote $e:expr)) => ($e);
+    (@atom ($($a:tt)+)) => ($crate::notwasm!(@atom $($a)+));
+    (@atom - ($($a:tt)+)) => ($crate::notwasm::syntax::Atom::Unary(
+        $crate::notwasm::syntax::UnaryOp::I32Neg,
+        Box::new($crate::notwasm!(@atom $($a)+)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom ! ($($a:tt)+)) => ($crate::notwasm::syntax::Atom::Unary(
+        $crate::notwasm::syntax::UnaryOp::Eqz,
+        Box::new($crate::notwasm!(@atom $($a)+)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom sqrt ($($a:tt)+)) => ($crate::notwasm::syntax::Atom::Unary(
+        $crate::notwasm::syntax::UnaryOp::Sqrt,
+        Box::new($crate::notwasm!(@atom $($a)+)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom any ($($a:tt)+)) => ($crate::notwasm::syntax::Atom::ToAny(
+        $crate::notwasm::syntax::ToAny::new($crate::notwasm!(@atom $($a)+)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom null) => ($crate::notwasm::syntax::Atom::Lit(
+        $crate::notwasm::syntax::Lit::Null,
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom undefined) => ($crate::notwasm::syntax::Atom::Lit(
+        $crate::notwasm::syntax::Lit::Undefined,
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom $l:literal) => ($crate::notwasm::syntax::Atom::Lit(
+        $crate::notwasm::dsl::ToLit::to_lit($l),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom $x:ident) => ($crate::notwasm::syntax::Atom::Id(
+        $crate::notwasm::syntax::Id::from(stringify!($x)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom $a:tt as $t:tt) => ($crate::notwasm::syntax::Atom::FromAny(
+        Box::new($crate::notwasm!(@atom $a)),
+        $crate::notwasm!(@type $t),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom $a:tt . $f:ident) => ($crate::notwasm::syntax::Atom::ObjectGet(
+        Box::new($crate::notwasm!(@atom $a)),
+        Box::new($crate::notwasm::syntax::Atom::Lit(
+            $crate::notwasm::syntax::Lit::String(stringify!($f).to_string()),
+            $crate::pos::Pos::UNKNOWN,
+        )),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom $a:tt $op:tt $b:tt) => ($crate::notwasm::syntax::Atom::Binary(
+        $crate::notwasm!(@binop $op),
+        Box::new($crate::notwasm!(@atom $a)),
+        Box::new($crate::notwasm!(@atom $b)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@atom $a:tt $op1:tt $op2:tt $b:tt) => ($crate::notwasm::syntax::Atom::Binary(
+        $crate::notwasm!(@binop $op1 $op2),
+        Box::new($crate::notwasm!(@atom $a)),
+        Box::new($crate::notwasm!(@atom $b)),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+
+    // Expressions. The rules for calls come after `any(..)` and `sqrt(..)`, which are atoms.
+    (@expr {}) => ($crate::notwasm::syntax::Expr::ObjectEmpty($crate::pos::Pos::UNKNOWN));
+    (@expr { $($f:ident),+ }) => ($crate::notwasm::syntax::Expr::ObjectLiteral(
+        vec![$($crate::notwasm::syntax::Lit::String(stringify!($f).to_string())),+],
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr any $a:tt) => ($crate::notwasm::syntax::Expr::Atom(
+        $crate::notwasm!(@atom any $a),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr sqrt $a:tt) => ($crate::notwasm::syntax::Expr::Atom(
+        $crate::notwasm!(@atom sqrt $a),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr ! $f:ident ($($x:ident),*)) => ($crate::notwasm::syntax::Expr::prim_call(
+        stringify!($f),
+        vec![$($crate::notwasm::syntax::Id::from(stringify!($x))),*],
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr $f:ident ! ($($x:ident),*)) => ($crate::notwasm::syntax::Expr::ClosureCall(
+        $crate::notwasm::syntax::Id::from(stringify!($f)),
+        vec![$($crate::notwasm::syntax::Id::from(stringify!($x))),*],
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr $f:ident ($($x:ident),*)) => ($crate::notwasm::syntax::Expr::Call(
+        $crate::notwasm::syntax::Id::from(stringify!($f)),
+        vec![$($crate::notwasm::syntax::Id::from(stringify!($x))),*],
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr $o:tt . $f:ident = $($v:tt)+) => ($crate::notwasm::syntax::Expr::ObjectSet(
+        $crate::notwasm!(@atom $o),
+        $crate::notwasm::syntax::Atom::Lit(
+            $crate::notwasm::syntax::Lit::String(stringify!($f).to_string()),
+            $crate::pos::Pos::UNKNOWN,
+        ),
+        $crate::notwasm!(@atom $($v)+),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr $a:tt [$($i:tt)+] = $($v:tt)+) => ($crate::notwasm::syntax::Expr::ArraySet(
+        $crate::notwasm!(@atom $a),
+        $crate::notwasm!(@atom $($i)+),
+        $crate::notwasm!(@atom $($v)+),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@expr $($a:tt)+) => ($crate::notwasm::syntax::Expr::Atom(
+        $crate::notwasm!(@atom $($a)+),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+
+    // Simple statements, without the semicolon
+    (@stmt) => ($crate::notwasm::syntax::Stmt::Empty);
+    (@stmt (unquote $e:expr)) => ($e);
+    (@stmt trap) => ($crate::notwasm::syntax::Stmt::Trap);
+    (@stmt var $x:ident : $t:tt = $($e:tt)+) => ($crate::notwasm::syntax::Stmt::Var(
+        $crate::notwasm::syntax::VarStmt {
+            id: $crate::notwasm::syntax::Id::from(stringify!($x)),
+            named: $crate::notwasm!(@expr $($e)+),
+            ty: Some($crate::notwasm!(@type $t)),
+        },
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@stmt var $x:ident = $($e:tt)+) => ($crate::notwasm::syntax::Stmt::Var(
+        $crate::notwasm::syntax::VarStmt::new(
+            $crate::notwasm::syntax::Id::from(stringify!($x)),
+            $crate::notwasm!(@expr $($e)+),
+        ),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@stmt return $($a:tt)+) => ($crate::notwasm::syntax::Stmt::Return(
+        $crate::notwasm!(@atom $($a)+),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@stmt break $l:literal) => ($crate::notwasm::syntax::Stmt::Break(
+        $crate::notwasm::syntax::Label::from($l),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@stmt $x:ident = $($e:tt)+) => ($crate::notwasm::syntax::Stmt::Assign(
+        $crate::notwasm::syntax::Id::from(stringify!($x)),
+        $crate::notwasm!(@expr $($e)+),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+    (@stmt $($e:tt)+) => ($crate::notwasm::syntax::Stmt::Expression(
+        $crate::notwasm!(@expr $($e)+),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+
+    (@block { $($s:tt)* }) => ($crate::notwasm::syntax::Stmt::Block(
+        $crate::notwasm!(@stmts [] [] $($s)*),
+        $crate::pos::Pos::UNKNOWN,
+    ));
+
+    // A sequence of statements. The second list holds the tokens of the simple statement that
+    // the macro is reading, which end at the next semicolon. To need fewer recursive
+    // expansions, the last rule reads three tokens at a time, which the preceding rules ensure
+    // are not semicolons.
+    (@stmts [$($done:expr),*] []) => (vec![$($done),*]);
+    (@stmts [$($done:expr),*] [$($cur:tt)*] ; $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm!(@stmt $($cur)*)] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [$($cur:tt)*] $a:tt ; $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm!(@stmt $($cur)* $a)] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [$($cur:tt)*] $a:tt $b:tt ; $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm!(@stmt $($cur)* $a $b)] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [] if $c:tt $t:tt else $f:tt $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm::syntax::Stmt::If(
+            $crate::notwasm!(@atom $c),
+            Box::new($crate::notwasm!(@block $t)),
+            Box::new($crate::notwasm!(@block $f)),
+            $crate::pos::Pos::UNKNOWN,
+        )] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [] if $c:tt $t:tt $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm::syntax::Stmt::If(
+            $crate::notwasm!(@atom $c),
+            Box::new($crate::notwasm!(@block $t)),
+            Box::new($crate::notwasm::syntax::Stmt::Block(vec![], $crate::pos::Pos::UNKNOWN)),
+            $crate::pos::Pos::UNKNOWN,
+        )] [] $($rest)*)
+    );
+    // The same as the `while` of the parser.
+    (@stmts [$($done:expr),*] [] while $c:tt $body:tt $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm::syntax::Stmt::Label(
+            $crate::notwasm::syntax::Label::from("$loop"),
+            Box::new($crate::notwasm::syntax::Stmt::Loop(
+                Box::new($crate::notwasm::syntax::Stmt::If(
+                    $crate::notwasm!(@atom $c),
+                    Box::new($crate::notwasm!(@block $body)),
+                    Box::new($crate::notwasm::syntax::Stmt::Break(
+                        $crate::notwasm::syntax::Label::from("$loop"),
+                        $crate::pos::Pos::UNKNOWN,
+                    )),
+                    $crate::pos::Pos::UNKNOWN,
+                )),
+                $crate::pos::Pos::UNKNOWN,
+            )),
+            $crate::pos::Pos::UNKNOWN,
+        )] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [] loop $body:tt $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm::syntax::Stmt::Loop(
+            Box::new($crate::notwasm!(@block $body)),
+            $crate::pos::Pos::UNKNOWN,
+        )] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [] $l:literal : $body:tt $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done,)* $crate::notwasm::syntax::Stmt::Label(
+            $crate::notwasm::syntax::Label::from($l),
+            Box::new($crate::notwasm!(@block $body)),
+            $crate::pos::Pos::UNKNOWN,
+        )] [] $($rest)*)
+    );
+    (@stmts [$($done:expr),*] [$($cur:tt)*] $a:tt $b:tt $c:tt $($rest:tt)*) => (
+        $crate::notwasm!(@stmts [$($done),*] [$($cur)* $a $b $c] $($rest)*)
+    );
+
+    // Functions
+    (@function $no_gc:literal $name:ident ($($x:ident : $t:tt),*) $ret:expr, $body:tt) => ((
+        $crate::notwasm::syntax::Id::from(stringify!($name)),
+        $crate::notwasm::syntax::Function {
+            body: $crate::notwasm!(@block $body),
+            fn_type: $crate::notwasm::syntax::FnType {
+                args: vec![$($crate::notwasm!(@type $t)),*],
+                result: $ret,
+            },
+            params: vec![$($crate::notwasm::syntax::Id::from(stringify!($x))),*],
+            span: $crate::pos::Pos::UNKNOWN,
+            no_gc: $no_gc,
+        },
+    ));
+    (@functions [$($done:expr),*]) => ({
+        let mut functions = std::collections::HashMap::new();
+        $(
+            let (name, function) = $done;
+            functions.insert(name, function);
+        )*
+        $crate::notwasm::syntax::Program {
+            rts_fn_imports: std::collections::HashMap::new(),
+            functions,
+            globals: std::collections::HashMap::new(),
+            data: Vec::new(),
+            interned: std::collections::HashMap::new(),
+        }
+    });
+    (@functions [$($done:expr),*] nogc $($rest:tt)*) => (
+        $crate::notwasm!(@no_gc [$($done),*] true $($rest)*)
+    );
+    (@functions [$($done:expr),*] $($rest:tt)+) => (
+        $crate::notwasm!(@no_gc [$($done),*] false $($rest)*)
+    );
+    (@no_gc [$($done:expr),*] $no_gc:literal
+        function $name:ident $params:tt : $ret:tt $body:tt $($rest:tt)*) => (
+        $crate::notwasm!(@functions [$($done,)* $crate::notwasm!(@function $no_gc $name $params
+            Some(Box::new($crate::notwasm!(@type $ret))), $body)] $($rest)*)
+    );
+    (@no_gc [$($done:expr),*] $no_gc:literal
+        function $name:ident $params:tt $body:tt $($rest:tt)*) => (
+        $crate::notwasm!(@functions [$($done,)* $crate::notwasm!(@function $no_gc $name $params
+            None, $body)] $($rest)*)
+    );
+
+    ($($program:tt)*) => ($crate::notwasm!(@functions [] $($program)*));
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::interpreter::interpret;
+    use super::super::parse;
+    use super::super::syntax::*;
+    use crate::notwasm;
+    use crate::pos::Pos;
+
+    #[test]
+    fn same_program_as_parser() {
+        let program = notwasm! {
+            function inc(x: i32): i32 {
+                return x + 1;
+            }
+            function main() {
+                var x: i32 = 1 + 2;
+                x = inc(x);
+                var y = any(x);
+                var z = (y as f64) +. 1.5;
+                var o = {a, b};
+                o.a = any(z);
+                var c = (o.a) === null;
+                while (x < 10) {
+                    if (c) {
+                        break "$loop";
+                    } else {
+                        x = x * 2;
+                    }
+                }
+                "l": {
+                    break "l";
+                }
+                trap;
+            }
+        };
+        let expected = parse(
+            "<test>",
+            r#"
+            function inc(x: i32): i32 {
+                return x + 1;
+            }
+            function main() {
+                var x: i32 = 1 + 2;
+                x = inc(x);
+                var y = any(x);
+                var z = (y as f64) +. 1.5f;
+                var o = {a, b};
+                o.a = any(z);
+                var c = o.a === null;
+                while (x < 10) {
+                    if (c) {
+                        break $loop;
+                    } else {
+                        x = x * 2;
+                    }
+                }
+                l: {
+                    break l;
+                }
+                trap;
+            }
+            "#,
+        );
+        assert_eq!(program.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn unquote_splices_rust_values() {
+        let n = 5;
+        let body = Stmt::Return(Atom::Lit(Lit::I32(n), Pos::UNKNOWN), Pos::UNKNOWN);
+        let program = notwasm! {
+            function main(): i32 {
+                var x: (unquote Type::I32) = (unquote Atom::Lit(Lit::I32(n), Pos::UNKNOWN));
+                (unquote body);
+            }
+        };
+        let main = &program.functions[&Id::from("main")];
+        assert_eq!(
+            main.body,
+            Stmt::Block(
+                vec![
+                    Stmt::Var(
+                        VarStmt {
+                            id: Id::from("x"),
+                            named: Expr::Atom(Atom::Lit(Lit::I32(5), Pos::UNKNOWN), Pos::UNKNOWN),
+                            ty: Some(Type::I32),
+                        },
+                        Pos::UNKNOWN
+                    ),
+                    Stmt::Return(Atom::Lit(Lit::I32(5), Pos::UNKNOWN), Pos::UNKNOWN),
+                ],
+                Pos::UNKNOWN
+            )
+        );
+    }
+
+    #[test]
+    fn runs_in_the_interpreter() {
+        let program = notwasm! {
+            function main() {
+                var i = 0;
+                var sum = 0;
+                while (i < 4) {
+                    sum = sum + i;
+                    i = i + 1;
+                }
+                var s = any(sum);
+                var r = !dbg_log(s);
+            }
+        };
+        assert_eq!(interpret(&program), Ok("I32(6)".to_string()));
+    }
+}
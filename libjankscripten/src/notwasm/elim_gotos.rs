@@ -44,7 +44,7 @@ pub fn elim_gotos(program: &mut Program) {
 #[derive(Default)]
 struct GotoVisitor;
 impl Visitor for GotoVisitor {
-    fn exit_stmt(&mut self, stmt: &mut Stmt) {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
         use Stmt::*;
         match stmt {
             Goto(Lbl::App(l), s) => {
@@ -175,7 +175,7 @@ struct LabelBoundsVisitor {
     bounds: Option<(i32, i32)>,
 }
 impl Visitor for LabelBoundsVisitor {
-    fn exit_stmt(&mut self, stmt: &mut Stmt) {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
         match stmt {
             Stmt::Label(Lbl::App(n), ..) => {
                 if let Some((lo, hi)) = self.bounds.as_mut() {
@@ -0,0 +1,447 @@
+//! Allocate objects that do not escape their function in its scratch region.
+//!
+//! Many objects are temporaries that the program cannot reach after the function that allocates
+//! them returns. This pass finds objects that a function only allocates, reads, and writes, and
+//! turns their allocations into `ScratchObject`, which allocates the object in the scratch region
+//! of the runtime system. The function frees its scratch objects when it returns, so they never
+//! burden the garbage collector.
+//!
+//! The analysis is flow-insensitive and tracks objects through the local variables that hold
+//! them. The object of `var x = {...}` does not escape when every use of `x` and its aliases is
+//! either:
+//!
+//! - the object of `x.f` or `x.f = a`, or
+//! - the whole right-hand side of `var y = x` or `y = x`, where `y` is a local variable.
+//!
+//! Any other use (e.g., an argument of a call, `any(x)`, `return x`, or the value of `o.f = x`)
+//! may store the object where the program can reach it later.
+//!
+//! A function that allocates scratch objects in a loop uses more of the scratch region on each
+//! iteration. When the region is full, the runtime system allocates on the heap instead.
+//!
+//! The same analysis finds the f64s that a function boxes only to pass them to an arithmetic or
+//! comparison operator of the runtime system, e.g., the intermediates of `a*b + c*d` when `a` is
+//! an `any`. The operator reads its operands and produces a new value, so the box of
+//! `var t = any(x)`, where `x` is an f64, does not escape when every use of `t` and its aliases is
+//! an operand of such an operator. This pass boxes it with `@f64_to_any_scratch(x)`, which
+//! allocates the box in the f64 scratch area of the runtime system, which the function frees
+//! when it returns. Objects are still only allocated in the scratch region when they are not
+//! operands, since the operators are rarely applied to objects.
+//!
+//! preconditions: type_check, which ensures that `x` in `x.f` is an object
+use super::syntax::*;
+use super::walk::*;
+use crate::rts_function::RTSFunction;
+use std::collections::{HashMap, HashSet};
+
+pub fn escape_analysis(program: &mut Program) {
+    for func in program.functions.values_mut() {
+        let mut escape = Escape::default();
+        escape.locals.extend(func.params.iter().cloned());
+        escape.stmt(&func.body);
+        let escaped = escape.escaped(true);
+        let boxes_escaped = escape.escaped(false);
+        func.body.walk(&mut ToScratch {
+            escaped: &escaped,
+            boxes_escaped: &boxes_escaped,
+        });
+    }
+}
+
+/// Determines if `body` boxes any f64s in the scratch area, which requires the function to free
+/// them when it returns.
+pub fn boxes_scratch_f64s(body: &mut Stmt) -> bool {
+    let mut v = BoxesScratchF64s(false);
+    body.walk(&mut v);
+    v.0
+}
+
+struct BoxesScratchF64s(bool);
+
+impl Visitor for BoxesScratchF64s {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::PrimCall(RTSFunction::F64ToAnyScratch, ..) = expr {
+            self.0 = true;
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.0
+    }
+}
+
+/// Determines if the runtime function only reads its arguments, and produces a number or a bool
+/// that is not one of them. `+` is not one of these, since it may produce its string operand.
+fn only_reads_operands(prim: &RTSFunction) -> bool {
+    use RTSFunction::*;
+    match prim {
+        Neg | Minus | Times | Over | Mod | StrictEqual | StrictNotEqual => true,
+        // The standard library calls them by name.
+        Import(name) => match name.as_str() {
+            "janky_neg"
+            | "janky_minus"
+            | "janky_times"
+            | "janky_over"
+            | "janky_mod"
+            | "any_strict_eq"
+            | "janky_strict_not_equal" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Determines if `body` allocates any scratch objects, which requires the function to free them
+/// when it returns.
+pub fn allocates_scratch_objects(body: &mut Stmt) -> bool {
+    let mut v = AllocatesScratchObjects(false);
+    body.walk(&mut v);
+    v.0
+}
+
+struct AllocatesScratchObjects(bool);
+
+impl Visitor for AllocatesScratchObjects {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if let Expr::ScratchObject(..) = expr {
+            self.0 = true;
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.0
+    }
+}
+
+#[derive(Default)]
+struct Escape {
+    /// The parameters of the function and the variables that it declares.
+    locals: HashSet<Id>,
+    /// Maps `y` to every `x` in `var y = x` or `y = x`, since the object in `x` escapes if `y`
+    /// escapes.
+    aliases: HashMap<Id, Vec<Id>>,
+    /// The variables that the function uses in a way that may let their object escape.
+    uses: HashSet<Id>,
+    /// The variables that are operands of a runtime function that only reads them.
+    operands: HashSet<Id>,
+}
+
+impl Escape {
+    /// The variables whose object may escape, which is every variable that flows into a variable
+    /// that escapes. When `operands_escape` holds, an operand of an operator escapes too.
+    fn escaped(&self, operands_escape: bool) -> HashSet<Id> {
+        let mut escaped = self.uses.clone();
+        if operands_escape {
+            escaped.extend(self.operands.iter().cloned());
+        }
+        // Assigning to a global lets the object escape too.
+        for (y, xs) in &self.aliases {
+            if !self.locals.contains(y) {
+                escaped.extend(xs.iter().cloned());
+            }
+        }
+        let mut worklist: Vec<Id> = escaped.iter().cloned().collect();
+        while let Some(y) = worklist.pop() {
+            for x in self.aliases.get(&y).into_iter().flatten() {
+                if escaped.insert(x.clone()) {
+                    worklist.push(x.clone());
+                }
+            }
+        }
+        escaped
+    }
+
+    fn escape(&mut self, x: &Id) {
+        self.uses.insert(x.clone());
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Empty | Stmt::Break(..) | Stmt::Trap | Stmt::Goto(..) => (),
+            Stmt::Var(var_stmt, _) => {
+                self.locals.insert(var_stmt.id.clone());
+                self.assign(&var_stmt.id, &var_stmt.named);
+            }
+            Stmt::Assign(x, e, _) => self.assign(x, e),
+            Stmt::Expression(e, _) => self.expr(e),
+            Stmt::Store(r, e, _) => {
+                self.escape(r);
+                self.expr(e);
+            }
+            Stmt::If(a, s1, s2, _) => {
+                self.atom(a);
+                self.stmt(s1);
+                self.stmt(s2);
+            }
+            Stmt::Loop(s, _) | Stmt::Label(_, s, _) => self.stmt(s),
+            Stmt::Return(a, _) => self.atom(a),
+            Stmt::Block(ss, _) => {
+                for s in ss {
+                    self.stmt(s);
+                }
+            }
+        }
+    }
+
+    fn assign(&mut self, y: &Id, e: &Expr) {
+        match e {
+            Expr::Atom(Atom::Id(x, _), _) => {
+                self.aliases.entry(y.clone()).or_default().push(x.clone());
+            }
+            _ => self.expr(e),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::ObjectEmpty(_) | Expr::ObjectLiteral(..) | Expr::ScratchObject(..) => (),
+            Expr::ArraySet(a1, a2, a3, _) => {
+                self.atom(a1);
+                self.atom(a2);
+                self.atom(a3);
+            }
+            Expr::PrimCall(prim, xs, _) | Expr::Speculate(_, prim, xs, _)
+                if only_reads_operands(prim) =>
+            {
+                self.operands.extend(xs.iter().cloned());
+            }
+            Expr::Call(_, xs, _) | Expr::PrimCall(_, xs, _) | Expr::Speculate(_, _, xs, _) => {
+                for x in xs {
+                    self.escape(x);
+                }
+            }
+            Expr::ClosureCall(f, xs, _) | Expr::AnyMethodCall(f, _, xs, ..) => {
+                self.escape(f);
+                for x in xs {
+                    self.escape(x);
+                }
+            }
+            Expr::ObjectSet(obj, field, value, _) => {
+                self.object(obj);
+                self.atom(field);
+                self.atom(value);
+            }
+            Expr::NewRef(a, ..) | Expr::Atom(a, _) => self.atom(a),
+            Expr::Closure(_, env, _) => {
+                for (a, _) in env {
+                    self.atom(a);
+                }
+            }
+        }
+    }
+
+    /// The object of `obj.f` or `obj.f = a`, which does not escape.
+    fn object(&mut self, obj: &Atom) {
+        match obj {
+            Atom::Id(..) => (),
+            _ => self.atom(obj),
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom) {
+        match atom {
+            Atom::Lit(..) | Atom::GetPrimFunc(..) | Atom::EnvGet(..) => (),
+            Atom::Id(x, _) | Atom::AnyLength(x, ..) => self.escape(x),
+            Atom::PrimApp(_, args, _) => {
+                for a in args {
+                    self.atom(a);
+                }
+            }
+            Atom::ToAny(to_any, _) => self.atom(&to_any.atom),
+            Atom::FromAny(a, ..)
+            | Atom::FloatToInt(a, _)
+            | Atom::IntToFloat(a, _)
+            | Atom::Unary(_, a, _)
+            | Atom::Deref(a, ..) => self.atom(a),
+            Atom::ObjectGet(obj, field, _) => {
+                self.object(obj);
+                self.atom(field);
+            }
+            Atom::Binary(_, a1, a2, _) => {
+                self.atom(a1);
+                self.atom(a2);
+            }
+        }
+    }
+}
+
+/// Allocates the objects of the variables that do not escape in the scratch region, and boxes
+/// their f64s in the f64 scratch area.
+struct ToScratch<'a> {
+    escaped: &'a HashSet<Id>,
+    boxes_escaped: &'a HashSet<Id>,
+}
+
+impl Visitor for ToScratch<'_> {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Var(var_stmt, _) = stmt {
+            if self.boxes_escaped.contains(&var_stmt.id) {
+                return;
+            }
+            let escaped = self.escaped.contains(&var_stmt.id);
+            let scratch = match &mut var_stmt.named {
+                Expr::ObjectEmpty(p) if !escaped => Expr::ScratchObject(Vec::new(), p.clone()),
+                Expr::ObjectLiteral(fields, p) if !escaped => {
+                    Expr::ScratchObject(std::mem::take(fields), p.clone())
+                }
+                Expr::Atom(Atom::ToAny(to_any, _), p) => match (&*to_any.atom, &to_any.ty) {
+                    (Atom::Id(x, _), Some(Type::F64)) => {
+                        Expr::PrimCall(RTSFunction::F64ToAnyScratch, vec![x.clone()], p.clone())
+                    }
+                    _ => return,
+                },
+                _ => return,
+            };
+            var_stmt.named = scratch;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    /// The variables of `main` that hold scratch objects.
+    fn scratch_vars(notwasm_code: &str) -> Vec<String> {
+        let mut program = parse("<test>", notwasm_code);
+        escape_analysis(&mut program);
+        let main = program.functions.get_mut(&Id::from("main")).unwrap();
+        let mut v = ScratchVars(Vec::new());
+        main.body.walk(&mut v);
+        v.0
+    }
+
+    /// The variables of `main` that hold scratch objects or f64s, after type-checking, which
+    /// determines which `any(x)` box f64s.
+    fn typed_scratch_vars(notwasm_code: &str) -> Vec<String> {
+        let mut program = parse("<test>", notwasm_code);
+        super::super::type_checking::type_check(&mut program).expect("type error");
+        escape_analysis(&mut program);
+        let main = program.functions.get_mut(&Id::from("main")).unwrap();
+        assert!(boxes_scratch_f64s(&mut main.body));
+        let mut v = ScratchVars(Vec::new());
+        main.body.walk(&mut v);
+        v.0
+    }
+
+    struct ScratchVars(Vec<String>);
+
+    impl Visitor for ScratchVars {
+        fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+            if let Stmt::Var(var_stmt, _) = stmt {
+                match var_stmt.named {
+                    Expr::ScratchObject(..) | Expr::PrimCall(RTSFunction::F64ToAnyScratch, ..) => {
+                        self.0.push(var_stmt.id.to_string())
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn object_that_only_has_fields_accessed() {
+        let vars = scratch_vars(
+            r#"
+            function main(): i32 {
+                var o = {x};
+                o.x = any(10);
+                var p = {};
+                p.y = o.x;
+                return p.y as i32;
+            }
+            "#,
+        );
+        assert_eq!(vars, vec!["o".to_string(), "p".to_string()]);
+    }
+
+    #[test]
+    fn returned_object_escapes() {
+        let vars = scratch_vars(
+            r#"
+            function main(): DynObject {
+                var o = {};
+                return o;
+            }
+            "#,
+        );
+        assert!(vars.is_empty());
+    }
+
+    #[test]
+    fn stored_object_escapes() {
+        let vars = scratch_vars(
+            r#"
+            function main(): i32 {
+                var o = {};
+                var p = {};
+                p.o = any(o);
+                return 0;
+            }
+            "#,
+        );
+        assert_eq!(vars, vec!["p".to_string()]);
+    }
+
+    #[test]
+    fn object_escapes_through_alias() {
+        let vars = scratch_vars(
+            r#"
+            function main(): i32 {
+                var o = {};
+                var p = {};
+                var q = p;
+                q.x = any(1);
+                var r = o;
+                var s = any(r);
+                return 0;
+            }
+            "#,
+        );
+        assert_eq!(vars, vec!["p".to_string()]);
+    }
+
+    #[test]
+    fn f64_operands_of_arithmetic() {
+        let vars = typed_scratch_vars(
+            r#"
+            import janky_times: (any, any) -> any;
+            import janky_minus: (any, any) -> any;
+            function main(): any {
+                var x = 1.5;
+                var a = any(x);
+                var b = any(x);
+                var c = any(x);
+                var d = c;
+                var ab = !janky_times(a, b);
+                var cd = !janky_times(d, b);
+                var e = any(x);
+                var o = {};
+                o.e = e;
+                var f = any(x);
+                var r = !janky_minus(ab, f);
+                return f;
+            }
+            "#,
+        );
+        assert_eq!(vars, vec!["a", "b", "c", "o"]);
+    }
+
+    #[test]
+    fn object_operands_escape() {
+        let vars = scratch_vars(
+            r#"
+            function main(): any {
+                var o = {};
+                var p = any(o);
+                var q = !janky_times(p, p);
+                var r = {};
+                var b = !any_strict_eq(r, r);
+                return q;
+            }
+            "#,
+        );
+        assert!(vars.is_empty());
+    }
+}
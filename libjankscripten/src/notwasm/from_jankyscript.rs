@@ -93,12 +93,12 @@
 //! - Two kinds of contexts
 
 use super::super::jankyscript::syntax as J;
-use super::super::rope::Rope;
 use super::constructors::*;
 use super::syntax::*;
 use crate::pos::Pos;
 use crate::shared::methods::METHODS_TABLE;
 use crate::shared::NameGen;
+use crate::shared::Rope;
 use std::collections::HashMap;
 
 fn compile_lit(lit: J::Lit) -> Lit {
@@ -126,9 +126,10 @@ impl S {
     }
 
     fn new_function(&mut self, name: Id, f: Function) {
-        // TODO(arjun): we will have to rename functions if there are two with the same name in
-        // different scopes.
-        self.functions.insert(name, f);
+        // Lifted functions have fresh names, and alpha-renaming ensures that functions with the
+        // same name in different scopes are different variables, so names never collide.
+        let previous = self.functions.insert(name, f);
+        assert!(previous.is_none(), "two functions have the same name");
     }
 }
 
@@ -141,6 +142,9 @@ enum C<'a> {
     /// Context expects an `Expr`. This is the easy case, since an `Atom` or `Id` can be injected
     /// into an `Expr`.
     Expr(Box<dyn FnOnce(&'a mut S, Expr) -> Rope<Stmt> + 'a>),
+    /// Context discards the result, so an `Expr` only runs for its effects and an `Atom` does not
+    /// run at all.
+    Discard,
 }
 
 impl<'a> C<'a> {
@@ -165,15 +169,21 @@ impl<'a> C<'a> {
             C::Id(f) => match a {
                 Atom::Id(x, _) => f(state, x),
                 _ => {
+                    // The temporary points at the expression that it names.
+                    let p = a.pos().clone();
                     let x = state.fresh();
                     Rope::singleton(Stmt::Var(
-                        VarStmt::new(x.clone(), Expr::Atom(a, Default::default())),
-                        Default::default(),
+                        VarStmt::new(x.clone(), Expr::Atom(a, p.clone())),
+                        p,
                     ))
                     .append(f(state, x))
                 }
             },
-            C::Expr(f) => f(state, Expr::Atom(a, Default::default())),
+            C::Expr(f) => {
+                let p = a.pos().clone();
+                f(state, Expr::Atom(a, p))
+            }
+            C::Discard => Rope::nil(),
         }
     }
 
@@ -182,15 +192,20 @@ impl<'a> C<'a> {
             // The Id and Atom cases are essentially identical
             C::Id(f) => {
                 let x = state.fresh();
-                Rope::singleton(Stmt::Var(VarStmt::new(x.clone(), e), Default::default()))
-                    .append(f(state, x))
+                let p = e.pos().clone();
+                Rope::singleton(Stmt::Var(VarStmt::new(x.clone(), e), p)).append(f(state, x))
             }
             C::Atom(f) => {
                 let x = state.fresh();
-                Rope::singleton(Stmt::Var(VarStmt::new(x.clone(), e), Default::default()))
-                    .append(f(state, Atom::Id(x, Default::default())))
+                let p = e.pos().clone();
+                Rope::singleton(Stmt::Var(VarStmt::new(x.clone(), e), p.clone()))
+                    .append(f(state, Atom::Id(x, p)))
             }
             C::Expr(f) => f(state, e),
+            C::Discard => {
+                let p = e.pos().clone();
+                Rope::singleton(Stmt::Expression(e, p))
+            }
         }
     }
 }
@@ -230,6 +245,36 @@ fn coercion_to_expr(c: J::Coercion, a: Atom, p: Pos) -> Atom {
     }
 }
 
+/// Creates the object for an object literal with the fields `keys`. The object starts with a
+/// class that has every field, so writing the fields does not transition its class. The class
+/// starts with a `__proto__` field, so a literal whose first key is `__proto__` (e.g., the
+/// objects that simple constructors create) writes its prototype into that field. A `__proto__`
+/// key anywhere else would replace the prototype after other fields, so such a literal starts as
+/// an empty object.
+fn object_literal_(keys: &[String], p: Pos) -> Expr {
+    let keys = match keys.split_first() {
+        Some((first, rest)) if first == "__proto__" => rest,
+        _ => keys,
+    };
+    if keys.is_empty() || keys.iter().any(|key| key == "__proto__") {
+        return Expr::ObjectEmpty(p);
+    }
+    // A repeated key is a single field, which is at the position of its first occurrence.
+    let mut fields: Vec<&String> = Vec::new();
+    for key in keys {
+        if !fields.contains(&key) {
+            fields.push(key);
+        }
+    }
+    Expr::ObjectLiteral(
+        fields
+            .into_iter()
+            .map(|key| Lit::String(key.clone()))
+            .collect(),
+        p,
+    )
+}
+
 fn compile_expr<'a>(state: &'a mut S, expr: J::Expr, cxt: C<'a>) -> Rope<Stmt> {
     match expr {
         J::Expr::JsOp(..) => panic!("impossible case: cannot compile JsOp to WebAssembly"),
@@ -253,18 +298,22 @@ fn compile_expr<'a>(state: &'a mut S, expr: J::Expr, cxt: C<'a>) -> Rope<Stmt> {
         }),
         J::Expr::Object(keys_exprs, p) => {
             let (keys, exprs): (Vec<_>, Vec<_>) = keys_exprs.into_iter().unzip();
+            let keys: Vec<String> = keys
+                .into_iter()
+                .map(|key| match key {
+                    J::Key::Str(key_str) => key_str,
+                    // JavaScript turns numeric keys into strings.
+                    J::Key::Int(n) => n.to_string(),
+                })
+                .collect();
+            let new_object = object_literal_(&keys, p.clone());
             compile_exprs(state, exprs, move |state, ids| {
-                // TODO: semi-static classes when objects are defined like this
                 let obj_name = state.fresh();
                 let mut rv = Rope::singleton(Stmt::Var(
-                    VarStmt::new(obj_name.clone(), Expr::ObjectEmpty),
+                    VarStmt::new(obj_name.clone(), new_object),
                     p.clone(),
                 ));
-                for (key, id) in keys.into_iter().zip(ids) {
-                    let key_str = match key {
-                        J::Key::Str(state) => state,
-                        J::Key::Int(_) => todo!(),
-                    };
+                for (key_str, id) in keys.into_iter().zip(ids) {
                     rv = rv.append(Rope::singleton(Stmt::Expression(
                         Expr::ObjectSet(
                             Atom::Id(obj_name.clone(), p.clone()),
@@ -355,60 +404,66 @@ fn compile_expr<'a>(state: &'a mut S, expr: J::Expr, cxt: C<'a>) -> Rope<Stmt> {
                 )
             }),
         ),
-        J::Expr::Assign(lv, e, p) => compile_expr(
-            state,
-            *e,
-            // TODO(luna): if we change Assign to an expression, we can make
-            // this C::e and drop the clone which will generate less useless
-            // locals; but it will mean sometimes dropping values. we
-            // could also change Assign to an atom, which would mean
-            // introducing new locals for assignment expressions
-            // but differently. see this discussion on slack:
-            // https://plasma.slack.com/archives/C013E3BK7QA/p1596656877066800
-            C::a(move |state, a| match *lv {
-                J::LValue::Id(id, _) => {
-                    Rope::singleton(Stmt::Assign(id, atom_(a.clone(), p.clone()), p))
-                        .append(cxt.recv_a(state, a))
-                }
-                J::LValue::Dot(container, field) => {
-                    // TODO(luna): don't assume bracket is array
-                    compile_expr(
+        // When the result of `x = e` is discarded, which is almost always the case, the
+        // assignment receives `e` directly, so `x = f() + 1` does not name `f() + 1`.
+        J::Expr::Assign(lv, e, p) => match (*lv, cxt) {
+            (J::LValue::Id(id, _), C::Discard) => compile_expr(
+                state,
+                *e,
+                C::e(move |_s, e| Rope::singleton(Stmt::Assign(id, e, p))),
+            ),
+            (lv, cxt) => compile_expr(
+                state,
+                *e,
+                C::a(move |state, a| match lv {
+                    // The value of the assignment is the variable, since the atom may compute
+                    // (e.g., box an f64) again.
+                    J::LValue::Id(id, _) => {
+                        Rope::singleton(Stmt::Assign(id.clone(), atom_(a, p.clone()), p.clone()))
+                            .append(cxt.recv_a(state, Atom::Id(id, p)))
+                    }
+                    J::LValue::Dot(container, field) => {
+                        // TODO(luna): don't assume bracket is array
+                        compile_expr(
+                            state,
+                            container,
+                            // TODO(luna): support array set in notwasm, i can't
+                            // believe we don't yet
+                            C::a(move |state, cont| {
+                                cxt.recv_e(
+                                    state,
+                                    Expr::ObjectSet(
+                                        cont,
+                                        Atom::Lit(Lit::String(field.to_pretty(80)), p.clone()),
+                                        a,
+                                        p,
+                                    ),
+                                )
+                            }),
+                        )
+                    }
+                    J::LValue::Bracket(container, field, typ) => compile_expr(
                         state,
                         container,
-                        // TODO(luna): support array set in notwasm, i can't
-                        // believe we don't yet
                         C::a(move |state, cont| {
-                            cxt.recv_e(
+                            compile_expr(
                                 state,
-                                Expr::ObjectSet(
-                                    cont,
-                                    Atom::Lit(Lit::String(field.to_pretty(80)), p.clone()),
-                                    a,
-                                    p,
-                                ),
+                                field,
+                                C::a(move |state, f| match typ {
+                                    J::Type::Array => {
+                                        cxt.recv_e(state, Expr::ArraySet(cont, f, a, p))
+                                    }
+                                    J::Type::DynObject => {
+                                        cxt.recv_e(state, Expr::ObjectSet(cont, f, a, p))
+                                    }
+                                    _ => panic!("bad bracket lvalue type"),
+                                }),
                             )
                         }),
-                    )
-                }
-                J::LValue::Bracket(container, field, typ) => compile_expr(
-                    state,
-                    container,
-                    C::a(move |state, cont| {
-                        compile_expr(
-                            state,
-                            field,
-                            C::a(move |state, f| match typ {
-                                J::Type::Array => cxt.recv_e(state, Expr::ArraySet(cont, f, a, p)),
-                                J::Type::DynObject => {
-                                    cxt.recv_e(state, Expr::ObjectSet(cont, f, a, p))
-                                }
-                                _ => panic!("bad bracket lvalue type"),
-                            }),
-                        )
-                    }),
-                ),
-            }),
-        ),
+                    ),
+                }),
+            ),
+        },
         J::Expr::PrimCall(prim_name, args, p) => {
             compile_exprs(state, args, move |state, arg_ids| {
                 cxt.recv_e(state, Expr::PrimCall(prim_name, arg_ids, p))
@@ -516,14 +571,9 @@ fn compile_stmt<'a>(state: &'a mut S, stmt: J::Stmt) -> Rope<Stmt> {
             p,
         )),
         S::Empty => Rope::singleton(Stmt::Empty),
-        S::Expr(e, _) => compile_expr(
-            state,
-            *e,
-            // We could use a C::e context. However, the C::a context will make generated code
-            // easier to understand in trivial examples. A C::e context would discard useless
-            // binary operations.
-            C::a(|_s, _a_notwasm| Rope::nil()),
-        ),
+        // Pragmas have served their purpose by the time we reach NotWasm.
+        S::Pragma(..) => Rope::new(),
+        S::Expr(e, _) => compile_expr(state, *e, C::Discard),
         S::If(cond, then_branch, else_branch, p) => compile_expr(
             state,
             *cond,
@@ -580,23 +630,32 @@ fn compile_function<'a>(state: &'a mut S, f: J::Func, p: Pos) -> Function {
         .chain(jnks_tys.into_iter().map(|t| t.notwasm_typ(true)))
         .collect();
     Function {
-        body: Stmt::Block(compile_stmt(state, *f.body).into_iter().collect(), p),
+        body: Stmt::Block(
+            compile_stmt(state, *f.body).into_iter().collect(),
+            p.clone(),
+        ),
         params: param_names,
         fn_type: FnType {
             args: param_tys,
             result: Some(Box::new(f.result_typ.notwasm_typ(true))),
         },
-        span: Default::default(),
+        span: p,
+        no_gc: false,
     }
 }
 
 pub fn from_jankyscript(janky_program: J::Stmt) -> Program {
     let mut state: S = Default::default();
+    // The parser wraps the whole program in a block, whose position is the entire file.
+    let p = match &janky_program {
+        J::Stmt::Block(_, p) => p.clone(),
+        _ => Pos::UNKNOWN,
+    };
     let main_body = Stmt::Block(
         compile_stmt(&mut state, janky_program)
             .into_iter()
             .collect(),
-        Default::default(),
+        p.clone(),
     );
     state.new_function(
         Id::from("main"),
@@ -607,7 +666,8 @@ pub fn from_jankyscript(janky_program: J::Stmt) -> Program {
                 args: Vec::new(),
                 result: None,
             },
-            span: Default::default(),
+            span: p,
+            no_gc: false,
         },
     );
     Program {
@@ -615,5 +675,292 @@ pub fn from_jankyscript(janky_program: J::Stmt) -> Program {
         functions: state.functions,
         globals: HashMap::new(),
         data: Vec::new(),
+        interned: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::walk::*;
+    use super::*;
+    use crate::jankyscript;
+    use crate::javascript::{desugar, parse};
+    use crate::opts::Opts;
+    use crate::rts_function::RTSFunction;
+    use crate::shared::NameGen;
+
+    /// Counts the variable declarations and expressions that do not have a source position.
+    #[derive(Default)]
+    struct CountUnknownPositions {
+        num_unknown: usize,
+    }
+
+    impl Visitor for CountUnknownPositions {
+        fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+            if let Stmt::Var(_, p) = stmt {
+                if p.is_unknown() {
+                    self.num_unknown += 1;
+                }
+            }
+        }
+
+        fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+            if expr.pos().is_unknown() {
+                self.num_unknown += 1;
+            }
+        }
+    }
+
+    fn count_unknown_positions(js_code: &str) -> usize {
+        let mut js = parse("<text>", js_code).expect("error parsing JavaScript");
+        desugar(&mut js, &mut NameGen::default());
+        let mut janky = jankyscript::from_js::from_javascript(js);
+        jankyscript::compile(&Opts::new(), &mut janky, |_| ())
+            .expect("error compiling JankyScript");
+        let mut program = from_jankyscript(janky);
+        let mut v = CountUnknownPositions::default();
+        program
+            .functions
+            .get_mut(&Id::from("main"))
+            .expect("no main function")
+            .body
+            .walk(&mut v);
+        v.num_unknown
+    }
+
+    /// Counts the variable declarations that A-normalization generates.
+    #[derive(Default)]
+    struct CountTemporaries {
+        num_temporaries: usize,
+    }
+
+    impl Visitor for CountTemporaries {
+        fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+            if let Stmt::Var(
+                VarStmt {
+                    id: Id::Generated(_),
+                    ..
+                },
+                _,
+            ) = stmt
+            {
+                self.num_temporaries += 1;
+            }
+        }
+    }
+
+    /// Counts the temporaries in `main` when `stmt` is the whole program.
+    fn count_temporaries(stmt: J::Stmt) -> usize {
+        let mut program = from_jankyscript(J::Stmt::Block(vec![stmt], Pos::UNKNOWN));
+        let mut v = CountTemporaries::default();
+        program
+            .functions
+            .get_mut(&Id::from("main"))
+            .expect("no main function")
+            .body
+            .walk(&mut v);
+        v.num_temporaries
+    }
+
+    fn id(x: &str) -> J::Expr {
+        J::Expr::Id(Id::from(x), J::Type::Int, Pos::UNKNOWN)
+    }
+
+    /// `f(x)`
+    fn call_f() -> J::Expr {
+        J::Expr::Call(Box::new(id("f")), vec![id("x")], Pos::UNKNOWN)
+    }
+
+    /// `f(x) + 1`
+    fn call_f_plus_one() -> J::Expr {
+        let one = J::Expr::Lit(J::Lit::Num(J::Num::Int(1)), Pos::UNKNOWN);
+        J::Expr::Binary(
+            BinaryOp::I32Add,
+            Box::new(call_f()),
+            Box::new(one),
+            Pos::UNKNOWN,
+        )
+    }
+
+    fn assign(x: &str, e: J::Expr) -> J::Expr {
+        let lv = J::LValue::Id(Id::from(x), J::Type::Int);
+        J::Expr::Assign(Box::new(lv), Box::new(e), Pos::UNKNOWN)
+    }
+
+    #[test]
+    fn var_names_only_operands() {
+        let stmt = J::Stmt::Var(
+            Id::from("r"),
+            J::Type::Int,
+            Box::new(call_f_plus_one()),
+            Pos::UNKNOWN,
+        );
+        assert_eq!(count_temporaries(stmt), 1);
+    }
+
+    #[test]
+    fn assignment_names_only_operands() {
+        let stmt = J::Stmt::Expr(Box::new(assign("r", call_f_plus_one())), Pos::UNKNOWN);
+        assert_eq!(count_temporaries(stmt), 1);
+    }
+
+    #[test]
+    fn assignment_receives_call() {
+        let stmt = J::Stmt::Expr(Box::new(assign("r", call_f())), Pos::UNKNOWN);
+        assert_eq!(count_temporaries(stmt), 0);
+    }
+
+    #[test]
+    fn discarded_call_is_not_named() {
+        let stmt = J::Stmt::Expr(Box::new(call_f()), Pos::UNKNOWN);
+        assert_eq!(count_temporaries(stmt), 0);
+    }
+
+    #[test]
+    fn nested_assignment_is_named() {
+        let stmt = J::Stmt::Var(
+            Id::from("r"),
+            J::Type::Int,
+            Box::new(assign("s", call_f())),
+            Pos::UNKNOWN,
+        );
+        assert_eq!(count_temporaries(stmt), 1);
+    }
+
+    /// Collects the fields of the object literals in a program.
+    #[derive(Default)]
+    struct ObjectLiterals {
+        fields: Vec<Vec<Lit>>,
+    }
+
+    impl Visitor for ObjectLiterals {
+        fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+            if let Expr::ObjectLiteral(fields, _) = expr {
+                self.fields.push(fields.clone());
+            }
+        }
+    }
+
+    fn object_literal_fields(keys: &[&str]) -> Vec<Vec<Lit>> {
+        object_literal_keys(keys.iter().map(|key| J::Key::Str(key.to_string())))
+    }
+
+    fn object_literal_keys(keys: impl Iterator<Item = J::Key>) -> Vec<Vec<Lit>> {
+        let props = keys.map(|key| (key, id("x"))).collect();
+        let object = J::Expr::Object(props, Pos::UNKNOWN);
+        let stmt = J::Stmt::Var(Id::from("o"), J::Type::Any, Box::new(object), Pos::UNKNOWN);
+        let mut program = from_jankyscript(J::Stmt::Block(vec![stmt], Pos::UNKNOWN));
+        let mut v = ObjectLiterals::default();
+        program
+            .functions
+            .get_mut(&Id::from("main"))
+            .expect("no main function")
+            .body
+            .walk(&mut v);
+        v.fields
+    }
+
+    #[test]
+    fn object_literal_has_each_field_once() {
+        let fields = vec![Lit::String("x".to_string()), Lit::String("y".to_string())];
+        assert_eq!(object_literal_fields(&["x", "y", "x"]), vec![fields]);
+    }
+
+    #[test]
+    fn object_literal_with_proto_starts_empty() {
+        assert!(object_literal_fields(&[]).is_empty());
+        assert!(object_literal_fields(&["x", "__proto__"]).is_empty());
+    }
+
+    #[test]
+    fn object_literal_may_start_with_proto() {
+        let fields = vec![Lit::String("x".to_string())];
+        assert_eq!(object_literal_fields(&["__proto__", "x"]), vec![fields]);
+    }
+
+    #[test]
+    fn object_literal_with_int_keys() {
+        let keys = vec![J::Key::Int(1), J::Key::Str("x".to_string()), J::Key::Int(1)];
+        let fields = vec![Lit::String("1".to_string()), Lit::String("x".to_string())];
+        assert_eq!(object_literal_keys(keys.into_iter()), vec![fields]);
+    }
+
+    #[test]
+    fn temporaries_have_positions() {
+        assert_eq!(
+            count_unknown_positions("var x = 1 + 2 * 3; var y = x * x - x;"),
+            0
+        );
+    }
+
+    /// Counts the f64s that a program boxes, which are the calls to `f64_to_any` and
+    /// `f64_to_any_scratch` that translation emits.
+    #[derive(Default)]
+    struct CountF64Boxes {
+        num_boxes: usize,
+    }
+
+    impl Visitor for CountF64Boxes {
+        fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+            if let Expr::PrimCall(RTSFunction::F64ToAnyScratch, ..) = expr {
+                self.num_boxes += 1;
+            }
+        }
+
+        fn enter_atom(&mut self, atom: &mut Atom, _loc: &Loc) {
+            if let Atom::ToAny(to_any, _) = atom {
+                if to_any.ty() == &Type::F64 {
+                    self.num_boxes += 1;
+                }
+            }
+        }
+    }
+
+    /// Counts the f64s that the functions of `js_code` box, after the passes that choose how to
+    /// box them. The functions of the standard library do not count.
+    fn count_f64_boxes(js_code: &str) -> usize {
+        let mut js = parse("<text>", js_code).expect("error parsing JavaScript");
+        desugar(&mut js, &mut NameGen::default());
+        let mut janky = jankyscript::from_js::from_javascript(js);
+        jankyscript::compile(&Opts::new(), &mut janky, |_| ())
+            .expect("error compiling JankyScript");
+        let mut program = from_jankyscript(janky);
+        let functions: Vec<Id> = program.functions.keys().cloned().collect();
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let stdlib_path = root.parent().unwrap().join("stdlib.notwasm");
+        let stdlib = std::fs::read_to_string(&stdlib_path).expect("error reading stdlib.notwasm");
+        let includes = super::super::stdlib::read_includes(&stdlib_path)
+            .expect("error reading the files that stdlib.notwasm includes");
+        super::super::stdlib::link(&mut program, &stdlib, &includes)
+            .expect("error linking the standard library");
+        super::super::type_checking::type_check(&mut program).expect("error type-checking");
+        super::super::escape_analysis(&mut program);
+        let mut v = CountF64Boxes::default();
+        for f in functions {
+            program.functions.get_mut(&f).unwrap().body.walk(&mut v);
+        }
+        v.num_boxes
+    }
+
+    /// The arithmetic of the loop keeps its floats unboxed, in locals, so the only f64 that the
+    /// program boxes is its result, whether `kernel` returns it as an `any` or the call passes it
+    /// to `log_any`. If this grows, a change to type inference or lowering boxes intermediates.
+    #[test]
+    fn numeric_kernel_boxes_only_its_result() {
+        let js_code = r#"
+            function kernel(n) {
+                var a = 1.5;
+                var b = 2.5;
+                var c = 0.5;
+                var d = 4.5;
+                var x = 0.5;
+                for (var i = 0; i < n; i = i + 1) {
+                    x = x + a * b + c * d;
+                }
+                return x;
+            }
+            log_any(kernel(10));
+        "#;
+        assert!(count_f64_boxes(js_code) <= 1);
     }
 }
@@ -3,27 +3,41 @@
 //! Important information about how strings are laid out:
 //! - A static string is an I32 pointer to memory
 //! - the memory is:
-//!   - first, 4 bytes: a little-endian encoding of the u32 string length
-//!   - the string, until the end of the length
+//!   - first, the tag of a string
+//!   - 4 bytes: a little-endian encoding of the u32 size of the string in bytes
+//!   - 4 bytes: a little-endian encoding of the u32 length of the string in UTF-16 code units
+//!   - the string, until the end of the size
 //!   - aligned to 4 byte increments
 
 use super::syntax::*;
 use super::walk::*;
+use abi::TypeTag;
 use std::collections::HashMap;
 
-/// String is specified as TypeTag 1 in the runtime to make it consistent
-/// across compiles
-/// [marked, String = 1, pad, pad]
-const STRING_TAG: [u8; 4] = [0, 1, 0, 0];
+/// The tag of a string in the runtime: [marked, String, pad, pad]
+const STRING_TAG: [u8; 4] = [0, TypeTag::String as u8, 0, 0];
 
+/// Interns the string literals of the program, and produces the symbol table, which maps each
+/// string to its offset in the data segment. The program keeps the table too, so that the passes
+/// that follow can refer to interned strings (see [Program::intern_string]).
 pub fn intern(program: &mut Program) -> HashMap<String, u32> {
-    let mut vis = InternVisitor::default();
+    let mut vis = InternVisitor {
+        data: std::mem::take(&mut program.data),
+        already_interned: std::mem::take(&mut program.interned),
+    };
     program.walk(&mut vis);
     program.data = vis.data;
-    return vis.already_interned;
+    program.interned = vis.already_interned;
+    return program.interned.clone();
+}
+
+impl Program {
+    /// The offset of `s` in the data segment, which interns it if it is not interned yet.
+    pub fn intern_string(&mut self, s: &str) -> u32 {
+        intern_string(&mut self.data, &mut self.interned, s)
+    }
 }
 
-#[derive(Default)]
 struct InternVisitor {
     data: Vec<u8>,
     /// Helps avoid interning the same static string multiple times.
@@ -31,57 +45,70 @@ struct InternVisitor {
 }
 
 impl Visitor for InternVisitor {
-    fn exit_atom(&mut self, atom: &mut Atom, _loc: &mut Loc) {
+    fn exit_atom(&mut self, atom: &mut Atom, _loc: &Loc) {
         match atom {
             Atom::Lit(old_lit @ Lit::String(_), _)
-            | Atom::AnyLength(_, old_lit @ Lit::String(_), _) => self.intern_string(old_lit),
+            | Atom::AnyLength(_, old_lit @ Lit::String(_), _) => self.intern_lit(old_lit),
             _ => (),
         }
     }
-    fn exit_expr(&mut self, expr: &mut Expr, _loc: &mut Loc) {
+    fn exit_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
         match expr {
-            Expr::AnyMethodCall(_, old_lit @ Lit::String(_), ..) => self.intern_string(old_lit),
+            Expr::AnyMethodCall(_, old_lit @ Lit::String(_), ..) => self.intern_lit(old_lit),
+            Expr::ObjectLiteral(fields, _) | Expr::ScratchObject(fields, _) => {
+                for field in fields {
+                    self.intern_lit(field);
+                }
+            }
             _ => (),
         }
     }
 }
 
 impl InternVisitor {
-    fn intern_string(&mut self, old_lit: &mut Lit) {
+    fn intern_lit(&mut self, old_lit: &mut Lit) {
         let lit = std::mem::replace(old_lit, Lit::I32(0));
         if let Lit::String(s) = lit {
-            if let Some(offset) = self.already_interned.get(&s) {
-                // We have seen this string before, so no need to
-                // reallocate.
-                *old_lit = Lit::Interned(s, *offset);
-                return;
-            }
-            let pos = self.data.len() as u32;
-            // Cache the offset, so that the interned string can be
-            // reused.
-            self.already_interned.insert(s.clone(), pos);
-            let mut bytes = s.clone().into_bytes();
+            let pos = intern_string(&mut self.data, &mut self.already_interned, &s);
             *old_lit = Lit::Interned(s, pos);
-            let length = bytes.len() as u32;
-            let length_bytes: [u8; 4] = unsafe { std::mem::transmute(length.to_le()) };
-            self.data.extend_from_slice(&STRING_TAG);
-            self.data.extend_from_slice(&length_bytes);
-            self.data.append(&mut bytes);
-            // tag(4), length(4)
-            let in_memory_length = length + 8;
-            // now we want to preserve alignment
-            // 0 -> 3 -> 0; 1 -> 0 -> 3; 2 -> 1 -> 2; 3 -> 2 -> 1
-            // +3 not -1 because 0 -> -1 % 4 = -1 -> 4, should be 0
-            let needed = 3 - ((in_memory_length + 3) % 4);
-            for _ in 0..needed {
-                self.data.push(b'\0');
-            }
         } else {
             unreachable!()
         }
     }
 }
 
+fn intern_string(data: &mut Vec<u8>, already_interned: &mut HashMap<String, u32>, s: &str) -> u32 {
+    if let Some(offset) = already_interned.get(s) {
+        // We have seen this string before, so no need to
+        // reallocate.
+        return *offset;
+    }
+    // The passes that follow intern may have written unaligned data.
+    while data.len() % 4 != 0 {
+        data.push(b'\0');
+    }
+    let pos = data.len() as u32;
+    // Cache the offset, so that the interned string can be
+    // reused.
+    already_interned.insert(s.to_string(), pos);
+    let size = s.len() as u32;
+    let length = s.encode_utf16().count() as u32;
+    data.extend_from_slice(&STRING_TAG);
+    data.extend_from_slice(&size.to_le_bytes());
+    data.extend_from_slice(&length.to_le_bytes());
+    data.extend_from_slice(s.as_bytes());
+    // tag(4), size(4), length(4)
+    let in_memory_length = size + 12;
+    // now we want to preserve alignment
+    // 0 -> 3 -> 0; 1 -> 0 -> 3; 2 -> 1 -> 2; 3 -> 2 -> 1
+    // +3 not -1 because 0 -> -1 % 4 = -1 -> 4, should be 0
+    let needed = 3 - ((in_memory_length + 3) % 4);
+    for _ in 0..needed {
+        data.push(b'\0');
+    }
+    pos
+}
+
 #[cfg(test)]
 mod test {
     use super::super::constructors::*;
@@ -118,12 +145,12 @@ mod test {
                         ),
                         s.clone(),
                     ),
-                    // 4(tag) + 4(len) + 6 -> 14 ->(align) -> 16
+                    // 4(tag) + 4(size) + 4(len) + 6 -> 18 ->(align) -> 20
                     Stmt::Var(
                         VarStmt::new(
                             id_("b"),
                             atom_(
-                                Atom::Lit(Lit::Interned("b".into(), 16), s.clone()),
+                                Atom::Lit(Lit::Interned("b".into(), 20), s.clone()),
                                 s.clone(),
                             ),
                         ),
@@ -139,13 +166,42 @@ mod test {
             },
             params: vec![],
             span: Default::default(),
+            no_gc: false,
         };
         let mut expected = program1_(indexed_func);
-        expected.data = b"\0\x01\0\0\x06\0\0\0012301\0\0\0\x01\0\0\x03\0\0\0012\0".to_vec();
+        expected.data =
+            b"\0\x01\0\0\x06\0\0\0\x06\0\0\0012301\0\0\0\x01\0\0\x03\0\0\0\x03\0\0\0012\0".to_vec();
         assert_eq!(
             program, expected,
             "got: {}\nexpected: {}",
             program, expected
         );
     }
+
+    #[test]
+    fn symbol_table() {
+        let mut program = parse(
+            "inline",
+            r#"
+            function main() : i32 {
+                var a = "012301";
+                var b = "012";
+                var c = "012301";
+                return 0;
+            }
+            "#,
+        );
+        let interned = intern(&mut program);
+        assert_eq!(interned.len(), 2);
+        assert_eq!(program.interned, interned);
+        // 4(tag) + 4(size) + 4(len) + 6 -> 18 ->(align) -> 20
+        let offsets = (interned["012301"], interned["012"]);
+        assert!(offsets == (0, 20) || offsets == (16, 0), "{:?}", offsets);
+        // A later pass reuses the interned strings, and interns new ones after them.
+        assert_eq!(program.intern_string("012"), interned["012"]);
+        program.data.push(1);
+        assert_eq!(program.intern_string("x"), 40);
+        assert_eq!(program.interned["x"], 40);
+        assert_eq!(program.data.len(), 56);
+    }
 }
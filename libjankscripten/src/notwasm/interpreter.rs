@@ -0,0 +1,1116 @@
+//! A reference interpreter for NotWasm.
+//!
+//! The interpreter runs a NotWasm program directly on its AST, so we can test the semantics of
+//! NotWasm without a WebAssembly engine, and compare the behavior of a program with the behavior
+//! of the WebAssembly that `translation` produces for it.
+//!
+//! Instead of the linear memory of the runtime system, the interpreter has a heap of Rust values
+//! that is never collected. Values of type `any` carry their own tags, so `any(..)` is the
+//! identity, and `.. as T` checks the tag. The interpreter implements the runtime functions that
+//! the standard library and generated code use most often, and reports an error when a program
+//! uses any other runtime function.
+//!
+//! Output that the runtime system would log (e.g., with `console_log` and `dbg_log`) is returned
+//! as a string, with one line per message, in the format that the runtime system uses.
+use super::syntax::*;
+use crate::rts_function::{RTSFunction, RTSFunctionImpl};
+use crate::shared::nondet;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InterpError {
+    #[error("unbound identifier `{0}`")]
+    Unbound(Id),
+    #[error("unbound label `{0:?}`")]
+    UnboundLabel(Label),
+    #[error("the runtime function `{0}` is not supported by the interpreter")]
+    UnsupportedPrimitive(String),
+    #[error("the interpreter does not support {0}")]
+    Unsupported(String),
+    #[error("expected {0}, but received `{1}` at `{2}`")]
+    Tag(String, String, Pos),
+    #[error("trap at `{0}`: {1}")]
+    Trap(Pos, String),
+    #[error("the program does not have a `main` function")]
+    NoMain,
+}
+
+pub type InterpResult<T> = Result<T, InterpError>;
+
+/// An index into the heap of the interpreter.
+pub type Addr = usize;
+
+/// The function that a function pointer or a closure refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    /// A function in the program.
+    Function(Id),
+    /// A function in the runtime system.
+    Prim(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    I32(i32),
+    F64(f64),
+    Bool(bool),
+    String(Rc<str>),
+    Array(Addr),
+    HT(Addr),
+    DynObject(Addr),
+    Ref(Addr),
+    Env(Addr),
+    Fn(Callee),
+    Closure(Addr, Callee),
+    Undefined,
+    Null,
+}
+
+#[derive(Debug)]
+enum HeapValue {
+    Array(Vec<Value>),
+    HT(HashMap<String, Value>),
+    /// The fields of an object, in the order in which they were added. The prototype of the
+    /// object is its `__proto__` field, as in the runtime system.
+    DynObject(Vec<(String, Value)>),
+    Ref(Value),
+    Env(Vec<Value>),
+}
+
+/// How a statement completes.
+enum Flow {
+    Normal,
+    Break(Label),
+    Return(Value),
+}
+
+/// The local variables of a function invocation.
+struct Frame {
+    locals: HashMap<Id, Value>,
+    /// The environment of the closure that is running, which is always its first argument.
+    env: Option<Addr>,
+}
+
+pub struct Interpreter<'a> {
+    program: &'a Program,
+    globals: HashMap<Id, Value>,
+    heap: Vec<HeapValue>,
+    output: Vec<String>,
+    /// The state of `Math.random`, which is deterministic (see [crate::shared::nondet]).
+    random_state: u64,
+    /// The objects that are sealed, and the objects that are frozen, which are sealed too.
+    sealed: HashSet<Addr>,
+    frozen: HashSet<Addr>,
+}
+
+/// Runs the program: initializes its global variables, calls `jnks_init` (if the program has
+/// the standard library), and then calls `main`. Returns what the program logged.
+pub fn interpret(program: &Program) -> InterpResult<String> {
+    let mut interp = Interpreter::new(program);
+    interp.run()?;
+    Ok(interp.output())
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Interpreter {
+            program,
+            globals: HashMap::new(),
+            heap: Vec::new(),
+            output: Vec::new(),
+            random_state: nondet::SEED,
+            sealed: HashSet::new(),
+            frozen: HashSet::new(),
+        }
+    }
+
+    pub fn run(&mut self) -> InterpResult<()> {
+        let mut frame = Frame {
+            locals: HashMap::new(),
+            env: None,
+        };
+        let program = self.program;
+        for (id, global) in &program.globals {
+            // Lazy globals are zero in WebAssembly. They must be written to before they are read.
+            let v = match &global.atom {
+                Some(a) => self.eval_atom(&mut frame, a)?,
+                None => Value::Undefined,
+            };
+            self.globals.insert(id.clone(), v);
+        }
+        let jnks_init = Id::from("jnks_init");
+        if program.functions.contains_key(&jnks_init) {
+            self.call_function(&jnks_init, vec![])?;
+        }
+        let main = Id::from("main");
+        if !program.functions.contains_key(&main) {
+            return Err(InterpError::NoMain);
+        }
+        self.call_function(&main, vec![])?;
+        Ok(())
+    }
+
+    /// The messages that the program has logged so far, one per line.
+    pub fn output(&self) -> String {
+        self.output.join("\n")
+    }
+
+    pub fn call_function(&mut self, f: &Id, args: Vec<Value>) -> InterpResult<Value> {
+        let program = self.program;
+        let func = program
+            .functions
+            .get(f)
+            .ok_or_else(|| InterpError::Unbound(f.clone()))?;
+        let env = match args.first() {
+            Some(Value::Env(addr)) => Some(*addr),
+            _ => None,
+        };
+        let mut frame = Frame {
+            locals: func.params.iter().cloned().zip(args).collect(),
+            env,
+        };
+        match self.exec(&mut frame, &func.body)? {
+            Flow::Return(v) => Ok(v),
+            Flow::Normal => Ok(Value::Undefined),
+            Flow::Break(l) => Err(InterpError::UnboundLabel(l)),
+        }
+    }
+
+    fn call(&mut self, callee: &Callee, args: Vec<Value>, p: &Pos) -> InterpResult<Value> {
+        match callee {
+            Callee::Function(f) => self.call_function(f, args),
+            Callee::Prim(name) => self.prim(name, args, p),
+        }
+    }
+
+    fn alloc(&mut self, v: HeapValue) -> Addr {
+        self.heap.push(v);
+        self.heap.len() - 1
+    }
+
+    fn lookup(&self, frame: &Frame, id: &Id) -> InterpResult<Value> {
+        if let Some(v) = frame.locals.get(id) {
+            return Ok(v.clone());
+        }
+        if let Some(v) = self.globals.get(id) {
+            return Ok(v.clone());
+        }
+        if self.program.functions.contains_key(id) {
+            return Ok(Value::Fn(Callee::Function(id.clone())));
+        }
+        Err(InterpError::Unbound(id.clone()))
+    }
+
+    fn lookup_all(&self, frame: &Frame, ids: &[Id]) -> InterpResult<Vec<Value>> {
+        ids.iter().map(|id| self.lookup(frame, id)).collect()
+    }
+
+    fn assign(&mut self, frame: &mut Frame, id: &Id, v: Value) -> InterpResult<()> {
+        if let Some(x) = frame.locals.get_mut(id) {
+            *x = v;
+        } else if let Some(x) = self.globals.get_mut(id) {
+            *x = v;
+        } else {
+            return Err(InterpError::Unbound(id.clone()));
+        }
+        Ok(())
+    }
+
+    fn exec(&mut self, frame: &mut Frame, stmt: &Stmt) -> InterpResult<Flow> {
+        match stmt {
+            Stmt::Empty => Ok(Flow::Normal),
+            Stmt::Var(var_stmt, _) => {
+                let v = self.eval_expr(frame, &var_stmt.named)?;
+                frame.locals.insert(var_stmt.id.clone(), v);
+                Ok(Flow::Normal)
+            }
+            Stmt::Expression(e, _) => {
+                self.eval_expr(frame, e)?;
+                Ok(Flow::Normal)
+            }
+            Stmt::Assign(id, e, _) => {
+                let v = self.eval_expr(frame, e)?;
+                self.assign(frame, id, v)?;
+                Ok(Flow::Normal)
+            }
+            Stmt::Store(id, e, p) => {
+                let v = self.eval_expr(frame, e)?;
+                match self.lookup(frame, id)? {
+                    Value::Ref(addr) => {
+                        self.heap[addr] = HeapValue::Ref(v);
+                        Ok(Flow::Normal)
+                    }
+                    r => Err(tag_error("a reference", &r, p)),
+                }
+            }
+            Stmt::If(a, s1, s2, p) => match self.eval_atom(frame, a)? {
+                Value::Bool(true) => self.exec(frame, s1),
+                Value::Bool(false) => self.exec(frame, s2),
+                v => Err(tag_error("a boolean", &v, p)),
+            },
+            Stmt::Loop(body, _) => loop {
+                match self.exec(frame, body)? {
+                    Flow::Normal => (),
+                    flow => return Ok(flow),
+                }
+            },
+            Stmt::Label(l, body, _) => match self.exec(frame, body)? {
+                Flow::Break(l2) if &l2 == l => Ok(Flow::Normal),
+                flow => Ok(flow),
+            },
+            Stmt::Break(l, _) => Ok(Flow::Break(l.clone())),
+            Stmt::Return(a, _) => Ok(Flow::Return(self.eval_atom(frame, a)?)),
+            Stmt::Block(stmts, _) => {
+                for s in stmts {
+                    match self.exec(frame, s)? {
+                        Flow::Normal => (),
+                        flow => return Ok(flow),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::Trap => Err(InterpError::Trap(Pos::UNKNOWN, "trap".to_string())),
+            Stmt::Goto(..) => Err(InterpError::Unsupported("goto".to_string())),
+        }
+    }
+
+    fn eval_expr(&mut self, frame: &mut Frame, expr: &Expr) -> InterpResult<Value> {
+        match expr {
+            Expr::Atom(a, _) => self.eval_atom(frame, a),
+            Expr::ArraySet(arr, index, v, p) => {
+                let arr = self.eval_atom(frame, arr)?;
+                let index = self.eval_atom(frame, index)?;
+                let v = self.eval_atom(frame, v)?;
+                self.prim("array_set", vec![arr, index, v], p)
+            }
+            Expr::ObjectSet(obj, field, v, p) => {
+                let obj = self.eval_atom(frame, obj)?;
+                let field = self.eval_atom(frame, field)?;
+                let v = self.eval_atom(frame, v)?;
+                self.prim("object_set", vec![obj, field, v, Value::Undefined], p)
+            }
+            // The fields of an object literal are empty until `ObjectSet` writes them, so it
+            // does not matter that the object starts without them. The interpreter does not have
+            // a scratch region, so scratch objects are ordinary objects.
+            Expr::ObjectEmpty(p) | Expr::ObjectLiteral(_, p) | Expr::ScratchObject(_, p) => {
+                // Like the translation, inherit from `Object.prototype` when the standard library
+                // is present.
+                let jnks_new_object = Id::from("jnks_new_object");
+                if self.program.functions.contains_key(&jnks_new_object) {
+                    self.call_function(&jnks_new_object, vec![])
+                } else {
+                    self.prim("object_empty", vec![], p)
+                }
+            }
+            // The fast path of `Speculate` computes the same result as the call.
+            Expr::PrimCall(rts_func, args, p) | Expr::Speculate(_, rts_func, args, p) => {
+                let args = self.lookup_all(frame, args)?;
+                match rts_func {
+                    RTSFunction::Method(name, _) => self.method(name, args, p),
+                    _ => match rts_func.name() {
+                        RTSFunctionImpl::Rust(name) => self.prim(&name, args, p),
+                        RTSFunctionImpl::NotWasm(name) => self.call_function(&Id::from(name), args),
+                    },
+                }
+            }
+            Expr::Call(f, args, p) => {
+                let args = self.lookup_all(frame, args)?;
+                match self.lookup(frame, f)? {
+                    Value::Fn(callee) => self.call(&callee, args, p),
+                    v => Err(tag_error("a function", &v, p)),
+                }
+            }
+            Expr::ClosureCall(f, args, p) => {
+                let f = self.lookup(frame, f)?;
+                let args = self.lookup_all(frame, args)?;
+                self.call_closure(f, args, p)
+            }
+            Expr::AnyMethodCall(x, method, args, _, p) => {
+                let receiver = self.lookup(frame, x)?;
+                let args = self.lookup_all(frame, args)?;
+                let method = lit_string(method);
+                match receiver {
+                    Value::DynObject(_) => {
+                        let f = self.object_get(&receiver, &method, p)?;
+                        self.call_closure(f, args, p)
+                    }
+                    _ => self.method(&method, args, p),
+                }
+            }
+            Expr::NewRef(a, _, _) => {
+                let v = self.eval_atom(frame, a)?;
+                Ok(Value::Ref(self.alloc(HeapValue::Ref(v))))
+            }
+            Expr::Closure(f, env, p) => {
+                let env = env
+                    .iter()
+                    .map(|(a, _)| self.eval_atom(frame, a))
+                    .collect::<InterpResult<Vec<_>>>()?;
+                // The function may be a local variable, e.g., `var f = rt(console_log);`.
+                let callee = match self.lookup(frame, f)? {
+                    Value::Fn(callee) => callee,
+                    v => return Err(tag_error("a function", &v, p)),
+                };
+                let addr = self.alloc(HeapValue::Env(env));
+                Ok(Value::Closure(addr, callee))
+            }
+        }
+    }
+
+    fn call_closure(&mut self, f: Value, args: Vec<Value>, p: &Pos) -> InterpResult<Value> {
+        match f {
+            Value::Closure(env, callee) => {
+                let args = std::iter::once(Value::Env(env)).chain(args).collect();
+                self.call(&callee, args, p)
+            }
+            v => Err(tag_error("a closure", &v, p)),
+        }
+    }
+
+    fn eval_atom(&mut self, frame: &mut Frame, atom: &Atom) -> InterpResult<Value> {
+        match atom {
+            Atom::Lit(lit, _) => Ok(match lit {
+                Lit::Bool(b) => Value::Bool(*b),
+                Lit::I32(n) => Value::I32(*n),
+                Lit::F64(x) => Value::F64(*x),
+                Lit::String(s) | Lit::Interned(s, _) => Value::String(s.as_str().into()),
+                Lit::Undefined => Value::Undefined,
+                Lit::Null => Value::Null,
+            }),
+            Atom::PrimApp(f, args, p) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.eval_atom(frame, a))
+                    .collect::<InterpResult<Vec<_>>>()?;
+                self.prim(f.name(), args, p)
+            }
+            Atom::ToAny(to_any, _) => self.eval_atom(frame, &to_any.atom),
+            Atom::FromAny(a, ty, p) => {
+                let v = self.eval_atom(frame, a)?;
+                from_any(v, ty, p)
+            }
+            Atom::FloatToInt(a, p) => match self.eval_atom(frame, a)? {
+                Value::F64(x) => Ok(Value::I32(x as i32)),
+                v => Err(tag_error("an f64", &v, p)),
+            },
+            Atom::IntToFloat(a, p) => match self.eval_atom(frame, a)? {
+                Value::I32(n) => Ok(Value::F64(n as f64)),
+                v => Err(tag_error("an i32", &v, p)),
+            },
+            Atom::ObjectGet(obj, field, p) => {
+                let obj = self.eval_atom(frame, obj)?;
+                let field = self.eval_atom(frame, field)?;
+                match field {
+                    Value::String(field) => self.object_get(&obj, &field, p),
+                    v => Err(tag_error("a string", &v, p)),
+                }
+            }
+            Atom::AnyLength(x, _, p) => match self.lookup(frame, x)? {
+                Value::String(s) => Ok(Value::I32(s.encode_utf16().count() as i32)),
+                Value::Array(addr) => match &self.heap[addr] {
+                    HeapValue::Array(elts) => Ok(Value::I32(elts.len() as i32)),
+                    _ => unreachable!("array address does not point to an array"),
+                },
+                obj @ Value::DynObject(_) => self.object_get(&obj, "length", p),
+                v => Err(tag_error("a value with a length", &v, p)),
+            },
+            Atom::Id(x, _) => self.lookup(frame, x),
+            Atom::GetPrimFunc(f, _) => Ok(Value::Fn(Callee::Prim(f.name().to_string()))),
+            Atom::Unary(op, a, p) => {
+                let v = self.eval_atom(frame, a)?;
+                unary(op, v, p)
+            }
+            Atom::Binary(op, a, b, p) => {
+                let v1 = self.eval_atom(frame, a)?;
+                let v2 = self.eval_atom(frame, b)?;
+                binary(op, v1, v2, p)
+            }
+            Atom::Deref(a, _, p) => match self.eval_atom(frame, a)? {
+                Value::Ref(addr) => match &self.heap[addr] {
+                    HeapValue::Ref(v) => Ok(v.clone()),
+                    _ => unreachable!("reference address does not point to a reference"),
+                },
+                v => Err(tag_error("a reference", &v, p)),
+            },
+            Atom::EnvGet(index, ty, p) => match frame.env {
+                Some(addr) => match &self.heap[addr] {
+                    HeapValue::Env(vs) => {
+                        let v = vs.get(*index as usize).cloned().ok_or_else(|| {
+                            InterpError::Trap(p.clone(), "environment index".to_string())
+                        })?;
+                        from_any(v, ty, p)
+                    }
+                    _ => unreachable!("environment address does not point to an environment"),
+                },
+                None => Err(InterpError::Trap(
+                    p.clone(),
+                    "no environment in a function that is not a closure".to_string(),
+                )),
+            },
+        }
+    }
+
+    fn object_get(&self, obj: &Value, field: &str, p: &Pos) -> InterpResult<Value> {
+        let mut obj = obj.clone();
+        loop {
+            let fields = match obj {
+                Value::DynObject(addr) => match &self.heap[addr] {
+                    HeapValue::DynObject(fields) => fields,
+                    _ => unreachable!("object address does not point to an object"),
+                },
+                v => return Err(tag_error("an object", &v, p)),
+            };
+            if let Some((_, v)) = fields.iter().find(|(x, _)| x == field) {
+                return Ok(v.clone());
+            }
+            match fields.iter().find(|(x, _)| x == "__proto__") {
+                Some((_, proto @ Value::DynObject(_))) => obj = proto.clone(),
+                _ => return Ok(Value::Undefined),
+            }
+        }
+    }
+
+    fn object_set(&mut self, obj: &Value, field: &str, v: Value, p: &Pos) -> InterpResult<()> {
+        match obj {
+            Value::DynObject(addr) => match &mut self.heap[*addr] {
+                HeapValue::DynObject(fields) => {
+                    // As in the runtime system, writes that would change a sealed or frozen
+                    // object do nothing.
+                    if self.frozen.contains(addr) {
+                        return Ok(());
+                    }
+                    match fields.iter_mut().find(|(x, _)| x == field) {
+                        Some((_, x)) => *x = v,
+                        None if self.sealed.contains(addr) => (),
+                        None => fields.push((field.to_string(), v)),
+                    }
+                    Ok(())
+                }
+                _ => unreachable!("object address does not point to an object"),
+            },
+            v => Err(tag_error("an object", v, p)),
+        }
+    }
+
+    fn array(&mut self, v: &Value, p: &Pos) -> InterpResult<&mut Vec<Value>> {
+        match v {
+            Value::Array(addr) => match &mut self.heap[*addr] {
+                HeapValue::Array(elts) => Ok(elts),
+                _ => unreachable!("array address does not point to an array"),
+            },
+            v => Err(tag_error("an array", v, p)),
+        }
+    }
+
+    fn ht(&mut self, v: &Value, p: &Pos) -> InterpResult<&mut HashMap<String, Value>> {
+        match v {
+            Value::HT(addr) => match &mut self.heap[*addr] {
+                HeapValue::HT(ht) => Ok(ht),
+                _ => unreachable!("hash table address does not point to a hash table"),
+            },
+            v => Err(tag_error("a hash table", v, p)),
+        }
+    }
+
+    /// Calls a type-specialized method (see `shared::methods`). The first argument is the
+    /// receiver.
+    fn method(&mut self, name: &str, args: Vec<Value>, p: &Pos) -> InterpResult<Value> {
+        match (name, args.as_slice()) {
+            ("push", [arr @ Value::Array(_), v]) => {
+                let elts = self.array(arr, p)?;
+                elts.push(v.clone());
+                Ok(Value::I32(elts.len() as i32))
+            }
+            ("slice", [Value::String(s), start, end]) => {
+                // Strings are indexed by UTF-16 code units, like in the runtime system.
+                let units: Vec<u16> = s.encode_utf16().collect();
+                let (start, end) = (to_i32(start, p)? as usize, to_i32(end, p)? as usize);
+                let end = end.min(units.len());
+                let start = start.min(end);
+                Ok(Value::String(
+                    String::from_utf16_lossy(&units[start..end]).into(),
+                ))
+            }
+            ("charAt", [Value::String(s), index]) => {
+                let index = to_i32(index, p)?;
+                let unit = s.encode_utf16().nth(index as usize);
+                Ok(Value::String(match unit {
+                    Some(unit) if index >= 0 => String::from_utf16_lossy(&[unit]).into(),
+                    _ => "".into(),
+                }))
+            }
+            ("charCodeAt", [Value::String(s), index]) => {
+                let index = to_i32(index, p)?;
+                match s.encode_utf16().nth(index as usize) {
+                    Some(unit) if index >= 0 => Ok(Value::I32(unit as i32)),
+                    _ => Ok(Value::F64(f64::NAN)),
+                }
+            }
+            ("codePointAt", [Value::String(s), index]) => {
+                let index = to_i32(index, p)?;
+                if index < 0 {
+                    return Ok(Value::Undefined);
+                }
+                let units = s.encode_utf16().skip(index as usize).take(2);
+                Ok(match std::char::decode_utf16(units).next() {
+                    Some(Ok(c)) => Value::I32(c as i32),
+                    Some(Err(e)) => Value::I32(e.unpaired_surrogate() as i32),
+                    None => Value::Undefined,
+                })
+            }
+            ("slice", [arr @ Value::Array(_), start, end]) => {
+                let (start, end) = (to_i32(start, p)? as usize, to_i32(end, p)? as usize);
+                let elts = self.array(arr, p)?;
+                let end = end.min(elts.len());
+                let start = start.min(end);
+                let slice = elts[start..end].to_vec();
+                Ok(Value::Array(self.alloc(HeapValue::Array(slice))))
+            }
+            ("concat", [Value::String(s1), Value::String(s2)]) => {
+                Ok(Value::String(format!("{}{}", s1, s2).into()))
+            }
+            ("concat", [arr1 @ Value::Array(_), arr2 @ Value::Array(_)]) => {
+                let mut elts = self.array(arr1, p)?.clone();
+                elts.extend(self.array(arr2, p)?.iter().cloned());
+                Ok(Value::Array(self.alloc(HeapValue::Array(elts))))
+            }
+            _ => {
+                // Method names are prefixed with the type of their receiver, e.g., `array_push`.
+                match name.find('_') {
+                    Some(i) => self.method(&name[i + 1..], args, p),
+                    None => Err(InterpError::UnsupportedPrimitive(name.to_string())),
+                }
+            }
+        }
+    }
+
+    /// Calls a function in the runtime system.
+    fn prim(&mut self, name: &str, args: Vec<Value>, p: &Pos) -> InterpResult<Value> {
+        use Value::*;
+        match (name, args.as_slice()) {
+            // The interpreter does not need to help the garbage collector.
+            ("init", _)
+            | ("gc_enter_fn", _)
+            | ("gc_exit_fn", _)
+            | ("set_in_current_shadow_frame_slot", _)
+            | ("set_any_in_current_shadow_frame_slot", _)
+            | ("set_in_globals_frame", _)
+            | ("set_any_in_globals_frame", _)
+            | ("run_gc", _)
+            | ("heap_dump", _)
+            | ("mem_info", _)
+            | ("heap_used", _)
+            | ("class_stats", _)
+            | ("gc_stats", _)
+            | ("track_class_sites", _) => Ok(Undefined),
+            ("get_undefined", []) => Ok(Undefined),
+            ("get_null", []) => Ok(Null),
+            ("ht_new", []) => Ok(HT(self.alloc(HeapValue::HT(HashMap::new())))),
+            ("ht_get", [ht, String(k)]) => {
+                Ok(self.ht(ht, p)?.get(&**k).cloned().unwrap_or(Undefined))
+            }
+            ("ht_set", [ht, String(k), v]) => {
+                self.ht(ht, p)?.insert(k.to_string(), v.clone());
+                Ok(v.clone())
+            }
+            ("array_new", []) => Ok(Array(self.alloc(HeapValue::Array(vec![])))),
+            ("array_index", [arr, index]) => {
+                let index = to_i32(index, p)?;
+                let elts = self.array(arr, p)?;
+                Ok(elts.get(index as usize).cloned().unwrap_or(Undefined))
+            }
+            ("array_set", [arr, index, v]) => {
+                let index = to_i32(index, p)? as usize;
+                let elts = self.array(arr, p)?;
+                if index >= elts.len() {
+                    elts.resize(index + 1, Undefined);
+                }
+                elts[index] = v.clone();
+                Ok(v.clone())
+            }
+            ("array_length", [arr]) => Ok(I32(self.array(arr, p)?.len() as i32)),
+            ("array_push", [arr, v]) => self.method("push", vec![arr.clone(), v.clone()], p),
+            ("string_length", [String(s)]) => Ok(I32(s.encode_utf16().count() as i32)),
+            ("object_empty", []) => Ok(DynObject(self.alloc(HeapValue::DynObject(vec![])))),
+            ("object_create", [_, _, proto]) => {
+                let fields = match proto {
+                    Null => vec![],
+                    DynObject(_) => vec![("__proto__".to_string(), proto.clone())],
+                    v => return Err(tag_error("an object or null", v, p)),
+                };
+                Ok(DynObject(self.alloc(HeapValue::DynObject(fields))))
+            }
+            ("object_get", [obj, String(field), _]) => self.object_get(obj, field, p),
+            ("global_get", [obj, String(name)]) => {
+                let exists = match obj {
+                    DynObject(addr) => match &self.heap[*addr] {
+                        HeapValue::DynObject(fields) => fields.iter().any(|(x, _)| x == &**name),
+                        _ => unreachable!("object address does not point to an object"),
+                    },
+                    v => return Err(tag_error("an object", v, p)),
+                };
+                if !exists {
+                    let msg = format!("ReferenceError: {} is not defined", name);
+                    return Err(InterpError::Trap(p.clone(), msg));
+                }
+                self.object_get(obj, name, p)
+            }
+            ("object_set", [obj, String(field), v, _]) => {
+                self.object_set(obj, field, v.clone(), p)?;
+                Ok(v.clone())
+            }
+            ("object_seal", [v]) | ("object_freeze", [v]) => {
+                if let DynObject(addr) = v {
+                    self.sealed.insert(*addr);
+                    if name == "object_freeze" {
+                        self.frozen.insert(*addr);
+                    }
+                }
+                Ok(v.clone())
+            }
+            ("object_is_sealed", [v]) | ("object_is_frozen", [v]) => Ok(Bool(match v {
+                DynObject(addr) if name == "object_is_sealed" => self.sealed.contains(addr),
+                DynObject(addr) => self.frozen.contains(addr),
+                Array(_) | HT(_) | Closure(..) | Fn(_) => false,
+                _ => true,
+            })),
+            ("ref_new_non_ptr_32", [v])
+            | ("ref_new_f64", [v])
+            | ("ref_new_any", [v])
+            | ("ref_new_ptr", [v]) => Ok(Ref(self.alloc(HeapValue::Ref(v.clone())))),
+            ("any_to_f64", [v]) => Ok(F64(to_number(v, p)?)),
+            ("to_boolean", [_, _, v]) => Ok(Bool(to_boolean(v))),
+            // The interpreter has no scratch area, so a scratch box is an ordinary f64.
+            ("f64_to_any", [v]) | ("f64_to_any_scratch", [v]) => Ok(F64(to_f64(v, p)?)),
+            (name, [v]) if name.starts_with("any_from_") || name.starts_with("any_to_") => {
+                Ok(v.clone())
+            }
+            ("console_log", [_, _, v]) => {
+                let s = self.display(v, p)?;
+                self.output.push(s);
+                Ok(Undefined)
+            }
+            ("log_any_raw", [_, v]) => {
+                let s = self.debug(v, p)?;
+                self.output.push(s);
+                Ok(I32(42))
+            }
+            ("dbg_log", [v]) => {
+                let s = self.debug(v, p)?;
+                self.output.push(s);
+                Ok(v.clone())
+            }
+            ("math_random", [_, _]) => Ok(F64(nondet::splitmix64(&mut self.random_state))),
+            // The interpreter does not run the tasks of setTimeout, so the clock never advances.
+            ("date_now", [_, _]) => Ok(F64(nondet::CLOCK)),
+            ("math_sqrt", [_, _, v]) => Ok(F64(to_f64(v, p)?.sqrt())),
+            ("math_sin", [_, _, v]) => Ok(F64(to_f64(v, p)?.sin())),
+            ("math_abs", [_, _, v]) => Ok(F64(to_f64(v, p)?.abs())),
+            ("math_min", [_, _, v1, v2]) => arith(
+                v1,
+                v2,
+                p,
+                |m, n| Some(m.min(n)),
+                |x, y| if x < y { x } else { y },
+            ),
+            ("math_max", [_, _, v1, v2]) => arith(
+                v1,
+                v2,
+                p,
+                |m, n| Some(m.max(n)),
+                |x, y| if x > y { x } else { y },
+            ),
+            ("string_from_char_code", [_, _, v]) => {
+                let unit = to_f64(v, p)?;
+                let unit = if unit.is_finite() {
+                    unit.trunc().rem_euclid(65536.0) as u16
+                } else {
+                    0
+                };
+                Ok(String(
+                    std::string::String::from_utf16_lossy(&[unit]).into(),
+                ))
+            }
+            ("parse_int", [_, _, I32(n), _]) => Ok(I32(*n)),
+            ("parse_int", [_, _, F64(x), _]) => Ok(I32(*x as i32)),
+            ("any_is_object", [v]) => Ok(Bool(matches!(v, DynObject(_)))),
+            ("janky_primitive_plus", [v1, v2]) => {
+                if is_ptr(v1) || is_ptr(v2) {
+                    let s = format!("{}{}", self.display(v1, p)?, self.display(v2, p)?);
+                    Ok(String(s.into()))
+                } else {
+                    arith(v1, v2, p, i32::checked_add, |x, y| x + y)
+                }
+            }
+            ("janky_minus", [v1, v2]) => arith(v1, v2, p, i32::checked_sub, |x, y| x - y),
+            ("janky_times", [v1, v2]) => arith(v1, v2, p, i32::checked_mul, |x, y| x * y),
+            ("janky_over", [v1, v2]) => Ok(F64(to_number(v1, p)? / to_number(v2, p)?)),
+            ("janky_mod", [v1, v2]) => arith(v1, v2, p, i32::checked_rem, |x, y| x % y),
+            ("janky_mod_f64", [v1, v2]) => Ok(F64(to_f64(v1, p)? % to_f64(v2, p)?)),
+            ("janky_neg", [I32(n)]) => Ok(I32(n.wrapping_neg())),
+            ("janky_neg", [v]) => Ok(F64(-to_f64(v, p)?)),
+            ("janky_not", [v]) => Ok(I32(!to_i32(v, p)?)),
+            ("any_strict_eq", [v1, v2]) => Ok(Bool(strict_equal(v1, v2))),
+            ("janky_strict_not_equal", [v1, v2]) => Ok(Bool(!strict_equal(v1, v2))),
+            ("string_eq", [String(s1), String(s2)]) => Ok(Bool(s1 == s2)),
+            ("string_ne", [String(s1), String(s2)]) => Ok(Bool(s1 != s2)),
+            ("janky_equal", [v1, v2]) => Ok(Bool(abstract_equal(v1, v2))),
+            ("janky_not_equal", [v1, v2]) => Ok(Bool(!abstract_equal(v1, v2))),
+            ("janky_typeof", [v]) => Ok(String(type_of(v).into())),
+            ("janky_void", [_]) => Ok(Undefined),
+            (name, _) => Err(InterpError::UnsupportedPrimitive(name.to_string())),
+        }
+    }
+
+    /// Formats a value like the `Display` trait of the runtime system.
+    fn display(&self, v: &Value, p: &Pos) -> InterpResult<String> {
+        Ok(match v {
+            Value::I32(n) => n.to_string(),
+            Value::F64(x) => x.to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::String(s) => s.to_string(),
+            Value::Array(addr) => match &self.heap[*addr] {
+                HeapValue::Array(elts) => {
+                    let mut s = "[".to_string();
+                    for elt in elts {
+                        s.push_str(&self.display(elt, p)?);
+                        s.push_str(", ");
+                    }
+                    s.push(']');
+                    s
+                }
+                _ => unreachable!("array address does not point to an array"),
+            },
+            Value::Undefined => "undefined".to_string(),
+            Value::Null => "null".to_string(),
+            v => return Err(InterpError::Unsupported(format!("printing `{:?}`", v))),
+        })
+    }
+
+    /// Formats a value like the `Debug` trait of the runtime system.
+    fn debug(&self, v: &Value, p: &Pos) -> InterpResult<String> {
+        Ok(match v {
+            Value::I32(n) => format!("I32({})", n),
+            Value::F64(x) => format!("F64({})", x),
+            Value::Bool(b) => format!("Bool({})", b),
+            Value::String(_) => format!("String({})", self.display(v, p)?),
+            Value::Array(_) => format!("Array({})", self.display(v, p)?),
+            Value::Undefined | Value::Null => self.display(v, p)?,
+            v => return Err(InterpError::Unsupported(format!("printing `{:?}`", v))),
+        })
+    }
+}
+
+fn tag_error(expected: &str, v: &Value, p: &Pos) -> InterpError {
+    InterpError::Tag(expected.to_string(), format!("{:?}", v), p.clone())
+}
+
+fn lit_string(lit: &Lit) -> String {
+    match lit {
+        Lit::String(s) | Lit::Interned(s, _) => s.clone(),
+        _ => panic!("method name is not a string: {:?}", lit),
+    }
+}
+
+fn is_ptr(v: &Value) -> bool {
+    matches!(
+        v,
+        Value::String(_)
+            | Value::Array(_)
+            | Value::HT(_)
+            | Value::DynObject(_)
+            | Value::Ref(_)
+            | Value::Env(_)
+    )
+}
+
+fn to_i32(v: &Value, p: &Pos) -> InterpResult<i32> {
+    match v {
+        Value::I32(n) => Ok(*n),
+        Value::F64(x) => Ok(*x as i32),
+        v => Err(tag_error("a number", v, p)),
+    }
+}
+
+fn to_f64(v: &Value, p: &Pos) -> InterpResult<f64> {
+    match v {
+        Value::I32(n) => Ok(*n as f64),
+        Value::F64(x) => Ok(*x),
+        v => Err(tag_error("a number", v, p)),
+    }
+}
+
+/// JavaScript's `ToBoolean`.
+fn to_boolean(v: &Value) -> bool {
+    match v {
+        Value::I32(n) => *n != 0,
+        Value::F64(x) => *x != 0.0 && !x.is_nan(),
+        Value::Bool(b) => *b,
+        Value::String(s) => !s.is_empty(),
+        Value::Undefined | Value::Null => false,
+        _ => true,
+    }
+}
+
+/// JavaScript's `ToNumber` on the primitive values that may appear in an `any`.
+fn to_number(v: &Value, p: &Pos) -> InterpResult<f64> {
+    match v {
+        Value::Bool(b) => Ok(*b as i32 as f64),
+        Value::Null => Ok(0.0),
+        Value::Undefined => Ok(f64::NAN),
+        Value::String(s) => Ok(crate::jankyscript::interpreter::string_to_number(s)),
+        v => to_f64(v, p),
+    }
+}
+
+/// Arithmetic on `any`-typed values: if both are `i32`s (and the result fits), the result is an
+/// `i32`. Otherwise, both are converted with `ToNumber`.
+fn arith(
+    v1: &Value,
+    v2: &Value,
+    p: &Pos,
+    on_i32: impl Fn(i32, i32) -> Option<i32>,
+    on_f64: impl Fn(f64, f64) -> f64,
+) -> InterpResult<Value> {
+    if let (Value::I32(m), Value::I32(n)) = (v1, v2) {
+        if let Some(r) = on_i32(*m, *n) {
+            return Ok(Value::I32(r));
+        }
+    }
+    Ok(Value::F64(on_f64(to_number(v1, p)?, to_number(v2, p)?)))
+}
+
+fn strict_equal(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::I32(_), Value::F64(_)) | (Value::F64(_), Value::I32(_)) => {
+            let p = Pos::UNKNOWN;
+            to_f64(v1, &p).ok() == to_f64(v2, &p).ok()
+        }
+        _ => v1 == v2,
+    }
+}
+
+fn abstract_equal(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::Null, Value::Undefined) | (Value::Undefined, Value::Null) => true,
+        _ => strict_equal(v1, v2),
+    }
+}
+
+fn type_of(v: &Value) -> &'static str {
+    match v {
+        Value::I32(_) | Value::F64(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::String(_) => "string",
+        Value::Undefined => "undefined",
+        Value::Closure(..) | Value::Fn(_) => "function",
+        _ => "object",
+    }
+}
+
+/// Checks the tag of an `any`-typed value, like `.. as T`.
+fn from_any(v: Value, ty: &Type, p: &Pos) -> InterpResult<Value> {
+    let ok = match (ty, &v) {
+        (Type::Any, _) => true,
+        (Type::I32, Value::I32(_)) => true,
+        // Like `any_to_f64` and `any_to_bool` in the runtime system.
+        (Type::F64, v) => return Ok(Value::F64(to_number(v, p)?)),
+        (Type::Bool, v) => return Ok(Value::Bool(to_boolean(v))),
+        (Type::String, Value::String(_)) => true,
+        (Type::Array, Value::Array(_)) => true,
+        (Type::HT, Value::HT(_)) => true,
+        (Type::DynObject, Value::DynObject(_)) => true,
+        (Type::Fn(_), Value::Fn(_)) => true,
+        (Type::Closure(_), Value::Closure(..)) => true,
+        (Type::Ref(_), Value::Ref(_)) => true,
+        (Type::Env, Value::Env(_)) => true,
+        (Type::Ptr, v) => is_ptr(v),
+        _ => false,
+    };
+    if ok {
+        Ok(v)
+    } else {
+        Err(tag_error(&format!("a value of type {}", ty), &v, p))
+    }
+}
+
+fn unary(op: &UnaryOp, v: Value, p: &Pos) -> InterpResult<Value> {
+    match (op, v) {
+        (UnaryOp::Sqrt, Value::F64(x)) => Ok(Value::F64(x.sqrt())),
+        (UnaryOp::F64Neg, Value::F64(x)) => Ok(Value::F64(-x)),
+        (UnaryOp::I32Neg, Value::I32(n)) => Ok(Value::I32(n.wrapping_neg())),
+        (UnaryOp::I32Not, Value::I32(n)) => Ok(Value::I32(!n)),
+        (UnaryOp::Eqz, Value::I32(n)) => Ok(Value::Bool(n == 0)),
+        (UnaryOp::Eqz, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (UnaryOp::Nop, v) => Ok(v),
+        (op, v) => Err(tag_error(&format!("an operand of {:?}", op), &v, p)),
+    }
+}
+
+fn binary(op: &BinaryOp, v1: Value, v2: Value, p: &Pos) -> InterpResult<Value> {
+    use BinaryOp::*;
+    use Value::{Bool, F64, I32};
+    let trap = |msg: &str| InterpError::Trap(p.clone(), msg.to_string());
+    Ok(match (op, &v1, &v2) {
+        (PtrEq, _, _) => Bool(v1 == v2),
+        (I32Eq, I32(m), I32(n)) => Bool(m == n),
+        (I32Eq, Bool(m), Bool(n)) => Bool(m == n),
+        (I32Ne, I32(m), I32(n)) => Bool(m != n),
+        (I32Ne, Bool(m), Bool(n)) => Bool(m != n),
+        (I32Add, I32(m), I32(n)) => I32(m.wrapping_add(*n)),
+        (I32Sub, I32(m), I32(n)) => I32(m.wrapping_sub(*n)),
+        (I32Mul, I32(m), I32(n)) => I32(m.wrapping_mul(*n)),
+        (I32Div, I32(m), I32(n)) => I32(m.checked_div(*n).ok_or_else(|| trap("division"))?),
+        (I32Rem, I32(m), I32(n)) => I32(m.checked_rem(*n).ok_or_else(|| trap("remainder"))?),
+        (I32GT, I32(m), I32(n)) => Bool(m > n),
+        (I32LT, I32(m), I32(n)) => Bool(m < n),
+        (I32Ge, I32(m), I32(n)) => Bool(m >= n),
+        (I32Le, I32(m), I32(n)) => Bool(m <= n),
+        (I32And, I32(m), I32(n)) => I32(m & n),
+        (I32And, Bool(m), Bool(n)) => Bool(*m && *n),
+        (I32Or, I32(m), I32(n)) => I32(m | n),
+        (I32Or, Bool(m), Bool(n)) => Bool(*m || *n),
+        (I32Xor, I32(m), I32(n)) => I32(m ^ n),
+        // WebAssembly uses the shift amount modulo 32.
+        (I32Shl, I32(m), I32(n)) => I32(m.wrapping_shl(*n as u32)),
+        (I32Shr, I32(m), I32(n)) => I32(m.wrapping_shr(*n as u32)),
+        (I32ShrU, I32(m), I32(n)) => I32((*m as u32).wrapping_shr(*n as u32) as i32),
+        (F64Add, F64(x), F64(y)) => F64(x + y),
+        (F64Sub, F64(x), F64(y)) => F64(x - y),
+        (F64Mul, F64(x), F64(y)) => F64(x * y),
+        (F64Div, F64(x), F64(y)) => F64(x / y),
+        (F64Eq, F64(x), F64(y)) => Bool(x == y),
+        (F64Ne, F64(x), F64(y)) => Bool(x != y),
+        (F64LT, F64(x), F64(y)) => Bool(x < y),
+        (F64Le, F64(x), F64(y)) => Bool(x <= y),
+        (F64GT, F64(x), F64(y)) => Bool(x > y),
+        (F64Ge, F64(x), F64(y)) => Bool(x >= y),
+        _ => {
+            return Err(InterpError::Tag(
+                format!("operands of {:?}", op),
+                format!("{:?}, {:?}", v1, v2),
+                p.clone(),
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    fn run(program: &str) -> InterpResult<String> {
+        interpret(&parse("<test>", program))
+    }
+
+    /// Runs a NotWasm program from the integration tests, with the standard library, and
+    /// compares its output to the expected output of the integration test.
+    fn run_integration_test(name: &str) {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap();
+        let test_data = root.join("integration_tests").join("test_data");
+        let read = |path: std::path::PathBuf| {
+            std::fs::read_to_string(&path)
+                .unwrap_or_else(|err| panic!("reading {}: {}", path.display(), err))
+        };
+        let mut program = parse(name, read(test_data.join(format!("{}.notwasm", name))));
+        let stdlib = root.join("stdlib.notwasm");
+        let includes = super::super::stdlib::read_includes(&stdlib).expect("reading includes");
+        super::super::stdlib::link(&mut program, &read(stdlib), &includes).expect("linking");
+        super::super::type_checking::type_check(&mut program).expect("type checking");
+        let expected = read(test_data.join(format!("{}.txt", name)));
+        match interpret(&program) {
+            Ok(output) => assert_eq!(output.trim(), expected.trim()),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn fib_loop() {
+        let output = run(r#"
+            function fib(n: i32): i32 {
+                var a = 0;
+                var b = 1;
+                var i = 0;
+                loop {
+                    if (i == n) {
+                        return a;
+                    } else { }
+                    var t = a + b;
+                    a = b;
+                    b = t;
+                    i = i + 1;
+                }
+                return a;
+            }
+            function main() {
+                var r = fib(17);
+                var _ = @dbg_log(any(r));
+            }
+        "#);
+        assert_eq!(output, Ok("I32(1597)".to_string()));
+    }
+
+    #[test]
+    fn closures_and_refs() {
+        let output = run(r#"
+            function add(_: env, y: i32): i32 {
+                var x = env.0: i32;
+                return x + y;
+            }
+            function main() {
+                var x = 10;
+                var r = newRef(x, i32);
+                *r = 32;
+                var y = *r: i32;
+                var f = clos(add, y: i32);
+                var z = f!(x);
+                var _ = @dbg_log(any(z));
+            }
+        "#);
+        assert_eq!(output, Ok("I32(42)".to_string()));
+    }
+
+    #[test]
+    fn objects_and_labels() {
+        let output = run(r#"
+            function main() {
+                var o = @object_empty();
+                o.x = any(1.5f);
+                var x = o.x;
+                var a = @array_new();
+                var _ = @array_push(a, x);
+                var _ = @array_push(a, any("s"));
+                lbl: {
+                    break lbl;
+                    var _ = @dbg_log(any(0));
+                }
+                var _ = @dbg_log(any(a));
+            }
+        "#);
+        assert_eq!(output, Ok("Array([1.5, s, ])".to_string()));
+    }
+
+    #[test]
+    fn bad_downcast_is_an_error() {
+        let output = run(r#"
+            function main() {
+                var x = any(1);
+                var y = x as str;
+            }
+        "#);
+        assert!(matches!(output, Err(InterpError::Tag(..))));
+    }
+
+    #[test]
+    fn integration_tests_with_stdlib() {
+        run_integration_test("basic_break");
+        run_integration_test("basic_ref_mutation");
+        run_integration_test("closures2");
+        run_integration_test("any_method");
+    }
+}
@@ -15,7 +15,7 @@ struct LabelAppsVisitor {
     n: i32,
 }
 impl Visitor for LabelAppsVisitor {
-    fn exit_stmt(&mut self, stmt: &mut Stmt) {
+    fn exit_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
         use Stmt::*;
         match stmt {
             &mut Assign(_, Expr::Call(..), ref s) => {
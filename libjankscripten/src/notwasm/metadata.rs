@@ -0,0 +1,179 @@
+//! Compiler metadata, which the compiler embeds in every module that it produces.
+//!
+//! The metadata is in a custom section named `jankscripten`, so WebAssembly engines ignore it. It
+//! records the version of the compiler, the options that produced the module, and a digest of the
+//! types of the functions and globals of the NotWasm program, which are the types that inference
+//! assigned. Caching layers can compare metadata to decide if a module is stale, and
+//! [read_metadata] tells which flags produced a given binary.
+//!
+//! The section holds one line per field, which holds the name of the field, a space, and its
+//! value.
+use super::binary::{sections, Malformed};
+use super::syntax::Program;
+use crate::opts::Opts;
+use parity_wasm::elements::{CustomSection, Section};
+use thiserror::Error;
+
+/// The name of the custom section that holds the metadata.
+pub const SECTION_NAME: &str = "jankscripten";
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    #[error("{0}")]
+    Wasm(#[from] Malformed),
+    #[error("the module has no `{}` custom section", SECTION_NAME)]
+    Missing,
+    #[error("malformed metadata: `{0}`")]
+    Malformed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// The version of the compiler.
+    pub version: String,
+    /// The options that produced the module, e.g., `disable_gc=false profile_gen=false
+    /// trace_coercions=false profile_use=none max_call_depth=none checked=false`. A profile
+    /// appears as its digest.
+    pub opts: String,
+    /// A digest of the types of the functions and globals of the program.
+    pub types: u64,
+}
+
+impl Metadata {
+    /// The metadata of `program`, which must be type-checked.
+    pub fn new(opts: &Opts, program: &Program) -> Metadata {
+        let profile_use = match &opts.profile_use {
+            None => "none".to_string(),
+            Some(profile) => format!("{:016x}", digest(&profile.to_string())),
+        };
+        let max_call_depth = match opts.max_call_depth {
+            None => "none".to_string(),
+            Some(depth) => depth.to_string(),
+        };
+        let opts = format!(
+            "disable_gc={} profile_gen={} trace_coercions={} profile_use={} max_call_depth={} \
+             checked={}",
+            opts.disable_gc,
+            opts.profile_gen,
+            opts.trace_coercions,
+            profile_use,
+            max_call_depth,
+            opts.checked
+        );
+        // Sort the names, so that the digest does not depend on the order of the hash tables.
+        let mut types: Vec<_> = program
+            .functions
+            .iter()
+            .map(|(f, func)| format!("function {}: {:?}", f, func.fn_type))
+            .chain(
+                program
+                    .globals
+                    .iter()
+                    .map(|(x, global)| format!("global {}: {:?}", x, global.ty)),
+            )
+            .collect();
+        types.sort();
+        Metadata {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            opts,
+            types: digest(&types.join("\n")),
+        }
+    }
+
+    /// The custom section that holds the metadata.
+    pub fn section(&self) -> Section {
+        let payload = format!(
+            "version {}\nopts {}\ntypes {:016x}\n",
+            self.version, self.opts, self.types
+        );
+        Section::Custom(CustomSection::new(
+            SECTION_NAME.to_string(),
+            payload.into_bytes(),
+        ))
+    }
+
+    fn parse(payload: &[u8]) -> Result<Metadata, MetadataError> {
+        let text = std::str::from_utf8(payload)
+            .map_err(|_| MetadataError::Malformed(String::from_utf8_lossy(payload).into()))?;
+        let (mut version, mut opts, mut types) = (None, None, None);
+        for line in text.lines() {
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some("version"), Some(value)) => version = Some(value.to_string()),
+                (Some("opts"), Some(value)) => opts = Some(value.to_string()),
+                (Some("types"), Some(value)) => types = u64::from_str_radix(value, 16).ok(),
+                _ => return Err(MetadataError::Malformed(line.to_string())),
+            }
+        }
+        match (version, opts, types) {
+            (Some(version), Some(opts), Some(types)) => Ok(Metadata {
+                version,
+                opts,
+                types,
+            }),
+            _ => Err(MetadataError::Malformed(text.to_string())),
+        }
+    }
+}
+
+/// Extracts the metadata from a module that the compiler produced.
+pub fn read_metadata(wasm: &[u8]) -> Result<Metadata, MetadataError> {
+    for section in sections(wasm)? {
+        if let Some((name, payload)) = section.custom()? {
+            if name == SECTION_NAME {
+                return Metadata::parse(payload);
+            }
+        }
+    }
+    Err(MetadataError::Missing)
+}
+
+/// The 64-bit FNV-1a hash of `text`, which, unlike the hashers of the standard library, is the
+/// same in every version of Rust.
+fn digest(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+    use parity_wasm::builder::module;
+
+    fn metadata(notwasm_code: &str) -> Metadata {
+        Metadata::new(&Opts::new(), &parse("<test>", notwasm_code))
+    }
+
+    #[test]
+    fn read_embedded_metadata() {
+        let metadata = metadata("function main(): i32 { return 0; }");
+        let mut module = module().build();
+        module.sections_mut().push(metadata.section());
+        let wasm = parity_wasm::serialize(module).unwrap();
+        assert_eq!(read_metadata(&wasm).unwrap(), metadata);
+        assert_eq!(
+            metadata.opts,
+            "disable_gc=false profile_gen=false trace_coercions=false profile_use=none \
+             max_call_depth=none checked=false"
+        );
+    }
+
+    #[test]
+    fn module_without_metadata() {
+        let wasm = parity_wasm::serialize(module().build()).unwrap();
+        assert!(matches!(read_metadata(&wasm), Err(MetadataError::Missing)));
+    }
+
+    #[test]
+    fn types_change_digest() {
+        let m1 = metadata("var x: i32 = 0; function main(): i32 { return x; }");
+        let m2 = metadata("var x: f64 = 0f; function main(): i32 { return 0; }");
+        assert_ne!(m1.types, m2.types);
+        assert_eq!(
+            m1,
+            metadata("var x: i32 = 1; function main(): i32 { return 0; }")
+        );
+    }
+}
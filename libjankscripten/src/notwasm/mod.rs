@@ -1,10 +1,16 @@
 //! NotWasm: It is not WebAssembly, but quite close to it.
+mod binary;
 mod compile;
+mod const_globals;
 #[allow(dead_code)]
 mod constructors;
+pub mod dsl;
+mod escape;
 mod intern;
+mod no_gc;
 pub mod parser;
 mod rt_bindings;
+mod speculate;
 mod translation;
 mod walk;
 
@@ -15,13 +21,24 @@ mod label_apps;
 lrlex::lrlex_mod!("notwasm/lexer.l"); // produces lexer_l.rs
 lrpar::lrpar_mod!("notwasm/parser.y"); // produces parser_y.rs
 
+use const_globals::propagate_constant_globals;
+use escape::escape_analysis;
 use intern::intern;
+use no_gc::{check_no_gc, elide_shadow_frames};
+use speculate::speculate;
 use translation::translate;
 
+pub mod call_graph;
+pub mod interpreter;
+pub mod metadata;
 pub mod pretty;
+pub mod size;
+pub mod stdlib;
 pub mod syntax;
 pub mod type_checking;
 
-pub use compile::compile;
+pub use compile::{compile, compile_to_wat};
 pub use from_jankyscript::*;
+pub use metadata::read_metadata;
 pub use parser::parse;
+pub use rt_bindings::runtime_features;
@@ -0,0 +1,186 @@
+//! Functions without shadow stack frames.
+//!
+//! The translator gives a `nogc` function no shadow stack frame, thus the garbage collector would
+//! not see the pointers in its local variables. That is only safe if nothing collects garbage
+//! while the function runs. Atoms do not allocate (including primitive applications, which only
+//! call primitives that do not allocate), except for `any(x)` where `x` is an `f64`, which boxes
+//! `x` on the heap. A function may evaluate any other atom, but the only expression that it may
+//! evaluate is a call to a function that satisfies the same conditions.
+//! Any other expression may allocate, even if it only allocates a little (e.g., an object in the
+//! scratch region, which spills over to the heap when the region is full).
+//!
+//! [check_no_gc] rejects the `nogc` functions that do not satisfy these conditions, and
+//! [elide_shadow_frames] makes every function that satisfies them `nogc`, so that small helpers
+//! do not pay for a frame on every call.
+//!
+//! The analysis is conservative, but it accepts recursive functions: it starts from the functions
+//! whose bodies allocate nothing, and removes the functions that call any other function, until
+//! nothing changes.
+use super::syntax::*;
+use super::walk::*;
+use std::collections::{HashMap, HashSet};
+
+pub fn check_no_gc(program: &mut Program) -> Result<(), String> {
+    let safe = safe_functions(program);
+    let mut rejected: Vec<String> = program
+        .functions
+        .iter()
+        .filter(|(f, func)| func.no_gc && !safe.contains(*f))
+        .map(|(f, _)| f.to_string())
+        .collect();
+    if rejected.is_empty() {
+        return Ok(());
+    }
+    rejected.sort();
+    Err(format!(
+        "{} may trigger garbage collection, so it cannot be nogc",
+        rejected.join(", ")
+    ))
+}
+
+pub fn elide_shadow_frames(program: &mut Program) {
+    let safe = safe_functions(program);
+    for (f, func) in program.functions.iter_mut() {
+        if safe.contains(f) {
+            func.no_gc = true;
+        }
+    }
+}
+
+/// The functions that cannot trigger garbage collection.
+fn safe_functions(program: &mut Program) -> HashSet<Id> {
+    // The functions that each function calls, or `None` if its body may allocate.
+    let mut callees: HashMap<Id, Option<HashSet<Id>>> = HashMap::new();
+    for (name, func) in program.functions.iter_mut() {
+        let mut v = Callees(Some(HashSet::new()));
+        func.body.walk(&mut v);
+        callees.insert(name.clone(), v.0);
+    }
+    let mut safe: HashSet<Id> = callees
+        .iter()
+        .filter(|(_, fs)| fs.is_some())
+        .map(|(f, _)| f.clone())
+        .collect();
+    loop {
+        let unsafe_fns: Vec<Id> = safe
+            .iter()
+            .filter(|f| match &callees[*f] {
+                Some(fs) => fs.iter().any(|g| !safe.contains(g)),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        if unsafe_fns.is_empty() {
+            return safe;
+        }
+        for f in unsafe_fns {
+            safe.remove(&f);
+        }
+    }
+}
+
+struct Callees(Option<HashSet<Id>>);
+
+impl Visitor for Callees {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Atom(..) => (),
+            Expr::Call(f, ..) => {
+                if let Some(fs) = &mut self.0 {
+                    fs.insert(f.clone());
+                }
+            }
+            _ => self.0 = None,
+        }
+    }
+
+    fn enter_atom(&mut self, atom: &mut Atom, _loc: &Loc) {
+        if let Atom::ToAny(to_any, _) = atom {
+            // Before type-checking, we do not know the type.
+            if let Some(Type::F64) | None = &to_any.ty {
+                self.0 = None;
+            }
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    fn check(program: &str) -> Result<(), String> {
+        check_no_gc(&mut parse("<test>", program))
+    }
+
+    #[test]
+    fn numeric_kernels() {
+        let program = r#"
+            nogc function fib(n: i32): i32 {
+                if (n < 2) {
+                    return n;
+                } else { }
+                var a = fib(n - 1);
+                var b = fib(n - 2);
+                return a + b;
+            }
+
+            nogc function main(): i32 {
+                var x = fib(10);
+                return x;
+            }
+        "#;
+        assert_eq!(check(program), Ok(()));
+    }
+
+    #[test]
+    fn allocating_callee() {
+        let program = r#"
+            function alloc(): i32 {
+                var o = {};
+                return 0;
+            }
+
+            nogc function main(): i32 {
+                var x = alloc();
+                return x;
+            }
+        "#;
+        assert_eq!(
+            check(program),
+            Err("main may trigger garbage collection, so it cannot be nogc".to_string())
+        );
+    }
+
+    #[test]
+    fn elide_frames_of_leaf_functions() {
+        let mut program = parse(
+            "<test>",
+            r#"
+            function square(x: f64): f64 {
+                var y = x *. x;
+                return y;
+            }
+
+            function alloc(): i32 {
+                var o = {};
+                var x = square(2.0f);
+                return 0;
+            }
+
+            function box(x: f64): any {
+                var y = any(x);
+                return y;
+            }
+        "#,
+        );
+        elide_shadow_frames(&mut program);
+        assert!(program.functions[&Id::from("square")].no_gc);
+        assert!(!program.functions[&Id::from("alloc")].no_gc);
+        assert!(!program.functions[&Id::from("box")].no_gc);
+    }
+}
@@ -79,25 +79,42 @@ AtomSeq -> Vec<Atom> :
   | AtomSeq ',' Atom { $1.push($3); $1 }
   ;
 
+FieldName -> Atom :
+    'ID'               { Atom::Lit(Lit::String($lexer.span_str($1.unwrap().span()).to_string()), pos($1)) }
+  | 'STRING_LIT'       { Atom::Lit(Lit::String(unescape_string($lexer.span_str($1.unwrap().span())).unwrap()), pos($1)) }
+  | '[' AtomAdd ']'    { $2 }
+  // `sqrt` is a keyword, but the standard library sets `Math.sqrt`.
+  | 'sqrt'             { Atom::Lit(Lit::String("sqrt".to_string()), pos($1)) }
+  ;
+
 Atom -> Atom :
-    '$' Id '(' AtomSeq ')' { Atom::PrimApp($2, $4, pos($1)) }
-  | 'any' '(' Atom ')'     { Atom::ToAny(ToAny::new($3), pos($1)) }
-  | 'env' '.' U32 ':' Type { Atom::EnvGet($3, $5, pos($4)) }
-  | 'rt' '(' Id ')'        { Atom::GetPrimFunc($3, pos($1)) }
-  | Lit                    { Atom::Lit($1.0, $1.1) }
-  // TODO(arjun): The concrete syntax is more restrictive than the abstract syntax.
-  | IdAtom '.' IdString    { Atom::ObjectGet(Box::new($1), Box::new(Atom::Lit(Lit::String($3), pos($2))), pos($2)) }
-  | IdAtom                 { $1 }
+    '$' Id '(' AtomSeq ')'            { Atom::PrimApp($2, $4, pos($1)) }
+  | 'any' '(' AtomAdd ')'             { Atom::ToAny(ToAny::new($3), pos($1)) }
+  | 'env' '.' U32 ':' Type            { Atom::EnvGet($3, $5, pos($4)) }
+  | 'rt' '(' Id ')'                   { Atom::GetPrimFunc($3, pos($1)) }
+  | Lit                               { Atom::Lit($1.0, $1.1) }
+  | Atom '.' FieldName                { Atom::ObjectGet(Box::new($1), Box::new($3), pos($2)) }
+  | Atom '.' 'length'                 { length_($1, pos($2)) }
+  | IdAtom                            { $1 }
   // TODO(arjun): The type annotation on deref should not be necessary in the
   // concrete syntax. The type-checker can figure it out.
-  | '*' Atom ':' Type      { Atom::Deref(Box::new($2), $4, pos($1)) }
-  | Atom 'as' Type         { Atom::FromAny(Box::new($1), $3, pos($2)) }
+  | '*' Atom ':' Type                 { Atom::Deref(Box::new($2), $4, pos($1)) }
+  | Atom 'as' Type                    { Atom::FromAny(Box::new($1), $3, pos($2)) }
+  | 'float_to_int' '(' AtomAdd ')'    { Atom::FloatToInt(Box::new($3), pos($1)) }
+  | 'int_to_float' '(' AtomAdd ')'    { Atom::IntToFloat(Box::new($3), pos($1)) }
+  | 'sqrt' '(' AtomAdd ')'            { Atom::Unary(UnaryOp::Sqrt, Box::new($3), pos($1)) }
+  | '-' '(' AtomAdd ')'               { Atom::Unary(UnaryOp::I32Neg, Box::new($3), pos($1)) }
+  | '-.' '(' AtomAdd ')'              { Atom::Unary(UnaryOp::F64Neg, Box::new($3), pos($1)) }
+  | '~' '(' AtomAdd ')'               { Atom::Unary(UnaryOp::I32Not, Box::new($3), pos($1)) }
+  | '!' '(' AtomAdd ')'               { Atom::Unary(UnaryOp::Eqz, Box::new($3), pos($1)) }
+  | '(' AtomAdd ')'                   { $2 }
   ;
 
 AtomMul -> Atom :
     Atom              { $1 }
   | Atom '*' AtomMul  { binary_(BinaryOp::I32Mul, $1, $3, pos($2)) }
-  | Atom '/' AtomMul  { binary_(BinaryOp::I32Div, $1, $3, pos($2)) }  
+  | Atom '/' AtomMul  { binary_(BinaryOp::I32Div, $1, $3, pos($2)) }
+  | Atom '%' AtomMul  { binary_(BinaryOp::I32Rem, $1, $3, pos($2)) }
   | Atom '*.' AtomMul { binary_(BinaryOp::F64Mul, $1, $3, pos($2)) }
   | Atom '/.' AtomMul { binary_(BinaryOp::F64Div, $1, $3, pos($2)) }
   ;
@@ -112,16 +129,37 @@ AtomAdd -> Atom :
   | AtomMul '-' AtomAdd   { binary_(BinaryOp::I32Sub, $1, $3, pos($2)) }
   | AtomMul '===' AtomAdd { binary_(BinaryOp::PtrEq, $1, $3, pos($2)) }
   | AtomMul '==' AtomAdd  { binary_(BinaryOp::I32Eq, $1, $3, pos($2)) }
+  | AtomMul '!=' AtomAdd  { binary_(BinaryOp::I32Ne, $1, $3, pos($2)) }
+  | AtomMul '&' AtomAdd   { binary_(BinaryOp::I32And, $1, $3, pos($2)) }
+  | AtomMul '|' AtomAdd   { binary_(BinaryOp::I32Or, $1, $3, pos($2)) }
+  | AtomMul '^' AtomAdd   { binary_(BinaryOp::I32Xor, $1, $3, pos($2)) }
+  | AtomMul '<<' AtomAdd  { binary_(BinaryOp::I32Shl, $1, $3, pos($2)) }
+  | AtomMul '>>' AtomAdd  { binary_(BinaryOp::I32Shr, $1, $3, pos($2)) }
+  | AtomMul '>>>' AtomAdd { binary_(BinaryOp::I32ShrU, $1, $3, pos($2)) }
   | AtomMul '+.' AtomAdd  { binary_(BinaryOp::F64Add, $1, $3, pos($2)) }
   | AtomMul '-.' AtomAdd  { binary_(BinaryOp::F64Sub, $1, $3, pos($2)) }
-  | AtomMul '>.' AtomAdd   { binary_(BinaryOp::F64GT, $1, $3, pos($2)) }
+  | AtomMul '==.' AtomAdd { binary_(BinaryOp::F64Eq, $1, $3, pos($2)) }
+  | AtomMul '!=.' AtomAdd { binary_(BinaryOp::F64Ne, $1, $3, pos($2)) }
+  | AtomMul '>.' AtomAdd  { binary_(BinaryOp::F64GT, $1, $3, pos($2)) }
+  | AtomMul '<.' AtomAdd  { binary_(BinaryOp::F64LT, $1, $3, pos($2)) }
+  | AtomMul '>=.' AtomAdd { binary_(BinaryOp::F64Ge, $1, $3, pos($2)) }
+  | AtomMul '<=.' AtomAdd { binary_(BinaryOp::F64Le, $1, $3, pos($2)) }
+  ;
+
+ObjectField -> Lit :
+    'ID'         { Lit::String($lexer.span_str($1.unwrap().span()).to_string()) }
+  | 'STRING_LIT' { Lit::String(unescape_string($lexer.span_str($1.unwrap().span())).unwrap()) }
   ;
 
-// TODO(arjun): The concrete syntax is more restrictive than the abstract syntax.
-IdAtomTypeSeq -> Vec<(Atom, Type)> :
-                                      { Vec::new() }
-  | IdAtom ':' Type                   { vec![($1, $3)] }
-  | IdAtomTypeSeq ',' IdAtom ':' Type { $1.push(($3, $5)); $1 }
+ObjectFieldSeq -> Vec<Lit> :
+    ObjectField                    { vec![$1] }
+  | ObjectFieldSeq ',' ObjectField { $1.push($3); $1 }
+  ;
+
+AtomTypeSeq -> Vec<(Atom, Type)> :
+                                  { Vec::new() }
+  | Atom ':' Type                 { vec![($1, $3)] }
+  | AtomTypeSeq ',' Atom ':' Type { $1.push(($3, $5)); $1 }
   ;
 
 IdTypeSeq -> Vec<(Id, Type)> :
@@ -131,14 +169,21 @@ IdTypeSeq -> Vec<(Id, Type)> :
   ;
 
 Expr -> Expr :
-    '{' '}'                             { Expr::ObjectEmpty }
+    '{' '}'                             { Expr::ObjectEmpty(pos($1)) }
+  | '{' ObjectFieldSeq '}'              { Expr::ObjectLiteral($2, pos($1)) }
+  | 'scratch' '{' '}'                   { Expr::ScratchObject(Vec::new(), pos($1)) }
+  | 'scratch' '{' ObjectFieldSeq '}'    { Expr::ScratchObject($3, pos($1)) }
   | '!' Id '(' IdSeq ')'                { Expr::prim_call($2.into_name(), $4, pos($1)) } 
-  | 'clos' '(' Id ',' IdAtomTypeSeq ')' { Expr::Closure($3, $5, pos($1)) }
+  | 'speculate' '(' AtomAdd ')' '!' Id '(' IdSeq ')' { speculate_($3, $6.into_name(), $8, pos($1)) }
+  | 'clos' '(' Id ')'                   { Expr::Closure($3, Vec::new(), pos($1)) }
+  | 'clos' '(' Id ',' AtomTypeSeq ')'   { Expr::Closure($3, $5, pos($1)) }
   // TODO(arjun): We can infer the type annotation.
   | 'newRef' '(' Atom ',' Type ')'      { Expr::NewRef($3, $5, pos($1)) }
   | Id '!' '(' IdSeq ')'                { Expr::ClosureCall($1, $4, pos($2)) }
   | Id '(' IdSeq ')'                    { Expr::Call($1, $3, pos($2)) }
   | Id '?' '.' IdString '<' TypeSeq '>' '(' IdSeq ')' { Expr::AnyMethodCall($1, Lit::String($4), $9, $6, pos($3)) }
+  | Atom '.' FieldName '=' AtomAdd      { Expr::ObjectSet($1, $3, $5, pos($4)) }
+  | Atom '[' AtomAdd ']' '=' AtomAdd    { Expr::ArraySet($1, $3, $6, pos($5)) }
   | AtomAdd                             { let p = $1.pos().clone(); Expr::Atom($1, p) }
   ;
 
@@ -161,9 +206,6 @@ Stmt -> Stmt :
     { Stmt::Var(VarStmt { id: $2, named: $5, ty: $3 }, pos($1)) }
   | Id '=' Expr ';'                         { Stmt::Assign($1, $3, pos($2)) }
   | IdString ':' Block                      { label_($1, $3, pos($2)) }
-  // TODO(arjun): The concrete syntax is more restrictive than the abstract syntax. This should be in Expr anyway.
-  | IdAtom '.' IdString '=' AtomAdd ';'
-    { Stmt::Var(VarStmt::new(id_("_"), Expr::ObjectSet($1, str_($3, pos($2)), $5, pos($2))), pos($2)) }
   | 'if' '(' AtomAdd ')' Block 'else' Block { Stmt::If($3, Box::new($5), Box::new($7), pos($1)) }
   | 'loop' Block                            { Stmt::Loop(Box::new($2), pos($1)) }
   | 'return' AtomAdd ';'                    { Stmt::Return($2, pos($1)) }
@@ -171,11 +213,14 @@ Stmt -> Stmt :
   | 'while' '(' AtomAdd ')' Block           { while_($3, $5, pos($1)) }
   | '*' Id '=' Expr ';'                     { Stmt::Store($2, $4, pos($1)) }
   | Expr ';'                                { Stmt::Expression($1, pos($2)) }
+  | 'trap' ';'                              { Stmt::Trap }
+  | ';'                                     { Stmt::Empty }
   ; 
 
 Global -> (Id, Global) :
     'const' Id ':' Type '=' AtomAdd ';' { ($2, Global { is_mut: false, ty: $4, atom: Some($6) }) }
   | 'var' Id ':' Type '=' AtomAdd ';'   { ($2, Global { is_mut: true, ty: $4, atom: Some($6) }) }
+  | 'const' Id ':' Type ';'             { ($2, Global { is_mut: false, ty: $4, atom: None }) }
   | 'var' Id ':' Type ';'               { ($2, Global { is_mut: true, ty: $4, atom: None }) }
   ;
 
@@ -190,13 +235,18 @@ FunctionVec -> HashMap<Id, Function> :
   ;
 
 Function -> (Id, Function) :
+    'nogc' FunctionDef { let (name, mut f) = $2; f.no_gc = true; (name, f) }
+  | FunctionDef { $1 }
+  ;
+
+FunctionDef -> (Id, Function) :
     'function' Id '(' IdTypeSeq ')' Block 
     {
       let mut args = Vec::new();
       let mut params = Vec::new();
       for (p, a) in $4.into_iter() { args.push(a); params.push(p); }
       let fn_type = FnType { args, result: None };
-      ($2, Function { body: $6, fn_type, params, span: pos($1) })
+      ($2, Function { body: $6, fn_type, params, span: pos($1), no_gc: false })
     }
   | 'function' Id '(' IdTypeSeq ')' ':' Type Block 
     {
@@ -204,7 +254,7 @@ Function -> (Id, Function) :
       let mut params = Vec::new();
       for (p, a) in $4.into_iter() { args.push(a); params.push(p); }
       let fn_type = FnType { args, result: Some(Box::new($7)) };
-      ($2, Function { body: $8, fn_type, params, span: pos($1) })
+      ($2, Function { body: $8, fn_type, params, span: pos($1), no_gc: false })
     }
   ;
 
@@ -219,7 +269,7 @@ Imports -> HashMap<String, Type> :
 
 Program -> Program :
     Imports GlobalVec FunctionVec 
-    { Program { rts_fn_imports: $1, globals: $2, functions: $3, data: Vec::new() } }
+    { Program { rts_fn_imports: $1, globals: $2, functions: $3, data: Vec::new(), interned: HashMap::new() } }
   ;
 
 // An idiom that turns lexing errors into parsing errors. Any mismatched
@@ -236,9 +286,27 @@ use super::syntax::*;
 use super::constructors::*;
 use super::parser::pos;
 use super::super::pos::Pos;
+use crate::rts_function::RTSFunction;
 use crate::string_escaping::unescape_string;
 use std::cell::RefCell;
 
+/// `x.length` is the length of any value, but other atoms may only have a
+/// field called "length".
+fn length_(atom: Atom, p: Pos) -> Atom {
+    match atom {
+        Atom::Id(id, _) => Atom::AnyLength(id, Lit::String("length".to_string()), p),
+        atom => Atom::ObjectGet(Box::new(atom), Box::new(Atom::Lit(Lit::String("length".to_string()), p.clone())), p),
+    }
+}
+
+/// `speculate(x op y) !f(x, y)`, where the fast path `x op y` names the operator.
+fn speculate_(fast_path: Atom, name: String, args: Vec<Id>, p: Pos) -> Expr {
+    match fast_path {
+        Atom::Binary(op, ..) => Expr::Speculate(op, RTSFunction::Import(name), args, p),
+        _ => panic!("the fast path of speculate is not a binary operator"),
+    }
+}
+
 fn parse_uint(s: &str) -> u32 {
     match s.parse::<u32>() {
         Ok(val) => val,
@@ -1,8 +1,164 @@
+//! A pretty-printer for NotWasm that produces the concrete syntax that `parser.y` accepts. Thus,
+//! for any program `p`, printing `parse(print(p))` produces the same text as printing `p`.
+//!
+//! There are a few exceptions:
+//!
+//! - `Goto` and `Label::App` only exist in GotoWasm, so they have no concrete syntax.
+//! - `Lit::Undefined` prints as `undefined`, which parses as an identifier.
+//! - A primitive call prints the name of the function that implements it, which parses as an
+//!   imported primitive.
+//! - A variable whose name is a keyword (e.g., a JavaScript variable called `trap`) does not
+//!   re-parse.
 use super::syntax::*;
 
 use crate::pretty::Pretty;
+use crate::rts_function::RTSFunctionImpl;
+use crate::string_escaping::escape_string;
 use crate::{impl_Display_Pretty, prettyp};
 
+/// The keywords of `lexer.l`. A field with one of these names must be printed as a string.
+const KEYWORDS: &[&str] = &[
+    "any",
+    "as",
+    "Array",
+    "bogus",
+    "bool",
+    "break",
+    "clos",
+    "const",
+    "DynObject",
+    "else",
+    "env",
+    "false",
+    "float_to_int",
+    "function",
+    "f64",
+    "i32",
+    "HT",
+    "import",
+    "if",
+    "int_to_float",
+    "length",
+    "loop",
+    "null",
+    "newRef",
+    "ptr",
+    "Ref",
+    "return",
+    "rt",
+    "scratch",
+    "speculate",
+    "sqrt",
+    "str",
+    "strlen",
+    "trap",
+    "true",
+    "var",
+    "void",
+    "while",
+];
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(ch) if ch.is_ascii_alphabetic() || ch == '_' || ch == '$' => {}
+        _ => return false,
+    }
+    chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '$') && !KEYWORDS.contains(&s)
+}
+
+/// True if the atom can appear as an operand without parentheses.
+fn is_closed(atom: &Atom) -> bool {
+    match atom {
+        Atom::Unary(UnaryOp::Nop, a, _) => is_closed(a),
+        Atom::Binary(..) | Atom::FromAny(..) | Atom::Deref(..) | Atom::EnvGet(..) => false,
+        _ => true,
+    }
+}
+
+/// Prints an atom that is an operand of another atom, with parentheses if necessary.
+fn operand<'b, D, A>(atom: &'b Atom, pp: &'b D) -> pretty::DocBuilder<'b, D, A>
+where
+    D: pretty::DocAllocator<'b, A>,
+    A: std::clone::Clone,
+    <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
+{
+    if is_closed(atom) {
+        atom.pretty(pp)
+    } else {
+        atom.pretty(pp).parens()
+    }
+}
+
+/// Prints `.field`, `."field"`, or `.[field]`, depending on the field.
+fn field<'b, D, A>(field: &'b Atom, pp: &'b D) -> pretty::DocBuilder<'b, D, A>
+where
+    D: pretty::DocAllocator<'b, A>,
+    A: std::clone::Clone,
+    <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
+{
+    match field {
+        Atom::Lit(Lit::String(s), _) | Atom::Lit(Lit::Interned(s, _), _) if is_identifier(s) => {
+            pp.text(".").append(pp.text(s))
+        }
+        Atom::Lit(lit @ Lit::String(..), _) | Atom::Lit(lit @ Lit::Interned(..), _) => {
+            pp.text(".").append(lit.pretty(pp))
+        }
+        _ => pp.text(".").append(field.pretty(pp).brackets()),
+    }
+}
+
+/// Prints the fields of an object literal as `{x, "a b"}`.
+fn object_fields<'b, D, A>(fields: &'b [Lit], pp: &'b D) -> pretty::DocBuilder<'b, D, A>
+where
+    D: pretty::DocAllocator<'b, A>,
+    A: std::clone::Clone,
+    <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
+{
+    pp.intersperse(
+        fields.iter().map(|field| match field {
+            Lit::String(s) | Lit::Interned(s, _) if is_identifier(s) => pp.text(s),
+            _ => field.pretty(pp),
+        }),
+        pp.text(",").append(pp.space()),
+    )
+    .braces()
+}
+
+/// Prints a statement as a block, so that it can appear in the body of a statement that requires
+/// a block.
+fn block<'b, D, A>(stmt: &'b Stmt, pp: &'b D) -> pretty::DocBuilder<'b, D, A>
+where
+    D: pretty::DocAllocator<'b, A>,
+    A: std::clone::Clone,
+    <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
+{
+    let stmts = match stmt {
+        Stmt::Block(stmts, _) => stmts.iter().collect(),
+        stmt => vec![stmt],
+    };
+    pp.text("{")
+        .append(
+            pp.concat(stmts.into_iter().map(|s| pp.hardline().append(s.pretty(pp))))
+                .nest(2),
+        )
+        .append(pp.hardline())
+        .append(pp.text("}"))
+}
+
+fn comma_sep<'b, D, A, T>(items: &'b [T], pp: &'b D) -> pretty::DocBuilder<'b, D, A>
+where
+    D: pretty::DocAllocator<'b, A>,
+    A: std::clone::Clone,
+    <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
+    T: Pretty,
+{
+    pp.intersperse(
+        items.iter().map(|item| item.pretty(pp)),
+        pp.text(",").append(pp.space()),
+    )
+}
+
 impl Pretty for Type {
     fn pretty<'b, D, A>(&'b self, pp: &'b D) -> pretty::DocBuilder<'b, D, A>
     where
@@ -14,14 +170,14 @@ impl Pretty for Type {
             Type::I32 => pp.text("i32"),
             Type::F64 => pp.text("f64"),
             Type::String => pp.text("str"),
-            Type::HT => pp.text("ht"),
-            Type::Array => pp.text("array"),
+            Type::HT => pp.text("HT"),
+            Type::Array => pp.text("Array"),
             Type::Bool => pp.text("bool"),
             Type::DynObject => pp.text("DynObject"),
             Type::Any => pp.text("any"),
-            Type::Ref(t) => pp.concat(vec![pp.text("ref"), t.pretty(pp).parens()]),
+            Type::Ref(t) => pp.text("Ref").append(t.pretty(pp).parens()),
             Type::Fn(fn_t) => fn_t.pretty(pp),
-            Type::Closure(fn_t) => pp.text("clos").append(fn_t.pretty(pp)),
+            Type::Closure(fn_t) => pp.text("clos").append(pp.space()).append(fn_t.pretty(pp)),
             Type::Env => pp.text("env"),
             Type::Ptr => pp.text("ptr"),
         }
@@ -36,18 +192,14 @@ impl Pretty for FnType {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         pp.concat(vec![
-            pp.intersperse(
-                self.args.iter().map(|t| t.pretty(pp)),
-                pp.text(",").append(pp.space()),
-            )
-            .parens(),
+            comma_sep(&self.args, pp).parens(),
             pp.space(),
             pp.text("->"),
             pp.space(),
             self.result
                 .as_ref()
                 .map(|t| t.pretty(pp))
-                .unwrap_or(pp.text("none")),
+                .unwrap_or(pp.text("void")),
         ])
     }
 }
@@ -60,7 +212,7 @@ impl Pretty for UnaryOp {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         match self {
-            UnaryOp::Sqrt => pp.text("Math.sqrt"),
+            UnaryOp::Sqrt => pp.text("sqrt"),
             UnaryOp::I32Neg => pp.text("-"),
             UnaryOp::I32Not => pp.text("~"),
             UnaryOp::F64Neg => pp.text("-."),
@@ -79,7 +231,7 @@ impl Pretty for BinaryOp {
     {
         match self {
             BinaryOp::PtrEq => pp.text("==="),
-            BinaryOp::I32Eq => pp.text("==="),
+            BinaryOp::I32Eq => pp.text("=="),
             BinaryOp::I32Ne => pp.text("!="),
             BinaryOp::I32Add => pp.text("+"),
             BinaryOp::I32Sub => pp.text("-"),
@@ -96,16 +248,16 @@ impl Pretty for BinaryOp {
             BinaryOp::I32Shl => pp.text("<<"),
             BinaryOp::I32Shr => pp.text(">>"),
             BinaryOp::I32ShrU => pp.text(">>>"),
-            BinaryOp::F64Eq => pp.text("==="),
-            BinaryOp::F64Ne => pp.text("!="),
-            BinaryOp::F64Add => pp.text("+"),
-            BinaryOp::F64Sub => pp.text("-"),
-            BinaryOp::F64Mul => pp.text("*"),
-            BinaryOp::F64Div => pp.text("/"),
-            BinaryOp::F64LT => pp.text("<"),
-            BinaryOp::F64GT => pp.text(">"),
-            BinaryOp::F64Le => pp.text("<="),
-            BinaryOp::F64Ge => pp.text(">="),
+            BinaryOp::F64Eq => pp.text("==."),
+            BinaryOp::F64Ne => pp.text("!=."),
+            BinaryOp::F64Add => pp.text("+."),
+            BinaryOp::F64Sub => pp.text("-."),
+            BinaryOp::F64Mul => pp.text("*."),
+            BinaryOp::F64Div => pp.text("/."),
+            BinaryOp::F64LT => pp.text("<."),
+            BinaryOp::F64GT => pp.text(">."),
+            BinaryOp::F64Le => pp.text("<=."),
+            BinaryOp::F64Ge => pp.text(">=."),
         }
     }
 }
@@ -135,10 +287,7 @@ impl Pretty for Lit {
             Lit::Bool(b) => pp.text(if *b { "true" } else { "false" }),
             Lit::I32(n) => pp.as_string(n),
             Lit::F64(x) => pp.text(format!("{}f", x)),
-            // TOOD(arjun): Escape characters
-            Lit::String(s) | Lit::Interned(s, _) => {
-                pp.text("\"").append(pp.text(s)).append(pp.text("\""))
-            }
+            Lit::String(s) | Lit::Interned(s, _) => pp.text(escape_string(s)),
             Lit::Undefined => pp.text("undefined"),
             Lit::Null => pp.text("null"),
         }
@@ -177,7 +326,7 @@ impl Pretty for (Atom, Type) {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         let (x, t) = self;
-        prettyp!(pp, (seq (id x) ":" (id t)))
+        pp.concat(vec![operand(x, pp), pp.text(":"), pp.space(), t.pretty(pp)])
     }
 }
 
@@ -190,22 +339,46 @@ impl Pretty for Atom {
     {
         match self {
             Atom::Lit(l, _) => l.pretty(pp),
-            Atom::PrimApp(f, args, _) => prettyp!(pp, (seq "@" (id f) (parens (comma_sep args)))),
+            Atom::PrimApp(f, args, _) => pp.concat(vec![
+                pp.text("@"),
+                f.pretty(pp),
+                pp.intersperse(
+                    args.iter().map(|a| operand(a, pp)),
+                    pp.text(",").append(pp.space()),
+                )
+                .parens(),
+            ]),
             Atom::ToAny(to_any, _) => to_any.pretty(pp),
-            Atom::FromAny(a, t, _) => prettyp!(pp, (seq (id a) space "as" space (id t))),
-            Atom::FloatToInt(a, _) => prettyp!(pp, (seq "float_to_int" space (id a))),
-            Atom::IntToFloat(a, _) => prettyp!(pp, (seq "int_to_float" space (id a))),
-            Atom::ObjectGet(l, r, _) => match &**r {
-                Atom::Lit(Lit::String(r), _) => prettyp!(pp, (seq (id l) ".htget" (id r))),
-                _ => prettyp!(pp, (seq (id l) "." (id r))),
-            },
-            Atom::AnyLength(obj, lit, _) => prettyp!(pp, (seq (id obj) "." (id lit))),
+            Atom::FromAny(a, t, _) => pp.concat(vec![
+                operand(a, pp),
+                pp.space(),
+                pp.text("as"),
+                pp.space(),
+                t.pretty(pp),
+            ]),
+            Atom::FloatToInt(a, _) => prettyp!(pp, (seq "float_to_int" (parens (id a)))),
+            Atom::IntToFloat(a, _) => prettyp!(pp, (seq "int_to_float" (parens (id a)))),
+            Atom::ObjectGet(l, r, _) => operand(l, pp).append(field(r, pp)),
+            Atom::AnyLength(obj, _, _) => prettyp!(pp, (seq (id obj) ".length")),
             Atom::Id(id, _) => pp.as_string(id),
             Atom::GetPrimFunc(id, _) => prettyp!(pp, (seq "rt" (parens (id id)))),
-            Atom::Unary(op, a, _) => prettyp!(pp, (seq (id op) (id a))),
-            Atom::Binary(op, l, r, _) => prettyp!(pp, (seq (id l) (id op) (id r))),
-            Atom::Deref(a, _, _) => prettyp!(pp, (seq "*" (id a))),
-            Atom::EnvGet(index, t, _) => prettyp!(pp, (seq "env." (id index) ":" (id t))),
+            Atom::Unary(UnaryOp::Nop, a, _) => a.pretty(pp),
+            Atom::Unary(op, a, _) => prettyp!(pp, (seq (id op) (parens (id a)))),
+            Atom::Binary(op, l, r, _) => pp.concat(vec![
+                operand(l, pp),
+                pp.space(),
+                op.pretty(pp),
+                pp.space(),
+                operand(r, pp),
+            ]),
+            Atom::Deref(a, t, _) => pp.concat(vec![
+                pp.text("*"),
+                operand(a, pp),
+                pp.text(":"),
+                pp.space(),
+                t.pretty(pp),
+            ]),
+            Atom::EnvGet(index, t, _) => prettyp!(pp, (seq "env." (id index) ":" space (id t))),
         }
     }
 }
@@ -218,51 +391,86 @@ impl Pretty for Expr {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         match self {
-            Expr::ArraySet(a, b, c, _) =>
-            /* pp.concat(vec![
-                pp.text("array_set"),
+            Expr::ArraySet(a, b, c, _) => pp.concat(vec![
+                operand(a, pp),
+                b.pretty(pp).brackets(),
+                pp.text(" = "),
+                c.pretty(pp),
+            ]),
+            Expr::Call(f, args, _) => f.pretty(pp).append(comma_sep(args, pp).parens()),
+            Expr::ClosureCall(f, args, _) => f
+                .pretty(pp)
+                .append(pp.text("!"))
+                .append(comma_sep(args, pp).parens()),
+            Expr::AnyMethodCall(obj, method, args, typs, _) => {
+                let method = match method {
+                    Lit::String(s) | Lit::Interned(s, _) => s,
+                    _ => panic!("method name is not a string"),
+                };
                 pp.concat(vec![
-                    a.pretty(pp),
-                    pp.text(", "),
-                    b.pretty(pp),
-                    pp.text(", "),
-                    c.pretty(pp),
+                    obj.pretty(pp),
+                    pp.text("?."),
+                    pp.text(method),
+                    pp.text("<"),
+                    comma_sep(typs, pp),
+                    pp.text(">"),
+                    comma_sep(args, pp).parens(),
                 ])
-                .braces(),
-            ]), */
-            {
-                prettyp!(pp, (seq "array_set" (braces (seq (id a) "," (id b) "," (id c)))))
-            }
-            Expr::Call(f, args, _) =>
-            /* pp.concat(vec![
-                pp.as_string(f),
-                pp.intersperse(
-                    args.iter().map(|a| pp.as_string(a)),
-                    pp.text(",").append(pp.space()),
-                )
-                .parens(),
-            ]), */
-            {
-                prettyp!(pp, (seq (id f) (parens (comma_sep args))))
-            }
-            Expr::ClosureCall(f, args, _) => {
-                prettyp!(pp, (seq (id f) "!" (parens (comma_sep args))))
-            }
-            Expr::AnyMethodCall(obj, method, args, typs, _) => {
-                let simple_types: Vec<_> = typs.into_iter().map(|t| &t.unwrap_fun().0[0]).collect();
-                prettyp!(pp, (seq (id obj) "." (id method) "?[" (comma_sep simple_types) "]" (parens (comma_sep args))))
             }
             Expr::PrimCall(rtsfun, args, _) => {
-                prettyp!(pp, (seq (as_string rtsfun) (parens (comma_sep args))))
+                let name = match rtsfun.name() {
+                    RTSFunctionImpl::Rust(name) => name,
+                    RTSFunctionImpl::NotWasm(name) => name.to_string(),
+                };
+                pp.text("!")
+                    .append(pp.text(name))
+                    .append(comma_sep(args, pp).parens())
             }
-            Expr::ObjectEmpty => pp.text("{}"),
-            Expr::ObjectSet(a, Atom::Lit(Lit::String(s), _), c, _) => {
-                prettyp!(pp, (seq (id a) "." (id s) "=" (id c) ";"))
+            Expr::Speculate(op, rtsfun, args, _) => {
+                let name = match rtsfun.name() {
+                    RTSFunctionImpl::Rust(name) => name,
+                    RTSFunctionImpl::NotWasm(name) => name.to_string(),
+                };
+                pp.concat(vec![
+                    pp.text("speculate"),
+                    pp.concat(vec![
+                        args[0].pretty(pp),
+                        pp.space(),
+                        op.pretty(pp),
+                        pp.space(),
+                        args[1].pretty(pp),
+                    ])
+                    .parens(),
+                    pp.space(),
+                    pp.text("!"),
+                    pp.text(name),
+                    comma_sep(args, pp).parens(),
+                ])
             }
-            Expr::ObjectSet(a, b, c, _) => prettyp!(pp, (seq (id a) "." (id b) "=" (id c) ";")),
-            Expr::NewRef(a, ty, _) => prettyp!(pp, (seq "new_ref::" (id ty) (parens (id a)))),
+            Expr::ObjectEmpty(_) => pp.text("{}"),
+            Expr::ObjectLiteral(fields, _) => object_fields(fields, pp),
+            Expr::ScratchObject(fields, _) => pp
+                .text("scratch")
+                .append(pp.space())
+                .append(object_fields(fields, pp)),
+            Expr::ObjectSet(a, b, c, _) => pp.concat(vec![
+                operand(a, pp),
+                field(b, pp),
+                pp.text(" = "),
+                c.pretty(pp),
+            ]),
+            Expr::NewRef(a, ty, _) => pp.text("newRef").append(
+                pp.concat(vec![operand(a, pp), pp.text(","), pp.space(), ty.pretty(pp)])
+                    .parens(),
+            ),
             Expr::Atom(a, _) => a.pretty(pp),
-            Expr::Closure(id, env, _) => prettyp!(pp, (seq "clos" (id id) (comma_sep env))),
+            Expr::Closure(id, env, _) if env.is_empty() => {
+                prettyp!(pp, (seq "clos" (parens (id id))))
+            }
+            Expr::Closure(id, env, _) => pp.text("clos").append(
+                pp.concat(vec![id.pretty(pp), pp.text(","), pp.space(), comma_sep(env, pp)])
+                    .parens(),
+            ),
         }
     }
 }
@@ -275,7 +483,7 @@ impl Pretty for VarStmt {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         if self.id == Id::from("_") {
-            return self.named.pretty(pp);
+            return self.named.pretty(pp).append(pp.text(";"));
         }
         pp.concat(vec![
             pp.text("var"),
@@ -283,8 +491,8 @@ impl Pretty for VarStmt {
             pp.as_string(self.id.clone()),
             self.ty
                 .as_ref()
-                .map(|t| pp.text(":").append(t.pretty(pp)))
-                .unwrap_or(pp.space()),
+                .map(|t| pp.text(":").append(pp.space()).append(t.pretty(pp)))
+                .unwrap_or(pp.nil()),
             pp.space(),
             pp.text("="),
             pp.space(),
@@ -302,42 +510,30 @@ impl Pretty for Stmt {
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
         match self {
-            Stmt::Empty => pp.space(),
+            Stmt::Empty => pp.text(";"),
             Stmt::Var(var_stmt, _) => var_stmt.pretty(pp),
-            Stmt::Expression(expr, _) => expr.pretty(pp),
-            Stmt::Assign(x, expr, _) => prettyp!(pp, (seq (id x) "=" (id expr) ";")),
-            Stmt::Store(x, expr, _) => prettyp!(pp, (seq "*" (id x) "=" (id expr) ";")),
-            Stmt::If(e, s1, s2, _) => prettyp!(pp, (seq (seq "if" space (parens (id e)) (nest (id s1)) "else" (nest (id s2))))),
-            Stmt::Loop(st, _) => prettyp!(pp, (seq "loop" (id st))),
-            Stmt::Label(lbl, st, _) => prettyp!(pp, (seq (id lbl) ":" line (id st))),
+            Stmt::Expression(expr, _) => prettyp!(pp, (seq (id expr) ";")),
+            Stmt::Assign(x, expr, _) => prettyp!(pp, (seq (id x) space "=" space (id expr) ";")),
+            Stmt::Store(x, expr, _) => {
+                prettyp!(pp, (seq "*" (id x) space "=" space (id expr) ";"))
+            }
+            Stmt::If(e, s1, s2, _) => pp.concat(vec![
+                prettyp!(pp, (seq "if" space (parens (id e)) space)),
+                block(s1, pp),
+                pp.text(" else "),
+                block(s2, pp),
+            ]),
+            Stmt::Loop(st, _) => pp.text("loop ").append(block(st, pp)),
+            Stmt::Label(lbl, st, _) => prettyp!(pp, (seq (id lbl) ":" space)).append(block(st, pp)),
             Stmt::Break(lbl, _) => prettyp!(pp, (seq "break" space (id lbl) ";")),
             Stmt::Return(e, _) => prettyp!(pp, (seq "return" space (id e) ";")),
-            Stmt::Block(stmts, _) => prettyp!(pp, (nest (braces (line_sep stmts)))),
-            Stmt::Trap => pp.text("trap"),
+            Stmt::Block(..) => block(self, pp),
+            Stmt::Trap => pp.text("trap;"),
             Stmt::Goto(lbl, _) => prettyp!(pp, (seq "goto" space (id lbl) ";")),
         }
     }
 }
 
-impl Pretty for Global {
-    fn pretty<'b, D, A>(&'b self, pp: &'b D) -> pretty::DocBuilder<'b, D, A>
-    where
-        D: pretty::DocAllocator<'b, A>,
-        A: std::clone::Clone,
-        <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
-    {
-        pp.concat(vec![
-            self.ty.pretty(pp),
-            pp.space(),
-            self.atom
-                .as_ref()
-                .map(|v| pp.text("= ").append(v.pretty(pp)))
-                .unwrap_or(pp.space()),
-            pp.text(";"),
-        ])
-    }
-}
-
 impl Pretty for Function {
     fn pretty<'b, D, A>(&'b self, pp: &'b D) -> pretty::DocBuilder<'b, D, A>
     where
@@ -347,22 +543,20 @@ impl Pretty for Function {
     {
         pp.concat(vec![
             pp.intersperse(
-                self.params
-                    .iter()
-                    .zip(&self.fn_type.args)
-                    .map(|(x, t)| pp.concat(vec![pp.as_string(x), pp.text(":"), t.pretty(pp)])),
+                self.params.iter().zip(&self.fn_type.args).map(|(x, t)| {
+                    pp.concat(vec![pp.as_string(x), pp.text(":"), pp.space(), t.pretty(pp)])
+                }),
                 pp.text(",").append(pp.space()),
             )
             .parens(),
-            pp.space(),
             // Print the result type, if there is one.
             self.fn_type
                 .result
                 .as_ref()
                 .map(|result_ty| pp.text(":").append(pp.space()).append(result_ty.pretty(pp)))
-                .unwrap_or(pp.space()),
+                .unwrap_or(pp.nil()),
             pp.space(),
-            self.body.pretty(pp),
+            block(&self.body, pp),
         ])
     }
 }
@@ -374,36 +568,54 @@ impl Pretty for Program {
         A: std::clone::Clone,
         <D as pretty::DocAllocator<'b, A>>::Doc: std::clone::Clone,
     {
+        // Sort everything by name, so that the output does not depend on the order of the
+        // hash tables.
+        let mut imports: Vec<_> = self.rts_fn_imports.iter().collect();
+        imports.sort_by(|(x, _), (y, _)| x.cmp(y));
+        let mut globals: Vec<_> = self.globals.iter().collect();
+        globals.sort_by_key(|(x, _)| x.to_string());
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|(x, _)| x.to_string());
         pp.concat(vec![
-            pp.intersperse(
-                self.globals.iter().map(|(k, v)| {
-                    pp.concat(vec![
-                        pp.text("var"),
-                        pp.space(),
-                        pp.as_string(k),
-                        pp.text(":"),
-                        pp.space(),
-                        v.pretty(pp),
-                    ])
-                }),
-                pp.hardline(),
-            )
-            .nest(2),
-            pp.hardline(),
-            pp.hardline(),
-            pp.intersperse(
-                self.functions.iter().map(|(fn_name, v)| {
-                    pp.concat(vec![
-                        pp.text("function"),
-                        pp.space(),
-                        pp.as_string(fn_name),
-                        pp.space(),
-                        v.pretty(pp),
-                    ])
-                }),
-                pp.hardline(),
-            )
-            .nest(2),
+            pp.concat(imports.into_iter().map(|(name, ty)| {
+                pp.concat(vec![
+                    pp.text("import"),
+                    pp.space(),
+                    pp.text(name),
+                    pp.text(":"),
+                    pp.space(),
+                    ty.pretty(pp),
+                    pp.text(";"),
+                    pp.hardline(),
+                ])
+            })),
+            pp.concat(globals.into_iter().map(|(x, global)| {
+                pp.concat(vec![
+                    pp.text(if global.is_mut { "var" } else { "const" }),
+                    pp.space(),
+                    pp.as_string(x),
+                    pp.text(":"),
+                    pp.space(),
+                    global.ty.pretty(pp),
+                    global
+                        .atom
+                        .as_ref()
+                        .map(|a| pp.text(" = ").append(a.pretty(pp)))
+                        .unwrap_or(pp.nil()),
+                    pp.text(";"),
+                    pp.hardline(),
+                ])
+            })),
+            pp.concat(functions.into_iter().map(|(fn_name, f)| {
+                pp.concat(vec![
+                    pp.hardline(),
+                    pp.text(if f.no_gc { "nogc function" } else { "function" }),
+                    pp.space(),
+                    pp.as_string(fn_name),
+                    f.pretty(pp),
+                    pp.hardline(),
+                ])
+            })),
             // NOTE(arjun): Not displaying data segment
         ])
     }
@@ -422,3 +634,170 @@ where
 }
 
 impl_Display_Pretty!(Program);
+
+#[cfg(test)]
+mod test {
+    use super::super::constructors::*;
+    use super::super::parse;
+    use super::super::stdlib::directives;
+    use super::super::syntax::*;
+    use crate::pos::Pos;
+    use std::fs;
+    use std::path::Path;
+
+    const BINARY_OPS: &[BinaryOp] = &[
+        BinaryOp::PtrEq,
+        BinaryOp::I32Eq,
+        BinaryOp::I32Ne,
+        BinaryOp::I32Add,
+        BinaryOp::I32Sub,
+        BinaryOp::I32Mul,
+        BinaryOp::I32Div,
+        BinaryOp::I32Rem,
+        BinaryOp::I32GT,
+        BinaryOp::I32LT,
+        BinaryOp::I32Ge,
+        BinaryOp::I32Le,
+        BinaryOp::I32And,
+        BinaryOp::I32Or,
+        BinaryOp::I32Xor,
+        BinaryOp::I32Shl,
+        BinaryOp::I32Shr,
+        BinaryOp::I32ShrU,
+        BinaryOp::F64Add,
+        BinaryOp::F64Sub,
+        BinaryOp::F64Mul,
+        BinaryOp::F64Div,
+        BinaryOp::F64LT,
+        BinaryOp::F64GT,
+        BinaryOp::F64Le,
+        BinaryOp::F64Ge,
+        BinaryOp::F64Eq,
+        BinaryOp::F64Ne,
+    ];
+
+    const UNARY_OPS: &[UnaryOp] = &[
+        UnaryOp::Sqrt,
+        UnaryOp::F64Neg,
+        UnaryOp::I32Neg,
+        UnaryOp::I32Not,
+        UnaryOp::Eqz,
+        UnaryOp::Nop,
+    ];
+
+    /// Checks that parsing the printed program and printing it again produces the same text.
+    fn assert_round_trips(program: &Program) {
+        let printed = program.to_string();
+        let reprinted = parse("round-trip", printed.as_str()).to_string();
+        assert_eq!(printed, reprinted);
+    }
+
+    fn leaves() -> Vec<Atom> {
+        let s = Pos::UNKNOWN;
+        vec![
+            i32_(7, s.clone()),
+            i32_(-1, s.clone()),
+            f64_(2.5, s.clone()),
+            f64_(-3.0, s.clone()),
+            str_("a \"quoted\"\n\\ string", s.clone()),
+            TRUE_,
+            Atom::Lit(Lit::Null, s.clone()),
+            get_id_("x", s.clone()),
+            Atom::AnyLength(id_("x"), Lit::String("length".to_string()), s.clone()),
+            Atom::EnvGet(0, Type::I32, s.clone()),
+            Atom::GetPrimFunc(id_("f"), s.clone()),
+        ]
+    }
+
+    /// Wraps every atom in every kind of atom, taking the other operand of binary operators from
+    /// the next atom.
+    fn grow(atoms: &[Atom]) -> Vec<Atom> {
+        let s = Pos::UNKNOWN;
+        let mut grown = Vec::new();
+        for (i, a) in atoms.iter().enumerate() {
+            let b = atoms[(i + 1) % atoms.len()].clone();
+            for op in UNARY_OPS {
+                grown.push(unary_(op.clone(), a.clone(), s.clone()));
+            }
+            let op = BINARY_OPS[i % BINARY_OPS.len()].clone();
+            grown.push(binary_(op, a.clone(), b.clone(), s.clone()));
+            grown.push(from_any_(a.clone(), Type::F64, s.clone()));
+            grown.push(deref_(a.clone(), Type::Ref(Box::new(Type::Any)), s.clone()));
+            grown.push(to_any_(a.clone(), s.clone()));
+            grown.push(Atom::FloatToInt(Box::new(a.clone()), s.clone()));
+            grown.push(Atom::IntToFloat(Box::new(a.clone()), s.clone()));
+            grown.push(prim_app_("f", vec![a.clone(), b.clone()], s.clone()));
+            for field in &["f", "length", "if", "two words"] {
+                grown.push(object_get_(a.clone(), str_(*field, s.clone()), s.clone()));
+            }
+            grown.push(object_get_(a.clone(), b, s.clone()));
+        }
+        grown
+    }
+
+    #[test]
+    fn atoms_round_trip() {
+        let s = Pos::UNKNOWN;
+        let body = grow(&grow(&leaves()))
+            .into_iter()
+            .map(|atom| var_("y", atom_(atom, s.clone()), s.clone()))
+            .collect();
+        assert_round_trips(&test_program_(Stmt::Block(body, s)));
+    }
+
+    #[test]
+    fn stmts_round_trip() {
+        let program = parse(
+            "inline",
+            r#"
+            import ht_new: () -> HT;
+            const c: i32 = 1;
+            var g: Ref(any);
+            function f(x: any, y: clos (i32, f64) -> void): str {
+                var o: DynObject = {};
+                o."if" = any(x);
+                o."two words" = any(-(1));
+                (o as DynObject).[x] = *x: Ref(i32);
+                var a = @array_new();
+                a[0] = any(1);
+                *g = newRef(x, any);
+                var k = clos(f, c: i32, (*g: any): any);
+                var r = k!(x, y);
+                var s = x?.slice<(str, i32) -> str>(x, x);
+                while (x == 0) {
+                    if (x >. 1.5f) {
+                        break $loop;
+                    } else {
+                        trap;
+                    }
+                }
+                lbl: {
+                    ;
+                }
+                return "\"escaped\"\n";
+            }
+            "#,
+        );
+        assert_round_trips(&program);
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let mut paths = vec![root.join("stdlib.notwasm")];
+        for entry in fs::read_dir(root.join("stdlib")).unwrap() {
+            paths.push(entry.unwrap().path());
+        }
+        for entry in fs::read_dir(root.join("integration_tests/test_data")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(false, |ext| ext == "notwasm") {
+                paths.push(path);
+            }
+        }
+        for path in paths {
+            let name = path.to_str().unwrap();
+            let (_, src) = directives(name, &fs::read_to_string(&path).unwrap()).unwrap();
+            assert_round_trips(&parse(name, src));
+        }
+    }
+}
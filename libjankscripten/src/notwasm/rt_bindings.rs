@@ -4,10 +4,31 @@ use crate::rts_function::{RTSFunction, RTSFunctionImpl};
 use crate::shared::methods::METHODS_TABLE;
 use std::collections::HashMap;
 use strum::IntoEnumIterator;
+use thiserror::Error;
 use Type::*;
 
 type BindMap = HashMap<std::string::String, Type>;
 
+/// The WebAssembly signatures of the functions that the runtime system exports, and the features
+/// of the runtime system that provide them, which `build.rs` reads from its source.
+mod manifest {
+    include!(concat!(env!("OUT_DIR"), "/runtime_manifest.rs"));
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum RuntimeBindingError {
+    #[error("the runtime system does not export a function named `{0}`")]
+    Missing(std::string::String),
+    #[error("`{0}` needs the feature `{1}` of the runtime system, which it does not have")]
+    Disabled(std::string::String, &'static str),
+    #[error("the runtime function `{0}` has the signature `{1}`, but NotWasm imports it as `{2}`")]
+    Mismatch(
+        std::string::String,
+        std::string::String,
+        std::string::String,
+    ),
+}
+
 /// Generate a map of the runtime functions available to NotWasm.
 /// Each runtime function is mapped to its NotWasm type signature.
 ///
@@ -85,3 +106,118 @@ fn insert_mono<'a, X, I>(
         assert!(map.insert(mono_name, fn_ty_(params_tys, ret_ty)).is_none());
     }
 }
+
+fn wasm_signature(
+    params: impl Iterator<Item = std::string::String>,
+    result: Option<std::string::String>,
+) -> std::string::String {
+    format!(
+        "({}) -> {}",
+        params.collect::<Vec<_>>().join(", "),
+        result.unwrap_or_else(|| "()".to_string())
+    )
+}
+
+/// The optional features of the runtime system, which are its cargo features.
+pub fn runtime_features() -> Vec<std::string::String> {
+    manifest::RUNTIME_FEATURES
+        .iter()
+        .map(|feature| feature.to_string())
+        .collect()
+}
+
+/// Determines if a runtime system with `features` exports `name`.
+pub fn rt_function_enabled(name: &str, features: &[std::string::String]) -> bool {
+    match manifest::RUNTIME_FUNCTIONS
+        .iter()
+        .find(|(f, ..)| *f == name)
+    {
+        Some((.., Some(feature))) => features.iter().any(|f| f == feature),
+        _ => true,
+    }
+}
+
+/// Checks that a runtime system with `features` exports `name`, and that its WebAssembly
+/// signature is the signature of `ty`, which is the type of the function in NotWasm.
+pub fn check_rt_binding(
+    name: &str,
+    ty: &Type,
+    features: &[std::string::String],
+) -> Result<(), RuntimeBindingError> {
+    let (params, result) = match manifest::RUNTIME_FUNCTIONS
+        .iter()
+        .find(|(f, ..)| *f == name)
+    {
+        Some((_, _, _, Some(feature))) if !features.iter().any(|f| f == feature) => {
+            return Err(RuntimeBindingError::Disabled(name.to_string(), *feature));
+        }
+        Some((_, params, result, _)) => (params, result),
+        None => return Err(RuntimeBindingError::Missing(name.to_string())),
+    };
+    let actual = wasm_signature(
+        params.iter().map(|t| t.to_string()),
+        result.map(|t| t.to_string()),
+    );
+    let expected = match ty {
+        Type::Fn(fn_ty) => wasm_signature(
+            fn_ty.args.iter().map(|t| t.as_wasm().to_string()),
+            fn_ty.result.as_ref().map(|t| t.as_wasm().to_string()),
+        ),
+        _ => "not a function".to_string(),
+    };
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(RuntimeBindingError::Mismatch(
+            name.to_string(),
+            actual,
+            expected,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bindings_match_the_runtime() {
+        for (name, ty) in get_rt_bindings() {
+            check_rt_binding(&name, &ty, &runtime_features()).unwrap();
+        }
+    }
+
+    #[test]
+    fn disabled_features() {
+        assert!(runtime_features().contains(&"uri".to_string()));
+        let ty = &get_rt_bindings()["encode_uri_component"];
+        assert_eq!(
+            check_rt_binding("encode_uri_component", ty, &[]),
+            Err(RuntimeBindingError::Disabled(
+                "encode_uri_component".to_string(),
+                "uri"
+            ))
+        );
+        assert!(!rt_function_enabled("encode_uri_component", &[]));
+        assert!(!rt_function_enabled("date_now", &[]));
+        assert!(rt_function_enabled("date_now", &["date".to_string()]));
+        assert!(rt_function_enabled("math_random", &[]));
+        assert!(rt_function_enabled("any_from_i32", &[]));
+    }
+
+    #[test]
+    fn mismatched_bindings() {
+        assert_eq!(
+            check_rt_binding("no_such_function", &fn_ty_(vec![], None), &[]),
+            Err(RuntimeBindingError::Missing("no_such_function".to_string()))
+        );
+        assert_eq!(
+            check_rt_binding("any_from_i32", &fn_ty_(vec![I32], Some(I32)), &[]),
+            Err(RuntimeBindingError::Mismatch(
+                "any_from_i32".to_string(),
+                "(i32) -> i64".to_string(),
+                "(i32) -> i32".to_string(),
+            ))
+        );
+    }
+}
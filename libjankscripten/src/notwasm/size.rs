@@ -0,0 +1,249 @@
+//! The size of a WebAssembly module, by section and by function.
+//!
+//! The standard library grows with every feature, and so do the tables that the compiler
+//! generates for it (e.g., the stubs of `METHODS_TABLE`), so [size_report] tells which parts of a
+//! module are large, and [Opts::max_module_size](crate::opts::Opts::max_module_size) stops a
+//! compilation that produces a module larger than a budget.
+//!
+//! The sizes are of the encoded module: a section includes its id and length, and a function
+//! includes the length of its body. The names of the functions are those of the name section.
+use super::binary::{sections, Malformed, Reader};
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// The number of functions that [SizeError::OverBudget] lists.
+const OFFENDERS: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum SizeError {
+    #[error("{0}")]
+    Malformed(#[from] Malformed),
+    #[error(
+        "the module is {size} bytes, which exceeds the budget of {budget} bytes. The largest \
+         functions are:\n{offenders}"
+    )]
+    OverBudget {
+        size: usize,
+        budget: usize,
+        offenders: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeReport {
+    /// The size of the module.
+    pub total: usize,
+    /// The size of each section, in the order of the module. A custom section is named by its
+    /// name, e.g., `name`.
+    pub sections: Vec<(String, usize)>,
+    /// The size of each function that the module defines, largest first. A function that the
+    /// name section does not name is `func[INDEX]`.
+    pub functions: Vec<(String, usize)>,
+}
+
+impl SizeReport {
+    /// Fails if the module is larger than `budget` bytes, with the largest functions.
+    pub fn check_budget(&self, budget: usize) -> Result<(), SizeError> {
+        if self.total <= budget {
+            return Ok(());
+        }
+        let offenders = self
+            .functions
+            .iter()
+            .take(OFFENDERS)
+            .map(|(name, size)| format!("    {}: {} bytes", name, size))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Err(SizeError::OverBudget {
+            size: self.total,
+            budget,
+            offenders,
+        })
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "module: {} bytes", self.total)?;
+        writeln!(f, "sections:")?;
+        for (name, size) in &self.sections {
+            writeln!(f, "    {}: {} bytes", name, size)?;
+        }
+        writeln!(f, "functions:")?;
+        for (name, size) in &self.functions {
+            writeln!(f, "    {}: {} bytes", name, size)?;
+        }
+        Ok(())
+    }
+}
+
+/// The sizes of the sections and functions of `wasm`.
+pub fn size_report(wasm: &[u8]) -> Result<SizeReport, SizeError> {
+    let sections = sections(wasm)?;
+    let mut num_imports = 0;
+    let mut names = HashMap::new();
+    for section in &sections {
+        if section.id == 2 {
+            num_imports = Reader::new(section.payload).function_imports()?;
+        }
+        if let Some((name, payload)) = section.custom()? {
+            if name == "name" {
+                // A malformed name section only loses the names.
+                names = function_names(payload).unwrap_or_default();
+            }
+        }
+    }
+
+    let mut report = Vec::new();
+    let mut functions = Vec::new();
+    for section in &sections {
+        let name = match (section.id, section.custom()?) {
+            (_, Some((name, _))) => name,
+            (10, _) => {
+                let mut payload = Reader::new(section.payload);
+                for i in 0..payload.u32()? as usize {
+                    let body_start = payload.pos;
+                    let body_len = payload.u32()? as usize;
+                    payload.take(body_len)?;
+                    let index = (num_imports + i) as u32;
+                    let name = match names.get(&index) {
+                        Some(name) => name.clone(),
+                        None => format!("func[{}]", index),
+                    };
+                    functions.push((name, payload.pos - body_start));
+                }
+                "code".to_string()
+            }
+            (id, _) => section_name(id)?.to_string(),
+        };
+        report.push((name, section.end - section.start));
+    }
+    functions.sort_by(|(f1, size1), (f2, size2)| size2.cmp(size1).then_with(|| f1.cmp(f2)));
+    Ok(SizeReport {
+        total: wasm.len(),
+        sections: report,
+        functions,
+    })
+}
+
+/// The names of the functions in the payload of a name section, which is the one subsection
+/// that we read.
+fn function_names(payload: &[u8]) -> Result<HashMap<u32, String>, Malformed> {
+    let mut reader = Reader::new(payload);
+    let mut names = HashMap::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.u32()? as usize;
+        let mut subsection = Reader::new(reader.take(len)?);
+        if id == 1 {
+            for _ in 0..subsection.u32()? {
+                let index = subsection.u32()?;
+                names.insert(index, subsection.name()?);
+            }
+        }
+    }
+    Ok(names)
+}
+
+fn section_name(id: u8) -> Result<&'static str, SizeError> {
+    Ok(match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        11 => "data",
+        12 => "datacount",
+        _ => return Err(Malformed("unknown section").into()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parity_wasm::builder::module;
+    use parity_wasm::elements::{
+        FunctionNameSubsection, Instruction, Instructions, NameSection, Section,
+    };
+
+    /// A module with a function that has `n` instructions for each of `sizes`, and names for the
+    /// first `named` of them.
+    fn wasm(sizes: &[usize], named: usize) -> Vec<u8> {
+        let mut module = module();
+        let mut names = FunctionNameSubsection::default();
+        for (i, n) in sizes.iter().enumerate() {
+            let mut insts = vec![Instruction::Nop; *n];
+            insts.push(Instruction::End);
+            module = module
+                .function()
+                .signature()
+                .build()
+                .body()
+                .with_instructions(Instructions::new(insts))
+                .build()
+                .build();
+            if i < named {
+                names.names_mut().insert(i as u32, format!("f{}", i));
+            }
+        }
+        let module = module
+            .with_section(Section::Name(NameSection::new(None, Some(names), None)))
+            .build();
+        parity_wasm::serialize(module).unwrap()
+    }
+
+    #[test]
+    fn functions_by_size() {
+        let wasm = wasm(&[10, 200, 30], 2);
+        let report = size_report(&wasm).unwrap();
+        assert_eq!(report.total, wasm.len());
+        // The header is 8 bytes.
+        let sections: usize = report.sections.iter().map(|(_, size)| size).sum();
+        assert_eq!(sections + 8, wasm.len());
+        let names: Vec<_> = report
+            .sections
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, vec!["type", "function", "code", "name"]);
+        // The length of the body (2 bytes if it is over 127), 1 byte of locals, the instructions,
+        // and `end`
+        assert_eq!(
+            report.functions,
+            vec![
+                ("f1".to_string(), 204),
+                ("func[2]".to_string(), 33),
+                ("f0".to_string(), 13)
+            ]
+        );
+    }
+
+    #[test]
+    fn over_budget() {
+        let wasm = wasm(&[10, 200], 2);
+        let report = size_report(&wasm).unwrap();
+        assert!(report.check_budget(wasm.len()).is_ok());
+        match report.check_budget(100) {
+            Err(SizeError::OverBudget {
+                size,
+                budget,
+                offenders,
+            }) => {
+                assert_eq!((size, budget), (wasm.len(), 100));
+                assert_eq!(offenders, "    f1: 204 bytes\n    f0: 13 bytes");
+            }
+            result => panic!("expected OverBudget, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn truncated_module() {
+        let wasm = wasm(&[10], 1);
+        assert!(size_report(&wasm[..wasm.len() - 1]).is_err());
+    }
+}
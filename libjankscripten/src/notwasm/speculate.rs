@@ -0,0 +1,135 @@
+//! Speculate that operations on `any` values operate on `i32`s.
+//!
+//! Type inference leaves the operands of many arithmetic operators and comparisons at `any`, so
+//! the program calls the runtime system, which inspects the types of the operands. When a profile
+//! (see [crate::profile]) shows that both operands of such a call were `i32`s every time that the
+//! program reached it, this pass turns the call into `Speculate`. The translator generates a
+//! guarded fast path for `Speculate`: it checks the discriminants of the operands and applies the
+//! `i32` operator directly. When the check fails, it calls the runtime system, thus a wrong
+//! speculation is slow, but never incorrect, and we never have to deoptimize. Like the `i32`
+//! arithmetic in the runtime system, the fast path wraps around on overflow.
+//!
+//! The pass numbers the candidates for speculation in each function in program order, which is
+//! also how the translator numbers them when it instruments the program to produce a profile.
+//!
+//! preconditions: type_check, which ensures that the operands of a candidate are `any`s
+use super::syntax::*;
+use super::walk::*;
+use crate::profile::Profile;
+use crate::rts_function::{RTSFunction, RTSFunctionImpl};
+
+pub fn speculate(program: &mut Program, profile: &Profile) {
+    for (name, func) in program.functions.iter_mut() {
+        let mut v = Speculate {
+            func: name.to_string(),
+            profile,
+            index: 0,
+        };
+        func.body.walk(&mut v);
+    }
+}
+
+/// The `i32` operator that computes the result of `prim` when both of its operands are `i32`s,
+/// if there is one.
+fn fast_path(prim: &RTSFunction) -> Option<BinaryOp> {
+    let name = match prim.name() {
+        RTSFunctionImpl::Rust(name) => name,
+        RTSFunctionImpl::NotWasm(name) => name.to_string(),
+    };
+    match name.as_str() {
+        "jnks_plus" => Some(BinaryOp::I32Add),
+        "janky_minus" => Some(BinaryOp::I32Sub),
+        "janky_times" => Some(BinaryOp::I32Mul),
+        "any_strict_eq" | "janky_equal" => Some(BinaryOp::I32Eq),
+        "janky_strict_not_equal" | "janky_not_equal" => Some(BinaryOp::I32Ne),
+        _ => None,
+    }
+}
+
+/// Determines if `expr` is a candidate for speculation, which is a call to the runtime system
+/// with two operands that has a fast path, or a call that the pass already speculated on.
+pub fn is_candidate(expr: &Expr) -> bool {
+    match expr {
+        Expr::PrimCall(prim, args, _) => args.len() == 2 && fast_path(prim).is_some(),
+        Expr::Speculate(..) => true,
+        _ => false,
+    }
+}
+
+struct Speculate<'a> {
+    func: String,
+    profile: &'a Profile,
+    /// The number of candidates in the function so far.
+    index: usize,
+}
+
+impl Visitor for Speculate<'_> {
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        if !is_candidate(expr) {
+            return;
+        }
+        let (site, i32_site) = Profile::speculation_sites(&self.func, self.index);
+        self.index += 1;
+        let runs = self.profile.count(&site);
+        if runs == 0 || self.profile.count(&i32_site) != runs {
+            return;
+        }
+        if let Expr::PrimCall(prim, args, p) = expr {
+            let op = fast_path(prim).unwrap();
+            let speculation = Expr::Speculate(op, prim.clone(), std::mem::take(args), p.clone());
+            *expr = speculation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    /// The speculations in `main`, after speculating with `profile`.
+    fn speculations(notwasm_code: &str, profile: &str) -> Vec<BinaryOp> {
+        let mut program = parse("<test>", notwasm_code);
+        speculate(&mut program, &Profile::parse(profile).unwrap());
+        let main = program.functions.get_mut(&Id::from("main")).unwrap();
+        let mut v = Speculations(Vec::new());
+        main.body.walk(&mut v);
+        v.0
+    }
+
+    struct Speculations(Vec<BinaryOp>);
+
+    impl Visitor for Speculations {
+        fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+            if let Expr::Speculate(op, ..) = expr {
+                self.0.push(op.clone());
+            }
+        }
+    }
+
+    const PROGRAM: &str = r#"
+        import janky_minus: (any, any) -> any;
+        import any_strict_eq: (any, any) -> bool;
+        import janky_typeof: (any) -> str;
+
+        function main(): i32 {
+            var x = any(10);
+            var y = any(2);
+            var t = !janky_typeof(x);
+            var z = !janky_minus(x, y);
+            var b = !any_strict_eq(z, y);
+            return 0;
+        }
+        "#;
+
+    #[test]
+    fn speculate_on_monomorphic_operations() {
+        let ops = speculations(PROGRAM, "3 main@0\n3 main@0:i32\n3 main@1\n2 main@1:i32\n");
+        assert_eq!(ops, vec![BinaryOp::I32Sub]);
+    }
+
+    #[test]
+    fn no_speculation_without_runs() {
+        assert!(speculations(PROGRAM, "1 main\n").is_empty());
+    }
+}
@@ -0,0 +1,507 @@
+//! The NotWasm standard library, which spans several files.
+//!
+//! The root file is `stdlib.notwasm`. It and the files that it includes may start with
+//! directives, before their imports:
+//!
+//! ```text
+//! include "stdlib/uri.notwasm";   // Links another file, named by its path from the root file
+//! optional;                       // Links this file only when the program refers to it
+//! init jnks_init_uri;             // jnks_init calls this function, of type () -> i32, last
+//! ```
+//!
+//! An optional file is linked only when the rest of the program, which includes the files that
+//! are linked, refers to one of its functions or globals. Thus a program that does not use a
+//! feature of the standard library (e.g., URI encoding) does not carry its code. The compiler
+//! calls some functions of the standard library implicitly (e.g., `jnks_new_object`), so they
+//! must be in files that are not optional.
+//!
+//! Every name has one definition: two files cannot define the same function or global, or import
+//! the same function with different types. Moreover, a function or global cannot have the name
+//! of a function of the Rust runtime system, since `rt(..)` and calls to the runtime system would
+//! silently refer to the Rust function.
+
+use super::constructors::program_;
+use super::parse;
+use super::rt_bindings::get_rt_bindings;
+use super::syntax::*;
+use super::walk::*;
+use crate::rts_function::RTSFunctionImpl;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use thiserror::Error;
+
+/// The name of the root file in errors.
+pub const ROOT_NAME: &str = "stdlib.notwasm";
+
+// `STDLIB_INCLUDES`: the files that the standard library of the repository includes, by the names
+// in their `include` directives, embedded at build time (see build.rs). Builds without a file
+// system (e.g., the playground and the fuzz targets) link the standard library with these.
+include!(concat!(env!("OUT_DIR"), "/stdlib_includes.rs"));
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StdlibError {
+    #[error("{0}, line {1}: malformed directive `{2}`")]
+    BadDirective(String, usize, String),
+    #[error("{0} includes {1}, which is missing")]
+    MissingInclude(String, String),
+    #[error("{0} is defined in both {1} and {2}")]
+    Duplicate(String, String, String),
+    #[error("{0} in {1} shadows the runtime function {0}")]
+    ShadowsRuntime(String, String),
+    #[error("{0} names {1} as its init function, which must have the type () -> i32")]
+    BadInit(String, String),
+}
+
+/// The directives at the top of a file of the standard library.
+#[derive(Debug, Default, PartialEq)]
+pub struct Directives {
+    pub includes: Vec<String>,
+    pub optional: bool,
+    pub init: Option<Id>,
+}
+
+/// The directives of the file `name`, and its source with the directives blanked out, so that
+/// the positions of the rest of the file do not change.
+pub fn directives(name: &str, source: &str) -> Result<(Directives, String), StdlibError> {
+    let mut directives = Directives::default();
+    let mut body = Vec::new();
+    let mut in_header = true;
+    for (index, line) in source.split('\n').enumerate() {
+        let text = line.trim();
+        let keyword = text.split(|c: char| c.is_whitespace() || c == ';').next();
+        if in_header && matches!(keyword, Some("include") | Some("optional") | Some("init")) {
+            let bad = || StdlibError::BadDirective(name.to_string(), index + 1, text.to_string());
+            let arg = text[keyword.unwrap().len()..]
+                .trim()
+                .strip_suffix(';')
+                .ok_or_else(bad)?
+                .trim();
+            match keyword {
+                Some("include") if arg.len() > 2 && arg.starts_with('"') && arg.ends_with('"') => {
+                    directives.includes.push(arg[1..arg.len() - 1].to_string());
+                }
+                Some("optional") if arg.is_empty() => directives.optional = true,
+                Some("init") if !arg.is_empty() && directives.init.is_none() => {
+                    directives.init = Some(Id::from(arg));
+                }
+                _ => return Err(bad()),
+            }
+            body.push("");
+            continue;
+        }
+        if !text.is_empty() && !text.starts_with("//") {
+            in_header = false;
+        }
+        body.push(line);
+    }
+    Ok((directives, body.join("\n")))
+}
+
+/// Reads the files that the standard library at `root` includes, directly or indirectly, by the
+/// names in their `include` directives.
+pub fn read_includes(root: &Path) -> std::io::Result<HashMap<String, String>> {
+    let dir = root.parent().unwrap_or_else(|| Path::new(""));
+    let includes_of = |source: &str| {
+        directives("", source)
+            .map(|(directives, _)| directives.includes)
+            .unwrap_or_default()
+    };
+    let mut files = HashMap::new();
+    let mut pending = includes_of(&std::fs::read_to_string(root)?);
+    while let Some(name) = pending.pop() {
+        if !files.contains_key(&name) {
+            let source = std::fs::read_to_string(dir.join(&name))?;
+            pending.extend(includes_of(&source));
+            files.insert(name, source);
+        }
+    }
+    Ok(files)
+}
+
+/// A parsed file of the standard library.
+struct File {
+    name: String,
+    directives: Directives,
+    program: Program,
+}
+
+impl File {
+    fn defines(&self) -> impl Iterator<Item = &Id> {
+        self.program
+            .functions
+            .keys()
+            .chain(self.program.globals.keys())
+    }
+}
+
+/// Links the standard library, whose root file is `root`, into `program`. `includes` holds the
+/// other files, by the names in `include` directives (see [read_includes]).
+pub fn link(
+    program: &mut Program,
+    root: &str,
+    includes: &HashMap<String, String>,
+) -> Result<(), StdlibError> {
+    link_files(program, root, includes, false)
+}
+
+/// The standard library with every file linked, including the optional files, which is the
+/// runtime that [super::type_checking::check_runtime] checks once for every program.
+pub fn link_all(root: &str, includes: &HashMap<String, String>) -> Result<Program, StdlibError> {
+    let mut program = program_(HashMap::new());
+    link_files(&mut program, root, includes, true)?;
+    Ok(program)
+}
+
+fn link_files(
+    program: &mut Program,
+    root: &str,
+    includes: &HashMap<String, String>,
+    all: bool,
+) -> Result<(), StdlibError> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(ROOT_NAME.to_string());
+    load(ROOT_NAME, root, includes, &mut seen, &mut files)?;
+    check_names(&files)?;
+    let (mut optional, required): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| file.directives.optional && !all);
+    let mut inits = Vec::new();
+    let mut linking = required;
+    while !linking.is_empty() {
+        for file in linking {
+            if let Some(init) = file.directives.init {
+                inits.push((file.name, init));
+            }
+            program.merge_in(file.program);
+        }
+        let referenced = references(program);
+        let (used, unused): (Vec<_>, Vec<_>) = optional
+            .into_iter()
+            .partition(|file| file.defines().any(|x| referenced.contains(x)));
+        linking = used;
+        optional = unused;
+    }
+    add_inits(program, inits)
+}
+
+fn load(
+    name: &str,
+    source: &str,
+    includes: &HashMap<String, String>,
+    seen: &mut HashSet<String>,
+    files: &mut Vec<File>,
+) -> Result<(), StdlibError> {
+    let (directives, body) = directives(name, source)?;
+    let program = parse(name, body);
+    let included = directives.includes.clone();
+    files.push(File {
+        name: name.to_string(),
+        directives,
+        program,
+    });
+    for include in included {
+        if seen.insert(include.clone()) {
+            let source = includes
+                .get(&include)
+                .ok_or_else(|| StdlibError::MissingInclude(name.to_string(), include.clone()))?;
+            load(&include, source, includes, seen, files)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_names(files: &[File]) -> Result<(), StdlibError> {
+    let rust = get_rt_bindings();
+    let mut imports: HashMap<&String, (&str, &Type)> = HashMap::new();
+    for file in files {
+        for (x, t) in &file.program.rts_fn_imports {
+            match imports.insert(x, (&file.name, t)) {
+                Some((other, t2)) if t != t2 => {
+                    return Err(StdlibError::Duplicate(
+                        x.clone(),
+                        other.to_string(),
+                        file.name.clone(),
+                    ));
+                }
+                _ => (),
+            }
+        }
+    }
+    let mut defined: HashMap<&Id, &str> = HashMap::new();
+    for file in files {
+        for x in file.defines() {
+            let name = x.to_string();
+            if rust.contains_key(&name) || imports.contains_key(&name) {
+                return Err(StdlibError::ShadowsRuntime(name, file.name.clone()));
+            }
+            if let Some(other) = defined.insert(x, &file.name) {
+                return Err(StdlibError::Duplicate(
+                    name,
+                    other.to_string(),
+                    file.name.clone(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The names of the functions and globals that `program` refers to.
+fn references(program: &mut Program) -> HashSet<Id> {
+    let mut v = References::default();
+    program.walk(&mut v);
+    v.0
+}
+
+#[derive(Default)]
+struct References(HashSet<Id>);
+
+impl Visitor for References {
+    fn enter_stmt(&mut self, stmt: &mut Stmt, _loc: &Loc) {
+        if let Stmt::Assign(x, ..) | Stmt::Store(x, ..) = stmt {
+            self.0.insert(x.clone());
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut Expr, _loc: &Loc) {
+        match expr {
+            Expr::Call(f, args, _) | Expr::ClosureCall(f, args, _) => {
+                self.0.insert(f.clone());
+                self.0.extend(args.iter().cloned());
+            }
+            Expr::PrimCall(rts, args, _) | Expr::Speculate(_, rts, args, _) => {
+                if let RTSFunctionImpl::NotWasm(f) = rts.name() {
+                    self.0.insert(Id::from(f));
+                }
+                self.0.extend(args.iter().cloned());
+            }
+            Expr::AnyMethodCall(obj, _, args, _, _) => {
+                self.0.insert(obj.clone());
+                self.0.extend(args.iter().cloned());
+            }
+            Expr::Closure(f, _, _) => {
+                self.0.insert(f.clone());
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_atom(&mut self, atom: &mut Atom, _loc: &Loc) {
+        if let Atom::Id(x, _) | Atom::AnyLength(x, _, _) = atom {
+            self.0.insert(x.clone());
+        }
+    }
+}
+
+/// Calls the `init` functions of the linked files at the end of `jnks_init`, before its final
+/// `return` if it has one, in the order that they were linked. The result of each call is
+/// dropped, which is why they produce `i32`s.
+fn add_inits(program: &mut Program, inits: Vec<(String, Id)>) -> Result<(), StdlibError> {
+    let mut calls = Vec::new();
+    for (file, init) in inits {
+        match program.functions.get(&init) {
+            Some(f)
+                if f.fn_type.args.is_empty() && f.fn_type.result == Some(Box::new(Type::I32)) =>
+            {
+                let p = f.span.clone();
+                calls.push(Stmt::Expression(Expr::Call(init, Vec::new(), p.clone()), p));
+            }
+            _ => return Err(StdlibError::BadInit(file, init.to_string())),
+        }
+    }
+    if calls.is_empty() {
+        return Ok(());
+    }
+    match program.functions.get_mut(&Id::from("jnks_init")) {
+        Some(Function {
+            body: Stmt::Block(body, _),
+            ..
+        }) => {
+            let end = match body.last() {
+                Some(Stmt::Return(..)) => body.len() - 1,
+                _ => body.len(),
+            };
+            body.splice(end..end, calls);
+            Ok(())
+        }
+        _ => panic!("the standard library has init functions, but no jnks_init"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::type_checking::type_check;
+    use super::*;
+
+    fn files(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    fn link_empty(root: &str, includes: &[(&str, &str)]) -> Result<Program, StdlibError> {
+        let mut program = program_(HashMap::new());
+        link(&mut program, root, &files(includes))?;
+        Ok(program)
+    }
+
+    const ROOT: &str = r#"
+        // The directives may follow comments.
+        include "opt.notwasm";
+        function jnks_init() {
+            var x = 0;
+        }
+    "#;
+
+    const OPT: &str = r#"
+        optional;
+        init init_opt;
+        var g: i32 = 1;
+        function init_opt(): i32 {
+            g = 2;
+            return 0;
+        }
+    "#;
+
+    #[test]
+    fn directives_are_blanked_out() {
+        let (directives, body) = directives("opt.notwasm", OPT).unwrap();
+        assert_eq!(
+            directives,
+            Directives {
+                includes: vec![],
+                optional: true,
+                init: Some(Id::from("init_opt")),
+            }
+        );
+        assert_eq!(body.lines().count(), OPT.lines().count());
+        assert!(!body.contains("optional"));
+    }
+
+    #[test]
+    fn unused_optional_file_is_not_linked() {
+        let program = link_empty(ROOT, &[("opt.notwasm", OPT)]).unwrap();
+        assert!(program.functions.contains_key(&Id::from("jnks_init")));
+        assert!(!program.functions.contains_key(&Id::from("init_opt")));
+        assert!(!program.globals.contains_key(&Id::from("g")));
+    }
+
+    #[test]
+    fn used_optional_file_is_linked_and_initialized() {
+        let mut program = parse("<test>", "function main() { var y = g; }");
+        link(&mut program, ROOT, &files(&[("opt.notwasm", OPT)])).unwrap();
+        assert!(program.globals.contains_key(&Id::from("g")));
+        match &program.functions[&Id::from("jnks_init")].body {
+            Stmt::Block(body, _) => match body.last() {
+                Some(Stmt::Expression(Expr::Call(f, args, _), _)) => {
+                    assert_eq!((f, args.len()), (&Id::from("init_opt"), 0));
+                }
+                s => panic!("expected a call to init_opt, got {:?}", s),
+            },
+            _ => panic!("expected a block"),
+        }
+        type_check(&mut program).expect("type checking");
+    }
+
+    #[test]
+    fn inits_precede_return() {
+        let root = r#"
+            include "opt.notwasm";
+            function jnks_init(): i32 {
+                return 0;
+            }
+        "#;
+        let mut program = parse("<test>", "function main() { var y = g; }");
+        link(&mut program, root, &files(&[("opt.notwasm", OPT)])).unwrap();
+        match &program.functions[&Id::from("jnks_init")].body {
+            Stmt::Block(body, _) => match &body[..] {
+                [Stmt::Expression(Expr::Call(f, _, _), _), Stmt::Return(..)] => {
+                    assert_eq!(f, &Id::from("init_opt"));
+                }
+                body => panic!("expected a call to init_opt, then return, got {:?}", body),
+            },
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn link_all_links_optional_files() {
+        let program = link_all(ROOT, &files(&[("opt.notwasm", OPT)])).unwrap();
+        assert!(program.functions.contains_key(&Id::from("init_opt")));
+        assert!(program.globals.contains_key(&Id::from("g")));
+    }
+
+    #[test]
+    fn missing_include() {
+        assert_eq!(
+            link_empty(ROOT, &[]).unwrap_err(),
+            StdlibError::MissingInclude(ROOT_NAME.to_string(), "opt.notwasm".to_string())
+        );
+    }
+
+    #[test]
+    fn duplicate_definitions() {
+        let other = r#"
+            var g: i32 = 3;
+        "#;
+        let root = r#"
+            include "opt.notwasm";
+            include "other.notwasm";
+        "#;
+        assert_eq!(
+            link_empty(root, &[("opt.notwasm", OPT), ("other.notwasm", other)]).unwrap_err(),
+            StdlibError::Duplicate(
+                "g".to_string(),
+                "opt.notwasm".to_string(),
+                "other.notwasm".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn function_shadows_runtime() {
+        let root = r#"
+            include "other.notwasm";
+            import array_new: () -> Array;
+        "#;
+        let other = r#"
+            function array_new(): i32 {
+                return 0;
+            }
+        "#;
+        assert_eq!(
+            link_empty(root, &[("other.notwasm", other)]).unwrap_err(),
+            StdlibError::ShadowsRuntime("array_new".to_string(), "other.notwasm".to_string())
+        );
+    }
+
+    #[test]
+    fn malformed_directive() {
+        assert_eq!(
+            link_empty("include stdlib;", &[]).unwrap_err(),
+            StdlibError::BadDirective(ROOT_NAME.to_string(), 1, "include stdlib;".to_string())
+        );
+    }
+
+    #[test]
+    fn standard_library_type_checks() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .join(ROOT_NAME);
+        let includes = read_includes(&root).expect("reading the standard library");
+        let mut program = parse("<test>", "function main() { var e = encodeURIComponent; }");
+        link(
+            &mut program,
+            &std::fs::read_to_string(&root).unwrap(),
+            &includes,
+        )
+        .expect("linking");
+        assert!(program
+            .functions
+            .contains_key(&Id::from("jnks_encode_uri_component")));
+        type_check(&mut program).expect("type checking");
+    }
+}
@@ -301,7 +301,7 @@ impl Atom {
 }
 
 // An `Expr` is an expression that may trigger garbage collection.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     /// TODO(luna, Pos): we need to detect out-of-bounds and turn into a hashmap
     ArraySet(Atom, Atom, Atom, Pos),
@@ -312,7 +312,19 @@ pub enum Expr {
     /// then an Interned when it gets interned
     AnyMethodCall(Id, Lit, Vec<Id>, Vec<Type>, Pos),
     PrimCall(RTSFunction, Vec<Id>, Pos),
-    ObjectEmpty,
+    /// `Speculate(op, prim, args, _)` is `PrimCall(prim, args, _)`, but when both arguments are
+    /// `i32`s, it applies `op` to them directly instead of calling `prim`. The speculation pass
+    /// produces it for calls whose arguments were always `i32`s in a profile.
+    Speculate(BinaryOp, RTSFunction, Vec<Id>, Pos),
+    ObjectEmpty(Pos),
+    /// `ObjectLiteral(fields, _)` creates an object that inherits from `Object.prototype` and
+    /// has `fields`, which are empty until `ObjectSet` writes them. The translator precomputes the
+    /// class of the object, so writing the fields does not change its class.
+    ObjectLiteral(Vec<Lit>, Pos),
+    /// `ScratchObject(fields, _)` is like `ObjectLiteral(fields, _)`, but allocates the object in
+    /// the scratch region of the function, which the function frees when it returns. Escape
+    /// analysis produces it for objects that the program cannot reach after the function returns.
+    ScratchObject(Vec<Lit>, Pos),
     /// `ObjectSet(obj, field, value, _)` is `obj.field = value;`. The translator generates code
     /// that caches the offset of `field` in `obj`, thus this is not a simple library call.
     ObjectSet(Atom, Atom, Atom, Pos),
@@ -327,7 +339,28 @@ pub enum Expr {
     Closure(Id, Vec<(Atom, Type)>, Pos),
 }
 
-#[derive(Debug, PartialEq)]
+impl Expr {
+    // Every Expr must have a position. Do not create a bogus position here.
+    pub fn pos(&self) -> &Pos {
+        match self {
+            Expr::ArraySet(.., p)
+            | Expr::Call(.., p)
+            | Expr::ClosureCall(.., p)
+            | Expr::AnyMethodCall(.., p)
+            | Expr::PrimCall(.., p)
+            | Expr::Speculate(.., p)
+            | Expr::ObjectEmpty(p)
+            | Expr::ObjectLiteral(_, p)
+            | Expr::ScratchObject(_, p)
+            | Expr::ObjectSet(.., p)
+            | Expr::NewRef(.., p)
+            | Expr::Atom(.., p)
+            | Expr::Closure(.., p) => p,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct VarStmt {
     pub id: Id,
     pub named: Expr,
@@ -366,7 +399,7 @@ impl VarStmt {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
     Empty,
     /// Concrete syntax: `var <id> = <named>;`
@@ -393,21 +426,24 @@ pub enum Stmt {
 pub struct Global {
     pub is_mut: bool,
     pub ty: Type,
-    /// restricted to a const expression.
-    /// also, parity_wasm restricts it to one instruction (this is not a
-    /// wasm restriction and could theoretically be fixed).
+    /// Any atom that does not need local variables. The generated main
+    /// initializes the globals whose atom is not a constant expression.
     /// The atom is optional to allow for lazily-initialized globals in the
     /// runtime. If you don't specify a value for the global, it will be 0.
     /// Write to a lazy global before ever reading from it.
     pub atom: Option<Atom>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Function {
     pub body: Stmt,
     pub fn_type: FnType,
     pub params: Vec<Id>,
     pub span: Pos,
+    /// Concrete syntax: `nogc function ...`. The function has no shadow stack frame, which is
+    /// only safe because it cannot trigger garbage collection (see [super::no_gc]). The compiler
+    /// also sets it on the functions that it proves safe.
+    pub no_gc: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -418,6 +454,9 @@ pub struct Program {
     pub globals: HashMap<Id, Global>,
     /// no need to initialize, populated by intern
     pub data: Vec<u8>,
+    /// The offset in `data` of each interned string, populated by intern (see
+    /// [Program::intern_string])
+    pub interned: HashMap<String, u32>,
 }
 
 impl Program {
@@ -426,6 +465,7 @@ impl Program {
         self.functions.extend(other.functions.into_iter());
         self.globals.extend(other.globals.into_iter());
         assert_eq!(other.data.len(), 0, "can't merge data segments");
+        assert!(other.interned.is_empty(), "can't merge data segments");
     }
 }
 
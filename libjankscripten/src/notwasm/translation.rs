@@ -2,33 +2,57 @@
 //!
 //! preconditions: [super::compile]
 //!
-//! Much of this module relies on constants duplicated in the runtime, of
-//! course. Check the constants at the top of the file, and any comments that refer
-//! to other definitions
+//! Much of this module relies on the representation of values in the runtime,
+//! which the `abi` crate defines for both.
 
 use super::super::rts_function::*;
+use super::call_graph::{call_graph, reachable};
 use super::constructors::*;
-use super::rt_bindings::get_rt_bindings;
+use super::escape::{allocates_scratch_objects, boxes_scratch_f64s};
+use super::metadata::Metadata;
+use super::rt_bindings::{
+    check_rt_binding, get_rt_bindings, rt_function_enabled, RuntimeBindingError,
+};
+use super::speculate::is_candidate;
 use super::syntax as N;
+use super::walk::{Loc, Visitor};
 use crate::opts::Opts;
+use crate::profile::Profile;
+use abi::{
+    AnyDiscriminant, TrapReason, TypeTag, ANY_PAYLOAD_OFFSET, ANY_SIZE, ENV_FUNC_OFFSET,
+    ENV_ITEMS_OFFSET, METHOD_TABLE_LEN, METHOD_TABLE_PTRS, OBJECT_DATA_OFFSET, OBJECT_SLOTS_OFFSET,
+    OBJECT_SLOT_SIZE, STRING_LENGTH_OFFSET, TAG_SIZE, TYPE_TAG_OFFSET,
+};
 use parity_wasm::builder::*;
 use parity_wasm::elements::*;
 use parity_wasm::serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
+use thiserror::Error;
 use Instruction::*;
 
+// The instructions below read these fields with loads of these sizes (see abi::LAYOUTS).
+abi::assert_abi!(abi::size("Tag", "type_tag") == 1);
+abi::assert_abi!(abi::size("Any", "payload") == 4);
+abi::assert_abi!(abi::size("Env", "func") == 4);
+abi::assert_abi!(abi::size("Object", "data") == 4);
+abi::assert_abi!(abi::size("String", "length") == 4);
+
 const JNKS_STRINGS_IDX: u32 = 0;
-/// in bytes. i don't forsee this changing as we did a lot of work getting
-/// it to fit in the largest wasm type
-const ANY_SIZE: u32 = 8;
-/// also bytes
-const TAG_SIZE: u32 = 4;
-const LENGTH_SIZE: u32 = 4;
-const FN_OBJ_SIZE: u32 = 4;
-// Check runtime::any_value::test::abi_any_discriminants_stable. For now, (my version of) rust seems to have stable and sensible discriminants for our any representation, which is defined by rust. Then we USE these assumptions in translation for:
-// Expr::AnyMethodCall
-// Expr::AnyLength (TODO)
+/// The table of functions maps the index of each function to itself, since function values and
+/// closures hold the index of their function (see ENV_FUNC_OFFSET).
+const FUNCTIONS_TABLE: u8 = 0;
+/// The table of method stubs, whose positions are the entries of method tables (see MethodStubs).
+const METHOD_STUBS_TABLE: u8 = 1;
+/// The fake name of the global that counts how deeply calls nest, which only exists when
+/// `max_call_depth` is set.
+const CALL_DEPTH: &str = "__CALL_DEPTH";
+/// The fake name of the global that points to the frames of the calls (see runtime::call_trace),
+/// which only exists when `max_call_depth` is set.
+const CALL_TRACE: &str = "__CALL_TRACE";
+// An i32 in an any is its payload shifted left, thus the discriminant of an i32 must be zero (see
+// Expr::Speculate and operands_are_i32).
+abi::assert_abi!(AnyDiscriminant::I32 as u8 == 0);
 
 type FuncTypeMap = HashMap<(Vec<ValueType>, Option<ValueType>), u32>;
 
@@ -39,21 +63,56 @@ fn opt_valuetype_to_blocktype(t: &Option<ValueType>) -> BlockType {
     }
 }
 
-pub fn translate(opts: &Opts, program: N::Program) -> Result<Vec<u8>, Error> {
-    serialize(translate_parity(opts, program))
+#[derive(Error, Debug)]
+pub enum TranslationError {
+    #[error("{0}")]
+    RuntimeBinding(#[from] RuntimeBindingError),
+    #[error("serializing the module: {0}")]
+    Serialize(#[from] parity_wasm::elements::Error),
+}
+
+pub fn translate(opts: &Opts, mut program: N::Program) -> Result<Vec<u8>, TranslationError> {
+    // The module imports every runtime function that the features of the runtime system provide,
+    // so the runtime system must export all of them. The program must not use the others.
+    let used = rt_functions_used(&mut program);
+    for (name, ty) in get_rt_bindings().iter().chain(&program.rts_fn_imports) {
+        match check_rt_binding(name, ty, &opts.runtime_features) {
+            Err(RuntimeBindingError::Disabled(..)) if !used.contains(name) => (),
+            result => result?,
+        }
+    }
+    let metadata = Metadata::new(opts, &program);
+    let mut module = translate_parity(opts, program);
+    module.sections_mut().push(metadata.section());
+    Ok(serialize(module)?)
 }
 
 type IdEnv = im_rc::HashMap<N::Id, IdIndex>;
 
+/// The names in `map`, sorted, so that the order of functions and globals in the module does not
+/// depend on the order of the hash table.
+fn sorted_names<T>(map: &HashMap<N::Id, T>) -> Vec<N::Id> {
+    let mut names: Vec<_> = map.keys().cloned().collect();
+    names.sort_by_key(|x| x.to_string());
+    names
+}
+
 pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
-    // The initial environment maps functions names to their indices.
     let mut global_env = IdEnv::default();
-    for (index, (name, _)) in program.functions.iter().enumerate() {
-        global_env.insert(
-            name.clone(),
-            IdIndex::Fun(index.try_into().expect("too many functions")),
-        );
-    }
+    let function_names = sorted_names(&program.functions);
+    let method_arities = method_arities(&mut program);
+    // The host calls back into the program after main returns only if the program may start an
+    // asynchronous operation (see runtime/src/host.rs) or schedule a task (see
+    // runtime/src/tasks.rs).
+    let graph = call_graph(&program);
+    let reached = reachable(
+        &program,
+        &graph,
+        &[N::Id::from("jnks_init"), N::Id::from("main")],
+    );
+    let host_calls_back = ["jnks_host_await", "jnks_set_timeout"]
+        .iter()
+        .any(|f| reached.contains(&N::Id::from(*f)));
 
     let mut module = module();
     // TODO(luna): these should eventually be enumerated separately in
@@ -82,10 +141,23 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
         global_env.insert(N::Id::Named(name.into()), IdIndex::RTGlobal(index, ty));
         index += 1;
     }
-    for (name, global) in &program.globals {
+    let global_names = sorted_names(&program.globals);
+    for name in &global_names {
+        let global = &program.globals[name];
         global_env.insert(name.clone(), IdIndex::Global(index, global.ty.clone()));
         index += 1;
     }
+    // The depth counter and the frames follow the globals of the program.
+    if opts.max_call_depth.is_some() {
+        global_env.insert(
+            N::Id::Named(CALL_DEPTH.into()),
+            IdIndex::Global(index, N::Type::I32),
+        );
+        global_env.insert(
+            N::Id::Named(CALL_TRACE.into()),
+            IdIndex::Global(index + 1, N::Type::I32),
+        );
+    }
 
     // Map from function indices to original names
     let mut function_name_subsection: FunctionNameSubsection = Default::default();
@@ -94,11 +166,23 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
     let mut rt_indexes = HashMap::new();
     // build up indexes for mutual recursion first
     let mut type_indexes = HashMap::new();
-    for (func_i, (name, ty)) in rt_types
+    let mut rt_imports: Vec<_> = rt_types
         .into_iter()
         .chain(program.rts_fn_imports)
+        .filter(|(name, _)| rt_function_enabled(name, &opts.runtime_features))
+        .collect();
+    rt_imports.sort_by(|(x, _), (y, _)| x.cmp(y));
+    // The runtime functions that closures may hold, e.g., `console.log`, which `jnks_apply` calls
+    // through the dispatcher like the functions of the program.
+    let rt_closures: Vec<(u32, N::FnType)> = rt_imports
+        .iter()
         .enumerate()
-    {
+        .filter_map(|(func_i, (_, ty))| match ty {
+            N::Type::Fn(fn_ty) if dispatchable(fn_ty) => Some((func_i as u32, fn_ty.clone())),
+            _ => None,
+        })
+        .collect();
+    for (func_i, (name, ty)) in rt_imports.into_iter().enumerate() {
         let type_i = if let N::Type::Fn(fn_ty) = ty {
             let wasm_ty = (types_as_wasm(&fn_ty.args), option_as_wasm(&fn_ty.result));
             let i_check = module.push_signature(
@@ -125,6 +209,30 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
             .with_external(External::Function(type_i))
             .build();
     }
+    // The signatures of the method stubs (see MethodStubs), which no function of the program may
+    // have.
+    for arity in method_arities {
+        let (params, result) = MethodStubs::signature(arity);
+        let i_check = module.push_signature(
+            signature()
+                .with_params(params.clone())
+                .with_results(result.into_iter().collect())
+                .build_sig(),
+        );
+        assert_eq!(
+            *type_indexes.entry((params, result)).or_insert(i_check),
+            i_check
+        );
+    }
+    // The initial environment maps functions names to their indices, which follow the indices of
+    // the imported runtime functions.
+    for (index, name) in function_names.iter().enumerate() {
+        let index = rt_indexes.len() + index;
+        global_env.insert(
+            name.clone(),
+            IdIndex::Fun(index.try_into().expect("too many functions")),
+        );
+    }
     module = module
         .import()
         .path("runtime", "memory")
@@ -133,7 +241,8 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
         .build();
     // Create a WebAssembly function type for each function in NotWasm. These
     // go in the table of types (type_indexes).
-    for func in program.functions.values() {
+    for name in &function_names {
+        let func = &program.functions[name];
         // has to be wasm types to dedup properly
         let func_ty = (
             types_as_wasm(&func.fn_type.args.clone()),
@@ -142,81 +251,94 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
         let next_index = type_indexes.len() as u32;
         type_indexes.entry(func_ty).or_insert(next_index);
     }
+    // The offsets of the class descriptors of object literals in the data segment.
+    let mut object_literals = Vec::new();
+    // The offsets of the profile counters in the data segment.
+    let mut profile_counters = Vec::new();
+    // The call stubs of closures follow the generated main, whose index follows the functions of
+    // the program.
+    let num_functions = (rt_indexes.len() + function_names.len()) as u32;
+    let mut closure_stubs = ClosureStubs::new(num_functions + 1);
+    let mut method_stubs = MethodStubs::new();
+    // The initializers of the globals that the generated main initializes, since they are not
+    // constant expressions. There is one entry per global.
+    let mut global_inits = Vec::new();
     // data segment
-    for global in program.globals.values_mut() {
+    for name in &global_names {
+        let global = program.globals.get_mut(name).unwrap();
         let mut visitor = Translate::new(
             opts,
             &rt_indexes,
             &type_indexes,
             &global_env,
             &mut program.data,
+            &mut object_literals,
+            &mut profile_counters,
+            &mut closure_stubs,
+            &mut method_stubs,
         );
         if let Some(atom) = &mut global.atom {
             visitor.translate_atom(atom);
-        } else {
-            // This global var is initialized lazily. it's default value will
-            // be 0. We just need to figure out if wasm is expecting an i32 or
-            // i64.
-            let zero = match global.ty.as_wasm() {
-                // 32-bit signed integer
-                ValueType::I32 => I32Const(0),
-                // 64-bit signed integer
-                ValueType::I64 => I64Const(0),
-                // 32-bit float
-                ValueType::F32 => F32Const(0),
-                // 64-bit float
-                ValueType::F64 => F64Const(0),
-            };
-
-            visitor.out.push(zero);
+            assert!(
+                visitor.locals.is_empty(),
+                "the initializer of {} needs local variables",
+                name
+            );
         }
-        let mut insts = visitor.out;
-        assert_eq!(
-            insts.len(),
-            1,
-            "
-            parity_wasm unneccessarily restricts init_expr to len=1,
-            so we're dealing with that for now i guess"
-        );
-        let restricted = insts.pop().unwrap();
-        let mut partial_global = module
-            .global()
-            .with_type(global.ty.as_wasm())
-            .init_expr(restricted);
-        if global.is_mut {
-            partial_global = partial_global.mutable();
+        // The builder of parity_wasm restricts initializers to one instruction, so we build the
+        // entry ourselves. When the initializer is not a constant expression, the global starts
+        // at zero, and the generated main sets it, which requires the global to be mutable.
+        let mut init = visitor.out;
+        let mut is_mut = global.is_mut;
+        if is_constant(&init) {
+            global_inits.push(None);
+        } else if init.is_empty() {
+            // This global var is initialized lazily.
+            global_inits.push(None);
+            init = vec![zero(global.ty.as_wasm())];
+        } else {
+            is_mut = true;
+            global_inits.push(Some(init));
+            init = vec![zero(global.ty.as_wasm())];
         }
-        module = partial_global.build();
+        init.push(End);
+        let global_type = GlobalType::new(global.ty.as_wasm(), is_mut);
+        module = module.with_global(GlobalEntry::new(global_type, InitExpr::new(init)));
     }
-    // fsr we need an identity table to call indirect
-    let num_runtime_functions = rt_indexes.len();
-    let num_functions = num_runtime_functions + program.functions.keys().len();
-    let mut table_build = module.table().with_min(num_functions as u32);
-    for index in 0..num_functions {
-        let index = index as u32;
-        table_build = table_build.with_element(index, vec![index]);
+    if opts.max_call_depth.is_some() {
+        for _ in &[CALL_DEPTH, CALL_TRACE] {
+            let global_type = GlobalType::new(ValueType::I32, true);
+            module = module.with_global(GlobalEntry::new(
+                global_type,
+                InitExpr::new(vec![I32Const(0), End]),
+            ));
+        }
     }
-    let mut module = table_build.build();
 
     // For each function index, a map from local variable indices to original names.
     let mut local_name_subsection: LocalNameSubsection = Default::default();
 
-    for (func_name, func) in program.functions.iter_mut() {
+    for func_name in &function_names {
+        let func = program.functions.get_mut(func_name).unwrap();
         let (f, local_map) = translate_func(
             opts,
+            func_name,
             func,
             &global_env,
             &rt_indexes,
             &type_indexes,
             &mut program.data,
+            &mut object_literals,
+            &mut profile_counters,
+            &mut closure_stubs,
+            &mut method_stubs,
         );
-        let loc = module.push_function(f);
-
-        // It is surprising that we have to do this arithmetic ourselves. It looks like loc.body
-        // does not account for the indices of the imported functions, which offset the indices
-        // of all functions in this module.
-        let offset: u32 = num_runtime_functions.try_into().expect("overflow");
-        let actual_function_index = loc.body + offset;
+        // We push the functions in the order of their indices.
+        module.push_function(f);
+        let actual_function_index = match global_env[func_name] {
+            IdIndex::Fun(index) => index,
+            _ => panic!("{} is not a function", func_name),
+        };
         function_name_subsection
             .names_mut()
             .insert(actual_function_index, func_name.to_string());
@@ -224,15 +346,83 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
             .local_names_mut()
             .insert(actual_function_index, local_map);
     }
+    let closures: Vec<_> = rt_closures
+        .iter()
+        .map(|(index, fn_ty)| (*index, fn_ty))
+        .chain(function_names.iter().filter_map(|name| {
+            let fn_ty = &program.functions[name].fn_type;
+            match &global_env[name] {
+                IdIndex::Fun(index) if dispatchable(fn_ty) => Some((*index, fn_ty)),
+                _ => None,
+            }
+        }))
+        .collect();
+    let mut dispatcher = Translate::new(
+        opts,
+        &rt_indexes,
+        &type_indexes,
+        &global_env,
+        &mut program.data,
+        &mut object_literals,
+        &mut profile_counters,
+        &mut closure_stubs,
+        &mut method_stubs,
+    );
+    dispatcher.closure_dispatcher(&closures, num_functions);
+    let dispatcher = dispatcher.out;
+    let object_literals_table = offsets_table(&object_literals, &mut program.data);
+    let profile_table = offsets_table(&profile_counters, &mut program.data);
+    let call_trace_table = match opts.max_call_depth {
+        Some(_) => names_table(&function_names, &mut program.data),
+        None => None,
+    };
+    let globals: Vec<_> = global_names.iter().map(|x| &program.globals[x]).collect();
+    // Translating the functions and the dispatcher generated all the method stubs.
+    let num_method_stubs = method_stubs.stubs.len() as u32;
     insert_generated_main(
         opts,
-        &program.globals,
+        &globals,
+        &global_inits,
         &global_env,
         &rt_indexes,
         rt_globals_len,
+        object_literals_table,
+        profile_table,
+        call_trace_table,
+        host_calls_back,
+        num_functions,
+        num_method_stubs,
         &mut module,
     );
-    let main_index = num_functions as u32;
+    let main_index = num_functions;
+    let method_stubs_base = closure_stubs.base + closure_stubs.types.len() as u32;
+    closure_stubs.insert(&type_indexes, &mut function_name_subsection, &mut module);
+    let method_stub_indices = method_stubs.insert(
+        method_stubs_base,
+        &mut function_name_subsection,
+        &mut module,
+    );
+    // The dispatcher follows the method stubs.
+    let dispatcher_index = method_stubs_base + method_stub_indices.len() as u32;
+    function_name_subsection
+        .names_mut()
+        .insert(dispatcher_index, "call_closure".to_string());
+    module.push_function(
+        function()
+            .signature()
+            .with_params(vec![ValueType::I32; 3])
+            .with_results(vec![ValueType::I64])
+            .build()
+            .body()
+            .with_instructions(Instructions::new(dispatcher))
+            .build()
+            .build(),
+    );
+    // The generated main fills the tables from the passive element segment (see init_tables).
+    let segment: Vec<u32> = (0..num_functions).chain(method_stub_indices).collect();
+    let module = module
+        .with_table(TableType::new(num_functions, Some(num_functions)))
+        .with_table(TableType::new(num_method_stubs, Some(num_method_stubs)));
     let module = module
         .data()
         .offset(GetGlobal(JNKS_STRINGS_IDX))
@@ -246,24 +436,76 @@ pub fn translate_parity(opts: &Opts, mut program: N::Program) -> Module {
     )));
 
     // jnks_init calls main
-    let module = module
+    let mut module = module
         .export()
         .field("main")
         .internal()
         .func(main_index)
         .build();
-    module.build()
+    // The runtime system calls closures through the host (see runtime::closure::call_closure).
+    module = module
+        .export()
+        .field("call_closure")
+        .internal()
+        .func(dispatcher_index)
+        .build();
+    // The host calls back into the program after main returns, when an asynchronous operation
+    // completes (see runtime/src/host.rs) and to run the tasks of setTimeout (see
+    // runtime/src/tasks.rs).
+    let callbacks = [
+        ("resume", "jnks_resume"),
+        ("run_microtasks", "jnks_run_microtasks"),
+    ];
+    for (export, name) in callbacks.iter() {
+        if let Some(IdIndex::Fun(func)) = global_env.get(&N::Id::Named(name.to_string())) {
+            module = module.export().field(export).internal().func(*func).build();
+        }
+    }
+    for name in &opts.exports {
+        let func = match global_env.get(&N::Id::Named(name.clone())) {
+            Some(IdIndex::Fun(func)) => *func,
+            _ => panic!("cannot export {}, which is not a function", name),
+        };
+        module = module.export().field(name).internal().func(func).build();
+    }
+    let mut module = module.build();
+    let code = module
+        .sections()
+        .iter()
+        .position(|section| matches!(section, Section::Code(_)))
+        .expect("module without code");
+    module
+        .sections_mut()
+        .insert(code, passive_element_section(&segment));
+    module
 }
 
 fn translate_func(
     opts: &Opts,
+    name: &N::Id,
     func: &mut N::Function,
     id_env: &IdEnv,
     rt_indexes: &HashMap<String, u32>,
     type_indexes: &FuncTypeMap,
     data: &mut Vec<u8>,
+    object_literals: &mut Vec<u32>,
+    profile_counters: &mut Vec<u32>,
+    closure_stubs: &mut ClosureStubs,
+    method_stubs: &mut MethodStubs,
 ) -> (FunctionDefinition, IndexMap<String>) {
-    let mut translator = Translate::new(opts, rt_indexes, type_indexes, id_env, data);
+    let mut translator = Translate::new(
+        opts,
+        rt_indexes,
+        type_indexes,
+        id_env,
+        data,
+        object_literals,
+        profile_counters,
+        closure_stubs,
+        method_stubs,
+    );
+    translator.func_name = name.to_string();
+    translator.shadow_frame = !opts.disable_gc && !func.no_gc;
 
     // Add indices for parameters
     for (arg_name, arg_typ) in func.params.iter().zip(func.fn_type.args.iter()) {
@@ -274,14 +516,73 @@ fn translate_func(
             .insert(arg_name.clone(), IdIndex::Local(index, arg_typ.clone()));
     }
 
+    // A function that allocates objects in the scratch region saves the top of the region in a
+    // local, and restores it when it returns.
+    if allocates_scratch_objects(&mut func.body) {
+        let index = translator.next_id;
+        translator.next_id += 1;
+        translator.locals.push(ValueType::I32);
+        translator.scratch_mark = Some(index);
+    }
+    // Likewise for the f64 scratch area.
+    if boxes_scratch_f64s(&mut func.body) {
+        let index = translator.next_id;
+        translator.next_id += 1;
+        translator.locals.push(ValueType::I32);
+        translator.f64_scratch_mark = Some(index);
+    }
+
     let mut env = Env::default();
     env.result_type = func.fn_type.result.as_ref().map(|x| x.as_wasm());
+    env.labels.push_back(TranslateLabel::Epilogue);
 
-    // generate the actual code
+    // Count the calls to the function, after the prologue below.
+    if opts.profile_gen {
+        translator.profile_counter(&name.to_string());
+    }
+
+    // The body is a block that produces the result, and every return statement breaks out of it
+    // to the epilogue below. Thus every path out of the function runs the same epilogue, which
+    // undoes the prologue.
+    translator
+        .out
+        .push(Block(opt_valuetype_to_blocktype(&env.result_type)));
     translator.translate_rec(&mut env, true, &mut func.body);
+    translator.out.push(End);
     let mut insts = vec![];
 
-    if opts.disable_gc == false {
+    // Count the nested calls, and stop the program before it exhausts the stack of the engine,
+    // which it cannot recover from.
+    if let Some((depth, limit)) = translator.call_depth() {
+        insts.push(GetGlobal(depth));
+        insts.push(I32Const(1));
+        insts.push(I32Add);
+        insts.push(SetGlobal(depth));
+        // Record the number of the function in the frame of its depth, so that the runtime system
+        // can report the calls that exceed the limit.
+        let number = match id_env[name] {
+            IdIndex::Fun(index) => index - rt_indexes.len() as u32,
+            _ => panic!("{} is not a function", name),
+        };
+        insts.push(GetGlobal(translator.call_trace()));
+        insts.push(GetGlobal(depth));
+        insts.push(I32Const(2));
+        insts.push(I32Shl);
+        insts.push(I32Add);
+        insts.push(I32Const(number as i32));
+        insts.push(I32Store(2, 0));
+        insts.push(GetGlobal(depth));
+        insts.push(I32Const(limit as i32));
+        insts.push(I32GtU);
+        insts.push(If(BlockType::NoResult));
+        insts.push(GetGlobal(depth));
+        insts.push(Call(
+            *rt_indexes.get("stack_overflow").expect("no overflow"),
+        ));
+        insts.push(End);
+    }
+
+    if translator.shadow_frame {
         // Eager shadow stack: The runtime system needs to create a shadow stack
         // frame that has enough slots for the local variables.
         let num_slots = translator.locals.len() + func.params.len();
@@ -289,9 +590,25 @@ fn translate_func(
         insts.push(Call(*rt_indexes.get("gc_enter_fn").expect("no enter")));
     }
 
-    if opts.disable_gc == false {
+    if let Some(mark) = translator.scratch_mark {
+        insts.push(Call(*rt_indexes.get("scratch_enter").expect("no scratch")));
+        insts.push(SetLocal(mark));
+    }
+    if let Some(mark) = translator.f64_scratch_mark {
+        insts.push(Call(
+            *rt_indexes.get("f64_scratch_enter").expect("no f64 scratch"),
+        ));
+        insts.push(SetLocal(mark));
+    }
+
+    // The epilogue undoes the prologue in reverse order. The result of the function stays on the
+    // stack, below the arguments of these calls.
+    translator.f64_scratch_exit();
+    translator.scratch_exit();
+    if translator.shadow_frame {
         translator.rt_call("gc_exit_fn");
     }
+    translator.call_depth_exit();
     insts.append(&mut translator.out);
 
     insts.push(End);
@@ -322,6 +639,14 @@ fn translate_func(
     (func, local_map)
 }
 
+/// Produces `true` if the dispatcher calls the closures that hold functions of type `fn_ty`, which
+/// receive an environment. The dispatcher cannot convert an any to a function, so it does not call
+/// functions that receive functions.
+fn dispatchable(fn_ty: &N::FnType) -> bool {
+    let receives_fn = fn_ty.args.iter().any(|t| matches!(t, N::Type::Fn(..)));
+    matches!(fn_ty.args.first(), Some(N::Type::Env)) && !receives_fn
+}
+
 fn types_as_wasm(types: &[N::Type]) -> Vec<ValueType> {
     types.iter().map(N::Type::as_wasm).collect()
 }
@@ -343,6 +668,30 @@ struct Translate<'a> {
     rt_indexes: &'a HashMap<String, u32>,
     type_indexes: &'a FuncTypeMap,
     data: &'a mut Vec<u8>,
+    /// The offsets of the class descriptors of object literals in `data`.
+    object_literals: &'a mut Vec<u32>,
+    /// The local that holds the top of the scratch region when the function starts, if the
+    /// function allocates objects in the scratch region.
+    scratch_mark: Option<u32>,
+    /// The local that holds the top of the f64 scratch area when the function starts, if the
+    /// function boxes f64s in the scratch area.
+    f64_scratch_mark: Option<u32>,
+    /// The offsets of the profile counters in `data`, when `opts.profile_gen` or
+    /// `opts.trace_coercions` is set.
+    profile_counters: &'a mut Vec<u32>,
+    closure_stubs: &'a mut ClosureStubs,
+    method_stubs: &'a mut MethodStubs,
+    /// Whether the function has a shadow stack frame, which it does unless GC is disabled or the
+    /// function is `nogc`.
+    shadow_frame: bool,
+    /// The name of the function, which names its profile counters.
+    func_name: String,
+    /// The number of inline caches in the function so far.
+    num_caches: usize,
+    /// The number of candidates for speculation in the function so far.
+    num_speculations: usize,
+    /// The number of coercions to and from any in the function so far.
+    num_coercions: usize,
     locals: Vec<ValueType>,
     next_id: u32,
     id_env: IdEnv,
@@ -362,17 +711,21 @@ struct Env {
 /// To compile a `break l` statement to WebAssembly, we scan the labels for
 /// the index of `TranslateLabel::Label(l)`. When the compiler introduces an
 /// unnamed WebAssembly block, it pushes a `TranslateLabel::Unused` onto the
-/// `LabelEnv`, which ensures that indices shift correctly.
+/// `LabelEnv`, which ensures that indices shift correctly. The outermost label,
+/// `TranslateLabel::Epilogue`, is the block of the body of the function, which
+/// return statements break out of.
 #[derive(Clone, PartialEq, Debug)]
 enum TranslateLabel {
     Unused,
     Label(N::Label),
+    Epilogue,
 }
 
 /// We use `IdIndex` to resolve identifiers that appear in a NotWasm program
 /// while compiling to WebAssembly. Before compiling the body of a function, we
 /// populate an `IdEnv` to map each function name `f` to its index `n`
-/// (`IdIndex::Fun(n)`).
+/// (`IdIndex::Fun(n)`). The index is in the WebAssembly module, whose function index space
+/// starts with the imported runtime functions.
 #[derive(Clone, PartialEq, Debug)]
 enum IdIndex {
     Local(u32, N::Type),
@@ -390,6 +743,10 @@ impl<'a> Translate<'a> {
         type_indexes: &'a FuncTypeMap,
         id_env: &IdEnv,
         data: &'a mut Vec<u8>,
+        object_literals: &'a mut Vec<u32>,
+        profile_counters: &'a mut Vec<u32>,
+        closure_stubs: &'a mut ClosureStubs,
+        method_stubs: &'a mut MethodStubs,
     ) -> Self {
         Self {
             opts,
@@ -400,6 +757,17 @@ impl<'a> Translate<'a> {
             id_env: id_env.clone(),
             locals: Vec::new(),
             data,
+            object_literals,
+            scratch_mark: None,
+            f64_scratch_mark: None,
+            profile_counters,
+            closure_stubs,
+            method_stubs,
+            shadow_frame: !opts.disable_gc,
+            func_name: String::new(),
+            num_caches: 0,
+            num_speculations: 0,
+            num_coercions: 0,
         }
     }
 
@@ -440,8 +808,17 @@ impl<'a> Translate<'a> {
                     return; // TODO(arjun): This happens
                 }
                 let last_index = ss.len() - 1;
+                // The object literal that the previous statements created and initialized.
+                let mut literal = None;
                 for (index, s) in ss.iter_mut().enumerate() {
+                    if let Some((obj, fields)) = &literal {
+                        if let Some(slot) = literal_slot(obj, fields, s) {
+                            self.init_literal_slot(s, slot);
+                            continue;
+                        }
+                    }
                     self.translate_rec(env, tail_position && index == last_index, s);
+                    literal = new_literal(s);
                 }
             }
             N::Stmt::Var(var_stmt, _) => {
@@ -472,7 +849,7 @@ impl<'a> Translate<'a> {
 
                 // Eager shadow stack: (and setting the actual value)
                 if !is_init {
-                    if self.opts.disable_gc == true || var_stmt.ty().is_gc_root() == false {
+                    if !self.shadow_frame || var_stmt.ty().is_gc_root() == false {
                         self.out.push(SetLocal(index));
                     } else {
                         self.out.push(TeeLocal(index));
@@ -494,7 +871,7 @@ impl<'a> Translate<'a> {
                 {
                     IdIndex::Local(n, ty) => {
                         self.translate_expr(expr);
-                        if self.opts.disable_gc == true || ty.is_gc_root() == false {
+                        if !self.shadow_frame || ty.is_gc_root() == false {
                             self.out.push(SetLocal(n));
                         } else {
                             self.out.push(TeeLocal(n));
@@ -569,14 +946,18 @@ impl<'a> Translate<'a> {
                 self.out.push(Br(i as u32));
             }
             N::Stmt::Return(atom, _) => {
-                if self.opts.disable_gc == false {
-                    self.rt_call("gc_exit_fn");
-                }
+                // The atom runs in the shadow frame, which keeps the locals alive if it allocates
+                // (e.g., to box an f64). The result cannot be a scratch object, but it may read
+                // the fields of one, so the epilogue restores the scratch region afterwards.
                 self.translate_atom(atom);
-                self.out.push(Return);
+                let i = env
+                    .labels
+                    .index_of(&TranslateLabel::Epilogue)
+                    .expect("return outside of a function");
+                self.out.push(Br(i as u32));
             }
             N::Stmt::Trap => {
-                self.out.push(Unreachable);
+                self.trap(TrapReason::Trap);
             }
             N::Stmt::Goto(..) => {
                 panic!(
@@ -636,6 +1017,11 @@ impl<'a> Translate<'a> {
     }
 
     fn translate_expr(&mut self, expr: &mut N::Expr) {
+        if self.opts.profile_gen && is_candidate(expr) {
+            if let N::Expr::PrimCall(_, args, _) | N::Expr::Speculate(_, _, args, _) = expr {
+                self.speculation_counters(args);
+            }
+        }
         match expr {
             N::Expr::Atom(atom, _) => self.translate_atom(atom),
             N::Expr::ArraySet(arr, index, value, _) => {
@@ -651,7 +1037,7 @@ impl<'a> Translate<'a> {
                 self.data_cache();
                 self.rt_call("object_set");
             }
-            N::Expr::ObjectEmpty => {
+            N::Expr::ObjectEmpty(_) => {
                 // New objects like `{}` or `new Object()` are created using
                 // the runtime function `jnks_new_object`, which is located in
                 // `runtime.notwasm`. We have to find the function index of
@@ -663,34 +1049,77 @@ impl<'a> Translate<'a> {
                 // must be resolved dynamically.
                 self.notwasm_rt_call("jnks_new_object");
             }
+            N::Expr::ObjectLiteral(fields, _) => {
+                // Like `{}`, an object literal inherits from the default Object prototype.
+                // However, instead of starting with the empty class and transitioning once for
+                // each field, the object starts with a class that has all of its fields. The
+                // runtime system creates these classes when the program starts, using the class
+                // descriptors that we write into the data segment.
+                self.object_literal_descriptor(fields);
+                self.get_id(&N::Id::from("jnks_objectPrototype"));
+                self.rt_call("object_literal");
+            }
+            N::Expr::ScratchObject(fields, _) => {
+                // Escape analysis ensures that the object does not outlive the function, so its
+                // header is in the scratch region instead of the heap. The descriptor of an empty
+                // object has the empty class.
+                self.object_literal_descriptor(fields);
+                self.get_id(&N::Id::from("jnks_objectPrototype"));
+                self.rt_call("scratch_object_literal");
+            }
             N::Expr::PrimCall(rts_func, args, _) => {
                 for arg in args {
                     self.get_id(arg);
                 }
-
-                let name = rts_func.name();
-
-                // Runtime functions can either be implemented in the
-                // Rust runtime or the NotWasm runtime.
-                match name {
-                    RTSFunctionImpl::Rust(name) => {
-                        self.rt_call(name.as_str());
-                    }
-                    RTSFunctionImpl::NotWasm(name) => {
-                        self.notwasm_rt_call(name);
+                match rts_func.name() {
+                    // The length of a string is the field that follows its tag.
+                    RTSFunctionImpl::Rust(name) if name == "string_length" => {
+                        self.out.push(I32Load(2, STRING_LENGTH_OFFSET))
                     }
+                    _ => self.prim_call(rts_func),
                 }
             }
+            // This is using assumptions from the runtime. See abi::AnyDiscriminant.
+            N::Expr::Speculate(op, rts_func, args, _) => {
+                // The operator produces an i32, which we box, or a bool, which is the result of
+                // the runtime function too.
+                let (_, result_ty) = op.notwasm_typ(false);
+                let boxed = result_ty == N::Type::I32;
+                let block_ty = if boxed {
+                    ValueType::I64
+                } else {
+                    ValueType::I32
+                };
+                self.operands_are_i32(args);
+                self.out.push(If(BlockType::Value(block_ty)));
+                for arg in args {
+                    // The payload of an i32 is the most significant 32 bits of the any.
+                    self.get_id(arg);
+                    self.out.push(I64Const(32));
+                    self.out.push(I64ShrU);
+                    self.out.push(I32WrapI64);
+                }
+                self.translate_binop(op);
+                if boxed {
+                    // The discriminant of an i32 is 0.
+                    self.out.push(I64ExtendUI32);
+                    self.out.push(I64Const(32));
+                    self.out.push(I64Shl);
+                }
+                self.out.push(Else);
+                for arg in args {
+                    self.get_id(arg);
+                }
+                self.prim_call(rts_func);
+                self.out.push(End);
+            }
             N::Expr::Call(f, args, s) => {
                 for arg in args {
                     self.get_id(arg);
                 }
                 match self.id_env.get(f).cloned() {
                     Some(IdIndex::Fun(i)) => {
-                        // we index in notwasm by 0 = first user function. but
-                        // wasm indexes by 0 = first rt function. so we have
-                        // to offset
-                        self.out.push(Call(i + self.rt_indexes.len() as u32));
+                        self.out.push(Call(i));
                     }
                     Some(IdIndex::Local(i, t)) => {
                         self.out.push(GetLocal(i));
@@ -704,7 +1133,7 @@ impl<'a> Translate<'a> {
                             .type_indexes
                             .get(&(params_tys, ret_ty))
                             .unwrap_or_else(|| panic!("function type was not indexed {:?}", s));
-                        self.out.push(CallIndirect(*ty_index, 0));
+                        self.out.push(CallIndirect(*ty_index, FUNCTIONS_TABLE));
                     }
                     Some(index) => panic!(
                         "can't translate Func ID for function ({}): ({:?})",
@@ -713,30 +1142,25 @@ impl<'a> Translate<'a> {
                     _ => panic!("expected Func ID ({})", f),
                 };
             }
-            // This is using assumptions from the runtime. See
-            // runtime::any_value::test::abi_any_discriminants_stable
             N::Expr::AnyMethodCall(any, method_lit, args, typs, s) => {
                 self.translate_any_method(any, method_lit, args, typs, s, true)
             }
             N::Expr::ClosureCall(f, args, s) => {
                 let t = self.get_id(f).unwrap();
-                self.rt_call("closure_env");
                 let typs = match t {
                     N::Type::Closure(fn_ty) => {
                         (types_as_wasm(&fn_ty.args), option_as_wasm(&fn_ty.result))
                     }
                     _ => panic!("identifier {:?} is not function-typed", f),
                 };
-                let ty_index = self
-                    .type_indexes
-                    .get(&typs)
-                    .unwrap_or_else(|| panic!("function type was not indexed {:?}", s));
+                if !self.type_indexes.contains_key(&typs) {
+                    panic!("function type was not indexed {:?}", s);
+                }
                 for arg in args {
                     self.get_id(arg);
                 }
-                self.get_id(f);
-                self.rt_call("closure_func");
-                self.out.push(CallIndirect(*ty_index, 0));
+                let stub = self.closure_stubs.index(typs);
+                self.out.push(Call(stub));
             }
             N::Expr::NewRef(a, ty, _) => {
                 self.translate_atom(a);
@@ -810,11 +1234,24 @@ impl<'a> Translate<'a> {
                     panic!("cannot find rt {}", id);
                 }
             }
-            N::Atom::ToAny(to_any, _) => {
+            N::Atom::ToAny(to_any, p) => {
+                if self.opts.trace_coercions {
+                    self.coercion_counter(&format!("{} -> any", to_any.ty()), p);
+                }
                 self.translate_atom(&mut to_any.atom);
                 self.to_any(to_any.ty());
             }
-            N::Atom::FromAny(a, ty, _) => {
+            N::Atom::FromAny(a, ty, p) => {
+                // Unboxing a value that was just boxed produces the value, so we do neither.
+                if let N::Atom::ToAny(to_any, _) = &mut **a {
+                    if *to_any.ty() == *ty {
+                        self.translate_atom(&mut to_any.atom);
+                        return;
+                    }
+                }
+                if self.opts.trace_coercions {
+                    self.coercion_counter(&format!("any -> {}", ty), p);
+                }
                 self.translate_atom(a);
                 self.from_any(ty);
             }
@@ -832,26 +1269,7 @@ impl<'a> Translate<'a> {
                 self.data_cache();
                 self.rt_call("object_get");
             }
-            N::Atom::AnyLength(any, method_lit, s) => {
-                let possible_typs = vec![
-                    N::Type::Fn(N::FnType {
-                        args: vec![N::Type::String],
-                        result: Some(Box::new(N::Type::I32)),
-                    }),
-                    N::Type::Fn(N::FnType {
-                        args: vec![N::Type::Array],
-                        result: Some(Box::new(N::Type::I32)),
-                    }),
-                ];
-                self.translate_any_method(
-                    any,
-                    method_lit,
-                    &vec![any.clone()],
-                    &possible_typs,
-                    s,
-                    false,
-                )
-            }
+            N::Atom::AnyLength(any, method_lit, s) => self.translate_any_length(any, method_lit, s),
             N::Atom::Binary(op, a, b, _) => {
                 self.translate_atom(a);
                 self.translate_atom(b);
@@ -867,31 +1285,16 @@ impl<'a> Translate<'a> {
             N::Atom::EnvGet(index, ty, _) => {
                 // get the env which is always the first argument
                 self.out.push(GetLocal(0));
-                let offset = TAG_SIZE + LENGTH_SIZE + FN_OBJ_SIZE + *index * ANY_SIZE;
+                let offset = ENV_ITEMS_OFFSET + *index * ANY_SIZE;
                 // as an optimization, we can avoid calling the coercion
                 // functions in the runtime since we know the type already
-                if let N::Type::Closure(_) = ty {
-                    // the closure is the only "special" type in an any: it is
-                    // stored is the most significant 48 bits of the AnyValue,
-                    // however, the closure is supposed to be stored in the
-                    // *least* significant 48 bits. one might load with an offset
-                    // of 2 and only load 48 bits, but it's not supported by
-                    // wasm. or, you could load the full 64 bits and let
-                    // the garbage be the padding. except, that is memory
-                    // unsafe. it could be tried as an optimization, but this will
-                    // work for now
+                if ty.as_wasm() == ValueType::I64 {
                     self.out.push(I64Load(2, offset));
-                    self.out.push(I64Const(16));
-                    self.out.push(I64ShrU);
                 } else {
-                    if ty.as_wasm() == ValueType::I64 {
-                        self.out.push(I64Load(2, offset));
-                    } else {
-                        // anything else is stored as the most significant 32 bits
-                        // of the AnyValue. Note That Because Of Little Endian
-                        // Byte Order This Means It's The Last Bytes
-                        self.out.push(I32Load(2, offset + 4));
-                    }
+                    // anything else is stored as the most significant 32 bits
+                    // of the AnyValue. Note That Because Of Little Endian
+                    // Byte Order This Means It's The Last Bytes
+                    self.out.push(I32Load(2, offset + ANY_PAYLOAD_OFFSET));
                 }
             }
         }
@@ -912,174 +1315,297 @@ impl<'a> Translate<'a> {
         self.out.push(Drop);
     }
 
-    fn translate_any_method(
-        &mut self,
-        any: &N::Id,
-        method_lit: &N::Lit,
-        args: &Vec<N::Id>,
-        typs: &Vec<N::Type>,
-        s: &N::Pos,
-        do_call: bool,
-    ) {
-        let method = if let N::Lit::Interned(m, _) = &method_lit {
-            m
-        } else {
-            panic!("method field should be interned string");
-        };
-        // We need a local to store our result because br_table doesn't
-        // support results properly (design overlook???)
-        // i checked and this is how rustc/llvm does it; we *need*
-        // a local. TODO(luna): re-use this local somehow? (or just
-        // leave it to wasm-opt)
-        let index = self.next_id;
-        self.next_id += 1;
-        self.locals.push(ValueType::I64);
-
-        // Once we figure out our type, if it's not Object, it's a
-        // simple call that looks the same for everyone
-        let typed_call = |s: &mut Self, typ| {
-            // When this isn't a type at all, we simply put nothing
-            // here. It won't be jumped to (if code is correct)
-            if let Some(t) = typs.iter().find(|t| t.unwrap_fun().0[0] == typ) {
-                let (arg_typs, result_typ) = t.unwrap_fun();
-                for (arg, typ) in args.iter().zip(arg_typs.iter()) {
-                    s.get_id(arg);
-                    s.from_any(typ);
-                }
-                let func_name = format!("{}_{}", typ, method);
-                s.rt_call(func_name.as_str());
-                // We could use unwrap because jankyscript doesn't have void
-                if let Some(result_typ) = result_typ {
-                    s.to_any(result_typ);
-                    s.out.push(SetLocal(index));
-                }
-            }
-        };
-
-        // TODO(luna): Call me crazy, but we could probably skip
-        // checking the any. All the types that have methods are heap
-        // types(right?) so we can just skip right to load pointer and
-        // check tag. At the least, when all the types in `typs` are,
-        // we could do this
-
-        // Each block breaks to this block to exit
-        self.out.push(Block(BlockType::NoResult));
-        self.out.push(Block(BlockType::NoResult)); // 4
-        self.out.push(Block(BlockType::NoResult)); // 3
-        self.out.push(Block(BlockType::NoResult)); // 2
-        self.out.push(Block(BlockType::NoResult)); // 1
-        self.out.push(Block(BlockType::NoResult)); // 0
-
-        // Get our object and look at the discriminant
+    /// Gets the length of an any. Strings and arrays, which are the common case, do not go
+    /// through the method table: we read the discriminant of the any and the tag of the heap
+    /// value directly, which is cheaper than caching them. The result is an any.
+    fn translate_any_length(&mut self, any: &N::Id, method_lit: &N::Lit, s: &N::Pos) {
+        self.out.push(Block(BlockType::Value(ValueType::I64))); // the result
+        self.out.push(Block(BlockType::NoResult)); // anything else
+        self.out.push(Block(BlockType::NoResult)); // Array
+        self.out.push(Block(BlockType::NoResult)); // String
+
+        // The tag of the heap value, if the any is a pointer. Otherwise, 0xff, which is not a
+        // tag.
         self.get_id(any);
-        // Assume that the discriminant is in the lowest
-        // two bytes. This IS tested in our ABI test
         self.out.push(I32WrapI64);
         self.out.push(I32Const(0x00ff));
         self.out.push(I32And);
+        self.out.push(I32Const(AnyDiscriminant::Ptr as i32));
+        self.out.push(I32Eq);
+        self.out.push(If(BlockType::Value(ValueType::I32)));
+        self.any_payload(any);
+        self.out.push(I32Load8U(0, TYPE_TAG_OFFSET));
+        self.out.push(Else);
+        self.out.push(I32Const(0x00ff));
+        self.out.push(End);
+        let mut table = vec![2; TypeTag::Extension as usize + 1];
+        table[TypeTag::String as usize] = 0;
+        table[TypeTag::Array as usize] = 1;
         self.out.push(BrTable(Box::new(BrTableData {
-            table: Box::new([0, 1, 2, 3, 4]),
-            // default should never be followed if all goes well. i
-            // could put a trap in there or i could just give it UB
-            default: 0,
+            table: table.into_boxed_slice(),
+            default: 2,
         })));
         self.out.push(End);
-        // I32 0
-        // For now i'm just going to put what i think the discriminant
-        // should be to make sure i'm generating code right
-        self.out.push(I64Const(0));
-        self.out.push(SetLocal(index));
-        self.out.push(Br(4));
-        self.out.push(End);
-        // F64 1
-        self.out.push(I64Const(1));
-        self.out.push(SetLocal(index));
-        self.out.push(Br(3));
-        self.out.push(End);
-        // Bool 2
-        self.out.push(I64Const(2));
-        self.out.push(SetLocal(index));
+        // String
+        self.any_payload(any);
+        self.out.push(I32Load(2, STRING_LENGTH_OFFSET));
+        self.rt_call("any_from_i32");
         self.out.push(Br(2));
         self.out.push(End);
-        // Ptr 3
-        self.translate_pointer_method(any, method_lit, args, s, index, typed_call, do_call);
+        // Array
+        self.any_payload(any);
+        self.rt_call("array_length");
+        self.rt_call("any_from_i32");
+        self.out.push(Br(1));
         self.out.push(End);
-        // Closure 4
-        self.out.push(I64Const(4));
-        self.out.push(SetLocal(index));
-        // Would be br(0) but that happens automatically
+        // Anything else, which is probably an object with a length field
+        let possible_typs = vec![
+            N::Type::Fn(N::FnType {
+                args: vec![N::Type::String],
+                result: Some(Box::new(N::Type::I32)),
+            }),
+            N::Type::Fn(N::FnType {
+                args: vec![N::Type::Array],
+                result: Some(Box::new(N::Type::I32)),
+            }),
+        ];
+        self.translate_any_method(
+            any,
+            method_lit,
+            &vec![any.clone()],
+            &possible_typs,
+            s,
+            false,
+        );
         self.out.push(End);
-        // we ignore Undefined 5
-        // we ignore Null 6
-        // This is where we exit to with our any result in the
-        // local. Simply get it
-        self.out.push(GetLocal(index));
     }
 
-    /// do_call represents whether to call the field as a function when any
-    /// is object (you probably want this unless this is Length
-    fn translate_pointer_method(
+    /// Pushes the payload of `any`, which is in its most significant 32 bits (e.g., the pointer
+    /// when `any` holds a pointer).
+    fn any_payload(&mut self, any: &N::Id) {
+        self.get_id(any);
+        self.out.push(I64Const(32));
+        self.out.push(I64ShrU);
+        self.out.push(I32WrapI64);
+    }
+
+    /// Calls the method through its method table (see MethodStubs). The result is an any.
+    fn translate_any_method(
         &mut self,
         any: &N::Id,
         method_lit: &N::Lit,
         args: &Vec<N::Id>,
+        typs: &Vec<N::Type>,
         s: &N::Pos,
-        index: u32,
-        typed_call: impl Fn(&mut Self, N::Type),
         do_call: bool,
     ) {
-        // So now we need to go look on the heap and use THAT tag to
-        // decide what type we REALLY are. Was this even a good decision?
-        // You can check these values at
-        // runtime::allocator::heap_values::TypeTag
-        // They are way more consistent than the any discriminant
-        // because i was able to specify them in rust
-        // Array = 0
-        // String = 1
-        // HT(still not used by JankyScript!) = 2
-        // Object = 3
-        // We don't need an outer block to break to because we're already in a block!
-        self.out.push(Block(BlockType::NoResult)); // 3
-        self.out.push(Block(BlockType::NoResult)); // 2
-        self.out.push(Block(BlockType::NoResult)); // 1
-        self.out.push(Block(BlockType::NoResult)); // 0
-
-        // Get the pointer
+        let table = self.method_table(method_lit, args.len(), typs, s, do_call);
         self.get_id(any);
-        self.out.push(I64Const(4 * 8));
-        self.out.push(I64ShrU);
-        self.out.push(I32WrapI64);
-        // Now load up the actual tag
-        // The actual tag is the *second* byte, after the marked bool
-        // Note that parity_wasm uses the arguments to load in the
-        // opposite order of the spec (here: alignment, offset)
-        self.out.push(I32Load8U(0, 1));
-        // And break
+        for arg in args {
+            self.get_id(arg);
+        }
+        // The runtime system looks at the discriminant of the any, and at the tag of the heap
+        // value when it is a pointer, to pick the entry of the table.
+        self.out.push(GetGlobal(JNKS_STRINGS_IDX));
+        self.get_id(any);
+        self.rt_call("any_method_kind");
+        self.out.push(I32Const(2));
+        self.out.push(I32Shl);
+        self.out.push(I32Add);
+        self.out.push(I32Load(2, table));
+        let ty_index = self.type_indexes[&MethodStubs::signature(args.len())];
+        self.out.push(CallIndirect(ty_index, METHOD_STUBS_TABLE));
+    }
+
+    /// The offset in the data segment of the method table for calls to `method_lit` with `arity`
+    /// arguments, which generates the table and its stubs on the first call.
+    fn method_table(
+        &mut self,
+        method_lit: &N::Lit,
+        arity: usize,
+        typs: &Vec<N::Type>,
+        s: &N::Pos,
+        do_call: bool,
+    ) -> u32 {
+        let key = MethodCall {
+            method: method_lit.clone(),
+            arity,
+            typs: typs.clone(),
+            do_call,
+        };
+        if let Some((_, table)) = self.method_stubs.tables.iter().find(|(k, _)| k == &key) {
+            return *table;
+        }
+        let method = if let N::Lit::Interned(m, _) = &method_lit {
+            m
+        } else {
+            panic!("method field should be interned string");
+        };
+        // A receiver that has no such method traps, including an HT (still not used by
+        // JankyScript!)
+        let missing = self.method_stubs.missing.iter().find(|(n, _)| *n == arity);
+        let missing = match missing.map(|(_, stub)| *stub) {
+            Some(stub) => stub,
+            None => {
+                let stub =
+                    self.method_stub(format!("method_missing_{}", arity), arity, |t, _, _| {
+                        t.trap(TrapReason::MethodMissing);
+                    });
+                self.method_stubs.missing.push((arity, stub));
+                stub
+            }
+        };
+        let mut entries = vec![missing; METHOD_TABLE_LEN as usize];
+        for (typ, tag) in [
+            (N::Type::Array, TypeTag::Array),
+            (N::Type::String, TypeTag::String),
+        ]
+        .iter()
+        {
+            // When this isn't a type at all, the receiver has no such method
+            if let Some(t) = typs.iter().find(|t| &t.unwrap_fun().0[0] == typ) {
+                let (arg_typs, result_typ) = t.unwrap_fun();
+                let func_name = format!("{}_{}", typ, method);
+                let stub =
+                    self.method_stub(format!("method_{}", func_name), arity, |t, _, args| {
+                        for (arg, typ) in args.iter().zip(arg_typs.iter()) {
+                            t.get_id(arg);
+                            t.from_any(typ);
+                        }
+                        t.rt_call(func_name.as_str());
+                        // We could use unwrap because jankyscript doesn't have void
+                        match result_typ {
+                            Some(result_typ) => t.to_any(result_typ),
+                            None => t.out.push(I64Const(0)),
+                        }
+                    });
+                entries[(METHOD_TABLE_PTRS + *tag as u32) as usize] = stub;
+            }
+        }
+        // The methods of a closure are the fields of its function object,
+        // which any_to_ptr produces
+        let object = self.method_stub(
+            format!("method_object_{}", method),
+            arity,
+            |t, any, args| t.translate_object_method(any, method_lit, &args.to_vec(), s, do_call),
+        );
+        entries[(METHOD_TABLE_PTRS + TypeTag::ObjectPtrPtr as u32) as usize] = object;
+        entries[AnyDiscriminant::Closure as usize] = object;
+
+        // The program loads the entries, so we align the table.
+        while self.data.len() % 4 != 0 {
+            self.data.push(0);
+        }
+        let table = self.data.len() as u32;
+        for entry in entries {
+            self.data.extend(&entry.to_le_bytes());
+        }
+        self.method_stubs.tables.push((key, table));
+        table
+    }
+
+    /// Generates a method stub with `arity` arguments and returns its index in the table of
+    /// method stubs. The stub has no shadow stack frame, since its caller holds its arguments.
+    /// `body` generates the body of the stub with a new translator, given the ids of the receiver
+    /// and the arguments, and must produce an any.
+    fn method_stub(
+        &mut self,
+        name: String,
+        arity: usize,
+        body: impl FnOnce(&mut Translate, &N::Id, &[N::Id]),
+    ) -> u32 {
+        let mut stub = Translate::new(
+            self.opts,
+            self.rt_indexes,
+            self.type_indexes,
+            &self.id_env,
+            self.data,
+            self.object_literals,
+            self.profile_counters,
+            self.closure_stubs,
+            self.method_stubs,
+        );
+        stub.func_name = name.clone();
+        stub.shadow_frame = false;
+        let any = N::Id::Named("%receiver".to_string());
+        let args: Vec<_> = (0..arity)
+            .map(|i| N::Id::Named(format!("%arg{}", i)))
+            .collect();
+        for id in std::iter::once(&any).chain(args.iter()) {
+            let index = stub.next_id;
+            stub.next_id += 1;
+            stub.id_env
+                .insert(id.clone(), IdIndex::Local(index, N::Type::Any));
+        }
+        body(&mut stub, &any, &args);
+        stub.out.push(End);
+        let insts = stub.out;
+        let locals = stub.locals;
+        self.method_stubs.push(name, arity, insts, locals)
+    }
+
+    /// Generates the body of the dispatcher that the runtime system calls closures with (see
+    /// runtime::closure::call_closure). It receives the closure, a pointer to its arguments (an
+    /// array of anys), and their number, and calls the function of the closure, which must be
+    /// one of `closures`. Like JavaScript, it passes undefined for missing arguments and ignores
+    /// extra arguments. There is one case for each type of function, which converts the arguments
+    /// from anys and the result to an any.
+    fn closure_dispatcher(&mut self, closures: &[(u32, &N::FnType)], num_functions: u32) {
+        let mut cases: Vec<(&N::FnType, Vec<u32>)> = Vec::new();
+        for (index, fn_ty) in closures {
+            match cases.iter_mut().find(|(ty, _)| ty == fn_ty) {
+                Some((_, indices)) => indices.push(*index),
+                None => cases.push((*fn_ty, vec![*index])),
+            }
+        }
+        // Any other function index traps.
+        let trap = cases.len() as u32;
+        for _ in 0..=trap {
+            self.out.push(Block(BlockType::NoResult));
+        }
+        let mut table = vec![trap; num_functions as usize];
+        for (case, (_, indices)) in cases.iter().enumerate() {
+            for index in indices {
+                table[*index as usize] = case as u32;
+            }
+        }
+        self.out.push(GetLocal(0));
+        self.out.push(I32Load(2, ENV_FUNC_OFFSET));
         self.out.push(BrTable(Box::new(BrTableData {
-            table: Box::new([0, 1, 2, 3]),
-            // Again, default is just UB
-            default: 0,
+            table: table.into_boxed_slice(),
+            default: trap,
         })));
         self.out.push(End);
-        // Array, 0
-        typed_call(self, N::Type::Array);
-        self.out.push(Br(4));
-        self.out.push(End);
-        // String, 1
-        typed_call(self, N::Type::String);
-        self.out.push(Br(3));
-        self.out.push(End);
-        // HT, 2
-        // TODO(luna)
-        // blah blah blah HT stuff
-        self.out.push(Br(2));
+        for (fn_ty, _) in cases {
+            // The closure is the environment, which is the first argument.
+            self.out.push(GetLocal(0));
+            for (i, ty) in fn_ty.args.iter().enumerate().skip(1) {
+                let i = i as u32 - 1;
+                self.out.push(GetLocal(2));
+                self.out.push(I32Const(i as i32));
+                self.out.push(I32GtU);
+                self.out.push(If(BlockType::Value(ValueType::I64)));
+                self.out.push(GetLocal(1));
+                self.out.push(I64Load(3, i * ANY_SIZE));
+                self.out.push(Else);
+                self.rt_call("get_undefined");
+                self.out.push(End);
+                self.from_any(ty);
+            }
+            let ty = (types_as_wasm(&fn_ty.args), option_as_wasm(&fn_ty.result));
+            let ty_index = self.type_indexes[&ty];
+            self.out.push(GetLocal(0));
+            self.out.push(I32Load(2, ENV_FUNC_OFFSET));
+            self.out.push(CallIndirect(ty_index, FUNCTIONS_TABLE));
+            match &fn_ty.result {
+                Some(ty) => self.to_any(ty),
+                None => self.rt_call("get_undefined"),
+            }
+            self.out.push(Return);
+            self.out.push(End);
+        }
+        self.trap(TrapReason::BadClosure);
         self.out.push(End);
-        // Object, 3
-        self.translate_object_method(any, method_lit, args, s, do_call);
-        self.out.push(SetLocal(index));
-        // No need for an outer block because we are already in an outer block
-        // We break 1 here which means breaking all the way out to GetLocal
-        self.out.push(Br(1));
     }
 
     /// The result of the call (an any) is now on the stack
@@ -1107,7 +1633,7 @@ impl<'a> Translate<'a> {
             // take care of this for us)
             let cl_call_idx = self.next_id;
             self.next_id += 1;
-            self.locals.push(ValueType::I64);
+            self.locals.push(closure_type.as_wasm());
             // I don't want to bring in namegen. This id can be
             // re-used. So, i'm just picking something. I do need an ID to
             // make the object case easy to write as a desugaring
@@ -1154,7 +1680,89 @@ impl<'a> Translate<'a> {
         }
     }
 
+    /// Frees the scratch objects of the function, if it has any.
+    fn scratch_exit(&mut self) {
+        if let Some(mark) = self.scratch_mark {
+            self.out.push(GetLocal(mark));
+            self.rt_call("scratch_exit");
+        }
+    }
+
+    /// Frees the scratch f64s of the function, if it has any.
+    fn f64_scratch_exit(&mut self) {
+        if let Some(mark) = self.f64_scratch_mark {
+            self.out.push(GetLocal(mark));
+            self.rt_call("f64_scratch_exit");
+        }
+    }
+
+    /// The index of the global that counts nested calls and the maximum depth of calls, when the
+    /// program limits the depth of calls.
+    fn call_depth(&self) -> Option<(u32, u32)> {
+        let limit = self.opts.max_call_depth?;
+        match self.id_env.get(&N::Id::Named(CALL_DEPTH.into())) {
+            Some(IdIndex::Global(index, _)) => Some((*index, limit)),
+            _ => panic!("no global for the call depth"),
+        }
+    }
+
+    /// The index of the global that points to the frames of the calls, when the program limits
+    /// the depth of calls.
+    fn call_trace(&self) -> u32 {
+        match self.id_env.get(&N::Id::Named(CALL_TRACE.into())) {
+            Some(IdIndex::Global(index, _)) => *index,
+            _ => panic!("no global for the frames of the calls"),
+        }
+    }
+
+    /// Leaves a call, when the program limits the depth of calls.
+    fn call_depth_exit(&mut self) {
+        if let Some((depth, _)) = self.call_depth() {
+            self.out.push(GetGlobal(depth));
+            self.out.push(I32Const(1));
+            self.out.push(I32Sub);
+            self.out.push(SetGlobal(depth));
+        }
+    }
+
+    /// Generate instructions to call a runtime function, which can either be implemented in the
+    /// Rust runtime or the NotWasm runtime.
+    fn prim_call(&mut self, rts_func: &RTSFunction) {
+        match rts_func.name() {
+            RTSFunctionImpl::Rust(name) => {
+                self.rt_call(name.as_str());
+            }
+            RTSFunctionImpl::NotWasm(name) => {
+                self.notwasm_rt_call(name);
+            }
+        }
+    }
+
+    /// Generates instructions that produce true if all `args`, which are `any`s, hold `i32`s,
+    /// whose discriminant is 0. The discriminant is in the least significant byte of an any.
+    fn operands_are_i32(&mut self, args: &[N::Id]) {
+        for (i, arg) in args.iter().enumerate() {
+            self.get_id(arg);
+            self.out.push(I32WrapI64);
+            self.out.push(I32Const(0x00ff));
+            self.out.push(I32And);
+            if i > 0 {
+                self.out.push(I32Or);
+            }
+        }
+        self.out.push(I32Eqz);
+    }
+
     /// Generate instructions to call a *Rust* runtime function.
+    /// Generates a trap, which records `reason` first in checked builds.
+    fn trap(&mut self, reason: TrapReason) {
+        if self.opts.checked {
+            self.out.push(I32Const(reason as i32));
+            self.rt_call("trap_set_reason");
+        }
+        self.out.push(Unreachable);
+    }
+
     fn rt_call(&mut self, name: &str) {
         if let Some(i) = self.rt_indexes.get(name) {
             self.out.push(Call(*i));
@@ -1167,7 +1775,7 @@ impl<'a> Translate<'a> {
     /// function index if its found.
     fn get_notwasm_rt_fn(&mut self, name: &str) -> Option<u32> {
         if let Some(IdIndex::Fun(func)) = self.id_env.get(&N::Id::Named(name.to_string())) {
-            Some(*func + self.rt_indexes.len() as u32)
+            Some(*func)
         } else {
             None
         }
@@ -1225,10 +1833,8 @@ impl<'a> Translate<'a> {
                 self.load(&ty, 0);
                 Some(ty)
             }
-            // notwasm indexes from our functions, wasm indexes from rt
             IdIndex::Fun(n) => {
-                self.out
-                    .push(I32Const(*n as i32 + self.rt_indexes.len() as i32));
+                self.out.push(I32Const(*n as i32));
                 None
             }
         }
@@ -1240,6 +1846,11 @@ impl<'a> Translate<'a> {
     /// 2. extends the inline cache to include a unique cache spot for these
     ///    generated object field lookup instructions.
     fn data_cache(&mut self) {
+        if self.opts.profile_gen {
+            let site = Profile::cache_site(&self.func_name, self.num_caches);
+            self.profile_counter(&site);
+        }
+        self.num_caches += 1;
         // the end of the data segment is the new cache
         self.out.push(GetGlobal(JNKS_STRINGS_IDX));
         self.out.push(I32Const(self.data.len() as i32));
@@ -1248,21 +1859,481 @@ impl<'a> Translate<'a> {
         self.data
             .extend(&unsafe { std::mem::transmute::<_, [u8; 4]>((-1i32).to_le()) });
     }
+
+    /// Writes the class descriptor of an object literal into the data segment and generates
+    /// instructions to push its address onto the stack. A descriptor is a slot for the class
+    /// tag, which the runtime system fills in when the program starts, followed by the number of
+    /// fields and the address of the interned name of each field (relative to the data segment).
+    fn object_literal_descriptor(&mut self, fields: &[N::Lit]) {
+        let offset = self.data.len() as u32;
+        self.object_literals.push(offset);
+        self.out.push(GetGlobal(JNKS_STRINGS_IDX));
+        self.out.push(I32Const(offset as i32));
+        self.out.push(I32Add);
+        // -1 is our placeholder, as in `data_cache`
+        self.data.extend(&(-1i32).to_le_bytes());
+        self.data.extend(&(fields.len() as u32).to_le_bytes());
+        for field in fields {
+            match field {
+                N::Lit::Interned(_, addr) => self.data.extend(&addr.to_le_bytes()),
+                _ => panic!("uninterned field name in object literal"),
+            }
+        }
+    }
+
+    /// Translates `obj.field = value;`, where `obj` is an object literal and `field` is at `slot`
+    /// in its class. The object has no other fields yet, so the slot is known, and we store the
+    /// value directly instead of calling object_set.
+    fn init_literal_slot(&mut self, stmt: &mut N::Stmt, slot: u32) {
+        let (obj, value) = match stmt {
+            N::Stmt::Expression(N::Expr::ObjectSet(obj, _, value, _), _) => (obj, value),
+            _ => panic!("expected the initialization of a field of an object literal"),
+        };
+        self.translate_atom(obj);
+        self.out.push(I32Load(2, OBJECT_DATA_OFFSET));
+        self.translate_atom(value);
+        self.out
+            .push(I64Store(3, OBJECT_SLOTS_OFFSET + slot * OBJECT_SLOT_SIZE));
+    }
+
+    /// Generates instructions that count the runs of a candidate for speculation with operands
+    /// `args`, and the runs where all of them are `i32`s.
+    fn speculation_counters(&mut self, args: &[N::Id]) {
+        let (site, i32_site) = Profile::speculation_sites(&self.func_name, self.num_speculations);
+        self.num_speculations += 1;
+        self.profile_counter(&site);
+        self.operands_are_i32(args);
+        self.out.push(If(BlockType::NoResult));
+        self.profile_counter(&i32_site);
+        self.out.push(End);
+    }
+
+    /// Generates instructions that count the runs of the coercion at `p`, which `coercion`
+    /// describes.
+    fn coercion_counter(&mut self, coercion: &str, p: &N::Pos) {
+        let description = format!("{} at {}", coercion, p);
+        let site = Profile::coercion_site(&self.func_name, self.num_coercions, &description);
+        self.num_coercions += 1;
+        self.profile_counter(&site);
+    }
+
+    /// Writes a profile counter for `site` into the data segment and generates instructions that
+    /// increment it. A counter is the count, followed by the length and the bytes of the name of
+    /// the site.
+    fn profile_counter(&mut self, site: &str) {
+        // The program loads and stores the count, so we align it.
+        while self.data.len() % 4 != 0 {
+            self.data.push(0);
+        }
+        let offset = self.data.len() as u32;
+        self.profile_counters.push(offset);
+        self.data.extend(&0u32.to_le_bytes());
+        self.data.extend(&(site.len() as u32).to_le_bytes());
+        self.data.extend(site.as_bytes());
+        self.out.push(GetGlobal(JNKS_STRINGS_IDX));
+        self.out.push(GetGlobal(JNKS_STRINGS_IDX));
+        self.out.push(I32Load(2, offset));
+        self.out.push(I32Const(1));
+        self.out.push(I32Add);
+        self.out.push(I32Store(2, offset));
+    }
+}
+
+/// The object literal and its fields, if `stmt` creates one (e.g., `var obj = {x, y};`).
+fn new_literal(stmt: &N::Stmt) -> Option<(N::Id, Vec<N::Lit>)> {
+    match stmt {
+        N::Stmt::Var(var_stmt, _) => match &var_stmt.named {
+            N::Expr::ObjectLiteral(fields, _) | N::Expr::ScratchObject(fields, _) => {
+                Some((var_stmt.id.clone(), fields.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The slot of the field that `stmt` writes, if it writes a field of the object literal `obj`.
+/// Slot 0 holds the prototype, so the fields of the literal follow it.
+fn literal_slot(obj: &N::Id, fields: &[N::Lit], stmt: &N::Stmt) -> Option<u32> {
+    match stmt {
+        N::Stmt::Expression(
+            N::Expr::ObjectSet(
+                N::Atom::Id(x, _),
+                N::Atom::Lit(N::Lit::Interned(_, field), _),
+                _,
+                _,
+            ),
+            _,
+        ) if x == obj => {
+            let index = fields.iter().position(|lit| match lit {
+                N::Lit::Interned(_, addr) => addr == field,
+                _ => false,
+            })?;
+            Some(index as u32 + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Writes a table of offsets in the data segment (e.g., of class descriptors), which is the
+/// number of offsets followed by the offsets, into the data segment. Produces the offset of the
+/// table, unless there are no offsets.
+fn offsets_table(offsets: &[u32], data: &mut Vec<u8>) -> Option<u32> {
+    if offsets.is_empty() {
+        return None;
+    }
+    let offset = data.len() as u32;
+    data.extend(&(offsets.len() as u32).to_le_bytes());
+    for entry in offsets {
+        data.extend(&entry.to_le_bytes());
+    }
+    Some(offset)
+}
+
+/// Writes the names of `functions` into the data segment, followed by the table of their offsets,
+/// which is the same as [offsets_table]. A name is its length and its bytes.
+fn names_table(functions: &[N::Id], data: &mut Vec<u8>) -> Option<u32> {
+    let mut offsets = Vec::new();
+    for name in functions {
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        let name = name.to_string();
+        offsets.push(data.len() as u32);
+        data.extend(&(name.len() as u32).to_le_bytes());
+        data.extend(name.as_bytes());
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    offsets_table(&offsets, data)
+}
+
+/// Each call to a closure calls a stub that takes the closure and the arguments, and calls the
+/// function of the closure with its environment and the arguments. There is one stub for each
+/// type of closure that the program calls. A closure is a pointer to its environment, which also
+/// holds the index of the function, so the stub passes the closure as the environment and loads
+/// the function directly, which relies on the layout of environments in the runtime system (see
+/// runtime::closure::test::closure_abi_stable).
+struct ClosureStubs {
+    /// The index of the first stub.
+    base: u32,
+    /// The types of the functions that the stubs call, in the order of the stubs.
+    types: Vec<(Vec<ValueType>, Option<ValueType>)>,
+}
+
+impl ClosureStubs {
+    fn new(base: u32) -> Self {
+        ClosureStubs {
+            base,
+            types: Vec::new(),
+        }
+    }
+
+    /// The index of the stub that calls closures whose function has type `ty`.
+    fn index(&mut self, ty: (Vec<ValueType>, Option<ValueType>)) -> u32 {
+        let i = match self.types.iter().position(|other| other == &ty) {
+            Some(i) => i,
+            None => {
+                self.types.push(ty);
+                self.types.len() - 1
+            }
+        };
+        self.base + i as u32
+    }
+
+    /// Adds the stubs to `module`. They must follow the generated main.
+    fn insert(
+        self,
+        type_indexes: &FuncTypeMap,
+        function_names: &mut FunctionNameSubsection,
+        module: &mut ModuleBuilder,
+    ) {
+        for (i, (args, result)) in self.types.into_iter().enumerate() {
+            let ty_index = type_indexes[&(args.clone(), result.clone())];
+            // The closure is the environment, which is the first argument.
+            let mut insts: Vec<_> = (0..args.len() as u32).map(GetLocal).collect();
+            insts.push(GetLocal(0));
+            insts.push(I32Load(2, ENV_FUNC_OFFSET));
+            insts.push(CallIndirect(ty_index, FUNCTIONS_TABLE));
+            insts.push(End);
+            function_names
+                .names_mut()
+                .insert(self.base + i as u32, format!("closure_call_{}", ty_index));
+            module.push_function(
+                function()
+                    .signature()
+                    .with_params(args)
+                    .with_results(result.into_iter().collect())
+                    .build()
+                    .body()
+                    .with_instructions(Instructions::new(insts))
+                    .build()
+                    .build(),
+            );
+        }
+    }
+}
+
+/// Each call to a method of an `any` calls a stub through a method table in the data segment,
+/// which holds the index in the table of method stubs of the stub for each kind of receiver (see
+/// abi::METHOD_TABLE_LEN). The runtime system computes the index of the entry from the receiver.
+/// The stubs of calls with `n` arguments take the receiver and the arguments, and produce an any,
+/// thus a single call_indirect calls any of them. There is one table for each method, arity, and
+/// set of types, which all calls to them share, along with its stubs (and the inline cache of the
+/// stub for objects).
+struct MethodStubs {
+    /// The names and definitions of the stubs, in the order of their indices.
+    stubs: Vec<(String, FunctionDefinition)>,
+    /// The offset of the method table in the data segment of each kind of call.
+    tables: Vec<(MethodCall, u32)>,
+    /// The index of the stub that traps for each arity, since the receiver has no such method.
+    missing: Vec<(usize, u32)>,
+}
+
+#[derive(PartialEq)]
+struct MethodCall {
+    method: N::Lit,
+    arity: usize,
+    typs: Vec<N::Type>,
+    do_call: bool,
+}
+
+impl MethodStubs {
+    fn new() -> Self {
+        MethodStubs {
+            stubs: Vec::new(),
+            tables: Vec::new(),
+            missing: Vec::new(),
+        }
+    }
+
+    /// The type of the stubs of calls with `arity` arguments.
+    fn signature(arity: usize) -> (Vec<ValueType>, Option<ValueType>) {
+        (vec![ValueType::I64; arity + 1], Some(ValueType::I64))
+    }
+
+    /// Adds a stub and returns its index in the table of method stubs.
+    fn push(
+        &mut self,
+        name: String,
+        arity: usize,
+        insts: Vec<Instruction>,
+        locals: Vec<ValueType>,
+    ) -> u32 {
+        let (params, result) = Self::signature(arity);
+        let func = function()
+            .signature()
+            .with_params(params)
+            .with_results(result.into_iter().collect())
+            .build()
+            .body()
+            .with_instructions(Instructions::new(insts))
+            .with_locals(locals.into_iter().map(|t| Local::new(1, t)).collect())
+            .build()
+            .build();
+        self.stubs.push((name, func));
+        self.stubs.len() as u32 - 1
+    }
+
+    /// Adds the stubs to `module`, starting at function index `base`, and returns their indices in
+    /// order. They must follow the closure stubs.
+    fn insert(
+        self,
+        base: u32,
+        function_names: &mut FunctionNameSubsection,
+        module: &mut ModuleBuilder,
+    ) -> Vec<u32> {
+        let mut indices = Vec::new();
+        for (i, (name, func)) in self.stubs.into_iter().enumerate() {
+            let index = base + i as u32;
+            function_names.names_mut().insert(index, name);
+            module.push_function(func);
+            indices.push(index);
+        }
+        indices
+    }
+}
+
+/// The numbers of arguments of the calls to methods of `any`s in `program`.
+fn method_arities(program: &mut N::Program) -> BTreeSet<usize> {
+    let mut v = MethodArities(BTreeSet::new());
+    program.walk(&mut v);
+    v.0
+}
+
+struct MethodArities(BTreeSet<usize>);
+
+/// The names of the functions of the runtime system that `program` calls or refers to.
+fn rt_functions_used(program: &mut N::Program) -> HashSet<String> {
+    let mut v = RTFunctionsUsed(HashSet::new());
+    program.walk(&mut v);
+    v.0
+}
+
+struct RTFunctionsUsed(HashSet<String>);
+
+impl Visitor for RTFunctionsUsed {
+    fn enter_expr(&mut self, expr: &mut N::Expr, _loc: &Loc) {
+        if let N::Expr::PrimCall(rts, ..) | N::Expr::Speculate(_, rts, ..) = expr {
+            if let RTSFunctionImpl::Rust(name) = rts.name() {
+                self.0.insert(name);
+            }
+        }
+    }
+
+    fn enter_atom(&mut self, atom: &mut N::Atom, _loc: &Loc) {
+        if let N::Atom::PrimApp(id, ..) | N::Atom::GetPrimFunc(id, _) = atom {
+            self.0.insert(id.name().to_string());
+        }
+    }
+}
+
+impl Visitor for MethodArities {
+    fn enter_expr(&mut self, expr: &mut N::Expr, _loc: &Loc) {
+        if let N::Expr::AnyMethodCall(_, _, args, _, _) = expr {
+            self.0.insert(args.len());
+        }
+    }
+
+    fn enter_atom(&mut self, atom: &mut N::Atom, _loc: &Loc) {
+        if let N::Atom::AnyLength(..) = atom {
+            self.0.insert(1);
+        }
+    }
+}
+
+/// Determines if `insts` is a constant expression, which may initialize a global.
+fn is_constant(insts: &[Instruction]) -> bool {
+    match insts {
+        [I32Const(_)] | [I64Const(_)] | [F32Const(_)] | [F64Const(_)] => true,
+        // The imported globals are immutable.
+        [GetGlobal(JNKS_STRINGS_IDX)] => true,
+        _ => false,
+    }
+}
+
+/// The zero of `ty`, which is the initial value of a global that is initialized later.
+fn zero(ty: ValueType) -> Instruction {
+    match ty {
+        ValueType::I32 => I32Const(0),
+        ValueType::I64 => I64Const(0),
+        ValueType::F32 => F32Const(0),
+        ValueType::F64 => F64Const(0),
+    }
+}
+
+/// The element section with one passive segment of the functions `segment`. parity_wasm writes
+/// passive segments without their kind of element, which engines reject, so we encode the section
+/// ourselves.
+fn passive_element_section(segment: &[u32]) -> Section {
+    let mut payload = Vec::new();
+    // One segment, which is passive (flags 1) and holds functions (kind 0)
+    VarUint32::from(1u32).serialize(&mut payload).unwrap();
+    VarUint32::from(1u32).serialize(&mut payload).unwrap();
+    payload.push(0);
+    VarUint32::from(segment.len())
+        .serialize(&mut payload)
+        .unwrap();
+    for func in segment {
+        VarUint32::from(*func).serialize(&mut payload).unwrap();
+    }
+    // An unparsed section holds its length too.
+    let mut section = Vec::new();
+    VarUint32::from(payload.len())
+        .serialize(&mut section)
+        .unwrap();
+    section.extend(payload);
+    Section::Unparsed {
+        id: 9,
+        payload: section,
+    }
+}
+
+/// Fills the tables from the passive element segment, which holds the `num_functions` functions
+/// followed by the `num_method_stubs` method stubs, and drops the segment.
+///
+/// parity_wasm writes the index of the segment of `table.init` as zero, and the operand as the
+/// index of the table, thus `TableInit(table)` fills `table` from the first segment, which is why
+/// there is a single segment.
+fn init_tables(num_functions: u32, num_method_stubs: u32) -> Vec<Instruction> {
+    let tables = [
+        (FUNCTIONS_TABLE, 0, num_functions),
+        (METHOD_STUBS_TABLE, num_functions, num_method_stubs),
+    ];
+    let mut insts = Vec::new();
+    for (table, start, len) in tables.iter() {
+        insts.push(I32Const(0));
+        insts.push(I32Const(*start as i32));
+        insts.push(I32Const(*len as i32));
+        insts.push(Bulk(BulkInstruction::TableInit(*table as u32)));
+    }
+    insts.push(Bulk(BulkInstruction::TableDrop(0)));
+    insts
 }
 
 fn insert_generated_main(
     opts: &Opts,
-    globals: &HashMap<N::Id, N::Global>,
+    globals: &[&N::Global],
+    global_inits: &[Option<Vec<Instruction>>],
     global_env: &IdEnv,
     rt_indexes: &HashMap<String, u32>,
     rt_globals_len: usize,
+    object_literals_table: Option<u32>,
+    profile_table: Option<u32>,
+    call_trace_table: Option<u32>,
+    host_calls_back: bool,
+    num_functions: u32,
+    num_method_stubs: u32,
     module: &mut ModuleBuilder,
 ) {
     // the true entry point is generated code to avoid GC instrumentation
     // messiness
     let mut insts = Vec::new();
+    // Before anything calls a closure or a method
+    insts.extend(init_tables(num_functions, num_method_stubs));
     // rust init function
     insts.push(Call(*rt_indexes.get("init").expect("no enter")));
+    // The classes of object literals must exist before any code creates objects.
+    if let Some(table) = object_literals_table {
+        insts.push(GetGlobal(JNKS_STRINGS_IDX));
+        insts.push(GetGlobal(JNKS_STRINGS_IDX));
+        insts.push(I32Const(table as i32));
+        insts.push(I32Add);
+        insts.push(Call(
+            *rt_indexes
+                .get("object_literal_classes_init")
+                .expect("no object_literal_classes_init"),
+        ));
+    }
+    // The runtime system needs the profile counters to serialize them after the run.
+    if let Some(table) = profile_table {
+        insts.push(GetGlobal(JNKS_STRINGS_IDX));
+        insts.push(GetGlobal(JNKS_STRINGS_IDX));
+        insts.push(I32Const(table as i32));
+        insts.push(I32Add);
+        insts.push(Call(
+            *rt_indexes.get("profile_init").expect("no profile_init"),
+        ));
+    }
+    // The runtime system reports the calls that exceed the maximum depth by the names of their
+    // functions, and it holds the frames that the functions record.
+    if let (Some(table), Some(limit)) = (call_trace_table, opts.max_call_depth) {
+        insts.push(GetGlobal(JNKS_STRINGS_IDX));
+        insts.push(GetGlobal(JNKS_STRINGS_IDX));
+        insts.push(I32Const(table as i32));
+        insts.push(I32Add);
+        insts.push(I32Const(limit as i32));
+        insts.push(Call(
+            *rt_indexes
+                .get("call_trace_init")
+                .expect("no call_trace_init"),
+        ));
+        match global_env.get(&N::Id::Named(CALL_TRACE.into())) {
+            Some(IdIndex::Global(index, _)) => insts.push(SetGlobal(*index)),
+            _ => panic!("no global for the frames of the calls"),
+        }
+    }
 
     if opts.disable_gc == false {
         // globals are roots! put them in the first shadow frame
@@ -1274,20 +2345,24 @@ fn insert_generated_main(
         ));
     }
 
-    // TODO(arjun): Luna -- is the following comment stale? Could we figure out
-    // what this is about?
-    // these two for loops, i realize now, don't do anything in practice since
-    // all the globals happen to be lazy
-    for (index, global) in globals.values().enumerate() {
+    // Initialize the globals whose initializers are not constant expressions, in the order of
+    // their names. A global is a root as soon as it is initialized, since initializing the next
+    // global may allocate.
+    for (index, (global, init)) in globals.iter().zip(global_inits).enumerate() {
+        let index = (rt_globals_len + index) as u32;
+        if let Some(init) = init {
+            insts.extend(init.iter().cloned());
+            insts.push(SetGlobal(index));
+        }
         if opts.disable_gc == false && global.ty.is_gc_root() && global.atom.is_some() {
-            insts.push(GetGlobal((rt_globals_len + index) as u32));
-            insts.push(I32Const((rt_globals_len + index) as i32));
+            insts.push(GetGlobal(index));
+            insts.push(I32Const(index as i32));
             insts.push(get_set_in_globals_frame(rt_indexes, &global.ty));
         }
     }
 
     if let Some(IdIndex::Fun(func)) = global_env.get(&N::Id::Named("jnks_init".to_string())) {
-        insts.push(Call(*func + rt_indexes.len() as u32))
+        insts.push(Call(*func))
     } else {
         panic!("cannot find notwasm runtime function jnks_init");
     }
@@ -1295,11 +2370,13 @@ fn insert_generated_main(
     // jnks_init, but in generated at least the locals of jnks_init aren't GC
     // roots i guess (tail call)
     if let Some(IdIndex::Fun(func)) = global_env.get(&N::Id::Named("main".to_string())) {
-        insts.push(Call(*func + rt_indexes.len() as u32));
+        insts.push(Call(*func));
     } else {
         panic!("cannot find notwasm main");
     }
-    if opts.disable_gc == false {
+    // When the host may call back into the program, the globals frame stays on the shadow stack
+    // after main returns, so that the globals remain roots.
+    if opts.disable_gc == false && !host_calls_back {
         insts.push(Call(*rt_indexes.get("gc_exit_fn").expect("no gc_exit_fn")));
     }
     insts.push(End);
@@ -1341,7 +2418,7 @@ impl N::Type {
             Array => ValueType::I32,
             DynObject => ValueType::I32,
             Fn(..) => ValueType::I32,
-            Closure(..) => ValueType::I64,
+            Closure(..) => ValueType::I32,
             Ref(..) => ValueType::I32,
             Env => ValueType::I32,
             Ptr => ValueType::I32,
@@ -1354,7 +2431,6 @@ impl N::Type {
 fn shadow_frame_fn(ty: &N::Type) -> &'static str {
     match ty {
         N::Type::Any => "set_any_in_current_shadow_frame_slot",
-        N::Type::Closure(..) => "set_closure_in_current_shadow_frame_slot",
         _ => "set_in_current_shadow_frame_slot",
     }
 }
@@ -1364,7 +2440,6 @@ fn shadow_frame_fn(ty: &N::Type) -> &'static str {
 #[must_use]
 fn get_set_in_globals_frame(rt_indexes: &HashMap<String, u32>, ty: &N::Type) -> Instruction {
     let func = match ty {
-        N::Type::Closure(..) => "set_closure_in_globals_frame",
         N::Type::Any => "set_any_in_globals_frame",
         _ => "set_in_globals_frame",
     };
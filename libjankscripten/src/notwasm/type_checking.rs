@@ -2,6 +2,7 @@ use super::constructors::*;
 use super::syntax::*;
 use crate::pos::Pos;
 use im_rc::HashMap;
+use std::collections::HashSet;
 use thiserror::Error;
 
 #[derive(Clone, Debug)]
@@ -60,6 +61,8 @@ pub enum TypeCheckingError {
     ArityMismatch(Id, usize, usize, Pos),
     #[error("identifier `{0}` is multiply defined at `{1}`")]
     MultiplyDefined(Id, Pos),
+    #[error("global `{0}` is read at `{1}` before it is initialized")]
+    UninitializedGlobal(Id, Pos),
     #[error("In context `{0}`, unexpected type `{1}` at `{2}`")]
     InvalidInContext(String, Type, Pos),
     #[error("Error type-checking NotWasm: `{0}` at `{1}`")]
@@ -114,7 +117,70 @@ fn ensure(msg: &str, expected: Type, got: Type, s: &Pos) -> TypeCheckingResult<T
 /// code-generation. We could require these annotations in the input program,
 /// but they are trivial to calculate.
 pub fn type_check(p: &mut Program) -> TypeCheckingResult<()> {
-    // Top-level type environment, including checks for duplicate identifiers.
+    let env = global_env(p)?;
+    for (id, f) in p.functions.iter_mut() {
+        type_check_function(env.clone(), id, f)?;
+    }
+
+    check_init_order(p)?;
+    return Ok(());
+}
+
+/// The runtime, which is the NotWasm standard library with all of its files linked, after
+/// [check_runtime] type-checked it. Every program links (part of) the same runtime, so
+/// [type_check_with] does not check its functions again.
+#[derive(Clone, Debug)]
+pub struct RuntimeEnv {
+    /// The types of the functions, globals, and imports of the runtime.
+    env: Env,
+    /// Each function of the runtime, before and after type-checking added its annotations.
+    functions: HashMap<Id, (Function, Function)>,
+}
+
+/// Type-checks the runtime, which must not refer to the functions of a program.
+pub fn check_runtime(runtime: &mut Program) -> TypeCheckingResult<RuntimeEnv> {
+    let unchecked = runtime.functions.clone();
+    let env = global_env(runtime)?;
+    for (id, f) in runtime.functions.iter_mut() {
+        type_check_function(env.clone(), id, f)?;
+    }
+    check_init_order(runtime)?;
+    let functions = unchecked
+        .into_iter()
+        .map(|(id, f)| {
+            let checked = runtime.functions[&id].clone();
+            (id, (f, checked))
+        })
+        .collect();
+    Ok(RuntimeEnv { env, functions })
+}
+
+/// Like [type_check], but a function that is identical to a function of `runtime` gets the
+/// annotations of that function instead of being checked again. Linking may change a function
+/// of the runtime (e.g., it adds calls to `jnks_init`), so we check the functions that differ,
+/// and every function if the program gives a name of the runtime a different type.
+pub fn type_check_with(p: &mut Program, runtime: &RuntimeEnv) -> TypeCheckingResult<()> {
+    let env = global_env(p)?;
+    let same_types = runtime
+        .env
+        .env
+        .iter()
+        .all(|(x, ty)| env.get(x).map_or(true, |ty2| ty == ty2));
+    for (id, f) in p.functions.iter_mut() {
+        match runtime.functions.get(id) {
+            Some((unchecked, checked)) if same_types && *unchecked == *f => *f = checked.clone(),
+            _ => type_check_function(env.clone(), id, f)?,
+        }
+    }
+
+    check_init_order(p)?;
+    return Ok(());
+}
+
+/// The top-level type environment of a program, which has the types of its imports, functions,
+/// and globals. This checks the initializers of the globals, and that no two definitions have
+/// the same name.
+fn global_env(p: &mut Program) -> TypeCheckingResult<Env> {
     let mut env: Env = Env::new();
     for (x, t) in &p.rts_fn_imports {
         env.insert(Id::Named(x.to_string()), t.clone());
@@ -149,12 +215,189 @@ pub fn type_check(p: &mut Program) -> TypeCheckingResult<()> {
             ));
         }
     }
+    Ok(env)
+}
 
-    for (id, f) in p.functions.iter_mut() {
-        type_check_function(env.clone(), id, f)?;
+/// Checks that the program does not read a lazily-initialized global (one without an atom)
+/// before it writes it, which would silently produce 0. The initializers of the globals run
+/// first, so they may not read lazy globals. Then `jnks_init` runs, so we follow its statements
+/// in order, and the functions that it calls directly at the point of the call, and track the
+/// lazy globals that are certainly written so far. A global that only one branch of an `if`
+/// writes, or that a loop writes, is not certainly written afterwards.
+///
+/// We cannot follow calls to closures, so this does not find every early read. The functions
+/// that run after `jnks_init` may read any lazy global, since we cannot tell whether a global that
+/// `jnks_init` does not write is written before they run.
+fn check_init_order(p: &Program) -> TypeCheckingResult<()> {
+    let mut init_order = InitOrder {
+        program: p,
+        lazy: p
+            .globals
+            .iter()
+            .filter(|(_, g)| g.atom.is_none())
+            .map(|(x, _)| x.clone())
+            .collect(),
+        locals: vec![HashSet::new()],
+        visited: HashSet::new(),
+    };
+    let mut written = HashSet::new();
+    for g in p.globals.values() {
+        if let Some(atom) = &g.atom {
+            init_order.atom(atom, &written)?;
+        }
     }
+    let jnks_init = Id::from("jnks_init");
+    if p.functions.contains_key(&jnks_init) {
+        init_order.call(&jnks_init, &mut written)?;
+    }
+    Ok(())
+}
 
-    return Ok(());
+struct InitOrder<'a> {
+    program: &'a Program,
+    /// The globals that do not have an initializer.
+    lazy: HashSet<Id>,
+    /// The parameters and variables of the functions that we are in, innermost last, which
+    /// shadow the globals.
+    locals: Vec<HashSet<Id>>,
+    /// The functions that we have followed, which we follow at most once.
+    visited: HashSet<Id>,
+}
+
+impl InitOrder<'_> {
+    fn is_local(&self, x: &Id) -> bool {
+        self.locals
+            .last()
+            .map_or(false, |locals| locals.contains(x))
+    }
+
+    fn read(&self, x: &Id, written: &HashSet<Id>, s: &Pos) -> TypeCheckingResult<()> {
+        if self.lazy.contains(x) && !self.is_local(x) && !written.contains(x) {
+            return Err(TypeCheckingError::UninitializedGlobal(x.clone(), s.clone()));
+        }
+        Ok(())
+    }
+
+    fn read_all(&self, xs: &[Id], written: &HashSet<Id>, s: &Pos) -> TypeCheckingResult<()> {
+        for x in xs {
+            self.read(x, written, s)?;
+        }
+        Ok(())
+    }
+
+    /// Follows a call to the function `f`, if we have not followed it yet.
+    fn call(&mut self, f: &Id, written: &mut HashSet<Id>) -> TypeCheckingResult<()> {
+        let program = self.program;
+        let func = match program.functions.get(f) {
+            Some(func) if self.visited.insert(f.clone()) => func,
+            _ => return Ok(()),
+        };
+        self.locals.push(func.params.iter().cloned().collect());
+        let result = self.stmt(&func.body, written);
+        self.locals.pop();
+        result
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, written: &mut HashSet<Id>) -> TypeCheckingResult<()> {
+        match stmt {
+            Stmt::Empty | Stmt::Break(..) | Stmt::Trap | Stmt::Goto(..) => Ok(()),
+            Stmt::Var(var_stmt, _) => {
+                self.expr(&var_stmt.named, written)?;
+                if let Some(locals) = self.locals.last_mut() {
+                    locals.insert(var_stmt.id.clone());
+                }
+                Ok(())
+            }
+            Stmt::Expression(e, _) => self.expr(e, written),
+            Stmt::Assign(x, e, _) => {
+                self.expr(e, written)?;
+                if self.lazy.contains(x) {
+                    written.insert(x.clone());
+                }
+                Ok(())
+            }
+            Stmt::Store(r, e, s) => {
+                self.read(r, written, s)?;
+                self.expr(e, written)
+            }
+            Stmt::If(a, s1, s2, _) => {
+                self.atom(a, written)?;
+                let mut written1 = written.clone();
+                self.stmt(s1, &mut written1)?;
+                let mut written2 = written.clone();
+                self.stmt(s2, &mut written2)?;
+                *written = written1.intersection(&written2).cloned().collect();
+                Ok(())
+            }
+            Stmt::Loop(s, _) => self.stmt(s, &mut written.clone()),
+            Stmt::Label(_, s, _) => self.stmt(s, written),
+            Stmt::Return(a, _) => self.atom(a, written),
+            Stmt::Block(ss, _) => {
+                for s in ss {
+                    self.stmt(s, written)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, written: &mut HashSet<Id>) -> TypeCheckingResult<()> {
+        match expr {
+            Expr::ObjectEmpty(_) | Expr::ObjectLiteral(..) | Expr::ScratchObject(..) => Ok(()),
+            Expr::Call(f, xs, s) => {
+                self.read_all(xs, written, s)?;
+                if self.is_local(f) {
+                    Ok(())
+                } else if self.lazy.contains(f) {
+                    self.read(f, written, s)
+                } else {
+                    self.call(f, written)
+                }
+            }
+            Expr::PrimCall(_, xs, s) | Expr::Speculate(_, _, xs, s) => {
+                self.read_all(xs, written, s)
+            }
+            Expr::ClosureCall(f, xs, s) | Expr::AnyMethodCall(f, _, xs, _, s) => {
+                self.read(f, written, s)?;
+                self.read_all(xs, written, s)
+            }
+            Expr::ArraySet(a1, a2, a3, _) | Expr::ObjectSet(a1, a2, a3, _) => {
+                self.atom(a1, written)?;
+                self.atom(a2, written)?;
+                self.atom(a3, written)
+            }
+            Expr::NewRef(a, ..) | Expr::Atom(a, _) => self.atom(a, written),
+            Expr::Closure(_, env, _) => {
+                for (a, _) in env {
+                    self.atom(a, written)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn atom(&self, atom: &Atom, written: &HashSet<Id>) -> TypeCheckingResult<()> {
+        match atom {
+            Atom::Lit(..) | Atom::GetPrimFunc(..) | Atom::EnvGet(..) => Ok(()),
+            Atom::Id(x, s) | Atom::AnyLength(x, _, s) => self.read(x, written, s),
+            Atom::PrimApp(_, args, _) => {
+                for a in args {
+                    self.atom(a, written)?;
+                }
+                Ok(())
+            }
+            Atom::ToAny(to_any, _) => self.atom(&to_any.atom, written),
+            Atom::FromAny(a, ..)
+            | Atom::FloatToInt(a, _)
+            | Atom::IntToFloat(a, _)
+            | Atom::Unary(_, a, _)
+            | Atom::Deref(a, ..) => self.atom(a, written),
+            Atom::ObjectGet(a1, a2, _) | Atom::Binary(_, a1, a2, _) => {
+                self.atom(a1, written)?;
+                self.atom(a2, written)
+            }
+        }
+    }
 }
 
 fn ensure_ref(msg: &str, got: Type, s: &Pos) -> TypeCheckingResult<Type> {
@@ -293,7 +536,9 @@ fn type_check_stmt(env: Env, s: &mut Stmt, ret_ty: &Option<Type>) -> TypeCheckin
 
 fn type_check_expr(env: &Env, e: &mut Expr) -> TypeCheckingResult<Type> {
     match e {
-        Expr::ObjectEmpty => Ok(Type::DynObject),
+        Expr::ObjectEmpty(_) | Expr::ObjectLiteral(..) | Expr::ScratchObject(..) => {
+            Ok(Type::DynObject)
+        }
         Expr::ArraySet(a_arr, a_idx, a_val, s) => {
             let got_arr = type_check_atom(env, a_arr)?;
             let got_idx = type_check_atom(env, a_idx)?;
@@ -314,6 +559,23 @@ fn type_check_expr(env: &Env, e: &mut Expr) -> TypeCheckingResult<Type> {
 
             Ok(Type::Any) // returns value set
         }
+        Expr::Speculate(op, prim, args, s) => {
+            for arg in args.iter() {
+                ensure("speculated argument", Type::Any, lookup(env, arg, s)?, s)?;
+            }
+            let ty = type_check_expr(
+                env,
+                &mut Expr::PrimCall(prim.clone(), args.clone(), s.clone()),
+            )?;
+            match (op.notwasm_typ(false), &ty) {
+                ((Type::I32, Type::I32), Type::Any) | ((Type::I32, Type::Bool), Type::Bool)
+                    if args.len() == 2 =>
+                {
+                    Ok(ty)
+                }
+                _ => error!(s, "cannot speculate {:?} for {}", op, prim),
+            }
+        }
         Expr::PrimCall(crate::rts_function::RTSFunction::Import(name), args, s) => {
             let ty = env.imports.get(&Id::Named(name.clone())).ok_or(err!(
                 s,
@@ -604,3 +866,175 @@ fn type_check_atom(env: &Env, a: &mut Atom) -> TypeCheckingResult<Type> {
         Atom::EnvGet(_, ty, _) => Ok(ty.clone()),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::parse;
+    use super::*;
+
+    fn check(program: &str) -> TypeCheckingResult<()> {
+        type_check(&mut parse("<test>", program))
+    }
+
+    fn uninitialized(result: TypeCheckingResult<()>) -> Option<(Id, Pos)> {
+        match result {
+            Err(TypeCheckingError::UninitializedGlobal(x, p)) => Some((x, p)),
+            _ => None,
+        }
+    }
+
+    const RUNTIME: &str = r#"
+        var g: i32 = 1;
+        function rt_get(): i32 {
+            var x = g;
+            return x;
+        }
+    "#;
+
+    fn with_runtime(program: &str, runtime: &str) -> (Program, TypeCheckingResult<()>) {
+        let runtime_env = check_runtime(&mut parse("<runtime>", RUNTIME)).expect("runtime");
+        let mut program = parse("<test>", program);
+        program.merge_in(parse("<runtime>", runtime));
+        let result = type_check_with(&mut program, &runtime_env);
+        (program, result)
+    }
+
+    #[test]
+    fn runtime_functions_get_annotations() {
+        let (program, result) = with_runtime(
+            r#"
+            function main(): i32 {
+                var y = rt_get();
+                return y;
+            }
+            "#,
+            RUNTIME,
+        );
+        result.expect("type checking");
+        match &program.functions[&Id::from("rt_get")].body {
+            Stmt::Block(body, _) => match &body[0] {
+                Stmt::Var(var_stmt, _) => assert_eq!(var_stmt.ty, Some(Type::I32)),
+                s => panic!("expected a var, got {:?}", s),
+            },
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn program_checked_against_runtime() {
+        let (_, result) = with_runtime(
+            r#"
+            function main(): bool {
+                var y = rt_get();
+                return y;
+            }
+            "#,
+            RUNTIME,
+        );
+        assert!(matches!(result, Err(TypeCheckingError::TypeMismatch(..))));
+    }
+
+    #[test]
+    fn changed_runtime_functions_are_checked() {
+        let (_, result) = with_runtime(
+            "function main(): i32 { return 0; }",
+            r#"
+            var g: i32 = 1;
+            function rt_get(): i32 {
+                return true;
+            }
+            "#,
+        );
+        assert!(matches!(result, Err(TypeCheckingError::TypeMismatch(..))));
+    }
+
+    #[test]
+    fn read_before_init() {
+        let result = check(
+            r#"
+            var g: any;
+            function jnks_init() {
+                var x = g;
+                g = any(1);
+            }
+            "#,
+        );
+        let (x, p) = uninitialized(result).expect("no error");
+        assert_eq!(x, Id::from("g"));
+        assert!(!p.is_unknown());
+    }
+
+    #[test]
+    fn init_in_called_function() {
+        let result = check(
+            r#"
+            var g: any;
+            function init_g(): i32 {
+                g = any(1);
+                return 0;
+            }
+            function read_g(): any {
+                return g;
+            }
+            function jnks_init() {
+                init_g();
+                var x = read_g();
+            }
+            "#,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn read_in_called_function_before_init() {
+        let result = check(
+            r#"
+            var g: any;
+            function read_g(): any {
+                return g;
+            }
+            function jnks_init() {
+                var x = read_g();
+                g = any(1);
+            }
+            "#,
+        );
+        assert_eq!(uninitialized(result).expect("no error").0, Id::from("g"));
+    }
+
+    #[test]
+    fn init_in_one_branch() {
+        let result = check(
+            r#"
+            var g: any;
+            var h: any;
+            function jnks_init() {
+                var b = true;
+                if (b) {
+                    g = any(1);
+                    h = any(2);
+                } else {
+                    h = any(3);
+                }
+                var y = h;
+                var x = g;
+            }
+            "#,
+        );
+        assert_eq!(uninitialized(result).expect("no error").0, Id::from("g"));
+    }
+
+    #[test]
+    fn locals_shadow_lazy_globals() {
+        let result = check(
+            r#"
+            var g: any;
+            function jnks_init() {
+                var g = any(1);
+                var x = g;
+            }
+            "#,
+        );
+        assert!(result.is_ok());
+    }
+}
@@ -10,73 +10,34 @@ use crate::rts_function::RTSFunction;
 /// only have to specify what you need
 pub trait Visitor {
     /// called before recursing on a statement
-    fn enter_stmt(&mut self, _stmt: &mut Stmt) {}
+    fn enter_stmt(&mut self, _stmt: &mut Stmt, _loc: &Loc) {}
     /// called before recursing on an expression
-    fn enter_expr(&mut self, _expr: &mut Expr, _loc: &mut Loc) {}
+    fn enter_expr(&mut self, _expr: &mut Expr, _loc: &Loc) {}
     /// called before recursing on an atom
-    fn enter_atom(&mut self, _atom: &mut Atom, _loc: &mut Loc) {}
+    fn enter_atom(&mut self, _atom: &mut Atom, _loc: &Loc) {}
     /// called after recursing on a statement, with the new value
-    fn exit_stmt(&mut self, _stmt: &mut Stmt) {}
+    fn exit_stmt(&mut self, _stmt: &mut Stmt, _loc: &Loc) {}
     /// called after recursing on an expression, with the new value
-    fn exit_expr(&mut self, _expr: &mut Expr, _loc: &mut Loc) {}
+    fn exit_expr(&mut self, _expr: &mut Expr, _loc: &Loc) {}
     /// called after recursing on an atom, with the new value
-    fn exit_atom(&mut self, _atom: &mut Atom, _loc: &mut Loc) {}
+    fn exit_atom(&mut self, _atom: &mut Atom, _loc: &Loc) {}
+    /// called before visiting each node. Once this produces `true`, the walk
+    /// ends without calling any more `enter_` or `exit_` methods
+    fn done(&self) -> bool {
+        false
+    }
 }
 
 pub struct VisitorState<'v, V> {
     visitor: &'v mut V,
 }
 
-#[derive(Debug)]
-pub struct BlockContext {
-    pub index: usize,
-    pub len: usize,
-    patches: Vec<(usize, Stmt)>,
-}
-
-impl BlockContext {
-    pub fn new(index: usize, len: usize) -> Self {
-        BlockContext {
-            index,
-            len,
-            patches: vec![],
-        }
-    }
-
-    /// Insert `stmt` at position `index` into the block. The position 0 is
-    /// before the first element, and the last position is after the last
-    /// element. E.g., if the block has statements `[s0, s1, s2]` then the
-    /// indices are `[0, s0, 1, s1, 2, s2, 3]`.
-    ///
-    /// Note that you can insert multiple statements at the same index.
-    /// They will appear in the order they were added.
-    ///
-    /// Panics if the index is invalid.
-    pub fn insert(&mut self, index: usize, stmt: Stmt) {
-        assert!(index <= self.len);
-        self.patches.push((index, stmt));
-    }
-
-    fn apply_patches(mut self, block: &mut Vec<Stmt>) {
-        // but we want patches to be applied in order they were added. because
-        // we add them in reverse order we want to iterate in reverse order
-        // Vec::sort_by maintains diff=0 values to be in their original order
-        // (stable), but we want the opposite behavior. to do this we sort in
-        // ascending order and reverse the whole iterator
-        self.patches.sort_by(|(m, _), (n, _)| m.cmp(n));
-        // .rev() reverses the reverse-sorted iterator now
-        for (index, stmt) in self.patches.drain(0..).rev() {
-            // Inserting shifts all elements to the right. However, the
-            // indices are in descending order.
-            block.insert(index, stmt);
-        }
-    }
-}
+pub type BlockContext = crate::shared::walk::BlockContext<Stmt>;
 
 #[derive(Debug)]
 pub enum Context<'a> {
     // Additional contexts can go here.
-    Block(&'a mut BlockContext),
+    Block(&'a BlockContext),
 }
 
 /// A data structure that represents the context of a call to a visitor.
@@ -97,9 +58,12 @@ where
         VisitorState { visitor }
     }
 
-    pub fn walk_stmt(&mut self, stmt: &mut Stmt, loc: &mut Loc) {
+    pub fn walk_stmt(&mut self, stmt: &mut Stmt, loc: &Loc) {
         use Stmt::*;
-        self.visitor.enter_stmt(stmt);
+        if self.visitor.done() {
+            return;
+        }
+        self.visitor.enter_stmt(stmt, loc);
         // recurse
         match stmt {
             // 0
@@ -111,8 +75,8 @@ where
                 let mut block_cxt = BlockContext::new(0, ss.len());
                 for (index, s) in ss.iter_mut().enumerate() {
                     block_cxt.index = index;
-                    let mut loc = Loc::Node(Context::Block(&mut block_cxt), loc);
-                    self.walk_stmt(s, &mut loc);
+                    let loc = Loc::Node(Context::Block(&block_cxt), loc);
+                    self.walk_stmt(s, &loc);
                 }
                 block_cxt.apply_patches(ss);
             }
@@ -130,15 +94,21 @@ where
                 self.walk_stmt(sb, loc);
             }
         }
-        self.visitor.exit_stmt(stmt);
+        if !self.visitor.done() {
+            self.visitor.exit_stmt(stmt, loc);
+        }
     }
 
-    pub fn walk_expr(&mut self, expr: &mut Expr, loc: &mut Loc) {
+    pub fn walk_expr(&mut self, expr: &mut Expr, loc: &Loc) {
         use Expr::*;
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_expr(expr, loc);
         match expr {
             // TODO(arjun): PrimCall should be walking atom!
-            ObjectEmpty | Call(..) | AnyMethodCall(..) | ClosureCall(..) | PrimCall(..) => (),
+            ObjectEmpty(..) | ObjectLiteral(..) | ScratchObject(..) | Call(..)
+            | AnyMethodCall(..) | ClosureCall(..) | PrimCall(..) | Speculate(..) => (),
             ObjectSet(ea, eb, ec, ..) | ArraySet(ea, eb, ec, _) => {
                 self.walk_atom(ea, loc);
                 self.walk_atom(eb, loc);
@@ -151,11 +121,16 @@ where
                 }
             }
         }
-        self.visitor.exit_expr(expr, loc);
+        if !self.visitor.done() {
+            self.visitor.exit_expr(expr, loc);
+        }
     }
 
-    pub fn walk_atom(&mut self, atom: &mut Atom, loc: &mut Loc) {
+    pub fn walk_atom(&mut self, atom: &mut Atom, loc: &Loc) {
         use Atom::*;
+        if self.visitor.done() {
+            return;
+        }
         self.visitor.enter_atom(atom, loc);
         match atom {
             // 0
@@ -176,15 +151,16 @@ where
                 self.walk_atom(eb, loc);
             }
         }
-        self.visitor.exit_atom(atom, loc);
+        if !self.visitor.done() {
+            self.visitor.exit_atom(atom, loc);
+        }
     }
 }
 
 impl Stmt {
     pub fn walk(&mut self, v: &mut impl Visitor) {
         let mut vs = VisitorState::new(v);
-        let mut loc = Loc::Top;
-        vs.walk_stmt(self, &mut loc);
+        vs.walk_stmt(self, &Loc::Top);
     }
     /// replace this statement with `;` and return its old value. this is
     /// used to gain ownership of a mutable reference, especially in [Stmt::walk]
@@ -198,8 +174,7 @@ impl Expr {
     /// same thing
     pub fn walk(&mut self, v: &mut impl Visitor) {
         let mut vs = VisitorState::new(v);
-        let mut loc = Loc::Top;
-        vs.walk_expr(self, &mut loc);
+        vs.walk_expr(self, &Loc::Top);
     }
 
     pub fn prim_call(name: impl Into<String>, args: Vec<Id>, pos: Pos) -> Self {
@@ -211,8 +186,7 @@ impl Atom {
     /// like [Stmt::walk], but as a method on Atom
     pub fn walk(&mut self, v: &mut impl Visitor) {
         let mut vs = VisitorState::new(v);
-        let mut loc = Loc::Top;
-        vs.walk_atom(self, &mut loc);
+        vs.walk_atom(self, &Loc::Top);
     }
     /// replace this atom with `false` and return its old
     /// value. this is used to gain ownership of a mutable reference,
@@ -223,13 +197,18 @@ impl Atom {
 }
 
 impl Program {
-    /// like [Stmt::walk], but as a method on Program
+    /// like [Stmt::walk], but as a method on Program. Visits functions and globals in order of
+    /// name, so that visitors that accumulate state (e.g., interning) are deterministic.
     pub fn walk(&mut self, v: &mut impl Visitor) {
-        for func in self.functions.values_mut() {
-            func.body.walk(v);
+        let mut names: Vec<_> = self.functions.keys().cloned().collect();
+        names.sort_by_key(|x| x.to_string());
+        for name in names {
+            self.functions.get_mut(&name).unwrap().body.walk(v);
         }
-        for global in self.globals.values_mut() {
-            if let Some(atom) = &mut global.atom {
+        let mut names: Vec<_> = self.globals.keys().cloned().collect();
+        names.sort_by_key(|x| x.to_string());
+        for name in names {
+            if let Some(atom) = &mut self.globals.get_mut(&name).unwrap().atom {
                 atom.walk(v);
             }
         }
@@ -1,9 +1,80 @@
-#[derive(Debug)]
+//! The options of the compiler.
+//!
+//! Options either come from [Opts::builder], which rejects combinations that conflict, or from
+//! deserializing them (e.g., a build system that persists its flags), in which case the compiler
+//! validates them before it starts (see [Opts::validate]). The standard library and the profile
+//! are inputs and not flags, so they are not serialized.
+use crate::profile::Profile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OptsError {
+    #[error("the maximum call depth must be at least 1")]
+    ZeroCallDepth,
+    #[error("cannot print the IR after `{0}`, which is disabled")]
+    PrintAfterDisabledPass(String),
+    #[error("cannot {0} without type inference (dynamic_types is set)")]
+    NoTypeinf(&'static str),
+    #[error("cannot instrument a program that a profile optimizes")]
+    ProfileGenAndUse,
+    #[error("the runtime system does not have a feature named `{0}`")]
+    UnknownRuntimeFeature(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Opts {
     /// Disabling GC can help debug jankscripten-generated code.
     pub disable_gc: bool,
     /// The source code of the NotWasm standard library
+    #[serde(skip)]
     pub notwasm_stdlib_source_code: String,
+    /// The files that the standard library includes, by the names in its `include` directives
+    /// (see [crate::notwasm::stdlib]).
+    #[serde(skip)]
+    pub notwasm_stdlib_includes: HashMap<String, String>,
+    /// Instrument the program with a counter for each function and inline cache, which the
+    /// runtime system serializes after a run (see [crate::profile]).
+    pub profile_gen: bool,
+    /// Give every coercion to and from any a counter, like the counters of [Opts::profile_gen],
+    /// so that a run reports how often each coercion ran (see [crate::profile]).
+    pub trace_coercions: bool,
+    /// A profile from an instrumented run of the same program.
+    #[serde(skip)]
+    pub profile_use: Option<Profile>,
+    /// The maximum depth of nested calls. When it is set, every function counts how deep the
+    /// call stack is, and a call that exceeds the limit stops the program with a
+    /// `RangeError: Maximum call stack size exceeded`, and the names of the functions on the
+    /// stack, instead of exhausting the stack of the WebAssembly engine.
+    pub max_call_depth: Option<u32>,
+    /// Record why the generated code traps (see `abi::TrapReason`) before each trap, so that the
+    /// host can report it after the program stops. This is for debugging miscompiles.
+    pub checked: bool,
+    /// NotWasm functions that the module exports under their own names, so that a host can call
+    /// them after `main` (see `bin/bridge.js`).
+    pub exports: Vec<String>,
+    /// Print the IR to standard error after each of these passes (see [crate::passes]).
+    pub print_after: Vec<String>,
+    /// Skip these passes, which is only for debugging: the passes that follow may rely on them.
+    pub disable_passes: Vec<String>,
+    /// Report the wall time of each pass to standard error.
+    pub time_passes: bool,
+    /// Do not infer types with Z3: every variable, argument, and function result is any, and only
+    /// literals and operators on them keep precise types. Builds without Z3 always do this.
+    pub dynamic_types: bool,
+    /// The costs that type inference assigns to coercions (see [TypeinfWeights]).
+    pub typeinf_weights: TypeinfWeights,
+    /// Report the cost of the types that type inference picks to standard error.
+    pub print_typeinf_objective: bool,
+    /// The features of the runtime system that the program runs with, which are its cargo
+    /// features (see runtime/Cargo.toml). The compiler rejects a program that needs a function
+    /// of another feature. By default, the runtime system has every feature.
+    pub runtime_features: Vec<String>,
+    /// The maximum size of the module in bytes. A compilation that produces a larger module fails
+    /// with the largest functions of the module (see [crate::notwasm::size]).
+    pub max_module_size: Option<usize>,
 }
 
 impl Opts {
@@ -11,6 +82,346 @@ impl Opts {
         Opts {
             disable_gc: false,
             notwasm_stdlib_source_code: "".to_string(),
+            notwasm_stdlib_includes: HashMap::new(),
+            profile_gen: false,
+            trace_coercions: false,
+            profile_use: None,
+            max_call_depth: None,
+            checked: false,
+            exports: Vec::new(),
+            print_after: Vec::new(),
+            disable_passes: Vec::new(),
+            time_passes: false,
+            dynamic_types: false,
+            typeinf_weights: TypeinfWeights::default(),
+            print_typeinf_objective: false,
+            runtime_features: crate::notwasm::runtime_features(),
+            max_module_size: None,
+        }
+    }
+
+    pub fn builder() -> OptsBuilder {
+        OptsBuilder::default()
+    }
+
+    /// Checks that no two options conflict.
+    pub fn validate(&self) -> Result<(), OptsError> {
+        if self.max_call_depth == Some(0) {
+            return Err(OptsError::ZeroCallDepth);
+        }
+        if let Some(pass) = self
+            .print_after
+            .iter()
+            .find(|pass| self.disable_passes.contains(*pass))
+        {
+            return Err(OptsError::PrintAfterDisabledPass(pass.clone()));
+        }
+        if self.dynamic_types {
+            if self.print_typeinf_objective {
+                return Err(OptsError::NoTypeinf(
+                    "print the objective of type inference",
+                ));
+            }
+            if self.typeinf_weights != TypeinfWeights::default() {
+                return Err(OptsError::NoTypeinf("set the costs of type inference"));
+            }
+        }
+        if self.profile_gen && self.profile_use.is_some() {
+            return Err(OptsError::ProfileGenAndUse);
+        }
+        let features = crate::notwasm::runtime_features();
+        if let Some(feature) = self
+            .runtime_features
+            .iter()
+            .find(|feature| !features.contains(feature))
+        {
+            return Err(OptsError::UnknownRuntimeFeature(feature.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Opts {
+    fn default() -> Self {
+        Opts::new()
+    }
+}
+
+/// Builds [Opts], e.g., `Opts::builder().disable_gc(true).max_call_depth(1000).build()`. Every
+/// option that the builder does not set has its default value.
+#[derive(Debug, Clone, Default)]
+pub struct OptsBuilder {
+    opts: Opts,
+}
+
+impl OptsBuilder {
+    pub fn disable_gc(mut self, disable_gc: bool) -> Self {
+        self.opts.disable_gc = disable_gc;
+        self
+    }
+
+    pub fn stdlib(mut self, source_code: impl Into<String>) -> Self {
+        self.opts.notwasm_stdlib_source_code = source_code.into();
+        self
+    }
+
+    /// Adds a file that the standard library includes as `name`.
+    pub fn stdlib_include(
+        mut self,
+        name: impl Into<String>,
+        source_code: impl Into<String>,
+    ) -> Self {
+        self.opts
+            .notwasm_stdlib_includes
+            .insert(name.into(), source_code.into());
+        self
+    }
+
+    pub fn stdlib_includes(mut self, includes: HashMap<String, String>) -> Self {
+        self.opts.notwasm_stdlib_includes.extend(includes);
+        self
+    }
+
+    pub fn profile_gen(mut self, profile_gen: bool) -> Self {
+        self.opts.profile_gen = profile_gen;
+        self
+    }
+
+    pub fn trace_coercions(mut self, trace_coercions: bool) -> Self {
+        self.opts.trace_coercions = trace_coercions;
+        self
+    }
+
+    pub fn profile_use(mut self, profile: Option<Profile>) -> Self {
+        self.opts.profile_use = profile;
+        self
+    }
+
+    pub fn max_call_depth(mut self, max_call_depth: Option<u32>) -> Self {
+        self.opts.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn checked(mut self, checked: bool) -> Self {
+        self.opts.checked = checked;
+        self
+    }
+
+    pub fn exports(mut self, exports: Vec<String>) -> Self {
+        self.opts.exports = exports;
+        self
+    }
+
+    pub fn print_after(mut self, passes: Vec<String>) -> Self {
+        self.opts.print_after = passes;
+        self
+    }
+
+    pub fn disable_passes(mut self, passes: Vec<String>) -> Self {
+        self.opts.disable_passes = passes;
+        self
+    }
+
+    pub fn time_passes(mut self, time_passes: bool) -> Self {
+        self.opts.time_passes = time_passes;
+        self
+    }
+
+    pub fn dynamic_types(mut self, dynamic_types: bool) -> Self {
+        self.opts.dynamic_types = dynamic_types;
+        self
+    }
+
+    pub fn typeinf_weights(mut self, weights: TypeinfWeights) -> Self {
+        self.opts.typeinf_weights = weights;
+        self
+    }
+
+    pub fn print_typeinf_objective(mut self, print_typeinf_objective: bool) -> Self {
+        self.opts.print_typeinf_objective = print_typeinf_objective;
+        self
+    }
+
+    pub fn runtime_features(mut self, features: Vec<String>) -> Self {
+        self.opts.runtime_features = features;
+        self
+    }
+
+    pub fn max_module_size(mut self, max_module_size: Option<usize>) -> Self {
+        self.opts.max_module_size = max_module_size;
+        self
+    }
+
+    pub fn build(self) -> Result<Opts, OptsError> {
+        self.opts.validate()?;
+        Ok(self.opts)
+    }
+}
+
+/// The costs of the coercions that type inference may insert, by where they occur. Type
+/// inference picks the types that minimize the sum of the costs of their coercions, so raising a
+/// cost makes the coercions of that kind rarer (e.g., a high `call` cost keeps the arguments and
+/// results of functions precise, at the expense of coercing more literals). A cost of zero makes
+/// a coercion free. The default costs are all 1, except `float_op`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TypeinfWeights {
+    /// Coercing a literal to any.
+    pub literal: u32,
+    /// Coercing any other value to any, e.g., a variable, a function, or an array.
+    pub value: u32,
+    /// Coercing the right-hand side of an assignment to the type of the variable.
+    pub assign: u32,
+    /// Coercing the operands of an operator to any.
+    pub operator: u32,
+    /// Using a value of type any where a more precise type is expected, e.g., in a condition or
+    /// when reading a field of an object.
+    pub elimination: u32,
+    /// Coercing at a call boundary: the function, its arguments, and its result.
+    pub call: u32,
+    /// Selecting an overload of an operator that produces a float, which makes inference prefer
+    /// int when both fit.
+    pub float_op: u32,
+}
+
+impl TypeinfWeights {
+    /// The names of the costs, which [TypeinfWeights::set] takes.
+    pub const NAMES: &'static [&'static str] = &[
+        "literal",
+        "value",
+        "assign",
+        "operator",
+        "elimination",
+        "call",
+        "float_op",
+    ];
+
+    /// Sets a cost from a `NAME=COST` string, e.g., `call=10`.
+    pub fn set(&mut self, setting: &str) -> Result<(), String> {
+        let mut parts = setting.splitn(2, '=');
+        let name = parts.next().unwrap();
+        let cost = parts
+            .next()
+            .ok_or_else(|| format!("expected NAME=COST, got {}", setting))?
+            .parse::<u32>()
+            .map_err(|err| format!("invalid cost in {}: {}", setting, err))?;
+        let field = match name {
+            "literal" => &mut self.literal,
+            "value" => &mut self.value,
+            "assign" => &mut self.assign,
+            "operator" => &mut self.operator,
+            "elimination" => &mut self.elimination,
+            "call" => &mut self.call,
+            "float_op" => &mut self.float_op,
+            _ => {
+                return Err(format!(
+                    "unknown cost {} (expected one of {})",
+                    name,
+                    Self::NAMES.join(", ")
+                ))
+            }
+        };
+        *field = cost;
+        Ok(())
+    }
+}
+
+impl Default for TypeinfWeights {
+    fn default() -> Self {
+        TypeinfWeights {
+            literal: 1,
+            value: 1,
+            assign: 1,
+            operator: 1,
+            elimination: 1,
+            call: 1,
+            float_op: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_typeinf_weights() {
+        let mut weights = TypeinfWeights::default();
+        weights.set("call=10").unwrap();
+        weights.set("float_op=3").unwrap();
+        assert_eq!(weights.call, 10);
+        assert_eq!(weights.float_op, 3);
+        assert_eq!(weights.literal, 1);
+        assert!(weights.set("call").is_err());
+        assert!(weights.set("call=-1").is_err());
+        assert!(weights.set("calls=1").is_err());
+    }
+
+    #[test]
+    fn builder_rejects_conflicts() {
+        let opts = Opts::builder()
+            .disable_gc(true)
+            .max_call_depth(Some(100))
+            .build()
+            .unwrap();
+        assert!(opts.disable_gc);
+        assert_eq!(opts.max_call_depth, Some(100));
+        assert_eq!(
+            Opts::builder().max_call_depth(Some(0)).build(),
+            Err(OptsError::ZeroCallDepth)
+        );
+        assert_eq!(
+            Opts::builder()
+                .disable_passes(vec!["speculate".to_string()])
+                .print_after(vec!["intern".to_string(), "speculate".to_string()])
+                .build(),
+            Err(OptsError::PrintAfterDisabledPass("speculate".to_string()))
+        );
+        let mut weights = TypeinfWeights::default();
+        weights.set("call=10").unwrap();
+        assert!(Opts::builder()
+            .dynamic_types(true)
+            .typeinf_weights(weights)
+            .build()
+            .is_err());
+        assert_eq!(
+            Opts::builder()
+                .profile_gen(true)
+                .profile_use(Some(Profile::default()))
+                .build(),
+            Err(OptsError::ProfileGenAndUse)
+        );
+        assert_eq!(
+            Opts::builder()
+                .runtime_features(vec!["json".to_string()])
+                .build(),
+            Err(OptsError::UnknownRuntimeFeature("json".to_string()))
+        );
+    }
+
+    #[test]
+    fn serialize_flags() {
+        let opts = Opts::builder()
+            .stdlib("function main(): i32 { return 0; }")
+            .checked(true)
+            .exports(vec!["f".to_string()])
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&opts).unwrap();
+        assert!(!json.contains("function main"));
+        let read: Opts = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            read,
+            Opts {
+                notwasm_stdlib_source_code: String::new(),
+                ..opts
+            }
+        );
+        // Missing flags have their default values.
+        let read: Opts = serde_json::from_str(r#"{"typeinf_weights": {"call": 5}}"#).unwrap();
+        assert_eq!(read.typeinf_weights.call, 5);
+        assert_eq!(read.typeinf_weights.literal, 1);
+        assert!(!read.checked);
+        assert!(serde_json::from_str::<Opts>(r#"{"checkd": true}"#).is_err());
+    }
+}
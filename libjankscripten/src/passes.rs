@@ -0,0 +1,114 @@
+//! Runs the passes of the compiler over an intermediate representation, in order.
+//!
+//! Every pass has a name, which is the name of the function that implements it (e.g., `typeinf`
+//! or `intern`). For debugging, the options can disable passes ([Opts::disable_passes]), print
+//! the IR after passes ([Opts::print_after]), and report the wall time of every pass
+//! ([Opts::time_passes]). Reports and IR go to standard error, so that they do not mix with the
+//! output of the compiler. Names that no pass has are ignored.
+use crate::opts::Opts;
+use std::fmt::Display;
+use std::time::Instant;
+
+enum Step<'a, T, E> {
+    Pass(&'static str, Box<dyn FnOnce(&mut T) -> Result<(), E> + 'a>),
+    Inspect(Box<dyn FnOnce(&T) + 'a>),
+}
+
+pub struct PassManager<'a, T, E> {
+    /// The name of the IR, for reports.
+    ir: &'static str,
+    opts: &'a Opts,
+    steps: Vec<Step<'a, T, E>>,
+}
+
+impl<'a, T: Display, E> PassManager<'a, T, E> {
+    pub fn new(ir: &'static str, opts: &'a Opts) -> Self {
+        PassManager {
+            ir,
+            opts,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Adds a pass that cannot fail.
+    pub fn add(&mut self, name: &'static str, pass: impl FnOnce(&mut T) + 'a) {
+        self.add_fallible(name, move |ir| {
+            pass(ir);
+            Ok(())
+        });
+    }
+
+    pub fn add_fallible(
+        &mut self,
+        name: &'static str,
+        pass: impl FnOnce(&mut T) -> Result<(), E> + 'a,
+    ) {
+        self.steps.push(Step::Pass(name, Box::new(pass)));
+    }
+
+    /// Lets the caller see the IR between two passes. Inspection is not a pass, so the options
+    /// cannot disable it.
+    pub fn inspect(&mut self, inspect: impl FnOnce(&T) + 'a) {
+        self.steps.push(Step::Inspect(Box::new(inspect)));
+    }
+
+    /// Runs the passes in the order in which they were added, and stops at the first error.
+    pub fn run(self, ir: &mut T) -> Result<(), E> {
+        let opts = self.opts;
+        for step in self.steps {
+            match step {
+                Step::Inspect(inspect) => inspect(ir),
+                Step::Pass(name, _) if opts.disable_passes.iter().any(|p| p == name) => (),
+                Step::Pass(name, pass) => {
+                    time(opts, self.ir, name, || pass(ir))?;
+                    if opts.print_after.iter().any(|p| p == name) {
+                        eprintln!("// {} after {}\n{}", self.ir, name, ir);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `step`, and reports its wall time if [Opts::time_passes] is set. Use this function for
+/// the steps of compilation that produce a new IR, which a [PassManager] cannot run.
+pub fn time<R>(opts: &Opts, ir: &str, name: &str, step: impl FnOnce() -> R) -> R {
+    if !opts.time_passes {
+        return step();
+    }
+    let start = Instant::now();
+    let result = step();
+    let elapsed = start.elapsed();
+    eprintln!("{} {}: {:.3} ms", ir, name, elapsed.as_secs_f64() * 1000.0);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(opts: &Opts) -> String {
+        let mut log = String::new();
+        let mut passes = PassManager::<String, ()>::new("Test", opts);
+        passes.add("first", |s| s.push('a'));
+        passes.inspect(|s| log.push_str(s));
+        passes.add("second", |s| s.push('b'));
+        passes.add_fallible("third", |s| {
+            s.push('c');
+            Err(())
+        });
+        passes.add("fourth", |s| s.push('d'));
+        let mut ir = String::new();
+        let result = passes.run(&mut ir);
+        format!("{} {} {:?}", log, ir, result)
+    }
+
+    #[test]
+    fn run_passes_in_order() {
+        let mut opts = Opts::new();
+        assert_eq!(run(&opts), "a abc Err(())");
+        opts.disable_passes = vec!["first".to_string(), "third".to_string()];
+        assert_eq!(run(&opts), " bd Ok(())");
+    }
+}
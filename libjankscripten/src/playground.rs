@@ -0,0 +1,23 @@
+//! The entry point of the browser playground, which runs the whole compiler in WebAssembly (see
+//! the README for how to build it). The browser has no file system, so the NotWasm standard
+//! library is part of the build.
+use crate::notwasm::stdlib::STDLIB_INCLUDES;
+use crate::opts::Opts;
+use wasm_bindgen::prelude::*;
+
+const STDLIB: &str = include_str!("../../stdlib.notwasm");
+
+/// Compiles the JavaScript program `js` to a WebAssembly module, which needs the runtime system
+/// to run. Throws the error message if compilation fails.
+#[wasm_bindgen]
+pub fn compile(js: &str) -> Result<Vec<u8>, JsValue> {
+    let mut opts = Opts::new();
+    opts.notwasm_stdlib_source_code = STDLIB.to_string();
+    for (name, source) in STDLIB_INCLUDES {
+        opts.notwasm_stdlib_includes
+            .insert(name.to_string(), source.to_string());
+    }
+    crate::javascript_to_wasm(opts, "playground.js", js, |_| (), |_| ())
+        .map(|(wasm, _)| wasm)
+        .map_err(|err| JsValue::from_str(&err.to_string()))
+}
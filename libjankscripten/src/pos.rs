@@ -92,6 +92,13 @@ impl Pos {
     }
 
     pub const UNKNOWN: Pos = Pos { pos: P::Unknown };
+
+    pub fn is_unknown(&self) -> bool {
+        match self.pos {
+            P::Unknown => true,
+            _ => false,
+        }
+    }
 }
 
 impl Default for Pos {
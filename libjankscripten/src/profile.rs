@@ -0,0 +1,155 @@
+//! Execution profiles, which drive profile-guided optimization.
+//!
+//! When [crate::opts::Opts::profile_gen] is set, the compiler gives every function and every
+//! inline cache of the program a counter, which the program increments when it calls the
+//! function or looks up a field with the inline cache. After a run, `profile_serialize` in the
+//! runtime system produces the counters in the format that [Profile::parse] reads: one line per
+//! site, which holds the count, a space, and the name of the site. The name of a function is its
+//! NotWasm name, and the name of the `n`th inline cache in function `f` is `f#n`.
+//!
+//! The `n`th operation in `f` that the speculation pass of NotWasm may speculate on has two
+//! sites: `f@n` counts how often the program reached it, and `f@n:i32` counts how often both of
+//! its operands were `i32`s.
+//!
+//! When [crate::opts::Opts::trace_coercions] is set, the `n`th coercion to or from `any` in `f`
+//! has a site too, which is named `f%n`, followed by the coercion and the position of the source
+//! code that it came from, e.g., `f%3 any -> i32 at test.js: line 2, column 9`.
+//!
+//! The names of sites depend on the order in which the compiler translates the program, so a
+//! profile is only meaningful for the program and options that produced it.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("malformed profile on line {0}: `{1}`")]
+    Malformed(usize, String),
+    #[error("the profile has a count for `{0}`, which is not in the program")]
+    NoSuchSite(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    counts: HashMap<String, u32>,
+}
+
+impl Profile {
+    pub fn parse(text: &str) -> Result<Profile, ProfileError> {
+        let mut counts = HashMap::new();
+        for (line_number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            let count = parts.next().and_then(|count| count.parse::<u32>().ok());
+            match (count, parts.next()) {
+                (Some(count), Some(site)) if !site.is_empty() => {
+                    *counts.entry(site.to_string()).or_insert(0) += count;
+                }
+                _ => return Err(ProfileError::Malformed(line_number + 1, line.to_string())),
+            }
+        }
+        Ok(Profile { counts })
+    }
+
+    /// The name of the `index`th inline cache in `func`.
+    pub fn cache_site(func: &str, index: usize) -> String {
+        format!("{}#{}", func, index)
+    }
+
+    /// The names of the sites of the `index`th speculation candidate in `func`, which count all
+    /// of its runs and the runs where both of its operands were `i32`s, respectively.
+    pub fn speculation_sites(func: &str, index: usize) -> (String, String) {
+        let site = format!("{}@{}", func, index);
+        let i32_site = format!("{}:i32", site);
+        (site, i32_site)
+    }
+
+    /// The name of the `index`th coercion in `func`, which `description` describes.
+    pub fn coercion_site(func: &str, index: usize, description: &str) -> String {
+        format!("{}%{} {}", func, index, description)
+    }
+
+    /// The number of times that the program reached `site`, which is zero for a site that the
+    /// profile does not mention.
+    pub fn count(&self, site: &str) -> u32 {
+        self.counts.get(site).copied().unwrap_or(0)
+    }
+
+    /// The sites that the program reached, from the most to the least frequent.
+    pub fn hottest(&self) -> Vec<(&str, u32)> {
+        let mut sites: Vec<_> = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(site, count)| (site.as_str(), *count))
+            .collect();
+        sites.sort_by(|(s1, c1), (s2, c2)| c2.cmp(c1).then(s1.cmp(s2)));
+        sites
+    }
+
+    /// Ensures that every function in the profile is in `functions`, which catches a profile of
+    /// a different program.
+    pub fn check<'a>(
+        &self,
+        functions: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), ProfileError> {
+        let functions: HashSet<&str> = functions.into_iter().collect();
+        for site in self.counts.keys() {
+            let func = site.splitn(2, &['#', '@', '%'][..]).next().unwrap();
+            if !functions.contains(&func) {
+                return Err(ProfileError::NoSuchSite(site.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sites: Vec<_> = self.counts.iter().collect();
+        sites.sort();
+        for (site, count) in sites {
+            writeln!(f, "{} {}", count, site)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        let profile = Profile::parse("3 main\n\n10 main#0\n2 f\n1 f\n").unwrap();
+        assert_eq!(profile.count("f"), 3);
+        assert_eq!(profile.count("g"), 0);
+        assert_eq!(
+            profile.hottest(),
+            vec![("main#0", 10), ("f", 3), ("main", 3)]
+        );
+        assert_eq!(Profile::parse(&profile.to_string()).unwrap(), profile);
+    }
+
+    #[test]
+    fn malformed_profile() {
+        assert!(matches!(
+            Profile::parse("1 main\nmain 2\n"),
+            Err(ProfileError::Malformed(2, _))
+        ));
+    }
+
+    #[test]
+    fn profile_of_another_program() {
+        let profile =
+            Profile::parse("1 main\n4 g#1\n2 g@0\n2 g@0:i32\n3 g%0 any -> i32 at line 1\n")
+                .unwrap();
+        assert!(profile.check(vec!["main", "g"]).is_ok());
+        assert!(matches!(
+            profile.check(vec!["main", "f"]),
+            Err(ProfileError::NoSuchSite(site)) if site == "g#1"
+        ));
+    }
+}
@@ -31,9 +31,26 @@ pub enum RTSFunction {
     Equal,
     StrictNotEqual,
     NotEqual,
+    // `===` and `!==` on operands that are both strings
+    StringEqual,
+    StringNotEqual,
     InstanceOf,
     In,
     BitwiseNot,
+    /// Reads a variable that the program does not declare from the global object.
+    GlobalGet,
+    // URI-encoding global functions, which the NotWasm standard library wraps in closures
+    EncodeURIComponent,
+    DecodeURIComponent,
+    Escape,
+    // Object.seal, Object.freeze, Object.isSealed, and Object.isFrozen, which the NotWasm
+    // standard library wraps in closures
+    ObjectSeal,
+    ObjectFreeze,
+    ObjectIsSealed,
+    ObjectIsFrozen,
+    /// Boxes an f64 that does not escape its function (see notwasm::escape)
+    F64ToAnyScratch,
     Import(std::string::String),
 }
 
@@ -66,13 +83,24 @@ impl RTSFunction {
             Over => Rust("janky_over".into()),
             Mod => Rust("janky_mod".into()),
             ModF64 => Rust("janky_mod_f64".into()),
-            StrictEqual => Rust("janky_strict_equal".into()),
+            StrictEqual => Rust("any_strict_eq".into()),
             Equal => Rust("janky_equal".into()),
             StrictNotEqual => Rust("janky_strict_not_equal".into()),
             NotEqual => Rust("janky_not_equal".into()),
+            StringEqual => Rust("string_eq".into()),
+            StringNotEqual => Rust("string_ne".into()),
             InstanceOf => Rust("instance_of".into()),
             In => Rust("janky_in".into()),
             BitwiseNot => Rust("janky_not".into()),
+            GlobalGet => Rust("global_get".into()),
+            EncodeURIComponent => Rust("encode_uri_component".into()),
+            DecodeURIComponent => Rust("decode_uri_component".into()),
+            Escape => Rust("janky_escape".into()),
+            ObjectSeal => Rust("object_seal".into()),
+            ObjectFreeze => Rust("object_freeze".into()),
+            ObjectIsSealed => Rust("object_is_sealed".into()),
+            ObjectIsFrozen => Rust("object_is_frozen".into()),
+            F64ToAnyScratch => Rust("f64_to_any_scratch".into()),
             Import(name) => Rust(name.clone()),
         }
     }
@@ -116,7 +144,15 @@ impl RTSFunction {
             StrictEqual | Equal | StrictNotEqual | NotEqual | In => {
                 Function(vec![Any, Any], Box::new(Bool))
             }
+            StringEqual | StringNotEqual => Function(vec![String, String], Box::new(Bool)),
             BitwiseNot => Function(vec![Int], Box::new(Int)),
+            GlobalGet => Function(vec![DynObject, String], Box::new(Any)),
+            EncodeURIComponent | DecodeURIComponent | Escape => {
+                Function(vec![Any], Box::new(String))
+            }
+            ObjectSeal | ObjectFreeze => Function(vec![Any], Box::new(Any)),
+            ObjectIsSealed | ObjectIsFrozen => Function(vec![Any], Box::new(Bool)),
+            F64ToAnyScratch => Function(vec![Float], Box::new(Any)),
             Import(..) => panic!("unimplemented function: {}", self),
         }
     }
@@ -154,9 +190,20 @@ impl std::fmt::Display for RTSFunction {
                 Equal => "==",
                 StrictNotEqual => "!==",
                 NotEqual => "!=",
+                StringEqual => "string_eq",
+                StringNotEqual => "string_ne",
                 InstanceOf => "instanceof",
                 In => "in",
                 BitwiseNot => "~",
+                GlobalGet => "global_get",
+                EncodeURIComponent => "encodeURIComponent",
+                DecodeURIComponent => "decodeURIComponent",
+                Escape => "escape",
+                ObjectSeal => "Object.seal",
+                ObjectFreeze => "Object.freeze",
+                ObjectIsSealed => "Object.isSealed",
+                ObjectIsFrozen => "Object.isFrozen",
+                F64ToAnyScratch => "f64_to_any_scratch",
                 Import(_s) => "import",
             }
         )
@@ -9,6 +9,10 @@ pub enum Id {
     Named(String),
     #[display(fmt = "{}", _0)]
     Generated(Generated),
+    /// A variable of the source program that alpha-renaming gave a new name, so that it does not
+    /// collide with another variable with the same name. We keep the original name for debugging.
+    #[display(fmt = "$jnks_{}${}", _0, _1)]
+    Renamed(String, usize),
     /// Bogus is a bit of a hack to work with the Rust type system. There are situations where it is
     /// convenient to move identifiers out of collections that are then consumed. However, they must
     /// be replaced with  another identifier. Instead of using `Option<Id>`, which is very
@@ -41,6 +45,7 @@ pub struct Generated {
 #[derive(Default)]
 pub struct NameGen {
     next_name: HashMap<&'static str, usize>,
+    next_renamed: HashMap<String, usize>,
 }
 
 impl Id {
@@ -85,4 +90,25 @@ impl NameGen {
                 .or_insert(0),
         })
     }
+
+    /// rename gives a new name to a variable of the source program that shares its name with
+    /// another variable. The new name keeps the original name, which is handy when reading
+    /// generated code, and different calls never return the same name
+    ///
+    /// ```
+    /// # use libjankscripten::javascript::*;
+    /// # let mut ng = NameGen::default();
+    /// let x = Id::from("x");
+    /// assert_eq!(ng.rename(&x).to_string(), "$jnks_x$0");
+    /// assert_eq!(ng.rename(&x).to_string(), "$jnks_x$1");
+    /// ```
+    pub fn rename(&mut self, original: &Id) -> Id {
+        let original = original.to_string();
+        let index = *self
+            .next_renamed
+            .entry(original.clone())
+            .and_modify(|i| *i += 1)
+            .or_insert(0);
+        Id::Renamed(original, index)
+    }
 }
@@ -20,6 +20,12 @@ fn methods_table() -> HashMap<(&'static str, usize), Vec<Type>> {
         //entry!(at, (string, int) -> any, (string, int) -> string),
         entry!(concat, (array, array) -> array, (string, string) -> string),
         entry!(push, (array, any) -> int),
+        // The pattern may be any value, and the replacement may also be a function
+        entry!(replace, (string, any, any) -> string),
+        // Strings are indexed by UTF-16 code units. charCodeAt is NaN out of bounds
+        entry!(charAt, (string, int) -> string),
+        entry!(charCodeAt, (string, int) -> any),
+        entry!(codePointAt, (string, int) -> any),
         // Source: https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Array
         // Array.prototype[@@unscopables] // ??
         // Array.prototype[@@iterator]() // ??
@@ -1,8 +1,14 @@
 pub mod coercions;
 mod id;
 pub mod methods;
+pub mod nondet;
+mod pragma;
+pub mod rope;
 pub mod std_lib;
 mod types;
+pub mod walk;
 
 pub use id::{Id, NameGen};
+pub use pragma::Pragma;
+pub use rope::Rope;
 pub use types::Type;
@@ -0,0 +1,21 @@
+//! The deterministic `Math.random` and `Date.now` of the interpreters.
+//!
+//! The interpreters behave like a run of the runtime system in which the host set the seed of
+//! `Math.random` to [SEED] and the virtual clock to [CLOCK] (see `runtime/src/nondet.rs` and
+//! `runtime/src/date.rs`), so they agree with such runs.
+
+/// The seed of `Math.random`.
+pub const SEED: u64 = 0;
+
+/// The time at which the virtual clock of `Date.now` starts, in milliseconds since the epoch.
+pub const CLOCK: f64 = 0.0;
+
+/// The next number in [0, 1) of the SplitMix64 generator, as in the runtime system.
+pub fn splitmix64(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,83 @@
+//! Hints from the programmer to the compiler.
+//!
+//! A pragma is either a directive in the prologue of a script or function
+//! (e.g., `"use strict";`), or a comment that starts with `jankscripten:`
+//! (e.g., `/* jankscripten: assume-int */`). The parser turns each pragma
+//! into a statement that sits immediately before the statement that it
+//! annotates, and the statement survives all the way to JankyScript, so
+//! that passes such as type inference can consume it. We drop pragmas when
+//! we compile JankyScript to NotWasm.
+
+/// The prefix that distinguishes a pragma from an ordinary comment.
+const COMMENT_PREFIX: &str = "jankscripten:";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pragma {
+    /// The `"use strict"` directive.
+    UseStrict,
+    /// `/* jankscripten: assume-int */`: the programmer promises that the
+    /// next statement only works with integers.
+    AssumeInt,
+    /// Any other `jankscripten:` comment. We keep the text after the prefix,
+    /// so that passes may define their own pragmas without changing the
+    /// parser.
+    Other(String),
+}
+
+impl Pragma {
+    /// The pragma for a directive, if we know what it means.
+    pub fn from_directive(directive: &str) -> Option<Pragma> {
+        match directive {
+            "use strict" => Some(Pragma::UseStrict),
+            _ => None,
+        }
+    }
+
+    /// Whether the pragma is a directive, which applies to the whole script
+    /// or function, so it stays at the start of its body.
+    pub fn is_directive(&self) -> bool {
+        matches!(self, Pragma::UseStrict)
+    }
+
+    /// The pragma for the text of a comment, without its delimiters, if the
+    /// comment is a pragma at all.
+    pub fn from_comment(text: &str) -> Option<Pragma> {
+        let text = text.trim();
+        if !text.starts_with(COMMENT_PREFIX) {
+            return None;
+        }
+        match text[COMMENT_PREFIX.len()..].trim() {
+            "assume-int" => Some(Pragma::AssumeInt),
+            other => Some(Pragma::Other(other.to_string())),
+        }
+    }
+}
+
+/// Prints the pragma as JavaScript, so that it can be read back in.
+impl std::fmt::Display for Pragma {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pragma::UseStrict => write!(f, "\"use strict\";"),
+            Pragma::AssumeInt => write!(f, "/* {} assume-int */", COMMENT_PREFIX),
+            Pragma::Other(text) => write!(f, "/* {} {} */", COMMENT_PREFIX, text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pragma_comments() {
+        assert_eq!(
+            Pragma::from_comment(" jankscripten: assume-int "),
+            Some(Pragma::AssumeInt)
+        );
+        assert_eq!(
+            Pragma::from_comment("jankscripten:inline"),
+            Some(Pragma::Other("inline".to_string()))
+        );
+        assert_eq!(Pragma::from_comment(" an ordinary comment "), None);
+    }
+}
@@ -0,0 +1,385 @@
+//! A data structure that supports *O(1)* append operations. See
+//! [https://cs.brown.edu/courses/cs019/2009/assignments/join_lists] for more
+//! information.
+//!
+//! A rope is a binary tree that holds its elements at its leaves. Appending two ropes creates a
+//! new node, so a long sequence of appends can produce a tree that is as deep as it is long,
+//! which makes indexing slow. Therefore, `append` rebuilds the rope into a balanced tree when it
+//! is more than twice as deep as a balanced tree (plus `SLACK`). Rebuilding takes *O(n)* time,
+//! but a rope that was just rebuilt can take many appends before it has to be rebuilt again.
+
+use std::iter::FromIterator;
+use std::ops::Index;
+
+/// A rope may be this much deeper than twice the depth of a balanced tree before `append`
+/// rebalances it, so small ropes are never rebalanced.
+const SLACK: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Rope<T> {
+    rope: R<T>,
+}
+
+#[derive(Debug, Clone)]
+enum R<T> {
+    Nil,
+    Singleton(T),
+    /// Instead of using `R::Append` directly, use the `R::join` smart
+    /// constructor. Neither side is `R::Nil`.
+    Append {
+        lhs: Box<R<T>>,
+        rhs: Box<R<T>>,
+        len: usize,
+        depth: usize,
+    },
+}
+
+/// An iterator that consumes a `Rope` and produces its values in order.
+pub struct RopeIntoIter<T> {
+    stack: Vec<R<T>>,
+    remaining: usize,
+}
+
+/// An iterator that produces references to the values of a `Rope` in order.
+pub struct RopeIter<'a, T> {
+    stack: Vec<&'a R<T>>,
+    remaining: usize,
+}
+
+/// The depth of a balanced tree with `len` leaves.
+fn balanced_depth(len: usize) -> usize {
+    8 * std::mem::size_of::<usize>() - len.saturating_sub(1).leading_zeros() as usize
+}
+
+impl<T> R<T> {
+    fn len(&self) -> usize {
+        match self {
+            R::Nil => 0,
+            R::Singleton(_) => 1,
+            R::Append { len, .. } => *len,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            R::Nil | R::Singleton(_) => 0,
+            R::Append { depth, .. } => *depth,
+        }
+    }
+
+    /// Joins two trees that are not `R::Nil`.
+    fn join(lhs: R<T>, rhs: R<T>) -> R<T> {
+        R::Append {
+            len: lhs.len() + rhs.len(),
+            depth: 1 + lhs.depth().max(rhs.depth()),
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    /// Builds a balanced tree from the next `len` items.
+    fn balanced(items: &mut impl Iterator<Item = T>, len: usize) -> R<T> {
+        match len {
+            0 => R::Nil,
+            1 => R::Singleton(items.next().expect("too few items to build rope")),
+            _ => {
+                let lhs = R::balanced(items, len / 2);
+                let rhs = R::balanced(items, len - len / 2);
+                R::join(lhs, rhs)
+            }
+        }
+    }
+}
+
+impl<T> Rope<T> {
+    pub fn new() -> Self {
+        Self::nil()
+    }
+
+    pub fn nil() -> Self {
+        Rope { rope: R::Nil }
+    }
+
+    pub fn singleton(item: T) -> Self {
+        Rope {
+            rope: R::Singleton(item),
+        }
+    }
+
+    /// The number of values in the rope. Takes *O(1)* time.
+    pub fn len(&self) -> usize {
+        self.rope.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn append(self, other: Rope<T>) -> Self {
+        match (&self.rope, &other.rope) {
+            (R::Nil, _) => other,
+            (_, R::Nil) => self,
+            _ => {
+                let rope = Rope {
+                    rope: R::join(self.rope, other.rope),
+                };
+                if rope.rope.depth() > 2 * balanced_depth(rope.len()) + SLACK {
+                    rope.balance()
+                } else {
+                    rope
+                }
+            }
+        }
+    }
+
+    /// Rebuilds the rope into a balanced tree, which makes indexing take *O(log n)* time.
+    pub fn balance(self) -> Self {
+        let len = self.len();
+        Rope {
+            rope: R::balanced(&mut self.into_iter(), len),
+        }
+    }
+
+    /// The value at `index`, or `None` if `index` is out of bounds. Takes time proportional to
+    /// the depth of the rope, which is *O(log n)* after `balance`.
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        let mut r = &self.rope;
+        loop {
+            match r {
+                R::Nil => return None,
+                R::Singleton(item) => return if index == 0 { Some(item) } else { None },
+                R::Append { lhs, rhs, len, .. } => {
+                    if index >= *len {
+                        return None;
+                    }
+                    let lhs_len = lhs.len();
+                    r = if index < lhs_len {
+                        &**lhs
+                    } else {
+                        index -= lhs_len;
+                        &**rhs
+                    };
+                }
+            }
+        }
+    }
+
+    /// Like `get`, but produces a mutable reference.
+    pub fn get_mut(&mut self, mut index: usize) -> Option<&mut T> {
+        let mut r = &mut self.rope;
+        loop {
+            match r {
+                R::Nil => return None,
+                R::Singleton(item) => return if index == 0 { Some(item) } else { None },
+                R::Append { lhs, rhs, len, .. } => {
+                    if index >= *len {
+                        return None;
+                    }
+                    let lhs_len = lhs.len();
+                    r = if index < lhs_len {
+                        &mut **lhs
+                    } else {
+                        index -= lhs_len;
+                        &mut **rhs
+                    };
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> RopeIter<'_, T> {
+        RopeIter {
+            stack: match &self.rope {
+                R::Nil => Vec::new(),
+                r => vec![r],
+            },
+            remaining: self.len(),
+        }
+    }
+}
+
+impl<T> Default for Rope<T> {
+    fn default() -> Self {
+        Self::nil()
+    }
+}
+
+impl<T> Index<usize> for Rope<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len();
+        self.get(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
+        })
+    }
+}
+
+impl<T> Iterator for RopeIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // Loop instead of recursion to avoid stack overflows.
+        loop {
+            match self.stack.pop() {
+                None => {
+                    return None;
+                }
+                Some(R::Nil) => {
+                    // This indicates a bug in the smart constructors.
+                    panic!("unexpected R::Nil in interior of Rope");
+                }
+                Some(R::Singleton(item)) => {
+                    self.remaining -= 1;
+                    return Some(item);
+                }
+                Some(R::Append { lhs, rhs, .. }) => {
+                    self.stack.push(*rhs);
+                    self.stack.push(*lhs);
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for RopeIntoIter<T> {}
+
+impl<'a, T> Iterator for RopeIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match self.stack.pop() {
+                None => {
+                    return None;
+                }
+                Some(R::Nil) => {
+                    panic!("unexpected R::Nil in interior of Rope");
+                }
+                Some(R::Singleton(item)) => {
+                    self.remaining -= 1;
+                    return Some(item);
+                }
+                Some(R::Append { lhs, rhs, .. }) => {
+                    self.stack.push(rhs);
+                    self.stack.push(lhs);
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for RopeIter<'a, T> {}
+
+impl<T> IntoIterator for Rope<T> {
+    type Item = T;
+    type IntoIter = RopeIntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let remaining = self.len();
+        RopeIntoIter {
+            stack: match self.rope {
+                R::Nil => Vec::new(),
+                r => vec![r],
+            },
+            remaining,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Rope<T> {
+    type Item = &'a T;
+    type IntoIter = RopeIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Builds a balanced rope.
+impl<T> From<Vec<T>> for Rope<T> {
+    fn from(items: Vec<T>) -> Self {
+        let len = items.len();
+        Rope {
+            rope: R::balanced(&mut items.into_iter(), len),
+        }
+    }
+}
+
+impl<T> FromIterator<T> for Rope<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Rope::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl<T> Extend<T> for Rope<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let rope = std::mem::take(self);
+        *self = rope.append(iter.into_iter().collect());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_balanced<T>(rope: &Rope<T>) -> bool {
+        rope.rope.depth() <= 2 * balanced_depth(rope.len()) + SLACK
+    }
+
+    #[test]
+    fn append_preserves_order() {
+        let rope = Rope::singleton(1)
+            .append(Rope::nil())
+            .append(Rope::from(vec![2, 3]))
+            .append(Rope::singleton(4));
+        assert_eq!(rope.len(), 4);
+        assert_eq!(rope.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(rope.into_iter().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn repeated_appends_stay_balanced() {
+        let mut left = Rope::new();
+        let mut right = Rope::new();
+        for i in 0..10_000 {
+            left = left.append(Rope::singleton(i));
+            right = Rope::singleton(9_999 - i).append(right);
+            assert!(is_balanced(&left) && is_balanced(&right));
+        }
+        for i in 0..10_000 {
+            assert_eq!(left[i], i);
+            assert_eq!(right[i], i);
+        }
+    }
+
+    #[test]
+    fn indexing() {
+        let mut rope: Rope<_> = (0..100).collect();
+        assert_eq!(rope.rope.depth(), balanced_depth(100));
+        assert_eq!(rope.get(100), None);
+        *rope.get_mut(42).unwrap() = -1;
+        assert_eq!(rope[42], -1);
+        assert_eq!(Rope::<i32>::new().get(0), None);
+    }
+
+    #[test]
+    fn extend_appends() {
+        let mut rope = Rope::singleton("a");
+        rope.extend(vec!["b", "c"]);
+        rope.extend(Vec::new());
+        assert_eq!(rope.iter().len(), 3);
+        assert_eq!(rope.into_iter().collect::<String>(), "abc");
+    }
+}
@@ -20,6 +20,10 @@ pub fn get_global_object() -> BindMap {
     // suite. Test suite should eventually move to either using __JNKS.log_any or
     // console.log
     insert(m, "log_any", typ!(fun (any, any) -> any));
+    // f(...args) becomes jnks_apply(f, this, args) (see javascript::desugar_this)
+    insert(m, "jnks_apply", typ!(fun (any, any, any, any) -> any));
+    // await e becomes jnks_await(e.op, e.arg, k) (see javascript::generators)
+    insert(m, "jnks_await", typ!(fun (any, any, any, any) -> any));
 
     // i don't know where this would be documented but i know we need it
     insert(m, "arguments", Array);
@@ -46,6 +50,7 @@ pub fn get_global_object() -> BindMap {
     // object and looking inside it for these functions, but for now this is
     // a stopgap
     insert(m, "global", DynObject);
+    insert(m, "globalThis", DynObject);
     insert(m, "window", Any);
     // Properties
     insert(m, "console", DynObject);
@@ -73,10 +78,10 @@ pub fn get_global_object() -> BindMap {
     insert(m, "ArrayBuffer", Any);
     // scala
     insert(m, "BigInt", Any);
-    insert(m, "Boolean", Any);
+    insert(m, "Boolean", Function(vec![Any, Any], Box::new(Any)));
     // maybe elm?
     insert(m, "DataView", Any);
-    insert(m, "Date", Any);
+    insert(m, "Date", DynObject);
     insert(m, "Error", Function(vec![Any, Any], Box::new(Any)));
     // ocaml
     insert(m, "EvalError", Any);
@@ -90,21 +95,23 @@ pub fn get_global_object() -> BindMap {
     insert(m, "Int32Array", Any);
     insert(m, "Int8Array", Any);
     insert(m, "JSON", Any);
+    insert(m, "Map", Any);
     insert(m, "Math", DynObject);
     insert(m, "NaN", Any);
-    insert(m, "Number", Any);
+    insert(m, "Number", DynObject);
     insert(m, "Object", DynObject);
     // maybe clojurescript?
     insert(m, "Promise", Any);
-    insert(m, "RangeError", Any);
+    insert(m, "RangeError", Function(vec![Any, Any], Box::new(Any)));
     insert(m, "ReferenceError", Any);
     insert(m, "RegExp", Any);
+    insert(m, "Set", Any);
     insert(m, "String", Any);
     // clojurescript
     insert(m, "Symbol", Any);
     // ocaml
-    insert(m, "SyntaxError", Any);
-    insert(m, "TypeError", Any);
+    insert(m, "SyntaxError", Function(vec![Any, Any], Box::new(Any)));
+    insert(m, "TypeError", Function(vec![Any, Any], Box::new(Any)));
     // there's a scala comment about this but it doesn't look actually used:
     // "The underlying Array is a TypedArray"
     insert(m, "TypedArray", Any);
@@ -119,6 +126,17 @@ pub fn get_global_object() -> BindMap {
     // i don't know why these functions aren't part of window
     // scheme
     insert(m, "eval", Any);
+    insert(
+        m,
+        "decodeURIComponent",
+        Function(vec![Any, Any], Box::new(Any)),
+    );
+    insert(
+        m,
+        "encodeURIComponent",
+        Function(vec![Any, Any], Box::new(Any)),
+    );
+    insert(m, "escape", Function(vec![Any, Any], Box::new(Any)));
     insert(m, "isFinite", Any);
     insert(m, "isNaN", Any);
     insert(m, "parseFloat", Any);
@@ -126,6 +144,12 @@ pub fn get_global_object() -> BindMap {
     // this always accepts the radix, which is normalized in
     // javascript::normalize_std_lib_calls
     insert(m, "parseInt", Function(vec![Any, Any, Any], Box::new(Any)));
+    // the callback runs after the program, and receives no arguments
+    insert(
+        m,
+        "setTimeout",
+        Function(vec![Any, Any, Any], Box::new(Any)),
+    );
     // constants
     insert(m, "undefined", Any);
     insert(m, "null", Any);
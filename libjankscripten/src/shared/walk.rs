@@ -0,0 +1,76 @@
+//! The parts of the AST visitors that JavaScript, JankyScript, and NotWasm share.
+//!
+//! Each IR has its own `walk` module, with a `Visitor` trait and a `Loc` type, since their
+//! syntax and contexts differ. However, every `Visitor` has the same shape:
+//!
+//! - `enter_*` and `exit_*` methods for each kind of node, which receive a mutable reference to
+//!   the node and an immutable reference to its `Loc`,
+//! - a `done` method that ends the walk early, and
+//! - a `Context::Block` that holds a `BlockContext`, which lets a visitor insert statements into
+//!   the enclosing block.
+use std::cell::RefCell;
+
+/// The context of a statement within a block.
+#[derive(Debug)]
+pub struct BlockContext<S> {
+    pub index: usize,
+    pub len: usize,
+    patches: RefCell<Vec<(usize, S)>>,
+}
+
+impl<S> BlockContext<S> {
+    pub fn new(index: usize, len: usize) -> Self {
+        BlockContext {
+            index,
+            len,
+            patches: RefCell::new(vec![]),
+        }
+    }
+
+    /// Insert `stmt` at position `index` into the block. The position 0 is
+    /// before the first element, and the last position is after the last
+    /// element. E.g., if the block has statements `[s0, s1, s2]` then the
+    /// indices are `[0, s0, 1, s1, 2, s2, 3]`.
+    ///
+    /// Note that you can insert multiple statements at the same index.
+    /// They will appear in the order they were added.
+    ///
+    /// Panics if the index is invalid.
+    pub fn insert(&self, index: usize, stmt: S) {
+        assert!(index <= self.len);
+        self.patches.borrow_mut().push((index, stmt));
+    }
+
+    pub(crate) fn apply_patches(mut self, block: &mut Vec<S>) {
+        // but we want patches to be applied in order they were added. because
+        // we add them in reverse order we want to iterate in reverse order
+        // Vec::sort_by maintains diff=0 values to be in their original order
+        // (stable), but we want the opposite behavior. to do this we sort in
+        // ascending order and reverse the whole iterator
+        let patches = self.patches.get_mut();
+        patches.sort_by(|(m, _), (n, _)| m.cmp(n));
+        // .rev() reverses the reverse-sorted iterator now
+        for (index, stmt) in patches.drain(0..).rev() {
+            // Inserting shifts all elements to the right. However, the
+            // indices are in descending order.
+            block.insert(index, stmt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BlockContext;
+
+    #[test]
+    fn patches_keep_insertion_order() {
+        let mut block = vec!["s0", "s1"];
+        let cxt = BlockContext::new(0, block.len());
+        cxt.insert(1, "a");
+        cxt.insert(0, "b");
+        cxt.insert(1, "c");
+        cxt.insert(2, "d");
+        cxt.apply_patches(&mut block);
+        assert_eq!(block, vec!["b", "s0", "a", "c", "s1", "d"]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Snapshot tests for lowering JavaScript to WebAssembly.
+//!
+//! Every `NAME.js` in `libjankscripten/snapshots` has three snapshots beside it: the
+//! JankyScript that we produce after closure conversion (`NAME.janky.snap`), the NotWasm that we
+//! translate it to (`NAME.notwasm.snap`), and the WebAssembly text of the compiled module
+//! (`NAME.wat.snap`). A test fails when the compiler produces different output, and writes the
+//! new output to `NAME.*.snap.new`, which makes it easy to diff.
+//!
+//! When a change to the output is intended, run the tests with `JNKS_UPDATE_SNAPSHOTS=1` to
+//! overwrite the snapshots. A missing snapshot is a failure too, so that a test cannot pass by
+//! recording whatever the compiler produces: adding a test requires adding a `.js` file, running
+//! the tests with `JNKS_UPDATE_SNAPSHOTS=1`, and committing the snapshots that they write.
+//!
+//! We renumber generated names (e.g., `$jnks_anf_12`) before comparing, so that a change to how
+//! many names a phase generates does not change every snapshot after it.
+use crate::opts::Opts;
+use crate::{jankyscript, javascript, notwasm};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The prefix of the names that `NameGen` generates.
+const GENERATED_PREFIX: &str = "$jnks_";
+
+/// Renumbers the generated names in `text` in the order in which they first occur. Names with
+/// different bases are numbered separately, so `$jnks_anf_7 $jnks_fun_3 $jnks_anf_2` becomes
+/// `$jnks_anf_0 $jnks_fun_0 $jnks_anf_1`.
+fn normalize_names(text: &str) -> String {
+    let mut renamed: HashMap<&str, String> = HashMap::new();
+    let mut next_index: HashMap<&str, usize> = HashMap::new();
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(GENERATED_PREFIX) {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + GENERATED_PREFIX.len()..];
+        let len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..len];
+        match generated_base(name) {
+            Some(base) => {
+                let renamed_name = renamed.entry(name).or_insert_with(|| {
+                    let index = next_index.entry(base).or_insert(0);
+                    *index += 1;
+                    format!("{}{}_{}", GENERATED_PREFIX, base, *index - 1)
+                });
+                output.push_str(renamed_name);
+            }
+            None => {
+                output.push_str(GENERATED_PREFIX);
+                output.push_str(name);
+            }
+        }
+        rest = &after[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The base of a generated name, which has the form `BASE_INDEX`.
+fn generated_base(name: &str) -> Option<&str> {
+    let underscore = name.rfind('_')?;
+    let index = &name[underscore + 1..];
+    if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(&name[..underscore])
+}
+
+/// The output of each phase of the compiler, after normalizing names.
+struct Lowered {
+    janky: String,
+    notwasm: String,
+    wat: String,
+}
+
+fn lower(
+    name: &str,
+    js_code: &str,
+    stdlib: &str,
+    stdlib_includes: &HashMap<String, String>,
+) -> Lowered {
+    let mut js_ast = javascript::parse(name, js_code).expect("error parsing JavaScript");
+    let mut ng = javascript::NameGen::default();
+    javascript::desugar(&mut js_ast, &mut ng);
+    let mut janky_ast = jankyscript::from_js::from_javascript(js_ast);
+    let mut opts = Opts::new();
+    jankyscript::compile(&opts, &mut janky_ast, |_| ()).expect("error compiling JankyScript");
+    let janky = janky_ast.to_string();
+    let notwasm_ast = notwasm::from_jankyscript(janky_ast);
+    let notwasm = notwasm_ast.to_string();
+    opts.notwasm_stdlib_source_code = stdlib.to_string();
+    opts.notwasm_stdlib_includes = stdlib_includes.clone();
+    let (wat, _) =
+        notwasm::compile_to_wat(&mut opts, notwasm_ast, |_| ()).expect("error compiling NotWasm");
+    Lowered {
+        janky: normalize_names(&janky),
+        notwasm: normalize_names(&notwasm),
+        wat: normalize_names(&wat),
+    }
+}
+
+/// Compares `actual` with the snapshot at `path`. Returns a description of the mismatch, if
+/// there is one.
+fn check_snapshot(path: &Path, actual: &str, update: bool) -> Option<String> {
+    let new_path = PathBuf::from(format!("{}.new", path.display()));
+    match std::fs::read_to_string(path) {
+        Ok(expected) if expected == actual => {
+            let _ = std::fs::remove_file(&new_path);
+            None
+        }
+        Ok(_) if !update => {
+            std::fs::write(&new_path, actual).expect("error writing snapshot");
+            Some(format!(
+                "{} does not match; the new output is in {}",
+                path.display(),
+                new_path.display()
+            ))
+        }
+        Err(_) if !update => {
+            std::fs::write(&new_path, actual).expect("error writing snapshot");
+            Some(format!(
+                "{} is missing; the output is in {}",
+                path.display(),
+                new_path.display()
+            ))
+        }
+        _ => {
+            std::fs::write(path, actual).expect("error writing snapshot");
+            let _ = std::fs::remove_file(&new_path);
+            None
+        }
+    }
+}
+
+#[test]
+fn normalize_renumbers_by_base() {
+    assert_eq!(
+        normalize_names("$jnks_anf_7 = $jnks_fun_3($jnks_anf_2, $jnks_anf_7);"),
+        "$jnks_anf_0 = $jnks_fun_0($jnks_anf_1, $jnks_anf_0);"
+    );
+}
+
+#[test]
+fn normalize_ignores_names_that_are_not_generated() {
+    assert_eq!(
+        normalize_names("jnks_plus($jnks_strings, $jnks_obj_this_1)"),
+        "jnks_plus($jnks_strings, $jnks_obj_this_0)"
+    );
+}
+
+#[test]
+fn lowering_matches_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let stdlib_path = root.parent().unwrap().join("stdlib.notwasm");
+    let stdlib = std::fs::read_to_string(&stdlib_path).expect("error reading stdlib.notwasm");
+    let stdlib_includes = notwasm::stdlib::read_includes(&stdlib_path)
+        .expect("error reading the files that stdlib.notwasm includes");
+    let update = std::env::var_os("JNKS_UPDATE_SNAPSHOTS").is_some();
+    let mut inputs = std::fs::read_dir(root.join("snapshots"))
+        .expect("error reading snapshots")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "js"))
+        .collect::<Vec<_>>();
+    inputs.sort();
+    let mut mismatches = Vec::new();
+    for input in inputs {
+        let js_code = std::fs::read_to_string(&input).unwrap();
+        let lowered = lower(
+            &input.display().to_string(),
+            &js_code,
+            &stdlib,
+            &stdlib_includes,
+        );
+        for (ext, actual) in &[
+            ("janky", lowered.janky),
+            ("notwasm", lowered.notwasm),
+            ("wat", lowered.wat),
+        ] {
+            let path = input.with_extension(format!("{}.snap", ext));
+            mismatches.extend(check_snapshot(&path, actual, update));
+        }
+    }
+    if !mismatches.is_empty() {
+        panic!(
+            "{}\nRun with JNKS_UPDATE_SNAPSHOTS=1 to accept the new output, and commit it.",
+            mismatches.join("\n")
+        );
+    }
+}
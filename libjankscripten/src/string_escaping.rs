@@ -128,6 +128,26 @@ pub fn unescape_string(s: &str) -> Result<String, UnescapingError> {
     return Err(err(s, offset, "missing closing quote"));
 }
 
+/// Turns a string into a double-quoted JavaScript string literal. This is the inverse of
+/// `unescape_string`.
+pub fn escape_string(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            ch if ch.is_control() => buf.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => buf.push(ch),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,4 +172,10 @@ mod tests {
     fn escaped_quotes_in_double_quote() {
         assert_eq!(unescape_string(r#""\"\'""#).unwrap(), r#""'"#);
     }
+
+    #[test]
+    fn escape_round_trips() {
+        let s = "a \"quoted\" \\ string\n\u{1}";
+        assert_eq!(unescape_string(&escape_string(s)).unwrap(), s);
+    }
 }
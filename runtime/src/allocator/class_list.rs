@@ -1,18 +1,59 @@
 //! The global classes with their offsets, transitions, and sizes
 //!
 //! Some terminology:
-//! - Class => A set of offsets and transitions
-//! - ClassList => The singe global list of classes, associated with a heap
+//! - Class => A set of offsets
+//! - ClassList => The singe global list of classes, associated with a heap,
+//!   and the transitions between them, which every object of a class shares
 //! - Object => An instance of a class, allocated on the heap with space
 //!   for every field but they may not be occupied
 
 use crate::heap_types::StringPtr;
+use std::collections::HashMap;
+
+/// Classes with more fields than this also index their offsets by name. A
+/// linear search is faster for fewer fields
+const INDEX_THRESHOLD: usize = 16;
+
+/// A site that sees objects of more classes than this is megamorphic
+const MEGAMORPHIC: usize = 4;
 
 pub struct ClassList {
     /// a HashMap to look up our class is obviously a non-starter when
     /// classes are meant to optimize HashMap lookup
     /// next class type is simply classes.len
     classes: Vec<Class>,
+    /// the class that adding a field to a class produces, by the class and
+    /// the name of the field
+    transitions: HashMap<(u16, StringPtr), u16>,
+    /// the classes that each site of an inline cache (by the address of the
+    /// cache) saw, up to one more than MEGAMORPHIC, once tracking starts
+    sites: Option<HashMap<usize, Vec<u16>>>,
+}
+
+/// What the classes of a program look like, which helps find the code that
+/// creates too many of them
+#[derive(Debug, PartialEq)]
+pub struct ClassStats {
+    pub classes: usize,
+    /// the length of the longest chain of transitions, which is the number
+    /// of fields of the largest class
+    pub max_depth: usize,
+    /// None if we did not track sites
+    pub megamorphic_sites: Option<usize>,
+}
+
+impl std::fmt::Display for ClassStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "classes: {}, max depth: {}",
+            self.classes, self.max_depth
+        )?;
+        match self.megamorphic_sites {
+            Some(n) => write!(f, ", megamorphic sites: {}", n),
+            None => write!(f, ", megamorphic sites: not tracked"),
+        }
+    }
 }
 
 impl ClassList {
@@ -20,6 +61,8 @@ impl ClassList {
         Self {
             // initialize with empty object
             classes: vec![Class::new()],
+            transitions: HashMap::new(),
+            sites: None,
         }
     }
     pub fn new_class_type(&mut self, class: Class) -> u16 {
@@ -36,23 +79,52 @@ impl ClassList {
     /// look up transitions, if none is relevant make one, and return new
     /// class tag
     pub fn transition(&mut self, class_tag: u16, name: StringPtr) -> u16 {
-        let new_tag = self.classes.len() as u16;
-        let class = &mut self.classes[class_tag as usize];
-        match class.lookup_transition(name) {
-            Some(tag) => tag,
+        match self.transitions.get(&(class_tag, name)) {
+            Some(tag) => *tag,
             None => {
-                let new_class = class.branch(name, new_tag);
-                self.new_class_type(new_class)
+                let new_class = self.classes[class_tag as usize].branch(name);
+                let new_tag = self.new_class_type(new_class);
+                self.transitions.insert((class_tag, name), new_tag);
+                new_tag
+            }
+        }
+    }
+    /// start recording the classes that each inline cache sees
+    pub fn track_sites(&mut self) {
+        if self.sites.is_none() {
+            self.sites = Some(HashMap::new());
+        }
+    }
+    /// record that the inline cache at `site` saw an object of `class_tag`,
+    /// if we are tracking sites
+    pub fn record_site(&mut self, site: usize, class_tag: u16) {
+        if let Some(sites) = &mut self.sites {
+            let seen = sites.entry(site).or_insert_with(Vec::new);
+            if seen.len() <= MEGAMORPHIC && !seen.contains(&class_tag) {
+                seen.push(class_tag);
             }
         }
     }
+    pub fn stats(&self) -> ClassStats {
+        ClassStats {
+            classes: self.classes.len(),
+            max_depth: self.classes.iter().map(|c| c.size).max().unwrap_or(0),
+            megamorphic_sites: self.sites.as_ref().map(|sites| {
+                sites
+                    .values()
+                    .filter(|seen| seen.len() > MEGAMORPHIC)
+                    .count()
+            }),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Class {
     pub size: usize,
     offsets: Vec<(StringPtr, usize)>,
-    transitions: Vec<(StringPtr, u16)>,
+    /// the offsets by name, when there are more than INDEX_THRESHOLD
+    index: Option<HashMap<StringPtr, usize>>,
 }
 impl Class {
     /// this is the very base class
@@ -61,7 +133,7 @@ impl Class {
         Self {
             size: 0,
             offsets: Vec::new(),
-            transitions: Vec::new(),
+            index: None,
         }
     }
     pub fn lookup(&self, name: StringPtr, cache: &mut isize) -> Option<usize> {
@@ -71,33 +143,35 @@ impl Class {
             // should be a flag or two functions or something
             Some(*cache as usize)
         } else {
-            self.offsets
-                .iter()
-                .find(|(offset_name, _)| offset_name == &name)
-                .map(|(_, index)| {
-                    *cache = *index as isize;
-                    *index
-                })
+            let offset = match &self.index {
+                Some(index) => index.get(&name).copied(),
+                None => self
+                    .offsets
+                    .iter()
+                    .find(|(offset_name, _)| offset_name == &name)
+                    .map(|(_, offset)| *offset),
+            };
+            if let Some(offset) = offset {
+                *cache = offset as isize;
+            }
+            offset
         }
     }
     pub fn keys(&self) -> Vec<StringPtr> {
         self.offsets.iter().map(|(s, _)| *s).collect()
     }
-    fn lookup_transition(&self, name: StringPtr) -> Option<u16> {
-        self.transitions
-            .iter()
-            // TODO: might need to match types
-            .find(|(trans_name, _)| trans_name == &name)
-            .map(|(_, index)| *index)
-    }
-    fn branch(&mut self, name: StringPtr, new_tag: u16) -> Self {
-        self.transitions.push((name, new_tag));
+    fn branch(&self, name: StringPtr) -> Self {
         let mut offsets = self.offsets.clone();
         offsets.push((name, self.size));
+        let index = if offsets.len() > INDEX_THRESHOLD {
+            Some(offsets.iter().copied().collect())
+        } else {
+            None
+        };
         Self {
             size: self.size + 1,
             offsets,
-            transitions: Vec::new(),
+            index,
         }
     }
 }
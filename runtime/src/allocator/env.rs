@@ -18,9 +18,12 @@ use crate::AnyEnum;
 ///    retrievals down. i feel fairly confident we can do hella math in
 ///    the compiler and turn EnvGet into (local.get 0, typ.load STATIC_OFFSET)
 ///
-/// Tag | u32 | Object | [EnvItem]
-///        ^      ^^
-///       len   fn_obj
+/// Tag | u32 | Object | u32  | [EnvItem]
+///        ^      ^^      ^^
+///       len   fn_obj   func
+///
+/// func is the index of the function of the closure, since a closure is just
+/// a pointer to its environment
 ///
 /// WARNING: breaking tradition with other pointer objects, this object does
 /// not handle 64-bit and 32-bit architectures differently. the tag is always
@@ -31,9 +34,14 @@ pub struct EnvPtr {
     ptr: *mut Tag,
 }
 
-const LEN_OFFSET: usize = 1;
-const FN_OBJ_OFFSET: usize = 2;
-const ENV_ITEM_OFFSET: usize = 3;
+// The offsets are in tags, since `ptr` is a `*mut Tag`.
+const LEN_OFFSET: usize = (abi::ENV_LENGTH_OFFSET / abi::TAG_SIZE) as usize;
+const FN_OBJ_OFFSET: usize = (abi::ENV_FN_OBJ_OFFSET / abi::TAG_SIZE) as usize;
+const FUNC_OFFSET: usize = (abi::ENV_FUNC_OFFSET / abi::TAG_SIZE) as usize;
+const ENV_ITEM_OFFSET: usize = (abi::ENV_ITEMS_OFFSET / abi::TAG_SIZE) as usize;
+
+#[cfg(target_pointer_width = "32")]
+abi::assert_abi!(std::mem::size_of::<ObjectPtr>() == abi::FN_OBJ_SIZE as usize);
 
 impl EnvPtr {
     /// # Safety
@@ -68,6 +76,7 @@ impl EnvPtr {
     pub unsafe fn init(ptr: *mut Tag, length: u32, fn_obj: ObjectPtr) -> Self {
         (ptr.add(LEN_OFFSET) as *mut u32).write(length);
         (ptr.add(FN_OBJ_OFFSET) as *mut ObjectPtr).write(fn_obj);
+        (ptr.add(FUNC_OFFSET) as *mut u32).write(0);
         Self { ptr }
     }
 
@@ -83,6 +92,17 @@ impl EnvPtr {
         unsafe { *(self.ptr.add(FN_OBJ_OFFSET) as *const ObjectPtr) }
     }
 
+    pub fn func(&self) -> u32 {
+        // SAFETY: the func was initialized in new, as long as it hasn't been
+        // overwritten by UB, it's still there
+        unsafe { *(self.ptr.add(FUNC_OFFSET) as *const u32) }
+    }
+
+    pub fn set_func(&mut self, func: u32) {
+        // SAFETY: the func field was allocated in new
+        unsafe { (self.ptr.add(FUNC_OFFSET) as *mut u32).write(func) }
+    }
+
     /// initialize the index field of the pointer. this must be called on
     /// every index from 0 to length - 1 before garbage collection is sound
     ///
@@ -114,7 +134,7 @@ impl HeapPtr for EnvPtr {
         self.ptr
     }
     fn get_data_size(&self, _heap: &Heap) -> usize {
-        std::mem::size_of::<EnvItem>() * self.len() + 4 + std::mem::size_of::<ObjectPtr>()
+        std::mem::size_of::<EnvItem>() * self.len() + 4 + std::mem::size_of::<ObjectPtr>() + 4
     }
     /// # Safety
     ///
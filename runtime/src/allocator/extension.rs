@@ -0,0 +1,189 @@
+//! Heap types that the allocator does not define itself, e.g., the values of
+//! host objects, or of classes of the standard library such as `Date`
+//!
+//! An extension is a Rust type that implements [HeapExtension]. After
+//! [Heap::register_extension], the heap allocates values of the type with
+//! [Heap::alloc_extension]. Such a value is laid out as follows:
+//!
+//! Tag | &ExtensionType | T
+//!
+//! The `type_tag` of the tag is `TypeTag::Extension` and its `class_tag` is
+//! the number of the extension. The value points to the functions that the
+//! garbage collector calls on it, so the allocator never needs to know `T`.
+//! The size of the value is the size of `T`, and the garbage collector drops
+//! it with the `Drop` of `T`, which must not allocate (see [Heap]).
+
+use super::constants::*;
+use super::heap_values::*;
+use super::layout;
+use super::Heap;
+use std::any::TypeId;
+
+/// A type that the heap may hold
+pub trait HeapExtension: Sized + 'static {
+    /// the name of the type, for debugging and for `toString`
+    const NAME: &'static str;
+    /// must supply if might have pointers!! (see HasTag::get_data_ptrs)
+    fn get_data_ptrs(&self, _heap: &Heap) -> (Vec<*mut Tag>, Vec<*mut *const f64>) {
+        (vec![], vec![])
+    }
+}
+
+/// The functions of an extension, which every value of the extension points
+/// to
+pub struct ExtensionType {
+    pub name: &'static str,
+    type_id: TypeId,
+    /// the number of the extension, which is the class_tag of its values
+    number: u16,
+    /// the size of the data that follows the tag
+    data_size: usize,
+    get_data_ptrs: fn(*mut Tag, &Heap) -> (Vec<*mut Tag>, Vec<*mut *const f64>),
+    drop: unsafe fn(*mut Tag),
+}
+
+/// The data that follows the tag of a value of an extension
+#[repr(C)]
+struct ExtensionData<T> {
+    extension: &'static ExtensionType,
+    value: T,
+}
+
+fn data_ptrs_of<T: HeapExtension>(
+    ptr: *mut Tag,
+    heap: &Heap,
+) -> (Vec<*mut Tag>, Vec<*mut *const f64>) {
+    let data = unsafe { &*data_ptr::<ExtensionData<T>>(ptr) };
+    data.value.get_data_ptrs(heap)
+}
+
+unsafe fn drop_data<T>(ptr: *mut Tag) {
+    std::ptr::drop_in_place(data_ptr::<ExtensionData<T>>(ptr))
+}
+
+impl Heap {
+    /// Makes `T` a heap type, and returns the number of the extension.
+    /// Registering a type again returns the same number.
+    pub fn register_extension<T: HeapExtension>(&self) -> u16 {
+        if let Some(extension) = self.extension::<T>() {
+            return extension.number;
+        }
+        let mut extensions = self.extensions.borrow_mut();
+        let number = extensions.len() as u16;
+        // the values of the extension point to this, and the heap never
+        // forgets an extension, so it lives as long as the program
+        extensions.push(Box::leak(Box::new(ExtensionType {
+            name: T::NAME,
+            type_id: TypeId::of::<T>(),
+            number,
+            data_size: layout::layout_aligned::<ExtensionData<T>>(ALIGNMENT).size(),
+            get_data_ptrs: data_ptrs_of::<T>,
+            drop: drop_data::<T>,
+        })));
+        number
+    }
+
+    fn extension<T: HeapExtension>(&self) -> Option<&'static ExtensionType> {
+        let extensions = self.extensions.borrow();
+        extensions
+            .iter()
+            .find(|extension| extension.type_id == TypeId::of::<T>())
+            .copied()
+    }
+
+    /// Allocates a value of a registered extension, or returns it if the
+    /// heap is full
+    pub fn alloc_extension<T: HeapExtension>(&self, value: T) -> Result<ExtensionPtr, T> {
+        let extension = match self.extension::<T>() {
+            Some(extension) => extension,
+            None => log_panic!("{} is not a registered heap type", T::NAME),
+        };
+        let ptr = match self.alloc_raw(self.tag_size + extension.data_size as isize) {
+            Some(ptr) => ptr,
+            None => return Err(value),
+        };
+        unsafe {
+            ptr.write(Tag {
+                marked: false,
+                type_tag: TypeTag::Extension,
+                class_tag: extension.number,
+            });
+            data_ptr::<ExtensionData<T>>(ptr).write(ExtensionData { extension, value });
+        }
+        Ok(ExtensionPtr { ptr })
+    }
+
+    pub fn alloc_extension_or_gc<T: HeapExtension>(&self, value: T) -> ExtensionPtr {
+        let mut value = value;
+        loop {
+            match self.alloc_extension(value) {
+                Ok(ptr) => return ptr,
+                Err(v) => value = v,
+            }
+            self.make_room(
+                self.tag_size
+                    + layout::layout_aligned::<ExtensionData<T>>(ALIGNMENT).size() as isize,
+            );
+        }
+    }
+}
+
+/// A pointer to a value of any extension
+#[derive(Clone, Copy, PartialEq)]
+#[repr(transparent)]
+pub struct ExtensionPtr {
+    ptr: *mut Tag,
+}
+
+impl ExtensionPtr {
+    /// # Safety
+    ///
+    /// ptr should point to the tag of a value that
+    /// [Heap::alloc_extension] allocated
+    pub unsafe fn new(ptr: *mut Tag) -> Self {
+        Self { ptr }
+    }
+
+    pub fn extension(&self) -> &'static ExtensionType {
+        // the reference is the first field of ExtensionData whatever T is
+        unsafe { *data_ptr::<&'static ExtensionType>(self.ptr) }
+    }
+
+    /// the value, if it has type `T`
+    pub fn get<T: HeapExtension>(&self) -> Option<&T> {
+        if self.extension().type_id == TypeId::of::<T>() {
+            Some(unsafe { &(*data_ptr::<ExtensionData<T>>(self.ptr)).value })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut<T: HeapExtension>(&mut self) -> Option<&mut T> {
+        if self.extension().type_id == TypeId::of::<T>() {
+            Some(unsafe { &mut (*data_ptr::<ExtensionData<T>>(self.ptr)).value })
+        } else {
+            None
+        }
+    }
+}
+
+impl HeapPtr for ExtensionPtr {
+    fn get_ptr(&self) -> *mut Tag {
+        self.ptr
+    }
+    fn get_data_size(&self, _heap: &Heap) -> usize {
+        self.extension().data_size
+    }
+    fn final_drop(&self) {
+        unsafe { (self.extension().drop)(self.ptr) }
+    }
+    fn get_gc_ptrs(&self, heap: &Heap) -> (Vec<*mut Tag>, Vec<*mut *const f64>) {
+        (self.extension().get_data_ptrs)(self.ptr, heap)
+    }
+}
+
+impl std::fmt::Debug for ExtensionPtr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}({:?})", self.extension().name, self.ptr)
+    }
+}
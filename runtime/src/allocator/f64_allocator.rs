@@ -1,46 +1,172 @@
+/// The number of f64s in each semispace at first. The semispaces grow when most f64s survive a
+/// collection (see [Heap::alloc_f64_or_gc](super::Heap::alloc_f64_or_gc)).
 const LEN: usize = 1000;
 
+/// The number of recently allocated f64s that [F64Allocator::alloc] may
+/// reuse. Must be a power of two.
+const CACHE_LEN: usize = 64;
+
+/// The number of f64s in the scratch area
+const SCRATCH_LEN: usize = 256;
+
 /// An `F64Allocator` is a heap that only stores f64s.  When we allocate an f64,
 /// it returns a `*const f64`. Since pointers are 32-bits wide in WebAssembly,
 /// we can store it in a 64-bit integer value, and still have room left for tag
 /// bits. Thus, we store `*const f64` in our `Any` values, which allows an `Any`
 /// to be a 64-bit integer that does not need to be heap allocated.
+///
+/// Nothing writes through the pointer to a boxed f64 (a `ref` of an f64 is a
+/// heap value of its own), so boxes with the same bits are interchangeable,
+/// and the allocator reuses a recent box instead of allocating another one.
+///
+/// The allocator also has a scratch area, which is a stack of boxes for f64s
+/// that do not escape the function that boxes them (see
+/// [F64Allocator::alloc_scratch]), e.g., the operands of arithmetic on `any`.
+/// The garbage collector does not copy these boxes, since nothing on the heap
+/// points to them.
+///
+/// The semispaces have `len` f64s each. Growing them cannot move the current
+/// space, since the program holds pointers into it, so [F64Allocator::grow]
+/// only changes `len`, and [F64Allocator::semispace_swap] resizes the other
+/// space, which nothing points into, before the garbage collector copies the
+/// live f64s to it.
 pub struct F64Allocator {
-    // TODO(arjun): No more that one thousand floats.
-    current_space: Box<[f64; LEN]>,
-    other_space: Box<[f64; LEN]>,
+    current_space: Vec<f64>,
+    other_space: Vec<f64>,
+    len: usize,
     next_slot: usize,
+    /// one more than the slot of a recent f64, by the hash of its bits, or 0
+    cache: [usize; CACHE_LEN],
+    scratch: Box<[f64; SCRATCH_LEN]>,
+    scratch_top: usize,
+    stats: F64Stats,
+}
+
+/// How the program boxes f64s
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct F64Stats {
+    /// boxes in a semispace
+    pub allocated: usize,
+    /// allocations that reused a box with the same bits
+    pub reused: usize,
+    /// boxes in the scratch area
+    pub scratch: usize,
+    /// boxes that the garbage collector copied to the other semispace
+    pub copied: usize,
+}
+
+impl std::fmt::Display for F64Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "f64 boxes allocated: {}, reused: {}, scratch: {}, copied: {}",
+            self.allocated, self.reused, self.scratch, self.copied
+        )
+    }
+}
+
+fn cache_index(value: f64) -> usize {
+    // Fibonacci hashing, since small integers and halves only differ in the
+    // high bits
+    (value.to_bits().wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 58) as usize & (CACHE_LEN - 1)
 }
 
 impl F64Allocator {
     pub fn new() -> Self {
         let next_slot = 0;
-        let current_space = Box::new([0.0; LEN]);
-        let other_space = Box::new([0.0; LEN]);
+        let current_space = vec![0.0; LEN];
+        let other_space = vec![0.0; LEN];
         return F64Allocator {
             current_space,
             other_space,
+            len: LEN,
             next_slot,
+            cache: [0; CACHE_LEN],
+            scratch: Box::new([0.0; SCRATCH_LEN]),
+            scratch_top: 0,
+            stats: F64Stats::default(),
         };
     }
 
     /// Note that we don't have a function to read the value of a float.
     pub fn alloc(&mut self, value: f64) -> Option<*const f64> {
-        assert!(self.next_slot <= LEN);
-        let index = self.next_slot;
-        self.next_slot += 1;
-        if self.next_slot == LEN {
+        let cached = self.cache[cache_index(value)];
+        if cached != 0 && self.current_space[cached - 1].to_bits() == value.to_bits() {
+            self.stats.reused += 1;
+            return Some(&self.current_space[cached - 1] as *const f64);
+        }
+        if self.next_slot == self.current_space.len() {
             return None;
         }
+        let index = self.next_slot;
+        self.next_slot += 1;
+        self.stats.allocated += 1;
+        self.cache[cache_index(value)] = index + 1;
         let f64_ref = unsafe { self.current_space.get_unchecked_mut(index) };
         *f64_ref = value;
         return Some(f64_ref as *const f64);
     }
 
+    /// Like [F64Allocator::alloc], for the garbage collector, which copies
+    /// the live f64s of the other space
+    pub fn copy(&mut self, value: f64) -> Option<*const f64> {
+        let ptr = self.alloc(value)?;
+        self.stats.copied += 1;
+        Some(ptr)
+    }
+
+    /// Boxes `value` in the scratch area, which [F64Allocator::scratch_exit]
+    /// frees, or produces `None` if the area is full
+    pub fn alloc_scratch(&mut self, value: f64) -> Option<*const f64> {
+        let f64_ref = self.scratch.get_mut(self.scratch_top)?;
+        *f64_ref = value;
+        self.scratch_top += 1;
+        self.stats.scratch += 1;
+        Some(f64_ref as *const f64)
+    }
+
+    /// the top of the scratch area, which [F64Allocator::scratch_exit]
+    /// restores
+    pub fn scratch_enter(&self) -> usize {
+        self.scratch_top
+    }
+
+    /// frees the boxes that the scratch area allocated after `top` was
+    /// produced by [F64Allocator::scratch_enter]
+    pub fn scratch_exit(&mut self, top: usize) {
+        debug_assert!(top <= self.scratch_top);
+        self.scratch_top = top;
+    }
+
+    pub fn stats(&self) -> F64Stats {
+        self.stats
+    }
+
+    /// Produces `true` if more than half of the current space is in use, which
+    /// right after a collection means that most f64s are live.
+    pub fn mostly_full(&self) -> bool {
+        2 * self.next_slot > self.current_space.len()
+    }
+
+    /// The number of bytes that [F64Allocator::grow] adds to the semispaces.
+    pub fn growth_bytes(&self) -> isize {
+        (2 * self.len * std::mem::size_of::<f64>()) as isize
+    }
+
+    /// Doubles the length of the semispaces, which takes effect when they
+    /// swap.
+    pub fn grow(&mut self) {
+        self.len *= 2;
+    }
+
     /// Swaps semispaces. All subsequent allocations will occur in the other
     /// space, and start from the first slot.
     pub fn semispace_swap(&mut self) {
+        if self.other_space.len() != self.len {
+            self.other_space = vec![0.0; self.len];
+        }
         std::mem::swap(&mut self.current_space, &mut self.other_space);
         self.next_slot = 0;
+        self.cache = [0; CACHE_LEN];
     }
 }
@@ -1,9 +1,11 @@
 pub use super::env::EnvPtr;
+pub use super::extension::{ExtensionPtr, HeapExtension};
 pub use super::object_ptr::{ObjectDataPtr, ObjectPtr};
 pub use super::string::StringPtr;
 use super::{AnyPtr, HeapPtr, Tag, TypePtr, TypeTag};
-use crate::{AnyEnum, AnyValue, Heap, Key};
-use std::collections::HashMap;
+use crate::ht::HT;
+use crate::{AnyEnum, AnyValue, Heap};
+use std::iter::once;
 
 pub trait HasTag {
     const TYPE_TAG: TypeTag;
@@ -22,11 +24,12 @@ impl HasTag for ObjectDataPtr {
     const TYPE_TAG: TypeTag = TypeTag::ObjectPtrPtr;
 }
 
-pub type HTPtr = TypePtr<HashMap<Key, AnyValue>>;
-impl HasTag for HashMap<Key, AnyValue> {
+pub type HTPtr = TypePtr<HT>;
+impl HasTag for HT {
     const TYPE_TAG: TypeTag = TypeTag::HT;
     fn get_data_ptrs(&self, _: &Heap) -> (Vec<*mut Tag>, Vec<*mut *const f64>) {
-        AnyEnum::iter_to_ptrs(self.values().map(|x| &**x))
+        // Keys may be objects too
+        AnyEnum::iter_to_ptrs(self.iter().flat_map(|(k, v)| once(&**k).chain(once(&**v))))
     }
 }
 
@@ -54,8 +57,9 @@ impl HasTag for AnyValue {
 }
 pub type MutF64Ptr = TypePtr<f64>;
 impl HasTag for f64 {
-    // TODO(luna): is this right?? What happened to MutF64?
-    const TYPE_TAG: TypeTag = TypeTag::Ptr;
+    // not Ptr, or the garbage collector would trace the bits of the f64 as a
+    // pointer
+    const TYPE_TAG: TypeTag = TypeTag::MutF64;
 }
 pub type PtrPtr = TypePtr<AnyPtr>;
 impl HasTag for AnyPtr {
@@ -16,7 +16,9 @@ use std::ops::{Deref, DerefMut};
 pub struct Tag {
     pub marked: bool,
     pub type_tag: TypeTag,
-    /// The `class_tag` is only meaningful if the `type_tag == TypeTag::DynObject`.
+    /// The `class_tag` is only meaningful if the `type_tag == TypeTag::DynObject`. The tag of an
+    /// `ObjectPtrPtr` has no class, so it holds whether the object is sealed or frozen instead
+    /// (see [ObjectPtr::seal]), and the tag of an `Extension` holds the number of its extension.
     pub class_tag: u16,
 }
 
@@ -38,42 +40,11 @@ impl Tag {
     }
 }
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-#[repr(u8)]
-/// We specify some values as an ABI with the compiler. In particular we need
-/// to discriminate on the type of anys and make strings
-///
-/// Most of these are *not* values and do *not* appear in Any
-pub enum TypeTag {
-    Array = 0,
-    /// We specify a value so we can make tags for strings from jankscripten
-    String = 1,
-    /// This is a DynObject from the perspective of JankyScript
-    HT = 2,
-    /// This is an actual object. In practice, it's just a pointer to the
-    /// actual object data, but when choosing what type this is, it's just an
-    /// object. i think
-    ObjectPtrPtr = 3,
-    /// This is *not* the tag of an object (poor naming!!). This is the tag
-    /// of the *object data*, which is one level of indirection behind the actual
-    /// object (does it even need a tag? what the heck is going on)
-    DynObject,
-    /// This only appears as part of
-    Env,
-    /// following are immediate values only ever on the heap for Ref
-    /// this is any 32-bit value on the heap that doesn't point to / store
-    /// other values. Examples: i32, bool, fn. These won't appear in Any
-    /// (right??) because they'd be dereffed before being coerced
-    NonPtr32,
-    Any,
-    /// these should only be used for Ref, most f64s go on the f64 heap. this
-    /// avoids another layer of indirection we just put a f64 immediately
-    /// following the tag. that f64 might be modified
-    /// TODO(luna): I'm pretty sure this is never used. Something is wrong
-    MutF64,
-    /// this may or may not be duplicated by ObjectPtrPtr
-    Ptr,
-}
+// We specify some values as an ABI with the compiler. In particular we need
+// to discriminate on the type of anys and make strings
+pub use abi::TypeTag;
+
+abi::assert_abi!(std::mem::size_of::<Tag>() == abi::TAG_SIZE as usize);
 
 /// Every pointer into the heap points to a tag, thus we could build an API
 /// where every function consumes and produces `*mut Tag`-typed values. However,
@@ -131,6 +102,7 @@ pub enum HeapRefView {
     NonPtr32(NonPtr32Ptr),
     MutF64(MutF64Ptr),
     Ptr(PtrPtr),
+    Extension(ExtensionPtr),
 }
 impl HeapRefView {
     /// Return a less specific `HeapPtr` that points to the same heap value,
@@ -147,6 +119,7 @@ impl HeapRefView {
             Self::NonPtr32(val) => val,
             Self::MutF64(val) => val,
             Self::Ptr(val) => val,
+            Self::Extension(val) => val,
         }
     }
 }
@@ -196,6 +169,7 @@ impl AnyPtr {
                 }
                 TypeTag::MutF64 => HeapRefView::MutF64(MutF64Ptr::new_tag_unchecked(self.ptr)),
                 TypeTag::Ptr => HeapRefView::Ptr(PtrPtr::new_tag_unchecked(self.ptr)),
+                TypeTag::Extension => HeapRefView::Extension(ExtensionPtr::new(self.ptr)),
             }
         }
     }
@@ -1,6 +1,7 @@
 //! managed allocation. most allocations should be made through [Heap]
 
 use crate::{AnyEnum, AnyValue};
+use abi::TrapReason;
 use std::alloc;
 use std::alloc::Layout;
 use std::cell::{Cell, RefCell};
@@ -8,11 +9,12 @@ use std::mem;
 mod class_list;
 mod constants;
 mod env;
+mod extension;
 mod heap_values;
 mod layout;
 mod object_ptr;
+mod scratch;
 mod string;
-use crate::util::*;
 
 pub mod heap_types;
 pub use heap_values::AnyPtr;
@@ -27,6 +29,7 @@ use heap_types::*;
 pub use heap_values::Tag;
 pub use heap_values::TypeTag;
 use heap_values::*;
+use scratch::Scratch;
 
 #[cfg(test)]
 mod tests;
@@ -36,20 +39,68 @@ mod tests;
 ///
 /// When running a program, we have a single global Heap ([crate::heap()]).
 /// However, during testing, we create several heaps.
+///
+/// # Reentrancy
+///
+/// The whole runtime shares the heap (`&Heap`), so its mutable state is in
+/// `Cell`s and `RefCell`s. A runtime function may reenter the heap at almost
+/// any point (e.g., an allocation may collect garbage, which reads classes and
+/// runs destructors), so the heap follows two rules to ensure that a borrow
+/// never panics:
+///
+/// 1. A borrow of a `RefCell` field never lasts across a call that may use
+///    the heap, including [Heap::gc], allocation, `HeapPtr` methods, and
+///    destructors. Borrow the field, read or update it, and drop the borrow.
+///    Code outside this module that borrows `classes` must do the same.
+/// 2. Nothing allocates or collects garbage while the garbage collector runs,
+///    since marking and sweeping assume that the heap does not change. The
+///    heap tracks its [Phase] and ends the program if this happens, so a
+///    destructor that `final_drop` runs must not allocate.
+///
+/// These rules are also what a future target with wasm threads needs: the
+/// cells would become locks, and no lock would be held across a call that
+/// may take it again.
 pub struct Heap {
     pub f64_allocator: RefCell<F64Allocator>,
-    size: isize,
+    size: Cell<isize>,
+    /// The heap may grow up to this size when garbage collection does not free enough memory for
+    /// an allocation. A heap that may not grow has `max_size == size`.
+    max_size: isize,
     // an estimate of the memory used by the rust allocator to support this
     // heap, based on allocation sizes, but not layout
     used_mem: Cell<isize>,
     alloc_list: RefCell<AllocList>,
     tag_size: isize,
     pub classes: RefCell<ClassList>,
+    /// The heap types that are not built in (see [extension])
+    extensions: RefCell<Vec<&'static extension::ExtensionType>>,
     /// We initialize this to the empty stack. Before calling [Heap::gc()], the
     /// shadow stack must contain all GC roots.
     shadow_stack: RefCell<Vec<Vec<Option<*mut Tag>>>>,
+    /// Objects that do not escape the function that allocates them
+    scratch: Scratch,
+    phase: Cell<Phase>,
+}
+
+/// The size of the scratch region in bytes. When it is full, the heap allocates objects that do
+/// not escape as usual.
+const SCRATCH_SIZE: usize = 64 * 1024;
+
+/// What the heap is doing, which determines what it may do next
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// The program is running, so it may allocate and collect garbage
+    Mutator,
+    /// The garbage collector is marking reachable blocks
+    Mark,
+    /// The garbage collector is freeing unmarked blocks
+    Sweep,
 }
 
+/// The blocks that the heap has allocated, which the sweep phase traverses to find unmarked
+/// blocks. The heap does not manage free memory itself: it frees blocks with the Rust allocator,
+/// which coalesces free blocks.
+///
 /// https://rust-unofficial.github.io/too-many-lists/second-option.html
 #[derive(Debug)]
 struct AllocList {
@@ -74,7 +125,7 @@ impl AllocList {
         }));
     }
 
-    /// Returns map from sizes to counts of everything in the free list
+    /// Returns map from sizes to counts of every allocated block
     fn histogram(&self, heap: &Heap) -> std::collections::BTreeMap<usize, usize> {
         let mut map = std::collections::BTreeMap::new();
         let mut to = &self.list;
@@ -92,6 +143,15 @@ impl AllocList {
     }
 }
 
+/// the size of an environment with `length` items, not including the tag
+fn env_data_size(length: u32) -> usize {
+    // + 4 for the length (not the tag, which isn't included)
+    mem::size_of::<AnyEnum>() * length as usize // EnvItems
+        + 4  // Len
+        + mem::size_of::<ObjectPtr>() // Fn obj pointer
+        + 4 // Func
+}
+
 /// allocate the number of bytes using the rust allocator, with an alignment of
 /// 4, and return the address
 fn alloc_raw(bytes: isize) -> *mut Tag {
@@ -113,28 +173,51 @@ impl Heap {
     /// 2. The occupied memory of the heap is merely an estimate, and may be
     ///    more or less depending on how rust deals with things
     pub fn new(size: isize) -> Self {
+        Heap::new_growable(size, size)
+    }
+
+    /// Create a new heap with the given approximate size, which may grow up to `max_size` when
+    /// garbage collection does not free enough memory for an allocation
+    pub fn new_growable(size: isize, max_size: isize) -> Self {
+        debug_assert!(size <= max_size);
         let f64_allocator = RefCell::new(F64Allocator::new());
         let used_mem = Cell::new(0);
         let alloc_list = RefCell::new(AllocList::new());
         let tag_size = layout::layout_aligned::<Tag>(ALIGNMENT).size() as isize;
         let classes = RefCell::new(ClassList::new());
         let shadow_stack = RefCell::new(vec![]);
+        let scratch = Scratch::new(SCRATCH_SIZE);
+        let phase = Cell::new(Phase::Mutator);
         return Heap {
             f64_allocator,
-            size,
+            size: Cell::new(size),
+            max_size,
             used_mem,
             alloc_list,
             tag_size,
             classes,
+            extensions: RefCell::new(vec![]),
             shadow_stack,
+            scratch,
+            phase,
         };
     }
 
+    /// ends the program if the garbage collector is running, since `action`
+    /// would change the heap while the garbage collector traverses it
+    fn check_mutator(&self, action: &str) {
+        let phase = self.phase.get();
+        if phase != Phase::Mutator {
+            log_panic!("cannot {} during garbage collection ({:?})", action, phase);
+        }
+    }
+
     /// if there is enough space to [`alloc_raw`], **increase used_mem by that
     /// amount, add entry to alloc_list** and return the address. otherwise,
     /// return None
     fn alloc_raw(&self, bytes: isize) -> Option<*mut Tag> {
-        if (self.size - self.used_mem.get()) < bytes {
+        self.check_mutator("allocate");
+        if (self.size.get() - self.used_mem.get()) < bytes {
             None
         } else {
             self.used_mem.set(self.used_mem.get() + bytes);
@@ -148,16 +231,56 @@ impl Heap {
         AnyEnum::F64(self.alloc_f64_or_gc(x)).into()
     }
 
+    /// Like [Heap::f64_to_any], but boxes `x` in the scratch area of the
+    /// f64 allocator, which frees it when the function that boxes it calls
+    /// [Heap::f64_scratch_exit]. If the area is full, `x` is boxed as usual.
+    pub fn f64_to_any_scratch(&self, x: f64) -> AnyValue {
+        let opt_ptr = self.f64_allocator.borrow_mut().alloc_scratch(x);
+        match opt_ptr {
+            Some(ptr) => AnyEnum::F64(ptr).into(),
+            None => self.f64_to_any(x),
+        }
+    }
+
+    /// the top of the f64 scratch area, which the function that is starting
+    /// passes to [Heap::f64_scratch_exit] when it returns
+    pub fn f64_scratch_enter(&self) -> usize {
+        self.f64_allocator.borrow().scratch_enter()
+    }
+
+    /// frees the f64s that the returning function boxed in the scratch area
+    pub fn f64_scratch_exit(&self, top: usize) {
+        self.f64_allocator.borrow_mut().scratch_exit(top);
+    }
+
+    /// Boxes `x`, and collects garbage if the current semispace of the f64 allocator is full. When
+    /// most f64s survive the collection, the program would soon collect again, so the semispaces
+    /// grow, if the heap may grow by their new size (see [Heap::grow]). If the semispace is still
+    /// full, this raises a `RangeError` (see [Heap::out_of_memory]).
     pub fn alloc_f64_or_gc(&self, x: f64) -> *const f64 {
-        let mut opt_ptr = self.f64_allocator.borrow_mut().alloc(x);
-        if let None = opt_ptr {
+        self.check_mutator("allocate");
+        // each borrow of the f64_allocator ends before self.gc borrows it
+        if let Some(ptr) = self.f64_allocator.borrow_mut().alloc(x) {
+            return ptr;
+        }
+        self.gc();
+        let (mostly_full, bytes) = {
+            let f64_allocator = self.f64_allocator.borrow();
+            (f64_allocator.mostly_full(), f64_allocator.growth_bytes())
+        };
+        if mostly_full && self.used_mem.get() + bytes <= self.max_size {
+            self.grow(bytes);
+            // The semispaces are not blocks of the heap, but they count as used memory.
+            self.used_mem.set(self.used_mem.get() + bytes);
+            self.f64_allocator.borrow_mut().grow();
+            // The collection copies the live f64s to the larger semispace.
             self.gc();
-            // TODO(arjun): This is a design flaw. We have to borrow_mut again,
-            // because self.gc also borrows the f64_allocator. Frankly, we
-            // should use a raw pointer.
-            opt_ptr = self.f64_allocator.borrow_mut().alloc(x);
         }
-        unwrap_log(opt_ptr, "out of f64 memory")
+        let opt_ptr = self.f64_allocator.borrow_mut().alloc(x);
+        match opt_ptr {
+            Some(ptr) => ptr,
+            None => self.out_of_memory(mem::size_of::<f64>() as isize),
+        }
     }
 
     /**
@@ -170,13 +293,60 @@ impl Heap {
         self.alloc_tag(T::get_tag(), value)
     }
     pub fn alloc_or_gc<T: HasTag + std::fmt::Debug>(&self, value: T) -> TypePtr<T> {
-        match self.alloc(value) {
-            Ok(ptr) => ptr,
-            Err(value) => {
-                self.gc();
-                // TODO(luna): grow?
-                self.alloc(value).expect("out of memory even after gc")
+        let mut value = value;
+        loop {
+            match self.alloc(value) {
+                Ok(ptr) => return ptr,
+                Err(v) => value = v,
+            }
+            self.make_room(self.tag_size + TypePtr::<T>::size());
+        }
+    }
+
+    /// Makes room for an allocation of `bytes` bytes that failed. This collects garbage, and if
+    /// that does not free enough memory, grows the heap (see [Heap::grow]).
+    fn make_room(&self, bytes: isize) {
+        self.gc();
+        self.grow(bytes);
+    }
+
+    /// Grows the heap, up to its maximum size, so that `bytes` more bytes fit. If they cannot fit,
+    /// this raises a `RangeError` (see [Heap::out_of_memory]).
+    fn grow(&self, bytes: isize) {
+        let needed = self.used_mem.get() + bytes;
+        if needed <= self.size.get() {
+            return;
+        }
+        if needed > self.max_size {
+            self.out_of_memory(bytes);
+        }
+        let new_size = needed.max(2 * self.size.get()).min(self.max_size);
+        error!("===== GROWING HEAP TO {} =====", new_size);
+        self.size.set(new_size);
+    }
+
+    /// Ends the program, since an allocation of `bytes` bytes cannot fit. NotWasm does not support
+    /// exceptions yet, so the program cannot catch the error. Instead, the runtime system records
+    /// [TrapReason::OutOfMemory] and traps, and the host reports a `RangeError` (see bin/run.js).
+    fn out_of_memory(&self, bytes: isize) -> ! {
+        crate::trap::set_reason(TrapReason::OutOfMemory);
+        log_panic!(
+            "RangeError: out of memory allocating {} bytes ({}/{} used)",
+            bytes,
+            self.used_mem.get(),
+            self.max_size
+        )
+    }
+
+    /// Calls `alloc` until it succeeds, and makes room for `bytes` bytes after every failure.
+    /// `bytes` must be at least the total size of the allocations that `alloc` makes, or this
+    /// may not terminate.
+    fn alloc_or_make_room<T>(&self, bytes: isize, mut alloc: impl FnMut() -> Option<T>) -> T {
+        loop {
+            if let Some(ptr) = alloc() {
+                return ptr;
             }
+            self.make_room(bytes);
         }
     }
     fn alloc_tag<T>(&self, tag: Tag, value: T) -> Result<TypePtr<T>, T> {
@@ -201,27 +371,48 @@ impl Heap {
         Some(unsafe { ObjectPtr::new(HeapPtr::get_ptr(&self.alloc(object_data).ok()?)) })
     }
     pub fn alloc_object_or_gc(&self, type_tag: u16) -> ObjectPtr {
-        // TODO(luna): alloc_object isn't really as atomic as it needs to be
-        match self.alloc_object(type_tag) {
-            Some(ptr) => ptr,
-            None => {
-                self.gc();
-                // TODO(luna): grow?
-                self.alloc_object(type_tag)
-                    .expect("out of memory even after gc")
+        // alloc_object allocates twice, and if the second allocation fails, the garbage collector
+        // frees the first one, so we make room for both
+        let bytes = self.alloc_object_data_bytes(type_tag)
+            + self.tag_size
+            + TypePtr::<ObjectDataPtr>::size();
+        self.alloc_or_make_room(bytes, || self.alloc_object(type_tag))
+    }
+    /// Allocates an object whose header is in the scratch region, which frees it when the
+    /// function that allocates it calls [Heap::scratch_exit]. If the region is full, the object
+    /// is on the heap instead.
+    pub fn alloc_scratch_object_or_gc(&self, type_tag: u16) -> ObjectPtr {
+        // allocate the fields first, since that may collect garbage, which reads every header in
+        // the scratch region
+        let object_data = self.alloc_object_data_or_gc(type_tag);
+        let bytes = self.tag_size + TypePtr::<ObjectDataPtr>::size();
+        match self.scratch.alloc(bytes as usize) {
+            // the fields are garbage, and the next collection frees them
+            None => self.alloc_object_or_gc(type_tag),
+            Some(tag_ptr) => {
+                let tag = ObjectDataPtr::get_tag();
+                unsafe { tag_ptr.write(tag) };
+                let ptr = TypePtr::new(tag_ptr, tag.type_tag, object_data);
+                unsafe { ObjectPtr::new(HeapPtr::get_ptr(&ptr)) }
             }
         }
     }
+    /// the top of the scratch region, which the function that is starting passes to
+    /// [Heap::scratch_exit] when it returns
+    pub fn scratch_enter(&self) -> usize {
+        self.scratch.top()
+    }
+    /// frees the objects that the returning function allocated in the scratch region
+    pub fn scratch_exit(&self, top: usize) {
+        self.scratch.reset(top);
+    }
     pub fn alloc_object_data_or_gc(&self, type_tag: u16) -> ObjectDataPtr {
-        match self.alloc_object_data(type_tag) {
-            Some(ptr) => ptr,
-            None => {
-                self.gc();
-                // TODO(luna): grow?
-                self.alloc_object_data(type_tag)
-                    .expect("out of memory even after gc")
-            }
-        }
+        let bytes = self.alloc_object_data_bytes(type_tag);
+        self.alloc_or_make_room(bytes, || self.alloc_object_data(type_tag))
+    }
+    /// the number of bytes that alloc_object_data allocates, including the tag
+    fn alloc_object_data_bytes(&self, type_tag: u16) -> isize {
+        self.tag_size + self.object_data_size(self.get_class_size(type_tag)) as isize
     }
     fn alloc_object_data(&self, type_tag: u16) -> Option<ObjectDataPtr> {
         let num_elements = self.get_class_size(type_tag);
@@ -235,35 +426,33 @@ impl Heap {
     }
     pub fn alloc_str(&self, s: &str) -> Option<StringPtr> {
         let from_str = s.as_ptr();
-        // + 4 for the length (not the tag, which isn't included)
-        let size = s.len() + 4;
+        // + 8 for the size and the length (not the tag, which isn't included)
+        let size = s.len() + 8;
+        let utf16_len = if s.is_ascii() {
+            s.len()
+        } else {
+            s.encode_utf16().count()
+        };
         unsafe {
             let tag_ptr = self.alloc_slice(Tag::with_type(TypeTag::String), size as isize)?;
             let len_ptr = tag_ptr.add(DATA_OFFSET) as *mut u32;
             len_ptr.write(u32::to_le(s.len() as u32));
-            let into_str = len_ptr.add(1) as *mut u8;
+            len_ptr.add(1).write(u32::to_le(utf16_len as u32));
+            let into_str = len_ptr.add(2) as *mut u8;
             std::ptr::copy_nonoverlapping(from_str, into_str, s.len());
             Some(StringPtr::new(tag_ptr))
         }
     }
     pub fn alloc_str_or_gc(&self, s: &str) -> StringPtr {
-        match self.alloc_str(s) {
-            Some(ptr) => ptr,
-            None => {
-                self.gc();
-                // TODO(luna): grow?
-                self.alloc_str(s).expect("out of memory even after gc")
-            }
-        }
+        // + 8 for the size and the length
+        let bytes = self.tag_size + s.len() as isize + 8;
+        self.alloc_or_make_room(bytes, || self.alloc_str(s))
     }
     /// # Safety
     ///
     /// [alloc_env_or_gc]
     unsafe fn alloc_env(&self, length: u32, fn_obj: ObjectPtr) -> Option<EnvPtr> {
-        // + 4 for the length (not the tag, which isn't included)
-        let size = mem::size_of::<AnyEnum>() * length as usize // EnvItems
-            + 4  // Len
-            + mem::size_of::<ObjectPtr>(); // Fn obj pointer
+        let size = env_data_size(length);
         let tag_ptr = self.alloc_slice(Tag::with_type(TypeTag::Env), size as isize)?;
         Some(EnvPtr::init(tag_ptr, length, fn_obj))
     }
@@ -272,14 +461,8 @@ impl Heap {
     /// this is unsafe for the same reason as EnvPtr::new(); it makes GC do
     /// UB unless/until you fill in the environment with values
     pub unsafe fn alloc_env_or_gc(&self, length: u32, fn_obj: ObjectPtr) -> EnvPtr {
-        match self.alloc_env(length, fn_obj) {
-            Some(ptr) => ptr,
-            None => {
-                self.gc();
-                self.alloc_env(length, fn_obj)
-                    .expect("out of memory even after gc")
-            }
-        }
+        let bytes = self.tag_size + env_data_size(length) as isize;
+        self.alloc_or_make_room(bytes, || self.alloc_env(length, fn_obj))
     }
     /// allocate a tag immediately followed by slice of memory of a fixed
     /// size in bytes
@@ -337,15 +520,8 @@ impl Heap {
     /// if push_shadow_frame / pop_shadow_frame / set_in_current_shadow_frame_slot were
     /// used correctly (tagged unsafe), this is safe
     pub fn gc(&self) {
-        let roots = self
-            .shadow_stack
-            .borrow()
-            .iter()
-            .flatten()
-            .flatten()
-            .map(|refptr| *refptr)
-            .collect::<Vec<*mut Tag>>();
-        self.mark_phase(roots, |_| ());
+        self.check_mutator("collect garbage");
+        self.mark_phase(self.roots(), |_| ());
         self.sweep_phase();
     }
 
@@ -354,16 +530,9 @@ impl Heap {
     /// if push_shadow_frame / pop_shadow_frame / set_in_current_shadow_frame_slot were
     /// used correctly (tagged unsafe), this is safe
     pub fn heap_dump(&self) {
-        let roots = self
-            .shadow_stack
-            .borrow()
-            .iter()
-            .flatten()
-            .flatten()
-            .map(|refptr| *refptr)
-            .collect::<Vec<*mut Tag>>();
+        self.check_mutator("dump the heap");
         log!("===== BEGIN JANKYSCRIPT HEAP DUMP =====");
-        self.mark_phase(roots, |any| {
+        self.mark_phase(self.roots(), |any| {
             log!("{:x?}    {:?}", any.get_ptr(), any.view())
         });
         log!("=====    BEGIN HEAP DUMP SWEEP    =====");
@@ -371,13 +540,30 @@ impl Heap {
         log!("=====  END JANKYSCRIPT HEAP DUMP  =====");
     }
 
+    /// the GC roots in the shadow stack, copied so that the shadow stack is
+    /// not borrowed during garbage collection
+    fn roots(&self) -> Vec<*mut Tag> {
+        self.shadow_stack
+            .borrow()
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|refptr| *refptr)
+            .collect::<Vec<*mut Tag>>()
+    }
+
+    /// Marks every block that is reachable from `roots`. The tag of a block
+    /// determines its type (see [AnyPtr::view]), and each type lists the
+    /// blocks and f64s that it points to with [HeapPtr::get_gc_ptrs], so
+    /// every container (objects, arrays, hash tables, environments, refs, and
+    /// extensions) is traced precisely.
     fn mark_phase(&self, roots: Vec<*mut Tag>, dump: impl Fn(AnyPtr)) {
         error!("=====    BEGIN JANKYPSCRIPT GC    =====");
+        self.phase.set(Phase::Mark);
         let mut current_roots = roots;
         let mut new_roots = Vec::<*mut Tag>::new();
 
-        let mut f64_allocator = self.f64_allocator.borrow_mut();
-        f64_allocator.semispace_swap();
+        self.f64_allocator.borrow_mut().semispace_swap();
 
         // this is just for debugging, but i'm going to leave it in because i
         // don't foresee not needing it any time soon
@@ -397,8 +583,11 @@ impl Heap {
 
                 let (mut tags, f64s) = any_ptr.get_gc_ptrs(self);
                 new_roots.append(&mut tags);
+                // get_gc_ptrs may use the heap, so we only borrow the
+                // f64_allocator after it returns
+                let mut f64_allocator = self.f64_allocator.borrow_mut();
                 for ptr in f64s {
-                    unsafe { *ptr = f64_allocator.alloc(**ptr).unwrap() }
+                    unsafe { *ptr = f64_allocator.copy(**ptr).unwrap() }
                 }
             }
             mem::swap(&mut current_roots, &mut new_roots);
@@ -407,8 +596,13 @@ impl Heap {
     }
 
     fn sweep_phase(&self) {
+        self.phase.set(Phase::Sweep);
         let mut count = 0;
-        let mut to = &mut self.alloc_list.borrow_mut().list;
+        // we take the list out of the heap instead of borrowing it, since
+        // get_data_size and final_drop may use the heap. nothing allocates
+        // during the sweep phase, so nothing else uses the list
+        let mut list = self.alloc_list.borrow_mut().list.take();
+        let mut to = &mut list;
         while let Some(ref item) = to {
             let ptr = item.tag;
             unsafe {
@@ -426,23 +620,33 @@ impl Heap {
                     );
                     self.used_mem.set(self.used_mem.get() - size as isize);
                     count += 1;
-                    // remove from the free list
+                    // remove from the allocation list
                     *to = to.take().unwrap().next;
                 }
             }
         }
+        self.alloc_list.borrow_mut().list = list;
+        // the mark phase also marks the live objects in the scratch region
+        self.scratch
+            .unmark((self.tag_size + TypePtr::<ObjectDataPtr>::size()) as usize);
+        self.phase.set(Phase::Mutator);
         error!(
             "===== FREED {} OBJECTS. {}/{} USED =====",
             count,
             self.used_mem.get(),
-            self.size
+            self.size.get()
         );
         error!("=====      END JANKYSCRIPT GC     =====");
     }
 
-    /// for debugging. print info about free vs allocated memory
+    /// the number of bytes that the heap has allocated and not freed
+    pub fn used(&self) -> usize {
+        self.used_mem.get() as usize
+    }
+
+    /// for debugging. print a histogram of the sizes of allocated blocks
     pub fn mem_info(&self) {
         let hist = self.alloc_list.borrow().histogram(self);
-        error!("FREE LIST HIST\n{:#?}\nEND", hist);
+        error!("ALLOC LIST HIST\n{:#?}\nEND", hist);
     }
 }
@@ -31,6 +31,10 @@ pub struct ObjectDataPtr {
     ptr: *mut Tag,
 }
 
+abi::assert_abi!(DATA_OFFSET * std::mem::size_of::<Tag>() == abi::OBJECT_DATA_OFFSET as usize);
+abi::assert_abi!(DATA_OFFSET * std::mem::size_of::<Tag>() == abi::OBJECT_SLOTS_OFFSET as usize);
+abi::assert_abi!(std::mem::size_of::<Option<AnyEnum>>() == abi::OBJECT_SLOT_SIZE as usize);
+
 impl HeapPtr for ObjectDataPtr {
     fn get_ptr(&self) -> *mut Tag {
         return self.ptr;
@@ -169,6 +173,21 @@ impl ObjectDataPtr {
         return AnyEnum::Undefined;
     }
 
+    /// Produces `true` if the object itself has a field named `name`, ignoring its prototype
+    /// chain.
+    pub fn has_own_property(&self, heap: &Heap, name: StringPtr) -> bool {
+        self.get_class(heap).lookup(name, &mut -1).is_some()
+    }
+
+    /// The fields of the object itself, in the order in which the program added them.
+    pub fn own_fields(&self, heap: &Heap) -> Vec<(StringPtr, AnyEnum)> {
+        let keys = self.get_class(heap).keys();
+        keys.into_iter()
+            .enumerate()
+            .filter_map(|(index, name)| self.read_at(heap, index).map(|value| (name, value)))
+            .collect()
+    }
+
     fn as_array(&self, heap: &Heap) -> &mut [Option<AnyEnum>] {
         let num_ptrs = heap.get_class_size(self.class_tag());
         let members_ptr: *mut Option<AnyEnum> = unsafe { data_ptr(self.ptr) };
@@ -183,10 +202,39 @@ impl ObjectDataPtr {
     }
 }
 
+/// The bits of the tag of an `ObjectPtrPtr` that restrict writes to the object.
+const SEALED: u16 = 1;
+const FROZEN: u16 = 2;
+
 impl ObjectPtr {
     pub const unsafe fn new(ptr: *mut Tag) -> Self {
         Self { ptr }
     }
+
+    /// Prevents adding fields to the object (`Object.seal`). The tag of the pointer holds this,
+    /// since the data of the object moves when its class changes.
+    pub fn seal(&self) {
+        unsafe { (*self.ptr).class_tag |= SEALED }
+    }
+
+    /// Prevents adding fields to the object and changing its fields (`Object.freeze`).
+    pub fn freeze(&self) {
+        unsafe { (*self.ptr).class_tag |= SEALED | FROZEN }
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        unsafe { (*self.ptr).class_tag & SEALED != 0 }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        unsafe { (*self.ptr).class_tag & FROZEN != 0 }
+    }
+
+    /// Whether writing the field `name` would change the object, which it does not when the
+    /// object is frozen, or when it is sealed and does not have the field.
+    pub fn is_writable(&self, heap: &Heap, name: StringPtr) -> bool {
+        !self.is_frozen() && (!self.is_sealed() || self.has_own_property(heap, name))
+    }
     /// if name is found, write to it. if not, transition, clone, write, and
     /// update pointer
     /// TODO: updating this pointer in particular isn't enough. i think we
@@ -265,4 +313,22 @@ mod test {
     fn object_ptr_size() {
         assert_eq!(std::mem::size_of::<ObjectPtr>(), 4);
     }
+
+    /// The compiler stores anys into the slots of object literals directly (see
+    /// abi::OBJECT_SLOT_SIZE), so a slot that holds a value must have the representation of the
+    /// value.
+    #[wasm_bindgen_test]
+    fn slot_is_any() {
+        let values = [
+            AnyEnum::I32(-7),
+            AnyEnum::Bool(true),
+            AnyEnum::Undefined,
+            AnyEnum::Null,
+        ];
+        for value in values.iter() {
+            let slot: u64 = unsafe { std::mem::transmute(Some(*value)) };
+            let any: u64 = unsafe { std::mem::transmute(*value) };
+            assert_eq!(slot, any, "{:?}", value);
+        }
+    }
 }
@@ -0,0 +1,72 @@
+//! A region for objects that do not escape the function that allocates them
+
+use super::heap_values::Tag;
+use std::cell::Cell;
+
+/// The compiler uses escape analysis to find objects that the program cannot reach after the
+/// function that allocates them returns. Instead of allocating these objects on the garbage
+/// collected heap, the function allocates them in the scratch region, which is a stack: a
+/// function saves the top of the region when it starts, and restores it when it returns, which
+/// frees all of its objects at once.
+///
+/// The region only holds the headers of objects (see [super::heap_types::ObjectPtr]), which all
+/// have the same size. The fields of an object are still on the heap, since adding a field to an
+/// object allocates new fields. The header of a live object is always in a shadow stack slot, so
+/// the garbage collector marks it like any other root, and [Scratch::unmark] resets the marks
+/// after garbage collection, since the sweep phase only visits the heap.
+pub struct Scratch {
+    /// The memory of the region. `Cell` allows us to write to it through a shared reference, and
+    /// `usize` aligns it.
+    memory: Box<[Cell<usize>]>,
+    /// The number of bytes in use
+    top: Cell<usize>,
+}
+
+impl Scratch {
+    pub fn new(bytes: usize) -> Self {
+        let words = bytes / std::mem::size_of::<usize>();
+        Scratch {
+            memory: (0..words).map(|_| Cell::new(0)).collect(),
+            top: Cell::new(0),
+        }
+    }
+
+    fn base(&self) -> *mut u8 {
+        self.memory.as_ptr() as *mut u8
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.len() * std::mem::size_of::<usize>()
+    }
+
+    /// the number of bytes in use, which [Scratch::reset] restores
+    pub fn top(&self) -> usize {
+        self.top.get()
+    }
+
+    /// frees every block that was allocated after `top` was produced by [Scratch::top]
+    pub fn reset(&self, top: usize) {
+        debug_assert!(top <= self.top.get());
+        self.top.set(top);
+    }
+
+    /// allocates `bytes` bytes, which must be a multiple of the alignment, or produces `None` if
+    /// the region is full
+    pub fn alloc(&self, bytes: usize) -> Option<*mut Tag> {
+        let top = self.top.get();
+        if self.capacity() - top < bytes {
+            return None;
+        }
+        self.top.set(top + bytes);
+        Some(unsafe { self.base().add(top) } as *mut Tag)
+    }
+
+    /// clears the mark of every block, which all have size `block_size`
+    pub fn unmark(&self, block_size: usize) {
+        let mut offset = 0;
+        while offset < self.top.get() {
+            unsafe { (*(self.base().add(offset) as *mut Tag)).marked = false };
+            offset += block_size;
+        }
+    }
+}
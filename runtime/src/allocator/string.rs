@@ -7,46 +7,81 @@ use std::ops::Deref;
 /// A managed thin pointer to a string. Interned strings are also tagged
 /// despite not being managed so this can always be used
 ///
-/// Tag | Size (LE 32) | str (utf-8)
+/// Tag | Size (LE 32) | Length (LE 32) | str (utf-8)
+///
+/// The size counts bytes, and the length counts UTF-16 code units, which is what JavaScript
+/// indexes strings by (see abi::STRING_LENGTH_OFFSET).
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct StringPtr {
     ptr: *mut Tag,
 }
 
+#[cfg(target_pointer_width = "32")]
+abi::assert_abi!(DATA_OFFSET * std::mem::size_of::<Tag>() == abi::STRING_SIZE_OFFSET as usize);
+#[cfg(target_pointer_width = "32")]
+abi::assert_abi!(
+    (DATA_OFFSET + STRING_HEADER) * std::mem::size_of::<Tag>() == abi::STRING_BYTES_OFFSET as usize
+);
+
+/// The number of tag-sized words between the data of a string and its bytes: the size and the
+/// length.
+pub const STRING_HEADER: usize = 2;
+
 impl StringPtr {
     /// # Safety
     ///
     /// ptr should point to a valid String tag, followed by a little-endian
-    /// 4-byte length encoding in memory; it should be aligned, then followed
-    /// by strictly that length of a utf-8 encoded string. if the string is
+    /// 4-byte size and a little-endian 4-byte length in UTF-16 code units;
+    /// it should be aligned, then followed by strictly that size of a utf-8
+    /// encoded string. if the string is
     /// not utf-8 encoded, Deref will turn it to a &str unchecked, and
     /// undefined behavior will result
     pub const unsafe fn new(ptr: *mut Tag) -> Self {
         Self { ptr }
     }
+    /// The size of the string in bytes
     pub fn len(&self) -> usize {
         u32::from_le(unsafe { *(self.ptr.add(DATA_OFFSET) as *const u32) }) as usize
     }
+    /// The length of the string in UTF-16 code units
+    pub fn utf16_len(&self) -> usize {
+        u32::from_le(unsafe { *(self.ptr.add(DATA_OFFSET + 1) as *const u32) }) as usize
+    }
+    /// whether every character is ASCII, so that code units are bytes
+    fn is_ascii(&self) -> bool {
+        self.len() == self.utf16_len()
+    }
+    /// String.prototype.slice, which indexes code units. Since a Rust string
+    /// cannot hold half of a surrogate pair, a slice that splits one holds
+    /// U+FFFD instead
     pub fn slice(&self, a: i32, b: i32) -> Self {
-        let a = if a < 0 { todo!() } else { a as usize };
-        let b = if b < 0 { todo!() } else { b as usize };
-        let a = if a > self.len() { self.len() } else { a };
-        let b = if b > self.len() {
-            self.len()
-        } else if b < a {
-            a
-        } else {
-            b
+        let len = self.utf16_len();
+        let clamp = |i: i32| {
+            let i = if i < 0 {
+                i as i64 + len as i64
+            } else {
+                i as i64
+            };
+            i.max(0).min(len as i64) as usize
         };
-        let rust_str = unsafe {
-            let start = self.ptr.add(DATA_OFFSET + 1) as *const u8;
-            // TODO(luna): unicode....
-            let first_char = start.add(a);
-            let length = b - a;
-            std::str::from_utf8_unchecked(std::slice::from_raw_parts(first_char, length))
-        };
-        heap().alloc_str_or_gc(rust_str)
+        let a = clamp(a);
+        let b = clamp(b).max(a);
+        if self.is_ascii() {
+            let rust_str = &self[a..b];
+            heap().alloc_str_or_gc(rust_str)
+        } else {
+            let units: Vec<u16> = self.encode_utf16().collect();
+            heap().alloc_str_or_gc(&String::from_utf16_lossy(&units[a..b]))
+        }
+    }
+    /// the code unit at `index`, if there is one
+    pub fn code_unit_at(&self, index: usize) -> Option<u16> {
+        if self.is_ascii() {
+            self.as_bytes().get(index).map(|b| *b as u16)
+        } else {
+            self.encode_utf16().nth(index)
+        }
     }
 }
 impl HeapPtr for StringPtr {
@@ -54,7 +89,7 @@ impl HeapPtr for StringPtr {
         self.ptr
     }
     fn get_data_size(&self, _heap: &Heap) -> usize {
-        self.len() + 4
+        self.len() + 4 * STRING_HEADER
     }
 }
 /// gain all the methods of string slices
@@ -64,7 +99,7 @@ impl Deref for StringPtr {
         // SAFETY: [StringPtr::new] being unsafe guarantees that we must be
         // well-formed
         unsafe {
-            let ptr = self.ptr.add(DATA_OFFSET + 1) as *const u8;
+            let ptr = self.ptr.add(DATA_OFFSET + STRING_HEADER) as *const u8;
             std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, self.len()))
         }
     }
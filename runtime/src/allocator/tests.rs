@@ -1,4 +1,5 @@
-use super::class_list::Class;
+use super::class_list::{Class, ClassStats};
+use super::f64_allocator::F64Stats;
 use super::*;
 use wasm_bindgen_test::*;
 
@@ -93,6 +94,48 @@ fn alloc_or_gc_gcs() {
     );
 }
 
+#[test]
+#[wasm_bindgen_test]
+fn alloc_or_gc_grows_heap() {
+    let heap = Heap::new_growable((ALIGNMENT * 4) as isize, (ALIGNMENT * 16) as isize);
+    heap.push_shadow_frame(4);
+    // every value is a root, so the garbage collector cannot free any of them
+    let xs = (0..4)
+        .map(|i| {
+            let x = heap.alloc_or_gc(i);
+            heap.set_in_current_shadow_frame_slot(i as usize, Some(x.get_ptr()));
+            x
+        })
+        .collect::<Vec<_>>();
+    for (i, x) in xs.iter().enumerate() {
+        assert_eq!(
+            *x.get(),
+            i as i32,
+            "growing the heap corrupted a value on the heap"
+        );
+    }
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn alloc_f64_grows_semispaces() {
+    let heap = Heap::new_growable((ALIGNMENT * 8) as isize, 1 << 20);
+    heap.push_shadow_frame(1);
+    let mut arr: TypePtr<Vec<AnyValue>> = heap.alloc_or_gc(Vec::new());
+    heap.set_in_current_shadow_frame_slot(0, Some(arr.get_ptr()));
+    // every f64 is live, so the semispaces of the first size cannot hold them
+    for i in 0..3000 {
+        let x = heap.f64_to_any(i as f64 + 0.5);
+        arr.push(x);
+    }
+    for (i, x) in arr.iter().enumerate() {
+        match **x {
+            AnyEnum::F64(ptr) => assert_eq!(unsafe { *ptr }, i as f64 + 0.5),
+            _ => panic!("not an f64"),
+        }
+    }
+}
+
 #[test]
 #[wasm_bindgen_test]
 fn array_members_marked() {
@@ -161,6 +204,47 @@ fn object_members_marked() {
     heap.alloc(12).expect("now an int has been freed");
 }
 
+#[test]
+#[wasm_bindgen_test]
+fn scratch_exit_frees_objects() {
+    let heap = Heap::new(256);
+    let top = heap.scratch_enter();
+    let obj = heap.alloc_scratch_object_or_gc(0);
+    assert_ne!(
+        heap.scratch_enter(),
+        top,
+        "object should be in the scratch region"
+    );
+    heap.scratch_exit(top);
+    let reused = heap.alloc_scratch_object_or_gc(0);
+    assert_eq!(obj.get_ptr(), reused.get_ptr());
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn scratch_object_members_marked() {
+    let heap = Heap::new(256);
+    // the roots will be: the object and the name of its field
+    heap.push_shadow_frame(2);
+    let top = heap.scratch_enter();
+    let mut obj = heap.alloc_scratch_object_or_gc(0);
+    heap.set_in_current_shadow_frame_slot(0, Some(obj.get_ptr()));
+    let x = heap.alloc_str_or_gc("x");
+    heap.set_in_current_shadow_frame_slot(1, Some(x.get_ptr()));
+    let value = heap.alloc_str_or_gc("value");
+    obj.insert(&heap, x, AnyEnum::Ptr(value.as_any_ptr()).into(), &mut -1);
+    let used_mem = heap.used_mem.get();
+    // the second collection only marks the fields if the first one unmarked the object
+    heap.gc();
+    heap.gc();
+    assert_eq!(
+        heap.used_mem.get(),
+        used_mem,
+        "nothing should have been freed"
+    );
+    heap.scratch_exit(top);
+}
+
 #[test]
 #[wasm_bindgen_test]
 fn gc_f64s() {
@@ -175,6 +259,113 @@ fn gc_f64s() {
     assert_ne!(x_copy, arr[0]);
 }
 
+#[test]
+#[wasm_bindgen_test]
+fn f64_reuse_and_scratch() {
+    let heap = Heap::new((ALIGNMENT * 6) as isize);
+    let x = heap.f64_to_any(1.5);
+    assert_eq!(heap.f64_to_any(1.5), x);
+    assert_ne!(heap.f64_to_any(2.5), x);
+    let top = heap.f64_scratch_enter();
+    let y = heap.f64_to_any_scratch(1.5);
+    assert_ne!(y, x);
+    match *y {
+        AnyEnum::F64(ptr) => assert_eq!(unsafe { *ptr }, 1.5),
+        _ => panic!("not an f64"),
+    }
+    heap.f64_scratch_exit(top);
+    assert_eq!(heap.f64_scratch_enter(), top);
+    // the collector copies the live boxes and forgets the others
+    heap.gc();
+    let stats = heap.f64_allocator.borrow().stats();
+    assert_eq!(
+        stats,
+        F64Stats {
+            allocated: 2,
+            reused: 1,
+            scratch: 1,
+            copied: 0,
+        }
+    );
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn env_members_marked() {
+    let heap = Heap::new((ALIGNMENT * 64) as isize);
+    heap.push_shadow_frame(1);
+    let fn_obj = heap.alloc_object_or_gc(0);
+    let x = heap.alloc_str("captured").unwrap();
+    let env = unsafe {
+        let mut env = heap.alloc_env_or_gc(2, fn_obj);
+        env.init_at(0, AnyEnum::Ptr(x.as_any_ptr()));
+        env.init_at(1, AnyEnum::I32(5));
+        env
+    };
+    heap.set_in_current_shadow_frame_slot(0, Some(env.get_ptr()));
+    let used = heap.used_mem.get();
+    heap.gc();
+    // the environment keeps the string and the function object alive
+    assert_eq!(heap.used_mem.get(), used);
+    assert_eq!(&*x, "captured");
+    heap.set_in_current_shadow_frame_slot(0, None);
+    heap.gc();
+    assert_eq!(heap.used_mem.get(), 0);
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn ht_members_marked() {
+    let heap = Heap::new((ALIGNMENT * 64) as isize);
+    heap.push_shadow_frame(1);
+    let mut ht: TypePtr<crate::ht::HT> = heap.alloc(Default::default()).unwrap();
+    heap.set_in_current_shadow_frame_slot(0, Some(ht.get_ptr()));
+    let key = heap.alloc_object_or_gc(0);
+    let value = heap.alloc_str("value").unwrap();
+    ht.insert(
+        AnyEnum::Ptr(key.as_any_ptr()).into(),
+        AnyEnum::Ptr(value.as_any_ptr()).into(),
+    );
+    let used = heap.used_mem.get();
+    heap.gc();
+    // both the keys and the values are reachable
+    assert_eq!(heap.used_mem.get(), used);
+    ht.clear();
+    heap.gc();
+    // only the table is left
+    assert_eq!(
+        heap.used_mem.get(),
+        heap.tag_size + TypePtr::<crate::ht::HT>::size()
+    );
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn refs_marked() {
+    let heap = Heap::new((ALIGNMENT * 64) as isize);
+    heap.push_shadow_frame(3);
+    let x = heap.alloc_str("referent").unwrap();
+    let any_ref: TypePtr<AnyValue> = heap.alloc(AnyEnum::Ptr(x.as_any_ptr()).into()).unwrap();
+    heap.set_in_current_shadow_frame_slot(0, Some(any_ref.get_ptr()));
+    let y = heap.alloc_str("pointee").unwrap();
+    let ptr_ref: TypePtr<AnyPtr> = heap.alloc(y.as_any_ptr()).unwrap();
+    heap.set_in_current_shadow_frame_slot(1, Some(ptr_ref.get_ptr()));
+    // the bits of this f64 are not a pointer, which the garbage collector
+    // must not follow
+    let f64_ref: TypePtr<f64> = heap.alloc(f64::from_bits(y.get_ptr() as u64 + 1)).unwrap();
+    heap.set_in_current_shadow_frame_slot(2, Some(f64_ref.get_ptr()));
+    let used = heap.used_mem.get();
+    heap.gc();
+    assert_eq!(heap.used_mem.get(), used);
+    assert_eq!(&*x, "referent");
+    assert_eq!(&*y, "pointee");
+    for slot in 0..3 {
+        heap.set_in_current_shadow_frame_slot(slot, None);
+    }
+    heap.gc();
+    assert_eq!(heap.used_mem.get(), 0);
+}
+
 #[test]
 #[wasm_bindgen_test]
 fn update_prims() {
@@ -264,6 +455,116 @@ fn alloc_container2() {
     }
 }
 
+#[test]
+#[wasm_bindgen_test]
+fn wide_class_lookup() {
+    let heap = Heap::new((ALIGNMENT * 128) as isize);
+    let mut type_tag = heap.classes.borrow_mut().new_class_type(Class::new());
+    let names = (0..40)
+        .map(|i| heap.alloc_str(&format!("field{}", i)).unwrap())
+        .collect::<Vec<_>>();
+    for name in &names {
+        type_tag = heap.classes.borrow_mut().transition(type_tag, *name);
+    }
+    let classes = heap.classes.borrow();
+    let class = classes.get_class(type_tag);
+    for (i, name) in names.iter().enumerate() {
+        let copy = heap.alloc_str(&format!("field{}", i)).unwrap();
+        assert_eq!(class.lookup(*name, &mut -1), Some(i));
+        assert_eq!(class.lookup(copy, &mut -1), Some(i));
+    }
+    let missing = heap.alloc_str("field40").unwrap();
+    assert_eq!(class.lookup(missing, &mut -1), None);
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn class_transitions_and_stats() {
+    let heap = Heap::new((ALIGNMENT * 64) as isize);
+    let x = heap.alloc_str("x").unwrap();
+    let y = heap.alloc_str("y").unwrap();
+    let mut classes = heap.classes.borrow_mut();
+    let xy = classes.transition(0, x);
+    let xy = classes.transition(xy, y);
+    let yx = classes.transition(0, y);
+    let yx = classes.transition(yx, x);
+    assert_ne!(xy, yx);
+    let x_again = classes.transition(0, heap.alloc_str("x").unwrap());
+    assert_eq!(classes.transition(x_again, y), xy);
+    classes.record_site(1000, xy);
+    assert_eq!(
+        classes.stats(),
+        ClassStats {
+            classes: 5,
+            max_depth: 2,
+            megamorphic_sites: None,
+        }
+    );
+    classes.track_sites();
+    let mut tag = 0;
+    for name in &["a", "b", "c", "d", "e"] {
+        tag = classes.transition(tag, heap.alloc_str(name).unwrap());
+        classes.record_site(1000, tag);
+        classes.record_site(2000, xy);
+    }
+    let stats = classes.stats();
+    assert_eq!(stats.max_depth, 5);
+    assert_eq!(stats.megamorphic_sites, Some(1));
+}
+
+/// An extension that holds a value, and counts how many times the garbage
+/// collector dropped a holder
+struct Holder {
+    value: AnyValue,
+    drops: std::rc::Rc<Cell<usize>>,
+}
+
+impl HeapExtension for Holder {
+    const NAME: &'static str = "Holder";
+    fn get_data_ptrs(&self, _: &Heap) -> (Vec<*mut Tag>, Vec<*mut *const f64>) {
+        AnyEnum::iter_to_ptrs(std::iter::once(&*self.value))
+    }
+}
+
+impl Drop for Holder {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
+
+#[test]
+#[wasm_bindgen_test]
+fn extension_members_marked() {
+    let heap = Heap::new((ALIGNMENT * 64) as isize);
+    heap.push_shadow_frame(1);
+    let number = heap.register_extension::<Holder>();
+    assert_eq!(heap.register_extension::<Holder>(), number);
+    let drops = std::rc::Rc::new(Cell::new(0));
+    let s = heap.alloc_str("held").unwrap();
+    let value = AnyEnum::Ptr(s.as_any_ptr()).into();
+    let holder = heap
+        .alloc_extension(Holder {
+            value,
+            drops: drops.clone(),
+        })
+        .ok()
+        .expect("extension allocation failed");
+    heap.set_in_current_shadow_frame_slot(0, Some(holder.get_ptr()));
+    match holder.as_any_ptr().view() {
+        HeapRefView::Extension(e) => assert_eq!(e.get::<Holder>().unwrap().value, value),
+        _ => panic!("not an extension"),
+    }
+    let used = heap.used_mem.get();
+    heap.gc();
+    // the holder keeps the string alive
+    assert_eq!(heap.used_mem.get(), used);
+    assert_eq!(drops.get(), 0);
+    heap.set_in_current_shadow_frame_slot(0, None);
+    heap.gc();
+    assert_eq!(heap.used_mem.get(), 0);
+    assert_eq!(drops.get(), 1);
+}
+
 #[wasm_bindgen_test]
 #[test]
 fn string_read_alloc() {
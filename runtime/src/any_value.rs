@@ -1,11 +1,13 @@
 //! An enum that can store any type known to the runtime
 
 pub use crate::allocator::{heap_types::EnvPtr, AnyPtr, HeapRefView};
-use crate::closure::{closure_env, Closure, ClosureVal};
+use crate::closure::{closure_new, Closure};
 use crate::i64_val::*;
+use crate::static_strings::static_strings;
 use crate::string::StringPtr;
 use crate::wasm32::heap;
 use crate::HeapPtr;
+use abi::{AnyDiscriminant, TrapReason};
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
 /// this is the actual Any type, however it should never be returned or
@@ -32,6 +34,24 @@ pub enum AnyEnum {
     Null,
 }
 
+abi::assert_abi!(std::mem::size_of::<AnyEnum>() == abi::ANY_SIZE as usize);
+
+impl AnyEnum {
+    /// The discriminant that the compiler assumes this variant has. Rust picks the actual
+    /// discriminant, which test::abi_any_discriminants_stable checks.
+    pub fn abi_discriminant(&self) -> AnyDiscriminant {
+        match self {
+            AnyEnum::I32(_) => AnyDiscriminant::I32,
+            AnyEnum::F64(_) => AnyDiscriminant::F64,
+            AnyEnum::Bool(_) => AnyDiscriminant::Bool,
+            AnyEnum::Ptr(_) => AnyDiscriminant::Ptr,
+            AnyEnum::Closure(_) => AnyDiscriminant::Closure,
+            AnyEnum::Undefined => AnyDiscriminant::Undefined,
+            AnyEnum::Null => AnyDiscriminant::Null,
+        }
+    }
+}
+
 impl Debug for AnyEnum {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         use AnyEnum::*;
@@ -88,6 +108,7 @@ impl Display for HeapRefView {
             NonPtr32(_) | MutF64(_) | Ptr(_) => log_panic!("ref inside any"),
             Env(_) => log_panic!("not a value"),
             HT(_) => log_panic!("Display trait not implemented"),
+            Extension(e) => write!(f, "[object {}]", e.extension().name),
         }
     }
 }
@@ -108,6 +129,7 @@ impl Debug for HeapRefView {
             MutF64(v) => write!(f, "!F64({})", *v),
             Ptr(p) => write!(f, "!Ref({:?})", p),
             Env(e) => write!(f, "Env({:?})", e),
+            Extension(e) => write!(f, "{:?}", e),
         }
     }
 }
@@ -122,11 +144,20 @@ pub extern "C" fn any_to_i32(any: AnyValue) -> i32 {
         AnyEnum::F64(f) => (unsafe { *f }) as i32,
         AnyEnum::Bool(b) => b as i32,
         AnyEnum::Ptr(ptr) => match ptr.view() {
-            HeapRefView::NonPtr32(_) => panic!("ref is not a value"),
+            HeapRefView::NonPtr32(_) => {
+                crate::trap::set_reason(TrapReason::FromAny);
+                panic!("ref is not a value")
+            }
             HeapRefView::String(s) => s.parse().expect("failed string->i32: what to do?"),
-            _ => panic!("failed to unwrap {:?} as i32", *any),
+            _ => {
+                crate::trap::set_reason(TrapReason::FromAny);
+                panic!("failed to unwrap {:?} as i32", *any)
+            }
         },
-        AnyEnum::Closure(_) | AnyEnum::Undefined => panic!("failed to unwrap {:?} as i32", *any),
+        AnyEnum::Closure(_) | AnyEnum::Undefined => {
+            crate::trap::set_reason(TrapReason::FromAny);
+            panic!("failed to unwrap {:?} as i32", *any)
+        }
         AnyEnum::Null => 0,
     }
 }
@@ -138,19 +169,7 @@ pub extern "C" fn any_from_i32(x: i32) -> AnyValue {
 
 #[no_mangle]
 pub extern "C" fn any_to_f64(any: AnyValue) -> f64 {
-    match *any {
-        AnyEnum::I32(i) => i as f64,
-        AnyEnum::F64(f) => unsafe { *f },
-        AnyEnum::Bool(b) => b as i32 as f64,
-        AnyEnum::Ptr(ptr) => match ptr.view() {
-            HeapRefView::NonPtr32(_) => panic!("ref is not a value"),
-            HeapRefView::String(s) => s.parse().unwrap_or(f64::NAN),
-            _ => f64::NAN,
-        },
-        AnyEnum::Closure(_) => f64::NAN,
-        AnyEnum::Undefined => f64::NAN,
-        AnyEnum::Null => 0.,
-    }
+    crate::coercions::any_to_number(any)
 }
 
 #[no_mangle]
@@ -158,30 +177,42 @@ pub extern "C" fn f64_to_any(x: f64) -> AnyValue {
     return heap().f64_to_any(x);
 }
 
+/// Like [f64_to_any], for an f64 that does not escape the function that boxes it (see
+/// `escape.rs` in the compiler)
+#[no_mangle]
+pub extern "C" fn f64_to_any_scratch(x: f64) -> AnyValue {
+    heap().f64_to_any_scratch(x)
+}
+
 #[no_mangle]
-pub extern "C" fn any_from_closure<'a>(val: ClosureVal) -> AnyValue {
-    AnyEnum::Closure(*val).into()
+pub extern "C" fn any_from_closure<'a>(val: Closure) -> AnyValue {
+    AnyEnum::Closure(val).into()
 }
 #[no_mangle]
-pub extern "C" fn any_to_closure<'a>(val: AnyValue) -> ClosureVal {
+pub extern "C" fn any_to_closure<'a>(val: AnyValue) -> Closure {
     if let AnyEnum::Closure(inner) = *val {
-        inner.into()
+        inner
     } else {
+        crate::trap::set_reason(TrapReason::FromAny);
         panic!("unwrap incorrect type {}", stringify!(Fn));
     }
 }
 
+/// a function that is not a closure still needs an environment to hold its
+/// index, which is empty
 #[no_mangle]
 pub extern "C" fn any_from_fn<'a>(val: u32) -> AnyValue {
-    AnyEnum::Closure(Closure(unsafe { EnvPtr::null() }, val as u16)).into()
+    let env = unsafe { heap().alloc_env_or_gc(0, crate::object::object_empty()) };
+    AnyEnum::Closure(closure_new(env, val)).into()
 }
 
 #[no_mangle]
 pub extern "C" fn any_to_ptr<'a>(val: AnyValue) -> AnyPtr {
     match *val {
         AnyEnum::Ptr(ptr) => ptr.into(),
-        AnyEnum::Closure(clos) => closure_env(clos.into()).fn_obj().as_any_ptr(),
+        AnyEnum::Closure(clos) => clos.0.fn_obj().as_any_ptr(),
         unknown_val => {
+            crate::trap::set_reason(TrapReason::FromAny);
             log!("cannot unwrap {:?} as Ptr", unknown_val);
             panic!("");
         }
@@ -193,6 +224,7 @@ pub extern "C" fn any_from_ptr<'a>(val: AnyPtr) -> AnyValue {
     AnyEnum::Ptr(val).into()
 }
 
+/// javascript's ToBoolean
 #[no_mangle]
 pub extern "C" fn any_to_bool<'a>(val: AnyValue) -> bool {
     match *val {
@@ -205,9 +237,16 @@ pub extern "C" fn any_to_bool<'a>(val: AnyValue) -> bool {
         AnyEnum::Ptr(ptr) => match ptr.view() {
             HeapRefView::NonPtr32(_) => panic!("ref is not a value"),
             HeapRefView::String(s) => &*s != "",
-            HeapRefView::Array(_) => true,
-            HeapRefView::ObjectPtrPtr(_) => true,
-            _ => log_panic!("TODO: any_to_bool {:?}", val),
+            // every object is truthy, https://tc39.es/ecma262/#sec-toboolean
+            HeapRefView::Array(_)
+            | HeapRefView::HT(_)
+            | HeapRefView::ObjectPtrPtr(_)
+            | HeapRefView::Class(_)
+            | HeapRefView::Env(_)
+            | HeapRefView::Extension(_) => true,
+            HeapRefView::Any(_) | HeapRefView::MutF64(_) | HeapRefView::Ptr(_) => {
+                log_panic!("ref is not a value")
+            }
         },
         AnyEnum::Closure(_) => true,
         AnyEnum::Undefined => false,
@@ -224,11 +263,31 @@ pub extern "C" fn any_from_bool<'a>(val: bool) -> AnyValue {
 /// https://www.ecma-international.org/ecma-262/5.1/#sec-9.8
 ///
 /// `any_to_string` reuses the `fmt::Display` trait on NotWasm structs,
-/// which should be implemented according to the above JS spec.
+/// which should be implemented according to the above JS spec. It does not
+/// allocate for strings, which it returns as they are, or for small integers,
+/// whose strings are interned.
 #[no_mangle]
-pub fn any_to_string(val: AnyValue) -> StringPtr {
-    let string = val.to_string();
-    heap().alloc_str_or_gc(string.as_str())
+pub extern "C" fn any_to_string(val: AnyValue) -> StringPtr {
+    let small_int = match *val {
+        AnyEnum::Ptr(ptr) => match ptr.view() {
+            HeapRefView::String(s) => return s,
+            _ => None,
+        },
+        AnyEnum::I32(n) => static_strings().small_int(n),
+        AnyEnum::F64(ptr) => {
+            let x = unsafe { *ptr };
+            if x.trunc() == x && x >= 0.0 && x <= i32::MAX as f64 {
+                static_strings().small_int(x as i32)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    match small_int {
+        Some(s) => s,
+        None => heap().alloc_str_or_gc(&val.to_string()),
+    }
 }
 
 #[no_mangle]
@@ -241,6 +300,15 @@ pub extern "C" fn get_null() -> AnyValue {
     AnyEnum::Null.into()
 }
 
+/// The index of the entry for the receiver `val` in a method table (see abi::METHOD_TABLE_LEN).
+#[no_mangle]
+pub extern "C" fn any_method_kind(val: AnyValue) -> u32 {
+    match *val {
+        AnyEnum::Ptr(ptr) => abi::METHOD_TABLE_PTRS + unsafe { (*ptr.get_ptr()).type_tag as u32 },
+        other => other.abi_discriminant() as u32,
+    }
+}
+
 /// Is the given any value an object?
 #[no_mangle]
 pub extern "C" fn any_is_object(val: AnyValue) -> bool {
@@ -258,21 +326,38 @@ mod test {
     use super::*;
     use wasm_bindgen_test::wasm_bindgen_test;
 
-    /// This serves as documentation of the discriminants and a test that they
-    /// are as expected. Keeping them in the same place avoids divergence
+    /// This checks that the discriminants are the ones in abi::AnyDiscriminant,
+    /// which the compiler uses.
     /// This is stable across compiles in the same compiler as long as AnyEnum
     /// isn't changed. This seems to closely follow just being sequential by
     /// the definition
     /// https://doc.rust-lang.org/std/mem/fn.discriminant.html
     #[wasm_bindgen_test]
     fn abi_any_discriminants_stable() {
-        assert_disc(AnyEnum::I32(0), 0);
-        assert_disc(AnyEnum::F64(&0.0 as *const f64), 1);
-        assert_disc(AnyEnum::Bool(false), 2);
-        assert_disc(AnyEnum::Ptr(heap().alloc_str_or_gc(".").as_any_ptr()), 3);
-        assert_disc(AnyEnum::Closure(Closure(unsafe { EnvPtr::null() }, 0)), 4);
-        assert_disc(AnyEnum::Undefined, 5);
-        assert_disc(AnyEnum::Null, 6);
+        let anys = [
+            AnyEnum::I32(0),
+            AnyEnum::F64(&0.0 as *const f64),
+            AnyEnum::Bool(false),
+            AnyEnum::Ptr(heap().alloc_str_or_gc(".").as_any_ptr()),
+            AnyEnum::Closure(Closure(unsafe { EnvPtr::null() })),
+            AnyEnum::Undefined,
+            AnyEnum::Null,
+        ];
+        for any in anys.iter() {
+            assert_disc(*any, any.abi_discriminant() as usize);
+        }
+    }
+    #[test]
+    #[wasm_bindgen_test]
+    fn small_integer_strings_are_interned() {
+        crate::init();
+        let a = any_to_string(AnyEnum::I32(42).into());
+        let b = any_to_string(f64_to_any(42.0));
+        assert_eq!(&*a, "42");
+        assert_eq!(a.get_ptr(), b.get_ptr());
+        assert_eq!(&*any_to_string(AnyEnum::I32(256).into()), "256");
+        assert_eq!(&*any_to_string(f64_to_any(-0.0)), "0");
+        assert_eq!(&*any_to_string(f64_to_any(2.5)), "2.5");
     }
     #[wasm_bindgen_test]
     fn any_size_is_64() {
@@ -316,14 +401,10 @@ mod test {
     fn cast_predicted_structure_32(a: AnyEnum) -> u32 {
         (AnyValue::from(a).raw_val() >> 32) as u32
     }
-    /// similarly to [any_value_has_predicted_structure_32], with 48-bit
-    /// structs, the predicted structure is:
-    /// an I64Val<Closure> looks like this:
-    /// [16-bit padding][48-bit closure]
-    /// an I64Val<AnyEnum::Closure> looks like this:
-    /// [48-bit closure][16-bit descriminant/padding]
+    /// a closure is a pointer to its environment, so it has the same
+    /// structure as the pointers in [any_value_has_predicted_structure_32]
     #[wasm_bindgen_test]
-    fn any_value_has_predicted_structure_48() {
+    fn closure_has_predicted_structure_32() {
         use crate::closure::*;
         use crate::env::*;
         use crate::object::*;
@@ -335,10 +416,8 @@ mod test {
         };
         let fake_closure = closure_new(fake_env, 13);
         let into_any = any_from_closure(fake_closure);
-        log!("{:064b}", fake_closure.raw_val());
-        log!("{:064b}", into_any.raw_val());
-        let raw_data_of_shifted = into_any.raw_val() >> 16;
-        let calculated_closure: ClosureVal = unsafe { std::mem::transmute(raw_data_of_shifted) };
+        let raw_data_of_shifted = (into_any.raw_val() >> 32) as u32;
+        let calculated_closure: Closure = unsafe { std::mem::transmute(raw_data_of_shifted) };
         assert_eq!(calculated_closure, fake_closure);
     }
     fn assert_disc(any: AnyEnum, expected: usize) {
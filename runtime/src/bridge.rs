@@ -0,0 +1,436 @@
+//! Conversions between the values of the host and the values of the runtime system, which let a
+//! host call the exports of a program with strings and JSON values, and read their results.
+//!
+//! The host shares the memory of the runtime system, so it passes text by writing UTF-8 into the
+//! buffer that [host_buffer] produces, and calling [host_string_to_heap] or [host_json_to_heap].
+//! [heap_to_host_json] serializes a value as JSON, which the host reads from memory.
+//! `bin/bridge.js` wraps these functions for JavaScript hosts.
+//!
+//! Objects from the host have no prototype. Serializing a value that refers to itself does not
+//! terminate.
+
+use crate::heap;
+use crate::heap_types::*;
+use crate::{AnyEnum, AnyValue, HeapPtr, HeapRefView, Tag};
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+/// The buffer that the host writes text into.
+static mut BUFFER: Vec<u8> = Vec::new();
+
+/// The JSON that [heap_to_host_json] produced last, which must outlive the call.
+static mut SERIALIZED: Option<CString> = None;
+
+/// Produces a buffer of `len` bytes, which the host fills before it calls [host_string_to_heap]
+/// or [host_json_to_heap]. The buffer is valid until the next call.
+#[no_mangle]
+pub unsafe extern "C" fn host_buffer(len: usize) -> *mut u8 {
+    BUFFER = vec![0; len];
+    BUFFER.as_mut_ptr()
+}
+
+/// Allocates a string that holds the text in the buffer.
+#[no_mangle]
+pub unsafe extern "C" fn host_string_to_heap() -> AnyValue {
+    let s = heap().alloc_str_or_gc(buffer_text());
+    AnyEnum::Ptr(s.as_any_ptr()).into()
+}
+
+/// Allocates the value of the JSON in the buffer.
+#[no_mangle]
+pub unsafe extern "C" fn host_json_to_heap() -> AnyValue {
+    let json = match Parser::new(buffer_text()).parse() {
+        Ok(json) => json,
+        Err(pos) => log_panic!("SyntaxError: invalid JSON at position {}", pos),
+    };
+    // Allocating may collect garbage, so the values that we have built so far are roots.
+    heap().push_shadow_frame(json.num_ptrs());
+    let mut slot = 0;
+    let value = json.to_heap(&mut slot);
+    heap().pop_shadow_frame();
+    value
+}
+
+/// Serializes `value` as JSON, and produces a null-terminated string that is valid until the
+/// next call. As in `JSON.stringify`, functions and `undefined` are `null`.
+#[no_mangle]
+pub unsafe extern "C" fn heap_to_host_json(value: AnyValue) -> *const c_char {
+    let mut json = String::new();
+    write_json(&mut json, *value);
+    SERIALIZED = Some(CString::new(json).expect("JSON with a null byte"));
+    SERIALIZED.as_ref().unwrap().as_ptr()
+}
+
+unsafe fn buffer_text() -> &'static str {
+    match std::str::from_utf8(&BUFFER) {
+        Ok(text) => text,
+        Err(_) => log_panic!("the host passed text that is not UTF-8"),
+    }
+}
+
+fn write_json(out: &mut String, value: AnyEnum) {
+    match value {
+        AnyEnum::I32(n) => out.push_str(&n.to_string()),
+        AnyEnum::F64(ptr) => {
+            let x = unsafe { *ptr };
+            if x.is_finite() {
+                out.push_str(&x.to_string())
+            } else {
+                out.push_str("null")
+            }
+        }
+        AnyEnum::Bool(b) => out.push_str(&b.to_string()),
+        AnyEnum::Ptr(ptr) => match ptr.view() {
+            HeapRefView::String(s) => write_string(out, &s),
+            HeapRefView::Any(a) => write_json(out, **a),
+            HeapRefView::Array(a) => {
+                out.push('[');
+                for (i, elem) in a.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json(out, **elem);
+                }
+                out.push(']');
+            }
+            HeapRefView::ObjectPtrPtr(o) => {
+                out.push('{');
+                let fields = o.own_fields(heap());
+                let fields = fields
+                    .into_iter()
+                    .filter(|(name, _)| &**name != "__proto__");
+                for (i, (name, value)) in fields.enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(out, &name);
+                    out.push(':');
+                    write_json(out, value);
+                }
+                out.push('}');
+            }
+            _ => out.push_str("null"),
+        },
+        AnyEnum::Closure(_) | AnyEnum::Undefined | AnyEnum::Null => out.push_str("null"),
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// A JSON value, before we allocate it.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// The number of heap values that allocating the value produces, including the names of
+    /// fields.
+    fn num_ptrs(&self) -> usize {
+        match self {
+            Json::Null | Json::Bool(_) | Json::Number(_) => 0,
+            Json::String(_) => 1,
+            Json::Array(elems) => 1 + elems.iter().map(Json::num_ptrs).sum::<usize>(),
+            Json::Object(fields) => {
+                1 + fields
+                    .iter()
+                    .map(|(_, value)| 1 + value.num_ptrs())
+                    .sum::<usize>()
+            }
+        }
+    }
+
+    /// Allocates the value, and roots each heap value that it allocates in the next slot of the
+    /// current shadow frame.
+    fn to_heap(&self, slot: &mut usize) -> AnyValue {
+        match self {
+            Json::Null => AnyEnum::Null.into(),
+            Json::Bool(b) => AnyEnum::Bool(*b).into(),
+            Json::Number(x)
+                if x.fract() == 0.0 && *x >= i32::MIN as f64 && *x <= i32::MAX as f64 =>
+            {
+                AnyEnum::I32(*x as i32).into()
+            }
+            Json::Number(x) => heap().f64_to_any(*x),
+            Json::String(s) => {
+                let s = heap().alloc_str_or_gc(s);
+                root(s.get_ptr(), slot);
+                AnyEnum::Ptr(s.as_any_ptr()).into()
+            }
+            Json::Array(elems) => {
+                let mut array = crate::array::array_new();
+                root(array.get_ptr(), slot);
+                for elem in elems {
+                    let elem = elem.to_heap(slot);
+                    array.push(elem);
+                }
+                AnyEnum::Ptr(array.as_any_ptr()).into()
+            }
+            Json::Object(fields) => {
+                let mut object = crate::object::object_empty();
+                root(object.get_ptr(), slot);
+                for (name, value) in fields {
+                    let name = heap().alloc_str_or_gc(name);
+                    root(name.get_ptr(), slot);
+                    let value = value.to_heap(slot);
+                    object.insert(heap(), name, value, &mut -1);
+                }
+                AnyEnum::Ptr(object.as_any_ptr()).into()
+            }
+        }
+    }
+}
+
+fn root(ptr: *mut Tag, slot: &mut usize) {
+    heap().set_in_current_shadow_frame_slot(*slot, Some(ptr));
+    *slot += 1;
+}
+
+/// A recursive-descent parser of JSON, which reports errors by their byte offset.
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<Json, usize> {
+        let json = self.value()?;
+        self.whitespace();
+        if self.pos != self.text.len() {
+            return Err(self.pos);
+        }
+        Ok(json)
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), usize> {
+        self.whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, keyword: &str, json: Json) -> Result<Json, usize> {
+        if !self.text[self.pos..].starts_with(keyword.as_bytes()) {
+            return Err(self.pos);
+        }
+        self.pos += keyword.len();
+        Ok(json)
+    }
+
+    fn value(&mut self) -> Result<Json, usize> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut elems = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(elems));
+                }
+                loop {
+                    elems.push(self.value()?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(elems));
+                        }
+                        _ => return Err(self.pos),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((name, self.value()?));
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(self.pos),
+                    }
+                }
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(self.pos),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, usize> {
+        let start = self.pos;
+        while let Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E')
+        | Some(b'0'..=b'9') = self.peek()
+        {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        text.parse().map(Json::Number).map_err(|_| start)
+    }
+
+    fn string(&mut self) -> Result<String, usize> {
+        if self.peek() != Some(b'"') {
+            return Err(self.pos);
+        }
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.pos),
+                Some(b'"') => {
+                    self.pos += 1;
+                    // The text is UTF-8, and escapes produce UTF-8.
+                    return Ok(String::from_utf8(bytes).unwrap());
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.pos),
+                    };
+                    self.pos += 1;
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(byte) => {
+                    bytes.push(byte);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// The character of `\uXXXX`, or of two such escapes that form a surrogate pair. Leaves
+    /// the position at the last digit.
+    fn unicode_escape(&mut self) -> Result<char, usize> {
+        let high = self.hex4()?;
+        if (0xd800..0xdc00).contains(&high) && self.text[self.pos + 1..].starts_with(b"\\u") {
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.pos);
+            }
+            let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+            return std::char::from_u32(c).ok_or(self.pos);
+        }
+        std::char::from_u32(high).ok_or(self.pos)
+    }
+
+    /// Reads the four hexadecimal digits after the current position.
+    fn hex4(&mut self) -> Result<u32, usize> {
+        let digits = self
+            .text
+            .get(self.pos + 1..self.pos + 5)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or(self.pos)?;
+        self.pos += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn parse_json() {
+        let json = Parser::new(r#" {"a": [1, -2.5e1, "x\né"], "b": {}, "c": null} "#)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            json,
+            Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number(1.0),
+                        Json::Number(-25.0),
+                        Json::String("x\né".to_string())
+                    ])
+                ),
+                ("b".to_string(), Json::Object(vec![])),
+                ("c".to_string(), Json::Null),
+            ])
+        );
+        assert_eq!(Parser::new("[1,]").parse(), Err(3));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn round_trip() {
+        crate::init();
+        let text = r#"{"name":"jank\"y","sizes":[1,2.5,true,null],"nested":{"x":-3}}"#;
+        unsafe {
+            host_buffer(text.len()).copy_from(text.as_ptr(), text.len());
+            let value = host_json_to_heap();
+            let json = std::ffi::CStr::from_ptr(heap_to_host_json(value));
+            assert_eq!(json.to_str().unwrap(), text);
+        }
+    }
+}
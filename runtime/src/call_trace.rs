@@ -0,0 +1,129 @@
+//! The calls that the program is in, which [stack_overflow] reports.
+//!
+//! When the program is compiled with `max_call_depth` set, every function counts the nested calls,
+//! and records its number in the frame of the depth that it runs at, so the frames up to the
+//! current depth are the functions on the stack. The module gives [call_trace_init] the names of
+//! its functions from the data segment, so a trace shows the names of the functions of the program
+//! without the name section, which only a debugger reads.
+
+use crate::object::read_u32;
+
+/// The number of frames that [stack_overflow] reports.
+const FRAMES: usize = 10;
+
+struct CallTrace {
+    strings: *const u8,
+    table: *const u8,
+    /// The number of the function that runs at each depth. Depth 0 is the generated main.
+    frames: Box<[u32]>,
+}
+
+static mut CALL_TRACE: Option<CallTrace> = None;
+
+/// Records the names of the functions of the program, and produces the frames of calls that are
+/// at most `max_depth` deep. `table` is the number of functions followed by the offsets of their
+/// names in the data segment, which starts at `strings`. A name is its length and its bytes.
+#[no_mangle]
+pub unsafe extern "C" fn call_trace_init(
+    strings: *const u8,
+    table: *const u8,
+    max_depth: u32,
+) -> *mut u32 {
+    // A function records its frame before it checks the depth, so the call that exceeds the
+    // limit has a frame too.
+    let frames = vec![0; max_depth as usize + 2].into_boxed_slice();
+    CALL_TRACE = Some(CallTrace {
+        strings,
+        table,
+        frames,
+    });
+    CALL_TRACE.as_mut().unwrap().frames.as_mut_ptr()
+}
+
+/// The names of the functions that run at the depths from `depth` down to 1, so the innermost call
+/// is first.
+pub fn call_trace(depth: u32) -> Vec<&'static str> {
+    let trace = match unsafe { &CALL_TRACE } {
+        Some(trace) => trace,
+        None => return vec![],
+    };
+    let depth = (depth as usize).min(trace.frames.len() - 1);
+    trace.frames[1..=depth]
+        .iter()
+        .rev()
+        .map(|number| unsafe { trace.function_name(*number) })
+        .collect()
+}
+
+impl CallTrace {
+    unsafe fn function_name(&self, number: u32) -> &'static str {
+        if number >= read_u32(self.table) {
+            return "<unknown>";
+        }
+        let name = self
+            .strings
+            .add(read_u32(self.table.add(4 * (number as usize + 1))) as usize);
+        let len = read_u32(name) as usize;
+        std::str::from_utf8_unchecked(std::slice::from_raw_parts(name.add(4), len))
+    }
+}
+
+/// Called when a call nests more deeply than the limit that the program was compiled with (see
+/// `max_call_depth` in the options of the compiler). `depth` is the depth of the call.
+#[no_mangle]
+pub extern "C" fn stack_overflow(depth: u32) {
+    let trace = call_trace(depth);
+    let mut message = "RangeError: Maximum call stack size exceeded".to_string();
+    for name in trace.iter().take(FRAMES) {
+        message.push_str("\n    at ");
+        message.push_str(name);
+    }
+    if trace.len() > FRAMES {
+        message.push_str(&format!("\n    ... {} more", trace.len() - FRAMES));
+    }
+    log_panic!("{}", message);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// A data segment with the names `f`, `g`, and `main`, and the offset of their table.
+    fn names() -> (Vec<u8>, usize) {
+        let mut data = vec![];
+        let mut offsets = vec![];
+        for name in &["f", "g", "main"] {
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            offsets.push(data.len() as u32);
+            data.extend(&(name.len() as u32).to_le_bytes());
+            data.extend(name.as_bytes());
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        let table = data.len();
+        data.extend(&(offsets.len() as u32).to_le_bytes());
+        for offset in offsets {
+            data.extend(&offset.to_le_bytes());
+        }
+        (data, table)
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn innermost_call_first() {
+        let (data, table) = names();
+        unsafe {
+            let frames = call_trace_init(data.as_ptr(), data.as_ptr().add(table), 4);
+            for (depth, number) in [2, 0, 1, 1].iter().enumerate() {
+                *frames.add(depth + 1) = *number;
+            }
+        }
+        assert_eq!(call_trace(4), vec!["g", "g", "f", "main"]);
+        assert_eq!(call_trace(2), vec!["f", "main"]);
+        assert_eq!(call_trace(0), Vec::<&str>::new());
+    }
+}
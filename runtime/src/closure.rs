@@ -1,20 +1,15 @@
 use super::heap_types::EnvPtr;
 use super::i64_val::*;
 use super::Tag;
+use crate::{AnyEnum, AnyValue, HeapRefView};
 
-/// this is a closure-converted closure, it represents a cons of an environment
-/// (a fixed array) and a function pointer. these two pointers can be combined
-/// into one i64 to be used as an immediate value. this closure has had its
-/// arity erased, for example if it is not stuck within an any
-///
-/// you must not obtain a reference to any of its fields, since it is
-/// unaligned. https://github.com/rust-lang/rust/issues/27060 it is unaligned
-/// because of the need to fit it in Any
+/// this is a closure-converted closure. it is a pointer to its environment
+/// (a fixed array), which also holds the index of the function, so a closure
+/// is a 32-bit pointer to a heap object like any other. this closure has had
+/// its arity erased, for example if it is not stuck within an any
 #[derive(Debug, Clone, Copy, PartialEq)]
-#[repr(packed(2))]
-pub struct Closure(pub EnvPtr, pub u16);
-impl AsI64 for Closure {}
-pub type ClosureVal = I64Val<Closure>;
+#[repr(transparent)]
+pub struct Closure(pub EnvPtr);
 
 /// Note: The `Display` trait on NotWasm structs should implement the
 /// internal `ToString` operation described in the ECMAScript spec:
@@ -32,19 +27,73 @@ impl std::fmt::Display for Closure {
     }
 }
 
-/// webassembly has no 16-bit value, but func should no higher than 2^16. it
-/// is truncated
+/// stores the index of the function in the environment, which becomes the
+/// closure
 #[no_mangle]
-pub extern "C" fn closure_new(ptr: EnvPtr, func: u32) -> ClosureVal {
-    Closure(ptr, func as u16).into()
+pub extern "C" fn closure_new(mut env: EnvPtr, func: u32) -> Closure {
+    env.set_func(func);
+    Closure(env)
 }
 #[no_mangle]
-pub extern "C" fn closure_env(closure: ClosureVal) -> EnvPtr {
+pub extern "C" fn closure_env(closure: Closure) -> EnvPtr {
     closure.0
 }
 #[no_mangle]
-pub extern "C" fn closure_func(closure: ClosureVal) -> u32 {
-    closure.1 as u32
+pub extern "C" fn closure_func(closure: Closure) -> u32 {
+    closure.0.func()
+}
+
+/// calls `closure` with `args`, which start with `this`. the program exports a
+/// dispatcher, which the host calls on our behalf, that passes undefined for
+/// missing arguments and drops the extra ones, like a call in JavaScript
+///
+/// the closure may allocate, so the caller must keep `closure`, `args`, and
+/// anything else that it needs afterwards in a shadow stack frame
+pub fn call_closure(closure: AnyValue, args: &[AnyValue]) -> AnyValue {
+    match *closure {
+        AnyEnum::Closure(closure) => call_closure_env(closure.0, args.as_ptr(), args.len() as u32),
+        _ => log_panic!("{:?} is not a function", *closure),
+    }
+}
+
+/// `f(...args)` in JavaScript, which calls `f` with `this` and the elements of the array `args`.
+/// desugaring turns the call into a call to `jnks_apply`, which is this function in the standard
+/// library. the dispatcher picks the case for the type of the function of `f`, so it receives
+/// undefined for missing arguments like any other call
+///
+/// the caller holds `f`, `this`, and `args` in its shadow stack frame, so the elements stay alive
+#[no_mangle]
+pub extern "C" fn closure_apply(
+    _: EnvPtr,
+    _this: AnyValue,
+    f: AnyValue,
+    this: AnyValue,
+    args: AnyValue,
+) -> AnyValue {
+    let mut this_and_args = vec![this];
+    match *args {
+        AnyEnum::Ptr(ptr) => match ptr.view() {
+            HeapRefView::Array(elements) => this_and_args.extend(elements.iter().cloned()),
+            _ => log_panic!("TypeError: {:?} is not an array", *args),
+        },
+        _ => log_panic!("TypeError: {:?} is not an array", *args),
+    }
+    call_closure(f, &this_and_args)
+}
+
+#[cfg(not(test))]
+fn call_closure_env(env: EnvPtr, args: *const AnyValue, nargs: u32) -> AnyValue {
+    extern "C" {
+        // The host forwards the call to call_closure in the exports of the
+        // program, which is instantiated after the runtime system.
+        fn jankscripten_call_closure(env: EnvPtr, args: *const AnyValue, nargs: u32) -> AnyValue;
+    }
+    unsafe { jankscripten_call_closure(env, args, nargs) }
+}
+
+#[cfg(test)]
+fn call_closure_env(_env: EnvPtr, _args: *const AnyValue, _nargs: u32) -> AnyValue {
+    log_panic!("there is no program to call closures in tests")
 }
 
 /// this is a closure with any arity, which holds its arity along with
@@ -91,16 +140,14 @@ mod test {
     use wasm_bindgen_test::wasm_bindgen_test;
 
     #[wasm_bindgen_test]
-    fn closure_size_is_64() {
-        assert_eq!(std::mem::size_of::<ClosureVal>(), 8);
-        assert_eq!(std::mem::size_of::<Closure>(), 6);
+    fn closure_size_is_32() {
+        assert_eq!(std::mem::size_of::<Closure>(), 4);
         assert_eq!(std::mem::size_of::<AnyClosureVal>(), 8);
         assert_eq!(std::mem::size_of::<AnyClosure>(), 7);
     }
     #[test]
-    fn closure_size_is_128() {
-        assert_eq!(std::mem::size_of::<ClosureVal>(), 16);
-        assert_eq!(std::mem::size_of::<Closure>(), 10);
+    fn closure_size_is_64() {
+        assert_eq!(std::mem::size_of::<Closure>(), 8);
         assert_eq!(std::mem::size_of::<AnyClosureVal>(), 16);
         assert_eq!(std::mem::size_of::<AnyClosure>(), 11);
     }
@@ -121,6 +168,18 @@ mod test {
         assert_eq!(env, back_out);
         // this could be finished with an EnvGet, but i found the bug already
     }
+    /// the closure call stubs of libjankscripten load the function of a
+    /// closure from its environment instead of calling closure_func. this
+    /// test ensures the offset they assume
+    #[wasm_bindgen_test]
+    fn closure_abi_stable() {
+        use crate::allocator::HeapPtr;
+        crate::init();
+        let env = unsafe { env_alloc(0, object_empty()) };
+        let clos = closure_new(env, 70000);
+        let func = unsafe { *(clos.0.get_ptr() as *const u32).add(3) };
+        assert_eq!(func, 70000);
+    }
     #[wasm_bindgen_test]
     fn closure_to_object() {
         use crate::any_value::*;
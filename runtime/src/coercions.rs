@@ -45,6 +45,93 @@ pub fn i32s_or_as_f64s_any(
     )
 }
 
+/// like [i32s_or_as_f64s_any], but operands that aren't numbers are
+/// converted with [any_to_number] instead of failing. this is what the
+/// arithmetic operators do in javascript, ie `"3" * 2 === 6`
+pub fn i32s_or_as_numbers_any(
+    a: Any,
+    b: Any,
+    floats: fn(f64, f64) -> f64,
+    ints: fn(i32, i32) -> i32,
+) -> Any {
+    i32s_or_as_f64s_any(a, b, floats, ints)
+        .unwrap_or_else(|| f64_to_any(floats(any_to_number(a), any_to_number(b))))
+}
+
+/// javascript's ToNumber, https://tc39.es/ecma262/#sec-tonumber
+///
+/// objects would have to go through ToPrimitive first, which we don't
+/// support, so they become NaN
+pub fn any_to_number(a: Any) -> f64 {
+    match *a {
+        AnyEnum::I32(i) => i as f64,
+        AnyEnum::F64(f) => unsafe { *f },
+        AnyEnum::Bool(b) => b as i32 as f64,
+        AnyEnum::Ptr(ptr) => match ptr.view() {
+            HeapRefView::NonPtr32(_) => log_panic!("ref is not a value"),
+            HeapRefView::String(s) => string_to_number(&s),
+            _ => f64::NAN,
+        },
+        AnyEnum::Closure(_) => f64::NAN,
+        AnyEnum::Undefined => f64::NAN,
+        AnyEnum::Null => 0.,
+    }
+}
+
+/// WhiteSpace and LineTerminator from the spec. this isn't quite
+/// char::is_whitespace, which has U+0085 but not U+FEFF
+fn is_js_whitespace(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\u{b}' | '\u{c}' | '\r' | ' ' | '\u{a0}' | '\u{1680}' | '\u{2028}'
+        | '\u{2029}' | '\u{202f}' | '\u{205f}' | '\u{3000}' | '\u{feff}' => true,
+        '\u{2000}'..='\u{200a}' => true,
+        _ => false,
+    }
+}
+
+/// javascript's StringToNumber, https://tc39.es/ecma262/#sec-stringtonumber
+///
+/// rust's parse accepts things javascript doesn't (`inf`, `NaN`) and
+/// vice versa (surrounding whitespace, `0x10`), so we only hand it plain
+/// decimal literals
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_js_whitespace);
+    if s.is_empty() {
+        return 0.;
+    }
+    let radix = match s.get(..2) {
+        Some("0x") | Some("0X") => Some(16),
+        Some("0o") | Some("0O") => Some(8),
+        Some("0b") | Some("0B") => Some(2),
+        _ => None,
+    };
+    if let Some(radix) = radix {
+        let digits = &s[2..];
+        if digits.is_empty() {
+            return f64::NAN;
+        }
+        return digits.chars().fold(0., |acc, c| match c.to_digit(radix) {
+            Some(d) => acc * radix as f64 + d as f64,
+            None => f64::NAN,
+        });
+    }
+    let unsigned = s.trim_start_matches(|c| c == '+' || c == '-');
+    if unsigned == "Infinity" && s.len() - unsigned.len() <= 1 {
+        return if s.starts_with('-') {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+    }
+    if s.chars()
+        .all(|c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || c == '+' || c == '-')
+    {
+        s.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
 /// if a is *already* an object (no coercion), return Some(a as
 /// Object). otherwise return None
 pub fn match_object(a: AnyEnum) -> Option<ObjectPtr> {
@@ -107,8 +194,8 @@ fn even_abstract_eq(a: AnyEnum, b: AnyEnum) -> Option<bool> {
         (a, AnyEnum::Ptr(p)) => match p.view() {
             // 4
             HeapRefView::String(s) => match a {
-                AnyEnum::I32(i) => i as f64 == s.parse().unwrap_or(f64::NAN),
-                AnyEnum::F64(f) => (unsafe { *f }) == s.parse().unwrap_or(f64::NAN),
+                AnyEnum::I32(i) => i as f64 == string_to_number(&s),
+                AnyEnum::F64(f) => (unsafe { *f }) == string_to_number(&s),
                 // presumably will be caught on the right side on the odd pass
                 _ => return None,
             },
@@ -123,3 +210,121 @@ fn even_abstract_eq(a: AnyEnum, b: AnyEnum) -> Option<bool> {
         _ => return None,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{heap, init, HeapPtr};
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn string_to_number_literals() {
+        assert_eq!(string_to_number("  42\n"), 42.);
+        assert_eq!(string_to_number(""), 0.);
+        assert_eq!(string_to_number(" \t"), 0.);
+        assert_eq!(string_to_number("-1.5e2"), -150.);
+        assert_eq!(string_to_number(".5"), 0.5);
+        assert_eq!(string_to_number("0x1F"), 31.);
+        assert_eq!(string_to_number("0b101"), 5.);
+        assert_eq!(string_to_number("0o17"), 15.);
+        assert_eq!(string_to_number("-Infinity"), f64::NEG_INFINITY);
+        assert_eq!(string_to_number("+Infinity"), f64::INFINITY);
+        for bad in &[
+            "-0x10",
+            "0x",
+            "inf",
+            "NaN",
+            "1_000",
+            "12px",
+            "--1",
+            "+-Infinity",
+        ] {
+            assert!(string_to_number(bad).is_nan(), "{}", bad);
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn arithmetic_on_strings() {
+        init();
+        let three: Any = AnyEnum::Ptr(heap().alloc_str_or_gc("3").as_any_ptr()).into();
+        let two = any_from_i32(2);
+        let six = i32s_or_as_numbers_any(three, two, |a, b| a * b, |a, b| a * b);
+        assert_eq!(any_to_number(six), 6.);
+        let nan =
+            i32s_or_as_numbers_any(AnyEnum::Undefined.into(), two, |a, b| a - b, |a, b| a - b);
+        assert!(any_to_number(nan).is_nan());
+    }
+}
+
+/// the ToBoolean and ToNumber tables from the spec, row by row
+/// (https://tc39.es/ecma262/#sec-toboolean and
+/// https://tc39.es/ecma262/#sec-tonumber), and the comparisons that
+/// go through them when one side is a boolean
+#[cfg(test)]
+mod spec_tables {
+    use super::*;
+    use crate::object::object_empty;
+    use crate::{heap, init, HeapPtr};
+    use wasm_bindgen_test::*;
+
+    fn string(s: &str) -> Any {
+        AnyEnum::Ptr(heap().alloc_str_or_gc(s).as_any_ptr()).into()
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn to_boolean_table() {
+        init();
+        let rows = [
+            (AnyEnum::Undefined.into(), false),
+            (AnyEnum::Null.into(), false),
+            (any_from_bool(true), true),
+            (any_from_bool(false), false),
+            (any_from_i32(0), false),
+            (any_from_i32(-3), true),
+            (f64_to_any(f64::NAN), false),
+            (f64_to_any(-0.0), false),
+            (f64_to_any(0.5), true),
+            (string(""), false),
+            (string("false"), true),
+            (string("0"), true),
+            (AnyEnum::Ptr(object_empty().as_any_ptr()).into(), true),
+        ];
+        for (any, expected) in rows.iter() {
+            assert_eq!(any_to_bool(*any), *expected, "ToBoolean({:?})", any);
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn to_number_table() {
+        init();
+        let rows = [
+            (AnyEnum::Null.into(), 0.),
+            (any_from_bool(true), 1.),
+            (any_from_bool(false), 0.),
+            (any_from_i32(7), 7.),
+            (string(" 12 "), 12.),
+            (string("\u{a0}0x1f\n"), 31.),
+        ];
+        for (any, expected) in rows.iter() {
+            assert_eq!(any_to_number(*any), *expected, "ToNumber({:?})", any);
+        }
+        assert!(any_to_number(AnyEnum::Undefined.into()).is_nan());
+        assert!(any_to_number(string("1 2")).is_nan());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn booleans_compare_as_numbers() {
+        init();
+        assert!(abstract_eq(AnyEnum::Bool(true), AnyEnum::I32(1)));
+        assert!(abstract_eq(AnyEnum::I32(0), AnyEnum::Bool(false)));
+        assert!(abstract_eq(AnyEnum::Bool(true), *string("1")));
+        assert!(!abstract_eq(AnyEnum::Bool(true), *string("true")));
+        assert!(!abstract_eq(AnyEnum::Bool(false), AnyEnum::Null));
+        assert!(!abstract_eq(AnyEnum::Bool(false), AnyEnum::Undefined));
+    }
+}
@@ -0,0 +1,176 @@
+//! The functions of the runtime system that `Map` and `Set` call on the `HT` of their entries
+//! (see stdlib/collections.notwasm). Without the `collections` feature, the runtime system does
+//! not have them, but it still has `HT`s, which the compiler uses for other purposes.
+
+use super::{heap, heap_types::ArrayPtr, heap_types::HTPtr};
+use crate::{AnyEnum, AnyValue, HeapRefView};
+
+/// The value of `key`, or `undefined` if the table does not have it, like `Map.prototype.get`.
+#[no_mangle]
+pub extern "C" fn ht_get_any(ht: HTPtr, key: AnyValue) -> AnyValue {
+    ht.get(key).unwrap_or_else(|| AnyEnum::Undefined.into())
+}
+
+#[no_mangle]
+pub extern "C" fn ht_set_any(mut ht: HTPtr, key: AnyValue, value: AnyValue) -> AnyValue {
+    ht.insert(key, value);
+    value
+}
+
+#[no_mangle]
+pub extern "C" fn ht_has(ht: HTPtr, key: AnyValue) -> bool {
+    ht.contains_key(key)
+}
+
+/// Deletes `key`, and determines if the table had it, like `Map.prototype.delete`.
+#[no_mangle]
+pub extern "C" fn ht_delete(mut ht: HTPtr, key: AnyValue) -> bool {
+    ht.remove(key)
+}
+
+/// Deletes every key, and returns the size of the table, which is 0.
+#[no_mangle]
+pub extern "C" fn ht_clear(mut ht: HTPtr) -> i32 {
+    ht.clear();
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn ht_size(ht: HTPtr) -> i32 {
+    ht.len() as i32
+}
+
+/// The keys of the table, in insertion order.
+#[no_mangle]
+pub extern "C" fn ht_keys(ht: HTPtr) -> ArrayPtr {
+    heap().alloc_or_gc(ht.iter().map(|(key, _)| *key).collect::<Vec<_>>())
+}
+
+/// The values of the table, in insertion order.
+#[no_mangle]
+pub extern "C" fn ht_values(ht: HTPtr) -> ArrayPtr {
+    heap().alloc_or_gc(ht.iter().map(|(_, value)| *value).collect::<Vec<_>>())
+}
+
+/// The elements of `iterable`, which is the argument of the constructor of `Map` or `Set`. It may
+/// be `undefined` or `null`, which have no elements. Other than those, we only support arrays.
+fn elements(iterable: AnyValue) -> Vec<AnyValue> {
+    match *iterable {
+        AnyEnum::Undefined | AnyEnum::Null => vec![],
+        AnyEnum::Ptr(ptr) => match ptr.view() {
+            HeapRefView::Array(elements) => elements.to_vec(),
+            _ => log_panic!("TypeError: {:?} is not an array", *iterable),
+        },
+        _ => log_panic!("TypeError: {:?} is not iterable", *iterable),
+    }
+}
+
+/// Inserts the `[key, value]` arrays in `iterable`, like `new Map(iterable)`, and returns the size
+/// of the table.
+#[no_mangle]
+pub extern "C" fn ht_add_entries(mut ht: HTPtr, iterable: AnyValue) -> i32 {
+    for entry in elements(iterable) {
+        match *entry {
+            AnyEnum::Ptr(ptr) => match ptr.view() {
+                HeapRefView::Array(pair) => {
+                    let undefined = AnyEnum::Undefined.into();
+                    let key = pair.get(0).copied().unwrap_or(undefined);
+                    let value = pair.get(1).copied().unwrap_or(undefined);
+                    ht.insert(key, value);
+                }
+                _ => log_panic!("TypeError: entry {:?} is not an array", *entry),
+            },
+            _ => log_panic!("TypeError: entry {:?} is not an object", *entry),
+        }
+    }
+    ht.len() as i32
+}
+
+/// Inserts the elements of `iterable` as keys, which are their own values, like
+/// `new Set(iterable)`, and returns the size of the table.
+#[no_mangle]
+pub extern "C" fn ht_add_values(mut ht: HTPtr, iterable: AnyValue) -> i32 {
+    for value in elements(iterable) {
+        ht.insert(value, value);
+    }
+    ht.len() as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::any_value::{any_from_bool, f64_to_any};
+    use crate::init;
+    use crate::object::object_empty;
+    use crate::HeapPtr;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn primitive_keys_compare_by_value() {
+        init();
+        let ht = ht_new();
+        let k1 = crate::heap().alloc_str_or_gc("key");
+        let k2 = crate::heap().alloc_str_or_gc("key");
+        ht_set_any(
+            ht,
+            AnyEnum::Ptr(k1.as_any_ptr()).into(),
+            AnyEnum::I32(1).into(),
+        );
+        ht_set_any(ht, f64_to_any(2.0), AnyEnum::I32(2).into());
+        ht_set_any(ht, f64_to_any(f64::NAN), AnyEnum::I32(3).into());
+        ht_set_any(ht, any_from_bool(true), AnyEnum::I32(4).into());
+        let key = AnyEnum::Ptr(k2.as_any_ptr()).into();
+        assert_eq!(ht_get_any(ht, key), AnyEnum::I32(1).into());
+        assert_eq!(
+            ht_get_any(ht, AnyEnum::I32(2).into()),
+            AnyEnum::I32(2).into()
+        );
+        assert_eq!(ht_get_any(ht, f64_to_any(f64::NAN)), AnyEnum::I32(3).into());
+        assert_eq!(ht_get_any(ht, any_from_bool(true)), AnyEnum::I32(4).into());
+        assert_eq!(
+            ht_get_any(ht, any_from_bool(false)),
+            AnyEnum::Undefined.into()
+        );
+        assert_eq!(ht_size(ht), 4);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn objects_compare_by_identity() {
+        init();
+        let ht = ht_new();
+        let o1 = AnyEnum::Ptr(object_empty().as_any_ptr()).into();
+        let o2 = AnyEnum::Ptr(object_empty().as_any_ptr()).into();
+        ht_set_any(ht, o1, AnyEnum::I32(1).into());
+        assert!(ht_has(ht, o1));
+        assert!(!ht_has(ht, o2));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn delete_keeps_insertion_order() {
+        init();
+        let ht = ht_new();
+        for i in 0..20 {
+            ht_set_any(ht, AnyEnum::I32(i).into(), AnyEnum::I32(i).into());
+        }
+        for i in 0..15 {
+            assert!(ht_delete(ht, AnyEnum::I32(i).into()));
+        }
+        assert!(!ht_delete(ht, AnyEnum::I32(0).into()));
+        ht_set_any(ht, AnyEnum::I32(0).into(), AnyEnum::I32(0).into());
+        ht_set_any(ht, AnyEnum::I32(16).into(), AnyEnum::I32(-16).into());
+        let keys = ht_keys(ht);
+        let keys = keys.iter().map(|key| **key).collect::<Vec<_>>();
+        let expected = [15, 16, 17, 18, 19, 0]
+            .iter()
+            .map(|i| AnyEnum::I32(*i))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+        assert_eq!(
+            ht_get_any(ht, AnyEnum::I32(16).into()),
+            AnyEnum::I32(-16).into()
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! `Date.now`, which is a source of nondeterminism of the standard library, like `Math.random`
+//! (see [crate::nondet]).
+//!
+//! By default, it asks the host. For reproducible runs (e.g., differential tests), the host calls
+//! [nondet_set_clock] before `main`, after which `Date.now` is a virtual clock that starts at that
+//! time. The virtual clock only advances when a task of `setTimeout` runs (see [crate::tasks]), so
+//! a program that reads it twice in a row sees the same time.
+
+use crate::any_value::{AnyValue as Any, *};
+use crate::heap_types::EnvPtr;
+
+/// The time at which the virtual clock starts, or `None` to ask the host.
+static mut CLOCK: Option<f64> = None;
+
+/// Makes `Date.now` a virtual clock that starts at `ms` milliseconds since the epoch.
+#[no_mangle]
+pub extern "C" fn nondet_set_clock(ms: f64) {
+    unsafe {
+        CLOCK = Some(ms);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn date_now(_: EnvPtr, _this: Any) -> Any {
+    f64_to_any(now())
+}
+
+fn now() -> f64 {
+    unsafe {
+        match CLOCK {
+            Some(start) => start + crate::tasks::now(),
+            None => host_now(),
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn host_now() -> f64 {
+    extern "C" {
+        fn jankscripten_now() -> f64;
+    }
+    unsafe { jankscripten_now() }
+}
+
+#[cfg(test)]
+fn host_now() -> f64 {
+    log_panic!("there is no host to ask for the time in tests")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn virtual_clock_starts_at_the_given_time() {
+        nondet_set_clock(1000.0);
+        assert_eq!(now(), 1000.0 + crate::tasks::now());
+    }
+}
@@ -0,0 +1,47 @@
+//! The support of the runtime system for the `Error` class, which stdlib/errors.notwasm
+//! defines. Without the `errors` feature, the runtime system does not have it.
+
+use crate::any_value::{AnyEnum, AnyValue as Any};
+use crate::string::StringPtr;
+use crate::wasm32::heap;
+
+/// The string of an error with the given `name` and `message`, which is `"name: message"`, or
+/// just one of the two if the other is empty.
+fn error_string(name: &str, message: &str) -> String {
+    if name.is_empty() {
+        message.to_string()
+    } else if message.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}: {}", name, message)
+    }
+}
+
+/// `Error.prototype.toString`, given the `name` and `message` of the error. An undefined `name`
+/// is `"Error"`, and an undefined `message` is empty.
+#[no_mangle]
+pub extern "C" fn error_to_string(name: Any, message: Any) -> StringPtr {
+    let name = match *name {
+        AnyEnum::Undefined => "Error".to_string(),
+        _ => name.to_string(),
+    };
+    let message = match *message {
+        AnyEnum::Undefined => String::new(),
+        _ => message.to_string(),
+    };
+    heap().alloc_str_or_gc(&error_string(&name, &message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn error_strings() {
+        assert_eq!(error_string("TypeError", "bad"), "TypeError: bad");
+        assert_eq!(error_string("Error", ""), "Error");
+        assert_eq!(error_string("", "bad"), "bad");
+    }
+}
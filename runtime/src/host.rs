@@ -0,0 +1,78 @@
+//! Calls to asynchronous operations of the host, such as timers.
+//!
+//! WebAssembly cannot suspend a call in progress, so a program that calls an asynchronous
+//! operation passes a continuation, which the host calls with the result of the operation after
+//! the program returns. `jnks_host_await` in the NotWasm standard library records the
+//! continuation, and [host_await] records the operation, under the same number. After `main`
+//! returns, the host takes each new operation with [host_next], performs it, and calls the
+//! `resume` export of the program with the number of the operation and its result. The
+//! continuation may start more operations, which the host takes in turn.
+//!
+//! An operation is a number that the host understands (e.g., `0` is a timer in `bin/run.js`) and
+//! a numeric argument.
+//!
+//! NotWasm programs pass the continuation explicitly. In JavaScript, an async function may
+//! `await` an operation `{op: n, arg: x}`, and the compiler turns the rest of the function into
+//! the continuation (see javascript::generators in libjankscripten). When a program may start an
+//! operation, the generated `main` leaves the globals frame on
+//! the shadow stack, so that the globals remain roots while the program is resumed.
+
+struct Operation {
+    op: i32,
+    arg: f64,
+}
+
+/// The operations that the program started, in order, thus the number of an operation is its
+/// index.
+static mut OPERATIONS: Vec<Operation> = Vec::new();
+
+/// The number of operations that the host took.
+static mut TAKEN: usize = 0;
+
+/// Starts the operation `op` with the argument `arg`, and produces its number.
+#[no_mangle]
+pub extern "C" fn host_await(op: i32, arg: f64) -> i32 {
+    unsafe {
+        OPERATIONS.push(Operation { op, arg });
+        (OPERATIONS.len() - 1) as i32
+    }
+}
+
+/// The number of the next operation that the host has not taken, or -1 if there is none.
+#[no_mangle]
+pub extern "C" fn host_next() -> i32 {
+    unsafe {
+        if TAKEN == OPERATIONS.len() {
+            return -1;
+        }
+        TAKEN += 1;
+        (TAKEN - 1) as i32
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn host_op(id: i32) -> i32 {
+    unsafe { OPERATIONS[id as usize].op }
+}
+
+#[no_mangle]
+pub extern "C" fn host_arg(id: i32) -> f64 {
+    unsafe { OPERATIONS[id as usize].arg }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn take_operations_in_order() {
+        let first = host_await(0, 10.0);
+        let second = host_await(1, 20.0);
+        assert_eq!(host_next(), first);
+        assert_eq!(host_next(), second);
+        assert_eq!(host_next(), -1);
+        assert_eq!((host_op(second), host_arg(second)), (1, 20.0));
+    }
+}
@@ -1,22 +1,141 @@
-//! create (currently unmanaged) HashMaps and manage them
+//! Hash tables, which are the `HT`s of NotWasm and the entries of JavaScript's `Map` and `Set`
+//! (see stdlib/collections.notwasm).
+//!
+//! A table remembers the order in which its keys were first inserted, which is the order in which
+//! a `Map` or a `Set` iterates. Keys are compared as `Map` compares them (SameValueZero):
+//! primitives by value, so `1` and `1.0` are the same key, as are `NaN` and `NaN`, and objects,
+//! arrays, and functions by identity.
 
 use super::{heap, heap_types::HTPtr};
-use crate::{AnyValue, Key};
+use crate::{AnyEnum, AnyValue, HeapPtr, HeapRefView, Key};
 use std::collections::HashMap;
 
+#[derive(Debug, Default)]
+pub struct HT {
+    /// The index of each key in `entries`.
+    index: HashMap<HTKey, usize>,
+    /// The entries, in insertion order. A deleted entry is `None`, so that deleting does not
+    /// move the others.
+    entries: Vec<Option<(AnyValue, AnyValue)>>,
+}
+
+/// The identity of a key: its value if it is a primitive, and its address otherwise. The heap
+/// does not move values, so the address of a live value does not change.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum HTKey {
+    /// The bits of a number, where `-0` is `0` and every `NaN` is the same `NaN`.
+    Number(u64),
+    Bool(bool),
+    String(String),
+    Ref(usize),
+    Undefined,
+    Null,
+}
+
+impl HTKey {
+    fn new(key: AnyValue) -> Self {
+        match *key {
+            AnyEnum::I32(n) => HTKey::number(n as f64),
+            AnyEnum::F64(ptr) => HTKey::number(unsafe { *ptr }),
+            AnyEnum::Bool(b) => HTKey::Bool(b),
+            AnyEnum::Ptr(ptr) => match ptr.view() {
+                HeapRefView::String(s) => HTKey::String(s.to_string()),
+                _ => HTKey::Ref(ptr.get_ptr() as usize),
+            },
+            // The environment of a closure is its identity (see ops::any_strict_eq).
+            AnyEnum::Closure(closure) => HTKey::Ref(closure.0.get_ptr() as usize),
+            AnyEnum::Undefined => HTKey::Undefined,
+            AnyEnum::Null => HTKey::Null,
+        }
+    }
+
+    fn number(x: f64) -> Self {
+        if x.is_nan() {
+            HTKey::Number(f64::NAN.to_bits())
+        } else if x == 0.0 {
+            HTKey::Number(0f64.to_bits())
+        } else {
+            HTKey::Number(x.to_bits())
+        }
+    }
+}
+
+impl HT {
+    pub fn get(&self, key: AnyValue) -> Option<AnyValue> {
+        let i = self.index.get(&HTKey::new(key))?;
+        self.entries[*i].map(|(_, value)| value)
+    }
+
+    /// Sets the value of `key`, which keeps its place in the order if the table has it already.
+    pub fn insert(&mut self, key: AnyValue, value: AnyValue) {
+        let k = HTKey::new(key);
+        match self.index.get(&k) {
+            Some(&i) => self.entries[i] = Some((key, value)),
+            None => {
+                self.index.insert(k, self.entries.len());
+                self.entries.push(Some((key, value)));
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: AnyValue) -> bool {
+        self.index.contains_key(&HTKey::new(key))
+    }
+
+    pub fn remove(&mut self, key: AnyValue) -> bool {
+        match self.index.remove(&HTKey::new(key)) {
+            Some(i) => {
+                self.entries[i] = None;
+                // Drop the deleted entries once they are most of the table.
+                if self.entries.len() > 2 * self.index.len() + 8 {
+                    self.compact();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The entries, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &(AnyValue, AnyValue)> {
+        self.entries.iter().flatten()
+    }
+
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        for (i, entry) in self.entries.iter().enumerate() {
+            let (key, _) = entry.unwrap();
+            self.index.insert(HTKey::new(key), i);
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn ht_new() -> HTPtr {
-    heap().alloc_or_gc(HashMap::new())
+    heap().alloc_or_gc(HT::default())
 }
 
 #[no_mangle]
 pub extern "C" fn ht_get(ht: HTPtr, field: Key) -> AnyValue {
-    HashMap::get(&ht, &field).unwrap().clone()
+    ht.get(AnyEnum::Ptr(field.as_any_ptr()).into()).unwrap()
 }
 
 #[no_mangle]
 pub extern "C" fn ht_set(mut ht: HTPtr, field: Key, value: AnyValue) -> AnyValue {
-    ht.insert(field, value.clone());
+    ht.insert(AnyEnum::Ptr(field.as_any_ptr()).into(), value);
     value
 }
 
@@ -24,7 +143,6 @@ pub extern "C" fn ht_set(mut ht: HTPtr, field: Key, value: AnyValue) -> AnyValue
 mod test {
     use super::*;
     use crate::init;
-    use crate::AnyEnum;
     use wasm_bindgen_test::wasm_bindgen_test;
     #[test]
     #[wasm_bindgen_test]
@@ -0,0 +1,114 @@
+//! The layouts of the values that the compiler reads and writes directly (see [abi::LAYOUTS]),
+//! which the host can print to document the memory of a program, and which the tests check
+//! against the types of the runtime system.
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+/// The layouts that [layout_dump] produced, which must outlive the call.
+static mut DUMPED: Option<CString> = None;
+
+/// The layouts of [abi::LAYOUTS] as tab-separated values, with a header line, and a line for each
+/// field that holds the value, the field, its byte offset, and its size. The items of a value
+/// with a variable number of them are named with a `[]` suffix, e.g., `Env items[] 16 8`.
+pub fn layouts() -> String {
+    let mut dump = String::from("value\tfield\toffset\tsize\n");
+    for layout in abi::LAYOUTS {
+        for field in layout.fields {
+            dump.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                layout.name, field.name, field.offset, field.size
+            ));
+        }
+        if let Some(items) = layout.items {
+            dump.push_str(&format!(
+                "{}\t{}[]\t{}\t{}\n",
+                layout.name, items.name, items.offset, items.size
+            ));
+        }
+    }
+    dump
+}
+
+/// Produces [layouts] as a null-terminated string, which is valid until the next call.
+#[no_mangle]
+pub unsafe extern "C" fn layout_dump() -> *const c_char {
+    DUMPED = Some(CString::new(layouts()).unwrap());
+    DUMPED.as_ref().unwrap().as_ptr()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::allocator::Tag;
+    use crate::closure::Closure;
+    use crate::heap_types::{EnvPtr, ObjectPtr};
+    use crate::AnyEnum;
+    use abi::{offset, size};
+    use std::mem::size_of;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// The byte offset of `field` in `value`.
+    fn offset_in<T, F>(value: &T, field: &F) -> u32 {
+        (field as *const F as usize - value as *const T as usize) as u32
+    }
+
+    #[wasm_bindgen_test]
+    fn tag_layout() {
+        let tag = Tag::object(5);
+        assert_eq!(size_of::<Tag>() as u32, abi::TAG_SIZE);
+        assert_eq!(offset_in(&tag, &tag.marked), offset("Tag", "marked"));
+        assert_eq!(offset_in(&tag, &tag.type_tag), offset("Tag", "type_tag"));
+        assert_eq!(offset_in(&tag, &tag.class_tag), offset("Tag", "class_tag"));
+        assert_eq!(size_of::<u16>() as u32, size("Tag", "class_tag"));
+    }
+
+    #[wasm_bindgen_test]
+    fn any_layout() {
+        let any: [u8; 8] = unsafe { std::mem::transmute(AnyEnum::I32(0x01020304)) };
+        let payload = offset("Any", "payload") as usize;
+        assert_eq!(any[offset("Any", "discriminant") as usize], 0);
+        assert_eq!(&any[payload..payload + 4], &0x01020304u32.to_le_bytes());
+        assert_eq!(
+            size_of::<Option<AnyEnum>>() as u32,
+            size("ObjectData", "slots")
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn pointer_layouts() {
+        assert_eq!(size_of::<Closure>() as u32, size("Closure", "env"));
+        assert_eq!(size_of::<EnvPtr>() as u32, size("Closure", "env"));
+        assert_eq!(size_of::<ObjectPtr>() as u32, size("Env", "fn_obj"));
+        assert_eq!(size_of::<ObjectPtr>() as u32, size("Object", "data"));
+    }
+
+    #[wasm_bindgen_test]
+    fn env_layout() {
+        // An environment with one item, in the words of a buffer.
+        let mut words = [0u32; 6];
+        let base = words.as_mut_ptr() as *mut Tag;
+        let fn_obj = unsafe { ObjectPtr::new(0x1234 as *mut Tag) };
+        let mut env = unsafe { EnvPtr::init(base, 1, fn_obj) };
+        env.set_func(13);
+        unsafe { env.init_at(0, AnyEnum::I32(42)) };
+        let word = |name| words[(offset("Env", name) / 4) as usize];
+        assert_eq!(word("length"), 1);
+        assert_eq!(word("fn_obj"), 0x1234);
+        assert_eq!(word("func"), 13);
+        assert_eq!(
+            words[(offset("Env", "items") + offset("Any", "payload")) as usize / 4],
+            42
+        );
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn dump_has_a_line_per_field() {
+        let dump = layouts();
+        assert!(dump.starts_with("value\tfield\toffset\tsize\n"));
+        assert!(dump.contains("Env\tfunc\t12\t4\n"));
+        assert!(dump.contains("Env\titems[]\t16\t8\n"));
+        assert!(dump.contains("Tag\ttype_tag\t1\t1\n"));
+    }
+}
@@ -41,20 +41,38 @@ pub mod any_value;
 #[cfg(all(target_arch = "wasm32"))]
 pub mod array;
 #[cfg(all(target_arch = "wasm32"))]
+pub mod bridge;
+#[cfg(all(target_arch = "wasm32"))]
+pub mod call_trace;
+#[cfg(all(target_arch = "wasm32"))]
 pub mod closure;
+#[cfg(all(target_arch = "wasm32", feature = "collections"))]
+pub mod collections;
 #[cfg(all(target_arch = "wasm32"))]
 mod coercions;
+#[cfg(all(target_arch = "wasm32", feature = "date"))]
+pub mod date;
 #[cfg(all(target_arch = "wasm32"))]
 pub mod env;
+#[cfg(all(target_arch = "wasm32", feature = "errors"))]
+pub mod errors;
 #[cfg(all(target_arch = "wasm32"))]
 pub mod ht;
 #[cfg(all(target_arch = "wasm32"))]
+pub mod host;
+#[cfg(all(target_arch = "wasm32"))]
+pub mod layouts;
+#[cfg(all(target_arch = "wasm32"))]
 pub mod math;
 #[cfg(all(target_arch = "wasm32"))]
+pub mod nondet;
+#[cfg(all(target_arch = "wasm32"))]
 pub mod object;
 #[cfg(all(target_arch = "wasm32"))]
 pub mod ops;
 #[cfg(all(target_arch = "wasm32"))]
+pub mod profile;
+#[cfg(all(target_arch = "wasm32"))]
 pub mod r#ref; // Rust raw identifier syntax
 #[cfg(all(target_arch = "wasm32"))]
 pub mod static_strings;
@@ -62,6 +80,12 @@ pub mod static_strings;
 pub mod std_lib;
 #[cfg(all(target_arch = "wasm32"))]
 pub mod string;
+#[cfg(all(target_arch = "wasm32"))]
+pub mod tasks;
+#[cfg(all(target_arch = "wasm32"))]
+pub mod trap;
+#[cfg(all(target_arch = "wasm32", feature = "uri"))]
+pub mod uri;
 
 #[cfg(all(target_arch = "wasm32"))]
 mod i64_val;
@@ -47,3 +47,99 @@ pub extern "C" fn math_max(_: EnvPtr, _this: Any, a: Any, b: Any) -> Any {
     )
     .unwrap()
 }
+
+/// `Number.isInteger`, which does not convert its argument to a number
+#[no_mangle]
+pub extern "C" fn number_is_integer(_: EnvPtr, _this: Any, a: Any) -> Any {
+    any_from_bool(match *a {
+        AnyEnum::I32(_) => true,
+        AnyEnum::F64(ptr) => {
+            let x = unsafe { *ptr };
+            x.is_finite() && x.trunc() == x
+        }
+        _ => false,
+    })
+}
+/// `Number.isNaN`, which does not convert its argument to a number
+#[no_mangle]
+pub extern "C" fn number_is_nan(_: EnvPtr, _this: Any, a: Any) -> Any {
+    any_from_bool(match *a {
+        AnyEnum::F64(ptr) => unsafe { *ptr }.is_nan(),
+        _ => false,
+    })
+}
+/// `Number.isFinite`, which does not convert its argument to a number
+#[no_mangle]
+pub extern "C" fn number_is_finite(_: EnvPtr, _this: Any, a: Any) -> Any {
+    any_from_bool(match *a {
+        AnyEnum::I32(_) => true,
+        AnyEnum::F64(ptr) => unsafe { *ptr }.is_finite(),
+        _ => false,
+    })
+}
+#[no_mangle]
+pub extern "C" fn number_parse_float(_: EnvPtr, _this: Any, a: Any) -> Any {
+    f64_to_any(parse_float(&a.to_string()))
+}
+
+/// `parseFloat`: the number that the longest prefix of `s` that is a decimal literal denotes,
+/// after any leading whitespace, or NaN if there is no such prefix.
+fn parse_float(s: &str) -> f64 {
+    let s = s.trim_start();
+    let bytes = s.as_bytes();
+    let digits = |i: usize| bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut end = match bytes.first() {
+        Some(b'+') | Some(b'-') => 1,
+        _ => 0,
+    };
+    if s[end..].starts_with("Infinity") {
+        return if s.starts_with('-') {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+    }
+    let int_digits = digits(end);
+    end += int_digits;
+    let frac_digits = match bytes.get(end) {
+        Some(b'.') => digits(end + 1),
+        _ => 0,
+    };
+    if int_digits + frac_digits == 0 {
+        return f64::NAN;
+    }
+    if bytes.get(end) == Some(&b'.') {
+        end += 1 + frac_digits;
+    }
+    if let Some(b'e') | Some(b'E') = bytes.get(end) {
+        let sign = match bytes.get(end + 1) {
+            Some(b'+') | Some(b'-') => 1,
+            _ => 0,
+        };
+        let exp_digits = digits(end + 1 + sign);
+        if exp_digits > 0 {
+            end += 1 + sign + exp_digits;
+        }
+    }
+    s[..end].parse().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn parse_float_prefixes() {
+        assert_eq!(parse_float("  3.25abc"), 3.25);
+        assert_eq!(parse_float("-.5"), -0.5);
+        assert_eq!(parse_float("1."), 1.0);
+        assert_eq!(parse_float("2e3x"), 2000.0);
+        assert_eq!(parse_float("2e"), 2.0);
+        assert_eq!(parse_float("+1.5E-1"), 0.15);
+        assert_eq!(parse_float("-Infinityx"), f64::NEG_INFINITY);
+        assert!(parse_float(".").is_nan());
+        assert!(parse_float("abc").is_nan());
+    }
+}
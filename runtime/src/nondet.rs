@@ -0,0 +1,77 @@
+//! `Math.random`, which is a source of nondeterminism of the standard library, like `Date.now`
+//! (see [crate::date]).
+//!
+//! By default, it asks the host. For reproducible runs (e.g., differential tests), the host calls
+//! [nondet_set_seed] before `main`, after which `Math.random` is a pseudo-random number generator
+//! with that seed.
+
+use crate::any_value::{AnyValue as Any, *};
+use crate::heap_types::EnvPtr;
+
+/// The state of the generator, or `None` to ask the host.
+static mut SEED: Option<u64> = None;
+
+/// Makes `Math.random` deterministic, starting from `seed`.
+#[no_mangle]
+pub extern "C" fn nondet_set_seed(seed: f64) {
+    unsafe {
+        SEED = Some(seed as u64);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn math_random(_: EnvPtr, _this: Any) -> Any {
+    f64_to_any(random())
+}
+
+fn random() -> f64 {
+    unsafe {
+        match &mut SEED {
+            Some(state) => splitmix64(state),
+            None => host_random(),
+        }
+    }
+}
+
+/// The next number in [0, 1) of the SplitMix64 generator, which has no bad seeds. The NotWasm
+/// and JankyScript interpreters use the same generator, so they agree with seeded runs.
+fn splitmix64(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    // The 53 high bits are the mantissa.
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(not(test))]
+fn host_random() -> f64 {
+    extern "C" {
+        fn jankscripten_random() -> f64;
+    }
+    unsafe { jankscripten_random() }
+}
+
+#[cfg(test)]
+fn host_random() -> f64 {
+    log_panic!("there is no host to ask for random numbers in tests")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn seeded_random_is_reproducible() {
+        nondet_set_seed(42.0);
+        let first: Vec<f64> = (0..3).map(|_| random()).collect();
+        nondet_set_seed(42.0);
+        let second: Vec<f64> = (0..3).map(|_| random()).collect();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|r| *r >= 0.0 && *r < 1.0));
+        assert_ne!(first[0], first[1]);
+    }
+}
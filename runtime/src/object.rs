@@ -1,5 +1,6 @@
 //! Bindings to heap_type's ObjectPtr: instances of hidden classes
 
+use crate::coercions::match_object;
 use crate::heap;
 use crate::heap_types::*;
 use crate::static_strings::static_strings;
@@ -17,15 +18,40 @@ pub extern "C" fn object_set(
     value: AnyValue,
     cache: &mut isize,
 ) -> AnyValue {
-    object.insert(heap(), field, value, cache);
+    record_site(object, cache);
+    // Outside of strict mode, which the compiler does not support, JavaScript ignores writes to
+    // sealed and frozen objects that would change them.
+    if object.is_writable(heap(), field) {
+        object.insert(heap(), field, value, cache);
+    }
     value
 }
 
 #[no_mangle]
 pub extern "C" fn object_get(object: ObjectPtr, field: StringPtr, cache: &mut isize) -> AnyValue {
+    record_site(object, cache);
     object.get(heap(), field, cache).into()
 }
 
+/// Records the class that the inline cache `cache` sees, for `__JNKS.class_stats`.
+fn record_site(object: ObjectPtr, cache: &isize) {
+    heap()
+        .classes
+        .borrow_mut()
+        .record_site(cache as *const isize as usize, object.class_tag());
+}
+
+/// Reads a variable that the program does not declare, which is a field of the global object.
+/// As in JavaScript, reading a variable that the global object does not have is a
+/// `ReferenceError`.
+#[no_mangle]
+pub extern "C" fn global_get(global: ObjectPtr, name: StringPtr) -> AnyValue {
+    if !global.has_own_property(heap(), name) {
+        log_panic!("ReferenceError: {} is not defined", name);
+    }
+    global.get(heap(), name, &mut -1).into()
+}
+
 /// Object.create
 /// TODO(luna): presumably there should be some way to type this correctly
 /// as returning a DynObject even though it's ultimately stored in a DynObject
@@ -61,3 +87,144 @@ pub extern "C" fn object_create(
     )
     .into()
 }
+
+/// Object.seal. Like JavaScript since ES2015, returns values that are not objects unchanged.
+/// Arrays and functions cannot be sealed or frozen.
+#[no_mangle]
+pub extern "C" fn object_seal(value: AnyValue) -> AnyValue {
+    if let Some(object) = match_object(*value) {
+        object.seal();
+    }
+    value
+}
+
+/// Object.freeze, which also seals the object.
+#[no_mangle]
+pub extern "C" fn object_freeze(value: AnyValue) -> AnyValue {
+    if let Some(object) = match_object(*value) {
+        object.freeze();
+    }
+    value
+}
+
+/// Object.isSealed. Like JavaScript since ES2015, values that are not objects are sealed.
+#[no_mangle]
+pub extern "C" fn object_is_sealed(value: AnyValue) -> bool {
+    integrity(value, |object| object.is_sealed())
+}
+
+/// Object.isFrozen
+#[no_mangle]
+pub extern "C" fn object_is_frozen(value: AnyValue) -> bool {
+    integrity(value, |object| object.is_frozen())
+}
+
+fn integrity(value: AnyValue, test: impl Fn(ObjectPtr) -> bool) -> bool {
+    match *value {
+        AnyEnum::Ptr(ptr) => match ptr.view() {
+            HeapRefView::ObjectPtrPtr(object) => test(object),
+            HeapRefView::String(_) => true,
+            _ => false,
+        },
+        AnyEnum::Closure(_) => false,
+        _ => true,
+    }
+}
+
+/// Reads the little-endian `u32` at `ptr`, which the compiler writes into the data segment.
+pub(crate) unsafe fn read_u32(ptr: *const u8) -> u32 {
+    u32::from_le(*(ptr as *const u32))
+}
+
+/// Creates the classes of the object literals in the program, which must happen before the
+/// program creates any objects. `table` is the number of class descriptors followed by their
+/// offsets in the data segment, which starts at `strings`. A class descriptor is a slot for the
+/// class tag, the number of fields, and the offset of the name of each field. The class of an
+/// object literal inherits from a prototype, so its first field is `__proto__`.
+#[no_mangle]
+pub unsafe extern "C" fn object_literal_classes_init(strings: *mut u8, table: *const u8) {
+    let mut classes = heap().classes.borrow_mut();
+    let num_descriptors = read_u32(table) as usize;
+    for i in 0..num_descriptors {
+        let descriptor = strings.add(read_u32(table.add(4 * (i + 1))) as usize);
+        let num_fields = read_u32(descriptor.add(4)) as usize;
+        let mut class_tag = classes.transition(0, static_strings().__proto__);
+        for j in 0..num_fields {
+            let name = strings.add(read_u32(descriptor.add(4 * (j + 2))) as usize);
+            class_tag = classes.transition(class_tag, StringPtr::new(name as *mut crate::Tag));
+        }
+        *(descriptor as *mut u32) = (class_tag as u32).to_le();
+    }
+}
+
+/// Creates an object literal, which inherits from `prototype` and has the class of
+/// `descriptor`. Its fields are empty until the program writes them.
+#[no_mangle]
+pub unsafe extern "C" fn object_literal(descriptor: *const u8, prototype: AnyValue) -> ObjectPtr {
+    let object = heap().alloc_object_or_gc(read_u32(descriptor) as u16);
+    object.write_at(heap(), 0, prototype);
+    object
+}
+
+/// Like [object_literal], but allocates the object in the scratch region of the function, since
+/// the object does not escape it.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_object_literal(
+    descriptor: *const u8,
+    prototype: AnyValue,
+) -> ObjectPtr {
+    let object = heap().alloc_scratch_object_or_gc(read_u32(descriptor) as u16);
+    object.write_at(heap(), 0, prototype);
+    object
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::init;
+    use wasm_bindgen_test::*;
+
+    fn set(object: ObjectPtr, field: &str, value: i32) {
+        let field = heap().alloc_str_or_gc(field);
+        object_set(object, field, AnyEnum::I32(value).into(), &mut -1);
+    }
+
+    fn get(object: ObjectPtr, field: &str) -> AnyEnum {
+        let field = heap().alloc_str_or_gc(field);
+        *object_get(object, field, &mut -1)
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn frozen_object_ignores_writes() {
+        init();
+        let object = object_empty();
+        set(object, "x", 1);
+        let any = AnyEnum::Ptr(object.as_any_ptr()).into();
+        assert!(!object_is_frozen(any));
+        object_freeze(any);
+        set(object, "x", 2);
+        set(object, "y", 3);
+        assert_eq!(get(object, "x"), AnyEnum::I32(1));
+        assert_eq!(get(object, "y"), AnyEnum::Undefined);
+        assert!(object_is_frozen(any));
+        assert!(object_is_sealed(any));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn sealed_object_only_changes_its_fields() {
+        init();
+        let object = object_empty();
+        set(object, "x", 1);
+        let any = AnyEnum::Ptr(object.as_any_ptr()).into();
+        object_seal(any);
+        set(object, "x", 2);
+        set(object, "y", 3);
+        assert_eq!(get(object, "x"), AnyEnum::I32(2));
+        assert_eq!(get(object, "y"), AnyEnum::Undefined);
+        assert!(object_is_sealed(any));
+        assert!(!object_is_frozen(any));
+        assert!(object_is_frozen(AnyEnum::I32(5).into()));
+    }
+}
@@ -7,6 +7,7 @@ use crate::heap_types::ObjectPtr;
 use crate::static_strings::static_strings;
 use crate::string::*;
 use crate::util::unwrap_log;
+use crate::HeapPtr;
 
 /// A helper function for the JavaScript `+` operator. This is called
 /// by `jnks_plus` in the NotWasm runtime, which is the full implementation
@@ -25,6 +26,13 @@ pub extern "C" fn janky_primitive_plus(a: Any, b: Any) -> Any {
             let a_string = any_to_string(a);
             let b_string = any_to_string(b);
 
+            // `"" + x` is just the string of `x`, which need not be copied
+            if a_string.is_empty() {
+                return AnyEnum::Ptr(b_string.as_any_ptr()).into();
+            } else if b_string.is_empty() {
+                return AnyEnum::Ptr(a_string.as_any_ptr()).into();
+            }
+
             // combine them
             let combined = format!("{}{}", a_string, b_string);
 
@@ -32,21 +40,15 @@ pub extern "C" fn janky_primitive_plus(a: Any, b: Any) -> Any {
             let combined = heap().alloc_str_or_gc(&combined);
             unsafe { AnyEnum::Ptr(std::mem::transmute(combined)).into() }
         }
-        // We have two primitive values. Try to perform numeric addition on them.
-        (_, _) => {
-            if let Some(res) = i32s_or_as_f64s_any(a, b, |a, b| a + b, |a, b| a + b) {
-                res
-            } else {
-                // TODO(luna): these panics in this file should be exceptions, when we support those
-                log_panic!("unsupported for +: {:?}, {:?}", a, b)
-            }
-        }
+        // We have two primitive values, so this is numeric addition after
+        // ToNumber (e.g. `true + 1 === 2`).
+        (_, _) => i32s_or_as_numbers_any(a, b, |a, b| a + b, |a, b| a + b),
     }
 }
 
 #[no_mangle]
 pub extern "C" fn janky_minus(a: Any, b: Any) -> Any {
-    i32s_or_as_f64s_any(a, b, |a, b| a - b, |a, b| a - b).expect("unsupported for -")
+    i32s_or_as_numbers_any(a, b, |a, b| a - b, |a, b| a - b)
 }
 #[no_mangle]
 pub extern "C" fn janky_neg(a: Any) -> Any {
@@ -58,23 +60,45 @@ pub extern "C" fn janky_neg(a: Any) -> Any {
 }
 #[no_mangle]
 pub extern "C" fn janky_times(a: Any, b: Any) -> Any {
-    i32s_or_as_f64s_any(a, b, |a, b| a * b, |a, b| a * b).expect("unsupported for *")
+    i32s_or_as_numbers_any(a, b, |a, b| a * b, |a, b| a * b)
 }
 #[no_mangle]
 pub extern "C" fn janky_over(a: Any, b: Any) -> f64 {
-    i32s_or_as_f64s(a, b, |a, b| a / b, |a, b| a as f64 / b as f64).expect("unsupported for /")
+    any_to_number(a) / any_to_number(b)
 }
 #[no_mangle]
 pub extern "C" fn janky_mod(a: Any, b: Any) -> Any {
-    i32s_or_as_f64s_any(a, b, |a, b| a % b, |a, b| a % b).expect("unsupported for %")
+    i32s_or_as_numbers_any(a, b, |a, b| a % b, |a, b| a % b)
 }
 #[no_mangle]
 pub extern "C" fn janky_mod_f64(a: f64, b: f64) -> f64 {
     a % b
 }
+/// The strict equality algorithm of JavaScript (`===`), which compares values of the same type:
+/// numbers by value, whether they are `i32`s or `f64`s (thus `NaN !== NaN` and `0 === -0`),
+/// strings by their contents, and objects, arrays, and closures by identity.
+///
+/// https://tc39.es/ecma262/#sec-isstrictlyequal
 #[no_mangle]
-pub extern "C" fn janky_strict_equal(a: Any, b: Any) -> bool {
-    a == b
+pub extern "C" fn any_strict_eq(a: Any, b: Any) -> bool {
+    if let Some(res) = i32s_or_as_f64s(a, b, |a, b| a == b, |a, b| a == b) {
+        return res;
+    }
+    match (*a, *b) {
+        (AnyEnum::Bool(a), AnyEnum::Bool(b)) => a == b,
+        (AnyEnum::Ptr(a), AnyEnum::Ptr(b)) => match (a.view(), b.view()) {
+            (HeapRefView::String(a), HeapRefView::String(b)) => a == b,
+            (HeapRefView::NonPtr32(_), _) | (_, HeapRefView::NonPtr32(_)) => {
+                log_panic!("ref is not a value")
+            }
+            _ => a == b,
+        },
+        // The environment of a closure is its identity, since every function expression
+        // allocates a new one.
+        (AnyEnum::Closure(a), AnyEnum::Closure(b)) => a == b,
+        (AnyEnum::Undefined, AnyEnum::Undefined) | (AnyEnum::Null, AnyEnum::Null) => true,
+        _ => false,
+    }
 }
 #[no_mangle]
 pub extern "C" fn janky_equal(a: Any, b: Any) -> bool {
@@ -82,7 +106,7 @@ pub extern "C" fn janky_equal(a: Any, b: Any) -> bool {
 }
 #[no_mangle]
 pub extern "C" fn janky_strict_not_equal(a: Any, b: Any) -> bool {
-    a != b
+    !any_strict_eq(a, b)
 }
 #[no_mangle]
 pub extern "C" fn janky_not_equal(a: Any, b: Any) -> bool {
@@ -190,7 +214,10 @@ fn typeof_as_str(a: Any) -> &'static str {
         AnyEnum::Bool(_) => "boolean",
         AnyEnum::Ptr(ptr) => match ptr.view() {
             HeapRefView::String(_) => "string",
-            HeapRefView::HT(_) | HeapRefView::Array(_) | HeapRefView::ObjectPtrPtr(_) => "object",
+            HeapRefView::HT(_)
+            | HeapRefView::Array(_)
+            | HeapRefView::ObjectPtrPtr(_)
+            | HeapRefView::Extension(_) => "object",
             HeapRefView::Any(what) => typeof_as_str(*what),
             HeapRefView::Class(_) => panic!("shouldn't be able to typeof non-value object data"),
             HeapRefView::MutF64(_) => "number",
@@ -203,3 +230,57 @@ fn typeof_as_str(a: Any) -> &'static str {
         AnyEnum::Null => "object",
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::closure::closure_new;
+    use crate::env::env_alloc;
+    use crate::init;
+    use crate::object::object_empty;
+    use wasm_bindgen_test::*;
+
+    fn string(s: &str) -> Any {
+        AnyEnum::Ptr(heap().alloc_str_or_gc(s).as_any_ptr()).into()
+    }
+
+    fn object() -> Any {
+        AnyEnum::Ptr(object_empty().as_any_ptr()).into()
+    }
+
+    fn closure() -> Any {
+        let env = unsafe { env_alloc(0, object_empty()) };
+        AnyEnum::Closure(closure_new(env, 0)).into()
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn strict_equality_of_primitives() {
+        init();
+        assert!(any_strict_eq(any_from_i32(1), f64_to_any(1.0)));
+        assert!(any_strict_eq(f64_to_any(0.5), f64_to_any(0.5)));
+        assert!(any_strict_eq(f64_to_any(0.0), f64_to_any(-0.0)));
+        assert!(!any_strict_eq(f64_to_any(f64::NAN), f64_to_any(f64::NAN)));
+        assert!(any_strict_eq(string("ab"), string("ab")));
+        assert!(!any_strict_eq(string("1"), any_from_i32(1)));
+        assert!(!any_strict_eq(any_from_bool(true), any_from_i32(1)));
+        assert!(any_strict_eq(AnyEnum::Null.into(), AnyEnum::Null.into()));
+        assert!(!any_strict_eq(
+            AnyEnum::Null.into(),
+            AnyEnum::Undefined.into()
+        ));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn strict_equality_of_objects_is_identity() {
+        init();
+        let (o, f) = (object(), closure());
+        assert!(any_strict_eq(o, o));
+        assert!(!any_strict_eq(o, object()));
+        assert!(any_strict_eq(f, f));
+        assert!(!any_strict_eq(f, closure()));
+        assert!(!any_strict_eq(o, f));
+        assert!(janky_strict_not_equal(o, object()));
+    }
+}
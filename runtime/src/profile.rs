@@ -0,0 +1,123 @@
+//! The profile counters of a program that the compiler instrumented for profile-guided
+//! optimization. The compiler writes a counter for each function and inline cache into the data
+//! segment of the program, and the program increments the counters itself. The runtime system
+//! only serializes them after a run.
+
+use crate::object::read_u32;
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+/// The start of the data segment and the table of profile counters, which the program passes to
+/// [profile_init] when it starts.
+static mut COUNTERS: Option<(*const u8, *const u8)> = None;
+
+/// The profile that [profile_serialize] produced last, which must outlive the call.
+static mut SERIALIZED: Option<CString> = None;
+
+/// Records the profile counters of the program. `table` is the number of counters followed by
+/// their offsets in the data segment, which starts at `strings`. A counter is the count, the
+/// length of the name of its site, and the bytes of the name.
+#[no_mangle]
+pub unsafe extern "C" fn profile_init(strings: *const u8, table: *const u8) {
+    COUNTERS = Some((strings, table));
+}
+
+/// The counts and names of the profile counters, or `None` if the program is not instrumented.
+unsafe fn counters() -> Option<Vec<(u32, &'static str)>> {
+    let (strings, table) = COUNTERS?;
+    let num_counters = read_u32(table) as usize;
+    let mut counters = Vec::with_capacity(num_counters);
+    for i in 0..num_counters {
+        let counter = strings.add(read_u32(table.add(4 * (i + 1))) as usize);
+        let len = read_u32(counter.add(4)) as usize;
+        let name = std::slice::from_raw_parts(counter.add(8), len);
+        counters.push((read_u32(counter), std::str::from_utf8_unchecked(name)));
+    }
+    Some(counters)
+}
+
+unsafe fn serialized(s: String) -> *const c_char {
+    SERIALIZED = Some(CString::new(s).expect("site name with a null byte"));
+    SERIALIZED.as_ref().unwrap().as_ptr()
+}
+
+/// Serializes the profile counters, with one line per site that holds the count, a space, and
+/// the name of the site. Produces a null-terminated string, which is valid until the next call,
+/// or null if the program is not instrumented.
+#[no_mangle]
+pub unsafe extern "C" fn profile_serialize() -> *const c_char {
+    let counters = match counters() {
+        Some(counters) => counters,
+        None => return std::ptr::null(),
+    };
+    let mut profile = String::new();
+    for (count, name) in counters {
+        profile.push_str(&format!("{} {}\n", count, name));
+    }
+    serialized(profile)
+}
+
+/// Reports the coercions to and from `any` that ran, hottest first, for a program that was
+/// compiled with `--trace-coercions`. Each line holds the count and the coercion, e.g.,
+/// `12 any -> i32 at test.js: line 2, column 9`. Coercions that ran zero times are omitted.
+/// Like [profile_serialize], produces a null-terminated string, or null if the program is not
+/// instrumented.
+#[no_mangle]
+pub unsafe extern "C" fn profile_coercion_report() -> *const c_char {
+    match counters() {
+        Some(counters) => serialized(coercion_report(&counters)),
+        None => std::ptr::null(),
+    }
+}
+
+/// The sites of coercions are named `f%n coercion` (see `Profile::coercion_site` in the
+/// compiler). Several sites may describe the same coercion, e.g., when desugaring duplicates
+/// code, so the report sums their counts.
+fn coercion_report(counters: &[(u32, &str)]) -> String {
+    let mut coercions: Vec<(u64, &str)> = Vec::new();
+    for (count, name) in counters {
+        let description = match name.splitn(2, '%').nth(1) {
+            Some(rest) => match rest.splitn(2, ' ').nth(1) {
+                Some(description) => description,
+                None => continue,
+            },
+            None => continue,
+        };
+        match coercions.iter_mut().find(|(_, d)| *d == description) {
+            Some((total, _)) => *total += *count as u64,
+            None => coercions.push((*count as u64, description)),
+        }
+    }
+    // The sort is stable, so equally hot coercions stay in the order of the program.
+    coercions.sort_by(|(m, _), (n, _)| n.cmp(m));
+    let mut report = String::new();
+    for (count, description) in coercions {
+        if count > 0 {
+            report.push_str(&format!("{} {}\n", count, description));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn coercion_report_is_hottest_first() {
+        let counters = vec![
+            (1, "main"),
+            (2, "main%0 i32 -> any at line 1"),
+            (0, "main%1 any -> f64 at line 2"),
+            (5, "g@0"),
+            (3, "g%0 any -> i32 at line 3"),
+            (4, "h%0 i32 -> any at line 1"),
+        ];
+        assert_eq!(
+            coercion_report(&counters),
+            "6 i32 -> any at line 1\n3 any -> i32 at line 3\n"
+        );
+    }
+}
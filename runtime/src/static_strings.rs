@@ -3,6 +3,9 @@
 //! if you would like an interned string, add it to the struct definition and
 //! the appropriate spot in init() (make sure it's less than 32 bytes)
 //!
+//! the strings of small integers are interned too, so that converting an
+//! integer to a string in a loop (e.g. `String(i)`) does not allocate
+//!
 //! Because rust doesn't inline the exact same way notwasm does,
 //! rather than allocate the string "__proto__" / "prototype" / etc whenever we
 //! need them, we allocate and initialize them all once, during init (doing it at
@@ -14,10 +17,25 @@ use crate::heap_types::StringPtr;
 
 static mut STATIC_STRINGS: Option<StaticStrings> = None;
 
-#[derive(Clone, Copy)]
+/// The number of small integers, starting from 0, whose strings are interned
+const SMALL_INTS: i32 = 256;
+
+#[derive(Clone)]
 pub struct StaticStrings {
     pub __proto__: StringPtr,
     pub prototype: StringPtr,
+    small_ints: Vec<StringPtr>,
+}
+
+impl StaticStrings {
+    /// The interned string of `n`, if it is a small integer
+    pub fn small_int(&self, n: i32) -> Option<StringPtr> {
+        if 0 <= n && n < SMALL_INTS {
+            Some(self.small_ints[n as usize])
+        } else {
+            None
+        }
+    }
 }
 
 pub fn init() {
@@ -25,6 +43,7 @@ pub fn init() {
         STATIC_STRINGS = Some(StaticStrings {
             __proto__: mkstr32("__proto__"),
             prototype: mkstr32("prototype"),
+            small_ints: (0..SMALL_INTS).map(|n| mkstr32(&n.to_string())).collect(),
         });
     }
 }
@@ -40,6 +59,7 @@ pub fn static_strings() -> &'static StaticStrings {
 struct Str32 {
     tag: Tag,
     len: u32,
+    utf16_len: u32,
     data: [u8; 32],
 }
 
@@ -48,10 +68,11 @@ struct Str32 {
 /// 1. actually use the heap allocation. disadvantage: have to jury-rig garbage
 ///    collection to not garbage collect them
 /// 2. use a vec. disadvantage: annoying to build
-fn mkstr32(s: &'static str) -> StringPtr {
+fn mkstr32(s: &str) -> StringPtr {
     let mut short_str = Str32 {
         tag: Tag::with_type(TypeTag::String),
         len: u32::to_le(s.len() as u32),
+        utf16_len: u32::to_le(s.encode_utf16().count() as u32),
         data: [0; 32],
     };
     unsafe {
@@ -17,7 +17,9 @@ pub extern "C" fn parse_int(env: EnvPtr, this: Any, a: Any, radix_any: Any) -> A
                 Ok(o) => AnyEnum::I32(o).into(),
                 Err(_) => todo!("support partial parse"),
             },
-            HeapRefView::HT(_) | HeapRefView::ObjectPtrPtr(_) => heap().f64_to_any(f64::NAN),
+            HeapRefView::HT(_) | HeapRefView::ObjectPtrPtr(_) | HeapRefView::Extension(_) => {
+                heap().f64_to_any(f64::NAN)
+            }
             HeapRefView::Array(a) => parse_int(env, this, a[0], radix_any),
             HeapRefView::Any(what) => parse_int(env, this, *what, radix_any),
             HeapRefView::Class(_)
@@ -29,6 +31,14 @@ pub extern "C" fn parse_int(env: EnvPtr, this: Any, a: Any, radix_any: Any) -> A
     }
 }
 
+/// `Boolean(x)` called as a function, which is just ToBoolean. `new
+/// Boolean(x)` would make a wrapper object, which (like every object) is
+/// truthy even when it wraps `false`; we don't support those
+#[no_mangle]
+pub extern "C" fn to_boolean(_: EnvPtr, _this: Any, a: Any) -> Any {
+    any_from_bool(any_to_bool(a))
+}
+
 /// we return undefined for type messiness for now
 #[no_mangle]
 pub extern "C" fn console_log(_: EnvPtr, _this: Any, a: Any) -> Any {
@@ -1,11 +1,16 @@
 //! create managed strings and manipulate them
 
 pub use super::heap_types::StringPtr;
-use crate::heap;
+use crate::any_value::any_to_f64;
+use crate::closure::call_closure;
+use crate::heap_types::EnvPtr;
+use crate::{heap, AnyEnum, AnyValue, HeapPtr};
 
+/// The length in UTF-16 code units, like JavaScript. The compiler reads it inline instead of
+/// calling this function.
 #[no_mangle]
 pub extern "C" fn string_length(string: StringPtr) -> i32 {
-    string.len() as i32
+    string.utf16_len() as i32
 }
 
 /// Append the given strings
@@ -18,11 +23,145 @@ pub extern "C" fn string_concat(a: StringPtr, b: StringPtr) -> StringPtr {
     heap().alloc_str_or_gc(combined.as_str())
 }
 
+/// `===` on strings. Equal strings that the compiler interns are the same string, so comparing
+/// the pointers first often avoids comparing the contents.
+#[no_mangle]
+pub extern "C" fn string_eq(a: StringPtr, b: StringPtr) -> bool {
+    a == b
+}
+
+/// `!==` on strings.
+#[no_mangle]
+pub extern "C" fn string_ne(a: StringPtr, b: StringPtr) -> bool {
+    a != b
+}
+
 #[no_mangle]
 pub extern "C" fn string_slice(s: StringPtr, a: i32, b: i32) -> StringPtr {
     s.slice(a, b)
 }
 
+/// String.prototype.charAt, which is the empty string out of bounds
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn string_charAt(s: StringPtr, index: i32) -> StringPtr {
+    if index < 0 || index as usize >= s.utf16_len() {
+        heap().alloc_str_or_gc("")
+    } else {
+        s.slice(index, index + 1)
+    }
+}
+
+/// String.prototype.charCodeAt, which is NaN out of bounds
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn string_charCodeAt(s: StringPtr, index: i32) -> AnyValue {
+    let unit = if index < 0 {
+        None
+    } else {
+        s.code_unit_at(index as usize)
+    };
+    match unit {
+        Some(unit) => AnyEnum::I32(unit as i32).into(),
+        None => heap().f64_to_any(f64::NAN),
+    }
+}
+
+/// String.prototype.codePointAt, which combines a surrogate pair into one code point, and is
+/// undefined out of bounds
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn string_codePointAt(s: StringPtr, index: i32) -> AnyValue {
+    if index < 0 {
+        return AnyEnum::Undefined.into();
+    }
+    let index = index as usize;
+    match (s.code_unit_at(index), s.code_unit_at(index + 1)) {
+        (Some(high @ 0xd800..=0xdbff), Some(low @ 0xdc00..=0xdfff)) => {
+            let code_point = 0x10000 + ((high as i32 - 0xd800) << 10) + (low as i32 - 0xdc00);
+            AnyEnum::I32(code_point).into()
+        }
+        (Some(unit), _) => AnyEnum::I32(unit as i32).into(),
+        (None, _) => AnyEnum::Undefined.into(),
+    }
+}
+
+/// String.fromCharCode with one code unit, which is converted with ToUint16. A string cannot
+/// hold half of a surrogate pair, so a surrogate becomes U+FFFD.
+#[no_mangle]
+pub extern "C" fn string_from_char_code(_: EnvPtr, _this: AnyValue, code: AnyValue) -> AnyValue {
+    let code = any_to_f64(code);
+    let unit = if code.is_finite() {
+        code.trunc().rem_euclid(65536.0) as u16
+    } else {
+        0
+    };
+    let s = heap().alloc_str_or_gc(&String::from_utf16_lossy(&[unit]));
+    AnyEnum::Ptr(s.as_any_ptr()).into()
+}
+
+/// Replaces the first occurrence of `pattern` in `s` (String.prototype.replace). When
+/// `replacement` is a closure, the replacement is the result of calling it with the match, its
+/// position, and `s`. Otherwise, it is a string, in which `$&` stands for the match, `` $` `` and
+/// `$'` for the text before and after it, and `$$` for `$`. Programs cannot create regular
+/// expressions yet, so the pattern is always converted to a string.
+#[no_mangle]
+pub extern "C" fn string_replace(
+    s: StringPtr,
+    pattern: AnyValue,
+    replacement: AnyValue,
+) -> StringPtr {
+    let pattern = pattern.to_string();
+    let start = match s.find(pattern.as_str()) {
+        Some(start) => start,
+        None => return s,
+    };
+    let end = start + pattern.len();
+    let replaced = match *replacement {
+        AnyEnum::Closure(_) => {
+            // The caller holds `s` and `replacement`, and the closure holds the match as soon as
+            // it starts. Like every function, the replacer receives `this` first, which is
+            // undefined.
+            let matched = heap().alloc_str_or_gc(pattern.as_str());
+            let args = [
+                AnyEnum::Undefined.into(),
+                AnyEnum::Ptr(matched.as_any_ptr()).into(),
+                AnyEnum::I32(s[..start].encode_utf16().count() as i32).into(),
+                AnyEnum::Ptr(s.as_any_ptr()).into(),
+            ];
+            call_closure(replacement, &args).to_string()
+        }
+        _ => expand_replacement(&replacement.to_string(), &s, start, end),
+    };
+    let result = format!("{}{}{}", &s[..start], replaced, &s[end..]);
+    heap().alloc_str_or_gc(result.as_str())
+}
+
+/// Expands the `$` patterns of `replacement` for the match of `s[start..end]`. A `$` that does
+/// not start a pattern stands for itself.
+fn expand_replacement(replacement: &str, s: &str, start: usize, end: usize) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('$') => result.push('$'),
+            Some('&') => result.push_str(&s[start..end]),
+            Some('`') => result.push_str(&s[..start]),
+            Some('\'') => result.push_str(&s[end..]),
+            _ => {
+                result.push('$');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    result
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -42,6 +181,17 @@ mod test {
     }
     #[test]
     #[wasm_bindgen_test]
+    fn string_eq_compares_contents() {
+        init();
+        let a = heap().alloc_str_or_gc("opal");
+        let b = heap().alloc_str_or_gc("opal");
+        let c = heap().alloc_str_or_gc("opals");
+        assert!(string_eq(a, a));
+        assert!(string_eq(a, b));
+        assert!(string_ne(a, c));
+    }
+    #[test]
+    #[wasm_bindgen_test]
     fn alloc_and_read() {
         init();
         assert_eq!(&*heap().alloc_str_or_gc("lapis"), "lapis");
@@ -59,4 +209,40 @@ mod test {
 
         assert_eq!(&*combined, "Hello world!");
     }
+    #[test]
+    #[wasm_bindgen_test]
+    fn replace_first_occurrence() {
+        init();
+        let s = heap().alloc_str_or_gc("a-b-c");
+        let pattern = AnyEnum::Ptr(heap().alloc_str_or_gc("-").as_any_ptr()).into();
+        let replacement = AnyEnum::Ptr(heap().alloc_str_or_gc("[$&$$]").as_any_ptr()).into();
+        assert_eq!(&*string_replace(s, pattern, replacement), "a[-$]b-c");
+        let missing = AnyEnum::I32(5).into();
+        assert_eq!(&*string_replace(s, missing, replacement), "a-b-c");
+    }
+    #[test]
+    #[wasm_bindgen_test]
+    fn utf16_code_units() {
+        init();
+        let s = heap().alloc_str_or_gc("a\u{e9}\u{1f600}b");
+        assert_eq!(string_length(s), 5);
+        assert_eq!(&*string_slice(s, 1, 4), "\u{e9}\u{1f600}");
+        assert_eq!(&*string_slice(s, -1, 5), "b");
+        assert_eq!(&*string_slice(s, 2, 3), "\u{fffd}");
+        assert_eq!(&*string_charAt(s, 4), "b");
+        assert_eq!(&*string_charAt(s, 5), "");
+        assert_eq!(*string_charCodeAt(s, 2), AnyEnum::I32(0xd83d));
+        assert_eq!(*string_codePointAt(s, 2), AnyEnum::I32(0x1f600));
+        assert_eq!(*string_codePointAt(s, 3), AnyEnum::I32(0xde00));
+        assert_eq!(*string_codePointAt(s, 5), AnyEnum::Undefined);
+        let ascii = heap().alloc_str_or_gc("jasper");
+        assert_eq!(&*string_slice(ascii, -3, 100), "per");
+        assert_eq!(*string_charCodeAt(ascii, 0), AnyEnum::I32(106));
+    }
+    #[test]
+    #[wasm_bindgen_test]
+    fn replace_with_context() {
+        assert_eq!(expand_replacement("<$'|$`>", "a-b-c", 1, 2), "<b-c|a>");
+        assert_eq!(expand_replacement("$1$", "a-b-c", 1, 2), "$1$");
+    }
 }
@@ -0,0 +1,91 @@
+//! The task queue of `setTimeout`.
+//!
+//! `setTimeout` in the NotWasm standard library records its callback in an array and schedules a
+//! task with [task_schedule], under the same number. After `main` returns, the host calls the
+//! `run_microtasks` export of the program, which takes tasks with [task_next] and calls their
+//! callbacks until the queue is empty.
+//!
+//! Time is virtual: the queue runs tasks in the order of the times at which they are due, and
+//! the tasks that are due at the same time in the order in which they were scheduled, but it
+//! never waits for a timer to expire.
+
+struct Task {
+    id: i32,
+    due: f64,
+}
+
+static mut TASKS: Vec<Task> = Vec::new();
+
+/// The number of tasks that the program scheduled.
+static mut NUM_TASKS: i32 = 0;
+
+/// The time at which the current task was due, or zero before the first task.
+static mut NOW: f64 = 0.0;
+
+/// The time at which the current task was due, in milliseconds since the program started.
+pub fn now() -> f64 {
+    unsafe { NOW }
+}
+
+/// Schedules a task that is due `delay` milliseconds from now, and produces its number. A delay
+/// that is negative or not a number is zero.
+#[no_mangle]
+pub extern "C" fn task_schedule(delay: f64) -> i32 {
+    let delay = if delay > 0.0 { delay } else { 0.0 };
+    unsafe {
+        let id = NUM_TASKS;
+        NUM_TASKS += 1;
+        TASKS.push(Task {
+            id,
+            due: NOW + delay,
+        });
+        id
+    }
+}
+
+/// Removes the next task from the queue, and produces its number, or -1 if the queue is empty.
+#[no_mangle]
+pub extern "C" fn task_next() -> i32 {
+    unsafe {
+        let mut next: Option<usize> = None;
+        for (i, task) in TASKS.iter().enumerate() {
+            match next {
+                // The tasks are in the order in which they were scheduled, so a task replaces
+                // the earlier one only if it is due earlier.
+                Some(j) if TASKS[j].due <= task.due => (),
+                _ => next = Some(i),
+            }
+        }
+        match next {
+            None => -1,
+            Some(i) => {
+                let task = TASKS.remove(i);
+                NOW = task.due;
+                task.id
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn run_tasks_when_due() {
+        let slow = task_schedule(10.0);
+        let fast = task_schedule(0.0);
+        let also_fast = task_schedule(f64::NAN);
+        assert_eq!(task_next(), fast);
+        assert_eq!(task_next(), also_fast);
+        assert_eq!(task_next(), slow);
+        // The delays of tasks that a task schedules start when that task was due.
+        let later = task_schedule(5.0);
+        let sooner = task_schedule(1.0);
+        assert_eq!(task_next(), sooner);
+        assert_eq!(task_next(), later);
+        assert_eq!(task_next(), -1);
+    }
+}
@@ -0,0 +1,42 @@
+//! The reason that the program trapped last. A trap in WebAssembly carries no information, so
+//! the code that records a reason runs just before the trap, and the host reads the reason after
+//! the program stops (see bin/run.js). This is only for debugging miscompiles: the generated
+//! code only records reasons when the program is compiled with `checked` set.
+
+use abi::TrapReason;
+
+static mut LAST_TRAP_REASON: u32 = TrapReason::None as u32;
+
+/// Records `reason`, which must be a [TrapReason], before the generated code traps.
+#[no_mangle]
+pub extern "C" fn trap_set_reason(reason: u32) {
+    unsafe {
+        LAST_TRAP_REASON = reason;
+    }
+}
+
+/// The [TrapReason] that the program recorded last.
+#[no_mangle]
+pub extern "C" fn last_trap_reason() -> u32 {
+    unsafe { LAST_TRAP_REASON }
+}
+
+/// Records `reason` before the runtime system traps.
+pub fn set_reason(reason: TrapReason) {
+    trap_set_reason(reason as u32);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn records_the_last_reason() {
+        set_reason(TrapReason::MethodMissing);
+        assert_eq!(last_trap_reason(), TrapReason::MethodMissing as u32);
+        trap_set_reason(TrapReason::FromAny as u32);
+        assert_eq!(last_trap_reason(), TrapReason::FromAny as u32);
+    }
+}
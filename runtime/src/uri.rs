@@ -0,0 +1,122 @@
+//! The URI-encoding functions of JavaScript (`encodeURIComponent`, `decodeURIComponent`, and
+//! `escape`), which stdlib/uri.notwasm wraps in closures. Without the `uri` feature, the runtime
+//! system does not have them.
+
+use crate::any_value::AnyValue as Any;
+use crate::string::StringPtr;
+use crate::wasm32::heap;
+
+/// The characters that encodeURIComponent does not escape.
+fn is_uri_unreserved(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_.!~*'()".contains(c)
+}
+
+/// The characters that escape does not escape.
+fn is_escape_unreserved(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "@*_+-./".contains(c)
+}
+
+/// Percent-encodes the UTF-8 bytes of every character of `s` but the unreserved ones. Rust
+/// strings cannot hold lone surrogates, so unlike JavaScript, this cannot fail.
+fn uri_encode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut bytes = [0; 4];
+    for c in s.chars() {
+        if is_uri_unreserved(c) {
+            result.push(c);
+        } else {
+            for b in c.encode_utf8(&mut bytes).bytes() {
+                result.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+    result
+}
+
+/// Decodes the percent-encoded UTF-8 bytes of `s`. Fails if an escape is not a `%` followed by
+/// two hexadecimal digits, or if the bytes are not UTF-8.
+fn uri_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b != b'%' {
+            bytes.push(b);
+            rest = tail;
+            continue;
+        }
+        let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+        if !hex.bytes().all(|d| d.is_ascii_hexdigit()) {
+            return None;
+        }
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        rest = &tail[2..];
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Escapes the UTF-16 code units of `s` with `%XX` or `%uXXXX`, like the legacy `escape`.
+fn legacy_escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for unit in s.encode_utf16() {
+        match std::char::from_u32(unit as u32) {
+            Some(c) if is_escape_unreserved(c) => result.push(c),
+            _ if unit < 256 => result.push_str(&format!("%{:02X}", unit)),
+            _ => result.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn encode_uri_component(a: Any) -> StringPtr {
+    heap().alloc_str_or_gc(&uri_encode(&a.to_string()))
+}
+
+/// NotWasm does not support exceptions yet, so a `URIError` ends the program.
+#[no_mangle]
+pub extern "C" fn decode_uri_component(a: Any) -> StringPtr {
+    match uri_decode(&a.to_string()) {
+        Some(decoded) => heap().alloc_str_or_gc(&decoded),
+        None => log_panic!("URIError: URI malformed"),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn janky_escape(a: Any) -> StringPtr {
+    heap().alloc_str_or_gc(&legacy_escape(&a.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn uri_round_trip() {
+        let s = "a b&c=d/é€😀";
+        let encoded = uri_encode(s);
+        assert_eq!(encoded, "a%20b%26c%3Dd%2F%C3%A9%E2%82%AC%F0%9F%98%80");
+        assert_eq!(uri_decode(&encoded).as_deref(), Some(s));
+        assert_eq!(uri_decode("%e2%82%ac").as_deref(), Some("€"));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn uri_decode_malformed() {
+        assert_eq!(uri_decode("%"), None);
+        assert_eq!(uri_decode("%4"), None);
+        assert_eq!(uri_decode("%zz"), None);
+        assert_eq!(uri_decode("%+1"), None);
+        // A truncated multi-byte sequence, an overlong encoding, and a surrogate
+        assert_eq!(uri_decode("%E2%82"), None);
+        assert_eq!(uri_decode("%C0%AF"), None);
+        assert_eq!(uri_decode("%ED%A0%80"), None);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn legacy_escape_units() {
+        assert_eq!(legacy_escape("a+b @é€😀"), "a+b%20@%E9%u20AC%uD83D%uDE00");
+    }
+}
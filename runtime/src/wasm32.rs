@@ -18,12 +18,14 @@ use crate::allocator::Tag;
 use crate::allocator::*;
 use crate::any_value::AnyEnum;
 use crate::any_value::AnyValue;
-use crate::closure::ClosureVal;
 use crate::heap_types::EnvPtr;
 use crate::static_strings;
 
 static mut HEAP: Option<Heap> = None;
 
+/// The size of the heap that [init] creates, or `None` for the default heap, which grows.
+static mut HEAP_SIZE: Option<isize> = None;
+
 #[no_mangle]
 pub static JNKS_STRINGS: [u8; 65536] = [0; 65536];
 
@@ -31,11 +33,23 @@ pub static JNKS_STRINGS: [u8; 65536] = [0; 65536];
 #[no_mangle]
 pub extern "C" fn init() {
     unsafe {
-        HEAP = Some(Heap::new(536870912));
+        HEAP = Some(match HEAP_SIZE {
+            Some(size) => Heap::new(size),
+            None => Heap::new_growable(536870912, 1 << 30),
+        });
         static_strings::init();
     }
 }
 
+/// Makes [init] create a heap of `bytes` bytes that does not grow. The host calls this before
+/// `main` to exercise the garbage collector with a small heap.
+#[no_mangle]
+pub extern "C" fn heap_set_size(bytes: i32) {
+    unsafe {
+        HEAP_SIZE = Some(bytes as isize);
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn gc_enter_fn(slots: usize) {
     heap().push_shadow_frame(slots);
@@ -46,6 +60,30 @@ pub unsafe extern "C" fn gc_exit_fn() {
     heap().pop_shadow_frame();
 }
 
+/// Called when a function that allocates objects in the scratch region starts. The function
+/// passes the result to [scratch_exit] when it returns.
+#[no_mangle]
+pub extern "C" fn scratch_enter() -> usize {
+    heap().scratch_enter()
+}
+
+#[no_mangle]
+pub extern "C" fn scratch_exit(top: usize) {
+    heap().scratch_exit(top);
+}
+
+/// Called when a function that boxes f64s in the scratch area of the f64 allocator starts. The
+/// function passes the result to [f64_scratch_exit] when it returns.
+#[no_mangle]
+pub extern "C" fn f64_scratch_enter() -> usize {
+    heap().f64_scratch_enter()
+}
+
+#[no_mangle]
+pub extern "C" fn f64_scratch_exit(top: usize) {
+    heap().f64_scratch_exit(top);
+}
+
 #[no_mangle]
 pub fn set_in_current_shadow_frame_slot(ptr: *mut Tag, slot: usize) {
     heap().set_in_current_shadow_frame_slot(slot, Some(ptr));
@@ -64,11 +102,6 @@ pub fn set_any_in_current_shadow_frame_slot(any: AnyValue, slot: usize) {
     heap().set_in_current_shadow_frame_slot(slot, out.pop());
 }
 
-#[no_mangle]
-pub fn set_closure_in_current_shadow_frame_slot(closure: ClosureVal, slot: usize) {
-    let env = closure.0;
-    heap().set_in_current_shadow_frame_slot(slot, Some(env.get_ptr()));
-}
 
 #[no_mangle]
 pub fn set_in_globals_frame(ptr: *mut Tag, slot: usize) {
@@ -80,11 +113,6 @@ pub fn set_any_in_globals_frame(any: AnyValue, slot: usize) {
     any.insert_ptr(&mut out, &mut Vec::new());
     heap().set_in_shadow_frame_slot(0, slot, out.pop());
 }
-#[no_mangle]
-pub fn set_closure_in_globals_frame(closure: ClosureVal, slot: usize) {
-    let env = closure.0;
-    heap().set_in_shadow_frame_slot(0, slot, Some(env.get_ptr()));
-}
 
 #[no_mangle]
 pub fn heap_dump(_: EnvPtr, _this: AnyValue) -> AnyValue {
@@ -104,6 +132,38 @@ pub fn mem_info(_: EnvPtr, _this: AnyValue) -> AnyValue {
     AnyEnum::Undefined.into()
 }
 
+/// The number of bytes that the heap uses, which is only the live values right after
+/// `__JNKS.run_gc()`.
+#[no_mangle]
+pub fn heap_used(_: EnvPtr, _this: AnyValue) -> AnyValue {
+    AnyEnum::I32(heap().used() as i32).into()
+}
+
+/// Logs the number of bytes that the heap uses, and how many f64s the program boxed, reused,
+/// boxed in the scratch area, and copied during garbage collection.
+#[no_mangle]
+pub fn gc_stats(_: EnvPtr, _this: AnyValue) -> AnyValue {
+    let f64_stats = heap().f64_allocator.borrow().stats();
+    log!("heap used: {}, {}", heap().used(), f64_stats);
+    AnyEnum::Undefined.into()
+}
+
+/// Logs the number of hidden classes, the length of the longest chain of transitions, and the
+/// number of megamorphic property accesses since the program called [track_class_sites].
+#[no_mangle]
+pub fn class_stats(_: EnvPtr, _this: AnyValue) -> AnyValue {
+    log!("{}", heap().classes.borrow().stats());
+    AnyEnum::Undefined.into()
+}
+
+/// Starts recording the classes of the objects that each property access sees, which makes
+/// property accesses slower.
+#[no_mangle]
+pub fn track_class_sites(_: EnvPtr, _this: AnyValue) -> AnyValue {
+    heap().classes.borrow_mut().track_sites();
+    AnyEnum::Undefined.into()
+}
+
 /// returns Any::I32(42) because jankyscript requires return values
 #[no_mangle]
 pub fn log_any_raw(_this: AnyValue, any: AnyValue) -> AnyValue {